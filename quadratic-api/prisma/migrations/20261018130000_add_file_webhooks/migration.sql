@@ -0,0 +1,56 @@
+-- CreateEnum
+CREATE TYPE "FileWebhookDeliveryStatus" AS ENUM ('PENDING', 'DELIVERED', 'FAILED');
+
+-- CreateTable
+CREATE TABLE "FileWebhook" (
+    "id" SERIAL NOT NULL,
+    "uuid" TEXT NOT NULL,
+    "file_id" INTEGER NOT NULL,
+    "url" TEXT NOT NULL,
+    "secret" TEXT NOT NULL,
+    "sheet_id" TEXT,
+    "range" TEXT,
+    "include_values" BOOLEAN NOT NULL DEFAULT false,
+    "deleted" BOOLEAN NOT NULL DEFAULT false,
+    "created_date" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "FileWebhook_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "FileWebhookDelivery" (
+    "id" SERIAL NOT NULL,
+    "uuid" TEXT NOT NULL,
+    "file_webhook_id" INTEGER NOT NULL,
+    "first_sequence_number" INTEGER NOT NULL,
+    "last_sequence_number" INTEGER NOT NULL,
+    "status" "FileWebhookDeliveryStatus" NOT NULL DEFAULT 'PENDING',
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "response_status" INTEGER,
+    "error" TEXT,
+    "created_date" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_date" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "FileWebhookDelivery_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "FileWebhook_uuid_key" ON "FileWebhook"("uuid");
+
+-- CreateIndex
+CREATE INDEX "FileWebhook_file_id_deleted_idx" ON "FileWebhook"("file_id", "deleted");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "FileWebhookDelivery_uuid_key" ON "FileWebhookDelivery"("uuid");
+
+-- CreateIndex
+CREATE INDEX "FileWebhookDelivery_file_webhook_id_created_date_idx" ON "FileWebhookDelivery"("file_webhook_id", "created_date" DESC);
+
+-- CreateIndex
+CREATE INDEX "FileWebhookDelivery_status_idx" ON "FileWebhookDelivery"("status");
+
+-- AddForeignKey
+ALTER TABLE "FileWebhook" ADD CONSTRAINT "FileWebhook_file_id_fkey" FOREIGN KEY ("file_id") REFERENCES "File"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "FileWebhookDelivery" ADD CONSTRAINT "FileWebhookDelivery_file_webhook_id_fkey" FOREIGN KEY ("file_webhook_id") REFERENCES "FileWebhook"("id") ON DELETE RESTRICT ON UPDATE CASCADE;
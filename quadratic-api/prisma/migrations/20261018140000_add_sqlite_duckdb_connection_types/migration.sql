@@ -0,0 +1,3 @@
+-- AlterEnum
+ALTER TYPE "ConnectionType" ADD VALUE 'SQLITE';
+ALTER TYPE "ConnectionType" ADD VALUE 'DUCKDB';
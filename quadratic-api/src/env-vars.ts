@@ -52,6 +52,10 @@ export const FREE_EDITABLE_FILE_LIMIT = process.env.FREE_EDITABLE_FILE_LIMIT
 export const STRIPE_SECRET_KEY = process.env.STRIPE_SECRET_KEY as string;
 export const ENCRYPTION_KEY = process.env.ENCRYPTION_KEY as string;
 export const STORAGE_TYPE = process.env.STORAGE_TYPE as string;
+
+// Files are encrypted at rest by the file service, so S3 files are read and
+// written through it instead of with S3 presigned URLs
+export const STORAGE_ENCRYPTED = process.env.STORAGE_ENCRYPTED === 'true';
 export const AUTH_TYPE = process.env.AUTH_TYPE as string;
 export const LICENSE_KEY = process.env.LICENSE_KEY as string;
 export const PLAID_CLIENT_ID = process.env.PLAID_CLIENT_ID as string;
@@ -1,5 +1,5 @@
 import type multer from 'multer';
-import { STORAGE_TYPE } from '../env-vars';
+import { STORAGE_ENCRYPTED, STORAGE_TYPE } from '../env-vars';
 import { getPresignedStorageUrl, getPresignedUploadStorageUrl, multerFileSystemStorage, upload } from './fileSystem';
 import { generatePresignedUrl, generatePresignedUploadUrl, multerS3Storage, S3Bucket, uploadStringAsFileS3 } from './s3';
 
@@ -8,10 +8,15 @@ export type UploadFileResponse = {
   key: string;
 };
 
+// Encrypted files can only be read and written through the file service, since
+// S3 presigned URLs and direct uploads bypass encryption.
+const isEncrypted = (bucket: S3Bucket) => STORAGE_ENCRYPTED && bucket === S3Bucket.FILES;
+
 // Get the URL for a given file (key).
 export const getFileUrl = async (key: string) => {
   switch (STORAGE_TYPE) {
     case 's3':
+      if (isEncrypted(S3Bucket.FILES)) return getPresignedStorageUrl(key);
       return await generatePresignedUrl(key, S3Bucket.FILES);
     case 'file-system':
       return getPresignedStorageUrl(key);
@@ -24,6 +29,7 @@ export const getFileUrl = async (key: string) => {
 export const getPresignedFileUrl = async (key: string) => {
   switch (STORAGE_TYPE) {
     case 's3':
+      if (isEncrypted(S3Bucket.FILES)) return getPresignedStorageUrl(key);
       return await generatePresignedUrl(key, S3Bucket.FILES);
     case 'file-system':
       return getPresignedStorageUrl(key);
@@ -36,6 +42,7 @@ export const getPresignedFileUrl = async (key: string) => {
 export const getPresignedFileUploadUrl = async (key: string, contentType: string = 'application/octet-stream') => {
   switch (STORAGE_TYPE) {
     case 's3':
+      if (isEncrypted(S3Bucket.FILES)) return getPresignedUploadStorageUrl(key);
       return await generatePresignedUploadUrl(key, S3Bucket.FILES, contentType);
     case 'file-system':
       return getPresignedUploadStorageUrl(key);
@@ -53,6 +60,7 @@ export const uploadFile = async (
 ): Promise<UploadFileResponse> => {
   switch (STORAGE_TYPE) {
     case 's3':
+      if (isEncrypted(bucket)) return await upload(key, contents, jwt);
       return await uploadStringAsFileS3(key, contents, bucket);
     case 'file-system':
       return await upload(key, contents, jwt);
@@ -65,6 +73,7 @@ export const uploadFile = async (
 export const uploadMiddleware = (bucket: S3Bucket): multer.Multer => {
   switch (STORAGE_TYPE) {
     case 's3':
+      if (isEncrypted(bucket)) return multerFileSystemStorage as unknown as multer.Multer;
       return multerS3Storage(bucket);
     case 'file-system':
       return multerFileSystemStorage as unknown as multer.Multer;
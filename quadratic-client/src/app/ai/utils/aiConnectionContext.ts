@@ -137,12 +137,31 @@ export const getConnectionSchemaMarkdown = (connectionInfo: ConnectionInfo): str
 
   const tablesInfo =
     connectionInfo.schema?.tables
-      ?.map((table: any) => {
+      ?.map((table) => {
         const columnsInfo =
           table.columns
-            ?.map((col: any) => `  - ${col.name}: ${col.type}${col.is_nullable ? ' (nullable)' : ''}`)
+            ?.map(
+              (col) =>
+                `  - ${col.name}: ${col.type}${col.is_nullable ? ' (nullable)' : ''}${col.comment ? ` -- ${col.comment}` : ''}`
+            )
             .join('\n') || '  No columns found';
-        return `Table: ${table.name} (Schema: ${table.schema || 'public'})\n${columnsInfo}`;
+        const kind = table.kind === 'materialized_view' ? 'Materialized view' : table.kind === 'view' ? 'View' : 'Table';
+        const details = [
+          table.comment ? `Description: ${table.comment}` : '',
+          table.row_estimate != null ? `Estimated rows: ${table.row_estimate}` : '',
+          table.primary_key?.length ? `Primary key: ${table.primary_key.join(', ')}` : '',
+          ...(table.foreign_keys ?? []).map(
+            (key) =>
+              `Foreign key: (${key.columns.join(', ')}) references ${key.foreign_schema}.${key.foreign_table} (${key.foreign_columns.join(', ')})`
+          ),
+          ...(table.indexes ?? []).map(
+            (index) => `${index.is_unique ? 'Unique index' : 'Index'}: ${index.name} (${index.columns.join(', ')})`
+          ),
+        ]
+          .filter(Boolean)
+          .map((detail) => `${detail}\n`)
+          .join('');
+        return `${kind}: ${table.name} (Schema: ${table.schema || 'public'})\n${details}${columnsInfo}`;
       })
       .join('\n\n') || 'No tables found';
 
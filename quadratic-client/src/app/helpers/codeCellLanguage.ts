@@ -19,6 +19,9 @@ export const codeCellsById = {
   MIXPANEL: { id: 'MIXPANEL', label: 'Mixpanel', type: 'connection' },
   GOOGLE_ANALYTICS: { id: 'GOOGLE_ANALYTICS', label: 'Google Analytics', type: 'connection' },
   PLAID: { id: 'PLAID', label: 'Plaid', type: 'connection' },
+  STRIPE: { id: 'STRIPE', label: 'Stripe', type: 'connection' },
+  SQLITE: { id: 'SQLITE', label: 'SQLite', type: 'connection' },
+  DUCKDB: { id: 'DUCKDB', label: 'DuckDB', type: 'connection' },
   // STOCKHISTORY is an internal connection type for the STOCKHISTORY formula (not user-manageable)
   STOCKHISTORY: { id: 'STOCKHISTORY', label: 'Stock History', type: 'internal' },
 } as const;
@@ -86,6 +89,12 @@ export const getLanguageForMonaco = (language?: CodeCellLanguage): string => {
         return 'sql';
       case 'PLAID':
         return 'sql';
+      case 'STRIPE':
+        return 'sql';
+      case 'SQLITE':
+        return 'sql';
+      case 'DUCKDB':
+        return 'sql';
     }
   }
 
@@ -12,18 +12,24 @@ declare var self: WorkerGlobalScope &
       y: number,
       sheetId: string,
       code: string,
+      parameters: string,
       connector_type: ConnectionKind,
       connection_id: string
     ) => void;
+    sendCancelConnection: (transactionId: string) => void;
   };
 
 class CoreConnection {
   controller: AbortController = new AbortController();
 
+  // in-flight queries by transaction id, so a single query can be cancelled
+  private queries = new Map<string, AbortController>();
+
   lastTransactionId?: string;
 
   start = async () => {
     self.sendConnection = this.sendConnection;
+    self.sendCancelConnection = this.sendCancelConnection;
 
     if (await debugFlagWait('debugWebWorkers')) console.log('[coreConnection] initialized.');
   };
@@ -34,6 +40,7 @@ class CoreConnection {
     y: number,
     sheetId: string,
     code: string,
+    parameters: string,
     connector_type: ConnectionKind,
     connection_id: string
   ) => {
@@ -51,13 +58,17 @@ class CoreConnection {
     const kind = connector_type.toLocaleLowerCase().replace(/_/g, '-');
     const url = `${base}/${kind}/query`;
     const jwt = await coreClient.getJwt();
+    // values referenced by handlebars are bound to the query's placeholders,
+    // and the transaction id identifies the query for cancelling it
     const body = {
       connection_id,
+      query_id: transactionId,
       query: code,
+      parameters: parameters ? JSON.parse(parameters) : [],
     };
 
     let buffer = new ArrayBuffer(0);
-    let std_out = undefined;
+    const std_out = undefined;
     let std_err = undefined;
     let extra = undefined;
     let codeRun: CodeRun = {
@@ -67,7 +78,9 @@ class CoreConnection {
       chartPixelWidth: 0,
       chartPixelHeight: 0,
     };
-    let signal = this.controller.signal;
+    const query = new AbortController();
+    this.queries.set(transactionId, query);
+    const signal = AbortSignal.any([this.controller.signal, query.signal]);
 
     try {
       if (core.teamUuid) {
@@ -86,12 +99,16 @@ class CoreConnection {
           std_err = (await response.text()) + `\n\nQuery: ${codeRun.code}`;
           console.warn(std_err);
         } else {
+          // the results are streamed; whether they were cut off is recorded in
+          // the parquet metadata, which core reads
           buffer = await response.arrayBuffer();
 
           const headers = response.headers;
-          const isOverTheLimit = headers.get('over-the-limit') === 'true';
-          std_out = isOverTheLimit ? 'Exceeded maximum allowed bytes, not all available records returned.' : '';
-          extra = ` in ${headers.get('elapsed-total-ms')}ms`;
+          const cacheAgeMs = headers.get('query-cache-age-ms');
+          extra =
+            headers.get('query-cache') === 'HIT' && cacheAgeMs
+              ? ` from cache (${Math.round(Number(cacheAgeMs) / 1000)}s old)`
+              : ` in ${headers.get('elapsed-total-ms')}ms`;
         }
       }
 
@@ -100,6 +117,49 @@ class CoreConnection {
       this.lastTransactionId = undefined;
     } catch (e) {
       console.error(`Error fetching ${url}`, e);
+
+      // the stream can fail after the response started; cancelled queries
+      // are completed in cancelExecution
+      if (!signal.aborted && this.lastTransactionId === transactionId) {
+        core.connectionComplete(transactionId, new ArrayBuffer(0), undefined, `Error reading query results: ${e}`);
+        this.lastTransactionId = undefined;
+      }
+    } finally {
+      this.queries.delete(transactionId);
+    }
+  };
+
+  // Called by core when a connection cell is re-run or deleted while its query
+  // is running. Core has already completed the transaction.
+  private sendCancelConnection = (transactionId: string) => {
+    this.queries.get(transactionId)?.abort();
+    this.queries.delete(transactionId);
+
+    if (this.lastTransactionId === transactionId) {
+      this.lastTransactionId = undefined;
+    }
+
+    this.cancelQuery(transactionId);
+  };
+
+  // Stops the query in the database; aborting the fetch only closes the stream
+  private cancelQuery = async (transactionId: string) => {
+    if (!core.teamUuid) return;
+
+    const base = coreClient.env.VITE_QUADRATIC_CONNECTION_URL;
+    const url = `${base}/query/${transactionId}/cancel`;
+
+    try {
+      const jwt = await coreClient.getJwt();
+      await fetch(url, {
+        method: 'POST',
+        headers: {
+          Authorization: `Bearer ${jwt}`,
+          'X-Team-Id': core.teamUuid,
+        },
+      });
+    } catch (e) {
+      console.warn(`Error cancelling query ${transactionId}`, e);
     }
   };
 
@@ -184,6 +244,8 @@ class CoreConnection {
     // It's possible that the transaction was completed before the message was
     // received.
     if (this.lastTransactionId) {
+      this.cancelQuery(this.lastTransactionId);
+
       const buffer = new ArrayBuffer(0);
       const std_out = undefined;
       const std_err = 'Execution cancelled by user';
@@ -52,9 +52,11 @@ declare var self: WorkerGlobalScope &
       y: number,
       sheetId: string,
       code: string,
+      parameters: string,
       connector_type: ConnectionKind,
       connection_id: string
     ) => void;
+    sendCancelConnection: (transactionId: string) => void;
     sendImage: (sheetId: string, x: number, y: number, image?: string, w?: string, h?: string) => void;
     sendSheetValidations: (sheetId: string, sheetValidations: Uint8Array) => void;
     sendSheetConditionalFormats: (sheetId: string, conditionalFormats: Uint8Array) => void;
@@ -204,10 +206,15 @@ export const jsConnection = (
   y: number,
   sheetId: string,
   code: string,
+  parameters: string,
   connector_type: ConnectionKind,
   connection_id: string
 ) => {
-  self.sendConnection(transactionId, x, y, sheetId, code, connector_type, connection_id);
+  self.sendConnection(transactionId, x, y, sheetId, code, parameters, connector_type, connection_id);
+};
+
+export const jsCancelConnection = (transactionId: string) => {
+  self.sendCancelConnection(transactionId);
 };
 
 export const jsSendImage = (sheetId: string, x: number, y: number, image?: string, w?: string, h?: string) => {
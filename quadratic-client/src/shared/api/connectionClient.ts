@@ -21,13 +21,38 @@ const SqlSchema = z.object({
     z.object({
       name: z.string(),
       schema: z.string(), // public or ...?
+      kind: z.enum(['table', 'view', 'materialized_view']).optional(),
+      comment: z.string().nullish(),
       columns: z.array(
         z.object({
           name: z.string(),
           type: z.string(),
           is_nullable: z.boolean(),
+          comment: z.string().nullish(),
         })
       ),
+      primary_key: z.array(z.string()).optional(),
+      foreign_keys: z
+        .array(
+          z.object({
+            name: z.string(),
+            columns: z.array(z.string()),
+            foreign_schema: z.string(),
+            foreign_table: z.string(),
+            foreign_columns: z.array(z.string()),
+          })
+        )
+        .optional(),
+      indexes: z
+        .array(
+          z.object({
+            name: z.string(),
+            columns: z.array(z.string()),
+            is_unique: z.boolean(),
+          })
+        )
+        .optional(),
+      row_estimate: z.number().nullish(),
     })
   ),
 });
@@ -74,7 +99,13 @@ export const connectionClient = {
         | 'google_analytics'
         | 'GOOGLE_ANALYTICS'
         | 'plaid'
-        | 'PLAID',
+        | 'PLAID'
+        | 'stripe'
+        | 'STRIPE'
+        | 'sqlite'
+        | 'SQLITE'
+        | 'duckdb'
+        | 'DUCKDB',
       connectionId: string,
       teamUuid: string,
       forceCacheRefresh: boolean = false,
@@ -0,0 +1,88 @@
+import { ConnectionFormSemantic } from '@/shared/components/connections/ConnectionFormSemantic';
+import type { ConnectionFormComponent, UseConnectionForm } from '@/shared/components/connections/connectionsByType';
+import {
+  Form,
+  FormControl,
+  FormDescription,
+  FormField,
+  FormItem,
+  FormLabel,
+  FormMessage,
+} from '@/shared/shadcn/ui/form';
+import { Input } from '@/shared/shadcn/ui/input';
+import { zodResolver } from '@hookform/resolvers/zod';
+import {
+  ConnectionNameSchema,
+  ConnectionSemanticDescriptionSchema,
+  ConnectionTypeDetailsDuckdbSchema,
+  ConnectionTypeSchema,
+} from 'quadratic-shared/typesAndSchemasConnections';
+import { useForm } from 'react-hook-form';
+import { z } from 'zod';
+
+const ConnectionFormDuckdbSchema = z.object({
+  name: ConnectionNameSchema,
+  semanticDescription: ConnectionSemanticDescriptionSchema,
+  type: z.literal(ConnectionTypeSchema.enum.DUCKDB),
+  ...ConnectionTypeDetailsDuckdbSchema.shape,
+});
+
+type FormValues = z.infer<typeof ConnectionFormDuckdbSchema>;
+
+export const useConnectionForm: UseConnectionForm<FormValues> = (connection) => {
+  const defaultValues: FormValues = {
+    name: connection ? connection.name : '',
+    type: 'DUCKDB',
+    database: String(connection?.typeDetails?.database || ''),
+    semanticDescription: String(connection?.semanticDescription || ''),
+  };
+
+  const form = useForm<FormValues>({
+    resolver: zodResolver(ConnectionFormDuckdbSchema),
+    defaultValues,
+  });
+
+  return { form };
+};
+
+export const ConnectionForm: ConnectionFormComponent<FormValues> = ({ form, children, handleSubmitForm }) => {
+  return (
+    <Form {...form}>
+      <form onSubmit={form.handleSubmit(handleSubmitForm)} className="space-y-2" autoComplete="off">
+        <FormField
+          control={form.control}
+          name="name"
+          render={({ field }) => (
+            <FormItem>
+              <FormLabel>Connection name</FormLabel>
+              <FormControl>
+                <Input autoComplete="off" {...field} autoFocus />
+              </FormControl>
+              <FormMessage />
+            </FormItem>
+          )}
+        />
+        <FormField
+          control={form.control}
+          name="database"
+          render={({ field }) => (
+            <FormItem>
+              <FormLabel>Database file</FormLabel>
+              <FormControl>
+                <Input autoComplete="off" placeholder="e.g. analytics.duckdb" {...field} />
+              </FormControl>
+              <FormDescription>
+                The path of the DuckDB file in the connection service's database directory.
+              </FormDescription>
+              <FormMessage />
+            </FormItem>
+          )}
+        />
+
+        <ConnectionFormSemantic form={form} />
+
+        {children}
+      </form>
+    </Form>
+  );
+};
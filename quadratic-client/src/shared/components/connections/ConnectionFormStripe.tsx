@@ -0,0 +1,128 @@
+import { deriveSyncStateFromConnectionList } from '@/app/atoms/useSyncedConnection';
+import { ConnectionFormSemantic } from '@/shared/components/connections/ConnectionFormSemantic';
+import { ConnectionInputPassword } from '@/shared/components/connections/ConnectionInputPassword';
+import type { ConnectionFormComponent, UseConnectionForm } from '@/shared/components/connections/connectionsByType';
+import { SyncedConnectionStatus } from '@/shared/components/connections/SyncedConnection';
+import { DOCUMENTATION_CONNECTIONS_STRIPE_URL } from '@/shared/constants/urls';
+import { Form, FormControl, FormField, FormItem, FormLabel, FormMessage } from '@/shared/shadcn/ui/form';
+import { Input } from '@/shared/shadcn/ui/input';
+import { zodResolver } from '@hookform/resolvers/zod';
+import {
+  ConnectionNameSchema,
+  ConnectionSemanticDescriptionSchema,
+  ConnectionTypeSchema,
+} from 'quadratic-shared/typesAndSchemasConnections';
+import { useForm } from 'react-hook-form';
+import { z } from 'zod';
+
+const ConnectionFormStripeSchema = z.object({
+  name: ConnectionNameSchema,
+  semanticDescription: ConnectionSemanticDescriptionSchema,
+  type: z.literal(ConnectionTypeSchema.enum.STRIPE),
+  api_key: z.string().min(1, { message: 'Required' }),
+  start_date: z.string().date(),
+});
+type FormValues = z.infer<typeof ConnectionFormStripeSchema>;
+
+export const useConnectionForm: UseConnectionForm<FormValues> = (connection) => {
+  const threeMonthsAgo = new Date();
+  threeMonthsAgo.setMonth(threeMonthsAgo.getMonth() - 3);
+  const defaultStartDate = threeMonthsAgo.toISOString().split('T')[0];
+
+  const defaultValues: FormValues = {
+    name: connection ? connection.name : '',
+    semanticDescription: String(connection?.semanticDescription || ''),
+    type: 'STRIPE',
+    api_key: connection?.typeDetails?.api_key || '',
+    start_date: connection?.typeDetails?.start_date || defaultStartDate,
+  };
+
+  const form = useForm<FormValues>({
+    resolver: zodResolver(ConnectionFormStripeSchema),
+    defaultValues,
+  });
+
+  return { form, connection };
+};
+
+export const ConnectionForm: ConnectionFormComponent<FormValues> = ({
+  form,
+  children,
+  handleSubmitForm,
+  connection,
+}) => {
+  return (
+    <Form {...form}>
+      <form onSubmit={form.handleSubmit(handleSubmitForm)} className="space-y-2" autoComplete="off">
+        <p className="pb-2 text-sm">
+          Create a restricted API key with read access in your Stripe Dashboard under Developers → API keys.{' '}
+          <a
+            href={DOCUMENTATION_CONNECTIONS_STRIPE_URL}
+            target="_blank"
+            rel="noopener noreferrer"
+            className="underline hover:text-primary"
+          >
+            Learn more
+          </a>
+          .
+        </p>
+        <FormField
+          control={form.control}
+          name="name"
+          render={({ field }) => (
+            <FormItem>
+              <FormLabel>Connection name</FormLabel>
+              <FormControl>
+                <Input autoComplete="off" {...field} autoFocus />
+              </FormControl>
+              <FormMessage />
+            </FormItem>
+          )}
+        />
+        <div className="grid grid-cols-3 gap-4">
+          <FormField
+            control={form.control}
+            name="api_key"
+            render={({ field }) => (
+              <FormItem className="col-span-2">
+                <FormLabel>API key</FormLabel>
+                <FormControl>
+                  <ConnectionInputPassword {...field} />
+                </FormControl>
+                <FormMessage />
+              </FormItem>
+            )}
+          />
+          <FormField
+            control={form.control}
+            name="start_date"
+            render={({ field }) => (
+              <FormItem className="col-span-1">
+                <FormLabel>Sync start date</FormLabel>
+                <FormControl>
+                  <Input type="date" autoComplete="off" className="block" {...field} />
+                </FormControl>
+                <FormMessage />
+              </FormItem>
+            )}
+          />
+        </div>
+
+        <ConnectionFormSemantic form={form} />
+
+        {connection && (
+          <div className="flex items-start gap-2 pt-2 text-sm">
+            <SyncedConnectionStatus
+              syncState={deriveSyncStateFromConnectionList(connection)}
+              updatedDate={connection.syncedConnectionUpdatedDate}
+              latestLogError={connection.syncedConnectionLatestLogError}
+              createdDate={connection.createdDate}
+            />
+          </div>
+        )}
+
+        {children}
+      </form>
+    </Form>
+  );
+};
@@ -365,11 +365,16 @@ function getTableQuery({ table: { name, schema }, connectionType }: { table: Tab
       return `SELECT * FROM "${schema}"."${name}" LIMIT 100`;
     case 'BIGQUERY':
       return `SELECT * FROM \`${schema}\`.\`${name}\` LIMIT 100`;
+    case 'SQLITE':
+      return `SELECT * FROM "${schema}"."${name}" LIMIT 100`;
+    case 'DUCKDB':
+      return `SELECT * FROM "${schema}"."${name}" LIMIT 100`;
 
     // datafusion connections
     case 'MIXPANEL':
     case 'GOOGLE_ANALYTICS':
     case 'PLAID':
+    case 'STRIPE':
       return `SELECT * FROM \`${name}\` LIMIT 100`;
     default:
       return '';
@@ -1,6 +1,7 @@
 import type { PlaidCategory } from '@/shared/components/connections/Connections';
 import * as Bigquery from '@/shared/components/connections/ConnectionFormBigquery';
 import * as Cockroachdb from '@/shared/components/connections/ConnectionFormCockroachdb';
+import * as Duckdb from '@/shared/components/connections/ConnectionFormDuckdb';
 import * as GoogleAnalytics from '@/shared/components/connections/ConnectionFormGoogleAnalytics';
 import * as Mariadb from '@/shared/components/connections/ConnectionFormMariadb';
 import * as Mixpanel from '@/shared/components/connections/ConnectionFormMixpanel';
@@ -10,6 +11,8 @@ import * as Neon from '@/shared/components/connections/ConnectionFormNeon';
 import * as Plaid from '@/shared/components/connections/ConnectionFormPlaid';
 import * as Postgres from '@/shared/components/connections/ConnectionFormPostgres';
 import * as Snowflake from '@/shared/components/connections/ConnectionFormSnowflake';
+import * as Sqlite from '@/shared/components/connections/ConnectionFormSqlite';
+import * as Stripe from '@/shared/components/connections/ConnectionFormStripe';
 import * as Supabase from '@/shared/components/connections/ConnectionFormSupabase';
 import type { Connection, ConnectionType } from 'quadratic-shared/typesAndSchemasConnections';
 import type { ReactNode } from 'react';
@@ -167,6 +170,27 @@ export const connectionsByType: Record<ConnectionType, ConnectionTypeData<any>>
     ConnectionForm: Plaid.ConnectionForm,
     useConnectionForm: Plaid.useConnectionForm,
   },
+  STRIPE: {
+    name: 'Stripe',
+    Logo: StripeLogo,
+    uiCategory: 'Analytics',
+    ConnectionForm: Stripe.ConnectionForm,
+    useConnectionForm: Stripe.useConnectionForm,
+  },
+  SQLITE: {
+    name: 'SQLite',
+    Logo: OtherLogo,
+    uiCategory: 'Databases',
+    ConnectionForm: Sqlite.ConnectionForm,
+    useConnectionForm: Sqlite.useConnectionForm,
+  },
+  DUCKDB: {
+    name: 'DuckDB',
+    Logo: OtherLogo,
+    uiCategory: 'Databases',
+    ConnectionForm: Duckdb.ConnectionForm,
+    useConnectionForm: Duckdb.useConnectionForm,
+  },
 };
 
 export type PotentialConnectionType =
@@ -182,7 +206,6 @@ export type PotentialConnectionType =
   | 'SALESFORCE'
   | 'HUBSPOT'
   | 'SLACK'
-  | 'STRIPE'
   | 'SAP'
   | 'NETSUITE'
   | 'QUICKBOOKS'
@@ -243,10 +266,6 @@ export const potentialConnectionsByType: Record<
     name: 'Slack',
     Logo: SlackLogo,
   },
-  STRIPE: {
-    name: 'Stripe',
-    Logo: StripeLogo,
-  },
   SAP: {
     name: 'SAP',
     Logo: SapLogo,
@@ -11,6 +11,7 @@ export const DOCUMENTATION_CONNECTIONS_IP_LIST_URL = `${DOCUMENTATION_CONNECTION
 export const DOCUMENTATION_CONNECTIONS_DATABASE_ACCESS_URL = `${DOCUMENTATION_CONNECTIONS_URL}/security#database-access`;
 export const DOCUMENTATION_CONNECTIONS_GOOGLE_ANALYTICS_URL = `${DOCUMENTATION_CONNECTIONS_URL}/google-analytics`;
 export const DOCUMENTATION_CONNECTIONS_MIXPANEL_URL = `${DOCUMENTATION_CONNECTIONS_URL}/mixpanel`;
+export const DOCUMENTATION_CONNECTIONS_STRIPE_URL = `${DOCUMENTATION_CONNECTIONS_URL}/stripe`;
 export const DOCUMENTATION_BROWSER_COMPATIBILITY_URL = `${DOCUMENTATION_URL}/spreadsheet/browser-compatibility`;
 export const DOCUMENTATION_DATE_TIME_FORMATTING = `${DOCUMENTATION_URL}/spreadsheet/date-time-formatting`;
 export const DOCUMENTATION_NEGATIVE_OFFSETS = `${DOCUMENTATION_URL}/spreadsheet/negative-offsets`;
@@ -45,6 +45,11 @@ pub(crate) struct Config {
     // StorageType::FileSystem
     pub(crate) storage_dir: Option<String>,
     pub(crate) storage_encryption_keys: Option<Vec<String>>,
+
+    // Files are encrypted at rest by the files service, so thumbnails are
+    // uploaded through it for any storage type
+    #[serde(default)]
+    pub(crate) storage_encrypted: bool,
 }
 
 fn default_start_server() -> bool {
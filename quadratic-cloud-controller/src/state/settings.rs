@@ -95,6 +95,13 @@ impl Settings {
     async fn new_storage(config: &Config) -> std::result::Result<StorageContainer, String> {
         let is_local = config.environment.is_local_or_docker();
 
+        // presigned upload URLs of the file system point to the files service,
+        // which encrypts the upload (the storage is only used for presigned URLs)
+        if config.storage_encrypted {
+            let storage_dir = config.storage_dir.to_owned().unwrap_or_default();
+            return Self::new_file_system_storage(config, storage_dir);
+        }
+
         match config.storage_type {
             StorageType::S3 => {
                 let bucket_name = config
@@ -136,26 +143,34 @@ impl Settings {
                     .as_ref()
                     .ok_or("Expected STORAGE_DIR to have a value")?
                     .to_owned();
-                let encryption_keys = config
-                    .storage_encryption_keys
-                    .as_ref()
-                    .ok_or("Expected STORAGE_ENCRYPTION_KEYS to have a value")?
-                    .to_owned();
 
-                Ok(StorageContainer::FileSystem(FileSystem::new(
-                    FileSystemConfig {
-                        path: storage_dir,
-                        encryption_keys,
-                        presigned_url_base: format!(
-                            "http://{}:{}/storage/presigned",
-                            config.files_host, config.files_port
-                        ),
-                    },
-                )))
+                Self::new_file_system_storage(config, storage_dir)
             }
         }
     }
 
+    fn new_file_system_storage(
+        config: &Config,
+        storage_dir: String,
+    ) -> std::result::Result<StorageContainer, String> {
+        let encryption_keys = config
+            .storage_encryption_keys
+            .as_ref()
+            .ok_or("Expected STORAGE_ENCRYPTION_KEYS to have a value")?
+            .to_owned();
+
+        Ok(StorageContainer::FileSystem(FileSystem::new(
+            FileSystemConfig {
+                path: storage_dir,
+                encryption_keys,
+                presigned_url_base: format!(
+                    "http://{}:{}/storage/presigned",
+                    config.files_host, config.files_port
+                ),
+            },
+        )))
+    }
+
     /// Generate a JWT token for a worker with a specific JTI for one-time use
     pub(crate) fn generate_worker_jwt_with_jti(
         &self,
@@ -24,8 +24,21 @@ pub(crate) struct Config {
     pub(crate) quadratic_api_uri: String,
     pub(crate) m2m_auth_token: String,
     pub(crate) max_response_bytes: u64,
+    pub(crate) query_timeout_s: u64,
+    pub(crate) pool_max_size: usize,
+    pub(crate) pool_idle_timeout_s: u64,
     pub(crate) static_ips: Vec<String>,
 
+    // SQLite and DuckDB database files are only opened from this directory
+    pub(crate) database_files_dir: Option<String>,
+
+    // Proxy egress policy
+    pub(crate) proxy_allow_private_ips: bool,
+    pub(crate) proxy_allowed_hosts: Option<Vec<String>>,
+    pub(crate) proxy_denied_hosts: Option<Vec<String>>,
+    pub(crate) proxy_max_response_bytes: u64,
+    pub(crate) proxy_max_redirects: usize,
+
     // Storage Type: s3 or file-system
     pub(crate) storage_type: StorageType,
 
@@ -0,0 +1,77 @@
+//! Egress Policy
+//!
+//! Restrict where the proxy can send requests, with the shared egress policy
+//! (see `quadratic_rust_shared::utils::egress`) configured for the proxy.
+//! Requests to non-public addresses are denied unless they're explicitly
+//! allowed, hosts can be allowed or denied by name, and redirects and
+//! response sizes are limited.
+
+use quadratic_rust_shared::utils::egress::{self, EgressDenied};
+use reqwest::{Client, Url};
+
+use crate::config::Config;
+use crate::error::{ConnectionError, Result, proxy_error};
+
+impl From<EgressDenied> for ConnectionError {
+    fn from(error: EgressDenied) -> Self {
+        ConnectionError::EgressDenied(error.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct EgressPolicy {
+    policy: egress::EgressPolicy,
+    pub(crate) max_response_bytes: u64,
+}
+
+impl EgressPolicy {
+    pub(crate) fn new(config: &Config) -> Self {
+        EgressPolicy {
+            policy: egress::EgressPolicy::new(
+                config.proxy_allow_private_ips,
+                config.proxy_max_redirects,
+            )
+            .with_hosts(&config.proxy_allowed_hosts, &config.proxy_denied_hosts),
+            max_response_bytes: config.proxy_max_response_bytes,
+        }
+    }
+
+    /// Create a client that resolves hosts and follows redirects according
+    /// to the policy
+    pub(crate) fn client(&self) -> Result<Client> {
+        self.policy
+            .client_builder()
+            .cookie_store(true)
+            .build()
+            .map_err(proxy_error)
+    }
+
+    /// Check a URL's scheme and host.  IP addresses are checked here, host
+    /// names are checked again when they're resolved.
+    pub(crate) fn check_url(&self, url: &Url) -> std::result::Result<(), EgressDenied> {
+        self.policy.check_url(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config;
+
+    #[test]
+    fn egress_policy_from_config() {
+        let config = Config {
+            proxy_allow_private_ips: false,
+            proxy_allowed_hosts: Some(vec!["*.example.com".into()]),
+            proxy_denied_hosts: Some(vec!["bad.example.com".into()]),
+            ..config().unwrap()
+        };
+        let policy = EgressPolicy::new(&config);
+        let check = |url: &str| policy.check_url(&url.parse().unwrap()).is_ok();
+
+        assert!(check("https://api.example.com/"));
+        assert!(!check("https://bad.example.com/"));
+        assert!(!check("https://www.google.com/"));
+        assert!(!check("http://169.254.169.254/latest/meta-data"));
+    }
+}
@@ -29,6 +29,9 @@ pub enum ConnectionError {
     #[error("Error creating object store: {0}")]
     CreateObjectStore(String),
 
+    #[error("Egress denied: {0}")]
+    EgressDenied(String),
+
     #[error("Header error: {0}")]
     Header(String),
 
@@ -153,6 +156,9 @@ impl IntoResponse for ConnectionError {
                 (StatusCode::UNAUTHORIZED, clean_errors(error))
             }
 
+            // 403 Forbidden - Requests the egress policy doesn't allow
+            ConnectionError::EgressDenied(error) => (StatusCode::FORBIDDEN, clean_errors(error)),
+
             // 404 Not Found - Resource Not Found
             ConnectionError::Connection(error) => (StatusCode::NOT_FOUND, clean_errors(error)),
 
@@ -10,11 +10,8 @@ pub fn time_header(time: Instant) -> HeaderValue {
         .unwrap_or(header::HeaderValue::from_static(""))
 }
 
-pub fn number_header(number: impl ToString) -> HeaderValue {
-    number
-        .to_string()
-        .parse()
-        .unwrap_or(header::HeaderValue::from_static(""))
+pub fn string_header(value: &str) -> HeaderValue {
+    header::HeaderValue::from_str(value).unwrap_or(header::HeaderValue::from_static(""))
 }
 
 /// Get the team id from the header
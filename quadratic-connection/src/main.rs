@@ -6,6 +6,7 @@
 mod auth;
 mod config;
 mod connection;
+mod egress;
 mod error;
 mod financial;
 mod header;
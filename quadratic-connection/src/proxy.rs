@@ -7,8 +7,10 @@ use axum::{
     extract::Request,
     response::{IntoResponse, Response},
 };
+use futures::StreamExt;
 use http::{HeaderName, HeaderValue};
-use reqwest::{Client, Method, RequestBuilder};
+use quadratic_rust_shared::utils::egress::egress_denied;
+use reqwest::{Client, Method, RequestBuilder, Url};
 
 use crate::error::{ConnectionError, Result, proxy_error};
 use crate::state::State;
@@ -61,7 +63,21 @@ pub(crate) async fn axum_to_reqwest(
     Ok(reqwest)
 }
 
-pub(crate) fn reqwest_to_axum(reqwest_response: reqwest::Response) -> Result<Response<Body>> {
+/// Convert a reqwest response into an axum response, ending the body with an
+/// error once it's larger than `max_response_bytes`
+pub(crate) fn reqwest_to_axum(
+    reqwest_response: reqwest::Response,
+    max_response_bytes: u64,
+) -> Result<Response<Body>> {
+    let too_large = move || format!("The response is larger than {max_response_bytes} bytes");
+
+    if reqwest_response
+        .content_length()
+        .is_some_and(|length| length > max_response_bytes)
+    {
+        return Err(ConnectionError::Proxy(too_large()));
+    }
+
     let mut response_builder = Response::builder().status(reqwest_response.status().as_u16());
 
     for (name, value) in reqwest_response.headers().into_iter() {
@@ -70,13 +86,32 @@ pub(crate) fn reqwest_to_axum(reqwest_response: reqwest::Response) -> Result<Res
         response_builder = response_builder.header(name, value);
     }
 
+    let mut received = 0;
+    let body = reqwest_response.bytes_stream().map(move |chunk| {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        received += chunk.len() as u64;
+
+        match received > max_response_bytes {
+            true => Err(std::io::Error::other(too_large())),
+            false => Ok(chunk),
+        }
+    });
+
     let response = response_builder
-        .body(Body::from_stream(reqwest_response.bytes_stream()))
+        .body(Body::from_stream(body))
         .map_err(proxy_error)?;
 
     Ok(response)
 }
 
+/// Denied redirects and addresses are reqwest errors
+fn send_error(error: reqwest::Error) -> ConnectionError {
+    match egress_denied(&error) {
+        Some(denied) => denied.to_owned().into(),
+        None => proxy_error(error),
+    }
+}
+
 pub(crate) async fn proxy(
     state: Extension<Arc<State>>,
     req: Request<Body>,
@@ -90,10 +125,13 @@ pub(crate) async fn proxy(
         .to_str()
         .map_err(proxy_error)?;
 
-    let request_builder = axum_to_reqwest(url, req, state.client.clone()).await?;
-    let reqwest_response = request_builder.send().await.map_err(proxy_error)?;
+    let url = Url::parse(url).map_err(proxy_error)?;
+    state.egress_policy.check_url(&url)?;
 
-    let response = reqwest_to_axum(reqwest_response)?;
+    let request_builder = axum_to_reqwest(url.as_str(), req, state.client.clone()).await?;
+    let reqwest_response = request_builder.send().await.map_err(send_error)?;
+
+    let response = reqwest_to_axum(reqwest_response, state.egress_policy.max_response_bytes)?;
 
     Ok(response)
 }
@@ -122,6 +160,20 @@ mod tests {
         assert_ne!(response_bytes(response).await.len(), 0);
     }
 
+    #[tokio::test]
+    async fn proxy_request_egress_denied() {
+        let state = Extension(Arc::new(new_state().await));
+        let mut request = Request::new(Body::empty());
+        request.headers_mut().insert(
+            PROXY_URL_HEADER,
+            HeaderValue::from_static("http://169.254.169.254/latest/meta-data"),
+        );
+        let error = proxy(state, request).await.err().unwrap();
+
+        assert!(matches!(error, ConnectionError::EgressDenied(_)));
+        assert_eq!(error.into_response().status(), 403);
+    }
+
     #[tokio::test]
     async fn proxy_axum_to_reqwest() {
         let state = Arc::new(new_state().await);
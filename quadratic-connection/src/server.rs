@@ -15,6 +15,7 @@ use http::{
     header::{CACHE_CONTROL, PRAGMA},
 };
 use quadratic_rust_shared::sql::Connection;
+use quadratic_rust_shared::sql::parameter::SqlParameter;
 use quadratic_rust_shared::{
     auth::jwt::{get_jwks, merge_jwks, parse_jwks},
     cache::memory::MemoryCache,
@@ -40,25 +41,37 @@ use crate::{
     proxy::proxy,
     sql::{
         bigquery::{query as query_bigquery, schema as schema_bigquery, test as test_bigquery},
+        cancel_query,
         datafusion::{
             query as query_datafusion, schema as schema_datafusion, test_google_analytics,
-            test_mixpanel, test_plaid,
+            test_mixpanel, test_plaid, test_rest, test_stripe,
         },
+        duckdb::{query as query_duckdb, schema as schema_duckdb, test as test_duckdb},
         mssql::{query as query_mssql, schema as schema_mssql, test as test_mssql},
         mysql::{query as query_mysql, schema as schema_mysql, test as test_mysql},
         postgres::{query as query_postgres, schema as schema_postgres, test as test_postgres},
         snowflake::{query as query_snowflake, schema as schema_snowflake, test as test_snowflake},
+        sqlite::{query as query_sqlite, schema as schema_sqlite, test as test_sqlite},
     },
     state::State,
 };
 
 const STATS_INTERVAL_S: u64 = 60;
+const POOL_EVICTION_INTERVAL_S: u64 = 30;
 pub(crate) const SCHEMA_CACHE_DURATION_S: Duration = Duration::from_secs(60 * 30); // 30 minutes
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct SqlQuery {
     pub(crate) query: String,
     pub(crate) connection_id: Uuid,
+    /// Identifies the query for cancelling it, one is generated if missing
+    #[serde(default)]
+    pub(crate) query_id: Option<Uuid>,
+    #[serde(default)]
+    pub(crate) parameters: Vec<SqlParameter>,
+    /// Run the query even if its results are cached
+    #[serde(default)]
+    pub(crate) force_cache_refresh: bool,
 }
 
 #[derive(Serialize, PartialEq, Debug)]
@@ -152,6 +165,14 @@ pub(crate) fn app(state: Arc<State>) -> Result<Router> {
         .route("/bigquery/query", post(query_bigquery))
         .route("/bigquery/schema/:id", get(schema_bigquery))
         //
+        // file databases
+        .route("/sqlite/test", post(test_sqlite))
+        .route("/sqlite/query", post(query_sqlite))
+        .route("/sqlite/schema/:id", get(schema_sqlite))
+        .route("/duckdb/test", post(test_duckdb))
+        .route("/duckdb/query", post(query_duckdb))
+        .route("/duckdb/schema/:id", get(schema_duckdb))
+        //
         // synced connections
         .route("/mixpanel/test", post(test_mixpanel))
         .route("/mixpanel/query", post(query_datafusion))
@@ -162,6 +183,15 @@ pub(crate) fn app(state: Arc<State>) -> Result<Router> {
         .route("/plaid/test", post(test_plaid))
         .route("/plaid/query", post(query_datafusion))
         .route("/plaid/schema/:id", get(schema_datafusion))
+        .route("/rest-api/test", post(test_rest))
+        .route("/rest-api/query", post(query_datafusion))
+        .route("/rest-api/schema/:id", get(schema_datafusion))
+        .route("/stripe/test", post(test_stripe))
+        .route("/stripe/query", post(query_datafusion))
+        .route("/stripe/schema/:id", get(schema_datafusion))
+        //
+        // query cancellation
+        .route("/query/:id/cancel", post(cancel_query))
         //
         // financial
         .route("/financial/stock-prices", post(stock_prices))
@@ -298,6 +328,19 @@ pub(crate) async fn serve() -> Result<()> {
         }
     });
 
+    // drop idle pooled connections and close idle SSH tunnels
+    let connection_pools = state.connection_pools.clone();
+    tokio::spawn({
+        async move {
+            let mut interval = time::interval(Duration::from_secs(POOL_EVICTION_INTERVAL_S));
+
+            loop {
+                interval.tick().await;
+                connection_pools.evict_idle().await;
+            }
+        }
+    });
+
     tracing::info!(
         "listening on {local_addr}, environment={}",
         config.environment
@@ -17,7 +17,7 @@ use crate::{
     header::get_team_id_header,
     server::{SqlQuery, TestResponse},
     sql::SchemaQuery,
-    state::State,
+    state::{State, connection_pool::fingerprint, running_queries::QueryOwner},
 };
 
 use super::{Schema, query_generic, schema_generic};
@@ -30,6 +30,9 @@ pub(crate) async fn test(
     let sql_query = SqlQuery {
         query: "SELECT 1".into(),
         connection_id: Uuid::new_v4(), // This is not used
+        query_id: None,
+        parameters: vec![],
+        force_cache_refresh: false,
     };
 
     let connection = BigqueryConnection::new(
@@ -39,7 +42,17 @@ pub(crate) async fn test(
     )
     .await?;
 
-    let response = query_generic::<BigqueryConnection>(connection, state, sql_query.into()).await;
+    // tests always run against the database
+    let response = query_generic::<BigqueryConnection>(
+        connection,
+        state,
+        sql_query.into(),
+        None,
+        0,
+        None,
+        None,
+    )
+    .await;
 
     let message = match response {
         Ok(_) => None,
@@ -88,9 +101,20 @@ pub(crate) async fn query(
         &headers,
     )
     .await?;
+    let fingerprint = fingerprint(&config_connection.type_details)?;
     let connection = BigqueryConnection::new_from_config(config_connection.type_details).await?;
+    let owner = QueryOwner::new(&claims, team_id);
 
-    query_generic::<BigqueryConnection>(connection, state, sql_query).await
+    query_generic::<BigqueryConnection>(
+        connection,
+        state,
+        sql_query,
+        Some(owner),
+        fingerprint,
+        None,
+        None,
+    )
+    .await
 }
 
 /// Get the schema of the database
@@ -111,7 +135,7 @@ pub(crate) async fn schema(
         type_details: BigqueryConnection::new_from_config(connection.type_details).await?,
     };
 
-    schema_generic(api_connection, state, params).await
+    schema_generic(api_connection, state, params, None).await
 }
 
 use std::sync::{Arc, LazyLock, Mutex};
@@ -128,6 +152,8 @@ pub async fn new_config() -> BigqueryConfig {
         service_account_configuration: credentials.to_string(),
         project_id: "quadratic-development".to_string(),
         dataset: "all_native_data_types".to_string(),
+        read_only: false,
+        cache_ttl_seconds: None,
     }
 }
 
@@ -143,9 +169,9 @@ mod tests {
         },
     };
     use arrow_schema::{DataType, TimeUnit};
-    use bytes::Bytes;
     use http::StatusCode;
     use quadratic_rust_shared::sql::bigquery_connection::tests::expected_bigquery_schema;
+    use quadratic_rust_shared::sql::parquet_writer::QuerySummary;
     use tracing_test::traced_test;
     use uuid::Uuid;
 
@@ -190,6 +216,9 @@ mod tests {
                 "select * from quadratic-development.all_native_data_types.all_data_types limit 1;"
                     .into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let state = Extension(Arc::new(new_state().await));
         let (_, headers) = new_team_id_with_header().await;
@@ -242,6 +271,9 @@ mod tests {
         let sql_query = SqlQuery {
             query: "SELECT * FROM quadratic-development.all_native_data_types.all_data_types ORDER BY id".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let mut test_state = new_state().await;
         test_state.settings.max_response_bytes = 0;
@@ -254,7 +286,10 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
 
+        // the truncation is recorded in the parquet metadata
         let body = response_bytes(response).await;
-        assert_eq!(body, Bytes::new());
+        let summary = QuerySummary::from_parquet(body).unwrap().unwrap();
+        assert!(summary.over_the_limit);
+        assert_eq!(summary.num_records, 0);
     }
 }
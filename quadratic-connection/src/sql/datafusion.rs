@@ -7,14 +7,15 @@ use http::HeaderMap;
 use quadratic_rust_shared::{
     quadratic_api::Connection as ApiConnection,
     sql::datafusion_connection::{
-        DatafusionConnection, EmptyConnection,
-        tests::new_datafusion_connection as new_datafusion_test_connection,
+        DatafusionConnection, tests::new_datafusion_connection as new_datafusion_test_connection,
     },
     synced::{
         SyncedClient, SyncedConnection,
         google_analytics::client::{GoogleAnalyticsClient, GoogleAnalyticsConnection},
         mixpanel::{MixpanelConnection, client::MixpanelClient},
         plaid::{PlaidConnection, client::PlaidClient},
+        rest::RestConnection,
+        stripe::{StripeConnection, client::StripeClient},
     },
 };
 use std::sync::Arc;
@@ -27,7 +28,7 @@ use crate::{
     header::get_team_id_header,
     server::{SqlQuery, TestResponse},
     sql::{Schema, SchemaQuery, query_generic, schema_generic},
-    state::State,
+    state::{State, connection_pool::fingerprint, running_queries::QueryOwner},
 };
 
 /// Macro to generate test handler functions for Datafusion connections.
@@ -47,6 +48,8 @@ macro_rules! test_handler {
 
 test_handler!(test_google_analytics, GoogleAnalyticsConnection);
 test_handler!(test_mixpanel, MixpanelConnection);
+test_handler!(test_rest, RestConnection);
+test_handler!(test_stripe, StripeConnection);
 
 pub(crate) async fn test_plaid(
     state: Extension<Arc<State>>,
@@ -70,7 +73,7 @@ async fn get_connection(
     team_id: &Uuid,
     headers: &HeaderMap,
 ) -> Result<ApiConnection<DatafusionConnection>> {
-    let api_connection: ApiConnection<EmptyConnection> = match cfg!(not(test)) {
+    let api_connection: ApiConnection<serde_json::Value> = match cfg!(not(test)) {
         true => {
             get_api_connection(&state, "", &claims.email, connection_id, team_id, headers).await?
         }
@@ -78,7 +81,7 @@ async fn get_connection(
             uuid: Uuid::new_v4(),
             name: "".into(),
             r#type: "".into(),
-            type_details: EmptyConnection {},
+            type_details: serde_json::Value::Null,
         },
     };
 
@@ -87,10 +90,23 @@ async fn get_connection(
         false => new_datafusion_test_connection(),
     };
 
+    // the streams of REST API connections are in their config
+    let rest_connection = match api_connection.r#type.as_str() {
+        "REST_API" => Some(serde_json::from_value::<RestConnection>(
+            api_connection.type_details,
+        )?),
+        _ => None,
+    };
+
     let streams = match api_connection.r#type.as_str() {
         "MIXPANEL" => MixpanelClient::streams(),
         "GOOGLE_ANALYTICS" => GoogleAnalyticsClient::streams(),
         "PLAID" => PlaidClient::streams(),
+        "STRIPE" => StripeClient::streams(),
+        "REST_API" => rest_connection
+            .as_ref()
+            .map(|connection| connection.streams())
+            .unwrap_or_default(),
         _ => vec![],
     };
 
@@ -127,8 +143,19 @@ pub(crate) async fn query(
         &headers,
     )
     .await?;
+    let owner = QueryOwner::new(&claims, team_id);
+    let fingerprint = fingerprint(&connection.type_details)?;
 
-    query_generic::<DatafusionConnection>(connection.type_details, state, sql_query).await
+    query_generic::<DatafusionConnection>(
+        connection.type_details,
+        state,
+        sql_query,
+        Some(owner),
+        fingerprint,
+        None,
+        None,
+    )
+    .await
 }
 
 /// Get the schema of the database
@@ -143,7 +170,7 @@ pub(crate) async fn schema(
     let api_connection =
         get_connection((**state).clone(), &claims, &id, &team_id, &headers).await?;
 
-    schema_generic(api_connection, state, params).await
+    schema_generic(api_connection, state, params, None).await
 }
 
 #[cfg(test)]
@@ -206,6 +233,9 @@ mod tests {
         let sql_query = SqlQuery {
             query: "SELECT 1 as test_column".to_string(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
 
         let result = query(headers, state, claims, Json(sql_query)).await;
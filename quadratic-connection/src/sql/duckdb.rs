@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Query},
+    response::IntoResponse,
+};
+use http::HeaderMap;
+use quadratic_rust_shared::{
+    quadratic_api::Connection as ApiConnection, sql::duckdb_connection::DuckdbConnection,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::Claims,
+    connection::get_api_connection,
+    error::Result,
+    header::get_team_id_header,
+    server::{SqlQuery, TestResponse, test_connection},
+    state::{State, connection_pool::fingerprint, running_queries::QueryOwner},
+};
+
+use super::{Schema, SchemaQuery, query_generic, schema_generic};
+
+/// Test the connection to the database.
+pub(crate) async fn test(
+    state: Extension<Arc<State>>,
+    Json(connection): Json<DuckdbConnection>,
+) -> Json<TestResponse> {
+    let connection = connection.with_directory(state.settings.database_files_dir.as_ref());
+    test_connection(connection).await
+}
+
+/// Get the connection details from the API and create a DuckdbConnection.
+async fn get_connection(
+    state: &State,
+    claims: &Claims,
+    connection_id: &Uuid,
+    team_id: &Uuid,
+    headers: &HeaderMap,
+) -> Result<ApiConnection<DuckdbConnection>> {
+    let mut connection: ApiConnection<DuckdbConnection> =
+        get_api_connection(state, "", &claims.email, connection_id, team_id, headers).await?;
+
+    // only open database files within the database files directory
+    connection.type_details.directory = state.settings.database_files_dir.to_owned();
+
+    Ok(connection)
+}
+
+/// Query the database and return the results as a parquet file.
+pub(crate) async fn query(
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+    claims: Claims,
+    sql_query: Json<SqlQuery>,
+) -> Result<impl IntoResponse> {
+    let team_id = get_team_id_header(&headers)?;
+    let connection = get_connection(
+        &state,
+        &claims,
+        &sql_query.connection_id,
+        &team_id,
+        &headers,
+    )
+    .await?;
+    let owner = QueryOwner::new(&claims, team_id);
+    let fingerprint = fingerprint(&connection.type_details)?;
+
+    query_generic::<DuckdbConnection>(
+        connection.type_details,
+        state,
+        sql_query,
+        Some(owner),
+        fingerprint,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Get the schema of the database
+pub(crate) async fn schema(
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+    claims: Claims,
+    Query(params): Query<SchemaQuery>,
+) -> Result<Json<Schema>> {
+    let team_id = get_team_id_header(&headers)?;
+    let api_connection = get_connection(&state, &claims, &id, &team_id, &headers).await?;
+
+    schema_generic(api_connection, state, params, None).await
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::new_state;
+    use tracing_test::traced_test;
+
+    #[tokio::test]
+    #[traced_test]
+    async fn duckdb_test_connection_missing_file() {
+        let state = Extension(Arc::new(new_state().await));
+        let connection = DuckdbConnection::new("missing.duckdb".into());
+        let response = test(state, Json(connection)).await;
+
+        assert!(!response.0.connected);
+        assert!(response.0.message.is_some());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn duckdb_test_connection_outside_directory() {
+        let state = Extension(Arc::new(new_state().await));
+        let connection = DuckdbConnection::new("/etc/passwd".into());
+        let response = test(state, Json(connection)).await;
+
+        assert!(!response.0.connected);
+    }
+}
@@ -1,29 +1,52 @@
+use std::io::{ErrorKind, Write};
 use std::sync::Arc;
 
-use axum::{Extension, Json, http::HeaderMap, response::IntoResponse};
+use axum::{Extension, Json, body::Body, extract::Path, http::HeaderMap, response::IntoResponse};
+use bytes::Bytes;
+use futures::{
+    SinkExt, StreamExt,
+    channel::mpsc::{Sender, channel},
+    stream,
+};
 use quadratic_rust_shared::{
     net::ssh::SshConfig,
-    sql::{Connection, schema::SchemaTable},
+    sql::{
+        CancelQuery, Connection,
+        parquet_writer::{ParquetBatchWriter, QuerySummary},
+        schema::SchemaTable,
+        statement::check_read_only,
+    },
 };
 use serde::Serialize;
-use tokio::time::Instant;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::{oneshot, watch};
+use tokio::task::{self, JoinHandle};
+use tokio::time::{self, Instant};
 use uuid::Uuid;
 
 use crate::{
+    auth::Claims,
     error::{ConnectionError, Result},
-    header::{number_header, time_header},
+    header::{get_team_id_header, string_header, time_header},
     server::SqlQuery,
-    ssh::{UsesSsh, open_ssh_tunnel_for_connection},
-    state::State,
+    ssh::UsesSsh,
+    state::{
+        State,
+        connection_pool::{PoolKey, PooledConnection, TunnelLease},
+        query_cache::QueryCache,
+        running_queries::QueryOwner,
+    },
 };
 use quadratic_rust_shared::quadratic_api::Connection as ApiConnection;
 
 pub(crate) mod bigquery;
 pub(crate) mod datafusion;
+pub(crate) mod duckdb;
 pub(crate) mod mssql;
 pub(crate) mod mysql;
 pub(crate) mod postgres;
 pub(crate) mod snowflake;
+pub(crate) mod sqlite;
 
 #[derive(Debug, Serialize, PartialEq, Clone)]
 pub struct Schema {
@@ -34,6 +57,11 @@ pub struct Schema {
     pub(crate) tables: Vec<SchemaTable>,
 }
 
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct CancelQueryResponse {
+    pub(crate) cancelled: bool,
+}
+
 #[derive(Debug, PartialEq, Clone, serde::Deserialize)]
 pub struct SchemaQuery {
     force_cache_refresh: Option<bool>,
@@ -48,43 +76,434 @@ impl SchemaQuery {
     }
 }
 
-/// Query the database and return the results as a parquet file.
-pub(crate) async fn query_generic<'a, T: Connection<'a>>(
+/// Size of the chunks of the response body
+const RESPONSE_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Number of chunks of the response body that are buffered before the query
+/// waits for the client to read them
+const RESPONSE_CHANNEL_CHUNKS: usize = 16;
+
+/// Sends the bytes written to it to the response body in chunks, keeping a
+/// copy of them when the results are cached.
+///
+/// Once the response body's channel is full, writes wait for the client to
+/// read the results or for the query to be stopped (see `watch_query`).
+struct ChannelWriter {
+    sender: Sender<Result<Bytes>>,
+    stopped: watch::Receiver<bool>,
+    buffer: Vec<u8>,
+    copy: Option<Vec<u8>>,
+}
+
+impl ChannelWriter {
+    fn new(sender: Sender<Result<Bytes>>, stopped: watch::Receiver<bool>, keep_copy: bool) -> Self {
+        Self {
+            sender,
+            stopped,
+            buffer: Vec::with_capacity(RESPONSE_CHUNK_BYTES),
+            copy: keep_copy.then(Vec::new),
+        }
+    }
+
+    /// Send a chunk, waiting while the channel is full.  Writers are
+    /// synchronous, so the query's task blocks while it waits, which needs the
+    /// multi-threaded runtime.
+    fn send(&mut self, chunk: Bytes) -> std::io::Result<()> {
+        // the receiver is dropped when the client disconnects
+        let dropped = || std::io::Error::new(ErrorKind::BrokenPipe, "Response body was dropped");
+
+        let chunk = match self.sender.try_send(Ok(chunk)) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.is_full() => e.into_inner(),
+            Err(_) => return Err(dropped()),
+        };
+
+        if Handle::current().runtime_flavor() == RuntimeFlavor::CurrentThread {
+            return Err(std::io::Error::new(
+                ErrorKind::WouldBlock,
+                "Response body is full",
+            ));
+        }
+
+        let Self {
+            sender, stopped, ..
+        } = self;
+
+        task::block_in_place(|| {
+            Handle::current().block_on(async {
+                tokio::select! {
+                    sent = sender.send(chunk) => sent.map_err(|_| dropped()),
+                    _ = stopped.wait_for(|stopped| *stopped) => Err(std::io::Error::new(
+                        ErrorKind::BrokenPipe,
+                        "Query was stopped",
+                    )),
+                }
+            })
+        })
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+
+        if self.buffer.len() >= RESPONSE_CHUNK_BYTES {
+            self.flush()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            let chunk = Bytes::from(std::mem::take(&mut self.buffer));
+
+            if let Some(copy) = &mut self.copy {
+                copy.extend_from_slice(&chunk);
+            }
+
+            self.send(chunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The results of a streamed query
+struct StreamedQuery<T: Connection<'static>> {
+    summary: QuerySummary,
+    connection: T,
+    pooled: PooledConnection<T::Conn>,
+
+    /// The complete parquet file, when the results are cached
+    parquet: Option<Bytes>,
+}
+
+/// Run the query, writing its results to the response body.
+///
+/// The connection is returned so that it can be reset and checked back in to
+/// its pool.
+async fn stream_query<T: Connection<'static>>(
     mut connection: T,
+    mut pooled: PooledConnection<T::Conn>,
+    sql_query: SqlQuery,
+    max_bytes: Option<u64>,
+    sender: Sender<Result<Bytes>>,
+    stopped: watch::Receiver<bool>,
+    keep_copy: bool,
+) -> Result<StreamedQuery<T>> {
+    let body = ChannelWriter::new(sender, stopped, keep_copy);
+    let mut writer = ParquetBatchWriter::new(body, max_bytes);
+    let total_records = connection
+        .query_stream(
+            &mut pooled.conn,
+            &sql_query.query,
+            &sql_query.parameters,
+            &mut writer,
+        )
+        .await?;
+
+    let (mut body, summary) = writer.finish(total_records)?;
+    body.flush()
+        .map_err(|e| ConnectionError::Query(e.to_string()))?;
+
+    // results that were cut off aren't cached
+    let parquet = body
+        .copy
+        .filter(|_| !summary.over_the_limit)
+        .map(Bytes::from);
+
+    Ok(StreamedQuery {
+        summary,
+        connection,
+        pooled,
+        parquet,
+    })
+}
+
+/// Query the database and stream the results as a parquet file.
+///
+/// The query runs in its own task, writing row groups to the response body as
+/// they are produced.  Only `RESPONSE_CHANNEL_CHUNKS` chunks are buffered, so
+/// the query waits for slow clients (within the query timeout).  Errors raised before any results are written are
+/// returned as an error response.
+///
+/// The headers are sent before the query completes, so the record count and
+/// whether the result was cut off are in the parquet file's metadata (see
+/// `QuerySummary`).
+///
+/// Queries are registered by id (the `QUERY-ID` header) so that their owner
+/// can cancel them, and are cancelled once they run longer than the query
+/// timeout.  See `watch_query`.
+///
+/// When the connection has a cache TTL, cached results that are younger than
+/// it are returned without running the query (unless `force_cache_refresh`
+/// is set), and the results of queries that are run are cached.  The
+/// `QUERY-CACHE` header is `HIT` or `MISS`, and `QUERY-CACHE-AGE-MS` is the
+/// age of cached results.  Results are cached by the connection's
+/// `fingerprint`, so they're not used once its details change.
+///
+/// With a pool key, the database connection is checked out of the
+/// connection's pool rather than opened for this query.  The tunnel lease
+/// keeps the connection's pooled SSH tunnel open until the query completes.
+pub(crate) async fn query_generic<T>(
+    connection: T,
     state: Extension<Arc<State>>,
     sql_query: Json<SqlQuery>,
-) -> Result<impl IntoResponse> {
+    owner: Option<QueryOwner>,
+    fingerprint: u64,
+    pool_key: Option<PoolKey>,
+    tunnel: Option<TunnelLease>,
+) -> Result<impl IntoResponse>
+where
+    T: Connection<'static> + Send + 'static,
+    T::Conn: 'static,
+{
     let mut headers = HeaderMap::new();
     let start = Instant::now();
     let max_response_bytes = Some(state.settings.max_response_bytes);
+    let Json(sql_query) = sql_query;
+    let query_id = sql_query.query_id.unwrap_or_else(Uuid::new_v4);
+
+    // reject statements that write before they reach the database
+    if connection.read_only() {
+        check_read_only(&sql_query.query)?;
+    }
+
+    let cache_ttl = connection.cache_ttl();
+    let cache_key = cache_ttl.map(|_| {
+        QueryCache::key(
+            sql_query.connection_id,
+            fingerprint,
+            &sql_query.query,
+            &sql_query.parameters,
+        )
+    });
+
+    if let (Some(ttl), Some(key)) = (cache_ttl, &cache_key) {
+        let cached = match sql_query.force_cache_refresh {
+            true => None,
+            false => state.query_cache.get(key, ttl).await,
+        };
+
+        if let Some(cached) = cached {
+            let age_ms = cached.age.as_millis().to_string();
+
+            headers.insert("QUERY-CACHE", string_header("HIT"));
+            headers.insert("QUERY-CACHE-AGE-MS", string_header(&age_ms));
+            headers.insert("ELAPSED-TOTAL-MS", time_header(start));
+            headers.insert("QUERY-ID", string_header(&query_id.to_string()));
+
+            return Ok((headers, Body::from(cached.parquet)));
+        }
+
+        headers.insert("QUERY-CACHE", string_header("MISS"));
+    }
 
     let start_connect = Instant::now();
-    let mut pool = connection.connect().await?;
+    let mut pooled = checkout(&state, pool_key, &connection, tunnel.is_some()).await?;
 
     headers.insert("ELAPSED-DATABASE-CONNECTION-MS", time_header(start_connect));
 
+    // the query still runs when the database can't cancel it
+    let cancel_handle = connection
+        .cancel_handle(&mut pooled.conn, &sql_query.query, &sql_query.parameters)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Error getting a cancel handle for query {query_id}: {e}");
+            None
+        });
+
+    let cancelled = state.running_queries.start(query_id, owner).await?;
+
     let start_query = Instant::now();
-    let (parquet, over_the_limit, num_records) = connection
-        .query(&mut pool, &sql_query.query, max_response_bytes)
-        .await?;
+    let (sender, mut receiver) = channel::<Result<Bytes>>(RESPONSE_CHANNEL_CHUNKS);
+    let (stop, stopped) = watch::channel(false);
+
+    let query = tokio::spawn(stream_query(
+        connection,
+        pooled,
+        sql_query,
+        max_response_bytes,
+        sender.clone(),
+        stopped,
+        cache_key.is_some(),
+    ));
 
-    headers.insert("RECORD-COUNT", number_header(num_records));
+    tokio::spawn(watch_query(
+        query_id,
+        query,
+        cancelled,
+        cancel_handle,
+        Arc::clone(&state),
+        sender,
+        stop,
+        tunnel,
+        cache_key,
+    ));
+
+    // wait for the first chunk so that errors are returned as a response
+    let first_chunk = match receiver.next().await {
+        Some(Err(e)) => return Err(e),
+        Some(Ok(chunk)) => Some(Ok(chunk)),
+        None => None,
+    };
+
+    // time until the results start streaming
     headers.insert("ELAPSED-DATABASE-QUERY-MS", time_header(start_query));
-    headers.insert("OVER-THE-LIMIT", number_header(over_the_limit));
+    headers.insert("ELAPSED-TOTAL-MS", time_header(start));
+    headers.insert("QUERY-ID", string_header(&query_id.to_string()));
+
+    let body = Body::from_stream(stream::iter(first_chunk).chain(receiver));
+
+    Ok((headers, body))
+}
+
+/// Get a connection from the connection's pool.  When connecting through a
+/// pooled SSH tunnel fails, the pool is evicted so that the next query opens
+/// a new tunnel.
+async fn checkout<T>(
+    state: &State,
+    pool_key: Option<PoolKey>,
+    connection: &T,
+    uses_tunnel: bool,
+) -> Result<PooledConnection<T::Conn>>
+where
+    T: Connection<'static>,
+    T::Conn: 'static,
+{
+    let pooled = state.connection_pools.checkout(pool_key, connection).await;
+
+    if pooled.is_err()
+        && uses_tunnel
+        && let Some(pool_key) = pool_key
+    {
+        state.connection_pools.evict(pool_key.connection_id()).await;
+    }
+
+    pooled
+}
+
+/// Wait for a query to complete, be cancelled or time out.
+///
+/// A query that is cancelled or times out is stopped with the database's
+/// native cancel (when it has one) and its task is aborted, which drops the
+/// connection.  Connections of queries that complete are reset and returned
+/// to their pool.  The tunnel lease is released last, since the native cancel may need
+/// the tunnel.
+///
+/// Aborting the task doesn't interrupt a query that's waiting for the client
+/// to read its results, so the query is also told to stop waiting.
+///
+/// With a cache key, the results of queries that complete are cached once
+/// the response body has ended.
+#[allow(clippy::too_many_arguments)]
+async fn watch_query<T: Connection<'static> + Send + 'static>(
+    query_id: Uuid,
+    query: JoinHandle<Result<StreamedQuery<T>>>,
+    cancelled: oneshot::Receiver<()>,
+    cancel_handle: Option<Box<dyn CancelQuery>>,
+    state: Arc<State>,
+    mut sender: Sender<Result<Bytes>>,
+    stop: watch::Sender<bool>,
+    tunnel: Option<TunnelLease>,
+    cache_key: Option<String>,
+) {
+    let timeout = state.settings.query_timeout;
+    let abort_handle = query.abort_handle();
+
+    let result = tokio::select! {
+        result = time::timeout(timeout, query) => match result {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => Err(ConnectionError::InternalServer(e.to_string())),
+            Err(_) => Err(ConnectionError::Query(format!(
+                "Query timed out after {} seconds",
+                timeout.as_secs()
+            ))),
+        },
+        Ok(()) = cancelled => Err(ConnectionError::Query("Query cancelled".into())),
+    };
+
+    let mut parquet = None;
+
+    match result {
+        Ok(StreamedQuery {
+            summary,
+            connection,
+            pooled,
+            parquet: results,
+        }) => {
+            tracing::info!(
+                "Query {query_id} streamed {} records (over the limit: {})",
+                summary.num_records,
+                summary.over_the_limit
+            );
+
+            // a query cut off at the limit may leave unread results on the
+            // connection, so it isn't reused
+            if !summary.over_the_limit {
+                state.connection_pools.checkin(&connection, pooled).await;
+            }
+
+            parquet = results;
+        }
+        Err(e) => {
+            // send the error before stopping the query, so that the client
+            // doesn't receive the database's cancellation error instead
+            // (each sender has a slot in the channel, so this doesn't wait
+            // for the client, and the receiver is dropped when the client
+            // disconnects)
+            let _ = sender.try_send(Err(e));
+
+            // stop waiting for the client to read the results
+            stop.send_replace(true);
+
+            if !abort_handle.is_finished() {
+                if let Some(cancel_handle) = cancel_handle
+                    && let Err(e) = cancel_handle.cancel().await
+                {
+                    tracing::warn!("Error cancelling query {query_id}: {e}");
+                }
+
+                abort_handle.abort();
+            }
+        }
+    }
 
+    // end the response body
+    drop(sender);
+
+    state.running_queries.finish(query_id).await;
     state.stats.lock().await.last_query_time = Some(Instant::now());
-    headers.insert("ELAPSED-TOTAL-MS", time_header(start));
+    drop(tunnel);
+
+    if let (Some(key), Some(parquet)) = (cache_key, parquet) {
+        state.query_cache.add(&key, parquet).await;
+    }
+}
+
+/// Cancel a running query by its id.  Only the user and team that ran the
+/// query can cancel it.
+pub(crate) async fn cancel_query(
+    Path(query_id): Path<Uuid>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+    claims: Claims,
+) -> Result<Json<CancelQueryResponse>> {
+    let owner = QueryOwner::new(&claims, get_team_id_header(&headers)?);
+    let cancelled = state.running_queries.cancel(query_id, &owner).await;
 
-    Ok((headers, parquet))
+    Ok(Json(CancelQueryResponse { cancelled }))
 }
 
-pub(crate) async fn schema_generic<'a, C>(
+pub(crate) async fn schema_generic<C>(
     api_connection: ApiConnection<C>,
     state: Extension<Arc<State>>,
     params: SchemaQuery,
+    pool_key: Option<PoolKey>,
 ) -> Result<Json<Schema>>
 where
-    C: Connection<'a> + 'a,
+    C: Connection<'static> + 'static,
 {
     let should_clear_cache = params.force_cache_refresh.unwrap_or(false);
 
@@ -99,8 +518,14 @@ where
     }
 
     // we're not using the cache, so get the schema from the database
-    let mut pool = api_connection.type_details.connect().await?;
-    let database_schema = api_connection.type_details.schema(&mut pool).await?;
+    let connection = &api_connection.type_details;
+    let mut pooled = state
+        .connection_pools
+        .checkout(pool_key, connection)
+        .await?;
+    let database_schema = connection.schema(&mut pooled.conn).await?;
+    state.connection_pools.checkin(connection, pooled).await;
+
     let schema = Schema {
         id: api_connection.uuid,
         name: api_connection.name,
@@ -118,22 +543,88 @@ where
     Ok(Json(schema))
 }
 
-pub(crate) async fn schema_generic_with_ssh<'a, C>(
+/// Get the schema of a database that may be behind an SSH tunnel, using the
+/// connection's pooled tunnel and connections.
+pub(crate) async fn schema_generic_with_ssh<C>(
     mut api_connection: ApiConnection<C>,
     state: Extension<Arc<State>>,
     params: SchemaQuery,
 ) -> Result<Json<Schema>>
 where
-    C: Connection<'a> + Clone + UsesSsh + 'a,
+    C: Connection<'static> + Clone + Serialize + UsesSsh + 'static,
     C: TryInto<SshConfig>,
     <C as TryInto<SshConfig>>::Error: Into<ConnectionError>,
 {
-    let tunnel = open_ssh_tunnel_for_connection(&mut api_connection.type_details).await?;
-    let schema = schema_generic(api_connection, state, params).await?;
+    let connection_id = api_connection.uuid;
+    let _tunnel = state
+        .connection_pools
+        .tunnel(connection_id, &mut api_connection.type_details)
+        .await?;
+    let pool_key = PoolKey::new(connection_id, &api_connection.type_details)?;
+
+    schema_generic(api_connection, state, params, Some(pool_key)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn spawn_writer(
+        sender: Sender<Result<Bytes>>,
+        stopped: watch::Receiver<bool>,
+        chunks: usize,
+    ) -> JoinHandle<std::io::Result<()>> {
+        tokio::spawn(async move {
+            let mut writer = ChannelWriter::new(sender, stopped, false);
 
-    if let Some(mut tunnel) = tunnel {
-        tunnel.close().await?;
+            for _ in 0..chunks {
+                writer.write_all(&[0; RESPONSE_CHUNK_BYTES])?;
+            }
+
+            writer.flush()
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_channel_writer_waits_for_the_client() {
+        let (sender, mut receiver) = channel::<Result<Bytes>>(1);
+        let (_stop, stopped) = watch::channel(false);
+        let writer = spawn_writer(sender, stopped, 4);
+
+        // the channel holds 2 chunks (its buffer and the sender's slot)
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(!writer.is_finished());
+
+        for _ in 0..4 {
+            let chunk = receiver.next().await.unwrap().unwrap();
+            assert_eq!(chunk.len(), RESPONSE_CHUNK_BYTES);
+        }
+
+        writer.await.unwrap().unwrap();
     }
 
-    Ok(schema)
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_channel_writer_stops_waiting() {
+        let (sender, _receiver) = channel::<Result<Bytes>>(1);
+        let (stop, stopped) = watch::channel(false);
+        let writer = spawn_writer(sender, stopped, 4);
+
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(!writer.is_finished());
+
+        stop.send_replace(true);
+        let error = writer.await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_channel_writer_client_disconnects() {
+        let (sender, receiver) = channel::<Result<Bytes>>(1);
+        let (_stop, stopped) = watch::channel(false);
+        drop(receiver);
+
+        let error = spawn_writer(sender, stopped, 1).await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+    }
 }
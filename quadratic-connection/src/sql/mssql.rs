@@ -18,7 +18,7 @@ use crate::{
     header::get_team_id_header,
     server::{SqlQuery, TestResponse, test_connection},
     ssh::open_ssh_tunnel_for_connection,
-    state::State as AppState,
+    state::{State as AppState, connection_pool::PoolKey, running_queries::QueryOwner},
 };
 
 use super::{Schema, SchemaQuery, query_generic, schema_generic_with_ssh};
@@ -70,6 +70,9 @@ async fn get_connection(
                 ssh_port: Some(ssh_config.port.to_string()),
                 ssh_username: Some(ssh_config.username.to_string()),
                 ssh_key: Some(ssh_config.private_key.to_string()),
+                tls: Default::default(),
+                read_only: false,
+                cache_ttl_seconds: None,
             },
         }
     };
@@ -93,23 +96,35 @@ pub(crate) async fn query(
         &headers,
     )
     .await?;
+    let owner = QueryOwner::new(&claims, team_id);
 
-    query_with_connection(state, sql_query, connection.type_details).await
+    query_with_connection(state, sql_query, connection.type_details, Some(owner)).await
 }
 
 pub(crate) async fn query_with_connection(
     state: Extension<Arc<AppState>>,
     sql_query: Json<SqlQuery>,
     mut connection: MsSqlConnection,
+    owner: Option<QueryOwner>,
 ) -> Result<impl IntoResponse> {
-    let tunnel = open_ssh_tunnel_for_connection(&mut connection).await?;
-    let result = query_generic::<MsSqlConnection>(connection, state, sql_query).await?;
-
-    if let Some(mut tunnel) = tunnel {
-        tunnel.close().await?;
-    }
-
-    Ok(result)
+    let connection_id = sql_query.connection_id;
+    let tunnel = state
+        .connection_pools
+        .tunnel(connection_id, &mut connection)
+        .await?;
+    let pool_key = PoolKey::new(connection_id, &connection)?;
+
+    // the tunnel is leased until the results are streamed
+    query_generic::<MsSqlConnection>(
+        connection,
+        state,
+        sql_query,
+        owner,
+        pool_key.fingerprint(),
+        Some(pool_key),
+        tunnel,
+    )
+    .await
 }
 
 /// Get the schema of the database
@@ -139,9 +154,9 @@ mod tests {
     };
     use arrow::datatypes::Date32Type;
     use arrow_schema::{DataType, TimeUnit};
-    use bytes::Bytes;
     use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
     use http::StatusCode;
+    use quadratic_rust_shared::sql::parquet_writer::QuerySummary;
     use quadratic_rust_shared::{
         net::ssh::tests::get_ssh_config,
         sql::schema::{SchemaColumn, SchemaTable},
@@ -165,6 +180,9 @@ mod tests {
                 ssh_port: Some(ssh_config.port.to_string()),
                 ssh_username: Some(ssh_config.username.to_string()),
                 ssh_key: Some(ssh_config.private_key.to_string()),
+                tls: Default::default(),
+                read_only: false,
+                cache_ttl_seconds: None,
             }
         } else {
             MsSqlConnection {
@@ -178,6 +196,9 @@ mod tests {
                 ssh_port: None,
                 ssh_username: None,
                 ssh_key: None,
+                tls: Default::default(),
+                read_only: false,
+                cache_ttl_seconds: None,
             }
         };
 
@@ -219,168 +240,206 @@ mod tests {
                         name: "id".into(),
                         r#type: "int".into(),
                         is_nullable: false,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "tinyint_col".into(),
                         r#type: "tinyint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "smallint_col".into(),
                         r#type: "smallint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "int_col".into(),
                         r#type: "int".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "bigint_col".into(),
                         r#type: "bigint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "bit_col".into(),
                         r#type: "bit".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "decimal_col".into(),
                         r#type: "decimal".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "numeric_col".into(),
                         r#type: "numeric".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "money_col".into(),
                         r#type: "money".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "smallmoney_col".into(),
                         r#type: "smallmoney".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "float_col".into(),
                         r#type: "float".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "real_col".into(),
                         r#type: "real".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "date_col".into(),
                         r#type: "date".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "time_col".into(),
                         r#type: "time".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "datetime2_col".into(),
                         r#type: "datetime2".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "datetimeoffset_col".into(),
                         r#type: "datetimeoffset".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "datetime_col".into(),
                         r#type: "datetime".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "smalldatetime_col".into(),
                         r#type: "smalldatetime".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "char_col".into(),
                         r#type: "char".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "varchar_col".into(),
                         r#type: "varchar".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "text_col".into(),
                         r#type: "text".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "nchar_col".into(),
                         r#type: "nchar".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "nvarchar_col".into(),
                         r#type: "nvarchar".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "ntext_col".into(),
                         r#type: "ntext".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "binary_col".into(),
                         r#type: "binary".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "varbinary_col".into(),
                         r#type: "varbinary".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "image_col".into(),
                         r#type: "image".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "json_col".into(),
                         r#type: "nvarchar".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "uniqueidentifier_col".into(),
                         r#type: "uniqueidentifier".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "xml_col".into(),
                         r#type: "xml".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "varchar_max_col".into(),
                         r#type: "varchar".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "nvarchar_max_col".into(),
                         r#type: "nvarchar".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "varbinary_max_col".into(),
                         r#type: "varbinary".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                 ],
+                primary_key: vec!["id".into()],
+                // index names and statistics depend on the server
+                indexes: response.0.tables[0].indexes.clone(),
+                row_estimate: response.0.tables[0].row_estimate,
+                ..Default::default()
             }],
         };
 
@@ -394,10 +453,13 @@ mod tests {
         let sql_query = SqlQuery {
             query: "SELECT TOP 1 * FROM [dbo].[all_native_data_types] ORDER BY id".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let state = Extension(Arc::new(new_state().await));
         let connection = get_connection(false);
-        let data = query_with_connection(state, Json(sql_query), (&connection).into())
+        let data = query_with_connection(state, Json(sql_query), (&connection).into(), None)
             .await
             .unwrap();
         let response = data.into_response();
@@ -501,20 +563,26 @@ mod tests {
         let sql_query = SqlQuery {
             query: "SELECT TOP 1 * FROM [dbo].[all_native_data_types] ORDER BY id".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let mut test_state = new_state().await;
         test_state.settings.max_response_bytes = 0;
         let state = Extension(Arc::new(test_state));
         let connection = get_connection(false);
-        let data = query_with_connection(state, Json(sql_query), (&connection).into())
+        let data = query_with_connection(state, Json(sql_query), (&connection).into(), None)
             .await
             .unwrap();
         let response = data.into_response();
 
         assert_eq!(response.status(), StatusCode::OK);
 
+        // the truncation is recorded in the parquet metadata
         let body = response_bytes(response).await;
-        assert_eq!(body, Bytes::new());
+        let summary = QuerySummary::from_parquet(body).unwrap().unwrap();
+        assert!(summary.over_the_limit);
+        assert_eq!(summary.num_records, 0);
     }
 
     #[tokio::test]
@@ -526,8 +594,12 @@ mod tests {
             Json(SqlQuery {
                 query: "SELECT * FROM ALL_NATIVE_DATA_TYPES".into(),
                 connection_id: Uuid::new_v4(),
+                query_id: None,
+                parameters: vec![],
+                force_cache_refresh: false,
             }),
             connection.type_details,
+            None,
         )
         .await
         .unwrap();
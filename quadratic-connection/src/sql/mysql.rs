@@ -18,7 +18,7 @@ use crate::{
     header::get_team_id_header,
     server::{SqlQuery, TestResponse, test_connection},
     ssh::open_ssh_tunnel_for_connection,
-    state::State,
+    state::{State, connection_pool::PoolKey, running_queries::QueryOwner},
 };
 
 use super::{Schema, SchemaQuery, query_generic, schema_generic_with_ssh};
@@ -72,23 +72,35 @@ pub(crate) async fn query(
         &headers,
     )
     .await?;
+    let owner = QueryOwner::new(&claims, team_id);
 
-    query_with_connection(state, sql_query, connection.type_details).await
+    query_with_connection(state, sql_query, connection.type_details, Some(owner)).await
 }
 
 pub(crate) async fn query_with_connection(
     state: Extension<Arc<State>>,
     sql_query: Json<SqlQuery>,
     mut connection: MySqlConnection,
+    owner: Option<QueryOwner>,
 ) -> Result<impl IntoResponse> {
-    let tunnel = open_ssh_tunnel_for_connection(&mut connection).await?;
-    let result = query_generic::<MySqlConnection>(connection, state, sql_query).await?;
-
-    if let Some(mut tunnel) = tunnel {
-        tunnel.close().await?;
-    }
-
-    Ok(result)
+    let connection_id = sql_query.connection_id;
+    let tunnel = state
+        .connection_pools
+        .tunnel(connection_id, &mut connection)
+        .await?;
+    let pool_key = PoolKey::new(connection_id, &connection)?;
+
+    // the tunnel is leased until the results are streamed
+    query_generic::<MySqlConnection>(
+        connection,
+        state,
+        sql_query,
+        owner,
+        pool_key.fingerprint(),
+        Some(pool_key),
+        tunnel,
+    )
+    .await
 }
 
 /// Get the schema of the database
@@ -115,9 +127,9 @@ mod tests {
     };
     use arrow::datatypes::Date32Type;
     use arrow_schema::{DataType, TimeUnit};
-    use bytes::Bytes;
     use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
     use http::StatusCode;
+    use quadratic_rust_shared::sql::parquet_writer::QuerySummary;
     use quadratic_rust_shared::{
         net::ssh::tests::get_ssh_config,
         sql::schema::{SchemaColumn, SchemaTable},
@@ -141,6 +153,9 @@ mod tests {
                 ssh_port: Some(ssh_config.port.to_string()),
                 ssh_username: Some(ssh_config.username.to_string()),
                 ssh_key: Some(ssh_config.private_key.to_string()),
+                tls: Default::default(),
+                read_only: false,
+                cache_ttl_seconds: None,
             }
         } else {
             MySqlConnection {
@@ -154,6 +169,9 @@ mod tests {
                 ssh_port: None,
                 ssh_username: None,
                 ssh_key: None,
+                tls: Default::default(),
+                read_only: false,
+                cache_ttl_seconds: None,
             }
         };
 
@@ -195,158 +213,194 @@ mod tests {
                         name: "id".into(),
                         r#type: "int".into(),
                         is_nullable: false,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "tinyint_col".into(),
                         r#type: "tinyint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "smallint_col".into(),
                         r#type: "smallint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "mediumint_col".into(),
                         r#type: "mediumint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "int_col".into(),
                         r#type: "int".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "bigint_col".into(),
                         r#type: "bigint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "decimal_col".into(),
                         r#type: "decimal".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "float_col".into(),
                         r#type: "float".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "double_col".into(),
                         r#type: "double".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "bit_col".into(),
                         r#type: "bit".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "char_col".into(),
                         r#type: "char".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "varchar_col".into(),
                         r#type: "varchar".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "binary_col".into(),
                         r#type: "binary".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "varbinary_col".into(),
                         r#type: "varbinary".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "tinyblob_col".into(),
                         r#type: "tinyblob".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "blob_col".into(),
                         r#type: "blob".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "mediumblob_col".into(),
                         r#type: "mediumblob".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "longblob_col".into(),
                         r#type: "longblob".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "tinytext_col".into(),
                         r#type: "tinytext".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "text_col".into(),
                         r#type: "text".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "mediumtext_col".into(),
                         r#type: "mediumtext".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "longtext_col".into(),
                         r#type: "longtext".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "enum_col".into(),
                         r#type: "enum".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "set_col".into(),
                         r#type: "set".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "date_col".into(),
                         r#type: "date".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "datetime_col".into(),
                         r#type: "datetime".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "timestamp_col".into(),
                         r#type: "timestamp".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "time_col".into(),
                         r#type: "time".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "year_col".into(),
                         r#type: "year".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "json_col".into(),
                         r#type: "json".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                     SchemaColumn {
                         name: "null_bool_col".into(),
                         r#type: "tinyint".into(),
                         is_nullable: true,
+                        comment: None,
                     },
                 ],
+                primary_key: vec!["id".into()],
+                // index names and statistics depend on the server
+                indexes: response.0.tables[0].indexes.clone(),
+                row_estimate: response.0.tables[0].row_estimate,
+                ..Default::default()
             }],
         };
 
@@ -360,10 +414,13 @@ mod tests {
         let sql_query = SqlQuery {
             query: "select * from all_native_data_types order by id limit 1".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let state = Extension(Arc::new(new_state().await));
         let connection = get_connection(false);
-        let data = query_with_connection(state, Json(sql_query), connection.type_details)
+        let data = query_with_connection(state, Json(sql_query), connection.type_details, None)
             .await
             .unwrap();
         let response = data.into_response();
@@ -441,20 +498,26 @@ mod tests {
         let sql_query = SqlQuery {
             query: "select * from all_native_data_types order by id limit 1".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let mut test_state = new_state().await;
         test_state.settings.max_response_bytes = 0;
         let state = Extension(Arc::new(test_state));
         let connection = get_connection(false);
-        let data = query_with_connection(state, Json(sql_query), connection.type_details)
+        let data = query_with_connection(state, Json(sql_query), connection.type_details, None)
             .await
             .unwrap();
         let response = data.into_response();
 
         assert_eq!(response.status(), StatusCode::OK);
 
+        // the truncation is recorded in the parquet metadata
         let body = response_bytes(response).await;
-        assert_eq!(body, Bytes::new());
+        let summary = QuerySummary::from_parquet(body).unwrap().unwrap();
+        assert!(summary.over_the_limit);
+        assert_eq!(summary.num_records, 0);
     }
 
     #[tokio::test]
@@ -466,8 +529,12 @@ mod tests {
             Json(SqlQuery {
                 query: "SELECT * FROM INFORMATION_SCHEMA.COLUMNS LIMIT 1".into(),
                 connection_id: Uuid::new_v4(),
+                query_id: None,
+                parameters: vec![],
+                force_cache_refresh: false,
             }),
             connection.type_details,
+            None,
         )
         .await
         .unwrap();
@@ -19,7 +19,7 @@ use crate::{
     server::{SqlQuery, TestResponse, test_connection},
     sql::SchemaQuery,
     ssh::open_ssh_tunnel_for_connection,
-    state::State as AppState,
+    state::{State as AppState, connection_pool::PoolKey, running_queries::QueryOwner},
 };
 
 use super::{Schema, query_generic, schema_generic_with_ssh};
@@ -74,8 +74,9 @@ pub(crate) async fn query(
         &headers,
     )
     .await?;
+    let owner = QueryOwner::new(&claims, team_id);
 
-    query_with_connection(state, sql_query, connection.type_details).await
+    query_with_connection(state, sql_query, connection.type_details, Some(owner)).await
 }
 
 /// Query the database and return the results as a parquet file.
@@ -83,15 +84,26 @@ pub(crate) async fn query_with_connection(
     state: Extension<Arc<AppState>>,
     sql_query: Json<SqlQuery>,
     mut connection: PostgresConnection,
+    owner: Option<QueryOwner>,
 ) -> Result<impl IntoResponse> {
-    let tunnel = open_ssh_tunnel_for_connection(&mut connection).await?;
-    let result = query_generic::<PostgresConnection>(connection, state, sql_query).await?;
-
-    if let Some(mut tunnel) = tunnel {
-        tunnel.close().await?;
-    }
-
-    Ok(result)
+    let connection_id = sql_query.connection_id;
+    let tunnel = state
+        .connection_pools
+        .tunnel(connection_id, &mut connection)
+        .await?;
+    let pool_key = PoolKey::new(connection_id, &connection)?;
+
+    // the tunnel is leased until the results are streamed
+    query_generic::<PostgresConnection>(
+        connection,
+        state,
+        sql_query,
+        owner,
+        pool_key.fingerprint(),
+        Some(pool_key),
+        tunnel,
+    )
+    .await
 }
 
 /// Get the schema of the database
@@ -113,15 +125,25 @@ pub(crate) async fn schema(
 pub mod tests {
     use super::*;
     use crate::{
-        num_vec, test_connection,
-        test_util::{new_state, response_bytes, str_vec, validate_parquet},
+        error::ConnectionError,
+        num_vec,
+        sql::cancel_query,
+        state::{connection_pool::fingerprint, query_cache::QueryCache},
+        test_connection,
+        test_util::{
+            get_claims, new_state, new_team_id_with_header, response_bytes, str_vec,
+            validate_parquet,
+        },
     };
+    use std::time::Duration;
 
     use arrow::datatypes::Date32Type;
     use arrow_schema::{DataType, TimeUnit};
-    use bytes::Bytes;
+    use axum::response::Response;
     use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
     use http::StatusCode;
+    use quadratic_rust_shared::sql::parquet_writer::QuerySummary;
+    use quadratic_rust_shared::sql::tls::{SslMode, TlsConfig};
     use quadratic_rust_shared::{
         net::ssh::tests::get_ssh_config, sql::postgres_connection::tests::expected_postgres_schema,
     };
@@ -143,6 +165,9 @@ pub mod tests {
                 ssh_port: Some(ssh_config.port.to_string()),
                 ssh_username: Some(ssh_config.username.to_string()),
                 ssh_key: Some(ssh_config.private_key.to_string()),
+                tls: Default::default(),
+                read_only: false,
+                cache_ttl_seconds: None,
             }
         } else {
             PostgresConnection {
@@ -156,6 +181,9 @@ pub mod tests {
                 ssh_port: None,
                 ssh_username: None,
                 ssh_key: None,
+                tls: Default::default(),
+                read_only: false,
+                cache_ttl_seconds: None,
             }
         };
 
@@ -174,6 +202,21 @@ pub mod tests {
         test_connection!(connection.type_details);
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn postgres_test_connection_tls_error() {
+        let tls = TlsConfig {
+            ssl_mode: SslMode::Require,
+            ..Default::default()
+        };
+        let connection = get_connection(false).type_details.with_tls(tls);
+        let response = test_connection(connection).await;
+
+        // the test database doesn't support TLS
+        assert!(!response.0.connected);
+        assert!(response.0.message.unwrap().contains("TLS error"));
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn postgres_schema() {
@@ -209,10 +252,13 @@ pub mod tests {
         let sql_query = SqlQuery {
             query: "select * from all_native_data_types order by id limit 1".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let state = Extension(Arc::new(new_state().await));
         let connection = get_connection(false);
-        let data = query_with_connection(state, Json(sql_query), connection.type_details)
+        let data = query_with_connection(state, Json(sql_query), connection.type_details, None)
             .await
             .unwrap();
         let response = data.into_response();
@@ -331,20 +377,180 @@ pub mod tests {
         let sql_query = SqlQuery {
             query: "select * from all_native_data_types order by id limit 1".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let mut test_state = new_state().await;
         test_state.settings.max_response_bytes = 0;
         let state = Extension(Arc::new(test_state));
         let connection = get_connection(false);
-        let data = query_with_connection(state, Json(sql_query), connection.type_details)
+        let data = query_with_connection(state, Json(sql_query), connection.type_details, None)
             .await
             .unwrap();
         let response = data.into_response();
 
         assert_eq!(response.status(), StatusCode::OK);
 
+        // the truncation is recorded in the parquet metadata
         let body = response_bytes(response).await;
-        assert_eq!(body, Bytes::new());
+        let summary = QuerySummary::from_parquet(body).unwrap().unwrap();
+        assert!(summary.over_the_limit);
+        assert_eq!(summary.num_records, 0);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn postgres_query_cancel() {
+        let query_id = Uuid::new_v4();
+        let sql_query = SqlQuery {
+            query: "select pg_sleep(30)".into(),
+            connection_id: Uuid::new_v4(),
+            query_id: Some(query_id),
+            parameters: vec![],
+            force_cache_refresh: false,
+        };
+        let state = Arc::new(new_state().await);
+        let cancel_state = Arc::clone(&state);
+        let (team_id, headers) = new_team_id_with_header().await;
+        let (_, other_team_headers) = new_team_id_with_header().await;
+        let owner = QueryOwner::new(&get_claims(), team_id);
+
+        let cancel = tokio::spawn(async move {
+            // give the query time to start
+            tokio::time::sleep(Duration::from_millis(500)).await;
+
+            // only the team that ran the query can cancel it
+            let cancel = |headers| {
+                cancel_query(
+                    Path(query_id),
+                    headers,
+                    Extension(Arc::clone(&cancel_state)),
+                    get_claims(),
+                )
+            };
+            let other_team = cancel(other_team_headers).await.unwrap().0.cancelled;
+
+            (other_team, cancel(headers).await.unwrap().0.cancelled)
+        });
+
+        let connection = get_connection(false);
+        let result = query_with_connection(
+            Extension(state),
+            Json(sql_query),
+            connection.type_details,
+            Some(owner),
+        )
+        .await;
+
+        assert_eq!(cancel.await.unwrap(), (false, true));
+        assert_eq!(
+            result.err(),
+            Some(ConnectionError::Query("Query cancelled".into()))
+        );
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn postgres_query_timeout() {
+        let sql_query = SqlQuery {
+            query: "select pg_sleep(30)".into(),
+            connection_id: Uuid::new_v4(),
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
+        };
+        let mut test_state = new_state().await;
+        test_state.settings.query_timeout = Duration::from_secs(1);
+        let state = Extension(Arc::new(test_state));
+        let connection = get_connection(false);
+        let result =
+            query_with_connection(state, Json(sql_query), connection.type_details, None).await;
+
+        assert_eq!(
+            result.err(),
+            Some(ConnectionError::Query(
+                "Query timed out after 1 seconds".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn postgres_query_read_only() {
+        let state = Extension(Arc::new(new_state().await));
+        let connection = get_connection(false).type_details.with_read_only(true);
+        let sql_query = |query: &str| SqlQuery {
+            query: query.into(),
+            connection_id: Uuid::new_v4(),
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
+        };
+
+        let result = query_with_connection(
+            state.clone(),
+            Json(sql_query("select 1; delete from all_native_data_types")),
+            connection.clone(),
+            None,
+        )
+        .await;
+        assert_eq!(
+            result.err(),
+            Some(ConnectionError::Query(
+                "Read-only connection: DELETE statements are not allowed, only queries that read data can be run".into()
+            ))
+        );
+
+        let data = query_with_connection(state, Json(sql_query("select 1")), connection, None)
+            .await
+            .unwrap();
+        assert_eq!(data.into_response().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn postgres_query_cache() {
+        let state = Extension(Arc::new(new_state().await));
+        let connection = get_connection(false).type_details.with_cache_ttl(Some(60));
+        let connection_id = Uuid::new_v4();
+        let sql = "select * from all_native_data_types order by id limit 1";
+        let fingerprint = fingerprint(&connection).unwrap();
+        let key = QueryCache::key(connection_id, fingerprint, sql, &[]);
+        let query = |force_cache_refresh: bool| {
+            let sql_query = SqlQuery {
+                query: sql.into(),
+                connection_id,
+                query_id: None,
+                parameters: vec![],
+                force_cache_refresh,
+            };
+            query_with_connection(state.clone(), Json(sql_query), connection.clone(), None)
+        };
+        let cache_header = |response: &Response| response.headers().get("QUERY-CACHE").cloned();
+
+        let response = query(false).await.unwrap().into_response();
+        assert_eq!(cache_header(&response), Some("MISS".parse().unwrap()));
+        let data = response_bytes(response).await;
+
+        // the results are cached after the response ends
+        let mut cached = None;
+        for _ in 0..50 {
+            cached = state.query_cache.get(&key, Duration::from_secs(60)).await;
+            if cached.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(cached.unwrap().parquet, data);
+
+        let response = query(false).await.unwrap().into_response();
+        assert_eq!(cache_header(&response), Some("HIT".parse().unwrap()));
+        assert!(response.headers().contains_key("QUERY-CACHE-AGE-MS"));
+        assert_eq!(response_bytes(response).await, data);
+
+        let response = query(true).await.unwrap().into_response();
+        assert_eq!(cache_header(&response), Some("MISS".parse().unwrap()));
     }
 
     #[tokio::test]
@@ -356,8 +562,12 @@ pub mod tests {
             Json(SqlQuery {
                 query: "SELECT * FROM pg_catalog.pg_tables;".into(),
                 connection_id: Uuid::new_v4(),
+                query_id: None,
+                parameters: vec![],
+                force_cache_refresh: false,
             }),
             connection.type_details,
+            None,
         )
         .await
         .unwrap();
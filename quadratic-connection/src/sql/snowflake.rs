@@ -16,7 +16,7 @@ use crate::{
     error::Result,
     header::get_team_id_header,
     server::{SqlQuery, TestResponse},
-    state::State,
+    state::{State, connection_pool::PoolKey, running_queries::QueryOwner},
 };
 
 use super::{Schema, SchemaQuery, query_generic, schema_generic};
@@ -29,8 +29,22 @@ pub(crate) async fn test(
     let sql_query = SqlQuery {
         query: "SELECT 1".into(),
         connection_id: Uuid::new_v4(), // This is not used
+        query_id: None,
+        parameters: vec![],
+        force_cache_refresh: false,
     };
-    let response = query_generic::<SnowflakeConnection>(connection, state, sql_query.into()).await;
+    // tests always run against the database
+    let connection = connection.with_cache_ttl(None);
+    let response = query_generic::<SnowflakeConnection>(
+        connection,
+        state,
+        sql_query.into(),
+        None,
+        0,
+        None,
+        None,
+    )
+    .await;
     let message = match response {
         Ok(_) => None,
         Err(e) => Some(e.to_string()),
@@ -78,7 +92,19 @@ pub(crate) async fn query(
         &headers,
     )
     .await?;
-    query_generic::<SnowflakeConnection>(connection.type_details, state, sql_query).await
+    let pool_key = PoolKey::new(sql_query.connection_id, &connection.type_details)?;
+    let owner = QueryOwner::new(&claims, team_id);
+
+    query_generic::<SnowflakeConnection>(
+        connection.type_details,
+        state,
+        sql_query,
+        Some(owner),
+        pool_key.fingerprint(),
+        Some(pool_key),
+        None,
+    )
+    .await
 }
 
 /// Get the schema of the database
@@ -91,8 +117,9 @@ pub(crate) async fn schema(
 ) -> Result<Json<Schema>> {
     let team_id = get_team_id_header(&headers)?;
     let api_connection = get_connection(&state, &claims, &id, &team_id, &headers).await?;
+    let pool_key = PoolKey::new(api_connection.uuid, &api_connection.type_details)?;
 
-    schema_generic(api_connection, state, params).await
+    schema_generic(api_connection, state, params, Some(pool_key)).await
 }
 
 use std::sync::{Arc, LazyLock, Mutex};
@@ -116,8 +143,8 @@ mod tests {
         get_claims, new_state, new_team_id_with_header, response_bytes, str_vec, validate_parquet,
     };
     use arrow_schema::{DataType, TimeUnit};
-    use bytes::Bytes;
     use http::StatusCode;
+    use quadratic_rust_shared::sql::parquet_writer::QuerySummary;
     use quadratic_rust_shared::sql::snowflake_connection::tests::expected_snowflake_schema;
     use tracing_test::traced_test;
     use uuid::Uuid;
@@ -157,6 +184,9 @@ mod tests {
                 "select * from ALL_NATIVE_DATA_TYPES.ALL_NATIVE_DATA_TYPES.ALL_NATIVE_DATA_TYPES;"
                     .into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let state = Extension(Arc::new(new_state().await));
         let (_, headers) = new_team_id_with_header().await;
@@ -210,6 +240,9 @@ mod tests {
         let sql_query = SqlQuery {
             query: "SELECT TOP 1 * FROM [dbo].[all_native_data_types] ORDER BY id".into(),
             connection_id,
+            query_id: None,
+            parameters: vec![],
+            force_cache_refresh: false,
         };
         let mut test_state = new_state().await;
         test_state.settings.max_response_bytes = 0;
@@ -222,7 +255,10 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
 
+        // the truncation is recorded in the parquet metadata
         let body = response_bytes(response).await;
-        assert_eq!(body, Bytes::new());
+        let summary = QuerySummary::from_parquet(body).unwrap().unwrap();
+        assert!(summary.over_the_limit);
+        assert_eq!(summary.num_records, 0);
     }
 }
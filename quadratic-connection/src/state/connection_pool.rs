@@ -0,0 +1,534 @@
+//! Connection Pools
+//!
+//! Keep database connections and SSH tunnels open between queries, so that
+//! repeated queries don't pay for the TLS, auth and SSH handshakes.
+//!
+//! Pools are per connection id.  A pool is keyed by a hash of the
+//! connection's details, so it's replaced when the connection's config or
+//! credentials change.  Idle connections are health checked before they're
+//! reused, and are dropped after the idle timeout.
+//!
+//! Sessions are reset when they're returned to a pool (see
+//! `Connection::reset`).  The drivers we use for MySQL, SQL Server and
+//! Snowflake can't reset a session, so only their SSH tunnels are reused.
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use quadratic_rust_shared::net::ssh::SshConfig;
+use quadratic_rust_shared::net::ssh_tunnel::SshTunnel;
+use quadratic_rust_shared::sql::{Connection, UsesSsh};
+use serde::Serialize;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::error::{ConnectionError, Result};
+use crate::ssh::open_ssh_tunnel_for_connection;
+
+/// Identifies the pool of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct PoolKey {
+    connection_id: Uuid,
+    fingerprint: u64,
+}
+
+impl PoolKey {
+    /// The fingerprint is a hash of the connection's details (including the
+    /// address of its SSH tunnel)
+    pub(crate) fn new<C: Serialize>(connection_id: Uuid, connection: &C) -> Result<Self> {
+        Ok(PoolKey {
+            connection_id,
+            fingerprint: fingerprint(connection)?,
+        })
+    }
+
+    pub(crate) fn connection_id(&self) -> Uuid {
+        self.connection_id
+    }
+
+    pub(crate) fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+}
+
+/// A hash of a connection's details, which changes when its config or
+/// credentials change
+pub(crate) fn fingerprint<C: Serialize>(connection: &C) -> Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(connection)?.hash(&mut hasher);
+
+    Ok(hasher.finish())
+}
+
+/// A connection checked out of a pool.  Connections without a pool key are
+/// never returned to a pool.
+pub(crate) struct PooledConnection<T> {
+    pub(crate) conn: T,
+    key: Option<PoolKey>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// A lease on a pooled SSH tunnel, which isn't closed while leased
+#[derive(Debug)]
+pub(crate) struct TunnelLease {
+    _lease: Arc<()>,
+}
+
+struct IdleConnection {
+    conn: Box<dyn Any + Send>,
+    idle_since: Instant,
+}
+
+struct Pool {
+    fingerprint: u64,
+    idle: VecDeque<IdleConnection>,
+    permits: Arc<Semaphore>,
+}
+
+struct PooledTunnel {
+    fingerprint: u64,
+    tunnel: SshTunnel,
+    host: String,
+    port: u16,
+    lease: Arc<()>,
+    last_used: Instant,
+}
+
+impl PooledTunnel {
+    fn is_leased(&self) -> bool {
+        Arc::strong_count(&self.lease) > 1
+    }
+
+    async fn close(mut self) {
+        if let Err(e) = self.tunnel.close().await {
+            tracing::warn!("Error closing SSH tunnel: {e}");
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ConnectionPools {
+    max_size: usize,
+    idle_timeout: Duration,
+    pools: Arc<Mutex<HashMap<Uuid, Pool>>>,
+    tunnels: Arc<Mutex<HashMap<Uuid, PooledTunnel>>>,
+
+    // tunnels of replaced pools, closed once no query uses them
+    retired_tunnels: Arc<Mutex<Vec<PooledTunnel>>>,
+}
+
+impl std::fmt::Debug for ConnectionPools {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionPools")
+            .field("max_size", &self.max_size)
+            .field("idle_timeout", &self.idle_timeout)
+            .finish()
+    }
+}
+
+impl ConnectionPools {
+    pub(crate) fn new(max_size: usize, idle_timeout: Duration) -> Self {
+        ConnectionPools {
+            max_size: max_size.max(1),
+            idle_timeout,
+            pools: Arc::new(Mutex::new(HashMap::new())),
+            tunnels: Arc::new(Mutex::new(HashMap::new())),
+            retired_tunnels: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Get a connection from the pool, or connect if there isn't a healthy
+    /// idle connection.  Waits while the pool has `max_size` connections
+    /// checked out.
+    pub(crate) async fn checkout<T>(
+        &self,
+        key: Option<PoolKey>,
+        connection: &T,
+    ) -> Result<PooledConnection<T::Conn>>
+    where
+        T: Connection<'static>,
+        T::Conn: 'static,
+    {
+        let Some(key) = key else {
+            return Ok(PooledConnection {
+                conn: connection.connect().await?,
+                key: None,
+                _permit: None,
+            });
+        };
+
+        let permits = {
+            let mut pools = self.pools.lock().await;
+            let pool = pools
+                .entry(key.connection_id)
+                .or_insert_with(|| self.new_pool(key));
+
+            // the connection's details changed, drop its connections
+            if pool.fingerprint != key.fingerprint {
+                *pool = self.new_pool(key);
+            }
+
+            Arc::clone(&pool.permits)
+        };
+
+        let permit = permits
+            .acquire_owned()
+            .await
+            .map_err(|e| ConnectionError::InternalServer(e.to_string()))?;
+
+        while let Some(idle) = self.take_idle(key).await {
+            if idle.idle_since.elapsed() > self.idle_timeout {
+                continue;
+            }
+
+            let Ok(conn) = idle.conn.downcast::<T::Conn>() else {
+                continue;
+            };
+            let mut conn = *conn;
+
+            match connection.health_check(&mut conn).await {
+                Ok(()) => {
+                    return Ok(PooledConnection {
+                        conn,
+                        key: Some(key),
+                        _permit: Some(permit),
+                    });
+                }
+                Err(e) => tracing::info!("Dropping unhealthy pooled connection: {e}"),
+            }
+        }
+
+        Ok(PooledConnection {
+            conn: connection.connect().await?,
+            key: Some(key),
+            _permit: Some(permit),
+        })
+    }
+
+    /// Reset a connection's session and return it to its pool once a query
+    /// succeeds.  Sessions that can't be reset are dropped, so a query never
+    /// sees the session state left by another.
+    pub(crate) async fn checkin<T>(&self, connection: &T, mut pooled: PooledConnection<T::Conn>)
+    where
+        T: Connection<'static>,
+        T::Conn: 'static,
+    {
+        if pooled.key.is_none() {
+            return;
+        }
+
+        match connection.reset(&mut pooled.conn).await {
+            Ok(()) => self.return_idle(pooled).await,
+            Err(e) => tracing::debug!("Dropping pooled connection that wasn't reset: {e}"),
+        }
+    }
+
+    /// Return a connection to its pool.  Connections of replaced pools are
+    /// dropped.
+    async fn return_idle<C: Send + 'static>(&self, pooled: PooledConnection<C>) {
+        let PooledConnection { conn, key, _permit } = pooled;
+
+        let Some(key) = key else {
+            return;
+        };
+
+        let mut pools = self.pools.lock().await;
+
+        if let Some(pool) = pools.get_mut(&key.connection_id)
+            && pool.fingerprint == key.fingerprint
+        {
+            pool.idle.push_back(IdleConnection {
+                conn: Box::new(conn),
+                idle_since: Instant::now(),
+            });
+        }
+    }
+
+    /// Point `connection` at the connection's pooled SSH tunnel, opening one if
+    /// there isn't one for the connection's current details.
+    pub(crate) async fn tunnel<C>(
+        &self,
+        connection_id: Uuid,
+        connection: &mut C,
+    ) -> Result<Option<TunnelLease>>
+    where
+        C: Connection<'static> + Clone + Serialize + UsesSsh + 'static,
+        C: TryInto<SshConfig>,
+        <C as TryInto<SshConfig>>::Error: Into<ConnectionError>,
+    {
+        if !connection.use_ssh() {
+            return Ok(None);
+        }
+
+        let fingerprint = fingerprint(connection)?;
+        let mut tunnels = self.tunnels.lock().await;
+
+        let reusable = tunnels.get(&connection_id).is_some_and(|pooled| {
+            pooled.fingerprint == fingerprint && pooled.tunnel.is_connected()
+        });
+
+        if !reusable {
+            if let Some(stale) = tunnels.remove(&connection_id) {
+                self.retire_tunnel(stale).await;
+            }
+
+            let tunnel = open_ssh_tunnel_for_connection(connection)
+                .await?
+                .ok_or_else(|| ConnectionError::Ssh("Expected an SSH tunnel".into()))?;
+            let port = connection
+                .port()
+                .ok_or_else(|| ConnectionError::Ssh("Port is required".into()))??;
+
+            tunnels.insert(
+                connection_id,
+                PooledTunnel {
+                    fingerprint,
+                    tunnel,
+                    host: connection.host(),
+                    port,
+                    lease: Arc::new(()),
+                    last_used: Instant::now(),
+                },
+            );
+        }
+
+        let pooled = tunnels
+            .get_mut(&connection_id)
+            .ok_or_else(|| ConnectionError::Ssh("Expected an SSH tunnel".into()))?;
+
+        connection.set_host(pooled.host.to_owned());
+        connection.set_port(pooled.port);
+        pooled.last_used = Instant::now();
+
+        Ok(Some(TunnelLease {
+            _lease: Arc::clone(&pooled.lease),
+        }))
+    }
+
+    /// Drop a connection's pool and retire its SSH tunnel, eg, after its
+    /// tunnel stops working.
+    pub(crate) async fn evict(&self, connection_id: Uuid) {
+        self.pools.lock().await.remove(&connection_id);
+
+        if let Some(tunnel) = self.tunnels.lock().await.remove(&connection_id) {
+            self.retire_tunnel(tunnel).await;
+        }
+    }
+
+    /// Drop connections and close tunnels that have been idle longer than
+    /// the idle timeout.  This is run periodically.
+    pub(crate) async fn evict_idle(&self) {
+        {
+            let mut pools = self.pools.lock().await;
+
+            for pool in pools.values_mut() {
+                pool.idle
+                    .retain(|idle| idle.idle_since.elapsed() <= self.idle_timeout);
+            }
+
+            pools.retain(|_, pool| {
+                !pool.idle.is_empty() || pool.permits.available_permits() < self.max_size
+            });
+        }
+
+        let idle_tunnels = {
+            let mut tunnels = self.tunnels.lock().await;
+            let idle_ids = tunnels
+                .iter()
+                .filter(|(_, pooled)| {
+                    !pooled.is_leased() && pooled.last_used.elapsed() > self.idle_timeout
+                })
+                .map(|(connection_id, _)| *connection_id)
+                .collect::<Vec<_>>();
+
+            idle_ids
+                .iter()
+                .filter_map(|connection_id| tunnels.remove(connection_id))
+                .collect::<Vec<_>>()
+        };
+
+        for tunnel in idle_tunnels {
+            tunnel.close().await;
+        }
+
+        let closable = {
+            let mut retired = self.retired_tunnels.lock().await;
+            let (leased, closable): (Vec<_>, Vec<_>) =
+                retired.drain(..).partition(PooledTunnel::is_leased);
+            *retired = leased;
+
+            closable
+        };
+
+        for tunnel in closable {
+            tunnel.close().await;
+        }
+    }
+
+    fn new_pool(&self, key: PoolKey) -> Pool {
+        Pool {
+            fingerprint: key.fingerprint,
+            idle: VecDeque::new(),
+            permits: Arc::new(Semaphore::new(self.max_size)),
+        }
+    }
+
+    /// Take the most recently returned idle connection of a pool
+    async fn take_idle(&self, key: PoolKey) -> Option<IdleConnection> {
+        self.pools
+            .lock()
+            .await
+            .get_mut(&key.connection_id)
+            .filter(|pool| pool.fingerprint == key.fingerprint)
+            .and_then(|pool| pool.idle.pop_back())
+    }
+
+    /// Close a tunnel now if no query uses it, otherwise once they're done
+    async fn retire_tunnel(&self, tunnel: PooledTunnel) {
+        if tunnel.is_leased() {
+            self.retired_tunnels.lock().await.push(tunnel);
+        } else {
+            tunnel.close().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quadratic_rust_shared::sql::mysql_connection::MySqlConnection;
+    use quadratic_rust_shared::sql::postgres_connection::PostgresConnection;
+    use quadratic_rust_shared::sql::postgres_connection::tests::new_postgres_connection;
+
+    fn new_connection(database: &str) -> PostgresConnection {
+        PostgresConnection::new(
+            Some("user".into()),
+            Some("password".into()),
+            "localhost".into(),
+            Some("5432".into()),
+            database.into(),
+            Some(false),
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_pool_key_changes_with_connection_details() {
+        let connection_id = Uuid::new_v4();
+        let key = PoolKey::new(connection_id, &new_connection("db")).unwrap();
+
+        assert_eq!(
+            key,
+            PoolKey::new(connection_id, &new_connection("db")).unwrap()
+        );
+        assert_ne!(
+            key,
+            PoolKey::new(connection_id, &new_connection("other")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_pools_checkin() {
+        let pools = ConnectionPools::new(2, Duration::from_secs(60));
+        let connection_id = Uuid::new_v4();
+        let key = PoolKey::new(connection_id, &new_connection("db")).unwrap();
+        let pooled = PooledConnection {
+            conn: 1_u8,
+            key: Some(key),
+            _permit: None,
+        };
+
+        // connections are only returned to an existing pool
+        pools.return_idle(pooled).await;
+        assert!(pools.take_idle(key).await.is_none());
+
+        pools
+            .pools
+            .lock()
+            .await
+            .insert(connection_id, pools.new_pool(key));
+        let pooled = PooledConnection {
+            conn: 1_u8,
+            key: Some(key),
+            _permit: None,
+        };
+        pools.return_idle(pooled).await;
+
+        // a different fingerprint doesn't reuse the connection
+        let other_key = PoolKey::new(connection_id, &new_connection("other")).unwrap();
+        assert!(pools.take_idle(other_key).await.is_none());
+
+        let idle = pools.take_idle(key).await.unwrap();
+        assert_eq!(*idle.conn.downcast::<u8>().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connection_pools_evict_idle() {
+        let pools = ConnectionPools::new(2, Duration::ZERO);
+        let connection_id = Uuid::new_v4();
+        let key = PoolKey::new(connection_id, &new_connection("db")).unwrap();
+
+        pools
+            .pools
+            .lock()
+            .await
+            .insert(connection_id, pools.new_pool(key));
+        pools
+            .return_idle(PooledConnection {
+                conn: 1_u8,
+                key: Some(key),
+                _permit: None,
+            })
+            .await;
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        pools.evict_idle().await;
+
+        assert!(pools.pools.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connection_pools_reuse_connection() {
+        let pools = ConnectionPools::new(1, Duration::from_secs(60));
+        let connection = new_postgres_connection();
+        let key = PoolKey::new(Uuid::new_v4(), &connection).unwrap();
+
+        let pooled = pools.checkout(Some(key), &connection).await.unwrap();
+        pools.checkin(&connection, pooled).await;
+
+        let pooled = pools.checkout(Some(key), &connection).await.unwrap();
+        assert!(pools.take_idle(key).await.is_none());
+        pools.checkin(&connection, pooled).await;
+        assert!(pools.take_idle(key).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_connection_pools_drop_sessions_that_are_not_reset() {
+        let pools = ConnectionPools::new(1, Duration::from_secs(60));
+        let connection = MySqlConnection::new(
+            Some("user".into()),
+            Some("password".into()),
+            "0.0.0.0".into(),
+            Some("3306".into()),
+            "mysql-connection".into(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let key = PoolKey::new(Uuid::new_v4(), &connection).unwrap();
+
+        // MySQL sessions can't be reset, so they aren't reused
+        let pooled = pools.checkout(Some(key), &connection).await.unwrap();
+        pools.checkin(&connection, pooled).await;
+        assert!(pools.take_idle(key).await.is_none());
+    }
+}
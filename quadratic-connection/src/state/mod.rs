@@ -3,20 +3,27 @@
 //! Store information about the state of the application in a send + sync
 //! struct.  All access and mutations to state should be performed here.
 
+pub mod connection_pool;
+pub mod query_cache;
+pub mod running_queries;
 pub mod schema_cache;
 pub mod settings;
 pub mod stats;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use jsonwebtoken::jwk::JwkSet;
 use quadratic_rust_shared::intrinio::client::IntrinioClient;
 use reqwest::Client;
-use reqwest::redirect::Policy;
 use tokio::sync::Mutex;
 
 use crate::config::Config;
-use crate::error::{Result, proxy_error};
+use crate::egress::EgressPolicy;
+use crate::error::Result;
+use crate::state::connection_pool::ConnectionPools;
+use crate::state::query_cache::QueryCache;
+use crate::state::running_queries::RunningQueries;
 use crate::state::schema_cache::SchemaCache;
 use crate::state::settings::Settings;
 
@@ -26,24 +33,34 @@ use self::stats::Stats;
 pub(crate) struct State {
     pub(crate) settings: Settings,
     pub(crate) client: Client,
+    pub(crate) egress_policy: Arc<EgressPolicy>,
     pub(crate) intrinio_client: IntrinioClient,
     pub(crate) schema_cache: SchemaCache,
+    pub(crate) query_cache: QueryCache,
+    pub(crate) running_queries: RunningQueries,
+    pub(crate) connection_pools: ConnectionPools,
     pub(crate) stats: Arc<Mutex<Stats>>,
 }
 
 impl State {
     pub(crate) async fn new(config: &Config, jwks: Option<JwkSet>) -> Result<Self> {
         let intrinio_client = IntrinioClient::new(&config.intrinio_api_key);
+        let egress_policy = Arc::new(EgressPolicy::new(config));
+        let settings = Settings::new(config, jwks).await?;
+        let query_cache = QueryCache::new(Arc::clone(&settings.synced_data_storage));
 
         Ok(State {
-            settings: Settings::new(config, jwks).await?,
-            client: Client::builder()
-                .cookie_store(true)
-                .redirect(Policy::limited(5))
-                .build()
-                .map_err(proxy_error)?,
+            settings,
+            client: egress_policy.client()?,
+            egress_policy,
             intrinio_client,
             schema_cache: SchemaCache::new(),
+            query_cache,
+            running_queries: RunningQueries::new(),
+            connection_pools: ConnectionPools::new(
+                config.pool_max_size,
+                Duration::from_secs(config.pool_idle_timeout_s),
+            ),
             stats: Arc::new(Mutex::new(Stats::new())),
         })
     }
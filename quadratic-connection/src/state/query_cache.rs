@@ -0,0 +1,261 @@
+//! Query Cache
+//!
+//! Cache the parquet results of queries in storage so that recomputing a
+//! connection cell doesn't run the same query against the database again.
+//! Results are keyed by the connection (its id and the fingerprint of its
+//! details, so results cached before it changed aren't used), the normalized
+//! SQL and the bound parameters, and are only used while they're younger than
+//! the connection's TTL (see `Connection::cache_ttl`).
+//!
+//! Expired results aren't deleted, they're overwritten the next time the
+//! query runs.  Storage lifecycle rules can expire the `query-cache/` prefix.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use quadratic_rust_shared::sql::parameter::SqlParameter;
+use quadratic_rust_shared::storage::{Storage, StorageContainer};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const KEY_PREFIX: &str = "query-cache";
+
+/// The cached results are prefixed with the time they were cached (in
+/// milliseconds since the epoch)
+const HEADER_BYTES: usize = 8;
+
+/// Results read from the cache
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CachedResult {
+    pub(crate) parquet: Bytes,
+    pub(crate) age: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct QueryCache {
+    storage: Arc<StorageContainer>,
+}
+
+impl QueryCache {
+    pub(crate) fn new(storage: Arc<StorageContainer>) -> Self {
+        Self { storage }
+    }
+
+    /// The storage key of a query's results.  `fingerprint` is the hash of
+    /// the connection's details (see `connection_pool::fingerprint`).
+    pub(crate) fn key(
+        connection_id: Uuid,
+        fingerprint: u64,
+        query: &str,
+        parameters: &[SqlParameter],
+    ) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(fingerprint.to_be_bytes());
+        hasher.update(normalize_sql(query).as_bytes());
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(parameters).unwrap_or_default());
+
+        format!("{KEY_PREFIX}/{connection_id}/{:x}", hasher.finalize())
+    }
+
+    /// Get a query's results from the cache.
+    /// If the results are not found or are older than the TTL, None is
+    /// returned.
+    pub(crate) async fn get(&self, key: &str, ttl: Duration) -> Option<CachedResult> {
+        let mut data = match self.storage.read(key).await {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::trace!("Query results for {key} are not cached: {e}");
+                return None;
+            }
+        };
+
+        if data.len() < HEADER_BYTES {
+            tracing::warn!("Cached query results for {key} are invalid");
+            return None;
+        }
+
+        let parquet = data.split_off(HEADER_BYTES);
+        let cached_at = u64::from_be_bytes(data[..].try_into().ok()?);
+        let age = Duration::from_millis(now_ms().saturating_sub(cached_at));
+
+        (age < ttl).then_some(CachedResult { parquet, age })
+    }
+
+    /// Add a query's results to the cache.  Results that can't be written
+    /// are logged, the query has already succeeded.
+    pub(crate) async fn add(&self, key: &str, parquet: Bytes) {
+        let mut data = BytesMut::with_capacity(HEADER_BYTES + parquet.len());
+        data.put_u64(now_ms());
+        data.put(parquet);
+
+        if let Err(e) = self.storage.write(key, &data.freeze()).await {
+            tracing::warn!("Error caching query results for {key}: {e}");
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Normalize a query so that formatting changes don't miss the cache.
+///
+/// Runs of whitespace are collapsed into a single space (or a newline, so
+/// that line comments still end), and surrounding whitespace and trailing
+/// semicolons are removed.  Quoted strings and identifiers and comments are
+/// kept as they are.  Queries whose quoting can't be followed reliably
+/// (backslash escapes and dollar quotes) are only trimmed, since two queries
+/// must never share a key.
+fn normalize_sql(sql: &str) -> String {
+    let trimmed = sql.trim().trim_end_matches([';', ' ', '\t', '\r', '\n']);
+    let chars = trimmed.chars().collect::<Vec<char>>();
+    let mut normalized = String::with_capacity(trimmed.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        // the end of a verbatim region, or None outside of one
+        let end: Option<&[char]> = match (c, next) {
+            ('\'', _) => Some(&['\'']),
+            ('"', _) => Some(&['"']),
+            ('`', _) => Some(&['`']),
+            ('-', Some('-')) | ('#', _) => Some(&['\n']),
+            ('/', Some('*')) => Some(&['*', '/']),
+            ('$', Some(next)) if next == '$' || next.is_alphabetic() || next == '_' => {
+                return trimmed.to_string();
+            }
+            _ => None,
+        };
+
+        if let Some(end) = end {
+            let is_quoted = matches!(c, '\'' | '"' | '`');
+            let start = i;
+            i += 1;
+
+            while i < chars.len() && !chars[i..].starts_with(end) {
+                if is_quoted && chars[i] == '\\' {
+                    return trimmed.to_string();
+                }
+                i += 1;
+            }
+
+            // line comments end with the newline, which is whitespace
+            let is_line_comment = end == ['\n'];
+            i = (i + if is_line_comment { 0 } else { end.len() }).min(chars.len());
+            normalized.extend(&chars[start..i]);
+        } else if c.is_whitespace() {
+            let start = i;
+
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+
+            match chars[start..i].contains(&'\n') {
+                true => normalized.push('\n'),
+                false => normalized.push(' '),
+            }
+        } else {
+            normalized.push(c);
+            i += 1;
+        }
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::connection_pool::fingerprint;
+    use crate::test_util::new_state;
+
+    #[test]
+    fn test_normalize_sql() {
+        assert_eq!(
+            normalize_sql("  select *\tfrom  users ;; "),
+            "select * from users"
+        );
+        assert_eq!(
+            normalize_sql("select *\r\n  from users\n\nwhere id = 1;"),
+            "select *\nfrom users\nwhere id = 1"
+        );
+
+        // quoted strings, identifiers and comments are kept
+        assert_eq!(
+            normalize_sql("select  'a  b', \"c  d\"  /* x  y */ from t"),
+            "select 'a  b', \"c  d\" /* x  y */ from t"
+        );
+        assert_eq!(
+            normalize_sql("select 1 -- don't\n  ,  'a  b'"),
+            "select 1 -- don't\n, 'a  b'"
+        );
+        assert_eq!(normalize_sql("select 'it''s  ok'"), "select 'it''s  ok'");
+
+        // quoting that can't be followed is only trimmed
+        assert_eq!(normalize_sql(" select 'a\\'  b'  "), "select 'a\\'  b'");
+        assert_eq!(normalize_sql("select $$a  b$$"), "select $$a  b$$");
+        assert_eq!(normalize_sql("select  $1"), "select $1");
+    }
+
+    #[test]
+    fn test_query_cache_key() {
+        let connection_id = Uuid::new_v4();
+        let key = |query: &str, parameters: &[SqlParameter]| {
+            QueryCache::key(connection_id, 1, query, parameters)
+        };
+        let parameter = [SqlParameter::Text("a".into())];
+
+        assert_eq!(key("select  1;", &[]), key("select 1", &[]));
+        assert_ne!(key("select 1", &[]), key("select 2", &[]));
+        assert_ne!(key("select $1", &[]), key("select $1", &parameter));
+        assert_ne!(
+            key("select 1", &[]),
+            QueryCache::key(Uuid::new_v4(), 1, "select 1", &[])
+        );
+        assert!(key("select 1", &[]).starts_with(&format!("query-cache/{connection_id}/")));
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() {
+        let cache = new_state().await.query_cache;
+        let key = QueryCache::key(Uuid::new_v4(), 1, "select 1", &[]);
+        let parquet = Bytes::from_static(b"PAR1");
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(cache.get(&key, ttl).await, None);
+
+        cache.add(&key, parquet.clone()).await;
+        let cached = cache.get(&key, ttl).await.unwrap();
+        assert_eq!(cached.parquet, parquet);
+        assert!(cached.age < ttl);
+
+        // expired
+        assert_eq!(cache.get(&key, Duration::ZERO).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_changed_connection() {
+        let cache = new_state().await.query_cache;
+        let connection_id = Uuid::new_v4();
+        let connection = |password: &str| {
+            let details = serde_json::json!({ "host": "db", "password": password });
+            fingerprint(&details).unwrap()
+        };
+        let key = |fingerprint: u64| QueryCache::key(connection_id, fingerprint, "select 1", &[]);
+        let ttl = Duration::from_secs(60);
+
+        cache
+            .add(&key(connection("old")), Bytes::from_static(b"PAR1"))
+            .await;
+
+        assert!(cache.get(&key(connection("old")), ttl).await.is_some());
+        assert_eq!(cache.get(&key(connection("new")), ttl).await, None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Running Queries
+//!
+//! Track in-flight queries by id so that they can be cancelled by the user
+//! that ran them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, oneshot};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::error::{ConnectionError, Result};
+
+/// The user and team that ran a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct QueryOwner {
+    email: String,
+    team_id: Uuid,
+}
+
+impl QueryOwner {
+    pub(crate) fn new(claims: &Claims, team_id: Uuid) -> Self {
+        Self {
+            email: claims.email.to_owned(),
+            team_id,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RunningQuery {
+    owner: Option<QueryOwner>,
+    cancel: oneshot::Sender<()>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RunningQueries {
+    queries: Arc<Mutex<HashMap<Uuid, RunningQuery>>>,
+}
+
+impl RunningQueries {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a query, returning a receiver that resolves when the query is
+    /// cancelled.  Queries without an owner (e.g. connection tests) can't be
+    /// cancelled.
+    pub(crate) async fn start(
+        &self,
+        query_id: Uuid,
+        owner: Option<QueryOwner>,
+    ) -> Result<oneshot::Receiver<()>> {
+        let mut queries = self.queries.lock().await;
+
+        if queries.contains_key(&query_id) {
+            return Err(ConnectionError::Query(format!(
+                "Query {query_id} is already running"
+            )));
+        }
+
+        let (cancel, receiver) = oneshot::channel();
+        queries.insert(query_id, RunningQuery { owner, cancel });
+
+        Ok(receiver)
+    }
+
+    /// Cancel a running query.
+    /// Returns false if the query is not running or was run by someone else.
+    pub(crate) async fn cancel(&self, query_id: Uuid, owner: &QueryOwner) -> bool {
+        let mut queries = self.queries.lock().await;
+
+        match queries.get(&query_id) {
+            Some(query) if query.owner.as_ref() == Some(owner) => queries
+                .remove(&query_id)
+                .is_some_and(|query| query.cancel.send(()).is_ok()),
+            _ => false,
+        }
+    }
+
+    /// Remove a query once it completes.
+    pub(crate) async fn finish(&self, query_id: Uuid) {
+        self.queries.lock().await.remove(&query_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::get_claims;
+
+    #[tokio::test]
+    async fn test_running_queries() {
+        let running_queries = RunningQueries::new();
+        let query_id = Uuid::new_v4();
+        let owner = QueryOwner::new(&get_claims(), Uuid::new_v4());
+
+        let receiver = running_queries
+            .start(query_id, Some(owner.clone()))
+            .await
+            .unwrap();
+
+        // ids are unique while the query runs
+        assert!(running_queries.start(query_id, None).await.is_err());
+
+        assert!(running_queries.cancel(query_id, &owner).await);
+        assert!(receiver.await.is_ok());
+
+        // cancelling a query that isn't running is a no-op
+        assert!(!running_queries.cancel(query_id, &owner).await);
+
+        // a finished query can't be cancelled
+        let _receiver = running_queries
+            .start(query_id, Some(owner.clone()))
+            .await
+            .unwrap();
+        running_queries.finish(query_id).await;
+        assert!(!running_queries.cancel(query_id, &owner).await);
+    }
+
+    #[tokio::test]
+    async fn test_running_queries_owner() {
+        let running_queries = RunningQueries::new();
+        let query_id = Uuid::new_v4();
+        let owner = QueryOwner::new(&get_claims(), Uuid::new_v4());
+
+        let _receiver = running_queries
+            .start(query_id, Some(owner.clone()))
+            .await
+            .unwrap();
+
+        // another user or team can't cancel the query
+        let mut claims = get_claims();
+        claims.email = "other@test.com".into();
+        let other_user = QueryOwner::new(&claims, owner.team_id);
+        let other_team = QueryOwner::new(&get_claims(), Uuid::new_v4());
+
+        assert!(!running_queries.cancel(query_id, &other_user).await);
+        assert!(!running_queries.cancel(query_id, &other_team).await);
+        assert!(running_queries.cancel(query_id, &owner).await);
+
+        // queries without an owner can't be cancelled
+        let _receiver = running_queries.start(query_id, None).await.unwrap();
+        assert!(!running_queries.cancel(query_id, &owner).await);
+    }
+}
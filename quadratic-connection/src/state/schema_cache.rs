@@ -61,6 +61,7 @@ mod tests {
                 name: "test".into(),
                 schema: "test".into(),
                 columns: expected_bigquery_schema(),
+                ..Default::default()
             }],
         };
         let cache = SchemaCache::new();
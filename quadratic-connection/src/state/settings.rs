@@ -7,7 +7,9 @@ use quadratic_rust_shared::storage::file_system::{FileSystem, FileSystemConfig};
 use quadratic_rust_shared::storage::s3::{S3, S3Config};
 use quadratic_rust_shared::storage::{StorageConfig, StorageContainer, StorageType};
 use quadratic_rust_shared::synced::plaid::client::PlaidEnvironment;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::config::Config;
 use crate::error::{ConnectionError, Result};
@@ -19,7 +21,10 @@ pub(crate) struct Settings {
     pub(crate) m2m_auth_token: String,
     pub(crate) jwks: Option<JwkSet>,
     pub(crate) max_response_bytes: u64,
+    pub(crate) query_timeout: Duration,
+    pub(crate) database_files_dir: Option<PathBuf>,
     pub(crate) datafusion_connection: DatafusionConnection,
+    pub(crate) synced_data_storage: Arc<StorageContainer>,
     pub(crate) plaid_client_id: String,
     pub(crate) plaid_secret: String,
     pub(crate) plaid_environment: PlaidEnvironment,
@@ -41,7 +46,14 @@ impl Settings {
             m2m_auth_token: config.m2m_auth_token.to_owned(),
             jwks,
             max_response_bytes: config.max_response_bytes,
+            query_timeout: Duration::from_secs(config.query_timeout_s),
+            database_files_dir: config
+                .database_files_dir
+                .as_ref()
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
             datafusion_connection,
+            synced_data_storage: Arc::new(synced_data_storage),
             plaid_client_id: config.plaid_client_id.to_owned(),
             plaid_secret: config.plaid_secret.to_owned(),
             plaid_environment: config.plaid_environment.to_owned(),
@@ -10,10 +10,9 @@ use std::io::Read;
 use std::sync::Arc;
 
 use arrow_schema::DataType;
-use axum::body::Body;
+use axum::body::{Body, to_bytes};
 use axum::response::Response;
 use bytes::Bytes;
-use futures::StreamExt;
 use http::{HeaderMap, HeaderName, HeaderValue};
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 use parquet::data_type::AsBytes;
@@ -96,11 +95,7 @@ pub(crate) fn get_claims() -> Claims {
 }
 
 pub(crate) async fn response_bytes(response: Response) -> Bytes {
-    StreamExt::into_future(response.into_body().into_data_stream())
-        .await
-        .0
-        .unwrap_or(Ok(Bytes::new()))
-        .unwrap()
+    to_bytes(response.into_body(), usize::MAX).await.unwrap()
 }
 
 pub(crate) async fn response_json<T: DeserializeOwned>(response: Response) -> T {
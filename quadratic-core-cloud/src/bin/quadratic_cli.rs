@@ -0,0 +1,19 @@
+//! Recalculates and converts Quadratic files from the command line.
+//!
+//! Run `quadratic_cli --help` for usage.
+
+use std::process::ExitCode;
+
+use clap::Parser;
+use quadratic_core_cloud::cli::{Args, run};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
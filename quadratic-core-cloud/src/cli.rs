@@ -0,0 +1,453 @@
+//! Headless Command Line Interface
+//!
+//! Opens a .grid, CSV, Excel or Parquet file, sets cell values, recalculates
+//! code cells and exports the result. Formulas are always recalculated.
+//! Python, JavaScript and connection cells are only run with `--run-code`,
+//! using the same runners as the cloud worker.
+//!
+//! ```shell
+//! quadratic_cli model.grid --set Inputs!B2=0.05 --output model.grid --output report.xlsx
+//! quadratic_cli data.csv --output data.parquet
+//! ```
+
+use std::{path::Path, path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use quadratic_core::{
+    Pos, SheetPos,
+    a1::A1Selection,
+    controller::{
+        GridController, active_transactions::transaction_name::TransactionName,
+        operations::operation::Operation,
+    },
+    grid::{
+        CodeCellLanguage, Grid, SheetId,
+        file::{export, export_json, import},
+    },
+    pos,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    core::process_transaction,
+    error::{CoreCloudError, Result},
+};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "quadratic_cli",
+    version,
+    about = "Recalculate and convert Quadratic files"
+)]
+pub struct Args {
+    /// File to open (.grid, .csv, .xlsx or .parquet)
+    pub input: PathBuf,
+
+    /// Sets a cell before recalculating, eg `B2=100` or `Inputs!B2==A1*2`.
+    /// Values starting with `=` are formulas.
+    #[arg(long = "set", value_name = "[SHEET!]CELL=VALUE")]
+    pub set: Vec<String>,
+
+    /// Sheet used for cells without a sheet name and for CSV and Parquet
+    /// output (defaults to the first sheet)
+    #[arg(long)]
+    pub sheet: Option<String>,
+
+    /// Skips recalculating the code cells in the file
+    #[arg(long)]
+    pub no_recalc: bool,
+
+    /// Also runs Python, JavaScript and connection cells
+    #[arg(long)]
+    pub run_code: bool,
+
+    /// Team used to run connection cells
+    #[arg(long, env = "TEAM_ID", default_value = "")]
+    pub team_id: String,
+
+    /// Token used to run connection cells
+    #[arg(
+        long,
+        env = "M2M_AUTH_TOKEN",
+        default_value = "",
+        hide_env_values = true
+    )]
+    pub token: String,
+
+    /// URL of the connection service
+    #[arg(long, env = "CONNECTION_URL", default_value = "http://localhost:3003")]
+    pub connection_url: String,
+
+    /// File to write (.grid, .xlsx, .csv, .parquet or .json). May be repeated.
+    #[arg(long, short)]
+    pub output: Vec<PathBuf>,
+}
+
+/// File formats the CLI reads and writes, by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Grid,
+    Excel,
+    Csv,
+    Parquet,
+    Json,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match extension.as_str() {
+            "grid" => Ok(FileFormat::Grid),
+            "xlsx" | "xlsm" | "xls" | "ods" => Ok(FileFormat::Excel),
+            "csv" => Ok(FileFormat::Csv),
+            "parquet" | "parq" => Ok(FileFormat::Parquet),
+            "json" => Ok(FileFormat::Json),
+            _ => Err(CoreCloudError::Argument(format!(
+                "Unsupported file type: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// The format to write a file in. Excel files are only written as .xlsx.
+    pub fn from_output_path(path: &Path) -> Result<Self> {
+        let format = Self::from_path(path)?;
+        let is_xlsx = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("xlsx"));
+
+        match format {
+            FileFormat::Excel if !is_xlsx => Err(CoreCloudError::Argument(format!(
+                "Excel files can only be written as .xlsx: {}",
+                path.display()
+            ))),
+            format => Ok(format),
+        }
+    }
+}
+
+/// Runs the CLI.
+pub async fn run(args: Args) -> Result<()> {
+    for path in &args.output {
+        FileFormat::from_output_path(path)?;
+    }
+
+    let mut grid = open(&args.input)?;
+    let sheet_id = sheet_id(&grid, args.sheet.as_deref())?;
+
+    let mut batches = vec![];
+    for set in &args.set {
+        batches.extend(set_operations(&mut grid, sheet_id, set)?);
+    }
+    if !args.no_recalc {
+        batches.push(recalc_operations(&grid, args.run_code));
+    }
+
+    let grid = Arc::new(Mutex::new(grid));
+    for operations in batches.into_iter().filter(|ops| !ops.is_empty()) {
+        if args.run_code {
+            process_transaction(
+                Arc::clone(&grid),
+                operations,
+                None,
+                TransactionName::Unknown,
+                args.team_id.clone(),
+                args.token.clone(),
+                args.connection_url.clone(),
+            )
+            .await?;
+        } else {
+            grid.lock().await.start_user_ai_transaction(
+                operations,
+                None,
+                TransactionName::Unknown,
+                false,
+            );
+        }
+
+        if let Some(pending) = grid
+            .lock()
+            .await
+            .active_transactions()
+            .async_transactions()
+            .first()
+        {
+            let cell = pending
+                .current_sheet_pos
+                .map(|sheet_pos| format!(" at {}", Pos::from(sheet_pos).a1_string()))
+                .unwrap_or_default();
+            return Err(CoreCloudError::Core(format!(
+                "A Python, JavaScript or connection cell{cell} needs to run; rerun with --run-code"
+            )));
+        }
+    }
+
+    let grid = grid.lock().await;
+    for path in &args.output {
+        save(&grid, sheet_id, path)?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Opens a file as a new grid.
+pub fn open(path: &Path) -> Result<GridController> {
+    let file_name = path.display().to_string();
+    let file = std::fs::read(path)
+        .map_err(|e| CoreCloudError::LoadFile(file_name.clone(), e.to_string()))?;
+    let import_error = |e: String| CoreCloudError::ImportFile(file_name.clone(), e);
+
+    match FileFormat::from_path(path)? {
+        FileFormat::Grid => Ok(GridController::from_grid(
+            import(file).map_err(|e| import_error(e.to_string()))?,
+            0,
+        )),
+        FileFormat::Excel => {
+            let mut grid = GridController::from_grid(Grid::new_blank(), 0);
+            grid.import_excel(&file, &file_name, None, false)
+                .map_err(|e| import_error(e.to_string()))?;
+            Ok(grid)
+        }
+        FileFormat::Csv => {
+            let (mut grid, sheet_id) = blank_grid();
+            grid.import_csv(
+                sheet_id,
+                &file,
+                &file_name,
+                pos![A1],
+                None,
+                None,
+                None,
+                false,
+                false,
+            )
+            .map_err(|e| import_error(e.to_string()))?;
+            Ok(grid)
+        }
+        FileFormat::Parquet => {
+            let (mut grid, sheet_id) = blank_grid();
+            grid.import_parquet(
+                sheet_id,
+                file,
+                &file_name,
+                pos![A1],
+                None,
+                None::<fn(&str, u32, u32)>,
+                false,
+                false,
+            )
+            .map_err(|e| import_error(e.to_string()))?;
+            Ok(grid)
+        }
+        FileFormat::Json => Err(CoreCloudError::Argument(format!(
+            "Cannot open JSON files: {file_name}"
+        ))),
+    }
+}
+
+/// Writes the grid to a file. CSV and Parquet files contain a single sheet.
+pub fn save(grid: &GridController, sheet_id: SheetId, path: &Path) -> Result<()> {
+    let file_name = path.display().to_string();
+    let export_error = |e: String| CoreCloudError::ExportFile(file_name.clone(), e);
+
+    let file = match FileFormat::from_output_path(path)? {
+        FileFormat::Grid => export(grid.grid().clone()).map_err(|e| export_error(e.to_string()))?,
+        FileFormat::Json => {
+            export_json(grid.grid().clone()).map_err(|e| export_error(e.to_string()))?
+        }
+        FileFormat::Excel => grid
+            .export_excel()
+            .map_err(|e| export_error(e.to_string()))?,
+        FileFormat::Csv => grid
+            .export_csv_selection(&mut A1Selection::all(sheet_id))
+            .map_err(|e| export_error(e.to_string()))?
+            .into_bytes(),
+        FileFormat::Parquet => grid
+            .export_parquet_selection(&mut A1Selection::all(sheet_id))
+            .map_err(|e| export_error(e.to_string()))?,
+    };
+
+    std::fs::write(path, file).map_err(|e| CoreCloudError::ExportFile(file_name, e.to_string()))
+}
+
+fn blank_grid() -> (GridController, SheetId) {
+    let mut grid = Grid::new_blank();
+    let sheet_id = grid.add_sheet(None);
+    (GridController::from_grid(grid, 0), sheet_id)
+}
+
+/// Finds a sheet by name, or the first sheet if no name is given.
+fn sheet_id(grid: &GridController, name: Option<&str>) -> Result<SheetId> {
+    match name {
+        Some(name) => grid
+            .a1_context()
+            .try_sheet_name(name)
+            .ok_or_else(|| CoreCloudError::Argument(format!("Sheet not found: {name}"))),
+        None => grid
+            .sheet_ids()
+            .first()
+            .copied()
+            .ok_or_else(|| CoreCloudError::Argument("File has no sheets".into())),
+    }
+}
+
+/// Parses a `[SHEET!]CELL=VALUE` argument into the batches of operations
+/// that set the cell.
+fn set_operations(
+    grid: &mut GridController,
+    sheet_id: SheetId,
+    set: &str,
+) -> Result<Vec<Vec<Operation>>> {
+    let invalid =
+        |message: &str| CoreCloudError::Argument(format!("Invalid --set {set:?}: {message}"));
+
+    let (cell, value) = set
+        .split_once('=')
+        .ok_or_else(|| invalid("expected [SHEET!]CELL=VALUE"))?;
+    let selection = A1Selection::parse_a1(cell, sheet_id, grid.a1_context())
+        .map_err(|e| invalid(&e.to_string()))?;
+    let pos = selection
+        .try_to_pos(grid.a1_context())
+        .ok_or_else(|| invalid("expected a single cell"))?;
+    let sheet_pos = SheetPos::new(selection.sheet_id, pos.x, pos.y);
+
+    if let Some(formula) = value.strip_prefix('=') {
+        let operations = grid.set_code_cell_operations(
+            sheet_pos,
+            CodeCellLanguage::Formula,
+            formula.into(),
+            None,
+        );
+        return Ok(vec![operations]);
+    }
+
+    let (operations, data_table_operations) = grid
+        .set_cell_values_operations(sheet_pos, vec![vec![value.to_string()]], true)
+        .map_err(|e| invalid(&e.to_string()))?;
+    Ok(vec![operations, data_table_operations])
+}
+
+/// Returns the operations to recalculate the code cells in the grid. Unless
+/// `run_code` is set, only formulas are included.
+fn recalc_operations(grid: &GridController, run_code: bool) -> Vec<Operation> {
+    grid.rerun_all_code_cells_operations()
+        .into_iter()
+        .filter(|operation| match operation {
+            Operation::ComputeCode { sheet_pos } => {
+                run_code
+                    || grid
+                        .try_sheet(sheet_pos.sheet_id)
+                        .and_then(|sheet| sheet.code_run_at(&(*sheet_pos).into()))
+                        .is_some_and(|code_run| code_run.language == CodeCellLanguage::Formula)
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use quadratic_core::{CellValue, number::decimal_from_str};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{name}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_file_format_from_path() {
+        assert_eq!(
+            FileFormat::from_path(Path::new("a/b.GRID")).unwrap(),
+            FileFormat::Grid
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("b.parquet")).unwrap(),
+            FileFormat::Parquet
+        );
+        assert!(FileFormat::from_path(Path::new("b.txt")).is_err());
+
+        assert_eq!(
+            FileFormat::from_path(Path::new("b.ods")).unwrap(),
+            FileFormat::Excel
+        );
+        assert_eq!(
+            FileFormat::from_output_path(Path::new("b.XLSX")).unwrap(),
+            FileFormat::Excel
+        );
+        for path in ["b.xls", "b.xlsm", "b.ods"] {
+            assert!(FileFormat::from_output_path(Path::new(path)).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_recalc_and_convert() {
+        let input = temp_path("input.csv");
+        std::fs::write(&input, "a,b\n1,2\n3,4\n").unwrap();
+        let grid_path = temp_path("output.grid");
+        let csv_path = temp_path("output.csv");
+
+        let args = Args::parse_from([
+            "quadratic_cli".into(),
+            input.display().to_string(),
+            "--set".into(),
+            "F1=10".into(),
+            "--set".into(),
+            "G1==F1*2".into(),
+            "--output".into(),
+            grid_path.display().to_string(),
+            "--output".into(),
+            csv_path.display().to_string(),
+        ]);
+        run(args).await.unwrap();
+
+        let grid = open(&grid_path).unwrap();
+        let sheet = grid.try_sheet(grid.sheet_ids()[0]).unwrap();
+        assert_eq!(
+            sheet.display_value(pos![G1]),
+            Some(CellValue::Number(decimal_from_str("20").unwrap()))
+        );
+        assert!(
+            std::fs::read_to_string(&csv_path)
+                .unwrap()
+                .contains("10,20")
+        );
+
+        // a changed input is recalculated
+        let args = Args::parse_from([
+            "quadratic_cli".into(),
+            grid_path.display().to_string(),
+            "--set".into(),
+            "Sheet1!F1=21".into(),
+            "--output".into(),
+            grid_path.display().to_string(),
+        ]);
+        run(args).await.unwrap();
+
+        let grid = open(&grid_path).unwrap();
+        let sheet = grid.try_sheet(grid.sheet_ids()[0]).unwrap();
+        assert_eq!(
+            sheet.display_value(pos![G1]),
+            Some(CellValue::Number(decimal_from_str("42").unwrap()))
+        );
+
+        for path in [input, grid_path, csv_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_set_operations_invalid() {
+        let (mut grid, sheet_id) = blank_grid();
+        assert!(set_operations(&mut grid, sheet_id, "A1").is_err());
+        assert!(set_operations(&mut grid, sheet_id, "A1:B2=1").is_err());
+        assert!(set_operations(&mut grid, sheet_id, "Missing!A1=1").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use bytes::Bytes;
 use quadratic_core::{
     controller::{GridController, transaction_types::JsConnectionResult},
-    grid::{ConnectionKind, SheetId},
+    grid::{ConnectionKind, ConnectionQuery, SheetId},
 };
 use serde_json::json;
 use std::sync::Arc;
@@ -38,7 +38,8 @@ pub(crate) struct ConnectionParams<'a> {
 
 /// Runs a connection.
 ///
-/// This function will replace handlebars with actual cell values and execute the connection.
+/// This function will replace handlebars with placeholders bound to the cell
+/// values and execute the connection.
 /// It will then return the result of the connection execution.
 pub(crate) async fn run_connection(params: ConnectionParams<'_>) -> Result<()> {
     let ConnectionParams {
@@ -60,16 +61,16 @@ pub(crate) async fn run_connection(params: ConnectionParams<'_>) -> Result<()> {
         transaction_id
     );
 
-    // Replace handlebars with actual cell values
-    let processed_query = {
+    // Replace handlebars with placeholders and typed parameters
+    let connection_query = {
         let grid_lock = grid.lock().await;
         grid_lock
-            .replace_handlebars(None, query, sheet_id)
+            .parameterize_handlebars(None, query, sheet_id, connection_kind)
             .map_err(|e| CoreCloudError::Core(e.to_string()))?
     };
 
     let (result, std_error) = execute(
-        &processed_query,
+        &connection_query,
         connection_kind,
         connection_id,
         transaction_id,
@@ -102,7 +103,7 @@ pub(crate) async fn run_connection(params: ConnectionParams<'_>) -> Result<()> {
 ///
 /// This function will send the query to the connection endpoint and return the result.
 pub(crate) async fn execute(
-    query: &str,
+    connection_query: &ConnectionQuery,
     connection_kind: ConnectionKind,
     connection_id: &str,
     transaction_id: &str,
@@ -118,7 +119,8 @@ pub(crate) async fn execute(
     );
 
     let body = json!({
-        "query": query,
+        "query": connection_query.query,
+        "parameters": connection_query.parameters,
         "connection_id": connection_id,
     });
     let request = build_request(&url, token, team_id, &body);
@@ -205,8 +207,12 @@ mod tests {
         let connection_kind = ConnectionKind::Postgres;
         let connection_id = "2f153e3a-aa1f-40e9-960e-759f3047bf85".to_string();
         let team_id = "5b5dd6a8-04d8-4ca5-baeb-2cf3e80c1d05".to_string();
+        let connection_query = ConnectionQuery {
+            query: query.to_string(),
+            parameters: vec![],
+        };
         let (result, std_error) = execute(
-            query,
+            &connection_query,
             connection_kind,
             &connection_id,
             "test",
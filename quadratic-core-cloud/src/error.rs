@@ -14,6 +14,9 @@ pub(crate) type Result<T> = std::result::Result<T, CoreCloudError>;
 
 #[derive(Error, Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum CoreCloudError {
+    #[error("Invalid argument: {0}")]
+    Argument(String),
+
     #[error("Async error: {0}")]
     Async(String),
 
@@ -2,6 +2,7 @@
 //!
 //! A core cloud service
 
+pub mod cli;
 mod connection;
 mod core;
 mod error;
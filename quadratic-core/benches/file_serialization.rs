@@ -0,0 +1,116 @@
+//! Compares the JSON and MessagePack chunk encodings of the current file
+//! schema, both for writing (serialize + compress) and reading (decompress +
+//! deserialize) a whole container.
+//!
+//! usage:
+//!
+//! npm run bench:run file_serialization
+
+use std::time::Duration;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use quadratic_core::{
+    Pos,
+    compression::{CompressionFormat, SerializationFormat},
+    controller::GridController,
+    grid::{
+        Grid,
+        file::{
+            self,
+            current::{ChunkEncodingSchema, GridContainer, GridSchema},
+        },
+    },
+};
+use quadratic_rust_shared::test::benchmark::benchmark;
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
+
+fn encodings() -> Vec<(&'static str, ChunkEncodingSchema)> {
+    [
+        ("json", SerializationFormat::Json),
+        ("message_pack", SerializationFormat::MessagePack),
+    ]
+    .into_iter()
+    .map(|(name, serialization_format)| {
+        let encoding = ChunkEncodingSchema {
+            serialization_format,
+            compression_format: CompressionFormat::Zstd,
+        };
+        (name, encoding)
+    })
+    .collect()
+}
+
+fn customers_grid() -> Grid {
+    let mut gc = GridController::from_grid(Grid::new(), 0);
+    let sheet_id = gc.sheet_ids()[0];
+    gc.import_csv(
+        sheet_id,
+        include_bytes!("test_files/customers-10000.csv"),
+        "customers-10000.csv",
+        Pos::new(1, 1),
+        None,
+        None,
+        None,
+        false,
+        false,
+    )
+    .unwrap();
+    gc.into_grid()
+}
+
+fn to_bytes(schema: &GridSchema, encoding: &ChunkEncodingSchema) -> Vec<u8> {
+    GridContainer::from_schema_with_encoding(schema.clone(), encoding.clone())
+        .unwrap()
+        .to_bytes()
+        .unwrap()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let airports = file::import(
+        include_bytes!("../../quadratic-rust-shared/data/grid/v1_4_airports_distance.grid")
+            .to_vec(),
+    )
+    .unwrap();
+
+    let grids = [("airports", airports), ("customers", customers_grid())];
+    let measurement_time = Some(Duration::new(5, 0));
+    let sample_size = Some(10);
+
+    for (grid_name, grid) in grids {
+        let schema = file::serialize::export(grid).unwrap();
+        let inputs = encodings();
+
+        for (name, encoding) in &inputs {
+            let size = to_bytes(&schema, encoding).len();
+            println!("{grid_name} ({name}): {size} bytes");
+        }
+
+        benchmark(
+            c,
+            &inputs,
+            format!("write_{grid_name}"),
+            measurement_time,
+            sample_size,
+            |b, encoding| b.iter(|| to_bytes(&schema, encoding)),
+        );
+
+        benchmark(
+            c,
+            &inputs,
+            format!("read_{grid_name}"),
+            measurement_time,
+            sample_size,
+            |b, encoding| {
+                let bytes = to_bytes(&schema, encoding);
+                b.iter(|| {
+                    GridContainer::from_bytes(&bytes)
+                        .unwrap()
+                        .into_schema()
+                        .unwrap()
+                })
+            },
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Compares .grid files.
+//!
+//! `grid_diff <old.grid> <new.grid>` prints the differences between two
+//! files. Exits with 1 if the files differ.
+//!
+//! `grid_diff --merge <base.grid> <ours.grid> <theirs.grid> [out.grid]`
+//! applies the changes from base to theirs onto ours and writes the result to
+//! `out.grid` (defaults to `ours-merged.grid` next to ours). The output can't
+//! be one of the inputs. Items changed differently in ours and theirs keep the
+//! value from ours and are reported as conflicts.
+//! Exits with 1 if there were conflicts.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use quadratic_core::controller::GridController;
+use quadratic_core::grid::Grid;
+use quadratic_core::grid::diff::{GridDiff, merge_grids};
+use quadratic_core::grid::file::{export, import};
+
+fn usage() -> ! {
+    eprintln!("usage: grid_diff <old.grid> <new.grid>");
+    eprintln!("       grid_diff --merge <base.grid> <ours.grid> <theirs.grid> [out.grid]");
+    exit(2);
+}
+
+fn load(path: &str) -> Grid {
+    let file = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("failed to read from {path}: {e}");
+        exit(2);
+    });
+    import(file).unwrap_or_else(|e| {
+        eprintln!("failed to import from {path}: {e}");
+        exit(2);
+    })
+}
+
+/// `ours-merged.grid` next to ours, whatever the extension of ours is.
+fn merged_path(ours: &str) -> PathBuf {
+    let ours = Path::new(ours);
+    let stem = ours.file_stem().unwrap_or_default().to_string_lossy();
+    ours.with_file_name(format!("{stem}-merged.grid"))
+}
+
+/// Whether two paths are the same file (or the same path if they don't exist).
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.as_slice() {
+        [flag, base, ours, theirs, rest @ ..] if flag == "--merge" && rest.len() <= 1 => {
+            let path_out = rest
+                .first()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| merged_path(ours));
+            if [base, ours, theirs]
+                .iter()
+                .any(|input| same_file(Path::new(input), &path_out))
+            {
+                eprintln!(
+                    "refusing to overwrite {}: pass another out.grid",
+                    path_out.display()
+                );
+                exit(2);
+            }
+            let ours_grid = load(ours);
+            let merge = merge_grids(&load(base), &ours_grid, &load(theirs));
+
+            let mut gc = GridController::from_grid(ours_grid, 0);
+            let operations = merge.operations.len();
+            gc.server_apply_transaction(merge.operations, None);
+            let data = export(gc.into_grid()).unwrap_or_else(|e| {
+                eprintln!("failed to export to file: {e}");
+                exit(2);
+            });
+            fs::write(&path_out, data).unwrap_or_else(|e| {
+                eprintln!("failed to write to {}: {e}", path_out.display());
+                exit(2);
+            });
+
+            println!("applied {operations} operations to {}", path_out.display());
+            for conflict in &merge.conflicts {
+                println!("conflict: {conflict}");
+            }
+            if !merge.conflicts.is_empty() {
+                exit(1);
+            }
+        }
+        [old, new] if !old.starts_with("--") => {
+            let diff = GridDiff::new(&load(old), &load(new));
+            print!("{diff}");
+            if !diff.is_empty() {
+                exit(1);
+            }
+        }
+        _ => usage(),
+    }
+}
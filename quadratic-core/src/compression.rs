@@ -19,17 +19,20 @@ const BINCODE_CONFIG: bincode::config::Configuration<
     .with_limit::<MAX_FILE_SIZE>();
 pub const ZSTD_COMPRESSION_LEVEL: i32 = 4;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum CompressionFormat {
     None,
     Zlib,
     Zstd,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum SerializationFormat {
     Bincode,
     Json,
+    // Self-describing binary format that supports the full serde data model
+    // (unlike bincode), so it can encode the file schema.
+    MessagePack,
 }
 
 #[function_timer::function_timer]
@@ -67,6 +70,7 @@ where
     match serialization_format {
         SerializationFormat::Bincode => Ok(bincode::serde::encode_to_vec(&data, BINCODE_CONFIG)?),
         SerializationFormat::Json => Ok(serde_json::to_string(&data)?.into_bytes()),
+        SerializationFormat::MessagePack => Ok(rmp_serde::to_vec_named(&data)?),
     }
 }
 
@@ -77,6 +81,7 @@ where
     match serialization_format {
         SerializationFormat::Bincode => Ok(deserialize_bincode(data)?),
         SerializationFormat::Json => Ok(serde_json::from_slice(data)?),
+        SerializationFormat::MessagePack => Ok(rmp_serde::from_slice(data)?),
     }
 }
 
@@ -196,4 +201,38 @@ mod test {
 
         assert_roundtrip_compression(&serialization_format, &compression_format);
     }
+
+    #[test]
+    fn roundtrip_compression_message_pack() {
+        let compression_format = CompressionFormat::Zstd;
+        let serialization_format = SerializationFormat::MessagePack;
+
+        assert_roundtrip_compression(&serialization_format, &compression_format);
+    }
+
+    #[test]
+    fn roundtrip_message_pack_skipped_fields() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Skipped {
+            value: i64,
+            #[serde(skip_serializing_if = "Option::is_none", default)]
+            optional: Option<String>,
+        }
+
+        let data = vec![
+            Skipped {
+                value: 1,
+                optional: None,
+            },
+            Skipped {
+                value: 2,
+                optional: Some("two".into()),
+            },
+        ];
+        let serialized = serialize(&SerializationFormat::MessagePack, &data).unwrap();
+        let deserialized =
+            deserialize::<Vec<Skipped>>(&SerializationFormat::MessagePack, &serialized).unwrap();
+
+        assert_eq!(data, deserialized);
+    }
 }
@@ -62,6 +62,9 @@ pub struct PendingTransaction {
     /// whether we are awaiting an async call for a code cell
     pub(crate) waiting_for_async_code_cell: bool,
 
+    /// whether the async call is a connection query, which can be cancelled
+    pub(crate) waiting_for_connection: bool,
+
     /// whether transaction is complete
     pub(crate) complete: bool,
 
@@ -146,6 +149,7 @@ impl Default for PendingTransaction {
             cells_accessed: Default::default(),
             current_sheet_pos: None,
             waiting_for_async_code_cell: false,
+            waiting_for_connection: false,
             complete: false,
             generate_thumbnail: false,
             cursor_undo_redo: None,
@@ -1,4 +1,25 @@
-use crate::{controller::GridController, grid::ConnectionKind};
+use uuid::Uuid;
+
+use crate::{
+    controller::{
+        GridController, active_transactions::transaction_name::TransactionName,
+        operations::changed_region::ChangedRegion,
+    },
+    grid::{ConnectionKind, ConnectionQuery},
+};
+
+/// A completed transaction, passed to the transaction callback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionEvent {
+    pub id: Uuid,
+    pub name: TransactionName,
+
+    /// The sheets and ranges changed by the transaction, including the output
+    /// of any code cells that were recalculated. Data tables are looked up
+    /// after the transaction is applied, so a deleted table is reported by
+    /// its position.
+    pub changes: Vec<ChangedRegion>,
+}
 
 impl GridController {
     pub fn with_run_python_callback<F>(&mut self, f: F)
@@ -17,8 +38,27 @@ impl GridController {
 
     pub fn with_run_connection_callback<F>(&mut self, f: F)
     where
-        F: FnMut(String, i32, i32, String, String, ConnectionKind, String) + Send + 'static,
+        F: FnMut(String, i32, i32, String, ConnectionQuery, ConnectionKind, String)
+            + Send
+            + 'static,
     {
         self.run_connection_callback = Some(Box::new(f));
     }
+
+    /// Sets a callback that is called when an in-flight connection query
+    /// should be cancelled, eg, its code cell was re-run or deleted.
+    pub fn with_cancel_connection_callback<F>(&mut self, f: F)
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        self.cancel_connection_callback = Some(Box::new(f));
+    }
+
+    /// Sets a callback that is called after each transaction completes.
+    pub fn with_transaction_callback<F>(&mut self, f: F)
+    where
+        F: FnMut(&TransactionEvent) + Send + 'static,
+    {
+        self.transaction_callback = Some(Box::new(f));
+    }
 }
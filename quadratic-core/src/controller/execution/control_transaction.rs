@@ -1,15 +1,17 @@
+use bytes::Bytes;
 use uuid::Uuid;
 
 use super::{GridController, TransactionSource};
 use crate::controller::active_transactions::pending_transaction::PendingTransaction;
 use crate::controller::active_transactions::transaction_name::TransactionName;
+use crate::controller::callbacks::TransactionEvent;
 use crate::controller::operations::operation::Operation;
 use crate::controller::transaction::Transaction;
 use crate::controller::transaction_types::JsCodeResult;
 use crate::error_core::Result;
 use crate::formulas::functions::financial::stock_history::process_stock_history_json;
 use crate::grid::{CodeCellLanguage, CodeRun, ConnectionKind, DataTable, DataTableKind};
-use crate::parquet::parquet_to_array;
+use crate::parquet::{ParquetQuerySummary, parquet_to_array};
 use crate::renderer_constants::{CELL_SHEET_HEIGHT, CELL_SHEET_WIDTH};
 use crate::{Pos, RunError, RunErrorMsg, Value};
 
@@ -149,6 +151,27 @@ impl GridController {
 
         transaction.send_transaction();
 
+        if self.transaction_callback.is_some() {
+            let mut changes = vec![];
+            for region in transaction
+                .forward_operations
+                .iter()
+                .flat_map(|operation| operation.changed_regions(self))
+            {
+                if !changes.contains(&region) {
+                    changes.push(region);
+                }
+            }
+
+            if let Some(f) = self.transaction_callback.as_mut() {
+                f(&TransactionEvent {
+                    id: transaction.id,
+                    name: transaction.transaction_name,
+                    changes,
+                });
+            }
+        }
+
         self.track_transactions(&transaction);
 
         // Send empty code running state when transaction completes, but only if there are no async transactions pending
@@ -165,6 +188,9 @@ impl GridController {
                 transaction_name,
             );
         }
+
+        // stop queries for connection cells deleted by this transaction
+        self.cancel_orphaned_connections();
     }
 
     pub fn start_user_ai_transaction(
@@ -249,6 +275,9 @@ impl GridController {
                             ConnectionKind::GoogleAnalytics => "GoogleAnalytics1",
                             ConnectionKind::Plaid => "Plaid1",
                             ConnectionKind::StockHistory => "StockHistory",
+                            ConnectionKind::Sqlite => "Sqlite1",
+                            ConnectionKind::Duckdb => "Duckdb1",
+                            ConnectionKind::Stripe => "Stripe1",
                         },
                         // Formula-based connections (like STOCKHISTORY)
                         CodeCellLanguage::Formula
@@ -268,11 +297,22 @@ impl GridController {
                         ("0x0 Array".to_string(), Value::default())
                     };
 
+                    let mut std_out = std_out;
+
                     let (mut return_type, value) = if is_stock_history {
                         parse_stock_history_data(&data, &std_err, &code.code, &parse_error)
                     } else {
-                        // Standard connections receive Parquet data
-                        let array = parquet_to_array(data, name, None::<fn(&str, u32, u32)>);
+                        // Standard connections receive Parquet data, with a
+                        // summary of the result in its metadata
+                        let data = Bytes::from(data);
+
+                        if let Some(message) = ParquetQuerySummary::from_parquet(&data)
+                            .and_then(|summary| summary.truncation_message())
+                        {
+                            std_out = Some(message);
+                        }
+
+                        let array = parquet_to_array(data.into(), name, None::<fn(&str, u32, u32)>);
                         match (array, &std_err) {
                             (Ok(array), None) => {
                                 // subtract 1 from the length to account for the header row
@@ -244,6 +244,9 @@ impl GridController {
                 }
             };
 
+            // a query still running for this cell is superseded by this run
+            self.cancel_connections_at(transaction, sheet_pos);
+
             // Clone for notification
             let language_for_notify = language.clone();
             let code_for_notify = code.clone();
@@ -306,6 +309,9 @@ impl GridController {
                 return;
             }
 
+            // a query still running for this cell is superseded by this run
+            self.cancel_connections_at(transaction, sheet_pos);
+
             // Clone for notification
             let language_for_notify = language.clone();
             let code_for_notify = code.clone();
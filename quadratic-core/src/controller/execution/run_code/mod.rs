@@ -38,6 +38,7 @@ impl GridController {
         transaction.current_sheet_pos = None;
         transaction.cells_accessed.clear();
         transaction.waiting_for_async_code_cell = false;
+        transaction.waiting_for_connection = false;
 
         self.update_cells_accessed_cache(sheet_pos, &new_data_table);
 
@@ -1,10 +1,17 @@
+use std::ops::Range;
+
 use anyhow::Result;
+use quadratic_rust_shared::utils::sql_lexer::{self, Lexeme, LexemeKind, Quoting};
+use uuid::Uuid;
 
 use crate::{
-    Pos, Rect, RunError, RunErrorMsg, SheetPos,
+    CellValue, Pos, Rect, RunError, RunErrorMsg, SheetPos,
     a1::{A1Error, A1Selection},
     controller::{GridController, active_transactions::pending_transaction::PendingTransaction},
-    grid::{ConnectionKind, HANDLEBARS_REGEX_COMPILED, Sheet, SheetId},
+    grid::{
+        ConnectionKind, ConnectionParameter, ConnectionQuery, HANDLEBARS_REGEX_COMPILED, Sheet,
+        SheetId,
+    },
 };
 
 impl GridController {
@@ -25,35 +32,120 @@ impl GridController {
         response
     }
 
-    /// Attempts to replace handlebars with the actual value from the grid
-    pub fn replace_handlebars(
+    /// Returns the values of the cells for a connection parameter. Blank
+    /// cells are skipped.
+    fn get_cells_for_parameters(sheet: &Sheet, rect: Rect) -> Vec<CellValue> {
+        rect.y_range()
+            .flat_map(|y| rect.x_range().map(move |x| Pos { x, y }))
+            .filter_map(|pos| sheet.display_value(pos))
+            .collect()
+    }
+
+    /// Splits a query into code, comments and strings the way the
+    /// connection's database does, ignoring quotes within handlebars (e.g., in
+    /// sheet names). A query that can't be split (e.g., an unterminated
+    /// string) is all code, and the database reports the error.
+    fn lex_query(code: &str, handlebars: &[Range<usize>], kind: ConnectionKind) -> Vec<Lexeme> {
+        let mut masked = code.to_string();
+        for range in handlebars {
+            masked.replace_range(range.clone(), &" ".repeat(range.len()));
+        }
+
+        sql_lexer::lex(&masked, kind.quoting()).unwrap_or_else(|_| {
+            vec![Lexeme {
+                kind: LexemeKind::Code,
+                range: 0..code.len(),
+            }]
+        })
+    }
+
+    /// Returns the text of part of a string literal, without its escapes.
+    fn unescape_literal(text: &str, literal: LexemeKind, quoting: Quoting) -> String {
+        // dollar quoted strings have no escapes
+        if literal != LexemeKind::String {
+            return text.to_string();
+        }
+
+        let mut unescaped = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if quoting == Quoting::Backslash => match chars.next() {
+                    Some('n') => unescaped.push('\n'),
+                    Some('r') => unescaped.push('\r'),
+                    Some('t') => unescaped.push('\t'),
+                    Some('0') => unescaped.push('\0'),
+                    // kept for LIKE patterns
+                    Some(c @ ('%' | '_')) => {
+                        unescaped.push('\\');
+                        unescaped.push(c);
+                    }
+                    Some(c) => unescaped.push(c),
+                    None => unescaped.push('\\'),
+                },
+                // a doubled quote is an escaped quote
+                '\'' if chars.peek() == Some(&'\'') => {
+                    chars.next();
+                    unescaped.push('\'');
+                }
+                c => unescaped.push(c),
+            }
+        }
+
+        unescaped
+    }
+
+    /// Replaces handlebars with placeholders for the connection's driver and
+    /// returns the referenced cell values as typed parameters.
+    ///
+    /// A handlebar outside a string literal becomes one placeholder per cell
+    /// (comma-separated for a 1d range, e.g., for use in an `IN (...)` list).
+    /// A string literal that contains handlebars (e.g., `'%{{A1}}%'`) becomes
+    /// a single text parameter with the cells' values substituted. Handlebars
+    /// in comments are left as they are.
+    pub fn parameterize_handlebars(
         &self,
         mut transaction: Option<&mut PendingTransaction>,
         code: &str,
         default_sheet_id: SheetId,
-    ) -> Result<String, A1Error> {
-        let mut result = String::new();
-        let mut last_match_end = 0;
+        kind: ConnectionKind,
+    ) -> Result<ConnectionQuery, A1Error> {
+        // (position in the code, reference)
+        let matches = HANDLEBARS_REGEX_COMPILED
+            .captures_iter(code)
+            .filter_map(|cap| {
+                let cap = cap.ok()?;
+                let content = cap.get(1).map(|m| m.as_str().trim()).unwrap_or("");
+                Some((cap.get(0)?.range(), content))
+            })
+            .collect::<Vec<_>>();
+
+        let ranges = matches
+            .iter()
+            .map(|(range, _)| range.clone())
+            .collect::<Vec<_>>();
+        let lexemes = Self::lex_query(code, &ranges, kind);
+        let lexeme_at = |range: &Range<usize>| {
+            lexemes
+                .iter()
+                .find(|lexeme| lexeme.range.start <= range.start && range.end <= lexeme.range.end)
+        };
+
+        // (position in the code, cell values, comma-delimited text)
+        let mut handlebars: Vec<(Range<usize>, Vec<CellValue>, String)> = vec![];
 
         let context = self.a1_context();
-        for cap in HANDLEBARS_REGEX_COMPILED.captures_iter(code) {
-            let Ok(cap) = cap else {
-                continue;
-            };
-
-            let Some(whole_match) = cap.get(0) else {
+        for (range, content) in matches {
+            // handlebars in comments are left as they are
+            if lexeme_at(&range).is_some_and(|lexeme| lexeme.kind == LexemeKind::Comment) {
                 continue;
-            };
-
-            result.push_str(&code[last_match_end..whole_match.start()]);
+            }
 
-            let content = cap.get(1).map(|m| m.as_str().trim()).unwrap_or("");
             let selection = A1Selection::parse_a1(content, default_sheet_id, context)?;
 
             // connections support either one cell or a 1d range of cells (ie,
-            // one column or row), which are entered as a comma-delimited list
-            // of entries (e.g., "2,3,10,1,...") in the query
-
+            // one column or row)
             if !selection.is_1d_range(context) {
                 return Err(A1Error::WrongCellCount(
                     "Connections only supports one cell or a 1d range of cells".to_string(),
@@ -71,7 +163,6 @@ impl GridController {
                 ));
             }
             let rect = rects[0];
-            result.push_str(&Self::get_cells_comma_delimited_string(sheet, rect));
 
             if let Some(trans) = transaction.as_mut() {
                 trans
@@ -79,13 +170,75 @@ impl GridController {
                     .add_sheet_rect(rect.to_sheet_rect(sheet.id));
             }
 
-            last_match_end = whole_match.end();
+            handlebars.push((
+                range,
+                Self::get_cells_for_parameters(sheet, rect),
+                Self::get_cells_comma_delimited_string(sheet, rect),
+            ));
+        }
+
+        let mut query = String::new();
+        let mut parameters = vec![];
+        let mut last_match_end = 0;
+        let mut i = 0;
+
+        while i < handlebars.len() {
+            let (range, values, _) = &handlebars[i];
+
+            let literal = lexeme_at(range).and_then(|lexeme| match lexeme.kind {
+                LexemeKind::String => Some((lexeme, 1)),
+                LexemeKind::DollarString { tag_len } => Some((lexeme, tag_len)),
+                _ => None,
+            });
+
+            if let Some((literal, quote_len)) = literal {
+                // the whole string literal becomes one text parameter
+                let unescape =
+                    |text: &str| Self::unescape_literal(text, literal.kind, kind.quoting());
+                let literal_end = literal.range.end;
+                query.push_str(&code[last_match_end..literal.range.start]);
+
+                let mut text = String::new();
+                let mut text_start = literal.range.start + quote_len;
+
+                while let Some((range, _, joined)) = handlebars.get(i)
+                    && range.end < literal_end
+                {
+                    text.push_str(&unescape(&code[text_start..range.start]));
+                    text.push_str(joined);
+                    text_start = range.end;
+                    i += 1;
+                }
+                text.push_str(&unescape(&code[text_start..literal_end - quote_len]));
+
+                parameters.push(ConnectionParameter::Text(text));
+                query.push_str(&kind.placeholder(parameters.len()));
+                last_match_end = literal_end;
+            } else {
+                query.push_str(&code[last_match_end..range.start]);
+
+                if values.is_empty() {
+                    parameters.push(ConnectionParameter::Null);
+                    query.push_str(&kind.placeholder(parameters.len()));
+                }
+
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        query.push_str(", ");
+                    }
+                    parameters.push(ConnectionParameter::from(value));
+                    query.push_str(&kind.placeholder(parameters.len()));
+                }
+
+                last_match_end = range.end;
+                i += 1;
+            }
         }
 
         // Add the remaining part of the string
-        result.push_str(&code[last_match_end..]);
+        query.push_str(&code[last_match_end..]);
 
-        Ok(result)
+        Ok(ConnectionQuery { query, parameters })
     }
 
     pub(crate) fn run_connection(
@@ -96,12 +249,17 @@ impl GridController {
         kind: ConnectionKind,
         id: String,
     ) {
-        let mut replaced_code = None;
+        let mut connection_query = None;
 
         if (cfg!(target_family = "wasm") || cfg!(test)) && !transaction.is_server() {
-            match self.replace_handlebars(Some(&mut *transaction), &code, sheet_pos.sheet_id) {
-                Ok(replaced) => {
-                    replaced_code = Some(replaced);
+            match self.parameterize_handlebars(
+                Some(&mut *transaction),
+                &code,
+                sheet_pos.sheet_id,
+                kind,
+            ) {
+                Ok(parameterized) => {
+                    connection_query = Some(parameterized);
                 }
                 Err(msg) => {
                     let error = RunError {
@@ -121,39 +279,124 @@ impl GridController {
         // stop the computation cycle until async returns
         transaction.current_sheet_pos = Some(sheet_pos);
         transaction.waiting_for_async_code_cell = true;
+        transaction.waiting_for_connection = true;
         self.transactions.add_async_transaction(transaction);
 
         if !transaction.is_server()
             && let Some(f) = self.run_connection_callback.as_mut()
-            && let Some(replaced_code) = replaced_code
+            && let Some(connection_query) = connection_query
         {
             f(
                 transaction.id.to_string(),
                 sheet_pos.x as i32,
                 sheet_pos.y as i32,
                 sheet_pos.sheet_id.to_string(),
-                replaced_code,
+                connection_query,
                 kind,
                 id,
             );
         }
     }
+
+    /// Cancels the in-flight connection queries of other transactions for the
+    /// code cell at sheet_pos, eg, when the cell is re-run before its query
+    /// returns.
+    pub(crate) fn cancel_connections_at(
+        &mut self,
+        transaction: &PendingTransaction,
+        sheet_pos: SheetPos,
+    ) {
+        let transaction_ids = self
+            .transactions
+            .async_transactions()
+            .iter()
+            .filter(|pending| {
+                pending.id != transaction.id
+                    && pending.waiting_for_connection
+                    && pending.current_sheet_pos == Some(sheet_pos)
+            })
+            .map(|pending| pending.id)
+            .collect::<Vec<_>>();
+
+        for transaction_id in transaction_ids {
+            self.cancel_connection(transaction_id);
+        }
+    }
+
+    /// Cancels the in-flight connection queries whose code cell no longer
+    /// exists (eg, it was deleted by the user or multiplayer).
+    pub(crate) fn cancel_orphaned_connections(&mut self) {
+        let transaction_ids = self
+            .transactions
+            .async_transactions()
+            .iter()
+            .filter(|pending| {
+                pending.waiting_for_connection
+                    && pending.current_sheet_pos.is_some_and(|sheet_pos| {
+                        self.try_sheet(sheet_pos.sheet_id)
+                            .and_then(|sheet| sheet.code_run_at(&sheet_pos.into()))
+                            .is_none()
+                    })
+            })
+            .map(|pending| pending.id)
+            .collect::<Vec<_>>();
+
+        for transaction_id in transaction_ids {
+            self.cancel_connection(transaction_id);
+        }
+    }
+
+    /// Asks the client to cancel a connection query and completes its
+    /// transaction without a result. The code cell keeps its previous output.
+    fn cancel_connection(&mut self, transaction_id: Uuid) {
+        let Ok(mut transaction) = self.transactions.remove_awaiting_async(transaction_id) else {
+            return;
+        };
+
+        if let Some(f) = self.cancel_connection_callback.as_mut() {
+            f(transaction_id.to_string());
+        }
+
+        transaction.current_sheet_pos = None;
+        transaction.cells_accessed.clear();
+        transaction.waiting_for_async_code_cell = false;
+        transaction.waiting_for_connection = false;
+
+        self.start_transaction(&mut transaction);
+        self.finalize_transaction(transaction);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         Pos, RunError, RunErrorMsg, SheetPos,
+        a1::A1Selection,
         constants::SHEET_NAME,
         controller::{
             GridController, active_transactions::pending_transaction::PendingTransaction,
         },
-        grid::{CodeCellLanguage, ConnectionKind, SheetId},
+        grid::{
+            CodeCellLanguage, ConnectionKind,
+            ConnectionKind::{Mssql, Mysql, Postgres, Sqlite},
+            ConnectionParameter, SheetId,
+        },
         test_util::*,
+        wasm_bindings::js::expect_js_call,
     };
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    fn text(value: &str) -> ConnectionParameter {
+        ConnectionParameter::Text(value.to_string())
+    }
+
+    fn number(value: i64) -> ConnectionParameter {
+        ConnectionParameter::Number(Decimal::from(value))
+    }
 
     #[test]
-    fn test_replace_handlebars() {
+    fn test_parameterize_handlebars() {
         let mut gc = GridController::test();
         let sheet_id = gc.sheet_ids()[0];
 
@@ -163,9 +406,10 @@ mod tests {
 
         let code = r#"{{$A$2}}"#;
         let result = gc
-            .replace_handlebars(Some(&mut transaction), code, sheet_id)
+            .parameterize_handlebars(Some(&mut transaction), code, sheet_id, Postgres)
             .unwrap();
-        assert_eq!(result, "test".to_string());
+        assert_eq!(result.query, "$1");
+        assert_eq!(result.parameters, vec![text("test")]);
         assert_eq!(transaction.cells_accessed.len(sheet_id), Some(1));
         assert!(
             transaction
@@ -179,9 +423,10 @@ mod tests {
 
         let code = r#"{{'Sheet2'!$A$2}}"#;
         let result = gc
-            .replace_handlebars(Some(&mut transaction), code, sheet_id)
+            .parameterize_handlebars(Some(&mut transaction), code, sheet_id, Postgres)
             .unwrap();
-        assert_eq!(result, "test2".to_string());
+        assert_eq!(result.query, "$1");
+        assert_eq!(result.parameters, vec![text("test2")]);
         assert_eq!(transaction.cells_accessed.len(sheet_id), Some(1));
         assert!(
             transaction
@@ -191,7 +436,7 @@ mod tests {
     }
 
     #[test]
-    fn test_replace_handlebars_relative() {
+    fn test_parameterize_handlebars_relative() {
         let mut gc = GridController::test();
         let sheet_id = gc.sheet_ids()[0];
 
@@ -200,9 +445,9 @@ mod tests {
         let mut transaction = PendingTransaction::default();
         let code = r#"{{A2}}"#;
         let result = gc
-            .replace_handlebars(Some(&mut transaction), code, sheet_id)
+            .parameterize_handlebars(Some(&mut transaction), code, sheet_id, Postgres)
             .unwrap();
-        assert_eq!(result, "test".to_string());
+        assert_eq!(result.parameters, vec![text("test")]);
         assert_eq!(transaction.cells_accessed.len(sheet_id), Some(1));
         let context = gc.a1_context();
         assert!(
@@ -213,9 +458,9 @@ mod tests {
 
         let code = format!(r#"{{{{'{SHEET_NAME}1'!A2}}}}"#);
         let result = gc
-            .replace_handlebars(Some(&mut transaction), &code, sheet_id)
+            .parameterize_handlebars(Some(&mut transaction), &code, sheet_id, Postgres)
             .unwrap();
-        assert_eq!(result, "test".to_string());
+        assert_eq!(result.parameters, vec![text("test")]);
         assert_eq!(transaction.cells_accessed.len(sheet_id), Some(1));
         assert!(
             transaction
@@ -225,7 +470,7 @@ mod tests {
     }
 
     #[test]
-    fn test_replace_handlebars_actual_case() {
+    fn test_parameterize_handlebars_actual_case() {
         let code = "SELECT age FROM 'public'.'test_table' WHERE name='{{A1}}' LIMIT 100";
         let mut gc = GridController::test();
         let sheet_id = gc.sheet_ids()[0];
@@ -234,14 +479,159 @@ mod tests {
 
         let mut transaction = PendingTransaction::default();
         let result = gc
-            .replace_handlebars(Some(&mut transaction), code, sheet_id)
+            .parameterize_handlebars(Some(&mut transaction), code, sheet_id, Postgres)
+            .unwrap();
+        assert_eq!(
+            result.query,
+            "SELECT age FROM 'public'.'test_table' WHERE name=$1 LIMIT 100"
+        );
+        assert_eq!(result.parameters, vec![text("test")]);
+    }
+
+    #[test]
+    fn test_parameterize_handlebars_injection() {
+        let code = "SELECT * FROM users WHERE name = '{{A1}}' AND id = {{A2}}";
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        gc.set_cell_value(pos![sheet_id!A1], "x' OR '1'='1".to_string(), None, false);
+        gc.set_cell_value(
+            pos![sheet_id!A2],
+            "1; DROP TABLE users".to_string(),
+            None,
+            false,
+        );
+
+        let result = gc
+            .parameterize_handlebars(None, code, sheet_id, Postgres)
             .unwrap();
         assert_eq!(
-            result,
-            "SELECT age FROM 'public'.'test_table' WHERE name='test' LIMIT 100"
+            result.query,
+            "SELECT * FROM users WHERE name = $1 AND id = $2"
+        );
+        assert_eq!(
+            result.parameters,
+            vec![text("x' OR '1'='1"), text("1; DROP TABLE users")]
         );
     }
 
+    #[test]
+    fn test_parameterize_handlebars_types() {
+        let code = "SELECT * FROM t WHERE a = {{A1}} AND b = {{B1}} AND c = {{C1}} AND d = {{D1}}";
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        gc.set_cell_value(pos![sheet_id!A1], "42".to_string(), None, false);
+        gc.set_cell_value(pos![sheet_id!B1], "true".to_string(), None, false);
+        gc.set_cell_value(pos![sheet_id!C1], "2024-05-28".to_string(), None, false);
+
+        let result = gc
+            .parameterize_handlebars(None, code, sheet_id, Mysql)
+            .unwrap();
+        assert_eq!(
+            result.query,
+            "SELECT * FROM t WHERE a = ? AND b = ? AND c = ? AND d = ?"
+        );
+        assert_eq!(
+            result.parameters,
+            vec![
+                number(42),
+                ConnectionParameter::Logical(true),
+                ConnectionParameter::Date(NaiveDate::from_ymd_opt(2024, 5, 28).unwrap()),
+                ConnectionParameter::Null,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameterize_handlebars_range() {
+        let code = "SELECT * FROM t WHERE id IN ({{A1:A4}})";
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        gc.set_cell_value(pos![sheet_id!A1], "1".to_string(), None, false);
+        gc.set_cell_value(pos![sheet_id!A2], "2".to_string(), None, false);
+        gc.set_cell_value(pos![sheet_id!A4], "4".to_string(), None, false);
+
+        let result = gc
+            .parameterize_handlebars(None, code, sheet_id, Mssql)
+            .unwrap();
+        assert_eq!(result.query, "SELECT * FROM t WHERE id IN (@P1, @P2, @P3)");
+        assert_eq!(result.parameters, vec![number(1), number(2), number(4)]);
+    }
+
+    #[test]
+    fn test_parameterize_handlebars_string_literals() {
+        let code = "SELECT * FROM t WHERE a LIKE '%{{A1}}%' AND b = 'it''s {{A1:A2}}' AND c = '{{'No Sheet'!A1}}' AND d = {{A2}}";
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        gc.set_cell_value(pos![sheet_id!A1], "o'k".to_string(), None, false);
+        gc.set_cell_value(pos![sheet_id!A2], "2".to_string(), None, false);
+
+        // an invalid reference inside a literal is still an error
+        assert!(
+            gc.parameterize_handlebars(None, code, sheet_id, Postgres)
+                .is_err()
+        );
+
+        let code = "SELECT * FROM t WHERE a LIKE '%{{A1}}%' AND b = 'it''s {{A1:A2}}' AND \"it's\" = {{A2}}";
+        let result = gc
+            .parameterize_handlebars(None, code, sheet_id, Sqlite)
+            .unwrap();
+        assert_eq!(
+            result.query,
+            "SELECT * FROM t WHERE a LIKE ?1 AND b = ?2 AND \"it's\" = ?3"
+        );
+        assert_eq!(
+            result.parameters,
+            vec![text("%o'k%"), text("it's o'k,2"), number(2)]
+        );
+    }
+
+    #[test]
+    fn test_parameterize_handlebars_comments_and_escapes() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        gc.set_cell_value(pos![sheet_id!A1], "o'k".to_string(), None, false);
+        gc.set_cell_value(pos![sheet_id!A2], "2".to_string(), None, false);
+
+        let code =
+            "-- it's {{A1}}\nSELECT * FROM t /* don't */ WHERE a = {{A1}} AND b = $$it's {{A2}}$$";
+        let result = gc
+            .parameterize_handlebars(None, code, sheet_id, Postgres)
+            .unwrap();
+        assert_eq!(
+            result.query,
+            "-- it's {{A1}}\nSELECT * FROM t /* don't */ WHERE a = $1 AND b = $2"
+        );
+        assert_eq!(result.parameters, vec![text("o'k"), text("it's 2")]);
+
+        let code = "SELECT * FROM t # it's\nWHERE a = 'it\\'s {{A1}}' AND b = {{A2}}";
+        let result = gc
+            .parameterize_handlebars(None, code, sheet_id, Mysql)
+            .unwrap();
+        assert_eq!(
+            result.query,
+            "SELECT * FROM t # it's\nWHERE a = ? AND b = ?"
+        );
+        assert_eq!(result.parameters, vec![text("it's o'k"), number(2)]);
+    }
+
+    #[test]
+    fn test_parameterize_handlebars_no_handlebars() {
+        let code = "SELECT 'a{b}c' FROM t";
+        let gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        let result = gc
+            .parameterize_handlebars(None, code, sheet_id, Postgres)
+            .unwrap();
+        assert_eq!(result.query, code);
+        assert!(result.parameters.is_empty());
+    }
+
     #[test]
     fn test_run_connection_sheet_name_error() {
         fn test_error(gc: &mut GridController, code: &str, sheet_id: SheetId) {
@@ -334,4 +724,68 @@ mod tests {
             "test,456,246"
         );
     }
+
+    #[test]
+    fn test_cancel_connection_on_rerun() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        let sheet_pos = pos![sheet_id!A1];
+        let language = CodeCellLanguage::Connection {
+            kind: Postgres,
+            id: "test".to_string(),
+        };
+
+        gc.set_code_cell(
+            sheet_pos,
+            language.clone(),
+            "SELECT 1".to_string(),
+            None,
+            None,
+            false,
+        );
+        let first_id = gc.async_transactions()[0].id;
+
+        gc.set_code_cell(
+            sheet_pos,
+            language,
+            "SELECT 2".to_string(),
+            None,
+            None,
+            false,
+        );
+        expect_js_call("jsCancelConnection", first_id.to_string(), false);
+
+        let async_transactions = gc.async_transactions();
+        assert_eq!(async_transactions.len(), 1);
+        assert_ne!(async_transactions[0].id, first_id);
+
+        // the cancelled query can no longer complete
+        assert!(
+            gc.connection_complete(first_id.to_string(), vec![], None, None, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cancel_connection_on_delete() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        gc.set_code_cell(
+            pos![sheet_id!A1],
+            CodeCellLanguage::Connection {
+                kind: Postgres,
+                id: "test".to_string(),
+            },
+            "SELECT 1".to_string(),
+            None,
+            None,
+            false,
+        );
+        let transaction_id = gc.async_transactions()[0].id;
+
+        gc.delete_cells(&A1Selection::test_a1("A1"), None, false);
+        expect_js_call("jsCancelConnection", transaction_id.to_string(), false);
+        assert!(gc.async_transactions().is_empty());
+    }
 }
@@ -20,8 +20,9 @@ use crate::{
     date_time::{DEFAULT_DATE_FORMAT, DEFAULT_DATE_TIME_FORMAT, DEFAULT_TIME_FORMAT},
     grid::{
         CellAlign, CellVerticalAlign, CellWrap, CodeCellLanguage, GridBounds, NumericFormatKind,
-        Sheet, sheet::borders::CellBorderLine,
+        Sheet, SheetId, sheet::borders::CellBorderLine,
     },
+    parquet::cell_values_to_parquet,
 };
 
 lazy_static! {
@@ -53,10 +54,9 @@ const MAX_EXCEL_ROW: i64 = 1048576;
 const MAX_EXCEL_COL: i64 = 16384;
 
 impl GridController {
-    /// exports a CSV string from a selection on the grid.
-    ///
-    /// Returns a [`String`].
-    pub fn export_csv_selection(&self, selection: &mut A1Selection) -> Result<String> {
+    /// Returns the values in a selection on the grid, row by row. Unselected
+    /// columns and rows within the selection's bounds are skipped.
+    fn export_selection_rows(&self, selection: &mut A1Selection) -> Result<Vec<Vec<CellValue>>> {
         let sheet = self
             .grid
             .try_sheet(selection.sheet_id)
@@ -71,9 +71,9 @@ impl GridController {
             .context("No values")?;
 
         let values = sheet.selection_sorted_vec(selection, false, true, &self.a1_context);
-        let mut writer = Writer::from_writer(vec![]);
         let mut iter = values.iter();
         let context = self.a1_context();
+        let mut rows = vec![];
         for y in bounds.min.y..=bounds.max.y {
             let mut line = vec![];
             for x in bounds.min.x..=bounds.max.x {
@@ -81,126 +81,197 @@ impl GridController {
                 if selection.might_contain_pos(Pos { x, y }, context) {
                     if let Some((_, value)) = iter.peeking_next(|(pos, _)| pos.x == x && pos.y == y)
                     {
-                        line.push(value.to_string());
+                        line.push((*value).clone());
                     } else {
-                        line.push("".to_string());
+                        line.push(CellValue::Blank);
                     }
                 }
             }
 
             if !line.is_empty() {
-                writer.write_record(line)?;
+                rows.push(line);
             }
         }
+
+        Ok(rows)
+    }
+
+    /// exports a CSV string from a selection on the grid.
+    ///
+    /// Returns a [`String`].
+    pub fn export_csv_selection(&self, selection: &mut A1Selection) -> Result<String> {
+        let rows = self.export_selection_rows(selection)?;
+        let mut writer = Writer::from_writer(vec![]);
+        for row in rows {
+            writer.write_record(row.iter().map(|value| value.to_string()))?;
+        }
         let output = String::from_utf8(writer.into_inner()?)?;
 
         Ok(output)
     }
 
+    /// Exports a Parquet file from a selection on the grid. The first row of
+    /// the selection is used as the column names.
+    ///
+    /// Returns a [`Vec<u8>`].
+    pub fn export_parquet_selection(&self, selection: &mut A1Selection) -> Result<Vec<u8>> {
+        let rows = self.export_selection_rows(selection)?;
+
+        cell_values_to_parquet(&rows)
+    }
+
     /// Exports an excel file from the grid.
     /// Only preserves formulas, everything else is flattened.
     ///
     /// Returns a [`Vec<u8>`].
     pub fn export_excel(&self) -> Result<Vec<u8>> {
         let mut workbook = Workbook::new();
-        let error = |e: XlsxError| anyhow!("Error exporting excel file: {}", e);
 
         for sheet in self.sheets() {
-            // add the sheet to the workbook and set the name
-            let worksheet = workbook.add_worksheet();
+            write_excel_sheet(&mut workbook, sheet)?;
+        }
+
+        save_excel(workbook)
+    }
+
+    /// Exports an excel file from a sheet of the grid.
+    /// Only preserves formulas, everything else is flattened.
+    ///
+    /// Returns a [`Vec<u8>`].
+    pub fn export_excel_sheet(&self, sheet_id: SheetId) -> Result<Vec<u8>> {
+        let sheet = self.grid.try_sheet(sheet_id).context("Sheet not found")?;
+        let mut workbook = Workbook::new();
+        write_excel_sheet(&mut workbook, sheet)?;
+
+        save_excel(workbook)
+    }
+
+    /// Exports an excel file from a selection on the grid. The values are
+    /// written to a single worksheet, without formats or formulas.
+    ///
+    /// Returns a [`Vec<u8>`].
+    pub fn export_excel_selection(&self, selection: &mut A1Selection) -> Result<Vec<u8>> {
+        let rows = self.export_selection_rows(selection)?;
+        let mut workbook = Workbook::new();
+        let worksheet = workbook.add_worksheet();
+
+        if let Some(sheet) = self.grid.try_sheet(selection.sheet_id) {
             worksheet
                 .set_name(sheet.name.to_string())
                 .map_err(|e| anyhow!("Error creating excel sheet: {}", e))?;
+        }
+
+        for (row, values) in rows.iter().enumerate().take(MAX_EXCEL_ROW as usize) {
+            for (col, value) in values.iter().enumerate().take(MAX_EXCEL_COL as usize) {
+                if !value.is_blank_or_empty_string() && !value.is_html() && !value.is_image() {
+                    write_excel_cell_value(worksheet, row as u32, col as u16, value)?;
+                }
+            }
+        }
 
-            // column widths
-            let custom_column_widths: Vec<(i64, f64)> =
-                sheet.offsets.iter_column_widths().collect();
+        save_excel(workbook)
+    }
+}
 
-            for (col, width) in custom_column_widths {
-                let excel_col = (col - 1) as u16;
-                // convert from pixels to Excel column width units
-                let excel_width = width / COLUMN_WIDTH_MULTIPLIER;
+/// Adds a sheet to an excel workbook.
+fn write_excel_sheet(workbook: &mut Workbook, sheet: &Sheet) -> Result<()> {
+    let error = |e: XlsxError| anyhow!("Error exporting excel file: {}", e);
 
-                worksheet
-                    .set_column_width(excel_col, excel_width)
-                    .map_err(error)?;
-            }
+    let worksheet = workbook.add_worksheet();
+    worksheet
+        .set_name(sheet.name.to_string())
+        .map_err(|e| anyhow!("Error creating excel sheet: {}", e))?;
 
-            // row heights
-            let custom_row_heights: Vec<(i64, f64)> = sheet.offsets.iter_row_heights().collect();
+    // column widths
+    let custom_column_widths: Vec<(i64, f64)> = sheet.offsets.iter_column_widths().collect();
 
-            for (row, height) in custom_row_heights {
-                let excel_row = (row - 1) as u32;
-                // convert from pixels to Excel row height units
-                let excel_height = height / ROW_HEIGHT_MULTIPLIER;
+    for (col, width) in custom_column_widths {
+        let excel_col = (col - 1) as u16;
+        // convert from pixels to Excel column width units
+        let excel_width = width / COLUMN_WIDTH_MULTIPLIER;
 
-                worksheet
-                    .set_row_height(excel_row, excel_height)
-                    .map_err(error)?;
-            }
+        worksheet
+            .set_column_width(excel_col, excel_width)
+            .map_err(error)?;
+    }
 
-            // add grid values to the worksheet
-            match sheet.all_bounds() {
-                GridBounds::Empty => continue,
-                GridBounds::NonEmpty(mut rect) => {
-                    rect.max.x = rect.max.x.min(MAX_EXCEL_COL);
-                    rect.max.y = rect.max.y.min(MAX_EXCEL_ROW);
-                    for pos in rect.iter() {
-                        let (col, row) = (pos.x as u16 - 1, pos.y as u32 - 1);
-                        let mut is_formula_output = false;
-
-                        // data table output
-                        if let Some((_pos, data_table)) = sheet.data_tables.get_contains(pos)
-                            && let Some(code_cell_value) = data_table.code_run()
-                        {
-                            let is_formula = code_cell_value.language == CodeCellLanguage::Formula;
-
-                            is_formula_output =
-                                data_table.get_language() == CodeCellLanguage::Formula;
-
-                            // we currently only care about formulas
-                            // skip spill and error formulas
-                            if is_formula && !data_table.has_spill() && !data_table.has_error() {
-                                let code = code_cell_value.code.as_str();
-                                let display_value = data_table.display_value(false)?;
-
-                                match display_value {
-                                    Value::Single(value) => {
-                                        worksheet
-                                            .write_formula(row, col, code)
-                                            .map_err(error)?
-                                            .set_formula_result(row, col, value.to_string());
-                                    }
-                                    Value::Array(array) => {
-                                        let size = array.size();
-                                        let last_row = row + size.h.get() - 1;
-                                        let last_col = col + size.w.get() as u16 - 1;
-
-                                        worksheet
-                                            .write_array_formula(row, col, last_row, last_col, code)
-                                            .map_err(error)?;
-                                    }
-                                    // we don't expect tuples
-                                    _ => bail!("Unexpected value type"),
-                                }
-                            }
-                        }
+    // row heights
+    let custom_row_heights: Vec<(i64, f64)> = sheet.offsets.iter_row_heights().collect();
 
-                        // flatten all non-formula data
-                        if !is_formula_output {
-                            write_excel_value(worksheet, pos, col, row, sheet)?;
+    for (row, height) in custom_row_heights {
+        let excel_row = (row - 1) as u32;
+        // convert from pixels to Excel row height units
+        let excel_height = height / ROW_HEIGHT_MULTIPLIER;
+
+        worksheet
+            .set_row_height(excel_row, excel_height)
+            .map_err(error)?;
+    }
+
+    // add grid values to the worksheet
+    match sheet.all_bounds() {
+        GridBounds::Empty => return Ok(()),
+        GridBounds::NonEmpty(mut rect) => {
+            rect.max.x = rect.max.x.min(MAX_EXCEL_COL);
+            rect.max.y = rect.max.y.min(MAX_EXCEL_ROW);
+            for pos in rect.iter() {
+                let (col, row) = (pos.x as u16 - 1, pos.y as u32 - 1);
+                let mut is_formula_output = false;
+
+                // data table output
+                if let Some((_pos, data_table)) = sheet.data_tables.get_contains(pos)
+                    && let Some(code_cell_value) = data_table.code_run()
+                {
+                    let is_formula = code_cell_value.language == CodeCellLanguage::Formula;
+
+                    is_formula_output = data_table.get_language() == CodeCellLanguage::Formula;
+
+                    // we currently only care about formulas
+                    // skip spill and error formulas
+                    if is_formula && !data_table.has_spill() && !data_table.has_error() {
+                        let code = code_cell_value.code.as_str();
+                        let display_value = data_table.display_value(false)?;
+
+                        match display_value {
+                            Value::Single(value) => {
+                                worksheet
+                                    .write_formula(row, col, code)
+                                    .map_err(error)?
+                                    .set_formula_result(row, col, value.to_string());
+                            }
+                            Value::Array(array) => {
+                                let size = array.size();
+                                let last_row = row + size.h.get() - 1;
+                                let last_col = col + size.w.get() as u16 - 1;
+
+                                worksheet
+                                    .write_array_formula(row, col, last_row, last_col, code)
+                                    .map_err(error)?;
+                            }
+                            // we don't expect tuples
+                            _ => bail!("Unexpected value type"),
                         }
                     }
                 }
+
+                // flatten all non-formula data
+                if !is_formula_output {
+                    write_excel_value(worksheet, pos, col, row, sheet)?;
+                }
             }
         }
+    }
 
-        let buffer = workbook
-            .save_to_buffer()
-            .map_err(|e| anyhow!("Error writing excel file: {}", e))?;
+    Ok(())
+}
 
-        Ok(buffer)
-    }
+/// Saves an excel workbook to a buffer.
+fn save_excel(mut workbook: Workbook) -> Result<Vec<u8>> {
+    let buffer = workbook
+        .save_to_buffer()
+        .map_err(|e| anyhow!("Error writing excel file: {}", e))?;
+
+    Ok(buffer)
 }
 
 /// Writes a value to an excel worksheet and sets the format.
@@ -217,17 +288,7 @@ fn write_excel_value(
         format = get_excel_formats(Some(&cell_value), pos, sheet);
 
         if !cell_value.is_html() && !cell_value.is_image() {
-            match &cell_value {
-                CellValue::Number(n) => worksheet.write_number(row, col, n.to_f64().unwrap_or(0.0)),
-                CellValue::Text(s) => worksheet.write_string(row, col, s),
-                CellValue::Date(d) => worksheet.write_datetime(row, col, d),
-                CellValue::Time(t) => worksheet.write_datetime(row, col, t),
-                CellValue::DateTime(dt) => worksheet.write_datetime(row, col, dt),
-                CellValue::Logical(b) => worksheet.write_boolean(row, col, *b),
-                _ => worksheet.write_string(row, col, cell_value.to_string()),
-            }
-            .map(|_| ())
-            .map_err(|e| anyhow!("Error writing excel value: {}", e))?;
+            write_excel_cell_value(worksheet, row, col, &cell_value)?;
         }
 
         adjust_cell_value_for_excel(cell_value, pos, sheet);
@@ -240,6 +301,26 @@ fn write_excel_value(
     Ok(())
 }
 
+/// Writes a cell value to an excel worksheet, without a format.
+fn write_excel_cell_value(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    cell_value: &CellValue,
+) -> Result<()> {
+    match cell_value {
+        CellValue::Number(n) => worksheet.write_number(row, col, n.to_f64().unwrap_or(0.0)),
+        CellValue::Text(s) => worksheet.write_string(row, col, s),
+        CellValue::Date(d) => worksheet.write_datetime(row, col, d),
+        CellValue::Time(t) => worksheet.write_datetime(row, col, t),
+        CellValue::DateTime(dt) => worksheet.write_datetime(row, col, dt),
+        CellValue::Logical(b) => worksheet.write_boolean(row, col, *b),
+        _ => worksheet.write_string(row, col, cell_value.to_string()),
+    }
+    .map(|_| ())
+    .map_err(|e| anyhow!("Error writing excel value: {}", e))
+}
+
 /// Gets the excel formats for a cell value.
 fn get_excel_formats(v: Option<&CellValue>, pos: Pos, sheet: &Sheet) -> Format {
     let mut format = Format::new();
@@ -505,6 +586,33 @@ mod tests {
         println!("{result}");
     }
 
+    #[test]
+    fn exports_parquet() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        let mut selected = A1Selection::test_a1("A1:B3");
+        let number = |s: &str| CellValue::Number(crate::number::decimal_from_str(s).unwrap());
+        let vals = vec![
+            vec!["name".into(), "amount".into()],
+            vec!["a".into(), number("1")],
+            vec!["b".into(), number("2.5")],
+        ];
+
+        let sheet = gc.sheet_mut(sheet_id);
+        sheet.set_cell_values(crate::Rect::new(1, 1, 2, 3), Array::from(vals));
+
+        let file = gc.export_parquet_selection(&mut selected).unwrap();
+        let array =
+            crate::parquet::parquet_to_array(file, "test.parquet", None::<fn(&str, u32, u32)>)
+                .unwrap();
+
+        assert_eq!(array.get(0, 0).unwrap(), &CellValue::from("name"));
+        assert_eq!(array.get(1, 0).unwrap(), &CellValue::from("amount"));
+        assert_eq!(array.get(0, 2).unwrap(), &CellValue::from("b"));
+        assert_eq!(array.get(1, 2).unwrap(), &number("2.5"));
+    }
+
     #[test]
     fn exports_excel() {
         let (gc, ..) = simple_csv();
@@ -529,6 +637,59 @@ mod tests {
         // TODO(ddimaria): test excel file formatting once import formatting is implemented
     }
 
+    #[test]
+    fn exports_excel_sheet() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        gc.set_cell_value(pos![sheet_id!A1], "first".into(), None, false);
+        gc.add_sheet(None, None, None, false);
+        let second_sheet_id = gc.sheet_ids()[1];
+        gc.set_cell_value(pos![second_sheet_id!B2], "second".into(), None, false);
+
+        let excel = gc.export_excel_sheet(second_sheet_id).unwrap();
+
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        // avoid replacing the empty default sheet
+        gc.grid.update_sheet_name(sheet_id, "ignore").unwrap();
+        gc.set_cell_value(pos![sheet_id!A1], "ignore".into(), None, false);
+        gc.import_excel(&excel, "test.xlsx", None, false).unwrap();
+
+        // only the exported sheet is imported
+        assert_eq!(gc.sheet_ids().len(), 2);
+        let sheet = gc.sheet(gc.sheet_ids()[1]);
+        assert_eq!(
+            sheet.display_value(pos![B2]),
+            Some(CellValue::Text("second".into()))
+        );
+    }
+
+    #[test]
+    fn exports_excel_selection() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        let vals = vec![vec!["1", "2", "3"], vec!["4", "5", "6"]];
+        let sheet = gc.sheet_mut(sheet_id);
+        sheet.set_cell_values(crate::Rect::new(2, 2, 4, 3), Array::from(vals));
+
+        let mut selected = A1Selection::test_a1("C2:D3");
+        let excel = gc.export_excel_selection(&mut selected).unwrap();
+
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        // avoid replacing the empty default sheet
+        gc.grid.update_sheet_name(sheet_id, "ignore").unwrap();
+        gc.set_cell_value(pos![sheet_id!A1], "ignore".into(), None, false);
+        gc.import_excel(&excel, "test.xlsx", None, false).unwrap();
+
+        // the selection starts at A1 of the exported worksheet
+        let sheet = gc.sheet(gc.sheet_ids()[1]);
+        let display = |pos| sheet.display_value(pos).map(|value| value.to_display());
+        assert_eq!(display(pos![A1]), Some("2".to_string()));
+        assert_eq!(display(pos![B2]), Some("6".to_string()));
+        assert_eq!(display(pos![C1]), None);
+    }
+
     #[test]
     fn test_write_excel_value() {
         let mut gc = GridController::test();
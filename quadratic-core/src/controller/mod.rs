@@ -1,4 +1,6 @@
-use self::{active_transactions::ActiveTransactions, transaction::Transaction};
+use self::{
+    active_transactions::ActiveTransactions, callbacks::TransactionEvent, transaction::Transaction,
+};
 use crate::{
     SheetPos,
     a1::A1Context,
@@ -6,7 +8,7 @@ use crate::{
         active_transactions::pending_transaction::PendingTransaction,
         tracked_transaction::TrackedTransactions,
     },
-    grid::{ConnectionKind, DataTable, Grid, RegionMap, SheetId},
+    grid::{ConnectionKind, ConnectionQuery, DataTable, Grid, RegionMap, SheetId},
     viewport::ViewportBuffer,
 };
 use wasm_bindgen::prelude::*;
@@ -59,8 +61,15 @@ pub struct GridController {
     run_javascript_callback: Option<Box<dyn FnMut(String, i32, i32, String, String) + Send>>,
 
     #[allow(clippy::type_complexity)]
-    run_connection_callback:
-        Option<Box<dyn FnMut(String, i32, i32, String, String, ConnectionKind, String) + Send>>,
+    run_connection_callback: Option<
+        Box<dyn FnMut(String, i32, i32, String, ConnectionQuery, ConnectionKind, String) + Send>,
+    >,
+
+    // callback for cancelling an in-flight connection query (by transaction id)
+    cancel_connection_callback: Option<Box<dyn FnMut(String) + Send>>,
+
+    // callback for embedders that want to know when the grid changes
+    transaction_callback: Option<Box<dyn FnMut(&TransactionEvent) + Send>>,
 }
 
 impl Default for GridController {
@@ -80,6 +89,8 @@ impl Default for GridController {
             run_python_callback: None,
             run_javascript_callback: None,
             run_connection_callback: None,
+            cancel_connection_callback: None,
+            transaction_callback: None,
         }
     }
 }
@@ -277,8 +288,25 @@ impl GridController {
             crate::wasm_bindings::js::jsRunJavascript(transaction_id, x, y, sheet_id, code);
         });
 
-        self.with_run_connection_callback(|transaction_id, x, y, sheet_id, code, kind, id| {
-            crate::wasm_bindings::js::jsConnection(transaction_id, x, y, sheet_id, code, kind, id);
+        self.with_run_connection_callback(
+            |transaction_id, x, y, sheet_id, connection_query, kind, id| {
+                let parameters =
+                    serde_json::to_string(&connection_query.parameters).unwrap_or_default();
+                crate::wasm_bindings::js::jsConnection(
+                    transaction_id,
+                    x,
+                    y,
+                    sheet_id,
+                    connection_query.query,
+                    parameters,
+                    kind,
+                    id,
+                );
+            },
+        );
+
+        self.with_cancel_connection_callback(|transaction_id| {
+            crate::wasm_bindings::js::jsCancelConnection(transaction_id);
         });
 
         self
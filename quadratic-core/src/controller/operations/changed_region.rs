@@ -0,0 +1,270 @@
+//! Finds the parts of a file that an Operation changes.
+//!
+//! This is used by the server to describe a transaction to other systems
+//! without diffing the file.  Operations that change a known range report a
+//! rect; operations that change a sheet more broadly (formats, borders, row
+//! and column changes) report the sheet.  Operations that don't change the
+//! contents of a file (eg, cursors and resizing) report nothing.
+
+use serde::Serialize;
+
+use super::operation::Operation;
+use crate::{
+    Rect, SheetPos, SheetRect,
+    controller::GridController,
+    grid::{DataTable, SheetId},
+};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ChangedRegion {
+    /// A range of cells in a sheet
+    Rect(SheetRect),
+
+    /// A sheet, without a known range
+    Sheet(SheetId),
+}
+
+impl ChangedRegion {
+    pub fn sheet_id(&self) -> SheetId {
+        match self {
+            ChangedRegion::Rect(sheet_rect) => sheet_rect.sheet_id,
+            ChangedRegion::Sheet(sheet_id) => *sheet_id,
+        }
+    }
+}
+
+/// Returns the rect of a data table, or the position if the table isn't found.
+fn data_table_rect(
+    data_table: Option<&DataTable>,
+    sheet_pos: SheetPos,
+    gc: &GridController,
+) -> ChangedRegion {
+    let data_table = data_table.or_else(|| {
+        gc.try_sheet(sheet_pos.sheet_id)
+            .and_then(|sheet| sheet.data_table_at(&sheet_pos.into()))
+    });
+    let rect = match data_table {
+        Some(data_table) => data_table.output_rect(sheet_pos.into(), false),
+        None => Rect::single_pos(sheet_pos.into()),
+    };
+
+    ChangedRegion::Rect(rect.to_sheet_rect(sheet_pos.sheet_id))
+}
+
+impl Operation {
+    /// Returns the parts of a file that this operation changes.  Data tables
+    /// are looked up in `gc` when the operation doesn't include them.
+    pub fn changed_regions(&self, gc: &GridController) -> Vec<ChangedRegion> {
+        match self {
+            Operation::SetCellValues { sheet_pos, values } => {
+                if values.w == 0 || values.h == 0 {
+                    return vec![];
+                }
+
+                vec![ChangedRegion::Rect(SheetRect::from_numbers(
+                    sheet_pos.x,
+                    sheet_pos.y,
+                    values.w as i64,
+                    values.h as i64,
+                    sheet_pos.sheet_id,
+                ))]
+            }
+            Operation::SetDataTable {
+                sheet_pos,
+                data_table,
+                ..
+            } => vec![data_table_rect(data_table.as_ref(), *sheet_pos, gc)],
+            Operation::AddDataTable {
+                sheet_pos,
+                data_table,
+                ..
+            } => vec![data_table_rect(Some(data_table), *sheet_pos, gc)],
+            Operation::SetDataTableAt { sheet_pos, values } => {
+                vec![ChangedRegion::Rect(SheetRect::from_numbers(
+                    sheet_pos.x,
+                    sheet_pos.y,
+                    values.w.max(1) as i64,
+                    values.h.max(1) as i64,
+                    sheet_pos.sheet_id,
+                ))]
+            }
+            Operation::MoveDataTable {
+                old_sheet_pos,
+                new_sheet_pos,
+            } => vec![
+                data_table_rect(None, *old_sheet_pos, gc),
+                data_table_rect(None, *new_sheet_pos, gc),
+            ],
+            Operation::DeleteDataTable { sheet_pos }
+            | Operation::SetChartSize { sheet_pos, .. }
+            | Operation::SetChartCellSize { sheet_pos, .. }
+            | Operation::FlattenDataTable { sheet_pos }
+            | Operation::SwitchDataTableKind { sheet_pos, .. }
+            | Operation::DataTableMeta { sheet_pos, .. }
+            | Operation::DataTableOptionMeta { sheet_pos, .. }
+            | Operation::DataTableFormats { sheet_pos, .. }
+            | Operation::DataTableBorders { sheet_pos, .. }
+            | Operation::SortDataTable { sheet_pos, .. }
+            | Operation::DataTableFirstRowAsHeader { sheet_pos, .. }
+            | Operation::InsertDataTableColumns { sheet_pos, .. }
+            | Operation::DeleteDataTableColumns { sheet_pos, .. }
+            | Operation::InsertDataTableRows { sheet_pos, .. }
+            | Operation::DeleteDataTableRows { sheet_pos, .. }
+            | Operation::ComputeCode { sheet_pos }
+            | Operation::SetComputeCode { sheet_pos, .. } => {
+                vec![data_table_rect(None, *sheet_pos, gc)]
+            }
+            Operation::GridToDataTable { sheet_rect }
+            | Operation::SetCellFormats { sheet_rect, .. }
+            | Operation::SetBorders { sheet_rect, .. } => vec![ChangedRegion::Rect(*sheet_rect)],
+            Operation::MoveCells { source, dest, .. } => {
+                let dest = SheetRect::from_numbers(
+                    dest.x,
+                    dest.y,
+                    source.width() as i64,
+                    source.height() as i64,
+                    dest.sheet_id,
+                );
+
+                vec![ChangedRegion::Rect(*source), ChangedRegion::Rect(dest)]
+            }
+            Operation::ComputeCodeSelection { selection } => selection
+                .iter()
+                .map(|selection| ChangedRegion::Sheet(selection.sheet_id))
+                .collect(),
+            Operation::SetValidation { validation }
+            | Operation::CreateOrUpdateValidation { validation } => {
+                vec![ChangedRegion::Sheet(validation.selection.sheet_id)]
+            }
+            Operation::SetConditionalFormat { conditional_format } => {
+                vec![ChangedRegion::Sheet(conditional_format.selection.sheet_id)]
+            }
+            Operation::AddSheet { sheet } => vec![ChangedRegion::Sheet(sheet.id)],
+            Operation::AddSheetSchema { schema } => schema
+                .sheet_id()
+                .map(ChangedRegion::Sheet)
+                .into_iter()
+                .collect(),
+            Operation::DuplicateSheet { new_sheet_id, .. } => {
+                vec![ChangedRegion::Sheet(*new_sheet_id)]
+            }
+            Operation::ReorderSheet { target, .. } => vec![ChangedRegion::Sheet(*target)],
+            Operation::SetCellFormatsA1 { sheet_id, .. }
+            | Operation::SetBordersA1 { sheet_id, .. }
+            | Operation::DeleteSheet { sheet_id, .. }
+            | Operation::SetSheetName { sheet_id, .. }
+            | Operation::SetSheetColor { sheet_id, .. }
+            | Operation::ReplaceSheet { sheet_id, .. }
+            | Operation::RemoveValidation { sheet_id, .. }
+            | Operation::RemoveValidationSelection { sheet_id, .. }
+            | Operation::DeleteColumn { sheet_id, .. }
+            | Operation::DeleteRow { sheet_id, .. }
+            | Operation::InsertColumn { sheet_id, .. }
+            | Operation::InsertRow { sheet_id, .. }
+            | Operation::MoveColumns { sheet_id, .. }
+            | Operation::MoveRows { sheet_id, .. }
+            | Operation::DeleteColumns { sheet_id, .. }
+            | Operation::DeleteRows { sheet_id, .. }
+            | Operation::SetMergeCells { sheet_id, .. }
+            | Operation::RemoveConditionalFormat { sheet_id, .. } => {
+                vec![ChangedRegion::Sheet(*sheet_id)]
+            }
+            Operation::SetCellFormatsSelection { .. }
+            | Operation::SetBordersSelection { .. }
+            | Operation::ResizeColumn { .. }
+            | Operation::ResizeRow { .. }
+            | Operation::ResizeColumns { .. }
+            | Operation::ResizeRows { .. }
+            | Operation::DefaultRowSize { .. }
+            | Operation::DefaultColumnSize { .. }
+            | Operation::SetCursor { .. }
+            | Operation::SetCursorSelection { .. }
+            | Operation::SetCursorA1 { .. }
+            | Operation::SetValidationWarning { .. } => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CellValue, CopyFormats, cell_values::CellValues, test_util::*};
+
+    #[test]
+    fn test_set_cell_values() {
+        let gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        let operation = Operation::SetCellValues {
+            sheet_pos: SheetPos::new(sheet_id, 2, 3),
+            values: CellValues::new(2, 4),
+        };
+
+        assert_eq!(
+            operation.changed_regions(&gc),
+            vec![ChangedRegion::Rect(SheetRect::new(2, 3, 3, 6, sheet_id))]
+        );
+    }
+
+    #[test]
+    fn test_data_table() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        let pos = pos![E2];
+        let data_table = test_create_data_table(&mut gc, sheet_id, pos, 3, 3);
+        let output_rect = data_table.output_rect(pos, false);
+
+        let operation = Operation::SortDataTable {
+            sheet_pos: pos.to_sheet_pos(sheet_id),
+            sort: None,
+            display_buffer: None,
+        };
+        assert_eq!(
+            operation.changed_regions(&gc),
+            vec![ChangedRegion::Rect(output_rect.to_sheet_rect(sheet_id))]
+        );
+
+        // a table that isn't in the grid is reported by its position
+        let operation = Operation::DeleteDataTable {
+            sheet_pos: SheetPos::new(sheet_id, 100, 100),
+        };
+        assert_eq!(
+            operation.changed_regions(&gc),
+            vec![ChangedRegion::Rect(SheetRect::single_pos(
+                (100, 100).into(),
+                sheet_id
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_sheet_and_ignored() {
+        let gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        let operation = Operation::InsertColumn {
+            sheet_id,
+            column: 1,
+            copy_formats: CopyFormats::None,
+            ignore_tables: false,
+        };
+        assert_eq!(
+            operation.changed_regions(&gc),
+            vec![ChangedRegion::Sheet(sheet_id)]
+        );
+
+        let operation = Operation::ResizeColumn {
+            sheet_id,
+            column: 1,
+            new_size: 10.0,
+            client_resized: true,
+        };
+        assert!(operation.changed_regions(&gc).is_empty());
+
+        let operation = Operation::SetCellValues {
+            sheet_pos: SheetPos::new(sheet_id, 1, 1),
+            values: CellValues::from(CellValue::Text("a".into())),
+        };
+        assert_eq!(operation.changed_regions(&gc)[0].sheet_id(), sheet_id);
+    }
+}
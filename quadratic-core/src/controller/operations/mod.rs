@@ -5,6 +5,7 @@
 pub mod autocomplete;
 pub mod borders;
 pub mod cell_value;
+pub mod changed_region;
 pub mod clipboard;
 pub mod code_cell;
 mod csv;
@@ -0,0 +1,105 @@
+//! Queries sent to connections.
+//!
+//! Handlebars in a connection cell are replaced by driver placeholders, and the
+//! referenced cell values are sent alongside the query as typed parameters so
+//! the connection service binds them instead of pasting them into the SQL.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::CellValue;
+
+/// A typed value bound to a placeholder of a connection query.
+///
+/// This is serialized as `{ "type": "number", "value": "1.5" }` and must stay
+/// in sync with `SqlParameter` in quadratic-rust-shared.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ConnectionParameter {
+    Null,
+    Text(String),
+    Number(Decimal),
+    Logical(bool),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+}
+
+impl From<&CellValue> for ConnectionParameter {
+    fn from(value: &CellValue) -> Self {
+        match value {
+            CellValue::Blank => ConnectionParameter::Null,
+            CellValue::Text(text) => ConnectionParameter::Text(text.to_owned()),
+            CellValue::Number(number) => ConnectionParameter::Number(*number),
+            CellValue::Logical(logical) => ConnectionParameter::Logical(*logical),
+            CellValue::Date(date) => ConnectionParameter::Date(*date),
+            CellValue::Time(time) => ConnectionParameter::Time(*time),
+            CellValue::DateTime(date_time) => ConnectionParameter::DateTime(*date_time),
+            CellValue::Code(code_cell) => ConnectionParameter::from(code_cell.output.as_ref()),
+            CellValue::Instant(_) => ConnectionParameter::Text(value.to_string()),
+            _ => ConnectionParameter::Text(value.to_get_cells()),
+        }
+    }
+}
+
+/// A connection query with its handlebars replaced by placeholders.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ConnectionQuery {
+    pub query: String,
+    pub parameters: Vec<ConnectionParameter>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_parameter_from_cell_value() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 28).unwrap();
+
+        assert_eq!(
+            ConnectionParameter::from(&CellValue::Blank),
+            ConnectionParameter::Null
+        );
+        assert_eq!(
+            ConnectionParameter::from(&CellValue::Text("it's".into())),
+            ConnectionParameter::Text("it's".into())
+        );
+        assert_eq!(
+            ConnectionParameter::from(&CellValue::Number(Decimal::from(42))),
+            ConnectionParameter::Number(Decimal::from(42))
+        );
+        assert_eq!(
+            ConnectionParameter::from(&CellValue::Logical(true)),
+            ConnectionParameter::Logical(true)
+        );
+        assert_eq!(
+            ConnectionParameter::from(&CellValue::Date(date)),
+            ConnectionParameter::Date(date)
+        );
+    }
+
+    #[test]
+    fn test_connection_query_serialization() {
+        let query = ConnectionQuery {
+            query: "SELECT * FROM users WHERE id = $1 AND name = $2".into(),
+            parameters: vec![
+                ConnectionParameter::Number(Decimal::from(1)),
+                ConnectionParameter::Text("Ann".into()),
+                ConnectionParameter::Null,
+            ],
+        };
+        let json = serde_json::to_value(&query).unwrap();
+
+        assert_eq!(
+            json["parameters"][1],
+            serde_json::json!({ "type": "text", "value": "Ann" })
+        );
+        assert_eq!(json["parameters"][2], serde_json::json!({ "type": "null" }));
+        assert_eq!(
+            serde_json::from_value::<ConnectionQuery>(json).unwrap(),
+            query
+        );
+    }
+}
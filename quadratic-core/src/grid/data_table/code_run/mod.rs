@@ -5,13 +5,16 @@
 //! performed yet).
 
 use crate::{RunError, formulas::Formula, grid::CellsAccessed};
+use quadratic_rust_shared::utils::sql_lexer::Quoting;
 use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 use wasm_bindgen::{JsValue, convert::IntoWasmAbi};
 
 mod adjust;
+mod connection_query;
 
 pub use adjust::*;
+pub use connection_query::*;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct CodeRun {
@@ -175,6 +178,56 @@ pub enum ConnectionKind {
     Plaid,
     /// Financial data connection for STOCKHISTORY formula
     StockHistory,
+    Sqlite,
+    Duckdb,
+    Stripe,
+}
+
+impl ConnectionKind {
+    /// Returns the placeholder the connection's driver binds the (1-based)
+    /// parameter `index` to.
+    pub fn placeholder(self, index: usize) -> String {
+        match self {
+            ConnectionKind::Mysql
+            | ConnectionKind::Mariadb
+            | ConnectionKind::Snowflake => "?".to_string(),
+            ConnectionKind::Sqlite => format!("?{index}"),
+            ConnectionKind::Bigquery => format!("@p{index}"),
+            ConnectionKind::Mssql => format!("@P{index}"),
+            ConnectionKind::Postgres
+            | ConnectionKind::Cockroachdb
+            | ConnectionKind::Supabase
+            | ConnectionKind::Neon
+            | ConnectionKind::Mixpanel
+            | ConnectionKind::GoogleAnalytics
+            | ConnectionKind::Plaid
+            | ConnectionKind::Stripe
+            | ConnectionKind::StockHistory
+            | ConnectionKind::Duckdb => format!("${index}"),
+        }
+    }
+
+    /// Returns how the connection's database quotes strings and comments.
+    pub fn quoting(self) -> Quoting {
+        match self {
+            ConnectionKind::Mysql
+            | ConnectionKind::Mariadb
+            | ConnectionKind::Snowflake
+            | ConnectionKind::Bigquery => Quoting::Backslash,
+            ConnectionKind::Postgres
+            | ConnectionKind::Mssql
+            | ConnectionKind::Cockroachdb
+            | ConnectionKind::Supabase
+            | ConnectionKind::Neon
+            | ConnectionKind::Mixpanel
+            | ConnectionKind::GoogleAnalytics
+            | ConnectionKind::Plaid
+            | ConnectionKind::Stripe
+            | ConnectionKind::StockHistory
+            | ConnectionKind::Sqlite
+            | ConnectionKind::Duckdb => Quoting::Standard,
+        }
+    }
 }
 
 impl wasm_bindgen::describe::WasmDescribe for ConnectionKind {
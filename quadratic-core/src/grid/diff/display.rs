@@ -0,0 +1,209 @@
+//! Readable output for a GridDiff.
+//!
+//! ```text
+//! sheet "Sheet1" (changed)
+//!   name: "Sheet1" -> "Data"
+//!   cell A1: "hello" -> "world"
+//!   cell B2: (empty) -> =SUM(A1:A3)
+//!   format B1:C4: (none) -> bold: true
+//!   data table C3: (none) -> "Table1" (Python, 2x3)
+//!   validation A1:A5: added
+//! ```
+//!
+//! Changes to the sheet's order, column widths and row heights, borders and
+//! merged cells are listed as `borders: changed`.
+
+use std::fmt;
+
+use super::{Change, GridDiff, SheetDiff, SheetDiffKind};
+use crate::{
+    CellValue,
+    a1::{A1Context, A1Selection},
+    grid::{CodeCellLanguage, DataTable, DataTableKind, formats::Format},
+};
+
+/// Describes a cell value. Code cells are shown as their code.
+pub(crate) fn describe_cell_value(value: &CellValue) -> String {
+    match value {
+        CellValue::Code(code_cell) => match code_cell.code_run.language {
+            CodeCellLanguage::Formula => format!("={}", code_cell.code_run.code),
+            ref language => format!(
+                "{} code {:?} (output {})",
+                language.as_string(),
+                code_cell.code_run.code,
+                describe_cell_value(&code_cell.output)
+            ),
+        },
+        CellValue::Blank => "(empty)".to_string(),
+        value => value.repr(),
+    }
+}
+
+fn describe_format(format: &Format) -> String {
+    format.to_string().trim_end_matches(", ").to_string()
+}
+
+fn describe_data_table(data_table: &DataTable) -> String {
+    let size = data_table.output_size();
+    let kind = match &data_table.kind {
+        DataTableKind::CodeRun(code_run) => {
+            format!("{} {:?}", code_run.language.as_string(), code_run.code)
+        }
+        DataTableKind::Import(import) => format!("import {}", import.file_name),
+    };
+    format!(
+        "{:?} ({kind}, {}x{})",
+        data_table.name(),
+        size.w.get(),
+        size.h.get()
+    )
+}
+
+fn describe_selection(selection: &A1Selection) -> String {
+    selection.to_string(Some(selection.sheet_id), &A1Context::default())
+}
+
+/// Writes a change as `label: old -> new`.
+fn write_change<K, T>(
+    f: &mut fmt::Formatter<'_>,
+    label: &str,
+    change: &Change<K, T>,
+    describe: impl Fn(&T) -> String,
+    none: &str,
+) -> fmt::Result {
+    let describe = |item: &Option<T>| item.as_ref().map_or(none.to_string(), &describe);
+    writeln!(
+        f,
+        "  {label}: {} -> {}",
+        describe(&change.old),
+        describe(&change.new)
+    )
+}
+
+impl fmt::Display for SheetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.kind {
+            SheetDiffKind::Added(_) => "added",
+            SheetDiffKind::Removed => "removed",
+            SheetDiffKind::Changed => "changed",
+        };
+        writeln!(f, "sheet {:?} ({status})", self.name)?;
+
+        if let Some((old, new)) = &self.name_change {
+            writeln!(f, "  name: {old:?} -> {new:?}")?;
+        }
+        if let Some((old, new)) = &self.color_change {
+            let describe = |color: &Option<String>| color.clone().unwrap_or("(none)".into());
+            writeln!(f, "  color: {} -> {}", describe(old), describe(new))?;
+        }
+        if self.order_change.is_some() {
+            writeln!(f, "  order: changed")?;
+        }
+        if self.offsets_change.is_some() {
+            writeln!(f, "  column widths and row heights: changed")?;
+        }
+        if self.borders_change.is_some() {
+            writeln!(f, "  borders: changed")?;
+        }
+        if self.merge_cells_change.is_some() {
+            writeln!(f, "  merged cells: changed")?;
+        }
+        for change in &self.cells {
+            let label = format!("cell {}", change.key.a1_string());
+            write_change(f, &label, change, describe_cell_value, "(empty)")?;
+        }
+        for change in &self.formats {
+            let label = format!("format {}", change.key);
+            write_change(f, &label, change, describe_format, "(none)")?;
+        }
+        for change in &self.data_tables {
+            let label = format!("data table {}", change.key.a1_string());
+            write_change(f, &label, change, describe_data_table, "(none)")?;
+        }
+        for change in &self.validations {
+            let validation = change.new.as_ref().or(change.old.as_ref());
+            let selection = validation.map(|v| describe_selection(&v.selection));
+            let status = match (&change.old, &change.new) {
+                (None, _) => "added",
+                (_, None) => "removed",
+                _ => "changed",
+            };
+            writeln!(
+                f,
+                "  validation {} ({}): {status}",
+                selection.unwrap_or_default(),
+                change.key
+            )?;
+        }
+        for change in &self.conditional_formats {
+            let conditional_format = change.new.as_ref().or(change.old.as_ref());
+            let selection = conditional_format.map(|cf| describe_selection(&cf.selection));
+            let status = match (&change.old, &change.new) {
+                (None, _) => "added",
+                (_, None) => "removed",
+                _ => "changed",
+            };
+            writeln!(
+                f,
+                "  conditional format {} ({}): {status}",
+                selection.unwrap_or_default(),
+                change.key
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for GridDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no differences");
+        }
+        for sheet in &self.sheets {
+            write!(f, "{sheet}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        controller::GridController,
+        grid::{CodeCellLanguage, diff::GridDiff},
+    };
+
+    #[test]
+    fn test_display_grid_diff() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        gc.set_cell_value(pos![sheet_id!A1], "hello".into(), None, false);
+        let old = gc.grid().clone();
+
+        gc.set_cell_value(pos![sheet_id!A1], "world".into(), None, false);
+        gc.set_code_cell(
+            pos![sheet_id!B2],
+            CodeCellLanguage::Formula,
+            "SUM(A1:A3)".into(),
+            None,
+            None,
+            false,
+        );
+        gc.sheet_mut(sheet_id)
+            .formats
+            .bold
+            .set_rect(2, 1, Some(3), Some(4), Some(true));
+
+        let diff = GridDiff::new(&old, gc.grid());
+        assert_eq!(
+            diff.to_string(),
+            "sheet \"Sheet1\" (changed)\n\
+             \x20 cell A1: \"hello\" -> \"world\"\n\
+             \x20 cell B2: (empty) -> =SUM(A1:A3)\n\
+             \x20 format B1:C4: (none) -> bold: true\n"
+        );
+
+        assert_eq!(GridDiff::new(&old, &old).to_string(), "no differences\n");
+    }
+}
@@ -0,0 +1,192 @@
+//! Diff of sheet formatting.
+//!
+//! Formatting is stored as (possibly unbounded) rectangles per format field,
+//! so it is compared region by region rather than cell by cell. The sheet is
+//! split into regions along every boundary of every field in either sheet;
+//! within a region the formatting of both sheets is constant, so comparing a
+//! single cell per region is exact.
+
+use std::{collections::BTreeSet, fmt};
+
+use super::{Change, FormatChange};
+use crate::{
+    Pos,
+    a1::RefRangeBounds,
+    grid::{sheet_formatting::SheetFormatting, sheet_formatting::SheetFormattingType},
+};
+
+/// A rectangle of cells. `None` ends are unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatRegion {
+    pub x1: i64,
+    pub y1: i64,
+    pub x2: Option<i64>,
+    pub y2: Option<i64>,
+}
+
+impl FormatRegion {
+    /// Returns the region as a range (eg, `A1:B2`, `C`, or `D5:`).
+    pub fn to_range(&self) -> RefRangeBounds {
+        RefRangeBounds::new_relative(
+            self.x1,
+            self.y1,
+            self.x2.unwrap_or(i64::MAX),
+            self.y2.unwrap_or(i64::MAX),
+        )
+    }
+
+    pub fn intersects(&self, other: &FormatRegion) -> bool {
+        fn overlaps(a1: i64, a2: Option<i64>, b1: i64, b2: Option<i64>) -> bool {
+            a2.is_none_or(|a2| a2 >= b1) && b2.is_none_or(|b2| b2 >= a1)
+        }
+
+        overlaps(self.x1, self.x2, other.x1, other.x2)
+            && overlaps(self.y1, self.y2, other.y1, other.y2)
+    }
+}
+
+impl fmt::Display for FormatRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_range())
+    }
+}
+
+/// Adds the coordinates where a format field's value may change.
+fn add_boundaries<T>(item: &SheetFormattingType<T>, xs: &mut BTreeSet<i64>, ys: &mut BTreeSet<i64>)
+where
+    T: Clone + PartialEq + fmt::Debug,
+{
+    for (x1, y1, x2, y2, _) in item.to_rects() {
+        xs.insert(x1);
+        ys.insert(y1);
+        if let Some(x2) = x2 {
+            xs.insert(x2.saturating_add(1));
+        }
+        if let Some(y2) = y2 {
+            ys.insert(y2.saturating_add(1));
+        }
+    }
+}
+
+/// Splits sorted coordinates into consecutive bands. The last band is
+/// unbounded.
+fn bands(coords: &[i64]) -> Vec<(i64, Option<i64>)> {
+    coords
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| (start, coords.get(i + 1).map(|next| next - 1)))
+        .collect()
+}
+
+/// Compares the formatting of two sheets. Changes are returned column band by
+/// column band, with vertically adjacent regions that have the same change
+/// combined.
+pub(crate) fn format_changes(old: &SheetFormatting, new: &SheetFormatting) -> Vec<FormatChange> {
+    let mut xs = BTreeSet::from([1]);
+    let mut ys = BTreeSet::from([1]);
+    for formats in [old, new] {
+        add_boundaries(&formats.align, &mut xs, &mut ys);
+        add_boundaries(&formats.vertical_align, &mut xs, &mut ys);
+        add_boundaries(&formats.wrap, &mut xs, &mut ys);
+        add_boundaries(&formats.numeric_format, &mut xs, &mut ys);
+        add_boundaries(&formats.numeric_decimals, &mut xs, &mut ys);
+        add_boundaries(&formats.numeric_commas, &mut xs, &mut ys);
+        add_boundaries(&formats.bold, &mut xs, &mut ys);
+        add_boundaries(&formats.italic, &mut xs, &mut ys);
+        add_boundaries(&formats.text_color, &mut xs, &mut ys);
+        add_boundaries(&formats.fill_color, &mut xs, &mut ys);
+        add_boundaries(&formats.date_time, &mut xs, &mut ys);
+        add_boundaries(&formats.underline, &mut xs, &mut ys);
+        add_boundaries(&formats.strike_through, &mut xs, &mut ys);
+        add_boundaries(&formats.font_size, &mut xs, &mut ys);
+    }
+
+    let xs = xs.into_iter().filter(|&x| x >= 1).collect::<Vec<_>>();
+    let ys = ys.into_iter().filter(|&y| y >= 1).collect::<Vec<_>>();
+    let y_bands = bands(&ys);
+
+    let mut changes = vec![];
+    for (x1, x2) in bands(&xs) {
+        let mut current: Option<FormatChange> = None;
+        for &(y1, y2) in &y_bands {
+            let pos = Pos { x: x1, y: y1 };
+            let old = old.try_format(pos);
+            let new = new.try_format(pos);
+
+            if old == new {
+                changes.extend(current.take());
+                continue;
+            }
+
+            match &mut current {
+                Some(change) if change.old == old && change.new == new => change.key.y2 = y2,
+                _ => {
+                    changes.extend(current.take());
+                    current = Some(Change {
+                        key: FormatRegion { x1, y1, x2, y2 },
+                        old,
+                        new,
+                    });
+                }
+            }
+        }
+        changes.extend(current);
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_changes_unbounded() {
+        let old = SheetFormatting::default();
+        let mut new = SheetFormatting::default();
+        new.italic.set_rect(1, 3, None, Some(3), Some(true));
+        new.italic.set_rect(2, 3, Some(2), Some(3), None);
+
+        let changes = format_changes(&old, &new);
+        let regions = changes
+            .iter()
+            .map(|change| change.key.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(regions, vec!["A3", "C3:3"]);
+        assert!(changes.iter().all(|change| change.old.is_none()));
+    }
+
+    #[test]
+    fn test_format_changes_same_formats() {
+        let mut old = SheetFormatting::default();
+        old.bold.set_rect(1, 1, Some(10), None, Some(true));
+        let new = old.clone();
+
+        assert!(format_changes(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_format_region_intersects() {
+        let region = FormatRegion {
+            x1: 2,
+            y1: 2,
+            x2: Some(4),
+            y2: Some(4),
+        };
+        let column = FormatRegion {
+            x1: 4,
+            y1: 1,
+            x2: Some(4),
+            y2: None,
+        };
+        let row = FormatRegion {
+            x1: 1,
+            y1: 5,
+            x2: None,
+            y2: Some(5),
+        };
+        assert!(region.intersects(&column));
+        assert!(column.intersects(&row));
+        assert!(!region.intersects(&row));
+    }
+}
@@ -0,0 +1,697 @@
+//! Operations from a GridDiff, and three-way merges.
+//!
+//! A three-way merge takes the changes from `base` to `theirs` and turns them
+//! into operations that can be applied to `ours`. Items that were changed
+//! differently in `ours` and `theirs` are conflicts: they are skipped (ours
+//! wins) and reported.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    hash::Hash,
+};
+
+use super::{
+    CellChange, Change, FormatChange, GridDiff, SheetDiff, SheetDiffKind, cell_values_eq,
+    data_tables_eq, match_sheets, option_eq,
+};
+use crate::{
+    CellValue, ClearOption, SheetPos,
+    a1::A1Selection,
+    cell_values::CellValues,
+    controller::operations::operation::Operation,
+    grid::{
+        Contiguous2D, Grid, SheetId,
+        file::sheet_schema::export_sheet,
+        formats::SheetFormatUpdates,
+        js_types::{JsColumnWidth, JsRowHeight},
+        sheet::borders::{Borders, BordersUpdates},
+    },
+    sheet_offsets::SheetOffsets,
+};
+
+/// An item that was changed differently in both grids of a merge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    /// Name of the sheet in `theirs`.
+    pub sheet_name: String,
+
+    /// Description of the conflicting item (eg, "cell A1").
+    pub item: String,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sheet {:?}: {}", self.sheet_name, self.item)
+    }
+}
+
+/// The result of a three-way merge.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GridMerge {
+    /// Operations that apply the non-conflicting changes from `theirs` to
+    /// `ours`.
+    pub operations: Vec<Operation>,
+
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl GridDiff {
+    /// Returns the operations that turn the old grid into the new grid.
+    pub fn operations(&self) -> Vec<Operation> {
+        let mut operations = vec![];
+        for sheet in &self.sheets {
+            match (&sheet.kind, sheet.old_sheet_id) {
+                (SheetDiffKind::Added(new_sheet), _) => {
+                    operations.push(Operation::AddSheetSchema {
+                        schema: Box::new(export_sheet((**new_sheet).clone())),
+                    });
+                }
+                (SheetDiffKind::Removed, Some(sheet_id)) => {
+                    operations.push(Operation::DeleteSheet {
+                        sheet_id,
+                        sheet_name: Some(sheet.name.clone()),
+                    });
+                }
+                (SheetDiffKind::Changed, Some(sheet_id)) => {
+                    let mut merge = SheetMerge::new(sheet, None, sheet_id);
+                    merge.apply();
+                    operations.extend(merge.operations);
+                }
+                _ => (),
+            }
+        }
+        operations
+    }
+}
+
+/// Merges the changes from `base` to `theirs` into `ours`.
+///
+/// Returns the operations to apply to `ours`, and the changes that were
+/// skipped because `ours` changed the same item differently.
+pub fn merge_grids(base: &Grid, ours: &Grid, theirs: &Grid) -> GridMerge {
+    let ours_diff = GridDiff::new(base, ours);
+    let theirs_diff = GridDiff::new(base, theirs);
+    let ours_sheet_ids = match_sheets(base, ours)
+        .into_iter()
+        .filter_map(|(base, ours)| Some((base?.id, ours?.id)))
+        .collect::<HashMap<_, _>>();
+
+    let mut result = GridMerge::default();
+    let mut conflict = |sheet: &SheetDiff, item: &str| {
+        result.conflicts.push(MergeConflict {
+            sheet_name: sheet.name.clone(),
+            item: item.to_string(),
+        })
+    };
+    let mut operations = vec![];
+
+    for sheet in &theirs_diff.sheets {
+        let base_sheet_id = sheet.old_sheet_id;
+        let ours_sheet_id = base_sheet_id.and_then(|id| ours_sheet_ids.get(&id).copied());
+        let ours_sheet = base_sheet_id.and_then(|id| ours_diff.sheet_by_old_id(id));
+
+        match &sheet.kind {
+            SheetDiffKind::Added(new_sheet) => {
+                let existing = ours
+                    .try_sheet(new_sheet.id)
+                    .or_else(|| ours.try_sheet_from_name(&new_sheet.name));
+                match existing {
+                    // added in both with the same content
+                    Some(existing)
+                        if SheetDiff::new(Some(existing), Some(new_sheet.as_ref())).is_empty() => {}
+                    Some(_) => conflict(sheet, "sheet added in both"),
+                    None => operations.push(Operation::AddSheetSchema {
+                        schema: Box::new(export_sheet((**new_sheet).clone())),
+                    }),
+                }
+            }
+            SheetDiffKind::Removed => match (ours_sheet_id, ours_sheet) {
+                // removed in both
+                (None, _) => (),
+                (Some(_), Some(_)) => conflict(sheet, "sheet removed but changed in ours"),
+                (Some(sheet_id), None) => operations.push(Operation::DeleteSheet {
+                    sheet_id,
+                    sheet_name: Some(sheet.name.clone()),
+                }),
+            },
+            SheetDiffKind::Changed => match ours_sheet_id {
+                None => conflict(sheet, "sheet changed but removed in ours"),
+                Some(sheet_id) => {
+                    let mut merge = SheetMerge::new(sheet, ours_sheet, sheet_id);
+                    merge.apply();
+                    operations.extend(merge.operations);
+                    for item in merge.conflicts {
+                        conflict(sheet, &item);
+                    }
+                }
+            },
+        }
+    }
+
+    result.operations = operations;
+    result
+}
+
+/// Builds the operations for one sheet's changes, skipping the changes that
+/// conflict with the changes already made in `ours`.
+struct SheetMerge<'a> {
+    theirs: &'a SheetDiff,
+    ours: Option<&'a SheetDiff>,
+
+    /// Id of the sheet the operations are applied to.
+    sheet_id: SheetId,
+
+    operations: Vec<Operation>,
+
+    /// Descriptions of the conflicting items.
+    conflicts: Vec<String>,
+}
+
+impl<'a> SheetMerge<'a> {
+    fn new(theirs: &'a SheetDiff, ours: Option<&'a SheetDiff>, sheet_id: SheetId) -> Self {
+        Self {
+            theirs,
+            ours,
+            sheet_id,
+            operations: vec![],
+            conflicts: vec![],
+        }
+    }
+
+    /// Returns the changes that are not already in `ours`, and records the
+    /// ones that conflict.
+    fn keyed<K: Eq + Hash, T>(
+        &mut self,
+        theirs: &'a [Change<K, T>],
+        ours: impl Fn(&'a SheetDiff) -> &'a [Change<K, T>],
+        eq: impl Fn(&T, &T) -> bool,
+        describe: impl Fn(&K) -> String,
+    ) -> Vec<&'a Change<K, T>> {
+        let ours = self
+            .ours
+            .map(|diff| {
+                ours(diff)
+                    .iter()
+                    .map(|change| (&change.key, change))
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+
+        theirs
+            .iter()
+            .filter(|change| match ours.get(&change.key) {
+                None => true,
+                Some(ours) => {
+                    if !option_eq(ours.new.as_ref(), change.new.as_ref(), &eq) {
+                        self.conflicts.push(describe(&change.key));
+                    }
+                    false
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the change to a property of the whole sheet if it's not
+    /// already in `ours`, and records it if it conflicts.
+    fn whole<T: PartialEq>(
+        &mut self,
+        theirs: &'a Option<(T, T)>,
+        ours: impl Fn(&'a SheetDiff) -> &'a Option<(T, T)>,
+        item: &str,
+    ) -> Option<&'a (T, T)> {
+        let theirs = theirs.as_ref()?;
+        match self.ours.and_then(|diff| ours(diff).as_ref()) {
+            Some((_, ours)) if *ours == theirs.1 => None,
+            Some(_) => {
+                self.conflicts.push(item.to_string());
+                None
+            }
+            None => Some(theirs),
+        }
+    }
+
+    fn apply(&mut self) {
+        let theirs = self.theirs;
+        let sheet_id = self.sheet_id;
+
+        // sheet name, color and order
+        if let Some((old_name, new_name)) =
+            self.whole(&theirs.name_change, |ours| &ours.name_change, "sheet name")
+        {
+            self.operations.push(Operation::SetSheetName {
+                sheet_id,
+                name: new_name.clone(),
+                old_sheet_name: Some(old_name.clone()),
+            });
+        }
+        if let Some((_, new_color)) = self.whole(
+            &theirs.color_change,
+            |ours| &ours.color_change,
+            "sheet color",
+        ) {
+            self.operations.push(Operation::SetSheetColor {
+                sheet_id,
+                color: new_color.clone(),
+            });
+        }
+        if let Some((_, new_order)) = self.whole(
+            &theirs.order_change,
+            |ours| &ours.order_change,
+            "sheet order",
+        ) {
+            self.operations.push(Operation::ReorderSheet {
+                target: sheet_id,
+                order: new_order.clone(),
+            });
+        }
+
+        // column widths and row heights
+        if let Some((old, new)) = self.whole(
+            &theirs.offsets_change,
+            |ours| &ours.offsets_change,
+            "column widths and row heights",
+        ) {
+            self.operations
+                .extend(offsets_operations(sheet_id, old, new));
+        }
+
+        // cell values
+        let cells = self.keyed(
+            &theirs.cells,
+            |ours| ours.cells.as_slice(),
+            cell_values_eq,
+            |pos| format!("cell {}", pos.a1_string()),
+        );
+        self.operations.extend(cell_operations(cells, sheet_id));
+
+        // formats
+        for change in &theirs.formats {
+            if self.format_conflicts(change) {
+                self.conflicts.push(format!("format {}", change.key));
+                continue;
+            }
+            let selection = A1Selection::from_ref_range_bounds(sheet_id, change.key.to_range());
+            let format = change.new.clone().unwrap_or_default();
+            self.operations.push(Operation::SetCellFormatsA1 {
+                sheet_id,
+                formats: SheetFormatUpdates::from_selection(&selection, format.to_replace()),
+            });
+        }
+
+        // borders
+        if let Some((old, new)) = self.whole(
+            &theirs.borders_change,
+            |ours| &ours.borders_change,
+            "borders",
+        ) {
+            self.operations.push(Operation::SetBordersA1 {
+                sheet_id,
+                borders: borders_updates(old, new),
+            });
+        }
+
+        // data tables
+        let data_tables = self.keyed(
+            &theirs.data_tables,
+            |ours| ours.data_tables.as_slice(),
+            data_tables_eq,
+            |pos| format!("data table {}", pos.a1_string()),
+        );
+        for change in data_tables {
+            self.operations.push(Operation::SetDataTable {
+                sheet_pos: change.key.to_sheet_pos(sheet_id),
+                data_table: change.new.clone(),
+                index: usize::MAX,
+                ignore_old_data_table: true,
+            });
+        }
+
+        // validations
+        let validations = self.keyed(
+            &theirs.validations,
+            |ours| ours.validations.as_slice(),
+            |a, b| a == b,
+            |id| format!("validation {id}"),
+        );
+        for change in validations {
+            self.operations.push(match &change.new {
+                Some(validation) => {
+                    let mut validation = validation.clone();
+                    validation.selection.sheet_id = sheet_id;
+                    Operation::SetValidation { validation }
+                }
+                None => Operation::RemoveValidation {
+                    sheet_id,
+                    validation_id: change.key,
+                },
+            });
+        }
+
+        // conditional formats
+        let conditional_formats = self.keyed(
+            &theirs.conditional_formats,
+            |ours| ours.conditional_formats.as_slice(),
+            |a, b| a == b,
+            |id| format!("conditional format {id}"),
+        );
+        for change in conditional_formats {
+            self.operations.push(match &change.new {
+                Some(conditional_format) => {
+                    let mut conditional_format = conditional_format.clone();
+                    conditional_format.selection.sheet_id = sheet_id;
+                    Operation::SetConditionalFormat { conditional_format }
+                }
+                None => Operation::RemoveConditionalFormat {
+                    sheet_id,
+                    conditional_format_id: change.key,
+                },
+            });
+        }
+
+        // merged cells, after the cell values since merging clears the cells
+        // that are covered by a merged cell
+        if let Some((old, new)) = self.whole(
+            &theirs.merge_cells_change,
+            |ours| &ours.merge_cells_change,
+            "merged cells",
+        ) {
+            self.operations.push(Operation::SetMergeCells {
+                sheet_id,
+                merge_cells_updates: replace_updates(old.export(), new.export()),
+            });
+        }
+    }
+
+    /// Returns true if `ours` changed the formatting of any cell in the
+    /// change's region to something other than `theirs`.
+    fn format_conflicts(&self, change: &FormatChange) -> bool {
+        self.ours.is_some_and(|ours| {
+            ours.formats
+                .iter()
+                .any(|ours| ours.key.intersects(&change.key) && ours.new != change.new)
+        })
+    }
+}
+
+/// Returns one SetCellValues operation per row of changed cells.
+fn cell_operations<'a>(
+    changes: impl IntoIterator<Item = &'a CellChange>,
+    sheet_id: SheetId,
+) -> Vec<Operation> {
+    let mut rows = BTreeMap::<i64, Vec<&CellChange>>::new();
+    for change in changes {
+        rows.entry(change.key.y).or_default().push(change);
+    }
+
+    rows.into_iter()
+        .filter_map(|(y, changes)| {
+            let x = changes.iter().map(|change| change.key.x).min()?;
+            let mut values = CellValues::new(0, 1);
+            for change in changes {
+                let value = change.new.clone().unwrap_or(CellValue::Blank);
+                values.set((change.key.x - x) as u32, 0, value);
+            }
+            Some(Operation::SetCellValues {
+                sheet_pos: SheetPos { x, y, sheet_id },
+                values,
+            })
+        })
+        .collect()
+}
+
+/// Returns the operations that change a sheet's column widths and row heights
+/// from `old` to `new`. Changing a default size clears the custom sizes, so
+/// the defaults are set first.
+fn offsets_operations(sheet_id: SheetId, old: &SheetOffsets, new: &SheetOffsets) -> Vec<Operation> {
+    let mut operations = vec![];
+    let (old_width, old_height) = old.defaults();
+    let (new_width, new_height) = new.defaults();
+
+    if old_width != new_width {
+        operations.push(Operation::DefaultColumnSize {
+            sheet_id,
+            size: new_width,
+        });
+    }
+    if old_height != new_height {
+        operations.push(Operation::DefaultRowSize {
+            sheet_id,
+            size: new_height,
+        });
+    }
+
+    let columns = old
+        .iter_column_widths()
+        .chain(new.iter_column_widths())
+        .map(|(column, _)| column)
+        .collect::<BTreeSet<_>>();
+    if !columns.is_empty() {
+        operations.push(Operation::ResizeColumns {
+            sheet_id,
+            column_widths: columns
+                .into_iter()
+                .map(|column| JsColumnWidth {
+                    column,
+                    width: new.column_width(column),
+                })
+                .collect(),
+        });
+    }
+
+    let rows = old
+        .iter_row_heights()
+        .chain(new.iter_row_heights())
+        .map(|(row, _)| row)
+        .collect::<BTreeSet<_>>();
+    if !rows.is_empty() {
+        operations.push(Operation::ResizeRows {
+            sheet_id,
+            row_heights: rows
+                .into_iter()
+                .map(|row| JsRowHeight {
+                    row,
+                    height: new.row_height(row),
+                })
+                .collect(),
+            client_resized: false,
+        });
+    }
+
+    operations
+}
+
+/// Returns the updates that change a sheet's borders from `old` to `new`.
+fn borders_updates(old: &Borders, new: &Borders) -> BordersUpdates {
+    BordersUpdates {
+        left: Some(replace_updates(&old.left, &new.left)),
+        right: Some(replace_updates(&old.right, &new.right)),
+        top: Some(replace_updates(&old.top, &new.top)),
+        bottom: Some(replace_updates(&old.bottom, &new.bottom)),
+    }
+}
+
+/// Returns the updates that replace the values of `old` with the values of
+/// `new`: `old`'s values are cleared and then `new`'s are set.
+fn replace_updates<T: Clone + PartialEq + fmt::Debug>(
+    old: &Contiguous2D<Option<T>>,
+    new: &Contiguous2D<Option<T>>,
+) -> Contiguous2D<Option<ClearOption<T>>> {
+    let mut updates = Contiguous2D::new();
+    for (x1, y1, x2, y2, _) in old.to_rects() {
+        updates.set_rect(x1, y1, x2, y2, Some(ClearOption::Clear));
+    }
+    for (x1, y1, x2, y2, value) in new.to_rects() {
+        updates.set_rect(x1, y1, x2, y2, Some(ClearOption::Some(value)));
+    }
+    updates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        controller::GridController,
+        grid::{
+            CodeCellLanguage,
+            sheet::borders::{BorderSelection, BorderStyle},
+        },
+        test_util::test_create_data_table,
+    };
+
+    /// Applies operations to a copy of a grid.
+    fn apply(grid: &Grid, operations: Vec<Operation>) -> Grid {
+        let mut gc = GridController::from_grid(grid.clone(), 0);
+        gc.server_apply_transaction(operations, None);
+        gc.into_grid()
+    }
+
+    #[test]
+    fn test_diff_operations() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        gc.set_cell_value(pos![sheet_id!A1], "hello".into(), None, false);
+        gc.set_cell_value(pos![sheet_id!C1], "removed".into(), None, false);
+        let old = gc.grid().clone();
+
+        gc.set_cell_value(pos![sheet_id!A1], "world".into(), None, false);
+        gc.set_cell_value(pos![sheet_id!C1], "".into(), None, false);
+        gc.set_code_cell(
+            pos![sheet_id!B2],
+            CodeCellLanguage::Formula,
+            "1 + 1".into(),
+            None,
+            None,
+            false,
+        );
+        test_create_data_table(&mut gc, sheet_id, pos![E5], 2, 2);
+        gc.sheet_mut(sheet_id)
+            .formats
+            .bold
+            .set_rect(1, 1, Some(2), None, Some(true));
+        gc.set_sheet_name(sheet_id, "Renamed".into(), None, false);
+        gc.add_sheet_with_name("Added".into(), None, false);
+        let new = gc.grid().clone();
+
+        let operations = GridDiff::new(&old, &new).operations();
+        let applied = apply(&old, operations);
+        let diff = GridDiff::new(&applied, &new);
+        assert!(diff.is_empty(), "{diff}");
+    }
+
+    #[test]
+    fn test_diff_operations_sheet_properties() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        gc.add_sheet_with_name("Other".into(), None, false);
+        gc.set_borders(
+            A1Selection::test_a1("A1:B2"),
+            BorderSelection::All,
+            Some(BorderStyle::default()),
+            None,
+            false,
+        );
+        gc.merge_cells(A1Selection::test_a1("D4:E5"), None, false);
+        let old = gc.grid().clone();
+
+        gc.set_borders(
+            A1Selection::test_a1("A1:B2"),
+            BorderSelection::All,
+            None,
+            None,
+            false,
+        );
+        gc.set_borders(
+            A1Selection::test_a1("C"),
+            BorderSelection::Left,
+            Some(BorderStyle::default()),
+            None,
+            false,
+        );
+        gc.unmerge_cells(A1Selection::test_a1("D4:E5"), None, false);
+        gc.merge_cells(A1Selection::test_a1("G1:H2"), None, false);
+        gc.resize_columns(
+            sheet_id,
+            vec![JsColumnWidth {
+                column: 2,
+                width: 200.0,
+            }],
+            None,
+            false,
+        );
+        gc.move_sheet(sheet_id, None, None, false);
+        let new = gc.grid().clone();
+
+        let diff = GridDiff::new(&old, &new);
+        let sheet = diff.sheet_by_old_id(sheet_id).unwrap();
+        assert!(sheet.order_change.is_some());
+        assert!(sheet.offsets_change.is_some());
+        assert!(sheet.borders_change.is_some());
+        assert!(sheet.merge_cells_change.is_some());
+
+        let applied = apply(&old, diff.operations());
+        let diff = GridDiff::new(&applied, &new);
+        assert!(diff.is_empty(), "{diff}");
+        assert_eq!(applied.sheets().values().last().unwrap().id, sheet_id);
+    }
+
+    #[test]
+    fn test_merge_grids() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        gc.set_cell_value(pos![sheet_id!A1], "base".into(), None, false);
+        let base = gc.grid().clone();
+
+        let mut ours = GridController::from_grid(base.clone(), 0);
+        ours.set_cell_value(pos![sheet_id!B1], "ours".into(), None, false);
+        ours.set_cell_value(pos![sheet_id!D1], "ours".into(), None, false);
+        ours.set_cell_value(pos![sheet_id!E1], "same".into(), None, false);
+
+        let mut theirs = GridController::from_grid(base.clone(), 0);
+        theirs.set_cell_value(pos![sheet_id!A1], "theirs".into(), None, false);
+        theirs.set_cell_value(pos![sheet_id!C1], "theirs".into(), None, false);
+        theirs.set_cell_value(pos![sheet_id!D1], "theirs".into(), None, false);
+        theirs.set_cell_value(pos![sheet_id!E1], "same".into(), None, false);
+        theirs.set_sheet_name(sheet_id, "Theirs".into(), None, false);
+
+        let merge = merge_grids(&base, ours.grid(), theirs.grid());
+        assert_eq!(
+            merge.conflicts,
+            vec![MergeConflict {
+                sheet_name: "Theirs".into(),
+                item: "cell D1".into(),
+            }]
+        );
+
+        let merged = apply(ours.grid(), merge.operations);
+        let sheet = merged.try_sheet(sheet_id).unwrap();
+        assert_eq!(sheet.name, "Theirs");
+        assert_eq!(
+            sheet.display_value(pos![A1]),
+            Some(CellValue::Text("theirs".into()))
+        );
+        assert_eq!(
+            sheet.display_value(pos![B1]),
+            Some(CellValue::Text("ours".into()))
+        );
+        assert_eq!(
+            sheet.display_value(pos![C1]),
+            Some(CellValue::Text("theirs".into()))
+        );
+        assert_eq!(
+            sheet.display_value(pos![D1]),
+            Some(CellValue::Text("ours".into()))
+        );
+        assert_eq!(
+            sheet.display_value(pos![E1]),
+            Some(CellValue::Text("same".into()))
+        );
+    }
+
+    #[test]
+    fn test_merge_sheets() {
+        let mut gc = GridController::test();
+        gc.add_sheet_with_name("Removed".into(), None, false);
+        gc.add_sheet_with_name("Edited".into(), None, false);
+        let base = gc.grid().clone();
+        let removed_id = base.try_sheet_from_name("Removed").unwrap().id;
+        let edited_id = base.try_sheet_from_name("Edited").unwrap().id;
+
+        let mut ours = GridController::from_grid(base.clone(), 0);
+        ours.set_cell_value(pos![edited_id!A1], "ours".into(), None, false);
+
+        let mut theirs = GridController::from_grid(base.clone(), 0);
+        theirs.delete_sheet(removed_id, None, false);
+        theirs.delete_sheet(edited_id, None, false);
+        theirs.add_sheet_with_name("Added".into(), None, false);
+
+        let merge = merge_grids(&base, ours.grid(), theirs.grid());
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].sheet_name, "Edited");
+
+        let merged = apply(ours.grid(), merge.operations);
+        assert!(merged.try_sheet(removed_id).is_none());
+        assert!(merged.try_sheet(edited_id).is_some());
+        assert!(merged.try_sheet_from_name("Added").is_some());
+    }
+}
@@ -0,0 +1,534 @@
+//! Semantic diff of two grids.
+//!
+//! Sheets are matched by id, then by name. For each pair of sheets the diff
+//! reports changes to cell values (including code cells), formats, data
+//! tables, validations and conditional formats, and whether the sheet's
+//! order, column widths and row heights, borders or merged cells changed.
+//! Timestamps and caches are ignored, so re-saving an unchanged file produces
+//! an empty diff.
+//!
+//! A diff can be displayed in a readable form (see `display.rs`), turned into
+//! the `Operation`s that move the old grid to the new grid, or used for a
+//! three-way merge (see `merge.rs`).
+
+mod display;
+mod formats;
+mod merge;
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+pub use formats::FormatRegion;
+pub use merge::{GridMerge, MergeConflict, merge_grids};
+
+use crate::{
+    CellValue, Pos,
+    grid::{
+        DataTable, DataTableKind, Grid, Sheet, SheetId,
+        formats::Format,
+        sheet::{
+            borders::Borders, conditional_format::ConditionalFormat, merge_cells::MergeCells,
+            validations::validation::Validation,
+        },
+    },
+    sheet_offsets::SheetOffsets,
+};
+
+/// A change to a single item that is identified by `key`.
+///
+/// `old` is `None` when the item was added, and `new` is `None` when it was
+/// removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<K, T> {
+    pub key: K,
+    pub old: Option<T>,
+    pub new: Option<T>,
+}
+
+impl<K, T> Change<K, T> {
+    pub fn is_added(&self) -> bool {
+        self.old.is_none()
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.new.is_none()
+    }
+}
+
+pub type CellChange = Change<Pos, CellValue>;
+pub type FormatChange = Change<FormatRegion, Format>;
+pub type DataTableChange = Change<Pos, DataTable>;
+pub type ValidationChange = Change<Uuid, Validation>;
+pub type ConditionalFormatChange = Change<Uuid, ConditionalFormat>;
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SheetDiffKind {
+    /// The sheet only exists in the new grid.
+    Added(Box<Sheet>),
+
+    /// The sheet only exists in the old grid.
+    Removed,
+
+    /// The sheet exists in both grids.
+    Changed,
+}
+
+/// Differences between two versions of a sheet.
+///
+/// For added and removed sheets, the item changes are relative to an empty
+/// sheet, so they list everything the sheet contains.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetDiff {
+    /// Id of the sheet in the old grid (None if the sheet was added).
+    pub old_sheet_id: Option<SheetId>,
+
+    /// Id of the sheet in the new grid (None if the sheet was removed).
+    pub new_sheet_id: Option<SheetId>,
+
+    /// Name of the sheet in the new grid (or the old grid if it was removed).
+    pub name: String,
+
+    pub kind: SheetDiffKind,
+
+    /// (old, new) sheet name.
+    pub name_change: Option<(String, String)>,
+
+    /// (old, new) sheet color.
+    pub color_change: Option<(Option<String>, Option<String>)>,
+
+    /// (old, new) sheet order.
+    pub order_change: Option<(String, String)>,
+
+    /// (old, new) column widths and row heights.
+    pub offsets_change: Option<(SheetOffsets, SheetOffsets)>,
+
+    /// (old, new) borders.
+    pub borders_change: Option<(Borders, Borders)>,
+
+    /// (old, new) merged cells.
+    pub merge_cells_change: Option<(MergeCells, MergeCells)>,
+
+    pub cells: Vec<CellChange>,
+    pub formats: Vec<FormatChange>,
+    pub data_tables: Vec<DataTableChange>,
+    pub validations: Vec<ValidationChange>,
+    pub conditional_formats: Vec<ConditionalFormatChange>,
+}
+
+impl SheetDiff {
+    /// Compares two versions of a sheet. Either (but not both) may be None.
+    pub fn new(old: Option<&Sheet>, new: Option<&Sheet>) -> Self {
+        let empty = Sheet::new(SheetId::new(), String::new(), String::new());
+        let kind = match (old, new) {
+            (None, Some(new)) => SheetDiffKind::Added(Box::new(new.clone())),
+            (Some(_), None) => SheetDiffKind::Removed,
+            _ => SheetDiffKind::Changed,
+        };
+        let name = new
+            .or(old)
+            .map(|sheet| sheet.name.clone())
+            .unwrap_or_default();
+        let old_sheet_id = old.map(|sheet| sheet.id);
+        let new_sheet_id = new.map(|sheet| sheet.id);
+
+        let change = SheetChange { old, new };
+        let name_change = change.of(|sheet| &sheet.name);
+        let color_change = change.of(|sheet| &sheet.color);
+        let order_change = change.of(|sheet| &sheet.order);
+        let offsets_change = change.of(|sheet| &sheet.offsets);
+        let borders_change = change.of(|sheet| &sheet.borders);
+        let merge_cells_change = change.of(|sheet| &sheet.merge_cells);
+
+        let old = old.unwrap_or(&empty);
+        let new = new.unwrap_or(&empty);
+
+        Self {
+            old_sheet_id,
+            new_sheet_id,
+            name,
+            kind,
+            name_change,
+            color_change,
+            order_change,
+            offsets_change,
+            borders_change,
+            merge_cells_change,
+            cells: cell_changes(old, new),
+            formats: formats::format_changes(&old.formats, &new.formats),
+            data_tables: data_table_changes(old, new),
+            validations: keyed_changes(
+                old.validations.validations.iter().map(|v| (v.id, v)),
+                new.validations.validations.iter().map(|v| (v.id, v)),
+                |a, b| a == b,
+            ),
+            conditional_formats: keyed_changes(
+                old.conditional_formats.iter().map(|cf| (cf.id, cf)),
+                new.conditional_formats.iter().map(|cf| (cf.id, cf)),
+                |a, b| a == b,
+            ),
+        }
+    }
+
+    /// Returns true if the sheet exists in both grids and nothing changed.
+    pub fn is_empty(&self) -> bool {
+        matches!(self.kind, SheetDiffKind::Changed)
+            && self.name_change.is_none()
+            && self.color_change.is_none()
+            && self.order_change.is_none()
+            && self.offsets_change.is_none()
+            && self.borders_change.is_none()
+            && self.merge_cells_change.is_none()
+            && self.cells.is_empty()
+            && self.formats.is_empty()
+            && self.data_tables.is_empty()
+            && self.validations.is_empty()
+            && self.conditional_formats.is_empty()
+    }
+}
+
+/// Two versions of a sheet, for comparing the properties of the whole sheet.
+struct SheetChange<'a> {
+    old: Option<&'a Sheet>,
+    new: Option<&'a Sheet>,
+}
+
+impl SheetChange<'_> {
+    /// Returns the (old, new) property if it changed. Properties only change
+    /// when the sheet exists in both grids.
+    fn of<T: Clone + PartialEq>(&self, property: impl Fn(&Sheet) -> &T) -> Option<(T, T)> {
+        let (old, new) = (property(self.old?), property(self.new?));
+        (old != new).then(|| (old.clone(), new.clone()))
+    }
+}
+
+/// Differences between two grids, one entry per sheet that changed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GridDiff {
+    pub sheets: Vec<SheetDiff>,
+}
+
+impl GridDiff {
+    /// Compares two grids.
+    pub fn new(old: &Grid, new: &Grid) -> Self {
+        let sheets = match_sheets(old, new)
+            .into_iter()
+            .map(|(old, new)| SheetDiff::new(old, new))
+            .filter(|diff| !diff.is_empty())
+            .collect();
+
+        Self { sheets }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sheets.is_empty()
+    }
+
+    /// Returns the diff for the sheet with the given id in the old grid.
+    pub fn sheet_by_old_id(&self, sheet_id: SheetId) -> Option<&SheetDiff> {
+        self.sheets
+            .iter()
+            .find(|sheet| sheet.old_sheet_id == Some(sheet_id))
+    }
+}
+
+/// Pairs the sheets of two grids. Sheets are matched by id first and then by
+/// name. Unmatched sheets are paired with None. The result is in the order of
+/// the old grid, followed by sheets that only exist in the new grid.
+pub(crate) fn match_sheets<'a>(
+    old: &'a Grid,
+    new: &'a Grid,
+) -> Vec<(Option<&'a Sheet>, Option<&'a Sheet>)> {
+    let mut unmatched = new.sheets().values().collect::<Vec<_>>();
+    let mut pairs = old
+        .sheets()
+        .values()
+        .map(|sheet| {
+            let index = unmatched.iter().position(|new| new.id == sheet.id);
+            (sheet, index.map(|index| unmatched.remove(index)))
+        })
+        .collect::<Vec<_>>();
+
+    for (old, new) in pairs.iter_mut().filter(|(_, new)| new.is_none()) {
+        if let Some(index) = unmatched.iter().position(|new| new.name == old.name) {
+            *new = Some(unmatched.remove(index));
+        }
+    }
+
+    pairs
+        .into_iter()
+        .map(|(old, new)| (Some(old), new))
+        .chain(unmatched.into_iter().map(|new| (None, Some(new))))
+        .collect()
+}
+
+/// Compares two cell values, ignoring when code cells were last run.
+pub(crate) fn cell_values_eq(a: &CellValue, b: &CellValue) -> bool {
+    match (a, b) {
+        (CellValue::Code(a), CellValue::Code(b)) => {
+            a.code_run.language == b.code_run.language
+                && a.code_run.code == b.code_run.code
+                && a.output == b.output
+        }
+        _ => a == b,
+    }
+}
+
+/// Compares two data tables, ignoring timestamps and cached formula ASTs.
+pub(crate) fn data_tables_eq(a: &DataTable, b: &DataTable) -> bool {
+    fn normalize(data_table: &DataTable) -> DataTable {
+        let mut data_table = data_table
+            .clone()
+            .with_last_modified(DateTime::<Utc>::UNIX_EPOCH);
+        if let DataTableKind::CodeRun(code_run) = &mut data_table.kind {
+            code_run.formula_ast = None;
+        }
+        data_table
+    }
+
+    a == b || normalize(a) == normalize(b)
+}
+
+/// Returns true if two optional items are equal according to `eq`.
+pub(crate) fn option_eq<T>(a: Option<&T>, b: Option<&T>, eq: impl Fn(&T, &T) -> bool) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Compares two sets of keyed items. Changes are returned in key order.
+fn keyed_changes<'a, K: Ord, T: Clone + 'a>(
+    old: impl Iterator<Item = (K, &'a T)>,
+    new: impl Iterator<Item = (K, &'a T)>,
+    eq: impl Fn(&T, &T) -> bool,
+) -> Vec<Change<K, T>> {
+    let mut items = BTreeMap::<K, (Option<&T>, Option<&T>)>::new();
+    for (key, item) in old {
+        items.entry(key).or_default().0 = Some(item);
+    }
+    for (key, item) in new {
+        items.entry(key).or_default().1 = Some(item);
+    }
+
+    items
+        .into_iter()
+        .filter(|(_, (old, new))| !option_eq(*old, *new, &eq))
+        .map(|(key, (old, new))| Change {
+            key,
+            old: old.cloned(),
+            new: new.cloned(),
+        })
+        .collect()
+}
+
+/// Compares the cell values of two sheets. Changes are returned in row order.
+fn cell_changes(old: &Sheet, new: &Sheet) -> Vec<CellChange> {
+    // keyed by (y, x) so changes are listed row by row
+    fn cells(sheet: &Sheet) -> impl Iterator<Item = ((i64, i64), &CellValue)> {
+        sheet
+            .columns
+            .expensive_iter()
+            .flat_map(|(&x, column)| column.values.iter().map(move |(&y, value)| ((y, x), value)))
+            .filter(|(_, value)| !value.is_blank_or_empty_string())
+    }
+
+    keyed_changes(cells(old), cells(new), cell_values_eq)
+        .into_iter()
+        .map(|change| Change {
+            key: Pos::new(change.key.1, change.key.0),
+            old: change.old,
+            new: change.new,
+        })
+        .collect()
+}
+
+/// Compares the data tables of two sheets, keyed by their anchor position.
+fn data_table_changes(old: &Sheet, new: &Sheet) -> Vec<DataTableChange> {
+    keyed_changes(
+        old.data_tables
+            .expensive_iter()
+            .map(|(pos, data_table)| ((pos.y, pos.x), data_table)),
+        new.data_tables
+            .expensive_iter()
+            .map(|(pos, data_table)| ((pos.y, pos.x), data_table)),
+        data_tables_eq,
+    )
+    .into_iter()
+    .map(|change| Change {
+        key: Pos::new(change.key.1, change.key.0),
+        old: change.old,
+        new: change.new,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        a1::A1Selection,
+        controller::GridController,
+        grid::CodeCellLanguage,
+        test_util::{test_create_checkbox, test_create_data_table},
+    };
+
+    #[test]
+    fn test_diff_identical_grids() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        gc.set_cell_value(pos![sheet_id!A1], "hello".into(), None, false);
+        test_create_data_table(&mut gc, sheet_id, pos![C3], 2, 2);
+
+        let diff = GridDiff::new(gc.grid(), &gc.grid().clone());
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_diff_cells() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        gc.set_cell_value(pos![sheet_id!A1], "hello".into(), None, false);
+        gc.set_cell_value(pos![sheet_id!B1], "removed".into(), None, false);
+        let old = gc.grid().clone();
+
+        gc.set_cell_value(pos![sheet_id!A1], "world".into(), None, false);
+        gc.set_cell_value(pos![sheet_id!B1], "".into(), None, false);
+        gc.set_code_cell(
+            pos![sheet_id!A2],
+            CodeCellLanguage::Formula,
+            "1 + 1".into(),
+            None,
+            None,
+            false,
+        );
+
+        let diff = GridDiff::new(&old, gc.grid());
+        assert_eq!(diff.sheets.len(), 1);
+        let cells = &diff.sheets[0].cells;
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cells[0].key, pos![A1]);
+        assert_eq!(cells[0].old, Some(CellValue::Text("hello".into())));
+        assert_eq!(cells[0].new, Some(CellValue::Text("world".into())));
+        assert_eq!(cells[1].key, pos![B1]);
+        assert!(cells[1].is_removed());
+        assert_eq!(cells[2].key, pos![A2]);
+        assert!(cells[2].is_added());
+        assert!(cells[2].new.as_ref().unwrap().is_code());
+    }
+
+    #[test]
+    fn test_diff_formats() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        let old = gc.grid().clone();
+
+        let sheet = gc.sheet_mut(sheet_id);
+        sheet
+            .formats
+            .bold
+            .set_rect(2, 2, Some(3), Some(4), Some(true));
+        sheet
+            .formats
+            .fill_color
+            .set_rect(5, 1, Some(5), None, Some("red".into()));
+
+        let diff = GridDiff::new(&old, gc.grid());
+        let formats = &diff.sheets[0].formats;
+        assert_eq!(formats.len(), 2);
+        assert_eq!(
+            formats[0].key,
+            FormatRegion {
+                x1: 2,
+                y1: 2,
+                x2: Some(3),
+                y2: Some(4)
+            }
+        );
+        assert_eq!(formats[0].old, None);
+        assert_eq!(formats[0].new.as_ref().unwrap().bold, Some(true));
+        assert_eq!(
+            formats[1].key,
+            FormatRegion {
+                x1: 5,
+                y1: 1,
+                x2: Some(5),
+                y2: None
+            }
+        );
+        assert_eq!(
+            formats[1].new.as_ref().unwrap().fill_color,
+            Some("red".into())
+        );
+    }
+
+    #[test]
+    fn test_diff_data_tables_validations_and_sheets() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        let old = gc.grid().clone();
+
+        test_create_data_table(&mut gc, sheet_id, pos![C3], 2, 2);
+        test_create_checkbox(&mut gc, A1Selection::test_a1("A1:A5"));
+        gc.set_sheet_name(sheet_id, "Renamed".into(), None, false);
+        gc.add_sheet_with_name("Added".into(), None, false);
+
+        let diff = GridDiff::new(&old, gc.grid());
+        assert_eq!(diff.sheets.len(), 2);
+
+        let changed = diff.sheet_by_old_id(sheet_id).unwrap();
+        assert_eq!(changed.kind, SheetDiffKind::Changed);
+        assert_eq!(
+            changed.name_change,
+            Some(("Sheet1".to_string(), "Renamed".to_string()))
+        );
+        assert_eq!(changed.data_tables.len(), 1);
+        assert_eq!(changed.data_tables[0].key, pos![C3]);
+        assert!(changed.data_tables[0].is_added());
+        assert_eq!(changed.validations.len(), 1);
+        assert!(changed.validations[0].is_added());
+
+        let added = &diff.sheets[1];
+        assert!(matches!(added.kind, SheetDiffKind::Added(_)));
+        assert_eq!(added.name, "Added");
+        assert_eq!(added.old_sheet_id, None);
+    }
+
+    #[test]
+    fn test_diff_ignores_last_modified() {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+        test_create_data_table(&mut gc, sheet_id, pos![A1], 2, 2);
+        let old = gc.grid().clone();
+
+        let mut new = old.clone();
+        let sheet = new.try_sheet_mut(sheet_id).unwrap();
+        sheet
+            .data_tables
+            .modify_data_table_at(&pos![A1], |dt| {
+                dt.last_modified = DateTime::<Utc>::UNIX_EPOCH;
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(GridDiff::new(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_match_sheets_by_name() {
+        let mut old = GridController::test();
+        old.add_sheet_with_name("Other".into(), None, false);
+        let mut new = GridController::test();
+        new.add_sheet_with_name("Other".into(), None, false);
+
+        // sheet ids differ between the two grids, so sheets match by name
+        let pairs = match_sheets(old.grid(), new.grid());
+        assert_eq!(pairs.len(), 2);
+        for (old, new) in pairs {
+            assert_eq!(old.unwrap().name, new.unwrap().name);
+        }
+    }
+}
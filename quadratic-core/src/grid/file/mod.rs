@@ -1,6 +1,6 @@
 use crate::compression::{
     CompressionFormat, SerializationFormat, add_header, decompress_and_deserialize, deserialize,
-    remove_header, serialize, serialize_and_compress,
+    remove_header, serialize,
 };
 
 use super::Grid;
@@ -13,7 +13,7 @@ use serde::{Deserialize, Serialize};
 pub use shift_negative_offsets::{add_import_offset_to_contiguous_2d_rect, shift_negative_offsets};
 use std::fmt::Debug;
 use std::str;
-pub use v1_13 as current;
+pub use v1_14 as current;
 
 mod migrate_code_cell_references;
 mod migrate_data_table_spills;
@@ -24,6 +24,7 @@ mod v1_10;
 mod v1_11;
 pub mod v1_12;
 pub mod v1_13;
+pub mod v1_14;
 mod v1_3;
 mod v1_4;
 mod v1_5;
@@ -33,9 +34,12 @@ mod v1_7_1;
 mod v1_8;
 mod v1_9;
 
-// Default values serialization and compression formats (current version)
-pub static CURRENT_VERSION: &str = "1.13";
-pub static SERIALIZATION_FORMAT: SerializationFormat = SerializationFormat::Json;
+// Default values serialization and compression formats (current version).
+// These are used for the chunks of the container. MessagePack is used instead
+// of bincode because the schema relies on `skip_serializing_if` and untagged
+// enums, which need a self-describing format.
+pub static CURRENT_VERSION: &str = "1.14";
+pub static SERIALIZATION_FORMAT: SerializationFormat = SerializationFormat::MessagePack;
 pub static COMPRESSION_FORMAT: CompressionFormat = CompressionFormat::Zstd;
 
 // Header serialization format, this should remain unchanged.
@@ -49,6 +53,11 @@ pub struct FileVersion {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "version")]
 enum GridFile {
+    #[serde(rename = "1.14")]
+    V1_14 {
+        #[serde(flatten)]
+        grid: v1_14::GridSchema,
+    },
     #[serde(rename = "1.13")]
     V1_13 {
         #[serde(flatten)]
@@ -114,7 +123,7 @@ enum GridFile {
 impl GridFile {
     fn into_latest(self) -> Result<current::GridSchema> {
         match self.upgrade_to_latest() {
-            Ok(GridFile::V1_13 { grid }) => Ok(grid),
+            Ok(GridFile::V1_14 { grid }) => Ok(grid),
             _ => anyhow::bail!("Failed to upgrade to latest version"),
         }
     }
@@ -122,7 +131,11 @@ impl GridFile {
     // Upgrade to the latest version
     fn upgrade_to_latest(self) -> Result<GridFile> {
         match self {
-            GridFile::V1_13 { grid } => Ok(GridFile::V1_13 { grid }),
+            GridFile::V1_14 { grid } => Ok(GridFile::V1_14 { grid }),
+            GridFile::V1_13 { grid } => GridFile::V1_14 {
+                grid: v1_13::upgrade(grid)?,
+            }
+            .upgrade_to_latest(),
             GridFile::V1_12 { grid } => GridFile::V1_13 {
                 grid: v1_12::upgrade(grid)?,
             }
@@ -253,8 +266,8 @@ fn import_binary(file_contents: Vec<u8>) -> Result<Grid> {
         }
         "1.11" => {
             let schema = decompress_and_deserialize::<v1_11::GridSchema>(
-                &SERIALIZATION_FORMAT,
-                &COMPRESSION_FORMAT,
+                &SerializationFormat::Json,
+                &CompressionFormat::Zstd,
                 data,
             )?;
 
@@ -271,13 +284,18 @@ fn import_binary(file_contents: Vec<u8>) -> Result<Grid> {
         }
         "1.13" => {
             let schema = decompress_and_deserialize::<v1_13::GridSchema>(
-                &SERIALIZATION_FORMAT,
-                &COMPRESSION_FORMAT,
+                &SerializationFormat::Json,
+                &CompressionFormat::Zstd,
                 data,
             )?;
 
             GridFile::V1_13 { grid: schema }.into_latest()
         }
+        "1.14" => {
+            let schema = v1_14::GridContainer::from_bytes(data)?.into_schema()?;
+
+            GridFile::V1_14 { grid: schema }.into_latest()
+        }
         _ => Err(anyhow::anyhow!(
             "Unsupported file version: {}",
             file_version.version
@@ -343,7 +361,7 @@ fn import_json(file_contents: String) -> Result<Grid> {
             | GridFile::V1_9 { .. }
             | GridFile::V1_10 { .. }
     );
-    // V1_11, V1_12, V1_13 don't need data_table_spills migration
+    // V1_11 and later don't need data_table_spills migration
 
     let file = json.into_latest()?;
     let mut grid = serialize::import(file);
@@ -366,12 +384,8 @@ pub fn export(grid: Grid) -> Result<Vec<u8>> {
     let header = serialize(&HEADER_SERIALIZATION_FORMAT, &version)?;
 
     let converted = serialize::export(grid)?;
-    let compressed = serialize_and_compress::<current::GridSchema>(
-        &SERIALIZATION_FORMAT,
-        &COMPRESSION_FORMAT,
-        converted,
-    )?;
-    let data = add_header(header, compressed)?;
+    let container = current::GridContainer::from_schema(converted)?;
+    let data = add_header(header, container.to_bytes()?)?;
 
     Ok(data)
 }
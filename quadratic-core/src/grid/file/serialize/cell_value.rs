@@ -31,6 +31,9 @@ pub fn export_code_cell_language(language: CodeCellLanguage) -> current::CodeCel
                 ConnectionKind::GoogleAnalytics => current::ConnectionKindSchema::GoogleAnalytics,
                 ConnectionKind::Plaid => current::ConnectionKindSchema::Plaid,
                 ConnectionKind::StockHistory => current::ConnectionKindSchema::StockHistory,
+                ConnectionKind::Sqlite => current::ConnectionKindSchema::Sqlite,
+                ConnectionKind::Duckdb => current::ConnectionKindSchema::Duckdb,
+                ConnectionKind::Stripe => current::ConnectionKindSchema::Stripe,
             },
             id,
         },
@@ -125,6 +128,9 @@ pub fn import_code_cell_language(language: current::CodeCellLanguageSchema) -> C
                 current::ConnectionKindSchema::GoogleAnalytics => ConnectionKind::GoogleAnalytics,
                 current::ConnectionKindSchema::Plaid => ConnectionKind::Plaid,
                 current::ConnectionKindSchema::StockHistory => ConnectionKind::StockHistory,
+                current::ConnectionKindSchema::Sqlite => ConnectionKind::Sqlite,
+                current::ConnectionKindSchema::Duckdb => ConnectionKind::Duckdb,
+                current::ConnectionKindSchema::Stripe => ConnectionKind::Stripe,
             },
             id,
         },
@@ -8,7 +8,7 @@ use super::v1_10;
 use super::v1_11;
 use super::v1_12;
 use super::v1_13;
-use crate::grid::Sheet;
+use crate::grid::{Sheet, SheetId};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
@@ -33,6 +33,23 @@ impl SheetSchema {
         self.upgrade_to_latest()
     }
 
+    /// Returns the id of the sheet without importing it.
+    pub fn sheet_id(&self) -> Option<SheetId> {
+        let id = match self {
+            SheetSchema::V1_13(sheet) => &sheet.id.id,
+            SheetSchema::V1_12(sheet) => &sheet.id.id,
+            SheetSchema::V1_11(sheet) => &sheet.id.id,
+            SheetSchema::V1_10(sheet) => &sheet.id.id,
+            SheetSchema::V1_9(sheet) => &sheet.id.id,
+            SheetSchema::V1_8(sheet) => &sheet.id.id,
+            SheetSchema::V1_7_1(sheet) => &sheet.id.id,
+            SheetSchema::V1_7(sheet) => &sheet.id.id,
+            SheetSchema::V1_6(sheet) => &sheet.id.id,
+        };
+
+        id.parse().ok()
+    }
+
     fn upgrade_to_latest(self) -> Result<Sheet> {
         match self {
             SheetSchema::V1_13(sheet) => import_sheet(sheet),
@@ -82,4 +99,11 @@ mod test {
         let imported = schema.into_latest().unwrap();
         assert_eq!(sheet, imported);
     }
+
+    #[test]
+    fn test_sheet_id() {
+        let sheet = Sheet::test();
+        let sheet_id = sheet.id;
+        assert_eq!(export_sheet(sheet).sheet_id(), Some(sheet_id));
+    }
 }
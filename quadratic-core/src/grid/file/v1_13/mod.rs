@@ -1,6 +1,8 @@
 pub mod schema;
+pub mod upgrade;
 
 pub use schema::*;
+pub use upgrade::upgrade;
 
 // Re-export formula_schema from v1_12 since it's unchanged
 pub use super::v1_12::formula_schema;
@@ -0,0 +1,32 @@
+//! Upgrade from v1_13 to v1_14 file format.
+//!
+//! The schema is unchanged in v1_14. Only the on-disk layout changes (see
+//! `v1_14::container`), so the upgrade only bumps the version.
+
+use anyhow::Result;
+
+use crate::grid::file::v1_13 as current;
+use crate::grid::file::v1_14;
+
+pub fn upgrade(grid: current::GridSchema) -> Result<v1_14::GridSchema> {
+    Ok(v1_14::GridSchema {
+        sheets: grid.sheets,
+        version: Some("1.14".into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_bumps_version() {
+        let grid = current::GridSchema {
+            sheets: vec![current::SheetSchema::default()],
+            version: Some("1.13".into()),
+        };
+        let upgraded = upgrade(grid.clone()).unwrap();
+        assert_eq!(upgraded.version, Some("1.14".into()));
+        assert_eq!(upgraded.sheets, grid.sheets);
+    }
+}
@@ -0,0 +1,672 @@
+//! Chunked container layout, introduced in v1_14.
+//!
+//! After the file header, the body is laid out as:
+//!
+//! ```text
+//! [manifest length: u64 LE][manifest][chunk][chunk]...
+//! ```
+//!
+//! The manifest records how chunks are encoded, and lists every sheet and
+//! data table together with the byte range and CRC32 checksum of its chunk.
+//! Each chunk is serialized and compressed on its own, so a single sheet (or a
+//! single data table) can be decoded without touching the rest of the file,
+//! and a changed sheet can be written back without recompressing the chunks
+//! of the other sheets. A chunk's checksum is verified before it is decoded.
+
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+use bytes::Bytes;
+
+use super::schema::*;
+use crate::{
+    Pos,
+    a1::A1Context,
+    compression::{
+        CompressionFormat, SerializationFormat, compress, decompress, decompress_and_deserialize,
+        deserialize, serialize, serialize_and_compress,
+    },
+    grid::{
+        Grid, Sheet, SheetId,
+        file::{COMPRESSION_FORMAT, SERIALIZATION_FORMAT, serialize::sheets::import_sheet},
+    },
+};
+
+pub static CONTAINER_VERSION: &str = "1.14";
+
+// The manifest only contains plain structs, so it is kept small with bincode
+pub static MANIFEST_SERIALIZATION_FORMAT: SerializationFormat = SerializationFormat::Bincode;
+pub static MANIFEST_COMPRESSION_FORMAT: CompressionFormat = CompressionFormat::Zstd;
+
+const MANIFEST_LENGTH_SIZE: usize = std::mem::size_of::<u64>();
+
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    data: Bytes,
+    checksum: u32,
+}
+
+impl Chunk {
+    fn new(data: Bytes) -> Self {
+        let checksum = crc32fast::hash(&data);
+        Self { data, checksum }
+    }
+
+    fn verified(&self) -> Result<&[u8]> {
+        let checksum = crc32fast::hash(&self.data);
+        if checksum != self.checksum {
+            bail!(
+                "Container chunk checksum mismatch: expected {:08x}, found {checksum:08x}",
+                self.checksum
+            );
+        }
+
+        Ok(&self.data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DataTableChunk {
+    pos: PosSchema,
+    name: String,
+    chunk: Chunk,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SheetChunks {
+    id: IdSchema,
+    name: String,
+    color: Option<String>,
+    order: String,
+    chunk: Chunk,
+    data_tables: Vec<DataTableChunk>,
+}
+
+/// A chunked file whose chunks are decoded on demand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridContainer {
+    encoding: ChunkEncodingSchema,
+    sheets: Vec<SheetChunks>,
+}
+
+impl Default for ChunkEncodingSchema {
+    fn default() -> Self {
+        Self {
+            serialization_format: SERIALIZATION_FORMAT.clone(),
+            compression_format: COMPRESSION_FORMAT.clone(),
+        }
+    }
+}
+
+impl GridContainer {
+    /// Splits a grid schema into chunks using the default chunk encoding.
+    pub fn from_schema(grid: GridSchema) -> Result<Self> {
+        Self::from_schema_with_encoding(grid, ChunkEncodingSchema::default())
+    }
+
+    /// Splits a grid schema into chunks using the given encoding. JSON chunks
+    /// are useful when debugging a file by hand.
+    pub fn from_schema_with_encoding(
+        grid: GridSchema,
+        encoding: ChunkEncodingSchema,
+    ) -> Result<Self> {
+        let sheets = grid
+            .sheets
+            .into_iter()
+            .map(|sheet| encode_sheet(&encoding, sheet))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { encoding, sheets })
+    }
+
+    /// How the chunks in this container are encoded.
+    pub fn encoding(&self) -> &ChunkEncodingSchema {
+        &self.encoding
+    }
+
+    /// Reads the manifest from a file body (without the header). Chunks are
+    /// not decompressed (or checked) until they are requested.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let (manifest, chunks) = split_manifest(data)?;
+        let manifest = decompress_and_deserialize::<ManifestSchema>(
+            &MANIFEST_SERIALIZATION_FORMAT,
+            &MANIFEST_COMPRESSION_FORMAT,
+            manifest,
+        )?;
+
+        if manifest.version != CONTAINER_VERSION {
+            bail!("Unsupported container version: {}", manifest.version);
+        }
+
+        let chunks = Bytes::copy_from_slice(chunks);
+        let slice = |chunk: &ChunkSchema| -> Result<Chunk> {
+            Ok(Chunk {
+                data: slice_chunk(&chunks, chunk.offset, chunk.length)?,
+                checksum: chunk.checksum,
+            })
+        };
+
+        let sheets = manifest
+            .sheets
+            .into_iter()
+            .map(|sheet| {
+                let data_tables = sheet
+                    .data_tables
+                    .into_iter()
+                    .map(|data_table| {
+                        Ok(DataTableChunk {
+                            chunk: slice(&data_table.chunk)?,
+                            pos: data_table.pos,
+                            name: data_table.name,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                Ok(SheetChunks {
+                    chunk: slice(&sheet.chunk)?,
+                    id: sheet.id,
+                    name: sheet.name,
+                    color: sheet.color,
+                    order: sheet.order,
+                    data_tables,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            encoding: manifest.encoding,
+            sheets,
+        })
+    }
+
+    /// Writes the manifest followed by all chunks.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut offset = 0;
+        let mut next_chunk = |chunk: &Chunk| {
+            let chunk = ChunkSchema {
+                offset,
+                length: chunk.data.len() as u64,
+                checksum: chunk.checksum,
+            };
+            offset += chunk.length;
+            chunk
+        };
+
+        let manifest = ManifestSchema {
+            version: CONTAINER_VERSION.into(),
+            encoding: self.encoding.clone(),
+            sheets: self
+                .sheets
+                .iter()
+                .map(|sheet| SheetManifestSchema {
+                    id: sheet.id.clone(),
+                    name: sheet.name.clone(),
+                    color: sheet.color.clone(),
+                    order: sheet.order.clone(),
+                    chunk: next_chunk(&sheet.chunk),
+                    data_tables: sheet
+                        .data_tables
+                        .iter()
+                        .map(|data_table| DataTableManifestSchema {
+                            pos: data_table.pos.clone(),
+                            name: data_table.name.clone(),
+                            chunk: next_chunk(&data_table.chunk),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+
+        let manifest = compress(
+            &MANIFEST_COMPRESSION_FORMAT,
+            serialize(&MANIFEST_SERIALIZATION_FORMAT, &manifest)?,
+        )?;
+
+        let mut output =
+            Vec::with_capacity(MANIFEST_LENGTH_SIZE + manifest.len() + offset as usize);
+        output.extend((manifest.len() as u64).to_le_bytes());
+        output.extend(manifest);
+
+        // chunks are written in the same order the offsets were assigned
+        for sheet in &self.sheets {
+            output.extend_from_slice(&sheet.chunk.data);
+            for data_table in &sheet.data_tables {
+                output.extend_from_slice(&data_table.chunk.data);
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Returns the ids of all sheets, in file order.
+    pub fn sheet_ids(&self) -> Result<Vec<SheetId>> {
+        self.sheets
+            .iter()
+            .map(|sheet| SheetId::from_str(&sheet.id.id))
+            .collect()
+    }
+
+    /// Returns the name of a sheet without decoding it.
+    pub fn sheet_name(&self, sheet_id: SheetId) -> Option<&str> {
+        self.find_sheet(sheet_id)
+            .ok()
+            .map(|sheet| sheet.name.as_str())
+    }
+
+    /// Returns the names of the data tables in a sheet without decoding them.
+    pub fn data_table_names(&self, sheet_id: SheetId) -> Result<Vec<(Pos, &str)>> {
+        Ok(self
+            .find_sheet(sheet_id)?
+            .data_tables
+            .iter()
+            .map(|data_table| {
+                let pos = Pos {
+                    x: data_table.pos.x,
+                    y: data_table.pos.y,
+                };
+                (pos, data_table.name.as_str())
+            })
+            .collect())
+    }
+
+    /// Decodes a single data table.
+    pub fn data_table_schema(&self, sheet_id: SheetId, pos: Pos) -> Result<DataTableSchema> {
+        let data_table = self
+            .find_sheet(sheet_id)?
+            .data_tables
+            .iter()
+            .find(|data_table| data_table.pos.x == pos.x && data_table.pos.y == pos.y)
+            .ok_or_else(|| anyhow!("Data table not found at {pos} in sheet {sheet_id}"))?;
+
+        decode_chunk(&self.encoding, &data_table.chunk)
+    }
+
+    /// Decodes a sheet and all of its data tables.
+    pub fn sheet_schema(&self, sheet_id: SheetId) -> Result<SheetSchema> {
+        decode_sheet(&self.encoding, self.find_sheet(sheet_id)?)
+    }
+
+    /// Decodes a single sheet. The `a1_context` is used to calculate the
+    /// sheet's bounds; a context that only knows about the sheets that are
+    /// already loaded is sufficient.
+    pub fn load_sheet(&self, sheet_id: SheetId, a1_context: &A1Context) -> Result<Sheet> {
+        let mut sheet = import_sheet(self.sheet_schema(sheet_id)?)?;
+        sheet.recalculate_bounds(a1_context);
+
+        Ok(sheet)
+    }
+
+    /// Re-encodes a sheet's chunks. The chunks of all other sheets are kept
+    /// as they are. The sheet is appended if it is not in the container.
+    pub fn replace_sheet(&mut self, sheet: SheetSchema) -> Result<()> {
+        let chunks = encode_sheet(&self.encoding, sheet)?;
+
+        match self.sheets.iter_mut().find(|s| s.id == chunks.id) {
+            Some(existing) => *existing = chunks,
+            None => self.sheets.push(chunks),
+        }
+
+        Ok(())
+    }
+
+    /// Re-encodes (or removes, if `data_table` is None) a single data table
+    /// chunk, leaving the rest of the sheet untouched.
+    pub fn replace_data_table(
+        &mut self,
+        sheet_id: SheetId,
+        pos: Pos,
+        data_table: Option<DataTableSchema>,
+    ) -> Result<()> {
+        let encoding = self.encoding.clone();
+        let sheet = self.find_sheet_mut(sheet_id)?;
+        let index = sheet
+            .data_tables
+            .iter()
+            .position(|data_table| data_table.pos.x == pos.x && data_table.pos.y == pos.y);
+
+        match (index, data_table) {
+            (Some(index), Some(data_table)) => {
+                sheet.data_tables[index] =
+                    encode_data_table(&encoding, sheet.data_tables[index].pos.clone(), data_table)?;
+            }
+            (None, Some(data_table)) => {
+                sheet.data_tables.push(encode_data_table(
+                    &encoding,
+                    PosSchema { x: pos.x, y: pos.y },
+                    data_table,
+                )?);
+            }
+            (Some(index), None) => {
+                sheet.data_tables.remove(index);
+            }
+            (None, None) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Removes a sheet and all of its chunks.
+    pub fn remove_sheet(&mut self, sheet_id: SheetId) -> Result<()> {
+        let id = sheet_id.to_string();
+        let index = self
+            .sheets
+            .iter()
+            .position(|sheet| sheet.id.id == id)
+            .ok_or_else(|| anyhow!("Sheet {sheet_id} not found in container"))?;
+        self.sheets.remove(index);
+
+        Ok(())
+    }
+
+    /// Decodes every chunk into a grid schema.
+    pub fn into_schema(self) -> Result<GridSchema> {
+        let sheets = self
+            .sheets
+            .iter()
+            .map(|sheet| decode_sheet(&self.encoding, sheet))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(GridSchema {
+            sheets,
+            version: Some(CONTAINER_VERSION.into()),
+        })
+    }
+
+    /// Decodes every chunk into a grid.
+    pub fn into_grid(self) -> Result<Grid> {
+        crate::grid::file::serialize::import(self.into_schema()?)
+    }
+
+    fn find_sheet(&self, sheet_id: SheetId) -> Result<&SheetChunks> {
+        let id = sheet_id.to_string();
+        self.sheets
+            .iter()
+            .find(|sheet| sheet.id.id == id)
+            .ok_or_else(|| anyhow!("Sheet {sheet_id} not found in container"))
+    }
+
+    fn find_sheet_mut(&mut self, sheet_id: SheetId) -> Result<&mut SheetChunks> {
+        let id = sheet_id.to_string();
+        self.sheets
+            .iter_mut()
+            .find(|sheet| sheet.id.id == id)
+            .ok_or_else(|| anyhow!("Sheet {sheet_id} not found in container"))
+    }
+}
+
+/// Splits a container body into the manifest and the chunk region.
+fn split_manifest(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    if data.len() < MANIFEST_LENGTH_SIZE {
+        bail!("Container is too short to contain a manifest");
+    }
+
+    let (length, rest) = data.split_at(MANIFEST_LENGTH_SIZE);
+    let manifest_length = u64::from_le_bytes(length.try_into()?) as usize;
+
+    if manifest_length > rest.len() {
+        bail!("Container manifest length exceeds the file size");
+    }
+
+    Ok(rest.split_at(manifest_length))
+}
+
+/// Returns the bytes of a chunk, checking that it is within the chunk region.
+fn slice_chunk(chunks: &Bytes, offset: u64, length: u64) -> Result<Bytes> {
+    let start = offset as usize;
+    let end = start
+        .checked_add(length as usize)
+        .filter(|end| *end <= chunks.len())
+        .ok_or_else(|| anyhow!("Container chunk is out of bounds"))?;
+
+    Ok(chunks.slice(start..end))
+}
+
+fn encode_chunk<T: serde::Serialize>(encoding: &ChunkEncodingSchema, value: &T) -> Result<Chunk> {
+    let data = serialize_and_compress(
+        &encoding.serialization_format,
+        &encoding.compression_format,
+        value,
+    )?;
+
+    Ok(Chunk::new(Bytes::from(data)))
+}
+
+fn decode_chunk<T: serde::de::DeserializeOwned>(
+    encoding: &ChunkEncodingSchema,
+    chunk: &Chunk,
+) -> Result<T> {
+    let decompressed = decompress(&encoding.compression_format, chunk.verified()?)?;
+    deserialize::<T>(&encoding.serialization_format, &decompressed)
+}
+
+fn encode_data_table(
+    encoding: &ChunkEncodingSchema,
+    pos: PosSchema,
+    data_table: DataTableSchema,
+) -> Result<DataTableChunk> {
+    Ok(DataTableChunk {
+        chunk: encode_chunk(encoding, &data_table)?,
+        name: data_table.name,
+        pos,
+    })
+}
+
+fn encode_sheet(encoding: &ChunkEncodingSchema, mut sheet: SheetSchema) -> Result<SheetChunks> {
+    // data tables are stored in their own chunks
+    let data_tables = std::mem::take(&mut sheet.data_tables)
+        .into_iter()
+        .map(|(pos, data_table)| encode_data_table(encoding, pos, data_table))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(SheetChunks {
+        chunk: encode_chunk(encoding, &sheet)?,
+        id: sheet.id,
+        name: sheet.name,
+        color: sheet.color,
+        order: sheet.order,
+        data_tables,
+    })
+}
+
+fn decode_sheet(encoding: &ChunkEncodingSchema, chunks: &SheetChunks) -> Result<SheetSchema> {
+    let mut sheet = decode_chunk::<SheetSchema>(encoding, &chunks.chunk)?;
+
+    sheet.data_tables = chunks
+        .data_tables
+        .iter()
+        .map(|data_table| {
+            Ok((
+                data_table.pos.clone(),
+                decode_chunk(encoding, &data_table.chunk)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        controller::GridController,
+        grid::file::{export, import, serialize},
+        test_util::*,
+    };
+
+    fn test_grid() -> (GridController, SheetId, SheetId) {
+        let mut gc = GridController::test();
+        let sheet_1 = gc.sheet_ids()[0];
+        gc.add_sheet(None, None, None, false);
+        let sheet_2 = gc.sheet_ids()[1];
+
+        gc.set_cell_value(pos![sheet_1!A1], "hello".into(), None, false);
+        gc.set_cell_value(pos![sheet_2!B2], "world".into(), None, false);
+        test_create_data_table(&mut gc, sheet_1, pos![C3], 2, 2);
+        test_create_data_table(&mut gc, sheet_2, pos![E5], 3, 3);
+
+        (gc, sheet_1, sheet_2)
+    }
+
+    #[test]
+    fn test_container_roundtrip() {
+        let (gc, _, _) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+
+        let container = GridContainer::from_schema(schema.clone()).unwrap();
+        let bytes = container.to_bytes().unwrap();
+        let read = GridContainer::from_bytes(&bytes).unwrap();
+
+        assert_eq!(read, container);
+        assert_eq!(read.into_schema().unwrap(), schema);
+    }
+
+    #[test]
+    fn test_export_import_container() {
+        let (gc, _, _) = test_grid();
+        let exported = export(gc.grid().clone()).unwrap();
+        let imported = import(exported).unwrap();
+
+        assert_eq!(&imported, gc.grid());
+    }
+
+    #[test]
+    fn test_load_single_sheet() {
+        let (gc, sheet_1, sheet_2) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+        let bytes = GridContainer::from_schema(schema)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let container = GridContainer::from_bytes(&bytes).unwrap();
+
+        assert_eq!(container.sheet_ids().unwrap(), vec![sheet_1, sheet_2]);
+        assert_eq!(
+            container.sheet_name(sheet_2),
+            Some(gc.sheet(sheet_2).name.as_str())
+        );
+        assert_eq!(container.data_table_names(sheet_2).unwrap().len(), 1);
+
+        let a1_context = gc.a1_context();
+        let sheet = container.load_sheet(sheet_2, a1_context).unwrap();
+        assert_eq!(&sheet, gc.sheet(sheet_2));
+
+        let data_table = container.data_table_schema(sheet_1, pos![C3]).unwrap();
+        assert_eq!(
+            data_table.name,
+            gc.sheet(sheet_1).data_table_at(&pos![C3]).unwrap().name()
+        );
+    }
+
+    #[test]
+    fn test_replace_sheet_keeps_other_chunks() {
+        let (mut gc, sheet_1, sheet_2) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+        let mut container = GridContainer::from_schema(schema).unwrap();
+        let untouched = container.find_sheet(sheet_2).unwrap().clone();
+
+        gc.set_cell_value(pos![sheet_1!A2], "changed".into(), None, false);
+        let changed = serialize::sheets::export_sheet(gc.sheet(sheet_1).clone());
+        container.replace_sheet(changed).unwrap();
+
+        assert_eq!(container.find_sheet(sheet_2).unwrap(), &untouched);
+
+        let bytes = container.to_bytes().unwrap();
+        let grid = GridContainer::from_bytes(&bytes)
+            .unwrap()
+            .into_grid()
+            .unwrap();
+        assert_eq!(&grid, gc.grid());
+    }
+
+    #[test]
+    fn test_replace_and_remove_data_table() {
+        let (gc, sheet_1, _) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+        let mut container = GridContainer::from_schema(schema).unwrap();
+
+        let mut data_table = container.data_table_schema(sheet_1, pos![C3]).unwrap();
+        data_table.name = "Renamed".into();
+        container
+            .replace_data_table(sheet_1, pos![C3], Some(data_table))
+            .unwrap();
+        assert_eq!(
+            container.data_table_names(sheet_1).unwrap(),
+            vec![(pos![C3], "Renamed")]
+        );
+
+        container
+            .replace_data_table(sheet_1, pos![C3], None)
+            .unwrap();
+        assert!(container.data_table_names(sheet_1).unwrap().is_empty());
+        assert!(
+            container
+                .sheet_schema(sheet_1)
+                .unwrap()
+                .data_tables
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_remove_sheet() {
+        let (gc, sheet_1, sheet_2) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+        let mut container = GridContainer::from_schema(schema).unwrap();
+
+        container.remove_sheet(sheet_1).unwrap();
+        assert_eq!(container.sheet_ids().unwrap(), vec![sheet_2]);
+        assert!(container.remove_sheet(sheet_1).is_err());
+    }
+
+    #[test]
+    fn test_corrupted_container() {
+        let (gc, _, _) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+        let bytes = GridContainer::from_schema(schema)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        assert!(GridContainer::from_bytes(&bytes[..4]).is_err());
+        assert!(GridContainer::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut too_long = bytes.clone();
+        too_long[..MANIFEST_LENGTH_SIZE].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(GridContainer::from_bytes(&too_long).is_err());
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let (gc, _, sheet_2) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+        let mut bytes = GridContainer::from_schema(schema)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+
+        // the last chunk is the data table of the last sheet
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let container = GridContainer::from_bytes(&bytes).unwrap();
+        let error = container.sheet_schema(sheet_2).unwrap_err();
+        assert!(error.to_string().contains("checksum mismatch"));
+    }
+
+    #[test]
+    fn test_json_encoding() {
+        let (gc, _, _) = test_grid();
+        let schema = serialize::export(gc.grid().clone()).unwrap();
+        let encoding = ChunkEncodingSchema {
+            serialization_format: SerializationFormat::Json,
+            compression_format: CompressionFormat::None,
+        };
+        let container =
+            GridContainer::from_schema_with_encoding(schema.clone(), encoding.clone()).unwrap();
+        let read = GridContainer::from_bytes(&container.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(read.encoding(), &encoding);
+        assert_eq!(read.into_schema().unwrap(), schema);
+    }
+}
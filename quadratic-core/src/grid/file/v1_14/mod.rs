@@ -0,0 +1,9 @@
+pub mod container;
+pub mod schema;
+
+pub use container::GridContainer;
+pub use schema::*;
+
+// Re-export formula_schema from v1_13 since it's unchanged
+pub use super::v1_13::formula_schema;
+pub use super::v1_13::formula_schema::*;
@@ -0,0 +1,51 @@
+//! v1_14 keeps the v1_13 grid schema. Only the on-disk layout changes: the
+//! file is split into a manifest and separately compressed chunks (see
+//! `v1_14::container`).
+
+use serde::{Deserialize, Serialize};
+
+use crate::compression::{CompressionFormat, SerializationFormat};
+
+// The grid schema itself is unchanged from v1_13
+pub use crate::grid::file::v1_13::schema::*;
+
+/// Byte range and CRC32 checksum of a chunk, relative to the end of the
+/// manifest.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkSchema {
+    pub offset: u64,
+    pub length: u64,
+    pub checksum: u32,
+}
+
+/// How every chunk in the container is serialized and compressed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkEncodingSchema {
+    pub serialization_format: SerializationFormat,
+    pub compression_format: CompressionFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataTableManifestSchema {
+    pub pos: PosSchema,
+    pub name: String,
+    pub chunk: ChunkSchema,
+}
+
+/// Sheet metadata that is available without decoding the sheet chunk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SheetManifestSchema {
+    pub id: IdSchema,
+    pub name: String,
+    pub color: Option<String>,
+    pub order: String,
+    pub chunk: ChunkSchema,
+    pub data_tables: Vec<DataTableManifestSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestSchema {
+    pub version: String,
+    pub encoding: ChunkEncodingSchema,
+    pub sheets: Vec<SheetManifestSchema>,
+}
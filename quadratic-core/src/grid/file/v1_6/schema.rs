@@ -278,6 +278,9 @@ pub enum ConnectionKind {
     Plaid,
     /// Financial data connection for STOCKHISTORY formula
     StockHistory,
+    Sqlite,
+    Duckdb,
+    Stripe,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
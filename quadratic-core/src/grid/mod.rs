@@ -28,6 +28,7 @@ mod cells_accessed_cache;
 pub mod column;
 pub mod contiguous;
 pub mod data_table;
+pub mod diff;
 pub mod file;
 pub mod formats;
 pub mod formatting;
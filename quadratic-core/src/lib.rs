@@ -35,6 +35,7 @@ mod span;
 #[macro_use]
 pub mod test_util;
 pub mod input;
+pub mod native;
 pub mod values;
 pub mod viewport;
 
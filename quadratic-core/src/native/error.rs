@@ -0,0 +1,41 @@
+//! Errors returned by the native API.
+
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, NativeError>;
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum NativeError {
+    #[error("Unable to access file: {0}")]
+    Io(String),
+
+    #[error("Unable to open file: {0}")]
+    InvalidFile(String),
+
+    #[error("Unable to save file: {0}")]
+    Save(String),
+
+    #[error("Invalid reference {reference:?}: {message}")]
+    InvalidReference { reference: String, message: String },
+
+    #[error("Data table not found: {0}")]
+    DataTableNotFound(String),
+
+    #[error("Formula error: {0}")]
+    Formula(String),
+
+    #[error("Unable to update the grid: {0}")]
+    Operation(String),
+
+    #[error("Code cell {0} needs to run outside of quadratic-core")]
+    AsyncCodeCell(String),
+
+    #[error("Arrow error: {0}")]
+    Arrow(String),
+}
+
+impl From<std::io::Error> for NativeError {
+    fn from(error: std::io::Error) -> Self {
+        NativeError::Io(error.to_string())
+    }
+}
@@ -0,0 +1,497 @@
+//! Native API
+//!
+//! A stable interface to [`GridController`] for Rust programs that embed
+//! quadratic-core outside of the browser. Cells are addressed with A1
+//! references (eg, `B2`, `'Sheet 2'!A1:C10`, or `Table1`), values are returned
+//! as [`CellValue`]s, data tables are read as Arrow [`RecordBatch`]es, and
+//! errors are returned as [`NativeError`].
+//!
+//! Formulas are calculated as part of each change. Python, JavaScript and
+//! connection cells cannot run inside quadratic-core; changes that need them
+//! return [`NativeError::AsyncCodeCell`]. Use quadratic-core-cloud to run
+//! them.
+//!
+//! ```
+//! use quadratic_core::native::Spreadsheet;
+//!
+//! let mut spreadsheet = Spreadsheet::new();
+//! spreadsheet.set_value("A1", "2").unwrap();
+//! spreadsheet.set_formula("A2", "A1 * 10").unwrap();
+//! assert_eq!(spreadsheet.get_value("A2").unwrap().to_string(), "20");
+//!
+//! let file = spreadsheet.save().unwrap();
+//! let spreadsheet = quadratic_core::native::Spreadsheet::open(file).unwrap();
+//! assert_eq!(spreadsheet.get_value("A2").unwrap().to_string(), "20");
+//! ```
+
+use std::path::Path;
+
+use arrow_array::RecordBatch;
+
+use crate::{
+    CellValue, Pos, SheetPos, Value,
+    a1::A1Selection,
+    arrow::cell_values_to_record_batch,
+    controller::{
+        GridController, active_transactions::transaction_name::TransactionName,
+        callbacks::TransactionEvent, operations::operation::Operation,
+    },
+    formulas::{Ctx, parse_formula},
+    grid::{
+        CodeCellLanguage, Grid, SheetId,
+        file::{export, import},
+    },
+};
+
+mod error;
+
+pub use error::{NativeError, Result};
+
+/// A spreadsheet file.
+#[derive(Debug)]
+pub struct Spreadsheet {
+    grid_controller: GridController,
+}
+
+impl Default for Spreadsheet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Spreadsheet {
+    /// Creates a spreadsheet with a single empty sheet.
+    pub fn new() -> Self {
+        Self::from_grid_controller(GridController::from_grid(Grid::new(), 0))
+    }
+
+    pub fn from_grid_controller(grid_controller: GridController) -> Self {
+        Self { grid_controller }
+    }
+
+    pub fn grid_controller(&self) -> &GridController {
+        &self.grid_controller
+    }
+
+    pub fn into_grid_controller(self) -> GridController {
+        self.grid_controller
+    }
+
+    /// Opens the contents of a .grid file. Older versions are upgraded.
+    pub fn open(file: Vec<u8>) -> Result<Self> {
+        let grid = import(file).map_err(|e| NativeError::InvalidFile(e.to_string()))?;
+
+        Ok(Self::from_grid_controller(GridController::from_grid(
+            grid, 0,
+        )))
+    }
+
+    pub fn open_path(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open(std::fs::read(path)?)
+    }
+
+    /// Returns the contents of a .grid file in the current version.
+    pub fn save(&self) -> Result<Vec<u8>> {
+        export(self.grid_controller.grid().clone()).map_err(|e| NativeError::Save(e.to_string()))
+    }
+
+    pub fn save_path(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.save()?)?;
+
+        Ok(())
+    }
+
+    /// Returns the sheet names in order.
+    pub fn sheet_names(&self) -> Vec<String> {
+        self.grid_controller
+            .sheets()
+            .into_iter()
+            .map(|sheet| sheet.name.clone())
+            .collect()
+    }
+
+    /// Returns the displayed value of a cell, or [`CellValue::Blank`] if the
+    /// cell is empty.
+    pub fn get_value(&self, a1: &str) -> Result<CellValue> {
+        let sheet_pos = self.sheet_pos(a1)?;
+
+        Ok(self.display_value(sheet_pos.sheet_id, sheet_pos.into()))
+    }
+
+    /// Returns the displayed values of a range, row by row.
+    pub fn get_values(&self, a1: &str) -> Result<Vec<Vec<CellValue>>> {
+        let selection = self.selection(a1)?;
+        let rect = selection
+            .single_rect_or_cursor(self.grid_controller.a1_context())
+            .ok_or_else(|| invalid_reference(a1, "expected a single finite range"))?;
+
+        Ok(rect
+            .y_range()
+            .map(|y| {
+                rect.x_range()
+                    .map(|x| self.display_value(selection.sheet_id, Pos { x, y }))
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Sets a cell as if a user typed `value` into it: numbers, percentages,
+    /// dates and logicals are parsed, and values starting with `=` are
+    /// formulas.
+    pub fn set_value(&mut self, a1: &str, value: &str) -> Result<()> {
+        if let Some(formula) = value.strip_prefix('=') {
+            return self.set_formula(a1, formula);
+        }
+        self.set_values(a1, vec![vec![value.to_string()]])
+    }
+
+    /// Sets a block of cells starting at `a1`. Values are parsed as in
+    /// [`Spreadsheet::set_value`], except that formulas are not created.
+    pub fn set_values(&mut self, a1: &str, values: Vec<Vec<String>>) -> Result<()> {
+        let sheet_pos = self.sheet_pos(a1)?;
+        let (operations, data_table_operations) = self
+            .grid_controller
+            .set_cell_values_operations(sheet_pos, values, true)
+            .map_err(|e| NativeError::Operation(e.to_string()))?;
+
+        self.apply(operations, TransactionName::SetCells)?;
+        self.apply(data_table_operations, TransactionName::SetCells)
+    }
+
+    /// Sets a formula cell. `formula` does not include the leading `=`.
+    pub fn set_formula(&mut self, a1: &str, formula: &str) -> Result<()> {
+        let sheet_pos = self.sheet_pos(a1)?;
+        parse_formula(formula, self.grid_controller.a1_context(), sheet_pos)
+            .map_err(|e| NativeError::Formula(e.to_string()))?;
+
+        let operations = self.grid_controller.set_code_cell_operations(
+            sheet_pos,
+            CodeCellLanguage::Formula,
+            formula.to_string(),
+            None,
+        );
+        self.apply(operations, TransactionName::SetCode)
+    }
+
+    /// Evaluates a formula without changing the spreadsheet. References are
+    /// relative to A1 of the first sheet.
+    ///
+    /// Errors within the formula's result (eg, `#DIV/0!`) are returned as
+    /// values; only formulas that cannot be parsed return an error.
+    pub fn evaluate(&self, formula: &str) -> Result<Value> {
+        let sheet_pos = pos![A1].to_sheet_pos(self.first_sheet_id()?);
+        self.evaluate_at(sheet_pos, formula)
+    }
+
+    /// Evaluates a formula as if it were in the cell `a1`, without changing
+    /// the spreadsheet.
+    pub fn evaluate_in(&self, a1: &str, formula: &str) -> Result<Value> {
+        self.evaluate_at(self.sheet_pos(a1)?, formula)
+    }
+
+    /// Recalculates every formula in the spreadsheet.
+    pub fn recalculate(&mut self) -> Result<()> {
+        let grid_controller = &self.grid_controller;
+        let operations = grid_controller
+            .rerun_all_code_cells_operations()
+            .into_iter()
+            .filter(|operation| match operation {
+                Operation::ComputeCode { sheet_pos } => grid_controller
+                    .try_sheet(sheet_pos.sheet_id)
+                    .and_then(|sheet| sheet.code_run_at(&(*sheet_pos).into()))
+                    .is_some_and(|code_run| code_run.language == CodeCellLanguage::Formula),
+                _ => true,
+            })
+            .collect();
+
+        self.apply(operations, TransactionName::RunCode)
+    }
+
+    /// Returns the names of the data tables in the spreadsheet.
+    pub fn data_table_names(&self) -> Vec<String> {
+        self.grid_controller
+            .a1_context()
+            .iter_tables()
+            .filter(|table| !table.is_html_image)
+            .map(|table| table.table_name.clone())
+            .collect()
+    }
+
+    /// Returns the visible columns and rows of a data table, in display
+    /// order. Column names are the table's column headers.
+    pub fn data_table_batch(&self, name: &str) -> Result<RecordBatch> {
+        let not_found = || NativeError::DataTableNotFound(name.to_string());
+
+        let table = self
+            .grid_controller
+            .a1_context()
+            .try_table(name)
+            .ok_or_else(not_found)?;
+        let data_table = self
+            .grid_controller
+            .try_sheet(table.sheet_id)
+            .and_then(|sheet| sheet.data_table_at(&table.bounds.min))
+            .ok_or_else(not_found)?;
+
+        let value = data_table
+            .display_value(false)
+            .map_err(|e| NativeError::Arrow(e.to_string()))?;
+        let skip_rows = usize::from(data_table.header_is_first_row);
+        let headers = data_table
+            .columns_map(false)
+            .into_iter()
+            .map(CellValue::Text)
+            .collect();
+
+        let rows = match value {
+            Value::Array(array) => array
+                .rows()
+                .skip(skip_rows)
+                .map(|row| row.to_vec())
+                .collect(),
+            Value::Single(value) => vec![vec![value]],
+            Value::Tuple(_) => vec![],
+        };
+        let rows = std::iter::once(headers).chain(rows).collect::<Vec<_>>();
+
+        cell_values_to_record_batch(&rows).map_err(|e| NativeError::Arrow(e.to_string()))
+    }
+
+    /// Iterates over every data table as `(name, batch)`.
+    pub fn data_table_batches(&self) -> impl Iterator<Item = (String, Result<RecordBatch>)> + '_ {
+        self.data_table_names().into_iter().map(|name| {
+            let batch = self.data_table_batch(&name);
+            (name, batch)
+        })
+    }
+
+    /// Calls `f` after every change to the spreadsheet, including changes
+    /// to formulas that were recalculated. Replaces any previous callback.
+    pub fn on_change(&mut self, f: impl FnMut(&TransactionEvent) + Send + 'static) {
+        self.grid_controller.with_transaction_callback(f);
+    }
+
+    fn first_sheet_id(&self) -> Result<SheetId> {
+        self.grid_controller
+            .sheet_ids()
+            .first()
+            .copied()
+            .ok_or_else(|| NativeError::Operation("The spreadsheet has no sheets".into()))
+    }
+
+    fn selection(&self, a1: &str) -> Result<A1Selection> {
+        A1Selection::parse_a1(
+            a1,
+            self.first_sheet_id()?,
+            self.grid_controller.a1_context(),
+        )
+        .map_err(|e| invalid_reference(a1, &e.to_string()))
+    }
+
+    fn sheet_pos(&self, a1: &str) -> Result<SheetPos> {
+        let selection = self.selection(a1)?;
+        let pos = selection
+            .try_to_pos(self.grid_controller.a1_context())
+            .ok_or_else(|| invalid_reference(a1, "expected a single cell"))?;
+
+        Ok(pos.to_sheet_pos(selection.sheet_id))
+    }
+
+    fn display_value(&self, sheet_id: SheetId, pos: Pos) -> CellValue {
+        self.grid_controller
+            .try_sheet(sheet_id)
+            .and_then(|sheet| sheet.display_value(pos))
+            .unwrap_or_default()
+    }
+
+    fn evaluate_at(&self, sheet_pos: SheetPos, formula: &str) -> Result<Value> {
+        let parsed = parse_formula(formula, self.grid_controller.a1_context(), sheet_pos)
+            .map_err(|e| NativeError::Formula(e.to_string()))?;
+        let mut ctx = Ctx::new(&self.grid_controller, sheet_pos);
+
+        Ok(parsed.eval(&mut ctx).into_non_tuple().inner)
+    }
+
+    /// Applies operations as a user transaction. Returns an error if the
+    /// transaction is waiting on a code cell that cannot run natively.
+    fn apply(&mut self, operations: Vec<Operation>, name: TransactionName) -> Result<()> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        self.grid_controller
+            .start_user_ai_transaction(operations, None, name, false);
+
+        match self
+            .grid_controller
+            .active_transactions()
+            .async_transactions()
+            .first()
+        {
+            Some(pending) => {
+                let cell = pending
+                    .current_sheet_pos
+                    .map(|sheet_pos| Pos::from(sheet_pos).a1_string())
+                    .unwrap_or_default();
+                Err(NativeError::AsyncCodeCell(cell))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+fn invalid_reference(reference: &str, message: &str) -> NativeError {
+    NativeError::InvalidReference {
+        reference: reference.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use arrow_array::{Array, Float64Array, StringArray};
+
+    use super::*;
+    use crate::{
+        SheetRect,
+        controller::{
+            operations::changed_region::ChangedRegion, user_actions::import::tests::simple_csv,
+        },
+        number::decimal_from_str,
+    };
+
+    fn number(s: &str) -> CellValue {
+        CellValue::Number(decimal_from_str(s).unwrap())
+    }
+
+    #[test]
+    fn test_set_and_get_values() {
+        let mut spreadsheet = Spreadsheet::new();
+        spreadsheet.set_value("A1", "1").unwrap();
+        spreadsheet.set_value("Sheet1!A2", "2").unwrap();
+        spreadsheet.set_value("A3", "=SUM(A1:A2)").unwrap();
+
+        assert_eq!(spreadsheet.get_value("A3").unwrap(), number("3"));
+        assert_eq!(
+            spreadsheet.get_values("A1:B2").unwrap(),
+            vec![
+                vec![number("1"), CellValue::Blank],
+                vec![number("2"), CellValue::Blank],
+            ]
+        );
+
+        // dependent formulas are recalculated
+        spreadsheet.set_value("A1", "10").unwrap();
+        assert_eq!(spreadsheet.get_value("A3").unwrap(), number("12"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut spreadsheet = Spreadsheet::new();
+        assert!(matches!(
+            spreadsheet.get_value("A1:B2"),
+            Err(NativeError::InvalidReference { .. })
+        ));
+        assert!(matches!(
+            spreadsheet.set_value("Missing!A1", "1"),
+            Err(NativeError::InvalidReference { .. })
+        ));
+        assert!(matches!(
+            spreadsheet.set_formula("A1", "SUM(("),
+            Err(NativeError::Formula(_))
+        ));
+        assert!(matches!(
+            spreadsheet.data_table_batch("Missing"),
+            Err(NativeError::DataTableNotFound(_))
+        ));
+        assert!(matches!(
+            Spreadsheet::open(vec![1, 2, 3]),
+            Err(NativeError::InvalidFile(_))
+        ));
+    }
+
+    #[test]
+    fn test_save_and_open() {
+        let mut spreadsheet = Spreadsheet::new();
+        spreadsheet.set_value("B2", "hello").unwrap();
+
+        let spreadsheet = Spreadsheet::open(spreadsheet.save().unwrap()).unwrap();
+        assert_eq!(spreadsheet.sheet_names(), vec!["Sheet1".to_string()]);
+        assert_eq!(
+            spreadsheet.get_value("B2").unwrap(),
+            CellValue::from("hello")
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut spreadsheet = Spreadsheet::new();
+        spreadsheet.set_value("A1", "4").unwrap();
+
+        assert_eq!(
+            spreadsheet.evaluate("A1 * 2").unwrap(),
+            Value::Single(number("8"))
+        );
+        assert_eq!(
+            spreadsheet.evaluate_in("B1", "A1 + 1").unwrap(),
+            Value::Single(number("5"))
+        );
+        assert!(spreadsheet.evaluate("1 / 0").unwrap().errors().len() == 1);
+
+        // evaluating does not change the spreadsheet
+        assert_eq!(spreadsheet.get_value("B1").unwrap(), CellValue::Blank);
+    }
+
+    #[test]
+    fn test_data_table_batch() {
+        let (gc, ..) = simple_csv();
+        let spreadsheet = Spreadsheet::from_grid_controller(gc);
+
+        assert_eq!(
+            spreadsheet.data_table_names(),
+            vec!["simple.csv".to_string()]
+        );
+        let batch = spreadsheet.data_table_batch("simple.csv").unwrap();
+        assert_eq!(batch.num_columns(), 4);
+        assert_eq!(batch.num_rows(), 10);
+        assert_eq!(batch.schema().field(0).name(), "city");
+
+        let cities = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(cities.value(0), "Southborough");
+        assert!(
+            batch
+                .column(3)
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .is_some_and(|population| !population.is_empty())
+        );
+
+        assert_eq!(spreadsheet.data_table_batches().count(), 1);
+    }
+
+    #[test]
+    fn test_on_change() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut spreadsheet = Spreadsheet::new();
+        let events_clone = Arc::clone(&events);
+        spreadsheet.on_change(move |event| {
+            events_clone.lock().unwrap().push(event.clone());
+        });
+
+        spreadsheet.set_value("A1", "1").unwrap();
+        spreadsheet.set_formula("A2", "A1 + 1").unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].name, TransactionName::SetCells);
+        let a1 = SheetRect::single_pos(pos![A1], spreadsheet.first_sheet_id().unwrap());
+        assert_eq!(events[0].changes, vec![ChangedRegion::Rect(a1)]);
+        assert_eq!(events[1].name, TransactionName::SetCode);
+    }
+}
@@ -2,15 +2,15 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use arrow_array::{
-    Array, ArrayRef,
+    Array, ArrayRef, BooleanArray, Date32Array, Float64Array, RecordBatch, StringArray,
     cast::AsArray,
     types::{Date32Type, Date64Type},
 };
 use arrow_buffer::ArrowNativeType;
 use arrow_data::ArrayData;
-use arrow_schema::{DataType, TimeUnit};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
 use chrono::{NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, prelude::ToPrimitive};
 
 use crate::{CellValue, cell_values::CellValues};
 
@@ -260,3 +260,89 @@ fn arrow_timestamp_to_cell_value(
 
     Ok(values)
 }
+
+/// Converts a column of cell values to an arrow array.
+///
+/// The arrow type is inferred from the non-blank values: numbers become
+/// Float64, logicals become Boolean, dates become Date32, and anything else
+/// (including mixed columns) becomes Utf8. Blanks become nulls.
+pub fn cell_values_to_arrow_col(values: &[CellValue]) -> ArrayRef {
+    let non_blank = || {
+        values
+            .iter()
+            .filter(|value| !value.is_blank_or_empty_string())
+    };
+
+    if non_blank().next().is_none() {
+        return Arc::new(StringArray::from(vec![None::<String>; values.len()]));
+    }
+
+    if non_blank().all(|value| matches!(value, CellValue::Number(_))) {
+        let array = values
+            .iter()
+            .map(|value| match value {
+                CellValue::Number(n) => n.to_f64(),
+                _ => None,
+            })
+            .collect::<Float64Array>();
+        return Arc::new(array);
+    }
+
+    if non_blank().all(|value| matches!(value, CellValue::Logical(_))) {
+        let array = values
+            .iter()
+            .map(|value| match value {
+                CellValue::Logical(b) => Some(*b),
+                _ => None,
+            })
+            .collect::<BooleanArray>();
+        return Arc::new(array);
+    }
+
+    if non_blank().all(|value| matches!(value, CellValue::Date(_))) {
+        let array = values
+            .iter()
+            .map(|value| match value {
+                CellValue::Date(d) => Some(Date32Type::from_naive_date(*d)),
+                _ => None,
+            })
+            .collect::<Date32Array>();
+        return Arc::new(array);
+    }
+
+    let array = values
+        .iter()
+        .map(|value| (!value.is_blank_or_empty_string()).then(|| value.to_string()))
+        .collect::<StringArray>();
+    Arc::new(array)
+}
+
+/// Converts rows of cell values to a record batch. The first row is used as
+/// the column names.
+pub fn cell_values_to_record_batch(rows: &[Vec<CellValue>]) -> Result<RecordBatch> {
+    let Some((headers, rows)) = rows.split_first() else {
+        return Ok(RecordBatch::new_empty(Arc::new(Schema::empty())));
+    };
+
+    let mut fields = Vec::with_capacity(headers.len());
+    let mut columns = Vec::with_capacity(headers.len());
+    for (index, header) in headers.iter().enumerate() {
+        let values = rows
+            .iter()
+            .map(|row| row.get(index).cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let column = cell_values_to_arrow_col(&values);
+
+        let name = match header.to_string() {
+            name if name.is_empty() => format!("Column {}", index + 1),
+            name => name,
+        };
+        fields.push(Field::new(name, column.data_type().clone(), true));
+        columns.push(column);
+    }
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
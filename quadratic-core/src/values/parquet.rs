@@ -1,8 +1,12 @@
 use anyhow::{Result, anyhow};
 use bytes::Bytes;
-use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::{ArrowWriter, arrow_reader::ParquetRecordBatchReaderBuilder};
+use parquet::file::reader::{FileReader, SerializedFileReader};
 
-use crate::{CellValue, arrow::arrow_col_to_cell_value_vec};
+use crate::{
+    CellValue,
+    arrow::{arrow_col_to_cell_value_vec, cell_values_to_record_batch},
+};
 
 use crate::{Array, ArraySize};
 
@@ -66,6 +70,69 @@ pub fn parquet_to_array(
 
     Ok(cell_values)
 }
+
+/// Summary of a connection query result, written to the Parquet file's
+/// metadata by the connection service.  The keys must stay in sync with
+/// `QuerySummary` in quadratic-rust-shared.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ParquetQuerySummary {
+    pub num_records: usize,
+    pub over_the_limit: bool,
+    pub total_records: Option<usize>,
+}
+
+impl ParquetQuerySummary {
+    const RECORD_COUNT_KEY: &str = "quadratic:record_count";
+    const OVER_THE_LIMIT_KEY: &str = "quadratic:over_the_limit";
+    const TOTAL_RECORD_COUNT_KEY: &str = "quadratic:total_record_count";
+
+    /// Reads the summary from the Parquet file's metadata, if it has one.
+    pub fn from_parquet(file: &Bytes) -> Option<Self> {
+        let reader = SerializedFileReader::new(file.clone()).ok()?;
+        let metadata = reader.metadata().file_metadata().key_value_metadata()?;
+        let value = |key: &str| {
+            metadata
+                .iter()
+                .find(|kv| kv.key == key)
+                .and_then(|kv| kv.value.as_deref())
+        };
+
+        Some(ParquetQuerySummary {
+            num_records: value(Self::RECORD_COUNT_KEY)?.parse().ok()?,
+            over_the_limit: value(Self::OVER_THE_LIMIT_KEY) == Some("true"),
+            total_records: value(Self::TOTAL_RECORD_COUNT_KEY).and_then(|v| v.parse().ok()),
+        })
+    }
+
+    /// Message shown to the user when the result was cut off.
+    pub fn truncation_message(&self) -> Option<String> {
+        if !self.over_the_limit {
+            return None;
+        }
+
+        let returned = match self.total_records {
+            Some(total_records) => format!("{} of {total_records}", self.num_records),
+            None => format!("the first {}", self.num_records),
+        };
+
+        Some(format!(
+            "Exceeded maximum allowed bytes, returned {returned} records."
+        ))
+    }
+}
+
+/// Writes rows of cell values to a Parquet file. The first row is used as the
+/// column names.
+pub fn cell_values_to_parquet(rows: &[Vec<CellValue>]) -> Result<Vec<u8>> {
+    let batch = cell_values_to_record_batch(rows)?;
+    let mut buffer = vec![];
+    let mut writer = ArrowWriter::try_new(&mut buffer, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(buffer)
+}
+
 #[cfg(test)]
 mod test {
     use std::fs::File;
@@ -83,4 +150,68 @@ mod test {
         file.read_exact(&mut buffer).expect("buffer overflow");
         parquet_to_array(buffer, PARQUET_FILE, None::<fn(&str, u32, u32)>).unwrap();
     }
+
+    #[test]
+    fn test_cell_values_to_parquet() {
+        let rows = vec![
+            vec!["name".into(), "amount".into(), "paid".into()],
+            vec!["a".into(), CellValue::Number(1.into()), true.into()],
+            vec!["b".into(), CellValue::Number(2.into()), false.into()],
+        ];
+        let file = cell_values_to_parquet(&rows).unwrap();
+        let array = parquet_to_array(file, "test.parquet", None::<fn(&str, u32, u32)>).unwrap();
+
+        assert_eq!(array.get(0, 0).unwrap(), &CellValue::from("name"));
+        assert_eq!(array.get(1, 0).unwrap(), &CellValue::from("amount"));
+        assert_eq!(array.get(0, 1).unwrap(), &CellValue::from("a"));
+        assert_eq!(array.get(1, 2).unwrap(), &CellValue::Number(2.into()));
+        assert_eq!(array.get(2, 1).unwrap(), &CellValue::Logical(true));
+        assert_eq!(array.get(2, 2).unwrap(), &CellValue::Logical(false));
+    }
+
+    #[test]
+    fn test_parquet_query_summary() {
+        let rows = vec![vec!["id".into()], vec![CellValue::Number(1.into())]];
+        let batch = cell_values_to_record_batch(&rows).unwrap();
+        let mut buffer = vec![];
+        let mut writer = ArrowWriter::try_new(&mut buffer, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        for (key, value) in [
+            ("quadratic:record_count", "1"),
+            ("quadratic:over_the_limit", "true"),
+            ("quadratic:total_record_count", "20"),
+        ] {
+            writer.append_key_value_metadata(parquet::file::metadata::KeyValue::new(
+                key.to_string(),
+                value.to_string(),
+            ));
+        }
+        writer.close().unwrap();
+
+        let summary = ParquetQuerySummary::from_parquet(&Bytes::from(buffer)).unwrap();
+        assert_eq!(
+            summary,
+            ParquetQuerySummary {
+                num_records: 1,
+                over_the_limit: true,
+                total_records: Some(20),
+            }
+        );
+        assert_eq!(
+            summary.truncation_message(),
+            Some("Exceeded maximum allowed bytes, returned 1 of 20 records.".to_string())
+        );
+
+        let summary = ParquetQuerySummary {
+            total_records: None,
+            ..summary
+        };
+        assert_eq!(
+            summary.truncation_message(),
+            Some("Exceeded maximum allowed bytes, returned the first 1 records.".to_string())
+        );
+
+        let file = Bytes::from(cell_values_to_parquet(&rows).unwrap());
+        assert_eq!(ParquetQuerySummary::from_parquet(&file), None);
+    }
 }
@@ -90,10 +90,13 @@ extern "C" {
         y: i32,
         sheet_id: String,
         query: String,
+        parameters: String,
         connector_type: ConnectionKind,
         connection_id: String,
     );
 
+    pub fn jsCancelConnection(transactionId: String);
+
     pub fn jsSendImage(sheet_id: String, x: i32, y: i32, w: i32, h: i32, image: Option<String>);
 
     // rows: Vec<i64>
@@ -470,22 +473,32 @@ pub fn jsUndoRedo(undo: bool, redo: bool) {
 
 #[cfg(test)]
 #[allow(non_snake_case)]
+#[allow(clippy::too_many_arguments)]
 pub fn jsConnection(
     transactionId: String,
     x: i32,
     y: i32,
     sheet_id: String,
     query: String,
+    parameters: String,
     connector_type: ConnectionKind,
     connection_id: String,
 ) -> JsValue {
     js_call(
         "jsConnection",
-        format!("{transactionId},{x},{y},{sheet_id},{query},{connector_type},{connection_id}"),
+        format!(
+            "{transactionId},{x},{y},{sheet_id},{query},{parameters},{connector_type},{connection_id}"
+        ),
     );
     JsValue::NULL
 }
 
+#[cfg(test)]
+#[allow(non_snake_case)]
+pub fn jsCancelConnection(transactionId: String) {
+    js_call("jsCancelConnection", transactionId);
+}
+
 #[cfg(test)]
 #[allow(non_snake_case)]
 #[allow(clippy::too_many_arguments)]
@@ -0,0 +1,292 @@
+//! Checkpoint Policy
+//!
+//! A file is checkpointed after `CHECKPOINT_TRANSACTIONS` transactions or
+//! `CHECKPOINT_INTERVAL_MINUTES` after its last checkpoint, whichever comes
+//! first.  Until then, its transactions wait in its stream and the file stays
+//! in the active channels.
+//!
+//! After a file is checkpointed, checkpoints outside of the retention policy
+//! are deleted.  Every checkpoint in the recent window is kept, then the
+//! latest checkpoint of each hour, day and week within their windows.  The
+//! latest checkpoint, and the latest checkpoint before the recent window, are
+//! always kept.  Versions within the recent window are rebuilt from the
+//! latter, so transactions are only truncated up to it (see `truncate.rs`).
+
+use std::{collections::HashSet, sync::Arc};
+
+use chrono::{Datelike, NaiveDateTime, TimeDelta, Timelike, Utc};
+use quadratic_rust_shared::{
+    quadratic_database::checkpoint::{
+        FileCheckpoint, delete_file_checkpoints, get_file_checkpoints,
+    },
+    storage::Storage,
+};
+use uuid::Uuid;
+
+use crate::{config::Config, error::Result, state::State};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CheckpointPolicy {
+    pub(crate) transactions: u64,
+    pub(crate) interval: TimeDelta,
+    pub(crate) recent: TimeDelta,
+    pub(crate) hourly: TimeDelta,
+    pub(crate) daily: TimeDelta,
+    pub(crate) weekly: TimeDelta,
+}
+
+impl CheckpointPolicy {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            transactions: config.checkpoint_transactions.max(1),
+            interval: TimeDelta::minutes(config.checkpoint_interval_minutes as i64),
+            recent: TimeDelta::hours(config.checkpoint_retention_recent_hours as i64),
+            hourly: TimeDelta::days(config.checkpoint_retention_hourly_days as i64),
+            daily: TimeDelta::days(config.checkpoint_retention_daily_days as i64),
+            weekly: TimeDelta::weeks(config.checkpoint_retention_weekly_weeks as i64),
+        }
+    }
+
+    /// Whether a file with `pending` transactions should be checkpointed
+    pub(crate) fn should_checkpoint(
+        &self,
+        pending: u64,
+        last_checkpoint: NaiveDateTime,
+        now: NaiveDateTime,
+    ) -> bool {
+        pending >= self.transactions || now - last_checkpoint >= self.interval
+    }
+
+    /// The start of the recent window
+    pub(crate) fn recent_cutoff(&self, now: NaiveDateTime) -> NaiveDateTime {
+        now - self.recent
+    }
+
+    /// The latest checkpoint before the recent window, which versions within
+    /// the window are rebuilt from.  `checkpoints` are ordered by sequence
+    /// number.
+    pub(crate) fn base<'a>(
+        &self,
+        checkpoints: &'a [FileCheckpoint],
+        now: NaiveDateTime,
+    ) -> Option<&'a FileCheckpoint> {
+        let cutoff = self.recent_cutoff(now);
+
+        checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.timestamp <= cutoff)
+    }
+
+    /// The checkpoints outside of the retention policy.  `checkpoints` are
+    /// ordered by sequence number.
+    pub(crate) fn expired<'a>(
+        &self,
+        checkpoints: &'a [FileCheckpoint],
+        now: NaiveDateTime,
+    ) -> Vec<&'a FileCheckpoint> {
+        let base = self.base(checkpoints, now);
+
+        let mut hours = HashSet::new();
+        let mut days = HashSet::new();
+        let mut weeks = HashSet::new();
+
+        // visit the latest checkpoints first so that they represent their hour,
+        // day and week
+        let mut expired = checkpoints
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(index, checkpoint)| {
+                let age = now - checkpoint.timestamp;
+                let date = checkpoint.timestamp.date();
+
+                let is_latest = *index == checkpoints.len() - 1;
+                let is_base =
+                    base.is_some_and(|base| base.sequence_number == checkpoint.sequence_number);
+                let is_recent = age < self.recent;
+                let is_hourly =
+                    age < self.hourly && hours.insert((date, checkpoint.timestamp.hour()));
+                let is_daily = age < self.daily && days.insert(date);
+                let is_weekly = age < self.weekly && weeks.insert(date.iso_week());
+
+                !(is_latest || is_base || is_recent || is_hourly || is_daily || is_weekly)
+            })
+            .map(|(_, checkpoint)| checkpoint)
+            .collect::<Vec<_>>();
+
+        expired.reverse();
+        expired
+    }
+}
+
+/// Delete the checkpoints of a file that are outside of the retention policy,
+/// returning the number deleted.  Checkpoints are deleted before their files
+/// so that a checkpoint never points to a missing file.
+pub(crate) async fn collect_garbage(state: &Arc<State>, file_id: Uuid) -> Result<usize> {
+    let checkpoints = get_file_checkpoints(&state.pool, &file_id).await?;
+    let expired = state
+        .settings
+        .checkpoint_policy
+        .expired(&checkpoints, Utc::now().naive_utc());
+
+    if expired.is_empty() {
+        return Ok(0);
+    }
+
+    let sequence_numbers = expired
+        .iter()
+        .map(|checkpoint| checkpoint.sequence_number)
+        .collect::<Vec<_>>();
+
+    delete_file_checkpoints(&state.pool, &file_id, &sequence_numbers).await?;
+
+    let storage = &state.settings.storage;
+
+    // checkpoints in other buckets (eg, a previous storage) are left in place
+    for checkpoint in expired
+        .iter()
+        .filter(|checkpoint| checkpoint.s3_bucket == storage.path())
+    {
+        if let Err(e) = storage.delete(&checkpoint.s3_key).await {
+            tracing::warn!("Error deleting checkpoint {}: {e}", checkpoint.s3_key);
+        }
+    }
+
+    tracing::info!(
+        "Deleted {} expired checkpoints of file {file_id}",
+        expired.len()
+    );
+
+    Ok(expired.len())
+}
+
+/// The sequence number that a file's transactions can be truncated to, which
+/// is at most the latest checkpoint before the recent window.  Returns None if
+/// there is no checkpoint before the window.  A file without any checkpoints
+/// (eg, a deleted file) has nothing to rebuild, so it's fully truncatable.
+pub(crate) async fn truncatable_sequence_number(
+    state: &Arc<State>,
+    file_id: &Uuid,
+    sequence_num: u64,
+) -> Result<Option<u64>> {
+    let checkpoints = get_file_checkpoints(&state.pool, file_id).await?;
+
+    if checkpoints.is_empty() {
+        return Ok(Some(sequence_num));
+    }
+
+    let base = state
+        .settings
+        .checkpoint_policy
+        .base(&checkpoints, Utc::now().naive_utc())
+        .map(|base| sequence_num.min(base.sequence_number as u64));
+
+    Ok(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> CheckpointPolicy {
+        CheckpointPolicy {
+            transactions: 100,
+            interval: TimeDelta::minutes(5),
+            recent: TimeDelta::hours(24),
+            hourly: TimeDelta::days(7),
+            daily: TimeDelta::days(30),
+            weekly: TimeDelta::weeks(52),
+        }
+    }
+
+    fn now() -> NaiveDateTime {
+        "2026-10-18T12:00:00".parse().unwrap()
+    }
+
+    fn checkpoint(sequence_number: i32, age: TimeDelta) -> FileCheckpoint {
+        FileCheckpoint {
+            sequence_number,
+            s3_bucket: "bucket".into(),
+            s3_key: format!("file-{sequence_number}.grid"),
+            timestamp: now() - age,
+        }
+    }
+
+    fn sequence_numbers(checkpoints: Vec<&FileCheckpoint>) -> Vec<i32> {
+        checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.sequence_number)
+            .collect()
+    }
+
+    #[test]
+    fn test_should_checkpoint() {
+        let policy = policy();
+        let now = now();
+
+        assert!(!policy.should_checkpoint(1, now - TimeDelta::minutes(1), now));
+        assert!(policy.should_checkpoint(100, now - TimeDelta::minutes(1), now));
+        assert!(policy.should_checkpoint(1, now - TimeDelta::minutes(5), now));
+    }
+
+    #[test]
+    fn test_expired_keeps_recent_and_base() {
+        let checkpoints = vec![
+            checkpoint(1, TimeDelta::hours(30)),
+            checkpoint(2, TimeDelta::hours(28)),
+            checkpoint(3, TimeDelta::hours(26)),
+            checkpoint(4, TimeDelta::hours(2)),
+            checkpoint(5, TimeDelta::hours(1)),
+        ];
+
+        // each checkpoint is the latest of its hour
+        assert!(policy().expired(&checkpoints, now()).is_empty());
+
+        // without hourly, daily or weekly retention only the recent window,
+        // the base (3) and the latest are kept
+        let policy = CheckpointPolicy {
+            hourly: TimeDelta::zero(),
+            daily: TimeDelta::zero(),
+            weekly: TimeDelta::zero(),
+            ..policy()
+        };
+        assert_eq!(
+            sequence_numbers(policy.expired(&checkpoints, now())),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_expired_keeps_latest_of_each_bucket() {
+        let checkpoints = vec![
+            // too old to keep
+            checkpoint(1, TimeDelta::weeks(60)),
+            // the same day, 60 days ago
+            checkpoint(2, TimeDelta::days(60) + TimeDelta::hours(1)),
+            checkpoint(3, TimeDelta::days(60)),
+            // the same day, 10 days ago
+            checkpoint(4, TimeDelta::days(10) + TimeDelta::hours(2)),
+            checkpoint(5, TimeDelta::days(10) + TimeDelta::hours(1)),
+            // the same hour, 2 days ago
+            checkpoint(6, TimeDelta::hours(48) + TimeDelta::minutes(30)),
+            checkpoint(7, TimeDelta::hours(48) + TimeDelta::minutes(20)),
+            checkpoint(8, TimeDelta::hours(48) + TimeDelta::minutes(10)),
+            // recent
+            checkpoint(9, TimeDelta::hours(1)),
+            checkpoint(10, TimeDelta::minutes(1)),
+        ];
+
+        assert_eq!(
+            sequence_numbers(policy().expired(&checkpoints, now())),
+            vec![1, 2, 4, 6, 7]
+        );
+    }
+
+    #[test]
+    fn test_expired_keeps_latest() {
+        let checkpoints = vec![checkpoint(1, TimeDelta::weeks(100))];
+
+        assert!(policy().expired(&checkpoints, now()).is_empty());
+    }
+}
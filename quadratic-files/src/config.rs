@@ -20,6 +20,38 @@ fn default_batch_size() -> usize {
     10
 }
 
+fn default_webhook_max_attempts() -> u32 {
+    5
+}
+
+fn default_webhook_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_checkpoint_transactions() -> u64 {
+    100
+}
+
+fn default_checkpoint_interval_minutes() -> u64 {
+    5
+}
+
+fn default_checkpoint_retention_recent_hours() -> u64 {
+    24
+}
+
+fn default_checkpoint_retention_hourly_days() -> u64 {
+    7
+}
+
+fn default_checkpoint_retention_daily_days() -> u64 {
+    30
+}
+
+fn default_checkpoint_retention_weekly_weeks() -> u64 {
+    52
+}
+
 #[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(crate) struct Config {
@@ -37,6 +69,34 @@ pub(crate) struct Config {
     /// Number of files to process per batch (default: 10)
     #[serde(default = "default_batch_size")]
     pub(crate) batch_size: usize,
+    /// Number of attempts to deliver a webhook (default: 5)
+    #[serde(default = "default_webhook_max_attempts")]
+    pub(crate) webhook_max_attempts: u32,
+    /// Delay before retrying a webhook, doubled after each attempt (default: 1000)
+    #[serde(default = "default_webhook_retry_delay_ms")]
+    pub(crate) webhook_retry_delay_ms: u64,
+    /// Allow webhooks to private and loopback addresses, only for local
+    /// development (default: false)
+    #[serde(default)]
+    pub(crate) webhook_allow_private_ips: bool,
+    /// Checkpoint a file after this many transactions (default: 100)
+    #[serde(default = "default_checkpoint_transactions")]
+    pub(crate) checkpoint_transactions: u64,
+    /// Checkpoint a file at least this often while it has transactions (default: 5)
+    #[serde(default = "default_checkpoint_interval_minutes")]
+    pub(crate) checkpoint_interval_minutes: u64,
+    /// Keep every checkpoint this recent (default: 24)
+    #[serde(default = "default_checkpoint_retention_recent_hours")]
+    pub(crate) checkpoint_retention_recent_hours: u64,
+    /// Keep the latest checkpoint of each hour this recent (default: 7)
+    #[serde(default = "default_checkpoint_retention_hourly_days")]
+    pub(crate) checkpoint_retention_hourly_days: u64,
+    /// Keep the latest checkpoint of each day this recent (default: 30)
+    #[serde(default = "default_checkpoint_retention_daily_days")]
+    pub(crate) checkpoint_retention_daily_days: u64,
+    /// Keep the latest checkpoint of each week this recent (default: 52)
+    #[serde(default = "default_checkpoint_retention_weekly_weeks")]
+    pub(crate) checkpoint_retention_weekly_weeks: u64,
 
     pub(crate) pubsub_host: String,
     pub(crate) pubsub_port: String,
@@ -64,6 +124,14 @@ pub(crate) struct Config {
     pub(crate) synced_data_storage_dir: Option<String>,
     pub(crate) storage_encryption_keys: Option<Vec<String>>,
 
+    // Encryption at rest of files, hex encoded 32 byte keys.  The first key
+    // encrypts new files, and the rest are only used to read existing files.
+    pub(crate) storage_master_keys: Option<Vec<String>>,
+    /// Read files that aren't encrypted as they are, only while migrating
+    /// existing files to encryption at rest (default: false)
+    #[serde(default)]
+    pub(crate) storage_allow_unencrypted_reads: bool,
+
     // Plaid
     pub(crate) plaid_client_id: String,
     pub(crate) plaid_secret: String,
@@ -9,7 +9,10 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use quadratic_rust_shared::{SharedError, clean_errors, storage::error::Storage as StorageError};
+use quadratic_rust_shared::{
+    SharedError, clean_errors, quadratic_database::error::QuadraticDatabase,
+    storage::error::Storage as StorageError,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
@@ -27,9 +30,15 @@ pub enum FilesError {
     #[error("Background service error: {0}")]
     BackgroundService(String),
 
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
     #[error("Internal server error: {0}")]
     Config(String),
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     #[error("Connection error: {0}")]
     Connection(String),
 
@@ -91,6 +100,8 @@ impl IntoResponse for FilesError {
     fn into_response(self) -> Response {
         let (status, error) = match &self {
             FilesError::Authentication(error) => (StatusCode::UNAUTHORIZED, clean_errors(error)),
+            FilesError::BadRequest(error) => (StatusCode::BAD_REQUEST, clean_errors(error)),
+            FilesError::Conflict(error) => (StatusCode::CONFLICT, clean_errors(error)),
             FilesError::InternalServer(error) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, clean_errors(error))
             }
@@ -111,6 +122,9 @@ impl From<SharedError> for FilesError {
             SharedError::Aws(error) => FilesError::S3(error.to_string()),
             SharedError::PubSub(error) => FilesError::PubSub(error),
             SharedError::QuadraticApi(error) => FilesError::QuadraticApi(error),
+            SharedError::QuadraticDatabase(QuadraticDatabase::NotFound(error)) => {
+                FilesError::NotFound(error)
+            }
             SharedError::Storage(error) => match error {
                 StorageError::Read(key, _) => FilesError::NotFound(format!("File {key} not found")),
                 _ => FilesError::Storage(error.to_string()),
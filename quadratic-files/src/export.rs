@@ -0,0 +1,323 @@
+//! Export
+//!
+//! Files are exported on the server for clients without a browser, eg.
+//! automated reports.  The latest version of a file is rebuilt from its
+//! checkpoint and the transactions that follow (see `history.rs`), and then
+//! the workbook, a sheet, a selection or a data table is exported as XLSX,
+//! CSV or Parquet.  Exports are cached by sequence number.
+
+use std::sync::Arc;
+
+use axum::{
+    Extension,
+    extract::{Path, Query},
+    http::{HeaderMap, HeaderName, header},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use quadratic_core::{a1::A1Selection, controller::GridController, grid::SheetId};
+use quadratic_rust_shared::auth::jwt::authorize_m2m;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    error::{FilesError, Result},
+    history::{SEQUENCE_NUMBER_HEADER, current_sequence_number, rebuild_at_sequence},
+    state::{State, export_cache::ExportKey},
+};
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExportFormat {
+    Xlsx,
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+/// What to export: the workbook by default, or a sheet, an A1 selection (in
+/// the sheet, or the first sheet) or a data table.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExportQuery {
+    pub(crate) format: ExportFormat,
+    pub(crate) sheet: Option<String>,
+    pub(crate) selection: Option<String>,
+    pub(crate) table: Option<String>,
+}
+
+/// The part of a file to export
+#[derive(Debug, PartialEq)]
+enum ExportTarget {
+    Workbook,
+    Sheet(SheetId),
+    Selection(A1Selection),
+}
+
+/// Export the latest version of a file (M2M only)
+pub(crate) async fn export_file(
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+) -> Result<Response> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    let state = Arc::clone(&state);
+    let sequence_num = current_sequence_number(&state, file_id).await?;
+    let key = ExportKey {
+        file_id,
+        sequence_num,
+        format: query.format,
+        sheet: query.sheet,
+        selection: query.selection,
+        table: query.table,
+    };
+
+    let export = match state.export_cache.get(&key).await {
+        Some(export) => export,
+        None => {
+            let grid = rebuild_at_sequence(&state, file_id, sequence_num).await?;
+            let export = export(&grid, &key)?;
+
+            state.export_cache.add(key.clone(), export.clone()).await;
+
+            export
+        }
+    };
+
+    let headers = [
+        (header::CONTENT_TYPE, key.format.content_type().to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{file_id}.{}\"",
+                key.format.extension()
+            ),
+        ),
+        (
+            HeaderName::from_static(SEQUENCE_NUMBER_HEADER),
+            sequence_num.to_string(),
+        ),
+    ];
+
+    Ok((headers, export).into_response())
+}
+
+/// Export a part of a file
+fn export(grid: &GridController, key: &ExportKey) -> Result<Bytes> {
+    let name = format!("{}.{}", key.file_id, key.format.extension());
+    let error = |e: anyhow::Error| FilesError::ExportFile(name.to_owned(), e.to_string());
+    let target = export_target(
+        grid,
+        key.sheet.as_deref(),
+        key.selection.as_deref(),
+        key.table.as_deref(),
+    )?;
+
+    let export = match (key.format, target) {
+        (ExportFormat::Xlsx, ExportTarget::Workbook) => grid.export_excel().map_err(error)?,
+        (ExportFormat::Xlsx, ExportTarget::Sheet(sheet_id)) => {
+            grid.export_excel_sheet(sheet_id).map_err(error)?
+        }
+        (ExportFormat::Xlsx, ExportTarget::Selection(mut selection)) => {
+            grid.export_excel_selection(&mut selection).map_err(error)?
+        }
+        (_, ExportTarget::Workbook) => {
+            return Err(FilesError::BadRequest(format!(
+                "A sheet, a selection or a table is needed to export {}",
+                key.format.extension()
+            )));
+        }
+        (format, ExportTarget::Sheet(sheet_id)) => {
+            export_selection(grid, format, &mut A1Selection::all(sheet_id)).map_err(error)?
+        }
+        (format, ExportTarget::Selection(mut selection)) => {
+            export_selection(grid, format, &mut selection).map_err(error)?
+        }
+    };
+
+    Ok(export.into())
+}
+
+/// Export a selection as CSV or Parquet
+fn export_selection(
+    grid: &GridController,
+    format: ExportFormat,
+    selection: &mut A1Selection,
+) -> anyhow::Result<Vec<u8>> {
+    match format {
+        ExportFormat::Xlsx => grid.export_excel_selection(selection),
+        ExportFormat::Csv => grid.export_csv_selection(selection).map(String::into_bytes),
+        ExportFormat::Parquet => grid.export_parquet_selection(selection),
+    }
+}
+
+/// Find the part of a file to export
+fn export_target(
+    grid: &GridController,
+    sheet: Option<&str>,
+    selection: Option<&str>,
+    table: Option<&str>,
+) -> Result<ExportTarget> {
+    let context = grid.a1_context();
+    let sheet_id = sheet
+        .map(|name| {
+            context
+                .try_sheet_name(name)
+                .ok_or_else(|| FilesError::NotFound(format!("Sheet {name} not found")))
+        })
+        .transpose()?;
+
+    match (sheet_id, selection, table) {
+        (None, None, None) => Ok(ExportTarget::Workbook),
+        (Some(sheet_id), None, None) => Ok(ExportTarget::Sheet(sheet_id)),
+        (_, Some(selection), None) => {
+            let default_sheet_id = sheet_id.unwrap_or_else(|| grid.grid().first_sheet_id());
+            let selection = A1Selection::parse_a1(selection, default_sheet_id, context)
+                .map_err(|e| FilesError::BadRequest(format!("Invalid selection: {e}")))?;
+
+            Ok(ExportTarget::Selection(selection))
+        }
+        (None, None, Some(table)) => {
+            let table = context
+                .try_table(table)
+                .ok_or_else(|| FilesError::NotFound(format!("Table {table} not found")))?;
+            let selection = A1Selection::parse_a1(&table.table_name, table.sheet_id, context)
+                .map_err(|e| FilesError::BadRequest(format!("Invalid table: {e}")))?;
+
+            Ok(ExportTarget::Selection(selection))
+        }
+        _ => Err(FilesError::BadRequest(
+            "A table can't be exported with a sheet or a selection".into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::load_file;
+    use crate::server::app;
+    use crate::test_util::new_arc_state;
+    use axum::{body::Body, http::StatusCode};
+    use quadratic_core::SheetPos;
+    use tower::util::ServiceExt;
+
+    fn test_grid() -> GridController {
+        let file = load_file(
+            "test",
+            include_bytes!("../../quadratic-rust-shared/data/grid/v1_4_simple.grid").to_vec(),
+        )
+        .unwrap();
+        let mut gc = GridController::from_grid(file, 0);
+        let sheet_id = gc.sheet_ids()[0];
+
+        for (x, value) in [(1, "name"), (2, "amount")] {
+            gc.set_cell_value(SheetPos { x, y: 1, sheet_id }, value.into(), None, false);
+        }
+
+        for (x, value) in [(1, "a"), (2, "1")] {
+            gc.set_cell_value(SheetPos { x, y: 2, sheet_id }, value.into(), None, false);
+        }
+
+        gc
+    }
+
+    fn key(format: ExportFormat, selection: Option<&str>) -> ExportKey {
+        ExportKey {
+            file_id: Uuid::new_v4(),
+            sequence_num: 1,
+            format,
+            sheet: None,
+            selection: selection.map(Into::into),
+            table: None,
+        }
+    }
+
+    #[test]
+    fn test_export_target() {
+        let gc = test_grid();
+        let sheet_id = gc.sheet_ids()[0];
+        let sheet_name = gc.sheet(sheet_id).name().to_string();
+
+        assert_eq!(
+            export_target(&gc, None, None, None).unwrap(),
+            ExportTarget::Workbook
+        );
+        assert_eq!(
+            export_target(&gc, Some(&sheet_name), None, None).unwrap(),
+            ExportTarget::Sheet(sheet_id)
+        );
+        assert!(matches!(
+            export_target(&gc, None, Some("A1:B2"), None).unwrap(),
+            ExportTarget::Selection(selection) if selection.sheet_id == sheet_id
+        ));
+        assert!(matches!(
+            export_target(&gc, Some("missing"), None, None),
+            Err(FilesError::NotFound(_))
+        ));
+        assert!(matches!(
+            export_target(&gc, None, None, Some("missing")),
+            Err(FilesError::NotFound(_))
+        ));
+        assert!(matches!(
+            export_target(&gc, None, Some("A1:B2"), Some("Table1")),
+            Err(FilesError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_export() {
+        let gc = test_grid();
+
+        let csv = export(&gc, &key(ExportFormat::Csv, Some("A1:B2"))).unwrap();
+        assert_eq!(csv, Bytes::from("name,amount\na,1\n"));
+
+        let parquet = export(&gc, &key(ExportFormat::Parquet, Some("A1:B2"))).unwrap();
+        assert!(!parquet.is_empty());
+
+        let xlsx = export(&gc, &key(ExportFormat::Xlsx, None)).unwrap();
+        assert!(!xlsx.is_empty());
+
+        // a workbook can only be exported as xlsx
+        let csv = export(&gc, &key(ExportFormat::Csv, None));
+        assert!(matches!(csv, Err(FilesError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_export_file_requires_m2m() {
+        let state = new_arc_state().await;
+        let response = app(state)
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::GET)
+                    .uri(format!("/files/{}/export?format=csv", Uuid::new_v4()))
+                    .header("authorization", "Bearer invalid")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
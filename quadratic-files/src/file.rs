@@ -20,16 +20,22 @@ use quadratic_rust_shared::{
     SharedError,
     pubsub::PubSub as PubSubTrait,
     quadratic_database::{
-        checkpoint::{get_max_sequence_number, set_file_checkpoint},
+        checkpoint::{get_latest_checkpoint, set_file_checkpoint},
         error::QuadraticDatabase,
+        webhook::{FileWebhook, get_file_webhooks},
     },
     storage::{Storage, StorageContainer},
 };
 
 use crate::{
+    checkpoint::collect_garbage,
     error::{FilesError, Result},
     state::State,
     truncate::{add_processed_transaction, processed_transaction_key},
+    webhook::{
+        dispatch,
+        payload::{FileChanges, changed_regions},
+    },
 };
 
 pub static GROUP_NAME: &str = "quadratic-file-service-1";
@@ -80,14 +86,16 @@ pub(crate) fn key(file_id: Uuid, sequence: u64) -> String {
     format!("{file_id}-{sequence}.grid")
 }
 
-/// Load a file from S3, add it to memory, process transactions and upload it back to S3
+/// Load a file from S3, add it to memory, process transactions and upload it back to S3.
+/// If the file has webhooks, the changes of the transactions are returned.
 pub(crate) async fn process_transactions(
     storage: &StorageContainer,
     file_id: Uuid,
     checkpoint_sequence_num: u64,
     final_sequence_num: u64,
     operations: Vec<Operation>,
-) -> Result<u64> {
+    webhooks: &[FileWebhook],
+) -> Result<(u64, Option<FileChanges>)> {
     let mut grid = get_and_load_object(
         storage,
         &key(file_id, checkpoint_sequence_num),
@@ -96,19 +104,28 @@ pub(crate) async fn process_transactions(
     .await?;
     let key = key(file_id, final_sequence_num);
 
+    // changed regions are found before applying so that deleted data tables are known
+    let regions = (!webhooks.is_empty()).then(|| changed_regions(&grid, &operations));
+
     apply_transaction(&mut grid, operations);
+
+    let include_values = webhooks.iter().any(|webhook| webhook.include_values);
+    let changes = regions.map(|regions| FileChanges::new(&grid, regions, include_values));
     let body = export_file(&key, grid.into_grid())?;
 
     storage.write(&key, &body.into()).await?;
 
-    Ok(final_sequence_num)
+    Ok((final_sequence_num, changes))
 }
 
-/// Process outstanding transactions in the queue
+/// Process outstanding transactions in the queue.  Unless `force`, the file is
+/// only checkpointed when the checkpoint policy calls for it (see
+/// `checkpoint.rs`).
 pub(crate) async fn process_queue_for_room(
     state: &Arc<State>,
     file_id: Uuid,
     active_channels: &str,
+    force: bool,
 ) -> Result<Option<u64>> {
     let start = Utc::now();
     let channel = &file_id.to_string();
@@ -116,10 +133,10 @@ pub(crate) async fn process_queue_for_room(
     // When a file is created in API, a zero checkpoint is created, so we
     // should always expect a return value.
     let db_start = Utc::now();
-    let checkpoint_sequence_num = get_max_sequence_number(&state.pool, &file_id).await;
+    let checkpoint = get_latest_checkpoint(&state.pool, &file_id).await;
 
-    let checkpoint_sequence_num = match checkpoint_sequence_num {
-        Ok(sequence_num) => sequence_num,
+    let checkpoint = match checkpoint {
+        Ok(checkpoint) => checkpoint,
         Err(e) => {
             if matches!(
                 e,
@@ -141,10 +158,12 @@ pub(crate) async fn process_queue_for_room(
     let db_elapsed = (Utc::now() - db_start).num_milliseconds();
     if db_elapsed > 1000 {
         tracing::warn!(
-            "Slow DB query for file {file_id}: get_max_sequence_number took {db_elapsed}ms"
+            "Slow DB query for file {file_id}: get_latest_checkpoint took {db_elapsed}ms"
         );
     }
 
+    let checkpoint_sequence_num = checkpoint.sequence_number;
+
     // this is an expensive lock since we're waiting for the file to write to S3 before unlocking
     let mut pubsub = state.pubsub.lock().await;
 
@@ -154,8 +173,34 @@ pub(crate) async fn process_queue_for_room(
         .subscribe(channel, GROUP_NAME, None)
         .await?;
 
-    // get stream length to understand the state
-    let stream_length = pubsub.connection.length(channel).await.unwrap_or(0);
+    // count the transactions after the checkpoint without reading them, as
+    // messages are keyed by their sequence numbers
+    let pending_transactions = pubsub
+        .connection
+        .last_message(channel, false)
+        .await
+        .ok()
+        .and_then(|(id, _)| id.parse::<u64>().ok())
+        .map_or(0, |last| {
+            last.saturating_sub(checkpoint_sequence_num as u64)
+        });
+
+    // wait for more transactions, keeping the file in active channels so
+    // that it's checked again
+    if !force
+        && pending_transactions > 0
+        && !state.settings.checkpoint_policy.should_checkpoint(
+            pending_transactions,
+            checkpoint.timestamp,
+            Utc::now().naive_utc(),
+        )
+    {
+        tracing::trace!(
+            "File {file_id}: deferring checkpoint of {pending_transactions} transactions"
+        );
+
+        return Ok(None);
+    }
 
     // get transactions after checkpoint
     let messages = pubsub
@@ -182,6 +227,9 @@ pub(crate) async fn process_queue_for_room(
         .collect();
 
     if transactions.is_empty() {
+        // get stream length to understand the state
+        let stream_length = pubsub.connection.length(channel).await.unwrap_or(0);
+
         // Log diagnostic info to understand why no transactions were found
         tracing::warn!(
             "File {file_id}: checkpoint={checkpoint_sequence_num}, stream_length={stream_length}, messages_after_checkpoint={messages_after_checkpoint}, parse_failures={parse_failures}"
@@ -233,14 +281,23 @@ pub(crate) async fn process_queue_for_room(
         .flatten()
         .collect::<Vec<Operation>>();
 
+    // a webhook lookup failure shouldn't hold up processing
+    let webhooks = get_file_webhooks(&state.pool, &file_id)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Error getting webhooks for file {file_id}: {e}");
+            vec![]
+        });
+
     // process the transactions and save the file to S3
     let start_processing = Utc::now();
-    let last_sequence_num = process_transactions(
+    let (last_sequence_num, changes) = process_transactions(
         &state.settings.storage,
         file_id,
         checkpoint_sequence_num as u64,
         last_sequence_num,
         operations,
+        &webhooks,
     )
     .await?;
 
@@ -286,6 +343,28 @@ pub(crate) async fn process_queue_for_room(
     )
     .await?;
 
+    // delete expired checkpoints in the background
+    tokio::spawn({
+        let state = Arc::clone(state);
+
+        async move {
+            if let Err(e) = collect_garbage(&state, file_id).await {
+                tracing::warn!("Error deleting expired checkpoints of file {file_id}: {e}");
+            }
+        }
+    });
+
+    // deliver the changes to the file's webhooks in the background
+    if let Some(changes) = changes {
+        dispatch(
+            state,
+            first_sequence_num,
+            last_sequence_num,
+            webhooks,
+            changes,
+        );
+    }
+
     // add FILE_ID.SEQUENCE_NUM to the processed transactions channel
     let message = processed_transaction_key(&file_id.to_string(), &last_sequence_num.to_string());
     let processed_transactions_channel = state
@@ -358,7 +437,8 @@ pub(crate) async fn process(
             // process file in a separate thread, tracked for graceful shutdown
             let handle = task_tracker.spawn(async move {
                 // TODO(ddimaria): instead of logging the error, move the file to a dead letter queue
-                if let Err(error) = process_queue_for_room(&state, file_id, &active_channels).await
+                if let Err(error) =
+                    process_queue_for_room(&state, file_id, &active_channels, false).await
                 {
                     tracing::error!("Error processing file {file_id}: {error}");
                 };
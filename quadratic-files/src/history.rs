@@ -0,0 +1,627 @@
+//! File History
+//!
+//! A file is checkpointed as its transactions are processed (see `file.rs`),
+//! so the file can be rebuilt at any sequence number by loading the latest
+//! checkpoint at or before it and replaying the transactions that follow.
+//! Transactions are truncated after `TRANSACTION_AGE_DAYS` (see
+//! `truncate.rs`), but never past the checkpoint that the recent retention
+//! window is rebuilt from (see `checkpoint.rs`), so older versions can only be
+//! rebuilt at a retained checkpoint.
+//!
+//! A restore doesn't rewrite the file's history.  The operations that turn
+//! the current version into the historical one are added to the file's
+//! transactions and checkpointed like any other transaction.  Multiplayer
+//! catches open rooms up to the restore and broadcasts it to collaborators
+//! (see multiplayer's `background_worker.rs`).
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Query},
+    http::{HeaderMap, HeaderName, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use quadratic_core::{
+    controller::{
+        GridController,
+        operations::operation::Operation,
+        transaction::{Transaction, TransactionServer},
+    },
+    grid::{
+        Grid,
+        diff::{GridDiff, SheetDiffKind},
+    },
+};
+use quadratic_rust_shared::{
+    auth::jwt::authorize_m2m,
+    protobuf::{Message, quadratic::transaction::ReceiveTransaction},
+    pubsub::PubSub as PubSubTrait,
+    quadratic_database::checkpoint::{
+        get_max_sequence_number, get_sequence_number_at_or_before, get_sequence_number_at_time,
+    },
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    error::{FilesError, Result},
+    file::{apply_transaction, export_file, get_and_load_object, key, process_queue_for_room},
+    state::State,
+};
+
+/// The response header that holds the sequence number of a rebuilt file
+pub(crate) const SEQUENCE_NUMBER_HEADER: &str = "x-sequence-number";
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HistoryFormat {
+    #[default]
+    Grid,
+    Diff,
+}
+
+/// A version of a file is requested by sequence number or by time.  A time
+/// resolves to the latest checkpoint written at or before it.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryQuery {
+    pub(crate) sequence_number: Option<u64>,
+    pub(crate) timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) format: HistoryFormat,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RestoreRequest {
+    pub(crate) sequence_number: Option<u64>,
+    pub(crate) timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RestoreResponse {
+    restored_sequence_number: u64,
+    sequence_number: u64,
+}
+
+/// The changes from a historical version of a file to its current version
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryDiff {
+    sequence_number: u64,
+    current_sequence_number: u64,
+    sheets: Vec<SheetChanges>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SheetStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SheetChanges {
+    name: String,
+    status: SheetStatus,
+    name_change: Option<(String, String)>,
+    cells: Vec<CellChange>,
+
+    /// Format, data table, validation and conditional format changes, and
+    /// changes to the sheet's color, order, column widths and row heights,
+    /// borders and merged cells, are counted, not listed
+    other_changes: usize,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct CellChange {
+    pos: String,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+/// Get a file as it was at a sequence number or time (M2M only), as a .grid
+/// file or as a diff against its current version
+pub(crate) async fn get_file_history(
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<HistoryQuery>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+) -> Result<Response> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    let state = Arc::clone(&state);
+    let sequence_num =
+        resolve_sequence_number(&state, file_id, query.sequence_number, query.timestamp).await?;
+    let historical = rebuild_at_sequence(&state, file_id, sequence_num).await?;
+
+    match query.format {
+        HistoryFormat::Grid => {
+            let body = export_file(&key(file_id, sequence_num), historical.into_grid())?;
+            let headers = [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (
+                    HeaderName::from_static(SEQUENCE_NUMBER_HEADER),
+                    sequence_num.to_string(),
+                ),
+            ];
+
+            Ok((headers, body).into_response())
+        }
+        HistoryFormat::Diff => {
+            let current_sequence_num = current_sequence_number(&state, file_id).await?;
+            let current = rebuild_at_sequence(&state, file_id, current_sequence_num).await?;
+
+            Ok(Json(HistoryDiff {
+                sequence_number: sequence_num,
+                current_sequence_number: current_sequence_num,
+                sheets: sheet_changes(historical.grid(), current.grid()),
+            })
+            .into_response())
+        }
+    }
+}
+
+/// Restore a file to its version at a sequence number or time (M2M only)
+pub(crate) async fn restore_file(
+    Path(file_id): Path<Uuid>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+    Json(request): Json<RestoreRequest>,
+) -> Result<Json<RestoreResponse>> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    let state = Arc::clone(&state);
+    let sequence_num =
+        resolve_sequence_number(&state, file_id, request.sequence_number, request.timestamp)
+            .await?;
+    let checkpoint_sequence_num = restore(&state, file_id, sequence_num).await?;
+
+    Ok(Json(RestoreResponse {
+        restored_sequence_number: sequence_num,
+        sequence_number: checkpoint_sequence_num,
+    }))
+}
+
+/// Get the sequence number of a requested version of a file
+async fn resolve_sequence_number(
+    state: &Arc<State>,
+    file_id: Uuid,
+    sequence_number: Option<u64>,
+    timestamp: Option<DateTime<Utc>>,
+) -> Result<u64> {
+    match (sequence_number, timestamp) {
+        (Some(sequence_number), None) => Ok(sequence_number),
+        (None, Some(timestamp)) => {
+            let sequence_number =
+                get_sequence_number_at_time(&state.pool, &file_id, timestamp.naive_utc()).await?;
+
+            Ok(sequence_number as u64)
+        }
+        _ => Err(FilesError::BadRequest(
+            "Expected either a sequence number or a timestamp".into(),
+        )),
+    }
+}
+
+/// Get the sequence number of the latest version of a file, including
+/// transactions that haven't been checkpointed yet
+pub(crate) async fn current_sequence_number(state: &Arc<State>, file_id: Uuid) -> Result<u64> {
+    let checkpoint_sequence_num = get_max_sequence_number(&state.pool, &file_id).await? as u64;
+    let last_message = state
+        .pubsub
+        .lock()
+        .await
+        .connection
+        .last_message(&file_id.to_string(), false)
+        .await;
+
+    // an empty channel has no transactions after the checkpoint
+    let last_sequence_num = last_message
+        .ok()
+        .and_then(|(id, _)| id.parse::<u64>().ok())
+        .unwrap_or_default();
+
+    Ok(checkpoint_sequence_num.max(last_sequence_num))
+}
+
+/// Rebuild a file at a sequence number from the latest checkpoint at or
+/// before it and the transactions that follow
+pub(crate) async fn rebuild_at_sequence(
+    state: &Arc<State>,
+    file_id: Uuid,
+    sequence_num: u64,
+) -> Result<GridController> {
+    let sequence_number = i32::try_from(sequence_num)
+        .map_err(|_| FilesError::BadRequest(format!("Invalid sequence number {sequence_num}")))?;
+    let checkpoint_sequence_num =
+        get_sequence_number_at_or_before(&state.pool, &file_id, sequence_number).await? as u64;
+
+    let mut grid = get_and_load_object(
+        &state.settings.storage,
+        &key(file_id, checkpoint_sequence_num),
+        checkpoint_sequence_num,
+    )
+    .await?;
+
+    if checkpoint_sequence_num < sequence_num {
+        let messages = state
+            .pubsub
+            .lock()
+            .await
+            .connection
+            .get_messages_between(
+                &file_id.to_string(),
+                &(checkpoint_sequence_num + 1).to_string(),
+                &sequence_num.to_string(),
+                false,
+            )
+            .await?;
+
+        let transactions = messages
+            .iter()
+            .map(|(_, message)| {
+                Transaction::process_incoming(message)
+                    .map_err(|e| FilesError::Serialization(e.to_string()))
+            })
+            .collect::<Result<Vec<TransactionServer>>>()?;
+
+        let operations =
+            replay_operations(file_id, checkpoint_sequence_num, sequence_num, transactions)?;
+
+        apply_transaction(&mut grid, operations);
+    }
+
+    Ok(grid)
+}
+
+/// Get the operations of the transactions after a checkpoint, up to and
+/// including a sequence number.  Every transaction is needed, so a file can't
+/// be rebuilt past a truncated transaction.
+fn replay_operations(
+    file_id: Uuid,
+    checkpoint_sequence_num: u64,
+    sequence_num: u64,
+    transactions: Vec<TransactionServer>,
+) -> Result<Vec<Operation>> {
+    let is_complete = transactions
+        .iter()
+        .map(|transaction| transaction.sequence_num)
+        .eq(checkpoint_sequence_num + 1..=sequence_num);
+
+    if !is_complete {
+        return Err(FilesError::NotFound(format!(
+            "Transactions {} - {} of file {file_id} are no longer available",
+            checkpoint_sequence_num + 1,
+            sequence_num
+        )));
+    }
+
+    let operations = transactions
+        .into_iter()
+        .map(|transaction| {
+            Transaction::decompress_and_deserialize::<Vec<Operation>>(&transaction.operations)
+                .map_err(|e| FilesError::Serialization(e.to_string()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(operations.into_iter().flatten().collect())
+}
+
+/// Restore a file to its version at a sequence number and checkpoint it.
+/// Returns the sequence number of the new checkpoint, or the current
+/// sequence number if the versions are the same.
+pub(crate) async fn restore(state: &Arc<State>, file_id: Uuid, sequence_num: u64) -> Result<u64> {
+    let current_sequence_num = current_sequence_number(state, file_id).await?;
+
+    if sequence_num > current_sequence_num {
+        return Err(FilesError::BadRequest(format!(
+            "Sequence number {sequence_num} is after the current sequence number {current_sequence_num}"
+        )));
+    }
+
+    let historical = rebuild_at_sequence(state, file_id, sequence_num).await?;
+    let current = rebuild_at_sequence(state, file_id, current_sequence_num).await?;
+    let operations = GridDiff::new(current.grid(), historical.grid()).operations();
+
+    if operations.is_empty() {
+        return Ok(current_sequence_num);
+    }
+
+    let restore_sequence_num = current_sequence_num + 1;
+    let message = restore_message(file_id, restore_sequence_num, &operations)?;
+    let active_channels = &state.settings.pubsub_active_channels;
+
+    // stream ids only increase, so this fails if a collaborator's transaction
+    // took the sequence number first
+    state
+        .pubsub
+        .lock()
+        .await
+        .connection
+        .publish(
+            &file_id.to_string(),
+            &restore_sequence_num.to_string(),
+            &message,
+            Some(active_channels),
+        )
+        .await
+        .map_err(|e| {
+            FilesError::Conflict(format!(
+                "File {file_id} changed while restoring sequence number {sequence_num}: {e}"
+            ))
+        })?;
+
+    tracing::info!(
+        "Restored file {file_id} to sequence number {sequence_num} at sequence number {restore_sequence_num}"
+    );
+
+    // the transaction queue may have checkpointed the restore already
+    let checkpoint_sequence_num =
+        process_queue_for_room(state, file_id, active_channels, true).await?;
+
+    Ok(checkpoint_sequence_num.unwrap_or(restore_sequence_num))
+}
+
+/// Encode operations as a transaction message, as multiplayer does
+fn restore_message(file_id: Uuid, sequence_num: u64, operations: &[Operation]) -> Result<Vec<u8>> {
+    let operations = Transaction::serialize_and_compress(operations)
+        .map_err(|e| FilesError::Serialization(e.to_string()))?;
+    let transaction = ReceiveTransaction {
+        r#type: "BinaryTransaction".to_string(),
+        id: Uuid::new_v4().to_string(),
+        file_id: file_id.to_string(),
+        sequence_num,
+        operations,
+    };
+
+    Transaction::add_header(transaction.encode_to_vec())
+        .map_err(|e| FilesError::Serialization(e.to_string()))
+}
+
+/// Get the changes of each sheet from one version of a file to another
+fn sheet_changes(old: &Grid, new: &Grid) -> Vec<SheetChanges> {
+    GridDiff::new(old, new)
+        .sheets
+        .into_iter()
+        .map(|sheet| SheetChanges {
+            status: match sheet.kind {
+                SheetDiffKind::Added(_) => SheetStatus::Added,
+                SheetDiffKind::Removed => SheetStatus::Removed,
+                SheetDiffKind::Changed => SheetStatus::Changed,
+            },
+            other_changes: sheet.formats.len()
+                + sheet.data_tables.len()
+                + sheet.validations.len()
+                + sheet.conditional_formats.len()
+                + [
+                    sheet.color_change.is_some(),
+                    sheet.order_change.is_some(),
+                    sheet.offsets_change.is_some(),
+                    sheet.borders_change.is_some(),
+                    sheet.merge_cells_change.is_some(),
+                ]
+                .into_iter()
+                .filter(|changed| *changed)
+                .count(),
+            cells: sheet
+                .cells
+                .into_iter()
+                .map(|cell| CellChange {
+                    pos: cell.key.a1_string(),
+                    old: cell.old.map(|value| value.to_display()),
+                    new: cell.new.map(|value| value.to_display()),
+                })
+                .collect(),
+            name: sheet.name,
+            name_change: sheet.name_change,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::load_file;
+    use crate::server::app;
+    use crate::test_util::new_arc_state;
+    use axum::{body::Body, http::StatusCode};
+    use quadratic_core::{
+        ClearOption, Pos, SheetPos, SheetRect,
+        a1::A1Selection,
+        grid::{
+            Contiguous2D,
+            js_types::JsColumnWidth,
+            sheet::borders::{BorderSelection, BorderStyle},
+        },
+    };
+    use tower::util::ServiceExt;
+
+    fn test_grid() -> GridController {
+        let file = load_file(
+            "test",
+            include_bytes!("../../quadratic-rust-shared/data/grid/v1_4_simple.grid").to_vec(),
+        )
+        .unwrap();
+
+        GridController::from_grid(file, 0)
+    }
+
+    /// Set a cell value and return the transaction as the server stores it
+    fn set_cell_value(
+        gc: &mut GridController,
+        file_id: Uuid,
+        sequence_num: u64,
+        value: &str,
+    ) -> TransactionServer {
+        let sheet_id = gc.sheet_ids()[0];
+        gc.set_cell_value(
+            SheetPos {
+                x: 1,
+                y: 2,
+                sheet_id,
+            },
+            value.to_string(),
+            None,
+            false,
+        );
+        let operations = gc.last_transaction().unwrap().operations.clone();
+
+        TransactionServer {
+            id: Uuid::new_v4(),
+            file_id,
+            operations: Transaction::serialize_and_compress(&operations).unwrap(),
+            sequence_num,
+        }
+    }
+
+    #[test]
+    fn test_replay_operations() {
+        let file_id = Uuid::new_v4();
+        let mut gc = test_grid();
+        let old = gc.grid().clone();
+        let transactions = vec![
+            set_cell_value(&mut gc, file_id, 4, "hello"),
+            set_cell_value(&mut gc, file_id, 5, "world"),
+        ];
+
+        // replaying the transactions after the checkpoint rebuilds the file
+        let operations = replay_operations(file_id, 3, 5, transactions.clone()).unwrap();
+        let mut rebuilt = GridController::from_grid(old, 3);
+        apply_transaction(&mut rebuilt, operations);
+        assert!(GridDiff::new(rebuilt.grid(), gc.grid()).is_empty());
+
+        // a missing transaction can't be replayed
+        let missing = replay_operations(file_id, 2, 5, transactions.clone()).unwrap_err();
+        assert!(matches!(missing, FilesError::NotFound(_)));
+
+        let missing = replay_operations(file_id, 3, 6, transactions).unwrap_err();
+        assert!(matches!(missing, FilesError::NotFound(_)));
+    }
+
+    #[test]
+    fn test_restore_message() {
+        let file_id = Uuid::new_v4();
+        let mut gc = test_grid();
+        let old = gc.grid().clone();
+        set_cell_value(&mut gc, file_id, 1, "hello");
+
+        let operations = GridDiff::new(gc.grid(), &old).operations();
+        let message = restore_message(file_id, 2, &operations).unwrap();
+
+        // the transaction queue can read and apply the restore
+        let transaction = Transaction::process_incoming(&message).unwrap();
+        assert_eq!(transaction.file_id, file_id);
+        assert_eq!(transaction.sequence_num, 2);
+
+        let operations = replay_operations(file_id, 1, 2, vec![transaction]).unwrap();
+        apply_transaction(&mut gc, operations);
+        assert!(GridDiff::new(gc.grid(), &old).is_empty());
+    }
+
+    #[test]
+    fn test_restore_borders_column_widths_and_merged_cells() {
+        let file_id = Uuid::new_v4();
+        let mut gc = test_grid();
+        let sheet_id = gc.sheet_ids()[0];
+        let old = gc.grid().clone();
+
+        gc.set_borders(
+            A1Selection::from_rect(SheetRect::new(1, 1, 2, 2, sheet_id)),
+            BorderSelection::All,
+            Some(BorderStyle::default()),
+            None,
+            false,
+        );
+        gc.resize_columns(
+            sheet_id,
+            vec![JsColumnWidth {
+                column: 2,
+                width: 200.0,
+            }],
+            None,
+            false,
+        );
+        let mut merge_cells_updates = Contiguous2D::new();
+        merge_cells_updates.set_rect(
+            4,
+            4,
+            Some(5),
+            Some(5),
+            Some(ClearOption::Some(Pos { x: 4, y: 4 })),
+        );
+        gc.server_apply_transaction(
+            vec![Operation::SetMergeCells {
+                sheet_id,
+                merge_cells_updates,
+            }],
+            None,
+        );
+
+        let diff = GridDiff::new(gc.grid(), &old);
+        assert!(diff.sheets[0].borders_change.is_some());
+        assert!(diff.sheets[0].offsets_change.is_some());
+        assert!(diff.sheets[0].merge_cells_change.is_some());
+
+        let message = restore_message(file_id, 4, &diff.operations()).unwrap();
+        let transaction = Transaction::process_incoming(&message).unwrap();
+        let operations = replay_operations(file_id, 3, 4, vec![transaction]).unwrap();
+        apply_transaction(&mut gc, operations);
+
+        let diff = GridDiff::new(gc.grid(), &old);
+        assert!(diff.is_empty(), "{diff}");
+    }
+
+    #[test]
+    fn test_sheet_changes() {
+        let file_id = Uuid::new_v4();
+        let mut gc = test_grid();
+        set_cell_value(&mut gc, file_id, 1, "hello");
+        let old = gc.grid().clone();
+        set_cell_value(&mut gc, file_id, 2, "world");
+
+        let sheets = sheet_changes(&old, gc.grid());
+        assert_eq!(sheets.len(), 1);
+        assert_eq!(sheets[0].status, SheetStatus::Changed);
+        assert_eq!(
+            sheets[0].cells,
+            vec![CellChange {
+                pos: "A2".into(),
+                old: Some("hello".into()),
+                new: Some("world".into()),
+            }]
+        );
+
+        assert!(sheet_changes(&old, &old).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_file_history_requires_a_version() {
+        let state = new_arc_state().await;
+        let token = state.settings.m2m_auth_token.clone();
+        let file_id = Uuid::new_v4();
+
+        let request = |token: &str| {
+            axum::http::Request::builder()
+                .method(axum::http::Method::GET)
+                .uri(format!("/files/{file_id}/history?format=diff"))
+                .header("authorization", format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app(state.clone())
+            .oneshot(request("invalid"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app(state).oneshot(request(&token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -4,10 +4,13 @@
 //! a grid and writes them to S3.
 
 mod auth;
+mod checkpoint;
 mod config;
 mod error;
+mod export;
 mod file;
 mod health;
+mod history;
 mod server;
 mod state;
 mod storage;
@@ -15,6 +18,7 @@ mod synced_connection;
 #[cfg(test)]
 mod test_util;
 mod truncate;
+mod webhook;
 
 use error::Result;
 
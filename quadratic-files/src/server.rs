@@ -7,7 +7,10 @@ use axum::Json;
 use axum::http::Method;
 use axum::middleware::map_response;
 use axum::response::{IntoResponse, Response};
-use axum::{Extension, Router, routing::get};
+use axum::{
+    Extension, Router,
+    routing::{delete, get, post},
+};
 use http::{HeaderValue, header::HeaderName};
 use quadratic_rust_shared::auth::jwt::get_jwks;
 use quadratic_rust_shared::storage::Storage;
@@ -22,12 +25,16 @@ use tower_http::trace::{DefaultMakeSpan, TraceLayer};
 use tracing_subscriber::Layer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use crate::export::export_file;
 use crate::file::{get_files_to_process, process};
 use crate::health::{full_healthcheck, healthcheck};
+use crate::history::{get_file_history, restore_file};
 use crate::state::stats::StatsResponse;
 use crate::storage::{get_presigned_storage, get_storage, upload_presigned_storage};
+use crate::synced_connection::backfill::backfill_synced_connection;
 use crate::synced_connection::background_workers::init_sync_workers;
 use crate::truncate::truncate_processed_transactions;
+use crate::webhook::{create_webhook, delete_webhook, list_webhook_deliveries, list_webhooks};
 use crate::{
     auth::get_middleware,
     config::config,
@@ -38,6 +45,8 @@ use crate::{
 
 const HEALTHCHECK_INTERVAL_S: u64 = 30;
 const SHUTDOWN_TIMEOUT_S: u64 = 60;
+pub(crate) const EXPORT_CACHE_DURATION_S: Duration = Duration::from_secs(60 * 30); // 30 minutes
+pub(crate) const EXPORT_CACHE_MAX_BYTES: usize = 512 * 1024 * 1024; // 512 MiB
 
 /// Construct the application router.  This is separated out so that it can be
 /// integration tested.
@@ -89,12 +98,45 @@ pub(crate) fn app(state: Arc<State>) -> Router {
         // presigned urls
         .route("/storage/presigned/{key}", get(get_presigned_storage))
         //
-        // presigned upload (for local dev thumbnail uploads)
+        // presigned upload (thumbnail uploads with file system or encrypted storage)
         .route(
             "/storage/upload/{key}",
             axum::routing::put(upload_presigned_storage),
         )
         //
+        // a file at a sequence number or time (M2M only)
+        .route("/files/{file_id}/history", get(get_file_history))
+        //
+        // restore a file to a sequence number or time (M2M only)
+        .route("/files/{file_id}/restore", post(restore_file))
+        //
+        // export a file as xlsx, csv or parquet (M2M only)
+        .route("/files/{file_id}/export", get(export_file))
+        //
+        // create and list the webhooks of a file (M2M only)
+        .route(
+            "/files/{file_id}/webhooks",
+            get(list_webhooks).post(create_webhook),
+        )
+        //
+        // delete a webhook of a file (M2M only)
+        .route(
+            "/files/{file_id}/webhooks/{webhook_id}",
+            delete(delete_webhook),
+        )
+        //
+        // the delivery log of a webhook (M2M only)
+        .route(
+            "/files/{file_id}/webhooks/{webhook_id}/deliveries",
+            get(list_webhook_deliveries),
+        )
+        //
+        // backfill a synced connection (M2M only)
+        .route(
+            "/synced-connection/{connection_id}/backfill",
+            post(backfill_synced_connection),
+        )
+        //
         // state
         .layer(Extension(state))
         //
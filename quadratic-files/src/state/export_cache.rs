@@ -0,0 +1,204 @@
+//! Export Cache
+//!
+//! Cache exports by sequence number, so downloading an unchanged file again
+//! doesn't rebuild and export it.  Exports expire after
+//! `EXPORT_CACHE_DURATION_S`, and the least recently used exports are evicted
+//! once the cache holds more than `EXPORT_CACHE_MAX_BYTES`.
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::export::ExportFormat;
+use crate::server::{EXPORT_CACHE_DURATION_S, EXPORT_CACHE_MAX_BYTES};
+
+/// An export of a version of a file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ExportKey {
+    pub(crate) file_id: Uuid,
+    pub(crate) sequence_num: u64,
+    pub(crate) format: ExportFormat,
+    pub(crate) sheet: Option<String>,
+    pub(crate) selection: Option<String>,
+    pub(crate) table: Option<String>,
+}
+
+#[derive(Debug)]
+struct CachedExport {
+    export: Bytes,
+    created: Instant,
+    last_used: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Exports {
+    exports: HashMap<ExportKey, CachedExport>,
+    bytes: usize,
+}
+
+impl Exports {
+    fn remove(&mut self, key: &ExportKey) {
+        if let Some(removed) = self.exports.remove(key) {
+            self.bytes -= removed.export.len();
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ExportCache {
+    exports: Arc<Mutex<Exports>>,
+    max_bytes: usize,
+}
+
+impl ExportCache {
+    /// Create a new cache
+    pub(crate) fn new() -> Self {
+        Self::with_max_bytes(EXPORT_CACHE_MAX_BYTES)
+    }
+
+    /// Create a new cache that holds at most `max_bytes` of exports
+    pub(crate) fn with_max_bytes(max_bytes: usize) -> Self {
+        Self {
+            exports: Arc::new(Mutex::new(Exports::default())),
+            max_bytes,
+        }
+    }
+
+    /// Get an export from the cache.
+    /// If the export is not found or expired, None is returned.
+    pub(crate) async fn get(&self, key: &ExportKey) -> Option<Bytes> {
+        let mut exports = self.exports.lock().await;
+
+        let expired = match exports.exports.get_mut(key) {
+            Some(cached) if cached.created.elapsed() < EXPORT_CACHE_DURATION_S => {
+                cached.last_used = Instant::now();
+                return Some(cached.export.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if expired {
+            exports.remove(key);
+        }
+
+        None
+    }
+
+    /// Add an export to the cache, evicting expired exports and then the
+    /// least recently used exports until it fits.  Exports larger than the
+    /// cache aren't cached.
+    pub(crate) async fn add(&self, key: ExportKey, export: Bytes) {
+        if export.len() > self.max_bytes {
+            return;
+        }
+
+        let mut exports = self.exports.lock().await;
+        exports.remove(&key);
+
+        if exports.bytes + export.len() > self.max_bytes {
+            let expired = exports
+                .exports
+                .iter()
+                .filter(|(_, cached)| cached.created.elapsed() >= EXPORT_CACHE_DURATION_S)
+                .map(|(key, _)| key.to_owned())
+                .collect::<Vec<_>>();
+
+            for key in expired {
+                exports.remove(&key);
+            }
+        }
+
+        while exports.bytes + export.len() > self.max_bytes {
+            let Some(least_recently_used) = exports
+                .exports
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.to_owned())
+            else {
+                break;
+            };
+
+            exports.remove(&least_recently_used);
+        }
+
+        let now = Instant::now();
+        exports.bytes += export.len();
+        exports.exports.insert(
+            key,
+            CachedExport {
+                export,
+                created: now,
+                last_used: now,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_cache() {
+        let cache = ExportCache::new();
+        let key = ExportKey {
+            file_id: Uuid::new_v4(),
+            sequence_num: 1,
+            format: ExportFormat::Csv,
+            sheet: Some("Sheet 1".into()),
+            selection: None,
+            table: None,
+        };
+        let export = Bytes::from("a,b\n1,2\n");
+
+        assert!(cache.get(&key).await.is_none());
+
+        cache.add(key.clone(), export.clone()).await;
+        assert_eq!(cache.get(&key).await, Some(export));
+
+        // a later version of the file isn't cached
+        let later = ExportKey {
+            sequence_num: 2,
+            ..key
+        };
+        assert!(cache.get(&later).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_export_cache_evicts_least_recently_used() {
+        let cache = ExportCache::with_max_bytes(10);
+        let key = |sequence_num| ExportKey {
+            file_id: Uuid::nil(),
+            sequence_num,
+            format: ExportFormat::Csv,
+            sheet: None,
+            selection: None,
+            table: None,
+        };
+
+        cache.add(key(1), Bytes::from("1234")).await;
+        cache.add(key(2), Bytes::from("1234")).await;
+
+        // the first export is used more recently than the second
+        assert!(cache.get(&key(1)).await.is_some());
+
+        cache.add(key(3), Bytes::from("1234")).await;
+        assert!(cache.get(&key(1)).await.is_some());
+        assert!(cache.get(&key(2)).await.is_none());
+        assert!(cache.get(&key(3)).await.is_some());
+        assert_eq!(cache.exports.lock().await.bytes, 8);
+
+        // replacing an export doesn't count it twice
+        cache.add(key(3), Bytes::from("123456")).await;
+        assert_eq!(cache.exports.lock().await.bytes, 10);
+
+        // exports larger than the cache aren't cached
+        cache.add(key(4), Bytes::from("12345678901")).await;
+        assert!(cache.get(&key(4)).await.is_none());
+        assert_eq!(cache.exports.lock().await.bytes, 10);
+    }
+}
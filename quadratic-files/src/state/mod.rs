@@ -3,6 +3,7 @@
 //! Store information about the state of the application in a send + sync
 //! struct.  All access and mutations to state should be performed here.
 
+pub mod export_cache;
 pub mod pubsub;
 pub mod settings;
 pub mod stats;
@@ -17,6 +18,7 @@ use tokio::sync::Mutex;
 
 use crate::config::Config;
 use crate::error::{FilesError, Result};
+use crate::state::export_cache::ExportCache;
 use crate::state::settings::Settings;
 use crate::synced_connection::cache::SyncedConnectionCache;
 
@@ -29,6 +31,7 @@ pub(crate) struct State {
     pub(crate) settings: Settings,
     pub(crate) stats: Mutex<Stats>,
     pub(crate) synced_connection_cache: SyncedConnectionCache,
+    pub(crate) export_cache: ExportCache,
     pub(crate) pool: PgPool,
     pub(crate) batch_size: usize,
 }
@@ -72,6 +75,7 @@ impl State {
             settings: Settings::new(config, jwks).await?,
             stats: Mutex::new(Stats::new()),
             synced_connection_cache: SyncedConnectionCache::new(),
+            export_cache: ExportCache::new(),
             pool,
             batch_size: config.batch_size,
         })
@@ -4,15 +4,19 @@ use jsonwebtoken::jwk::JwkSet;
 use quadratic_rust_shared::SharedError;
 use quadratic_rust_shared::arrow::object_store::ObjectStore;
 use quadratic_rust_shared::environment::Environment;
+use quadratic_rust_shared::storage::encrypted::{EncryptedStorage, MasterKey};
 use quadratic_rust_shared::storage::file_system::{FileSystem, FileSystemConfig};
 use quadratic_rust_shared::storage::s3::{S3, S3Config};
 use quadratic_rust_shared::storage::{StorageConfig, StorageContainer, StorageType};
 use quadratic_rust_shared::synced::SyncedClient;
 use quadratic_rust_shared::synced::plaid::client::{PlaidClient, PlaidEnvironment};
+use reqwest::Client;
 use serde::Serialize;
 
+use crate::checkpoint::CheckpointPolicy;
 use crate::config::Config;
 use crate::error::{FilesError, Result};
+use crate::webhook::delivery::webhook_client;
 
 #[derive(Debug)]
 pub(crate) struct Settings {
@@ -21,9 +25,16 @@ pub(crate) struct Settings {
     pub(crate) quadratic_api_uri: String,
     pub(crate) m2m_auth_token: String,
     pub(crate) storage: StorageContainer,
+    pub(crate) storage_encryption_keys: Vec<String>,
     pub(crate) pubsub_processed_transactions_channel: String,
+    pub(crate) pubsub_active_channels: String,
     pub(crate) object_store: Arc<dyn ObjectStore>,
     pub(crate) checkpoint_bucket_name: String,
+    pub(crate) webhook_max_attempts: u32,
+    pub(crate) webhook_retry_delay_ms: u64,
+    pub(crate) webhook_allow_private_ips: bool,
+    pub(crate) webhook_client: Client,
+    pub(crate) checkpoint_policy: CheckpointPolicy,
 
     // Plaid
     pub(crate) plaid_client_id: String,
@@ -48,14 +59,24 @@ impl Settings {
             quadratic_api_uri: config.quadratic_api_uri.to_owned(),
             m2m_auth_token: config.m2m_auth_token.to_owned(),
             storage: file_storage,
+            storage_encryption_keys: config
+                .storage_encryption_keys
+                .to_owned()
+                .unwrap_or_default(),
             pubsub_processed_transactions_channel: config
                 .pubsub_processed_transactions_channel
                 .to_owned(),
+            pubsub_active_channels: config.pubsub_active_channels.to_owned(),
             object_store,
             plaid_client_id: config.plaid_client_id.to_owned(),
             plaid_secret: config.plaid_secret.to_owned(),
             plaid_environment: config.plaid_environment.to_owned(),
             checkpoint_bucket_name: Self::checkpoint_bucket_name(config),
+            webhook_max_attempts: config.webhook_max_attempts,
+            webhook_retry_delay_ms: config.webhook_retry_delay_ms,
+            webhook_allow_private_ips: config.webhook_allow_private_ips,
+            webhook_client: webhook_client(config.webhook_allow_private_ips)?,
+            checkpoint_policy: CheckpointPolicy::new(config),
         })
     }
 
@@ -117,7 +138,20 @@ impl Settings {
             }
         };
 
-        Ok(storage)
+        // only files are encrypted, synced data is read in place by the object store
+        match (&config.storage_master_keys, is_file_storage) {
+            (Some(master_keys), true) if !master_keys.is_empty() => {
+                let master_keys = master_keys
+                    .iter()
+                    .map(|key| MasterKey::from_hex(key))
+                    .collect::<std::result::Result<Vec<_>, SharedError>>()?;
+                let storage = EncryptedStorage::new(storage, master_keys)?
+                    .with_unencrypted_reads(config.storage_allow_unencrypted_reads);
+
+                Ok(StorageContainer::Encrypted(Box::new(storage)))
+            }
+            _ => Ok(storage),
+        }
     }
 
     /// Create a PlaidClient using Settings' credentials and the access_token from the connection.
@@ -4,10 +4,7 @@ use axum::{
     extract::{Path, Request},
     response::IntoResponse,
 };
-use quadratic_rust_shared::{
-    crypto::aes_cbc::decrypt_from_api,
-    storage::{Storage, StorageContainer},
-};
+use quadratic_rust_shared::{crypto::aes_cbc::decrypt_from_api, storage::Storage};
 use serde::Serialize;
 use std::sync::Arc;
 
@@ -31,25 +28,33 @@ pub(crate) async fn get_storage(
     Ok(file.into_response())
 }
 
-/// Get a file from storage from a presigned URL (encrypted)
+/// Decrypt the file name of a presigned URL, which the API encrypts with the
+/// first storage encryption key.
+fn presigned_file_name(state: &State, encrypted_file_name: &str) -> Result<String> {
+    let key = state
+        .settings
+        .storage_encryption_keys
+        .first()
+        .ok_or_else(|| {
+            FilesError::Storage("Presigned URLs require STORAGE_ENCRYPTION_KEYS".to_string())
+        })?;
+
+    Ok(decrypt_from_api(key, encrypted_file_name)?)
+}
+
+/// Get a file from storage from a presigned URL (encrypted).  Files are read
+/// through the storage container so that files encrypted at rest are
+/// decrypted, for any storage type.
 pub(crate) async fn get_presigned_storage(
     Path(encrypted_file_name): Path<String>,
     state: Extension<Arc<State>>,
 ) -> Result<impl IntoResponse> {
     tracing::trace!("Get presigned file {}", encrypted_file_name);
 
-    match &state.settings.storage {
-        StorageContainer::FileSystem(fs) => {
-            let key = fs.first_key()?;
-            let file_name = decrypt_from_api(&key, &encrypted_file_name)?;
-            let file = fs.read(&file_name).await?;
-
-            Ok(file.into_response())
-        }
-        _ => Err(FilesError::Storage(
-            "Presigned URLs only supported in FileSystem storage options".to_string(),
-        )),
-    }
+    let file_name = presigned_file_name(&state, &encrypted_file_name)?;
+    let file = state.settings.storage.read(&file_name).await?;
+
+    Ok(file.into_response())
 }
 
 /// Upload a file to storage
@@ -84,26 +89,43 @@ pub(crate) async fn upload_presigned_storage(
 ) -> Result<Json<UploadStorageResponse>> {
     tracing::trace!("Upload presigned file {}", encrypted_file_name);
 
-    match &state.settings.storage {
-        StorageContainer::FileSystem(fs) => {
-            let key = fs.first_key()?;
-            let file_name = decrypt_from_api(&key, &encrypted_file_name)?;
+    let file_name = presigned_file_name(&state, &encrypted_file_name)?;
+
+    tracing::trace!(
+        "Uploading presigned file {} to {}",
+        file_name,
+        state.settings.storage.path()
+    );
+
+    let bytes = to_bytes(request.into_body(), usize::MAX)
+        .await
+        .map_err(|e| FilesError::Storage(e.to_string()))?;
+
+    state.settings.storage.write(&file_name, &bytes).await?;
+
+    Ok(Json(UploadStorageResponse {
+        bucket: state.settings.storage.path().to_owned(),
+        key: file_name,
+    }))
+}
 
-            tracing::trace!("Uploading presigned file {} to {}", file_name, fs.path());
+#[cfg(test)]
+mod tests {
+    use quadratic_rust_shared::crypto::aes_cbc::{encrypt_from_api, str_to_key};
 
-            let bytes = to_bytes(request.into_body(), usize::MAX)
-                .await
-                .map_err(|e| FilesError::Storage(e.to_string()))?;
+    use super::*;
+    use crate::test_util::new_state;
 
-            fs.write(&file_name, &bytes).await?;
+    #[tokio::test]
+    async fn decrypts_presigned_file_names() {
+        let state = new_state().await;
+        let key = str_to_key(&state.settings.storage_encryption_keys[0]).unwrap();
+        let encrypted = encrypt_from_api(&key, "file-0.grid").unwrap();
 
-            Ok(Json(UploadStorageResponse {
-                bucket: fs.path().to_owned(),
-                key: file_name,
-            }))
-        }
-        _ => Err(FilesError::Storage(
-            "Presigned upload URLs only supported in FileSystem storage options".to_string(),
-        )),
+        assert_eq!(
+            presigned_file_name(&state, &encrypted).unwrap(),
+            "file-0.grid"
+        );
+        assert!(presigned_file_name(&state, "not-encrypted").is_err());
     }
 }
@@ -0,0 +1,498 @@
+//! Backfill Synced Connections
+//!
+//! Scheduled syncs only sync dates that are missing, so a date range that a
+//! source corrected is re-synced on demand by a backfill.  A backfill is
+//! queued for a connection's stream and date range, runs in a separate
+//! thread and reports its progress like a scheduled sync.  A connection runs
+//! one backfill at a time, and isn't synced or compacted meanwhile.
+
+use std::sync::Arc;
+
+use axum::{Extension, Json, extract::Path, http::HeaderMap, http::StatusCode};
+use chrono::NaiveDate;
+use quadratic_rust_shared::{
+    auth::jwt::authorize_m2m,
+    quadratic_api::get_synced_connections_by_type,
+    synced::{
+        SyncedConnection,
+        backfill::{backfill_date_range, discard_staged, replace, staging_prefix},
+        chunk_date_range,
+        google_analytics::client::GoogleAnalyticsConnection,
+        mixpanel::MixpanelConnection,
+        object_store_path,
+        plaid::PlaidConnection,
+        rest::RestConnection,
+        stripe::StripeConnection,
+        upload,
+    },
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+use crate::{
+    error::{FilesError, Result},
+    state::State,
+    synced_connection::{
+        SyncKind, SyncedConnectionStatus, complete_connection_status, failed_connection_status,
+        process::{CHUNK_SIZE, new_synced_client},
+        start_connection_status, update_connection_status,
+    },
+};
+
+/// The longest date range that can be requested for a backfill
+const MAX_BACKFILL_DAYS: i64 = 366;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BackfillRequest {
+    pub(crate) connection_type: String,
+    pub(crate) stream: String,
+    pub(crate) start_date: NaiveDate,
+    pub(crate) end_date: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BackfillResponse {
+    run_id: Uuid,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+}
+
+/// Queue a backfill of a synced connection's stream (M2M only).
+/// The date range can be extended to whole compacted months, so the range
+/// that's backfilled is returned.
+pub(crate) async fn backfill_synced_connection(
+    Path(connection_id): Path<Uuid>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+    Json(request): Json<BackfillRequest>,
+) -> Result<(StatusCode, Json<BackfillResponse>)> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    if request.start_date > request.end_date {
+        return Err(FilesError::BadRequest(format!(
+            "Start date {} is after end date {}",
+            request.start_date, request.end_date
+        )));
+    }
+
+    if (request.end_date - request.start_date).num_days() >= MAX_BACKFILL_DAYS {
+        return Err(FilesError::BadRequest(format!(
+            "Backfills are limited to {MAX_BACKFILL_DAYS} days"
+        )));
+    }
+
+    let state = Arc::clone(&state);
+    let response = match request.connection_type.to_uppercase().as_str() {
+        "MIXPANEL" => queue_backfill::<MixpanelConnection>(state, connection_id, request).await?,
+        "GOOGLE_ANALYTICS" => {
+            queue_backfill::<GoogleAnalyticsConnection>(state, connection_id, request).await?
+        }
+        "PLAID" => queue_backfill::<PlaidConnection>(state, connection_id, request).await?,
+        "REST_API" => queue_backfill::<RestConnection>(state, connection_id, request).await?,
+        "STRIPE" => queue_backfill::<StripeConnection>(state, connection_id, request).await?,
+        connection_type => {
+            return Err(FilesError::BadRequest(format!(
+                "Unsupported connection type {connection_type}"
+            )));
+        }
+    };
+
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+/// Reserve the connection for the backfill and start it in a separate thread.
+async fn queue_backfill<T: SyncedConnection + Serialize + DeserializeOwned + 'static>(
+    state: Arc<State>,
+    connection_id: Uuid,
+    request: BackfillRequest,
+) -> Result<BackfillResponse> {
+    let synced_connection = get_synced_connections_by_type::<T>(
+        &state.settings.quadratic_api_uri,
+        &state.settings.m2m_auth_token,
+        &request.connection_type.to_uppercase(),
+    )
+    .await?
+    .into_iter()
+    .find(|synced_connection| synced_connection.uuid == connection_id)
+    .ok_or_else(|| FilesError::NotFound(format!("Synced connection {connection_id} not found")))?;
+
+    let connection = synced_connection.type_details;
+    let synced_connection_id = synced_connection.id;
+
+    if !connection.streams().contains(&request.stream.as_str()) {
+        return Err(FilesError::BadRequest(format!(
+            "Stream '{}' not found in connection {}",
+            request.stream, connection_id
+        )));
+    }
+
+    // refuse overlapping jobs, and keep syncs and compaction from starting
+    let reserved = state
+        .synced_connection_cache
+        .add_if_absent(
+            connection_id,
+            connection.kind(),
+            SyncKind::Backfill,
+            SyncedConnectionStatus::Setup,
+        )
+        .await;
+
+    if !reserved {
+        return Err(FilesError::Conflict(format!(
+            "Connection {connection_id} is already syncing"
+        )));
+    }
+
+    // the range depends on which months are compacted, so it's calculated
+    // once compaction can't run
+    let prefix = object_store_path(connection_id, &request.stream);
+    let (start_date, end_date) = match backfill_date_range(
+        &state.settings.object_store,
+        &prefix,
+        connection.start_date(),
+        request.start_date,
+        request.end_date,
+    )
+    .await
+    {
+        Ok(range) => range,
+        Err(e) => {
+            state.synced_connection_cache.delete(connection_id).await;
+            return Err(FilesError::BadRequest(e.to_string()));
+        }
+    };
+
+    let run_id = Uuid::new_v4();
+    let stream = request.stream;
+
+    tracing::info!(
+        "Queued backfill of stream '{}' for {} connection {} from {} to {}",
+        stream,
+        connection.name(),
+        connection_id,
+        start_date,
+        end_date
+    );
+
+    tokio::spawn(async move {
+        let connection_name = connection.name().to_owned();
+
+        state
+            .stats
+            .lock()
+            .await
+            .increment_num_connections_processing();
+
+        let result = process_backfill(
+            Arc::clone(&state),
+            connection,
+            connection_id,
+            synced_connection_id,
+            run_id,
+            &stream,
+            start_date,
+            end_date,
+        )
+        .await;
+
+        state
+            .stats
+            .lock()
+            .await
+            .decrement_num_connections_processing();
+
+        if let Err(e) = result {
+            let error_message = format!(
+                "Error backfilling stream '{}' for {} connection {}: {}",
+                stream, connection_name, connection_id, e
+            );
+
+            tracing::warn!("{}", error_message);
+
+            if let Err(log_err) = failed_connection_status(
+                state,
+                connection_id,
+                synced_connection_id,
+                run_id,
+                Vec::new(),
+                error_message,
+            )
+            .await
+            {
+                tracing::error!(
+                    "Failed to send failure log for {} connection {}: {}",
+                    connection_name,
+                    connection_id,
+                    log_err
+                );
+            }
+        }
+    });
+
+    Ok(BackfillResponse {
+        run_id,
+        start_date,
+        end_date,
+    })
+}
+
+/// Backfill a stream's date range.  Chunks are staged as they're requested,
+/// and replace the range's partitions once every chunk is staged, so a failed
+/// request leaves the synced data as it was.  Partitions are replaced one at
+/// a time, so a failed replace can leave part of the range backfilled.  The
+/// staged records are discarded either way.
+#[allow(clippy::too_many_arguments)]
+async fn process_backfill<T: SyncedConnection + Serialize>(
+    state: Arc<State>,
+    connection: T,
+    connection_id: Uuid,
+    synced_connection_id: u64,
+    run_id: Uuid,
+    stream: &str,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<()> {
+    let start_time = std::time::Instant::now();
+    let object_store = state.settings.object_store.clone();
+
+    start_connection_status(
+        state.clone(),
+        connection_id,
+        synced_connection_id,
+        run_id,
+        connection.kind(),
+        SyncKind::Backfill,
+    )
+    .await?;
+
+    let client = new_synced_client(&state, &connection).await?;
+    let prefix = object_store_path(connection_id, stream);
+    let staging = staging_prefix(connection_id, stream, run_id);
+
+    let result = async {
+        for (chunk_start, chunk_end) in chunk_date_range(start_date, end_date, CHUNK_SIZE) {
+            update_connection_status(
+                state.clone(),
+                connection_id,
+                connection.kind(),
+                SyncKind::Backfill,
+                SyncedConnectionStatus::ApiRequest,
+            )
+            .await?;
+
+            match client.process(stream, chunk_start, chunk_end).await? {
+                Some(chunk) => {
+                    upload(&object_store, &staging, chunk).await?;
+                }
+                None => {
+                    return Err(FilesError::SyncedConnection(format!(
+                        "Stream '{}' is not supported by connection {}",
+                        stream, connection_id
+                    )));
+                }
+            }
+        }
+
+        update_connection_status(
+            state.clone(),
+            connection_id,
+            connection.kind(),
+            SyncKind::Backfill,
+            SyncedConnectionStatus::Upload,
+        )
+        .await?;
+
+        Ok::<_, FilesError>(replace(&object_store, &prefix, &staging, start_date, end_date).await?)
+    }
+    .await;
+
+    if let Err(e) = discard_staged(&object_store, &staging).await {
+        tracing::warn!(
+            "Failed to discard staged backfill of stream '{}' for connection {}: {}",
+            stream,
+            connection_id,
+            e
+        );
+    }
+
+    let num_files = result?;
+
+    let dates_processed = start_date
+        .iter_days()
+        .take_while(|date| *date <= end_date)
+        .collect();
+
+    complete_connection_status(
+        state,
+        connection_id,
+        synced_connection_id,
+        run_id,
+        dates_processed,
+    )
+    .await?;
+
+    tracing::info!(
+        "Finished backfilling stream '{}' with {} files for {} connection {}, elapsed: {:?}",
+        stream,
+        num_files,
+        connection.name(),
+        connection_id,
+        start_time.elapsed()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::app;
+    use crate::test_util::new_arc_state;
+    use axum::body::Body;
+    use chrono::Utc;
+    use httpmock::prelude::*;
+    use serial_test::serial;
+    use tower::util::ServiceExt;
+
+    fn mock_server(connection_id: Uuid) -> MockServer {
+        let server = MockServer::start();
+
+        server.mock(|when, then| {
+            when.method(GET).path("/v0/internal/synced-connection");
+            then.status(200).json_body(serde_json::json!([{
+                "id": 1,
+                "uuid": connection_id,
+                "connectionId": 1,
+                "percentCompleted": 100,
+                "status": "ACTIVE",
+                "updatedDate": Utc::now().to_rfc3339(),
+                "type": "MIXPANEL",
+                "typeDetails": {
+                    "api_secret": "secret",
+                    "project_id": "1",
+                    "start_date": "2024-01-01"
+                }
+            }]));
+        });
+
+        // TODO: Audit that the environment access only happens in single-threaded code.
+        unsafe {
+            std::env::set_var("FILES__QUADRATIC_API_URI", server.base_url());
+            std::env::set_var("QUADRATIC_API_URI", server.base_url());
+        }
+
+        server
+    }
+
+    async fn backfill(
+        state: Arc<State>,
+        connection_id: Uuid,
+        token: &str,
+        body: serde_json::Value,
+    ) -> StatusCode {
+        app(state)
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri(format!("/synced-connection/{connection_id}/backfill"))
+                    .header("authorization", format!("Bearer {token}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    fn request(stream: &str, start_date: &str, end_date: &str) -> serde_json::Value {
+        serde_json::json!({
+            "connectionType": "MIXPANEL",
+            "stream": stream,
+            "startDate": start_date,
+            "endDate": end_date,
+        })
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_backfill_validates_request() {
+        let connection_id = Uuid::new_v4();
+        let _server = mock_server(connection_id);
+        let state = new_arc_state().await;
+        let token = state.settings.m2m_auth_token.clone();
+        let events = request("events", "2024-01-01", "2024-01-31");
+
+        let status = backfill(state.clone(), connection_id, "invalid", events.clone()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let status = backfill(
+            state.clone(),
+            connection_id,
+            &token,
+            request("events", "2024-02-01", "2024-01-01"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let status = backfill(
+            state.clone(),
+            connection_id,
+            &token,
+            request("events", "2023-01-01", "2024-01-31"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let status = backfill(
+            state.clone(),
+            connection_id,
+            &token,
+            request("funnels", "2024-01-01", "2024-01-31"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let status = backfill(state, Uuid::new_v4(), &token, events).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_backfill_refuses_overlapping_jobs() {
+        let connection_id = Uuid::new_v4();
+        let _server = mock_server(connection_id);
+        let state = new_arc_state().await;
+        let token = state.settings.m2m_auth_token.clone();
+
+        state
+            .synced_connection_cache
+            .add(
+                connection_id,
+                quadratic_rust_shared::synced::SyncedConnectionKind::Mixpanel,
+                SyncKind::Backfill,
+                SyncedConnectionStatus::ApiRequest,
+            )
+            .await;
+
+        let status = backfill(
+            state.clone(),
+            connection_id,
+            &token,
+            request("events", "2024-01-01", "2024-01-31"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // the running job is left as is
+        let (_, sync_kind, status) = state
+            .synced_connection_cache
+            .get(connection_id)
+            .await
+            .unwrap();
+        assert_eq!(sync_kind, SyncKind::Backfill);
+        assert_eq!(status, SyncedConnectionStatus::ApiRequest);
+    }
+}
@@ -7,10 +7,12 @@ use tokio_util::sync::CancellationToken;
 use crate::error::Result;
 use crate::state::State;
 use crate::synced_connection::SyncKind;
+use crate::synced_connection::compaction::compact_all_synced_connections;
 use crate::synced_connection::process::process_all_synced_connections;
 
 const DAILY_SYNC_INTERVAL_M: u64 = 60; // 1 hour
 const FULL_SYNC_INTERVAL_M: u64 = 1; // 1 minute
+const COMPACTION_INTERVAL_M: u64 = 60 * 24; // 1 day
 
 /// Initialize the sync workers in separate threads.
 ///
@@ -36,6 +38,13 @@ pub(crate) fn init_sync_workers(
         tokio::spawn(async move { daily_sync_worker(daily_sync_state, daily_sync_token).await });
     handles.push(daily_sync_handle);
 
+    // compact synced data in a separate thread (with initial delay handled inside the worker)
+    let compaction_state = Arc::clone(&state);
+    let compaction_token = cancellation_token.clone();
+    let compaction_handle =
+        tokio::spawn(async move { compaction_worker(compaction_state, compaction_token).await });
+    handles.push(compaction_handle);
+
     Ok(handles)
 }
 
@@ -86,3 +95,31 @@ pub(crate) async fn full_sync_worker(state: Arc<State>, cancellation_token: Canc
         }
     }
 }
+
+/// Compact the synced data of all connections every COMPACTION_INTERVAL_M minutes.
+/// Starts with a 1-minute delay so that startup syncs are scheduled first.
+pub(crate) async fn compaction_worker(state: Arc<State>, cancellation_token: CancellationToken) {
+    tokio::select! {
+        _ = cancellation_token.cancelled() => {
+            tracing::info!("Compaction worker received shutdown signal during startup delay");
+            return;
+        }
+        _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+    }
+
+    let mut interval = time::interval(Duration::from_secs(COMPACTION_INTERVAL_M * 60));
+
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => {
+                tracing::info!("Compaction worker received shutdown signal");
+                break;
+            }
+            _ = interval.tick() => {
+                if let Err(e) = compact_all_synced_connections(state.clone()).await {
+                    tracing::error!("Error compacting all connections: {e}");
+                }
+            }
+        }
+    }
+}
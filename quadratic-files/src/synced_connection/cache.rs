@@ -68,6 +68,26 @@ impl SyncedConnectionCache {
             .await;
     }
 
+    /// Add a status to the cache if the connection doesn't have one.
+    /// Returns false, without changing the existing status, if it does.
+    pub(crate) async fn add_if_absent(
+        &self,
+        uuid: Uuid,
+        kind: SyncedConnectionKind,
+        sync_kind: SyncKind,
+        status: SyncedConnectionStatus,
+    ) -> bool {
+        let mut cache = self.synced_connection.lock().await;
+
+        if cache.get(&uuid).await.is_some() {
+            return false;
+        }
+
+        cache.create(&uuid, (kind, sync_kind, status), None).await;
+
+        true
+    }
+
     /// Update a status in the cache
     pub(crate) async fn update(
         &self,
@@ -212,6 +232,32 @@ mod tests {
         assert!(result1_again.is_some());
     }
 
+    #[tokio::test]
+    async fn test_add_if_absent() {
+        let cache = Arc::new(SyncedConnectionCache::new());
+        let id = Uuid::new_v4();
+        let mut tasks = JoinSet::new();
+
+        for _ in 0..10 {
+            let cache = Arc::clone(&cache);
+            tasks.spawn(async move { cache.add_if_absent(id, KIND, SYNC_KIND, STATUS).await });
+        }
+
+        let mut num_added = 0;
+        while let Some(added) = tasks.join_next().await {
+            if added.unwrap() {
+                num_added += 1;
+            }
+        }
+        assert_eq!(num_added, 1);
+
+        let added = cache.add_if_absent(id, KIND, SYNC_KIND, STATUS_2).await;
+        assert!(!added);
+
+        let (kind, sync_kind, status) = cache.get(id).await.unwrap();
+        assert_matches(kind, sync_kind, status);
+    }
+
     #[tokio::test]
     async fn test_update() {
         let cache = SyncedConnectionCache::new();
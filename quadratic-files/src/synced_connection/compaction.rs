@@ -0,0 +1,162 @@
+//! Compact Synced Connections
+//!
+//! Synced connections write a parquet file per date for each stream.  Once a
+//! month is closed and fully synced, its date partitions are compacted into
+//! a single file so that queries open fewer files.  A connection isn't
+//! compacted while it's syncing, and isn't synced while it's compacting.
+
+use std::sync::Arc;
+
+use chrono::Duration;
+use quadratic_rust_shared::{
+    quadratic_api::get_synced_connections_by_type,
+    synced::{
+        SyncedConnection, compaction::compact, google_analytics::client::GoogleAnalyticsConnection,
+        mixpanel::MixpanelConnection, object_store_path, plaid::PlaidConnection,
+        rest::RestConnection, stripe::StripeConnection,
+    },
+};
+use serde::{Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+use crate::{
+    error::{FilesError, Result},
+    state::State,
+    synced_connection::{SyncKind, SyncedConnectionStatus, can_process_connection},
+};
+
+/// How long superseded date partitions are kept after their month is
+/// compacted, so that queries that already listed them can finish
+const COMPACTION_GRACE_PERIOD_M: i64 = 60; // 1 hour
+
+/// Compact all connections.
+/// Each connection type is compacted independently; failures in one type do
+/// not prevent compacting the others.
+pub(crate) async fn compact_all_synced_connections(state: Arc<State>) -> Result<()> {
+    if let Err(e) =
+        compact_synced_connections::<MixpanelConnection>(state.clone(), "MIXPANEL").await
+    {
+        tracing::error!("Error compacting MIXPANEL connections (continuing with other types): {e}");
+    }
+
+    if let Err(e) =
+        compact_synced_connections::<GoogleAnalyticsConnection>(state.clone(), "GOOGLE_ANALYTICS")
+            .await
+    {
+        tracing::error!(
+            "Error compacting GOOGLE_ANALYTICS connections (continuing with other types): {e}"
+        );
+    }
+
+    if let Err(e) = compact_synced_connections::<PlaidConnection>(state.clone(), "PLAID").await {
+        tracing::error!("Error compacting PLAID connections (continuing with other types): {e}");
+    }
+
+    if let Err(e) = compact_synced_connections::<RestConnection>(state.clone(), "REST_API").await {
+        tracing::error!("Error compacting REST_API connections (continuing with other types): {e}");
+    }
+
+    if let Err(e) = compact_synced_connections::<StripeConnection>(state.clone(), "STRIPE").await {
+        tracing::error!("Error compacting STRIPE connections (continuing with other types): {e}");
+    }
+
+    Ok(())
+}
+
+/// Compact all synced connections of a type, one at a time.
+pub(crate) async fn compact_synced_connections<
+    T: SyncedConnection + Serialize + DeserializeOwned,
+>(
+    state: Arc<State>,
+    connection_type: &str,
+) -> Result<()> {
+    let connections = get_synced_connections_by_type::<T>(
+        &state.settings.quadratic_api_uri,
+        &state.settings.m2m_auth_token,
+        &connection_type.to_uppercase(),
+    )
+    .await?;
+
+    tracing::trace!(
+        "Compacting {} {connection_type} connections",
+        connections.len()
+    );
+
+    for connection in connections {
+        if let Err(e) =
+            compact_synced_connection(state.clone(), &connection.type_details, connection.uuid)
+                .await
+        {
+            state.synced_connection_cache.delete(connection.uuid).await;
+
+            tracing::warn!(
+                "Error compacting {} connection {}: {}",
+                connection.type_details.name(),
+                connection.uuid,
+                e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Compact the streams of a synced connection.
+pub(crate) async fn compact_synced_connection<T: SyncedConnection>(
+    state: Arc<State>,
+    connection: &T,
+    connection_id: Uuid,
+) -> Result<()> {
+    let connection_name = connection.name();
+
+    if !can_process_connection(state.clone(), connection_id, SyncKind::Compaction).await? {
+        tracing::trace!(
+            "Skipping compaction of {connection_name} connection {}, a sync is running",
+            connection_id
+        );
+        return Ok(());
+    }
+
+    state
+        .synced_connection_cache
+        .add(
+            connection_id,
+            connection.kind(),
+            SyncKind::Compaction,
+            SyncedConnectionStatus::Compaction,
+        )
+        .await;
+
+    let object_store = state.settings.object_store.clone();
+    let grace_period = Duration::minutes(COMPACTION_GRACE_PERIOD_M);
+
+    for stream in connection.streams() {
+        let prefix = object_store_path(connection_id, stream);
+        let num_months = compact(
+            &object_store,
+            &prefix,
+            connection.start_date(),
+            grace_period,
+        )
+        .await
+        .map_err(|e| {
+            FilesError::SyncedConnection(format!(
+                "Failed to compact stream '{}' for connection {} ({}): {}",
+                stream, connection_name, connection_id, e
+            ))
+        })?;
+
+        if num_months > 0 {
+            tracing::info!(
+                "Compacted {} month(s) of stream '{}' for {connection_name} connection {}",
+                num_months,
+                stream,
+                connection_id
+            );
+        }
+    }
+
+    state.synced_connection_cache.delete(connection_id).await;
+
+    Ok(())
+}
@@ -10,8 +10,10 @@ use uuid::Uuid;
 use crate::error::{FilesError, Result};
 use crate::state::State;
 
+pub(crate) mod backfill;
 pub(crate) mod background_workers;
 pub(crate) mod cache;
+pub(crate) mod compaction;
 pub(crate) mod process;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -19,18 +21,22 @@ pub(crate) enum SyncedConnectionStatus {
     Setup,
     ApiRequest,
     Upload,
+    Compaction,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub(crate) enum SyncKind {
     Daily,
     Full,
+    Compaction,
+    Backfill,
 }
 
 /// Check if a connection can be processed.
 /// - If no sync is running: allow
 /// - If FULL is running: block all syncs
 /// - If DAILY is running: block DAILY, allow FULL
+/// - If COMPACTION or BACKFILL is running or requested: block unless nothing is running
 async fn can_process_connection(
     state: Arc<State>,
     connection_id: Uuid,
@@ -43,8 +49,12 @@ async fn can_process_connection(
         Some((_, running_sync_kind, _)) => {
             match (running_sync_kind, sync_kind) {
                 (SyncKind::Full, _) => Ok(false), // FULL running: block everything
+                // COMPACTION or BACKFILL running: block everything
+                (SyncKind::Compaction | SyncKind::Backfill, _) => Ok(false),
+                // sync running: block COMPACTION and BACKFILL
+                (_, SyncKind::Compaction | SyncKind::Backfill) => Ok(false),
                 (SyncKind::Daily, SyncKind::Daily) => Ok(false), // DAILY running: block DAILY
-                (SyncKind::Daily, SyncKind::Full) => Ok(true), // DAILY running: allow FULL
+                (SyncKind::Daily, SyncKind::Full) => Ok(true),   // DAILY running: allow FULL
             }
         }
     }
@@ -247,6 +257,47 @@ mod tests {
         assert!(!can_process);
     }
 
+    #[tokio::test]
+    async fn test_can_process_connection_compaction() {
+        let state = new_arc_state().await;
+        let connection_id = Uuid::new_v4();
+
+        update_connection_status(
+            state.clone(),
+            connection_id,
+            SyncedConnectionKind::Mixpanel,
+            SyncKind::Daily,
+            SyncedConnectionStatus::ApiRequest,
+        )
+        .await
+        .unwrap();
+        let can_process =
+            can_process_connection(state.clone(), connection_id, SyncKind::Compaction)
+                .await
+                .unwrap();
+
+        assert!(!can_process);
+
+        state.synced_connection_cache.delete(connection_id).await;
+        update_connection_status(
+            state.clone(),
+            connection_id,
+            SyncedConnectionKind::Mixpanel,
+            SyncKind::Compaction,
+            SyncedConnectionStatus::Compaction,
+        )
+        .await
+        .unwrap();
+
+        for sync_kind in [SyncKind::Daily, SyncKind::Full, SyncKind::Compaction] {
+            let can_process = can_process_connection(state.clone(), connection_id, sync_kind)
+                .await
+                .unwrap();
+
+            assert!(!can_process);
+        }
+    }
+
     #[tokio::test]
     #[serial]
     async fn test_start_connection_status() {
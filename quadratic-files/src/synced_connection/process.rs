@@ -4,15 +4,27 @@
 //! Synced connections are synced daily and full.
 //! Daily sync is done every 1 hour and full sync is done every 1 minute.
 //! The sync is done in a separate thread.
+//!
+//! Incremental streams sync the records that changed since their cursor
+//! rather than missing dates.  They're synced hourly, and by a full sync
+//! only until they have a cursor.
 
 use std::sync::Arc;
 
+use chrono::NaiveDate;
 use quadratic_rust_shared::{
     quadratic_api::get_synced_connections_by_type,
     synced::{
-        SyncedConnection, SyncedConnectionKind, chunk_date_range, dates_to_sync,
-        google_analytics::client::GoogleAnalyticsConnection, mixpanel::MixpanelConnection,
-        object_store_path, plaid::PlaidConnection, upload, write_synced_markers,
+        DATE_FORMAT, SyncedClient, SyncedConnection, SyncedConnectionKind, chunk_date_range,
+        dates_to_sync,
+        google_analytics::client::GoogleAnalyticsConnection,
+        incremental::{SyncState, get_sync_state, merge, set_sync_state},
+        mixpanel::MixpanelConnection,
+        object_store_path,
+        plaid::PlaidConnection,
+        rest::RestConnection,
+        stripe::StripeConnection,
+        upload, write_synced_markers,
     },
 };
 use serde::{Serialize, de::DeserializeOwned};
@@ -27,7 +39,7 @@ use crate::{
     },
 };
 
-const CHUNK_SIZE: u32 = 30; // 30 days
+pub(crate) const CHUNK_SIZE: u32 = 30; // 30 days
 
 /// Process all connections.
 /// Each connection type is processed independently; failures in one type do not
@@ -66,6 +78,20 @@ pub(crate) async fn process_all_synced_connections(
         tracing::error!("Error syncing PLAID connections (continuing with other types): {e}");
     }
 
+    if let Err(e) =
+        process_synced_connections::<RestConnection>(state.clone(), sync_kind.clone(), "REST_API")
+            .await
+    {
+        tracing::error!("Error syncing REST_API connections (continuing with other types): {e}");
+    }
+
+    if let Err(e) =
+        process_synced_connections::<StripeConnection>(state.clone(), sync_kind.clone(), "STRIPE")
+            .await
+    {
+        tracing::error!("Error syncing STRIPE connections (continuing with other types): {e}");
+    }
+
     Ok(())
 }
 
@@ -150,6 +176,22 @@ pub(crate) async fn process_synced_connections<
     Ok(())
 }
 
+/// Create the client of a synced connection.
+pub(crate) async fn new_synced_client<T: SyncedConnection + Serialize>(
+    state: &State,
+    connection: &T,
+) -> Result<Box<dyn SyncedClient>> {
+    let client = match connection.kind() {
+        // we need to manually assemble the PlaidClient b/c API doesn't store the client_id or secret
+        SyncedConnectionKind::Plaid => state.settings.new_plaid_client(connection)?,
+
+        // for other connections, we can use the to_client method
+        _ => connection.to_client(state.settings.environment).await?,
+    };
+
+    Ok(client)
+}
+
 /// Process a synced connection.
 pub(crate) async fn process_synced_connection<
     T: SyncedConnection + Serialize + DeserializeOwned,
@@ -182,41 +224,53 @@ pub(crate) async fn process_synced_connection<
     let streams_len = streams.len();
     let mut connection_started = false;
 
-    let client = match connection.kind() {
-        // we need to manually assemble the PlaidClient b/c API doesn't store the client_id or secret
-        SyncedConnectionKind::Plaid => state.settings.new_plaid_client(&connection)?,
-
-        // for other connections, we can use the to_client method
-        _ => connection.to_client(state.settings.environment).await?,
-    };
+    let client = new_synced_client(&state, &connection).await?;
 
     // Process each stream/table
     for stream in streams {
-        let dates_to_exclude = state
-            .synced_connection_cache
-            .get_dates(connection_id, stream)
-            .await;
-        let mut date_ranges = dates_to_sync(
-            &object_store,
-            connection_id,
-            stream,
-            sync_start_date,
-            dates_to_exclude,
-        )
-        .await?;
+        let incremental = client.is_incremental(stream);
+        let mut sync_state = None;
+        let mut date_ranges = vec![];
 
-        if sync_kind == SyncKind::Full && date_ranges.is_empty() {
-            tracing::trace!(
-                "Skipping stream '{}' for {connection_name} connection {}, kind: {:?}, no dates to sync",
-                stream,
+        if incremental {
+            sync_state = get_sync_state(&object_store, connection_id, stream).await?;
+
+            if sync_kind == SyncKind::Full && sync_state.is_some() {
+                tracing::trace!(
+                    "Skipping incremental stream '{}' for {connection_name} connection {}, kind: {:?}, already synced",
+                    stream,
+                    connection_id,
+                    sync_kind
+                );
+                continue;
+            }
+        } else {
+            let dates_to_exclude = state
+                .synced_connection_cache
+                .get_dates(connection_id, stream)
+                .await;
+            date_ranges = dates_to_sync(
+                &object_store,
                 connection_id,
-                sync_kind
-            );
-            continue;
-        }
+                stream,
+                sync_start_date,
+                dates_to_exclude,
+            )
+            .await?;
 
-        if sync_kind == SyncKind::Daily {
-            date_ranges = vec![(today, today)];
+            if sync_kind == SyncKind::Full && date_ranges.is_empty() {
+                tracing::trace!(
+                    "Skipping stream '{}' for {connection_name} connection {}, kind: {:?}, no dates to sync",
+                    stream,
+                    connection_id,
+                    sync_kind
+                );
+                continue;
+            }
+
+            if sync_kind == SyncKind::Daily {
+                date_ranges = vec![(today, today)];
+            }
         }
 
         // Only start the connection status when we have confirmed work to do
@@ -247,6 +301,38 @@ pub(crate) async fn process_synced_connection<
             .await?;
         }
 
+        if incremental {
+            let (num_files, dates_processed) = process_incremental_stream(
+                state.clone(),
+                client.as_ref(),
+                connection.kind(),
+                connection_id,
+                sync_kind.clone(),
+                stream,
+                sync_state,
+            )
+            .await
+            .map_err(|e| {
+                FilesError::SyncedConnection(format!(
+                    "Failed to sync incremental stream '{}' for connection {} ({}): {}",
+                    stream, connection_name, connection_id, e
+                ))
+            })?;
+
+            total_files_processed += num_files;
+
+            complete_connection_status(
+                state.clone(),
+                connection_id,
+                synced_connection_id,
+                run_id,
+                dates_processed,
+            )
+            .await?;
+
+            continue;
+        }
+
         tracing::info!(
             "Processing stream '{}' for {connection_name} connection {}, kind: {:?}, dates: {:?}",
             stream,
@@ -393,3 +479,80 @@ pub(crate) async fn process_synced_connection<
 
     Ok(())
 }
+
+/// Process an incremental stream: request the records that changed since the
+/// stream's cursor, merge them into their date partitions and save the new
+/// cursor.  The cursor is saved after the merge, so a failed sync is retried
+/// from the same cursor.
+///
+/// Returns the number of files written and their dates.
+async fn process_incremental_stream(
+    state: Arc<State>,
+    client: &dyn SyncedClient,
+    kind: SyncedConnectionKind,
+    connection_id: Uuid,
+    sync_kind: SyncKind,
+    stream: &str,
+    sync_state: Option<SyncState>,
+) -> Result<(usize, Vec<NaiveDate>)> {
+    let object_store = state.settings.object_store.clone();
+    let cursor = sync_state.and_then(|sync_state| sync_state.cursor);
+
+    tracing::info!(
+        "Processing incremental stream '{}' for connection {}, kind: {:?}, cursor: {:?}",
+        stream,
+        connection_id,
+        sync_kind,
+        cursor
+    );
+
+    update_connection_status(
+        state.clone(),
+        connection_id,
+        kind.clone(),
+        sync_kind.clone(),
+        SyncedConnectionStatus::ApiRequest,
+    )
+    .await?;
+
+    let Some(batch) = client
+        .process_incremental(stream, cursor.as_deref())
+        .await?
+    else {
+        return Ok((0, vec![]));
+    };
+
+    update_connection_status(
+        state.clone(),
+        connection_id,
+        kind,
+        sync_kind,
+        SyncedConnectionStatus::Upload,
+    )
+    .await?;
+
+    let dates_processed = batch
+        .data
+        .keys()
+        .flat_map(|date| NaiveDate::parse_from_str(date, DATE_FORMAT))
+        .collect::<Vec<_>>();
+    let prefix = object_store_path(connection_id, stream);
+    let num_files = merge(&object_store, &prefix, &batch.primary_key, batch.data).await?;
+
+    set_sync_state(
+        &object_store,
+        connection_id,
+        stream,
+        &SyncState::new(batch.cursor.or(cursor)),
+    )
+    .await?;
+
+    tracing::trace!(
+        "Completed incremental stream '{}' for connection {}: merged {} files",
+        stream,
+        connection_id,
+        num_files
+    );
+
+    Ok((num_files, dates_processed))
+}
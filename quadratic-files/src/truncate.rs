@@ -2,8 +2,10 @@ use chrono::{Days, Utc};
 use std::{str::from_utf8, sync::Arc};
 
 use quadratic_rust_shared::pubsub::PubSub as PubSubTrait;
+use uuid::Uuid;
 
 use crate::{
+    checkpoint::truncatable_sequence_number,
     error::{FilesError, Result},
     state::State,
 };
@@ -63,19 +65,29 @@ pub(crate) async fn truncate_processed_transaction(
 ) -> Result<()> {
     let start = Utc::now();
 
+    // keep the transactions needed to rebuild retained checkpoints
+    let truncatable_sequence_num = truncatable_sequence_number(
+        state,
+        &Uuid::parse_str(file_id)?,
+        sequence_num.parse::<u64>().unwrap_or(0),
+    )
+    .await?;
+
     // this is an expensive lock
     let mut pubsub = state.pubsub.lock().await;
 
     tracing::trace!("Attempting to truncate at sequence number {sequence_num} for file {file_id}");
 
-    // Redis does not trim inclusively, so we need to add a 1 to the sequence number
-    let inclusive_sequence_num = sequence_num.parse::<u64>().unwrap_or(0) + 1;
-
     // trim the channel at the sequence number
-    pubsub
-        .connection
-        .trim(file_id, &inclusive_sequence_num.to_string())
-        .await?;
+    if let Some(truncatable_sequence_num) = truncatable_sequence_num {
+        // Redis does not trim inclusively, so we need to add a 1 to the sequence number
+        let inclusive_sequence_num = truncatable_sequence_num + 1;
+
+        pubsub
+            .connection
+            .trim(file_id, &inclusive_sequence_num.to_string())
+            .await?;
+    }
 
     // trim the process transactions channel for this checkpoint
     pubsub
@@ -0,0 +1,370 @@
+//! Webhook Delivery
+//!
+//! Changes are POSTed to a webhook's URL as JSON, signed with the webhook's
+//! secret so that receivers can verify them.  The `x-quadratic-signature`
+//! header is `sha256=` followed by the hex HMAC-SHA256 of `{timestamp}.{body}`,
+//! where `timestamp` is the `x-quadratic-timestamp` header.
+//!
+//! Failed deliveries are retried with exponential backoff, and each delivery
+//! is recorded in the delivery log.
+//!
+//! Webhook URLs come from users, so deliveries (and their redirects) to
+//! private, loopback or other non-public addresses are denied.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use quadratic_rust_shared::quadratic_database::webhook::{
+    FileWebhook, FileWebhookDeliveryStatus, create_file_webhook_delivery,
+    update_file_webhook_delivery,
+};
+use quadratic_rust_shared::utils::egress::EgressPolicy;
+use reqwest::{Client, Url};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    error::{FilesError, Result},
+    state::State,
+    webhook::payload::{FileChanges, WebhookPayload},
+};
+
+pub(crate) const DELIVERY_HEADER: &str = "x-quadratic-delivery";
+pub(crate) const SIGNATURE_HEADER: &str = "x-quadratic-signature";
+pub(crate) const TIMESTAMP_HEADER: &str = "x-quadratic-timestamp";
+
+pub(crate) const REQUEST_TIMEOUT_S: u64 = 10;
+pub(crate) const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) initial_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub(crate) fn new(state: &State) -> Self {
+        Self {
+            max_attempts: state.settings.webhook_max_attempts.max(1),
+            initial_delay: Duration::from_millis(state.settings.webhook_retry_delay_ms),
+        }
+    }
+
+    /// The delay after a failed attempt, doubled after each attempt
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DeliveryResult {
+    pub(crate) status: FileWebhookDeliveryStatus,
+    pub(crate) attempts: u32,
+    pub(crate) response_status: Option<u16>,
+    pub(crate) error: Option<String>,
+}
+
+/// Create the client that delivers webhooks, which only requests public
+/// addresses unless `allow_private_ips`
+pub(crate) fn webhook_client(allow_private_ips: bool) -> Result<Client> {
+    EgressPolicy::new(allow_private_ips, MAX_REDIRECTS)
+        .client_builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_S))
+        .build()
+        .map_err(|e| FilesError::Config(format!("Error creating webhook client: {e}")))
+}
+
+/// Sign a delivery with a webhook's secret
+pub(crate) fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    // HMAC accepts keys of any length
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Send a delivery once, returning the response status
+async fn send(
+    client: &Client,
+    url: &str,
+    secret: &str,
+    delivery_id: Uuid,
+    body: &[u8],
+) -> std::result::Result<u16, (Option<u16>, String)> {
+    let timestamp = Utc::now().timestamp();
+    let response = client
+        .post(url)
+        .header("content-type", "application/json")
+        .header(DELIVERY_HEADER, delivery_id.to_string())
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, sign(secret, timestamp, body))
+        .body(body.to_vec())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+    let status = response.status();
+
+    match status.is_success() {
+        true => Ok(status.as_u16()),
+        false => Err((Some(status.as_u16()), format!("Received {status}"))),
+    }
+}
+
+/// Send a delivery, retrying with backoff until it succeeds or the attempts
+/// run out
+pub(crate) async fn send_with_retry(
+    client: &Client,
+    url: &str,
+    secret: &str,
+    delivery_id: Uuid,
+    body: &[u8],
+    retry: RetryPolicy,
+) -> DeliveryResult {
+    let mut result = DeliveryResult {
+        status: FileWebhookDeliveryStatus::Failed,
+        attempts: 0,
+        response_status: None,
+        error: None,
+    };
+
+    for attempt in 1..=retry.max_attempts {
+        result.attempts = attempt;
+
+        match send(client, url, secret, delivery_id, body).await {
+            Ok(response_status) => {
+                result.status = FileWebhookDeliveryStatus::Delivered;
+                result.response_status = Some(response_status);
+                result.error = None;
+                break;
+            }
+            Err((response_status, error)) => {
+                tracing::warn!(
+                    "Webhook delivery {delivery_id} attempt {attempt} of {} failed: {error}",
+                    retry.max_attempts
+                );
+
+                result.response_status = response_status;
+                result.error = Some(error);
+
+                if attempt < retry.max_attempts {
+                    tokio::time::sleep(retry.delay(attempt)).await;
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Deliver changes to a webhook and record the delivery
+pub(crate) async fn deliver(
+    state: &State,
+    client: &Client,
+    webhook: &FileWebhook,
+    first_sequence_number: u64,
+    last_sequence_number: u64,
+    changes: FileChanges,
+) -> Result<DeliveryResult> {
+    let delivery_id = create_file_webhook_delivery(
+        &state.pool,
+        &webhook.uuid,
+        first_sequence_number as i32,
+        last_sequence_number as i32,
+    )
+    .await?;
+
+    let payload = WebhookPayload {
+        webhook_id: webhook.uuid,
+        delivery_id,
+        file_id: webhook.file_id,
+        first_sequence_number,
+        last_sequence_number,
+        sheets: changes.sheets,
+    };
+    let body = serde_json::to_vec(&payload)?;
+    let retry = RetryPolicy::new(state);
+
+    // host names are checked when they're resolved by the client
+    let checked_url = Url::parse(&webhook.url)
+        .map_err(|e| e.to_string())
+        .and_then(|url| {
+            EgressPolicy::new(state.settings.webhook_allow_private_ips, MAX_REDIRECTS)
+                .check_url(&url)
+                .map_err(|denied| denied.to_string())
+        });
+
+    let result = match checked_url {
+        Ok(()) => {
+            send_with_retry(
+                client,
+                &webhook.url,
+                &webhook.secret,
+                delivery_id,
+                &body,
+                retry,
+            )
+            .await
+        }
+        Err(error) => DeliveryResult {
+            status: FileWebhookDeliveryStatus::Failed,
+            attempts: 0,
+            response_status: None,
+            error: Some(error),
+        },
+    };
+
+    update_file_webhook_delivery(
+        &state.pool,
+        &delivery_id,
+        result.status,
+        result.attempts as i32,
+        result.response_status.map(i32::from),
+        result.error.as_deref(),
+    )
+    .await?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    fn retry() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn test_sign() {
+        // echo -n '1700000000.{"a":1}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            sign("secret", 1700000000, br#"{"a":1}"#),
+            "sha256=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686"
+        );
+        assert_ne!(
+            sign("secret", 1700000001, br#"{"a":1}"#),
+            sign("secret", 1700000000, br#"{"a":1}"#)
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let retry = RetryPolicy {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(100),
+        };
+
+        assert_eq!(retry.delay(1), Duration::from_millis(100));
+        assert_eq!(retry.delay(2), Duration::from_millis(200));
+        assert_eq!(retry.delay(4), Duration::from_millis(800));
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_delivers() {
+        let server = MockServer::start();
+        let delivery_id = Uuid::new_v4();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/webhook")
+                .header("content-type", "application/json")
+                .header(DELIVERY_HEADER, delivery_id.to_string())
+                .header_exists(TIMESTAMP_HEADER)
+                .header_exists(SIGNATURE_HEADER)
+                .body(r#"{"a":1}"#);
+            then.status(204);
+        });
+
+        let result = send_with_retry(
+            &Client::new(),
+            &server.url("/webhook"),
+            "secret",
+            delivery_id,
+            br#"{"a":1}"#,
+            retry(),
+        )
+        .await;
+
+        mock.assert_calls(1);
+        assert_eq!(
+            result,
+            DeliveryResult {
+                status: FileWebhookDeliveryStatus::Delivered,
+                attempts: 1,
+                response_status: Some(204),
+                error: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_fails() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/webhook");
+            then.status(500);
+        });
+
+        let result = send_with_retry(
+            &Client::new(),
+            &server.url("/webhook"),
+            "secret",
+            Uuid::new_v4(),
+            b"{}",
+            retry(),
+        )
+        .await;
+
+        mock.assert_calls(3);
+        assert_eq!(result.status, FileWebhookDeliveryStatus::Failed);
+        assert_eq!(result.attempts, 3);
+        assert_eq!(result.response_status, Some(500));
+        assert!(result.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_client_denies_private_addresses() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/webhook");
+            then.status(204);
+        });
+
+        let result = send_with_retry(
+            &webhook_client(false).unwrap(),
+            &format!("http://localhost:{}/webhook", server.port()),
+            "secret",
+            Uuid::new_v4(),
+            b"{}",
+            retry(),
+        )
+        .await;
+
+        mock.assert_calls(0);
+        assert_eq!(result.status, FileWebhookDeliveryStatus::Failed);
+        assert_eq!(result.response_status, None);
+
+        // private addresses are allowed for local development
+        let result = send_with_retry(
+            &webhook_client(true).unwrap(),
+            &format!("http://localhost:{}/webhook", server.port()),
+            "secret",
+            Uuid::new_v4(),
+            b"{}",
+            retry(),
+        )
+        .await;
+
+        mock.assert_calls(1);
+        assert_eq!(result.status, FileWebhookDeliveryStatus::Delivered);
+    }
+}
@@ -0,0 +1,267 @@
+//! Webhooks
+//!
+//! Other systems subscribe to changes to a file with a webhook.  After
+//! `process_queue_for_room` checkpoints new transactions, the changed sheets
+//! and ranges (and optionally the changed values) are delivered to each
+//! webhook whose sheet and range filters match.  Deliveries run in the
+//! background so they don't hold up processing (see `delivery.rs`).
+
+pub(crate) mod delivery;
+pub(crate) mod payload;
+
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json,
+    extract::{Path, Query},
+    http::HeaderMap,
+};
+use futures::future::join_all;
+use quadratic_rust_shared::{
+    auth::jwt::authorize_m2m,
+    quadratic_database::webhook::{
+        FileWebhook, FileWebhookDelivery, create_file_webhook, delete_file_webhook,
+        get_file_webhook_deliveries, get_file_webhooks,
+    },
+    utils::egress::EgressPolicy,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    error::{FilesError, Result},
+    state::State,
+    webhook::{
+        delivery::{MAX_REDIRECTS, deliver},
+        payload::{FileChanges, WebhookFilter},
+    },
+};
+
+const DEFAULT_DELIVERIES_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateWebhookRequest {
+    pub(crate) url: String,
+    pub(crate) sheet_id: Option<String>,
+    pub(crate) range: Option<String>,
+    #[serde(default)]
+    pub(crate) include_values: bool,
+}
+
+/// A new webhook, with the secret used to sign its deliveries.  The secret
+/// is only returned when the webhook is created.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateWebhookResponse {
+    #[serde(flatten)]
+    webhook: FileWebhook,
+    secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeliveriesQuery {
+    pub(crate) limit: Option<i64>,
+}
+
+/// Create a webhook for a file (M2M only)
+pub(crate) async fn create_webhook(
+    Path(file_id): Path<Uuid>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<Json<CreateWebhookResponse>> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    WebhookFilter::new(request.sheet_id.as_deref(), request.range.as_deref())?;
+    validate_url(&request.url, state.settings.webhook_allow_private_ips).await?;
+
+    let secret = format!("whsec_{}", Uuid::new_v4().simple());
+    let webhook = create_file_webhook(
+        &state.pool,
+        &file_id,
+        &request.url,
+        &secret,
+        request.sheet_id.as_deref(),
+        request.range.as_deref(),
+        request.include_values,
+    )
+    .await?;
+
+    Ok(Json(CreateWebhookResponse { webhook, secret }))
+}
+
+/// List the webhooks of a file (M2M only)
+pub(crate) async fn list_webhooks(
+    Path(file_id): Path<Uuid>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+) -> Result<Json<Vec<FileWebhook>>> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    let webhooks = get_file_webhooks(&state.pool, &file_id).await?;
+
+    Ok(Json(webhooks))
+}
+
+/// Delete a webhook of a file (M2M only)
+pub(crate) async fn delete_webhook(
+    Path((file_id, webhook_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+) -> Result<()> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    delete_file_webhook(&state.pool, &file_id, &webhook_id).await?;
+
+    Ok(())
+}
+
+/// List the latest deliveries to a webhook of a file, newest first (M2M only)
+pub(crate) async fn list_webhook_deliveries(
+    Path((file_id, webhook_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<DeliveriesQuery>,
+    headers: HeaderMap,
+    state: Extension<Arc<State>>,
+) -> Result<Json<Vec<FileWebhookDelivery>>> {
+    authorize_m2m(&headers, &state.settings.m2m_auth_token)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_DELIVERIES_LIMIT).max(1);
+    let deliveries = get_file_webhook_deliveries(&state.pool, &file_id, &webhook_id, limit).await?;
+
+    Ok(Json(deliveries))
+}
+
+/// Webhooks can only deliver to http(s) URLs of public addresses (unless
+/// private addresses are allowed).  Deliveries are checked again, since a
+/// host can resolve to a different address later.
+async fn validate_url(url: &str, allow_private_ips: bool) -> Result<()> {
+    let parsed =
+        Url::parse(url).map_err(|e| FilesError::BadRequest(format!("Invalid url: {e}")))?;
+
+    let policy = EgressPolicy::new(allow_private_ips, MAX_REDIRECTS);
+
+    policy
+        .check_url(&parsed)
+        .map_err(|e| FilesError::BadRequest(e.to_string()))?;
+
+    // check_url ensures there's a host
+    if !allow_private_ips {
+        let host = parsed.host_str().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        policy
+            .resolve(host)
+            .await
+            .map_err(|e| FilesError::BadRequest(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Deliver changes to the webhooks of a file in the background
+pub(crate) fn dispatch(
+    state: &Arc<State>,
+    first_sequence_number: u64,
+    last_sequence_number: u64,
+    webhooks: Vec<FileWebhook>,
+    changes: FileChanges,
+) {
+    let state = Arc::clone(state);
+
+    tokio::spawn(async move {
+        let client = &state.settings.webhook_client;
+
+        let deliveries = webhooks.iter().filter_map(|webhook| {
+            let filter = WebhookFilter::new(webhook.sheet_id.as_deref(), webhook.range.as_deref())
+                .inspect_err(|e| tracing::warn!("Skipping webhook {}: {e}", webhook.uuid))
+                .ok()?;
+            let changes = changes.filter(&filter, webhook.include_values)?;
+
+            Some(async {
+                let result = deliver(
+                    &state,
+                    client,
+                    webhook,
+                    first_sequence_number,
+                    last_sequence_number,
+                    changes,
+                )
+                .await;
+
+                if let Err(e) = result {
+                    tracing::error!("Error delivering webhook {}: {e}", webhook.uuid);
+                }
+            })
+        });
+
+        join_all(deliveries).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::app;
+    use crate::test_util::new_arc_state;
+    use axum::{body::Body, http::StatusCode};
+    use tower::util::ServiceExt;
+
+    async fn create(token: &str, body: serde_json::Value) -> StatusCode {
+        let state = new_arc_state().await;
+
+        app(state)
+            .oneshot(
+                axum::http::Request::builder()
+                    .method(axum::http::Method::POST)
+                    .uri(format!("/files/{}/webhooks", Uuid::new_v4()))
+                    .header("authorization", format!("Bearer {token}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_validate_url() {
+        assert!(validate_url("https://1.1.1.1/webhook", false).await.is_ok());
+        assert!(validate_url("http://localhost:8080", false).await.is_err());
+        assert!(validate_url("http://127.0.0.1:8080", false).await.is_err());
+        assert!(
+            validate_url("http://169.254.169.254/latest", false)
+                .await
+                .is_err()
+        );
+        assert!(validate_url("http://[::1]/webhook", false).await.is_err());
+        assert!(validate_url("ftp://example.com", false).await.is_err());
+        assert!(validate_url("not a url", false).await.is_err());
+
+        // private addresses are allowed for local development
+        assert!(validate_url("http://localhost:8080", true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_webhook_validates_request() {
+        let token = new_arc_state().await.settings.m2m_auth_token.clone();
+        let body = serde_json::json!({ "url": "https://example.com/webhook" });
+
+        let status = create("invalid", body).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let body = serde_json::json!({ "url": "ftp://example.com" });
+        let status = create(&token, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let body = serde_json::json!({ "url": "https://example.com", "range": "not a range" });
+        let status = create(&token, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let body = serde_json::json!({ "url": "http://169.254.169.254/latest/meta-data" });
+        let status = create(&token, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
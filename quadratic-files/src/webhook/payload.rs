@@ -0,0 +1,379 @@
+//! Webhook Payload
+//!
+//! The changes of processed transactions are collected from their operations
+//! (see `Operation::changed_regions`), grouped by sheet, and then filtered for
+//! each webhook by its sheet and range.
+
+use quadratic_core::{
+    Pos, Rect,
+    a1::RefRangeBounds,
+    controller::{
+        GridController,
+        operations::{changed_region::ChangedRegion, operation::Operation},
+    },
+    grid::SheetId,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::error::{FilesError, Result};
+
+/// The maximum number of cell values included in a delivery
+pub(crate) const MAX_CELL_VALUES: usize = 1_000;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WebhookPayload {
+    pub(crate) webhook_id: Uuid,
+    pub(crate) delivery_id: Uuid,
+    pub(crate) file_id: Uuid,
+    pub(crate) first_sequence_number: u64,
+    pub(crate) last_sequence_number: u64,
+    pub(crate) sheets: Vec<SheetChanges>,
+}
+
+/// The changes of processed transactions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FileChanges {
+    pub(crate) sheets: Vec<SheetChanges>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SheetChanges {
+    pub(crate) sheet_id: String,
+
+    /// None if the sheet was deleted
+    pub(crate) name: Option<String>,
+
+    /// The changed ranges, in A1 notation
+    pub(crate) ranges: Vec<String>,
+
+    /// The sheet changed outside of known ranges (eg, formats, borders,
+    /// inserted or deleted rows and columns)
+    pub(crate) sheet_changed: bool,
+
+    /// The values of changed cells, when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cells: Option<Vec<CellValueChange>>,
+
+    /// More than `MAX_CELL_VALUES` cells changed, so not all are included
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) cells_truncated: bool,
+
+    #[serde(skip)]
+    pub(crate) rects: Vec<Rect>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub(crate) struct CellValueChange {
+    pub(crate) pos: String,
+    pub(crate) value: Option<String>,
+}
+
+/// A webhook's filter, parsed from its sheet and range
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct WebhookFilter {
+    pub(crate) sheet_id: Option<String>,
+    pub(crate) range: Option<Rect>,
+}
+
+impl WebhookFilter {
+    pub(crate) fn new(sheet_id: Option<&str>, range: Option<&str>) -> Result<Self> {
+        let range = range
+            .map(|range| {
+                RefRangeBounds::from_str(range, None)
+                    .map(|bounds| bounds.to_rect_unbounded())
+                    .map_err(|e| FilesError::BadRequest(format!("Invalid range {range}: {e}")))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            sheet_id: sheet_id.map(Into::into),
+            range,
+        })
+    }
+}
+
+/// A rect in A1 notation, eg. `A1:B2` or `A1` for a single cell
+fn a1_string(rect: Rect) -> String {
+    if rect.min == rect.max {
+        rect.min.a1_string()
+    } else {
+        rect.a1_string()
+    }
+}
+
+/// Collect the regions changed by operations.  This is called before the
+/// operations are applied, so data tables are looked up in the current grid.
+pub(crate) fn changed_regions(
+    grid: &GridController,
+    operations: &[Operation],
+) -> Vec<ChangedRegion> {
+    operations
+        .iter()
+        .flat_map(|operation| operation.changed_regions(grid))
+        .collect()
+}
+
+impl FileChanges {
+    /// Group changed regions by sheet, reading the values of changed cells
+    /// from `grid` (after the operations are applied) if `include_values`.
+    pub(crate) fn new(
+        grid: &GridController,
+        regions: Vec<ChangedRegion>,
+        include_values: bool,
+    ) -> Self {
+        let mut sheets = Vec::<SheetChanges>::new();
+
+        for region in regions {
+            let sheet_id = region.sheet_id().to_string();
+            let index = match sheets.iter().position(|sheet| sheet.sheet_id == sheet_id) {
+                Some(index) => index,
+                None => {
+                    sheets.push(SheetChanges {
+                        name: grid
+                            .try_sheet(region.sheet_id())
+                            .map(|sheet| sheet.name().to_string()),
+                        sheet_id,
+                        ranges: vec![],
+                        sheet_changed: false,
+                        cells: include_values.then(Vec::new),
+                        cells_truncated: false,
+                        rects: vec![],
+                    });
+                    sheets.len() - 1
+                }
+            };
+            let sheet = &mut sheets[index];
+
+            match region {
+                ChangedRegion::Rect(sheet_rect) => {
+                    let rect = Rect::from(sheet_rect);
+
+                    if !sheet.rects.contains(&rect) {
+                        sheet.rects.push(rect);
+                        sheet.ranges.push(a1_string(rect));
+                    }
+                }
+                ChangedRegion::Sheet(_) => sheet.sheet_changed = true,
+            }
+        }
+
+        let mut changes = Self { sheets };
+
+        if include_values {
+            changes.read_values(grid);
+        }
+
+        changes
+    }
+
+    /// Read the values of changed cells, up to `MAX_CELL_VALUES`
+    fn read_values(&mut self, grid: &GridController) {
+        let mut count = 0;
+
+        for changes in self.sheets.iter_mut() {
+            let Some(sheet) = changes
+                .sheet_id
+                .parse()
+                .ok()
+                .and_then(|sheet_id| grid.try_sheet(sheet_id))
+            else {
+                continue;
+            };
+            let cells = changes.cells.get_or_insert_with(Vec::new);
+
+            'rects: for rect in changes.rects.iter() {
+                for y in rect.y_range() {
+                    for x in rect.x_range() {
+                        let pos = Pos { x, y };
+
+                        if count == MAX_CELL_VALUES {
+                            changes.cells_truncated = true;
+                            break 'rects;
+                        }
+
+                        if cells.iter().any(|cell| cell.pos == pos.a1_string()) {
+                            continue;
+                        }
+
+                        cells.push(CellValueChange {
+                            pos: pos.a1_string(),
+                            value: sheet.display_value(pos).map(|value| value.to_display()),
+                        });
+                        count += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The changes that match a webhook's filter, or None if there are none.
+    /// Cell values are only included if the webhook asks for them.
+    pub(crate) fn filter(&self, filter: &WebhookFilter, include_values: bool) -> Option<Self> {
+        let sheets = self
+            .sheets
+            .iter()
+            .filter(|sheet| {
+                filter
+                    .sheet_id
+                    .as_ref()
+                    .is_none_or(|sheet_id| sheet_id == &sheet.sheet_id)
+            })
+            .filter_map(|sheet| {
+                let mut sheet = sheet.to_owned();
+
+                if let Some(range) = filter.range {
+                    let (rects, ranges) = sheet
+                        .rects
+                        .iter()
+                        .zip(sheet.ranges.iter())
+                        .filter(|(rect, _)| rect.intersects(range))
+                        .map(|(rect, a1)| (*rect, a1.to_owned()))
+                        .unzip();
+
+                    sheet.rects = rects;
+                    sheet.ranges = ranges;
+                    sheet.cells = sheet.cells.map(|cells| {
+                        cells
+                            .into_iter()
+                            .filter(|cell| {
+                                Pos::try_a1_string(&cell.pos).is_some_and(|pos| range.contains(pos))
+                            })
+                            .collect()
+                    });
+                }
+
+                if !include_values {
+                    sheet.cells = None;
+                    sheet.cells_truncated = false;
+                }
+
+                (sheet.sheet_changed || !sheet.rects.is_empty()).then_some(sheet)
+            })
+            .collect::<Vec<_>>();
+
+        (!sheets.is_empty()).then_some(Self { sheets })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quadratic_core::{SheetPos, SheetRect, cell_values::CellValues};
+
+    fn test_grid() -> (GridController, SheetId) {
+        let mut gc = GridController::test();
+        let sheet_id = gc.sheet_ids()[0];
+
+        gc.set_cell_value(SheetPos::new(sheet_id, 1, 1), "a".into(), None, false);
+        gc.set_cell_value(SheetPos::new(sheet_id, 2, 1), "b".into(), None, false);
+        gc.set_cell_value(SheetPos::new(sheet_id, 10, 10), "c".into(), None, false);
+
+        (gc, sheet_id)
+    }
+
+    fn set_cell_values(sheet_id: SheetId, x: i64, y: i64, w: u32, h: u32) -> Operation {
+        Operation::SetCellValues {
+            sheet_pos: SheetPos::new(sheet_id, x, y),
+            values: CellValues::new(w, h),
+        }
+    }
+
+    #[test]
+    fn test_file_changes() {
+        let (gc, sheet_id) = test_grid();
+        let operations = vec![
+            set_cell_values(sheet_id, 1, 1, 2, 1),
+            set_cell_values(sheet_id, 1, 1, 2, 1),
+            set_cell_values(sheet_id, 10, 10, 1, 1),
+        ];
+        let regions = changed_regions(&gc, &operations);
+        let changes = FileChanges::new(&gc, regions, true);
+
+        assert_eq!(changes.sheets.len(), 1);
+
+        let sheet = &changes.sheets[0];
+        assert_eq!(sheet.sheet_id, sheet_id.to_string());
+        assert_eq!(sheet.name.as_deref(), Some(gc.sheet(sheet_id).name()));
+        assert_eq!(sheet.ranges, vec!["A1:B1", "J10"]);
+        assert!(!sheet.sheet_changed);
+        assert_eq!(
+            sheet.cells,
+            Some(vec![
+                CellValueChange {
+                    pos: "A1".into(),
+                    value: Some("a".into())
+                },
+                CellValueChange {
+                    pos: "B1".into(),
+                    value: Some("b".into())
+                },
+                CellValueChange {
+                    pos: "J10".into(),
+                    value: Some("c".into())
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_file_changes_truncates_values() {
+        let (gc, sheet_id) = test_grid();
+        let regions = vec![ChangedRegion::Rect(SheetRect::new(
+            1, 1, 100, 100, sheet_id,
+        ))];
+        let changes = FileChanges::new(&gc, regions, true);
+
+        let sheet = &changes.sheets[0];
+        assert_eq!(sheet.cells.as_ref().unwrap().len(), MAX_CELL_VALUES);
+        assert!(sheet.cells_truncated);
+    }
+
+    #[test]
+    fn test_filter() {
+        let (gc, sheet_id) = test_grid();
+        let regions = vec![
+            ChangedRegion::Rect(SheetRect::new(1, 1, 2, 1, sheet_id)),
+            ChangedRegion::Rect(SheetRect::new(10, 10, 10, 10, sheet_id)),
+        ];
+        let changes = FileChanges::new(&gc, regions, true);
+
+        // no filter
+        let filtered = changes.filter(&WebhookFilter::default(), true).unwrap();
+        assert_eq!(filtered, changes);
+
+        // values are only included when requested
+        let filtered = changes.filter(&WebhookFilter::default(), false).unwrap();
+        assert_eq!(filtered.sheets[0].cells, None);
+
+        // range
+        let filter = WebhookFilter::new(None, Some("J1:J20")).unwrap();
+        let filtered = changes.filter(&filter, true).unwrap();
+        assert_eq!(filtered.sheets[0].ranges, vec!["J10"]);
+        assert_eq!(filtered.sheets[0].cells.as_ref().unwrap().len(), 1);
+
+        // columns
+        let filter = WebhookFilter::new(Some(&sheet_id.to_string()), Some("A:B")).unwrap();
+        let filtered = changes.filter(&filter, true).unwrap();
+        assert_eq!(filtered.sheets[0].ranges, vec!["A1:B1"]);
+
+        // no matching changes
+        let filter = WebhookFilter::new(None, Some("Z100")).unwrap();
+        assert_eq!(changes.filter(&filter, true), None);
+
+        let filter = WebhookFilter::new(Some(&SheetId::new().to_string()), None).unwrap();
+        assert_eq!(changes.filter(&filter, true), None);
+
+        // sheet changes match any range
+        let changes = FileChanges::new(&gc, vec![ChangedRegion::Sheet(sheet_id)], false);
+        let filter = WebhookFilter::new(None, Some("Z100")).unwrap();
+        assert!(changes.filter(&filter, false).unwrap().sheets[0].sheet_changed);
+
+        assert!(matches!(
+            WebhookFilter::new(None, Some("not a range")),
+            Err(FilesError::BadRequest(_))
+        ));
+    }
+}
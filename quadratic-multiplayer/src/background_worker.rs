@@ -3,12 +3,19 @@ use std::{sync::Arc, time::Duration};
 use tokio::{task::JoinHandle, time};
 use uuid::Uuid;
 
-use crate::{error::Result, get_room, message::broadcast, state::State};
+use crate::{
+    error::Result,
+    get_room,
+    message::{broadcast, broadcast_queued_transactions},
+    state::State,
+};
 
 const BACKGROUND_WORKER_INTERVAL_MS: u64 = 1000;
 
 /// In a separate thread:
 ///   * Check for stale users in rooms and remove them.
+///   * Broadcast transactions that were added to a room's transaction queue
+///     without multiplayer (e.g. restores from the files service).
 #[tracing::instrument(level = "trace")]
 pub(crate) fn start(
     state: Arc<State>,
@@ -55,6 +62,17 @@ pub(crate) fn start(
                             error
                         );
                     }
+
+                    let queued =
+                        broadcast_room_queued_transactions(Arc::clone(&state), file_id).await;
+
+                    if let Err(error) = queued {
+                        tracing::warn!(
+                            "Error broadcasting queued transactions to room {}: {:?}",
+                            file_id,
+                            error
+                        );
+                    }
                 }
             });
 
@@ -95,6 +113,17 @@ async fn remove_stale_users_in_room(
     )))
 }
 
+// broadcast the transactions after the room's sequence number
+#[tracing::instrument(level = "trace")]
+async fn broadcast_room_queued_transactions(state: Arc<State>, file_id: &Uuid) -> Result<usize> {
+    // the room was closed
+    let Ok(sequence_num) = state.get_sequence_num(file_id).await else {
+        return Ok(0);
+    };
+
+    broadcast_queued_transactions(state, file_id.to_owned(), sequence_num + 1).await
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -118,4 +147,26 @@ mod tests {
         let room = state.get_room(&file_id).await;
         assert!(room.is_err());
     }
+
+    #[tokio::test]
+    async fn broadcast_room_queued_transactions() {
+        let state = new_arc_state().await;
+        let file_id = Uuid::new_v4();
+        add_new_user_to_room(file_id, state.clone(), 0).await;
+
+        // the files service restores the file while the room is open
+        state
+            .push(Uuid::new_v4(), file_id, vec![1, 2, 3], 1)
+            .await
+            .unwrap();
+        assert_eq!(state.get_sequence_num(&file_id).await.unwrap(), 0);
+
+        let queued = super::broadcast_room_queued_transactions(state.clone(), &file_id).await;
+        assert_eq!(queued.unwrap(), 1);
+        assert_eq!(state.get_sequence_num(&file_id).await.unwrap(), 1);
+
+        // the room is caught up
+        let queued = super::broadcast_room_queued_transactions(state.clone(), &file_id).await;
+        assert_eq!(queued.unwrap(), 0);
+    }
 }
@@ -46,7 +46,7 @@ fn validate_jwt_file_id(pre_connection: &PreConnection, requested_file_id: Uuid)
 
 use crate::error::{MpError, Result};
 use crate::get_mut_room;
-use crate::message::{broadcast, send_user_message};
+use crate::message::{broadcast, broadcast_queued_transactions, send_user_message};
 use crate::permissions::{
     validate_can_edit_or_view_file, validate_user_can_edit_file,
     validate_user_can_edit_or_view_file,
@@ -54,6 +54,39 @@ use crate::permissions::{
 use crate::state::user::UserSocket;
 use crate::state::{State, pubsub::GROUP_NAME, user::User};
 
+/// Add a transaction to the transaction queue at the room's next sequence
+/// number.  If a transaction that was added without multiplayer (e.g. a
+/// restore from the files service) took the sequence number, the room
+/// catches up to the queue and the transaction is added after it.
+async fn push_transaction(
+    state: &Arc<State>,
+    id: Uuid,
+    file_id: Uuid,
+    operations: Vec<u8>,
+) -> Result<u64> {
+    // get and increment the room's sequence_num
+    let room_sequence_num = get_mut_room!(state, file_id)?.increment_sequence_num();
+
+    match state
+        .push(id, file_id, operations.to_owned(), room_sequence_num)
+        .await
+    {
+        Ok(sequence_num) => Ok(sequence_num),
+        Err(error) => {
+            let queued =
+                broadcast_queued_transactions(Arc::clone(state), file_id, room_sequence_num)
+                    .await?;
+
+            if queued == 0 {
+                return Err(error);
+            }
+
+            let room_sequence_num = get_mut_room!(state, file_id)?.increment_sequence_num();
+            state.push(id, file_id, operations, room_sequence_num).await
+        }
+    }
+}
+
 /// Handle incoming messages.  All requests and responses are strictly typed.
 #[tracing::instrument(level = "trace")]
 pub(crate) async fn handle_message(
@@ -221,8 +254,6 @@ pub(crate) async fn handle_message(
                 &operations
             );
 
-            // get and increment the room's sequence_num
-            let room_sequence_num = get_mut_room!(state, file_id)?.increment_sequence_num();
             let decoded_operations = STANDARD.decode(&operations).map_err(|e| {
                 MpError::Serialization(format!(
                     "Could not decode base64 encoded operations in transaction {id}: {e:?}"
@@ -230,9 +261,7 @@ pub(crate) async fn handle_message(
             })?;
 
             // add the transaction to the transaction queue
-            let sequence_num = state
-                .push(id, file_id, decoded_operations, room_sequence_num)
-                .await?;
+            let sequence_num = push_transaction(&state, id, file_id, decoded_operations).await?;
 
             // broadcast the transaction to all users in the room (except the initiator)
             let response = MessageResponse::Transaction {
@@ -272,15 +301,10 @@ pub(crate) async fn handle_message(
                 &operations
             );
 
-            // get and increment the room's sequence_num
-            let room_sequence_num = get_mut_room!(state, file_id)?.increment_sequence_num();
-
             // add the transaction to the transaction queue
             // we need to clone operations since we broadcast it later
             let start_push_pubsub = std::time::Instant::now();
-            let sequence_num = state
-                .push(id, file_id, operations.to_owned(), room_sequence_num)
-                .await?;
+            let sequence_num = push_transaction(&state, id, file_id, operations.to_owned()).await?;
             tracing::trace!("Pushed to pubsub in {:?}", start_push_pubsub.elapsed());
 
             // broadcast the transaction to all users in the room (except the initiator)
@@ -734,6 +758,60 @@ pub(crate) mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn handle_transaction_after_restore() {
+        let (socket, state, _, file_id, user_1, _) = setup().await;
+        let operations = CoreTransaction::serialize_and_compress(&vec![Operation::SetSheetColor {
+            sheet_id: SheetId::new(),
+            color: Some("red".to_string()),
+        }])
+        .unwrap();
+
+        // the files service restores the file while the room is open, taking
+        // the room's next sequence number
+        let restore_id = Uuid::new_v4();
+        state
+            .push(restore_id, file_id, operations.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(state.get_sequence_num(&file_id).await.unwrap(), 0);
+
+        // the room catches up to the restore and adds the transaction after it
+        let id = Uuid::new_v4();
+        let request = MessageRequest::BinaryTransaction {
+            id,
+            file_id,
+            session_id: user_1.session_id,
+            operations: operations.clone(),
+        };
+        let response = MessageResponse::TransactionAck {
+            id,
+            file_id,
+            sequence_num: 2,
+        };
+
+        test_handle(
+            socket,
+            state.clone(),
+            file_id,
+            user_1,
+            request,
+            Some(response),
+            None,
+        )
+        .await;
+
+        let transactions = state.get_messages_from_pubsub(&file_id, 1).await.unwrap();
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|transaction| (transaction.id, transaction.sequence_num))
+                .collect::<Vec<_>>(),
+            vec![(restore_id, 1), (id, 2)]
+        );
+        assert_eq!(state.get_sequence_num(&file_id).await.unwrap(), 2);
+    }
+
     #[tokio::test]
     async fn handle_missing_transactions() {
         let (socket, state, _, file_id, user_1, _) = setup().await;
@@ -5,9 +5,10 @@ use std::sync::Arc;
 use tokio::task::JoinHandle;
 use uuid::Uuid;
 
-use crate::error::MpError;
+use crate::error::{MpError, Result};
+use crate::get_mut_room;
 use crate::state::State;
-use quadratic_rust_shared::multiplayer::message::response::MessageResponse;
+use quadratic_rust_shared::multiplayer::message::response::{BinaryTransaction, MessageResponse};
 
 pub mod handle;
 pub mod proto;
@@ -84,6 +85,52 @@ pub(crate) fn broadcast(
     })
 }
 
+/// Catch a room up to the transactions that were added to its transaction
+/// queue without multiplayer (e.g. a restore from the files service), from
+/// a sequence number on, and broadcast them to everyone in the room.
+/// Returns the number of transactions.
+pub(crate) async fn broadcast_queued_transactions(
+    state: Arc<State>,
+    file_id: Uuid,
+    min_sequence_num: u64,
+) -> Result<usize> {
+    let transactions = state
+        .get_messages_from_pubsub(&file_id, min_sequence_num)
+        .await?;
+
+    let Some(last) = transactions.last() else {
+        return Ok(0);
+    };
+
+    get_mut_room!(state, file_id)?.catch_up_sequence_num(last.sequence_num);
+
+    tracing::info!(
+        "Room {file_id} caught up to sequence number {} from the transaction queue",
+        last.sequence_num
+    );
+
+    let count = transactions.len();
+    let transactions = transactions
+        .into_iter()
+        .map(|transaction| BinaryTransaction {
+            id: transaction.id,
+            file_id: transaction.file_id,
+            sequence_num: transaction.sequence_num,
+            operations: transaction.operations,
+        })
+        .collect();
+
+    // one message keeps the transactions in order
+    broadcast(
+        vec![],
+        file_id,
+        state,
+        MessageResponse::BinaryTransactions { transactions },
+    );
+
+    Ok(count)
+}
+
 /// Send a message to a specific user in a room.
 /// All messages are sent in a separate thread.
 #[tracing::instrument(level = "trace")]
@@ -45,6 +45,12 @@ impl Room {
         self.sequence_num
     }
 
+    /// Catch up to a transaction that was added to the transaction queue
+    /// without multiplayer (e.g. a restore from the files service)
+    pub fn catch_up_sequence_num(&mut self, sequence_num: u64) {
+        self.sequence_num = self.sequence_num.max(sequence_num);
+    }
+
     pub fn get_user(&self, session_id: &Uuid) -> Result<User> {
         let user = self
             .users
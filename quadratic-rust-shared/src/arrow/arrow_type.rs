@@ -100,7 +100,13 @@ impl ArrowType {
     /// Convert a vector of ArrowType to an Arrow ArrayRef
     pub fn to_array_ref(values: Vec<ArrowType>) -> ArrayRef {
         let data_type = ArrowType::first_data_type(&values);
+        ArrowType::to_array_ref_as(values, &data_type)
+    }
 
+    /// Convert a vector of ArrowType to an Arrow ArrayRef of the array type
+    /// for `data_type` (as returned by `data_type()`).  Values of other types
+    /// are converted to nulls.
+    pub fn to_array_ref_as(values: Vec<ArrowType>, data_type: &DataType) -> ArrayRef {
         match data_type {
             DataType::Int8 => {
                 vec_arrow_type_to_array_ref!(ArrowType::Int8, Int8Array, values)
@@ -127,7 +133,7 @@ impl ArrowType {
                 vec_arrow_type_to_array_ref!(ArrowType::UInt64, UInt64Array, values)
             }
             DataType::Decimal256(_precision, _scale) => {
-                let converted = values.iter().filter_map(|value| match value {
+                let converted = values.iter().map(|value| match value {
                     ArrowType::Decimal(value) => value.to_f64(),
                     _ => None,
                 });
@@ -168,12 +174,12 @@ impl ArrowType {
                 vec_arrow_type_to_array_ref!(ArrowType::Date64, Date64Array, values)
             }
             DataType::Time32(TimeUnit::Second) | DataType::Time64(TimeUnit::Microsecond) => {
-                let converted = values.iter().flat_map(|value| match value {
+                let converted = values.iter().map(|value| match value {
                     ArrowType::Time32(value) => Some(value.num_seconds_from_midnight() as i32),
                     _ => None,
                 });
 
-                Arc::new(Time32SecondArray::from_iter_values(converted)) as ArrayRef
+                Arc::new(Time32SecondArray::from_iter(converted)) as ArrayRef
             }
             DataType::Timestamp(TimeUnit::Millisecond, None) => {
                 vec_time_arrow_type_to_array_ref!(values)
@@ -3,4 +3,5 @@
 pub mod arrow_type;
 pub mod error;
 pub mod object_store;
+pub mod schema;
 pub mod utils;
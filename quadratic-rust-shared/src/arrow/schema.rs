@@ -0,0 +1,228 @@
+//! Schema evolution for Arrow.
+//!
+//! Files written at different times can have different schemas, e.g. when a
+//! source adds a field or a field's inferred type changes.  These functions
+//! unify the schemas so that the files can be read as a single table.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, new_null_array};
+use arrow::compute::{CastOptions, cast_with_options};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+
+use crate::error::Result;
+
+/// The type of a column that has both UInt64 and signed integers, which
+/// neither Int64 nor UInt64 can hold.
+const WIDE_INTEGER: DataType = DataType::Decimal128(20, 0);
+
+/// Get the type that both types can be cast to.  Unsigned integers widen to
+/// UInt64 and other integers to Int64, unless one is a UInt64, which widens
+/// to a Decimal128 that holds any 64-bit integer.  Mixed numbers widen to
+/// Float64 and any other conflict widens to Utf8.
+pub fn widen_data_type(left: &DataType, right: &DataType) -> DataType {
+    let is_integer = |data_type: &DataType| data_type.is_integer() || *data_type == WIDE_INTEGER;
+    let is_wide =
+        |data_type: &DataType| matches!(data_type, DataType::UInt64) || *data_type == WIDE_INTEGER;
+
+    match (left, right) {
+        (left, right) if left == right => left.clone(),
+        (DataType::Null, other) | (other, DataType::Null) => other.clone(),
+        (left, right) if left.is_unsigned_integer() && right.is_unsigned_integer() => {
+            DataType::UInt64
+        }
+        (left, right)
+            if is_integer(left) && is_integer(right) && (is_wide(left) || is_wide(right)) =>
+        {
+            WIDE_INTEGER
+        }
+        (left, right) if left.is_integer() && right.is_integer() => DataType::Int64,
+        (left, right) if left.is_numeric() && right.is_numeric() => DataType::Float64,
+        _ => DataType::Utf8,
+    }
+}
+
+/// Unify schemas into a schema with every field.  Fields are ordered by
+/// their first appearance and are nullable, since they can be missing from
+/// some of the schemas.
+pub fn unify_schemas(schemas: &[SchemaRef]) -> Schema {
+    let mut fields: Vec<Field> = vec![];
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+
+    for field in schemas.iter().flat_map(|schema| schema.fields().iter()) {
+        match indexes.get(field.name()) {
+            Some(index) => {
+                let data_type = widen_data_type(fields[*index].data_type(), field.data_type());
+                fields[*index] = Field::new(field.name(), data_type, true);
+            }
+            None => {
+                indexes.insert(field.name().to_owned(), fields.len());
+                fields.push(Field::new(field.name(), field.data_type().clone(), true));
+            }
+        }
+    }
+
+    Schema::new(fields)
+}
+
+/// Conform a record batch to a schema: columns are cast to the schema's
+/// types, and columns that are missing from the batch are null.  A value
+/// that doesn't fit the schema's type is an error rather than a null.
+pub fn conform_record_batch(batch: &RecordBatch, schema: SchemaRef) -> Result<RecordBatch> {
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| -> Result<ArrayRef> {
+            match batch.column_by_name(field.name()) {
+                Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+                Some(column) => Ok(cast_with_options(column, field.data_type(), &options)?),
+                None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// Conform record batches to their unified schema
+pub fn unify_record_batches(batches: &[RecordBatch]) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    let schemas = batches.iter().map(RecordBatch::schema).collect::<Vec<_>>();
+    let schema = Arc::new(unify_schemas(&schemas));
+    let batches = batches
+        .iter()
+        .map(|batch| conform_record_batch(batch, schema.clone()))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((schema, batches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Decimal128Type, Float64Type};
+    use arrow_array::{
+        BooleanArray, Float64Array, Int32Array, Int64Array, StringArray, UInt64Array,
+    };
+
+    #[test]
+    fn test_widen_data_type() {
+        let widen = |left: DataType, right: DataType| widen_data_type(&left, &right);
+
+        assert_eq!(widen(DataType::Utf8, DataType::Utf8), DataType::Utf8);
+        assert_eq!(widen(DataType::Null, DataType::Boolean), DataType::Boolean);
+        assert_eq!(widen(DataType::Int32, DataType::Int64), DataType::Int64);
+        assert_eq!(widen(DataType::UInt32, DataType::Int64), DataType::Int64);
+        assert_eq!(widen(DataType::UInt8, DataType::UInt64), DataType::UInt64);
+        assert_eq!(widen(DataType::UInt64, DataType::Int8), WIDE_INTEGER);
+        assert_eq!(widen(WIDE_INTEGER, DataType::Int64), WIDE_INTEGER);
+        assert_eq!(widen(DataType::Int64, DataType::Float64), DataType::Float64);
+        assert_eq!(widen(DataType::Float64, DataType::Boolean), DataType::Utf8);
+        assert_eq!(widen(DataType::Utf8View, DataType::Float64), DataType::Utf8);
+    }
+
+    #[test]
+    fn test_unify_schemas() {
+        let old = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Float64, true),
+            Field::new("amount", DataType::Float64, true),
+        ]));
+        let new = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Float64, true),
+            Field::new("amount", DataType::Utf8, true),
+            Field::new("currency", DataType::Utf8, false),
+        ]));
+
+        let schema = unify_schemas(&[old, new]);
+
+        assert_eq!(
+            schema,
+            Schema::new(vec![
+                Field::new("id", DataType::Float64, true),
+                Field::new("amount", DataType::Utf8, true),
+                Field::new("currency", DataType::Utf8, true),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unify_record_batches() {
+        let old = RecordBatch::try_from_iter(vec![
+            ("id", Arc::new(Int32Array::from(vec![1, 2])) as ArrayRef),
+            (
+                "paid",
+                Arc::new(BooleanArray::from(vec![true, false])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        let new = RecordBatch::try_from_iter(vec![
+            ("id", Arc::new(Float64Array::from(vec![3.5])) as ArrayRef),
+            ("paid", Arc::new(StringArray::from(vec!["yes"])) as ArrayRef),
+            (
+                "note",
+                Arc::new(StringArray::from(vec!["added"])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+
+        let (schema, batches) = unify_record_batches(&[old, new]).unwrap();
+
+        assert_eq!(schema.field(0).data_type(), &DataType::Float64);
+        assert_eq!(schema.field(1).data_type(), &DataType::Utf8);
+        assert_eq!(schema.field(2).data_type(), &DataType::Utf8);
+
+        let old = &batches[0];
+        assert_eq!(
+            old.column(0)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec(),
+            vec![1.0, 2.0]
+        );
+        assert_eq!(old.column(1).as_string::<i32>().value(0), "true");
+        assert_eq!(old.column(2).null_count(), 2);
+        assert_eq!(batches[1].column(2).as_string::<i32>().value(0), "added");
+    }
+
+    #[test]
+    fn test_unify_record_batches_with_unsigned_integers() {
+        let old = RecordBatch::try_from_iter(vec![(
+            "id",
+            Arc::new(UInt64Array::from(vec![u64::MAX])) as ArrayRef,
+        )])
+        .unwrap();
+        let new = RecordBatch::try_from_iter(vec![(
+            "id",
+            Arc::new(Int64Array::from(vec![-1])) as ArrayRef,
+        )])
+        .unwrap();
+
+        let (schema, batches) = unify_record_batches(&[old, new]).unwrap();
+
+        assert_eq!(schema.field(0).data_type(), &WIDE_INTEGER);
+
+        let values = batches
+            .iter()
+            .map(|batch| batch.column(0).as_primitive::<Decimal128Type>().value(0))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![u64::MAX as i128, -1]);
+    }
+
+    #[test]
+    fn test_conform_record_batch_errors_on_overflow() {
+        let batch = RecordBatch::try_from_iter(vec![(
+            "id",
+            Arc::new(UInt64Array::from(vec![u64::MAX])) as ArrayRef,
+        )])
+        .unwrap();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, true)]));
+
+        assert!(conform_record_batch(&batch, schema).is_err());
+    }
+}
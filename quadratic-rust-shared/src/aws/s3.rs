@@ -4,7 +4,9 @@
 
 use aws_sdk_s3::{
     Client,
-    operation::{get_object::GetObjectOutput, put_object::PutObjectOutput},
+    operation::{
+        delete_object::DeleteObjectOutput, get_object::GetObjectOutput, put_object::PutObjectOutput,
+    },
     primitives::{ByteStream, SdkBody},
 };
 
@@ -49,6 +51,21 @@ pub async fn upload_object(
         })
 }
 
+/// Delete an object from S3
+pub async fn delete_object(client: &Client, bucket: &str, key: &str) -> Result<DeleteObjectOutput> {
+    client
+        .delete_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(|error| {
+            SharedError::Aws(AwsError::S3(format!(
+                "Error deleting file {key} from bucket {bucket}: {error:?}."
+            )))
+        })
+}
+
 #[cfg(test)]
 pub mod tests {
     // use aws_config::{imds::Client as ImdsClient, provider_config::ProviderConfig};
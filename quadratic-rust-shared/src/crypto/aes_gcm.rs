@@ -0,0 +1,122 @@
+//! AES GCM Encryption and Decryption
+//!
+//! Functions to encrypt and decrypt data using AES-256-GCM, which
+//! authenticates the data (and any associated data) in addition to
+//! encrypting it.  A random nonce is generated for each encryption and is
+//! prepended to the encrypted data.
+
+use std::fmt::Debug;
+
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, Payload},
+};
+use bytes::Bytes;
+use rand_core::{OsRng, RngCore};
+
+use crate::{SharedError, crypto::error::Crypto as CryptoError, error::Result};
+
+pub const KEY_LENGTH: usize = 32;
+pub const NONCE_LENGTH: usize = 12;
+pub const TAG_LENGTH: usize = 16;
+
+/// Generate a random AES-256 key.
+pub fn generate_key() -> [u8; KEY_LENGTH] {
+    let mut key = [0u8; KEY_LENGTH];
+    OsRng.fill_bytes(&mut key);
+
+    key
+}
+
+/// Encrypt data using AES-256-GCM, authenticating `aad` along with the data.
+/// Returns the nonce followed by the encrypted data and tag.
+pub fn encrypt(key: &[u8; KEY_LENGTH], data: &[u8], aad: &[u8]) -> Result<Bytes> {
+    let cipher = Aes256Gcm::new(key.into());
+
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+
+    let encrypted = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: data, aad })
+        .map_err(encrypt_error)?;
+
+    let mut output = Vec::with_capacity(NONCE_LENGTH + encrypted.len());
+    output.extend_from_slice(&nonce);
+    output.extend_from_slice(&encrypted);
+
+    Ok(output.into())
+}
+
+/// Convenience function to handle errors when encrypting data.
+fn encrypt_error(e: impl Debug) -> SharedError {
+    let error = CryptoError::AesGcmEncode(format!("Error encoding data: {e:?}"));
+    SharedError::Crypto(error)
+}
+
+/// Decrypt data encrypted with `encrypt`, failing if the data or `aad` have
+/// been changed.
+pub fn decrypt(key: &[u8; KEY_LENGTH], data: &[u8], aad: &[u8]) -> Result<Bytes> {
+    if data.len() < NONCE_LENGTH + TAG_LENGTH {
+        return Err(decrypt_error("data is too short"));
+    }
+
+    let cipher = Aes256Gcm::new(key.into());
+    let (nonce, encrypted) = data.split_at(NONCE_LENGTH);
+    let decrypted = cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: encrypted,
+                aad,
+            },
+        )
+        .map_err(decrypt_error)?;
+
+    Ok(decrypted.into())
+}
+
+/// Convenience function to handle errors when decrypting data.
+fn decrypt_error(e: impl Debug) -> SharedError {
+    let error = CryptoError::AesGcmDecode(format!("Error decoding data: {e:?}"));
+    SharedError::Crypto(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_and_decrypt_aes_gcm() {
+        let key = [0x42; KEY_LENGTH];
+        let text = b"Hello, world!";
+
+        let encrypted = encrypt(&key, text, b"aad").unwrap();
+        assert_eq!(encrypted.len(), NONCE_LENGTH + text.len() + TAG_LENGTH);
+
+        let decrypted = decrypt(&key, &encrypted, b"aad").unwrap();
+        assert_eq!(text, decrypted.as_ref());
+
+        // a new nonce is used for each encryption
+        assert_ne!(encrypted, encrypt(&key, text, b"aad").unwrap());
+    }
+
+    #[test]
+    fn decrypt_aes_gcm_fails_when_changed() {
+        let key = [0x42; KEY_LENGTH];
+        let encrypted = encrypt(&key, b"Hello, world!", b"aad").unwrap();
+
+        // wrong key
+        assert!(decrypt(&generate_key(), &encrypted, b"aad").is_err());
+
+        // wrong associated data
+        assert!(decrypt(&key, &encrypted, b"other").is_err());
+
+        // tampered data
+        let mut tampered = encrypted.to_vec();
+        tampered[NONCE_LENGTH] ^= 1;
+        assert!(decrypt(&key, &tampered, b"aad").is_err());
+
+        // truncated data
+        assert!(decrypt(&key, &encrypted[..NONCE_LENGTH], b"aad").is_err());
+    }
+}
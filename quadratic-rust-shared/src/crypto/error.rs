@@ -13,4 +13,10 @@ pub enum Crypto {
 
     #[error("Error encoding {0}")]
     AesCbcEncode(String),
+
+    #[error("Error decoding {0}")]
+    AesGcmDecode(String),
+
+    #[error("Error encoding {0}")]
+    AesGcmEncode(String),
 }
@@ -1,4 +1,5 @@
 //! Crytpographic functions
 
 pub mod aes_cbc;
+pub mod aes_gcm;
 pub mod error;
@@ -2,10 +2,12 @@
 //!
 //! Functions to interact with Parquet files
 
+use arrow::compute::concat_batches;
 use arrow::datatypes::{DataType, TimeUnit};
 use arrow::record_batch::RecordBatch;
 use bytes::Bytes;
 use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 use parquet::basic::Compression;
 use parquet::file::properties::WriterProperties;
 use parquet::file::reader::{ChunkReader, FileReader, SerializedFileReader};
@@ -95,6 +97,17 @@ pub fn record_batch_to_parquet_bytes(record_batch: RecordBatch) -> Result<Bytes>
     Ok(arrow_writer.into_inner()?.into())
 }
 
+/// Read parquet bytes into a single record batch
+pub fn parquet_bytes_to_record_batch(bytes: Bytes) -> Result<RecordBatch> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+    let schema = builder.schema().clone();
+    let batches = builder
+        .build()?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok(concat_batches(&schema, &batches)?)
+}
+
 /// Write a record batch to a writer using Arrow with direct Field to DataType conversion
 pub fn write_record_batch<W: Write + Send>(
     record_batch: RecordBatch,
@@ -119,7 +132,6 @@ mod tests {
         TimestampMillisecondArray,
     };
     use bytes::Bytes;
-    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
     use parquet::data_type::{ByteArray, Decimal};
     use parquet::record::{Field, RowAccessor};
     use std::fs::File;
@@ -235,6 +247,23 @@ mod tests {
         assert_eq!(batch.num_rows(), 3);
     }
 
+    #[test]
+    fn test_parquet_bytes_to_record_batch() {
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "id",
+            DataType::Int64,
+            false,
+        )]));
+        let id_array = Arc::new(Int64Array::from(vec![1, 2, 3]));
+        let record_batch = RecordBatch::try_new(schema, vec![id_array]).unwrap();
+        let parquet_bytes = record_batch_to_parquet_bytes(record_batch.clone()).unwrap();
+
+        assert_eq!(
+            parquet_bytes_to_record_batch(parquet_bytes).unwrap(),
+            record_batch
+        );
+    }
+
     #[test]
     fn test_write_record_batch() {
         let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use sqlx::{PgPool, query_scalar};
 use uuid::Uuid;
 
@@ -47,6 +48,82 @@ pub async fn get_max_sequence_number(pool: &PgPool, file_id: &Uuid) -> Result<i3
     })
 }
 
+/// Get the sequence number of the latest checkpoint at or before a sequence number
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+/// * `sequence_number` - The sequence number to look up
+///
+/// # Returns
+///
+pub async fn get_sequence_number_at_or_before(
+    pool: &PgPool,
+    file_id: &Uuid,
+    sequence_number: i32,
+) -> Result<i32> {
+    let query = "
+        SELECT MAX(\"sequence_number\")
+        FROM \"FileCheckpoint\" fc
+        INNER JOIN \"File\" f ON fc.\"file_id\" = f.\"id\"
+        WHERE f.\"uuid\" = $1::text AND fc.\"sequence_number\" <= $2";
+
+    let result: Option<i32> = query_scalar(query)
+        .bind(file_id)
+        .bind(sequence_number)
+        .fetch_one(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    result.ok_or_else(|| {
+        QuadraticDatabase::NotFound(format!(
+            "No checkpoint found for file {} at or before sequence number {}",
+            file_id, sequence_number
+        ))
+        .into()
+    })
+}
+
+/// Get the sequence number of the latest checkpoint written at or before a time
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+/// * `timestamp` - The time to look up (UTC)
+///
+/// # Returns
+///
+pub async fn get_sequence_number_at_time(
+    pool: &PgPool,
+    file_id: &Uuid,
+    timestamp: NaiveDateTime,
+) -> Result<i32> {
+    let query = "
+        SELECT fc.\"sequence_number\"
+        FROM \"FileCheckpoint\" fc
+        INNER JOIN \"File\" f ON fc.\"file_id\" = f.\"id\"
+        WHERE f.\"uuid\" = $1::text AND fc.\"timestamp\" <= $2
+        ORDER BY fc.\"sequence_number\" DESC
+        LIMIT 1";
+
+    let result: Option<i32> = query_scalar(query)
+        .bind(file_id)
+        .bind(timestamp)
+        .fetch_optional(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    result.ok_or_else(|| {
+        QuadraticDatabase::NotFound(format!(
+            "No checkpoint found for file {} at or before {}",
+            file_id, timestamp
+        ))
+        .into()
+    })
+}
+
 /// Get the transactions hash for a specific checkpoint
 ///
 /// # Arguments
@@ -113,7 +190,7 @@ pub async fn set_file_checkpoint(
     // Note: Not using a transaction here for better connection pool utilization.
     // There's a small TOCTOU race window between insert and conflict check, but:
     // - Same file's checkpoints are processed sequentially via pubsub
-    // - Checkpoints aren't deleted during normal operation
+    // - Only expired checkpoints are deleted, never the latest one
     // - Worst case is a false conflict that self-corrects on retry
     let insert_query = "
         INSERT INTO \"FileCheckpoint\" (file_id, sequence_number, s3_bucket, s3_key, version, transactions_hash)
@@ -175,6 +252,115 @@ pub async fn set_file_checkpoint(
     }
 }
 
+/// A checkpoint of a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCheckpoint {
+    pub sequence_number: i32,
+    pub s3_bucket: String,
+    pub s3_key: String,
+    pub timestamp: NaiveDateTime,
+}
+
+type FileCheckpointRow = (i32, String, String, NaiveDateTime);
+
+impl From<FileCheckpointRow> for FileCheckpoint {
+    fn from((sequence_number, s3_bucket, s3_key, timestamp): FileCheckpointRow) -> Self {
+        Self {
+            sequence_number,
+            s3_bucket,
+            s3_key,
+            timestamp,
+        }
+    }
+}
+
+/// Get the latest checkpoint of a file
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+///
+/// # Returns
+///
+/// * `Err(NotFound)` - The file has no checkpoints
+pub async fn get_latest_checkpoint(pool: &PgPool, file_id: &Uuid) -> Result<FileCheckpoint> {
+    let query = "
+        SELECT fc.\"sequence_number\", fc.\"s3_bucket\", fc.\"s3_key\", fc.\"timestamp\"
+        FROM \"FileCheckpoint\" fc
+        INNER JOIN \"File\" f ON fc.\"file_id\" = f.\"id\"
+        WHERE f.\"uuid\" = $1::text
+        ORDER BY fc.\"sequence_number\" DESC
+        LIMIT 1";
+
+    let result = sqlx::query_as::<_, FileCheckpointRow>(query)
+        .bind(file_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    result.map(FileCheckpoint::from).ok_or_else(|| {
+        QuadraticDatabase::NotFound(format!("No checkpoint found for file {}", file_id)).into()
+    })
+}
+
+/// Get the checkpoints of a file, ordered by sequence number
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+///
+/// # Returns
+///
+pub async fn get_file_checkpoints(pool: &PgPool, file_id: &Uuid) -> Result<Vec<FileCheckpoint>> {
+    let query = "
+        SELECT fc.\"sequence_number\", fc.\"s3_bucket\", fc.\"s3_key\", fc.\"timestamp\"
+        FROM \"FileCheckpoint\" fc
+        INNER JOIN \"File\" f ON fc.\"file_id\" = f.\"id\"
+        WHERE f.\"uuid\" = $1::text
+        ORDER BY fc.\"sequence_number\" ASC";
+
+    let result = sqlx::query_as::<_, FileCheckpointRow>(query)
+        .bind(file_id)
+        .fetch_all(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    Ok(result.into_iter().map(FileCheckpoint::from).collect())
+}
+
+/// Delete checkpoints of a file.  The checkpoint's files are not deleted.
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+/// * `sequence_numbers` - The sequence numbers of the checkpoints to delete
+///
+/// # Returns
+///
+/// * `Ok(count)` - The number of deleted checkpoints
+pub async fn delete_file_checkpoints(
+    pool: &PgPool,
+    file_id: &Uuid,
+    sequence_numbers: &[i32],
+) -> Result<u64> {
+    let query = "
+        DELETE FROM \"FileCheckpoint\" fc
+        USING \"File\" f
+        WHERE fc.\"file_id\" = f.\"id\" AND f.\"uuid\" = $1::text AND fc.\"sequence_number\" = ANY($2)";
+
+    let result = sqlx::query(query)
+        .bind(file_id)
+        .bind(sequence_numbers)
+        .execute(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    Ok(result.rows_affected())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{SharedError, quadratic_database::connect_test};
@@ -334,6 +520,91 @@ mod tests {
             SharedError::QuadraticDatabase(QuadraticDatabase::Conflict(_))
         ));
 
+        test_teardown(&pool, file_id, team_id, user_id).await;
+    }
+    #[tokio::test]
+    async fn test_sequence_number_lookups() {
+        let (pool, file_uuid, file_id, team_id, user_id) = test_setup().await;
+
+        for (seq_num, hash) in [(10, "hash-10"), (20, "hash-20")] {
+            set_file_checkpoint(&pool, &file_uuid, seq_num, "test-bucket", "1.0.0", hash)
+                .await
+                .unwrap();
+        }
+
+        // the nearest checkpoint at or before a sequence number
+        let seq_num = get_sequence_number_at_or_before(&pool, &file_uuid, 15)
+            .await
+            .unwrap();
+        assert_eq!(seq_num, 10);
+
+        let seq_num = get_sequence_number_at_or_before(&pool, &file_uuid, 20)
+            .await
+            .unwrap();
+        assert_eq!(seq_num, 20);
+
+        let not_found = get_sequence_number_at_or_before(&pool, &file_uuid, 5)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            not_found,
+            SharedError::QuadraticDatabase(QuadraticDatabase::NotFound(_))
+        ));
+
+        // the latest checkpoint written at or before a time
+        let now = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(1);
+        let seq_num = get_sequence_number_at_time(&pool, &file_uuid, now)
+            .await
+            .unwrap();
+        assert_eq!(seq_num, 20);
+
+        let long_ago = now - chrono::Duration::days(1);
+        let not_found = get_sequence_number_at_time(&pool, &file_uuid, long_ago)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            not_found,
+            SharedError::QuadraticDatabase(QuadraticDatabase::NotFound(_))
+        ));
+
+        test_teardown(&pool, file_id, team_id, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_file_checkpoints() {
+        let (pool, file_uuid, file_id, team_id, user_id) = test_setup().await;
+
+        let not_found = get_latest_checkpoint(&pool, &file_uuid).await.unwrap_err();
+        assert!(matches!(
+            not_found,
+            SharedError::QuadraticDatabase(QuadraticDatabase::NotFound(_))
+        ));
+
+        for seq_num in [10, 20, 30] {
+            set_file_checkpoint(&pool, &file_uuid, seq_num, "test-bucket", "1.0.0", "hash")
+                .await
+                .unwrap();
+        }
+
+        let latest = get_latest_checkpoint(&pool, &file_uuid).await.unwrap();
+        assert_eq!(latest.sequence_number, 30);
+        assert_eq!(latest.s3_key, format!("{file_uuid}-30.grid"));
+
+        let checkpoints = get_file_checkpoints(&pool, &file_uuid).await.unwrap();
+        let sequence_numbers = checkpoints
+            .iter()
+            .map(|checkpoint| checkpoint.sequence_number)
+            .collect::<Vec<_>>();
+        assert_eq!(sequence_numbers, vec![10, 20, 30]);
+
+        let deleted = delete_file_checkpoints(&pool, &file_uuid, &[10, 20, 40])
+            .await
+            .unwrap();
+        assert_eq!(deleted, 2);
+
+        let checkpoints = get_file_checkpoints(&pool, &file_uuid).await.unwrap();
+        assert_eq!(checkpoints, vec![latest]);
+
         test_teardown(&pool, file_id, team_id, user_id).await;
     }
 }
@@ -1,5 +1,6 @@
 pub mod checkpoint;
 pub mod error;
+pub mod webhook;
 
 // re-exports
 pub use sqlx::PgPool;
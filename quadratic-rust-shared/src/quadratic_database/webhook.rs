@@ -0,0 +1,526 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sqlx::{PgPool, Row, postgres::PgRow};
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::quadratic_database::error::QuadraticDatabase;
+
+/*
+-- Table Definition
+CREATE TABLE "public"."FileWebhook" (
+    "id" SERIAL NOT NULL,
+    "uuid" TEXT NOT NULL,
+    "file_id" INTEGER NOT NULL,
+    "url" TEXT NOT NULL,
+    "secret" TEXT NOT NULL,
+    "sheet_id" TEXT,
+    "range" TEXT,
+    "include_values" BOOLEAN NOT NULL DEFAULT false,
+    "deleted" BOOLEAN NOT NULL DEFAULT false,
+    "created_date" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "FileWebhook_file_id_fkey" FOREIGN KEY ("file_id") REFERENCES "public"."File"("id") ON DELETE RESTRICT ON UPDATE CASCADE,
+    PRIMARY KEY ("id")
+);
+
+CREATE TABLE "public"."FileWebhookDelivery" (
+    "id" SERIAL NOT NULL,
+    "uuid" TEXT NOT NULL,
+    "file_webhook_id" INTEGER NOT NULL,
+    "first_sequence_number" INTEGER NOT NULL,
+    "last_sequence_number" INTEGER NOT NULL,
+    "status" "FileWebhookDeliveryStatus" NOT NULL DEFAULT 'PENDING',
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "response_status" INTEGER,
+    "error" TEXT,
+    "created_date" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_date" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "FileWebhookDelivery_file_webhook_id_fkey" FOREIGN KEY ("file_webhook_id") REFERENCES "public"."FileWebhook"("id") ON DELETE RESTRICT ON UPDATE CASCADE,
+    PRIMARY KEY ("id")
+);
+*/
+
+const FILE_WEBHOOK_COLUMNS: &str = "
+    fw.\"uuid\", f.\"uuid\" AS file_uuid, fw.\"url\", fw.\"secret\", fw.\"sheet_id\",
+    fw.\"range\", fw.\"include_values\", fw.\"created_date\"";
+
+const FILE_WEBHOOK_DELIVERY_COLUMNS: &str = "
+    fwd.\"uuid\", fw.\"uuid\" AS webhook_uuid, fwd.\"first_sequence_number\",
+    fwd.\"last_sequence_number\", fwd.\"status\"::text AS status, fwd.\"attempts\",
+    fwd.\"response_status\", fwd.\"error\", fwd.\"created_date\", fwd.\"updated_date\"";
+
+/// A subscription to changes to a file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileWebhook {
+    pub uuid: Uuid,
+    pub file_id: Uuid,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Only deliver changes to this sheet
+    pub sheet_id: Option<String>,
+    /// Only deliver changes to this A1 range (in `sheet_id`)
+    pub range: Option<String>,
+    /// Include the values of changed cells in deliveries
+    pub include_values: bool,
+    pub created_date: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Display, EnumString)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum FileWebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// A delivery of changes to a webhook
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileWebhookDelivery {
+    pub uuid: Uuid,
+    pub webhook_id: Uuid,
+    pub first_sequence_number: i32,
+    pub last_sequence_number: i32,
+    pub status: FileWebhookDeliveryStatus,
+    pub attempts: i32,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created_date: NaiveDateTime,
+    pub updated_date: NaiveDateTime,
+}
+
+fn parse_uuid(value: &str) -> Result<Uuid> {
+    Uuid::parse_str(value)
+        .map_err(|e| QuadraticDatabase::Query(format!("Invalid uuid {value}: {e}")).into())
+}
+
+fn file_webhook_from_row(row: PgRow) -> Result<FileWebhook> {
+    Ok(FileWebhook {
+        uuid: parse_uuid(row.try_get("uuid").map_err(QuadraticDatabase::from)?)?,
+        file_id: parse_uuid(row.try_get("file_uuid").map_err(QuadraticDatabase::from)?)?,
+        url: row.try_get("url").map_err(QuadraticDatabase::from)?,
+        secret: row.try_get("secret").map_err(QuadraticDatabase::from)?,
+        sheet_id: row.try_get("sheet_id").map_err(QuadraticDatabase::from)?,
+        range: row.try_get("range").map_err(QuadraticDatabase::from)?,
+        include_values: row
+            .try_get("include_values")
+            .map_err(QuadraticDatabase::from)?,
+        created_date: row
+            .try_get("created_date")
+            .map_err(QuadraticDatabase::from)?,
+    })
+}
+
+fn file_webhook_delivery_from_row(row: PgRow) -> Result<FileWebhookDelivery> {
+    let status: &str = row.try_get("status").map_err(QuadraticDatabase::from)?;
+
+    Ok(FileWebhookDelivery {
+        uuid: parse_uuid(row.try_get("uuid").map_err(QuadraticDatabase::from)?)?,
+        webhook_id: parse_uuid(
+            row.try_get("webhook_uuid")
+                .map_err(QuadraticDatabase::from)?,
+        )?,
+        first_sequence_number: row
+            .try_get("first_sequence_number")
+            .map_err(QuadraticDatabase::from)?,
+        last_sequence_number: row
+            .try_get("last_sequence_number")
+            .map_err(QuadraticDatabase::from)?,
+        status: FileWebhookDeliveryStatus::from_str(status)
+            .map_err(|e| QuadraticDatabase::Query(format!("Invalid status {status}: {e}")))?,
+        attempts: row.try_get("attempts").map_err(QuadraticDatabase::from)?,
+        response_status: row
+            .try_get("response_status")
+            .map_err(QuadraticDatabase::from)?,
+        error: row.try_get("error").map_err(QuadraticDatabase::from)?,
+        created_date: row
+            .try_get("created_date")
+            .map_err(QuadraticDatabase::from)?,
+        updated_date: row
+            .try_get("updated_date")
+            .map_err(QuadraticDatabase::from)?,
+    })
+}
+
+/// Create a webhook for a file
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+/// * `url` - The URL to deliver changes to
+/// * `secret` - The secret used to sign deliveries
+/// * `sheet_id` - Only deliver changes to this sheet
+/// * `range` - Only deliver changes to this A1 range
+/// * `include_values` - Include the values of changed cells in deliveries
+///
+/// # Returns
+///
+/// * `Err(NotFound)` - The file doesn't exist
+pub async fn create_file_webhook(
+    pool: &PgPool,
+    file_id: &Uuid,
+    url: &str,
+    secret: &str,
+    sheet_id: Option<&str>,
+    range: Option<&str>,
+    include_values: bool,
+) -> Result<FileWebhook> {
+    let query = format!(
+        "
+        WITH fw AS (
+            INSERT INTO \"FileWebhook\" (uuid, file_id, url, secret, sheet_id, range, include_values)
+            SELECT $2::text, f.\"id\", $3, $4, $5, $6, $7
+            FROM \"File\" f
+            WHERE f.\"uuid\" = $1::text
+            RETURNING *
+        )
+        SELECT {FILE_WEBHOOK_COLUMNS}
+        FROM fw
+        INNER JOIN \"File\" f ON fw.\"file_id\" = f.\"id\""
+    );
+
+    let row = sqlx::query(&query)
+        .bind(file_id)
+        .bind(Uuid::new_v4())
+        .bind(url)
+        .bind(secret)
+        .bind(sheet_id)
+        .bind(range)
+        .bind(include_values)
+        .fetch_optional(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?
+        .ok_or_else(|| QuadraticDatabase::NotFound(format!("File {file_id} not found")))?;
+
+    file_webhook_from_row(row)
+}
+
+/// Get the webhooks of a file
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+///
+/// # Returns
+///
+pub async fn get_file_webhooks(pool: &PgPool, file_id: &Uuid) -> Result<Vec<FileWebhook>> {
+    let query = format!(
+        "
+        SELECT {FILE_WEBHOOK_COLUMNS}
+        FROM \"FileWebhook\" fw
+        INNER JOIN \"File\" f ON fw.\"file_id\" = f.\"id\"
+        WHERE f.\"uuid\" = $1::text AND fw.\"deleted\" = false
+        ORDER BY fw.\"id\""
+    );
+
+    sqlx::query(&query)
+        .bind(file_id)
+        .fetch_all(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?
+        .into_iter()
+        .map(file_webhook_from_row)
+        .collect()
+}
+
+/// Delete a webhook of a file
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+/// * `webhook_id` - The UUID of the webhook
+///
+/// # Returns
+///
+/// * `Err(NotFound)` - The webhook doesn't exist
+pub async fn delete_file_webhook(pool: &PgPool, file_id: &Uuid, webhook_id: &Uuid) -> Result<()> {
+    let query = "
+        UPDATE \"FileWebhook\" fw
+        SET \"deleted\" = true
+        FROM \"File\" f
+        WHERE fw.\"file_id\" = f.\"id\"
+            AND f.\"uuid\" = $1::text
+            AND fw.\"uuid\" = $2::text
+            AND fw.\"deleted\" = false";
+
+    let result = sqlx::query(query)
+        .bind(file_id)
+        .bind(webhook_id)
+        .execute(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    if result.rows_affected() == 0 {
+        return Err(QuadraticDatabase::NotFound(format!("Webhook {webhook_id} not found")).into());
+    }
+
+    Ok(())
+}
+
+/// Log a pending delivery to a webhook
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `webhook_id` - The UUID of the webhook
+/// * `first_sequence_number` - The first sequence number of the changes
+/// * `last_sequence_number` - The last sequence number of the changes
+///
+/// # Returns
+///
+/// The UUID of the delivery
+pub async fn create_file_webhook_delivery(
+    pool: &PgPool,
+    webhook_id: &Uuid,
+    first_sequence_number: i32,
+    last_sequence_number: i32,
+) -> Result<Uuid> {
+    let query = "
+        INSERT INTO \"FileWebhookDelivery\" (uuid, file_webhook_id, first_sequence_number, last_sequence_number)
+        SELECT $2::text, fw.\"id\", $3, $4
+        FROM \"FileWebhook\" fw
+        WHERE fw.\"uuid\" = $1::text
+        RETURNING uuid";
+
+    let uuid: Option<String> = sqlx::query_scalar(query)
+        .bind(webhook_id)
+        .bind(Uuid::new_v4())
+        .bind(first_sequence_number)
+        .bind(last_sequence_number)
+        .fetch_optional(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    let uuid =
+        uuid.ok_or_else(|| QuadraticDatabase::NotFound(format!("Webhook {webhook_id} not found")))?;
+
+    parse_uuid(&uuid)
+}
+
+/// Record an attempt to deliver to a webhook
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `delivery_id` - The UUID of the delivery
+/// * `status` - The status of the delivery after the attempt
+/// * `attempts` - The number of attempts so far
+/// * `response_status` - The HTTP status of the response, if any
+/// * `error` - The error of the attempt, if any
+///
+/// # Returns
+///
+pub async fn update_file_webhook_delivery(
+    pool: &PgPool,
+    delivery_id: &Uuid,
+    status: FileWebhookDeliveryStatus,
+    attempts: i32,
+    response_status: Option<i32>,
+    error: Option<&str>,
+) -> Result<()> {
+    let query = "
+        UPDATE \"FileWebhookDelivery\"
+        SET \"status\" = $2::\"FileWebhookDeliveryStatus\", \"attempts\" = $3,
+            \"response_status\" = $4, \"error\" = $5, \"updated_date\" = NOW()
+        WHERE \"uuid\" = $1::text";
+
+    sqlx::query(query)
+        .bind(delivery_id)
+        .bind(status.to_string())
+        .bind(attempts)
+        .bind(response_status)
+        .bind(error)
+        .execute(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?;
+
+    Ok(())
+}
+
+/// Get the latest deliveries to a webhook of a file, newest first
+///
+/// # Arguments
+///
+/// * `pool` - The PostgreSQL pool
+/// * `file_id` - The UUID of the file
+/// * `webhook_id` - The UUID of the webhook
+/// * `limit` - The maximum number of deliveries to return
+///
+/// # Returns
+///
+pub async fn get_file_webhook_deliveries(
+    pool: &PgPool,
+    file_id: &Uuid,
+    webhook_id: &Uuid,
+    limit: i64,
+) -> Result<Vec<FileWebhookDelivery>> {
+    let query = format!(
+        "
+        SELECT {FILE_WEBHOOK_DELIVERY_COLUMNS}
+        FROM \"FileWebhookDelivery\" fwd
+        INNER JOIN \"FileWebhook\" fw ON fwd.\"file_webhook_id\" = fw.\"id\"
+        INNER JOIN \"File\" f ON fw.\"file_id\" = f.\"id\"
+        WHERE f.\"uuid\" = $1::text AND fw.\"uuid\" = $2::text
+        ORDER BY fwd.\"id\" DESC
+        LIMIT $3"
+    );
+
+    sqlx::query(&query)
+        .bind(file_id)
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+        .map_err(QuadraticDatabase::from)?
+        .into_iter()
+        .map(file_webhook_delivery_from_row)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{SharedError, quadratic_database::connect_test};
+
+    use super::*;
+
+    async fn test_setup() -> (PgPool, Uuid, i32, i32, i32) {
+        let file_uuid = Uuid::new_v4();
+        let pool = connect_test().unwrap();
+
+        let user_id: i32 = sqlx::query_scalar(
+            "INSERT INTO \"User\" (auth0_id, email) VALUES ($1, $2) RETURNING id",
+        )
+        .bind(format!("test-auth0-{}", Uuid::new_v4()))
+        .bind(format!("test-{}@example.com", Uuid::new_v4()))
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let team_id: i32 = sqlx::query_scalar(
+            "INSERT INTO \"Team\" (uuid, name) VALUES ($1::text, 'test-team') RETURNING id",
+        )
+        .bind(Uuid::new_v4())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let file_id: i32 = sqlx::query_scalar(
+            "INSERT INTO \"File\" (uuid, name, created_date, updated_date, owner_team_id, creator_user_id)
+             VALUES ($1::text, 'test-file', NOW(), NOW(), $2, $3)
+             RETURNING id",
+        )
+        .bind(file_uuid)
+        .bind(team_id)
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        (pool, file_uuid, file_id, team_id, user_id)
+    }
+
+    async fn test_teardown(pool: &PgPool, file_id: i32, team_id: i32, user_id: i32) {
+        let queries = [
+            (
+                "DELETE FROM \"FileWebhookDelivery\" WHERE file_webhook_id IN (SELECT id FROM \"FileWebhook\" WHERE file_id = $1)",
+                file_id,
+            ),
+            ("DELETE FROM \"FileWebhook\" WHERE file_id = $1", file_id),
+            ("DELETE FROM \"File\" WHERE id = $1", file_id),
+            ("DELETE FROM \"Team\" WHERE id = $1", team_id),
+            ("DELETE FROM \"User\" WHERE id = $1", user_id),
+        ];
+
+        for (query, id) in queries {
+            sqlx::query(query).bind(id).execute(pool).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_webhooks() {
+        let (pool, file_uuid, file_id, team_id, user_id) = test_setup().await;
+
+        let webhook = create_file_webhook(
+            &pool,
+            &file_uuid,
+            "http://localhost/webhook",
+            "secret",
+            Some("sheet-id"),
+            Some("A1:B2"),
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(webhook.file_id, file_uuid);
+        assert_eq!(webhook.range.as_deref(), Some("A1:B2"));
+        assert!(webhook.include_values);
+
+        let webhooks = get_file_webhooks(&pool, &file_uuid).await.unwrap();
+        assert_eq!(webhooks, vec![webhook.clone()]);
+
+        // deliveries are logged newest first
+        let first = create_file_webhook_delivery(&pool, &webhook.uuid, 1, 2)
+            .await
+            .unwrap();
+        let second = create_file_webhook_delivery(&pool, &webhook.uuid, 3, 3)
+            .await
+            .unwrap();
+        update_file_webhook_delivery(
+            &pool,
+            &first,
+            FileWebhookDeliveryStatus::Failed,
+            3,
+            Some(500),
+            Some("Internal Server Error"),
+        )
+        .await
+        .unwrap();
+
+        let deliveries = get_file_webhook_deliveries(&pool, &file_uuid, &webhook.uuid, 10)
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].uuid, second);
+        assert_eq!(deliveries[0].status, FileWebhookDeliveryStatus::Pending);
+        assert_eq!(deliveries[1].uuid, first);
+        assert_eq!(deliveries[1].status, FileWebhookDeliveryStatus::Failed);
+        assert_eq!(deliveries[1].attempts, 3);
+        assert_eq!(deliveries[1].response_status, Some(500));
+        assert_eq!(
+            (
+                deliveries[1].first_sequence_number,
+                deliveries[1].last_sequence_number
+            ),
+            (1, 2)
+        );
+
+        // deleted webhooks are no longer delivered to
+        delete_file_webhook(&pool, &file_uuid, &webhook.uuid)
+            .await
+            .unwrap();
+        assert!(
+            get_file_webhooks(&pool, &file_uuid)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let not_found = delete_file_webhook(&pool, &file_uuid, &webhook.uuid)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            not_found,
+            SharedError::QuadraticDatabase(QuadraticDatabase::NotFound(_))
+        ));
+
+        test_teardown(&pool, file_id, team_id, user_id).await;
+    }
+}
@@ -3,10 +3,11 @@
 //! Functions to interact with BigQuery
 
 use std::collections::BTreeMap;
+use std::io::Write;
 use std::str::FromStr;
+use std::time::Duration;
 
 use async_trait::async_trait;
-use bytes::Bytes;
 use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
 use google_cloud_bigquery::client::google_cloud_auth::credentials::CredentialsFile;
 use google_cloud_bigquery::client::{Client, ClientConfig};
@@ -14,13 +15,19 @@ use google_cloud_bigquery::http::error::Error as BigqueryError;
 use google_cloud_bigquery::http::job::query::QueryRequest;
 use google_cloud_bigquery::http::table::{TableFieldSchema, TableFieldType};
 use google_cloud_bigquery::http::tabledata::list::{Cell, Tuple, Value};
+use google_cloud_bigquery::http::types::{QueryParameter, QueryParameterType, QueryParameterValue};
+use google_cloud_bigquery::query::Iterator as QueryIterator;
 use google_cloud_bigquery::query::row::Row;
 use rust_decimal::Decimal;
 use serde::{self, Deserialize, Serialize};
 use serde_json::json;
 
 use crate::error::Result;
-use crate::sql::schema::{DatabaseSchema, SchemaColumn, SchemaTable};
+use crate::sql::parameter::SqlParameter;
+use crate::sql::parquet_writer::ParquetBatchWriter;
+use crate::sql::schema::{
+    DatabaseSchema, SchemaColumn, SchemaForeignKey, SchemaTable, TableKind, comment,
+};
 use crate::sql::{ArrowType, Connection};
 use crate::sql::{query_error, schema_error};
 use crate::{bigquery_type, sql_unwrap_or_null};
@@ -32,6 +39,14 @@ pub struct BigqueryConfig {
     pub project_id: String,
     pub service_account_configuration: String,
     pub dataset: String,
+
+    /// Only allow queries that read data
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Cache query results for this many seconds
+    #[serde(default)]
+    pub cache_ttl_seconds: Option<u64>,
 }
 
 /// Bigquery connection
@@ -40,6 +55,8 @@ pub struct BigqueryConnection {
     pub client: Client,
     pub dataset: String,
     pub columns: Vec<ColumnSchema>,
+    pub read_only: bool,
+    pub cache_ttl_seconds: Option<u64>,
 }
 
 pub struct ColumnSchema {
@@ -68,25 +85,63 @@ impl BigqueryConnection {
             client,
             dataset,
             columns: Vec::new(),
+            read_only: false,
+            cache_ttl_seconds: None,
         })
     }
 
     pub async fn new_from_config(config: BigqueryConfig) -> Result<Self> {
+        let read_only = config.read_only;
+        let cache_ttl_seconds = config.cache_ttl_seconds;
+
         BigqueryConnection::new(
             config.service_account_configuration,
             config.project_id,
             config.dataset,
         )
         .await
+        .map(|connection| {
+            connection
+                .with_read_only(read_only)
+                .with_cache_ttl(cache_ttl_seconds)
+        })
+    }
+
+    /// Only allow queries that read data
+    pub fn with_read_only(self, read_only: bool) -> Self {
+        Self { read_only, ..self }
+    }
+
+    /// Cache query results for this many seconds
+    pub fn with_cache_ttl(self, cache_ttl_seconds: Option<u64>) -> Self {
+        Self {
+            cache_ttl_seconds,
+            ..self
+        }
+    }
+
+    /// Run a schema query, returning an iterator over its rows
+    async fn schema_rows(&self, sql: String) -> Result<QueryIterator<Row>> {
+        let request = QueryRequest {
+            query: sql,
+            ..Default::default()
+        };
+
+        self.client
+            .query::<Row>(&self.project_id, request)
+            .await
+            .map_err(schema_error)
     }
 
     pub async fn raw_query(
         &mut self,
         sql: &str,
+        params: &[SqlParameter],
         max_bytes: Option<u64>,
     ) -> Result<(Vec<Tuple>, bool, usize)> {
         let request = QueryRequest {
             query: sql.to_string(),
+            query_parameters: to_query_parameters(params),
             maximum_bytes_billed: max_bytes.map(|b| b as i64),
             ..Default::default()
         };
@@ -222,21 +277,39 @@ impl<'a> Connection<'a> for BigqueryConnection {
         }
     }
 
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl_seconds.map(Duration::from_secs)
+    }
+
     /// Connect to a Snowflake database
     async fn connect(&self) -> Result<Self::Conn> {
         Ok(None)
     }
 
-    async fn query(
+    async fn query_stream<W: Write + Send>(
         &mut self,
         _: &mut Self::Conn,
         sql: &str,
-        _max_bytes: Option<u64>,
-    ) -> Result<(Bytes, bool, usize)> {
-        let (rows, over_the_limit, num_records) = self.raw_query(sql, _max_bytes).await?;
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
+        let (rows, over_the_limit, _) = self.raw_query(sql, params, writer.max_bytes()).await?;
 
-        let (bytes, _) = self.to_parquet(rows)?;
-        Ok((bytes, over_the_limit, num_records))
+        if over_the_limit {
+            writer.set_over_the_limit();
+        }
+
+        for row in &rows {
+            if !self.write_row(row, writer)? {
+                break;
+            }
+        }
+
+        Ok(None)
     }
 
     async fn schema(&self, _pool: &mut Self::Conn) -> Result<DatabaseSchema> {
@@ -251,6 +324,10 @@ impl<'a> Connection<'a> for BigqueryConnection {
                 c.data_type,
                 c.is_nullable,
                 c.column_default,
+                t.table_type,
+                p.description,
+                o.option_value,
+                CAST(r.row_count AS STRING),
             FROM 
                 {project_id}.{dataset}.INFORMATION_SCHEMA.TABLES t
             LEFT JOIN 
@@ -258,6 +335,18 @@ impl<'a> Connection<'a> for BigqueryConnection {
                 ON t.table_name = c.table_name
                     AND t.table_schema = c.table_schema
                     AND t.table_catalog = c.table_catalog
+            LEFT JOIN
+                {project_id}.{dataset}.INFORMATION_SCHEMA.COLUMN_FIELD_PATHS p
+                ON p.table_name = c.table_name
+                    AND p.column_name = c.column_name
+                    AND p.field_path = c.column_name
+            LEFT JOIN
+                {project_id}.{dataset}.INFORMATION_SCHEMA.TABLE_OPTIONS o
+                ON o.table_name = t.table_name
+                    AND o.option_name = 'description'
+            LEFT JOIN
+                `{project_id}.{dataset}.__TABLES__` r
+                ON r.table_id = t.table_name
             WHERE 
                 t.table_type IN ('BASE TABLE', 'VIEW', 'MATERIALIZED VIEW')
             ORDER BY 
@@ -267,15 +356,7 @@ impl<'a> Connection<'a> for BigqueryConnection {
             "
         );
 
-        let request = QueryRequest {
-            query: sql.to_string(),
-            ..Default::default()
-        };
-        let mut iter = self
-            .client
-            .query::<Row>(&self.project_id, request)
-            .await
-            .map_err(schema_error)?;
+        let mut iter = self.schema_rows(sql).await?;
 
         let mut schema = DatabaseSchema {
             database: dataset.to_owned(),
@@ -290,15 +371,31 @@ impl<'a> Connection<'a> for BigqueryConnection {
             let table_name = bigquery_row
                 .column::<String>(0)
                 .unwrap_or(format!("table_{table_count}"));
+            let optional = |index: usize| {
+                bigquery_row
+                    .column::<Option<String>>(index)
+                    .map_err(schema_error)
+            };
+            let kind = match optional(5)?.as_deref() {
+                Some("VIEW") => TableKind::View,
+                Some("MATERIALIZED VIEW") => TableKind::MaterializedView,
+                _ => TableKind::Table,
+            };
+
+            // option values are string literals
+            let table_comment =
+                comment(optional(7)?.map(|description| description.trim_matches('"').to_string()));
+            let row_estimate = optional(8)?.and_then(|rows| rows.parse().ok());
 
             schema
                 .tables
                 // get or insert the table
                 .entry(table_name.to_owned())
                 .or_insert_with(|| SchemaTable {
-                    name: table_name,
-                    schema: dataset.to_owned(),
-                    columns: vec![],
+                    kind,
+                    comment: table_comment,
+                    row_estimate,
+                    ..SchemaTable::new(table_name, dataset.to_owned())
                 })
                 .columns
                 // add the column to the table
@@ -313,15 +410,121 @@ impl<'a> Connection<'a> for BigqueryConnection {
                             .as_str(),
                         "yes"
                     ),
+                    comment: comment(optional(6)?),
                 });
 
             table_count += 1;
         }
 
+        // primary and foreign keys (which aren't enforced), a row per column.
+        // A foreign key's columns are matched to the referenced primary key's
+        // columns by position.  BigQuery doesn't have indexes.
+        let sql = format!(
+            "
+            SELECT
+                k.table_name,
+                k.constraint_name,
+                tc.constraint_type,
+                k.column_name,
+                u.table_schema,
+                u.table_name,
+                rk.column_name,
+            FROM
+                {project_id}.{dataset}.INFORMATION_SCHEMA.KEY_COLUMN_USAGE k
+            JOIN
+                {project_id}.{dataset}.INFORMATION_SCHEMA.TABLE_CONSTRAINTS tc
+                ON tc.constraint_name = k.constraint_name
+                    AND tc.table_name = k.table_name
+            LEFT JOIN
+                (
+                    SELECT DISTINCT constraint_name, table_schema, table_name
+                    FROM {project_id}.{dataset}.INFORMATION_SCHEMA.CONSTRAINT_COLUMN_USAGE
+                ) u
+                ON u.constraint_name = k.constraint_name
+                    AND tc.constraint_type = 'FOREIGN KEY'
+            LEFT JOIN
+                {project_id}.{dataset}.INFORMATION_SCHEMA.TABLE_CONSTRAINTS rtc
+                ON rtc.table_name = u.table_name
+                    AND rtc.constraint_type = 'PRIMARY KEY'
+            LEFT JOIN
+                {project_id}.{dataset}.INFORMATION_SCHEMA.KEY_COLUMN_USAGE rk
+                ON rk.constraint_name = rtc.constraint_name
+                    AND rk.table_name = u.table_name
+                    AND rk.ordinal_position = k.position_in_unique_constraint
+            ORDER BY
+                k.table_name,
+                k.constraint_name,
+                k.ordinal_position;
+            "
+        );
+
+        let mut iter = self.schema_rows(sql).await?;
+
+        while let Some(bigquery_row) = iter.next().await.map_err(schema_error)? {
+            let column = |index: usize| {
+                bigquery_row
+                    .column::<Option<String>>(index)
+                    .map(Option::unwrap_or_default)
+                    .map_err(schema_error)
+            };
+            let table_name = column(0)?;
+
+            match column(2)?.as_str() {
+                "PRIMARY KEY" => schema.add_primary_key_column(&table_name, column(3)?),
+                _ => schema.add_foreign_key(
+                    &table_name,
+                    SchemaForeignKey {
+                        name: column(1)?,
+                        columns: vec![column(3)?],
+                        foreign_schema: column(4)?,
+                        foreign_table: column(5)?,
+                        foreign_columns: vec![column(6)?],
+                    },
+                ),
+            }
+        }
+
         Ok(schema)
     }
 }
 
+/// Convert parameters to BigQuery named parameters (`@p1`, `@p2`, ...)
+fn to_query_parameters(params: &[SqlParameter]) -> Vec<QueryParameter> {
+    params
+        .iter()
+        .enumerate()
+        .map(|(index, param)| {
+            let (parameter_type, value) = match param {
+                SqlParameter::Null => ("STRING", None),
+                SqlParameter::Text(text) => ("STRING", Some(text.to_owned())),
+                SqlParameter::Number(number) => match param.as_integer() {
+                    Some(integer) => ("INT64", Some(integer.to_string())),
+                    None => ("NUMERIC", Some(number.to_string())),
+                },
+                SqlParameter::Logical(logical) => ("BOOL", Some(logical.to_string())),
+                SqlParameter::Date(date) => ("DATE", Some(date.format("%Y-%m-%d").to_string())),
+                SqlParameter::Time(time) => ("TIME", Some(time.format("%H:%M:%S%.f").to_string())),
+                SqlParameter::DateTime(date_time) => (
+                    "DATETIME",
+                    Some(date_time.format("%Y-%m-%d %H:%M:%S%.f").to_string()),
+                ),
+            };
+
+            QueryParameter {
+                name: Some(format!("p{}", index + 1)),
+                parameter_type: QueryParameterType {
+                    parameter_type: parameter_type.to_string(),
+                    ..Default::default()
+                },
+                parameter_value: QueryParameterValue {
+                    value,
+                    ..Default::default()
+                },
+            }
+        })
+        .collect()
+}
+
 fn map_schema(schema: Vec<TableFieldSchema>) -> Vec<(TableFieldType, String, Option<Vec<String>>)> {
     schema
         .into_iter()
@@ -481,6 +684,8 @@ pub mod tests {
             service_account_configuration: credentials.to_string(),
             project_id: "quadratic-development".to_string(),
             dataset: "all_native_data_types".to_string(),
+            read_only: false,
+            cache_ttl_seconds: None,
         }
     }
 
@@ -496,81 +701,97 @@ pub mod tests {
                 name: "id".into(),
                 r#type: "INT64".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "string_col".into(),
                 r#type: "STRING".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bytes_col".into(),
                 r#type: "BYTES".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "int_col".into(),
                 r#type: "INT64".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "float_col".into(),
                 r#type: "FLOAT64".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "numeric_col".into(),
                 r#type: "NUMERIC".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bignumeric_col".into(),
                 r#type: "BIGNUMERIC".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bool_col".into(),
                 r#type: "BOOL".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamp_col".into(),
                 r#type: "TIMESTAMP".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "date_col".into(),
                 r#type: "DATE".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "time_col".into(),
                 r#type: "TIME".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "datetime_col".into(),
                 r#type: "DATETIME".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "json_col".into(),
                 r#type: "JSON".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "array_int_col".into(),
                 r#type: "ARRAY<INT64>".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "struct_col".into(),
                 r#type: "STRUCT<name STRING, value INT64>".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "interval_col".into(),
                 r#type: "INTERVAL".into(),
                 is_nullable: true,
+                comment: None,
             },
         ]
     }
@@ -588,7 +809,7 @@ pub mod tests {
         let mut connection = new_connection().await;
 
         let sql = "SELECT * FROM `quadratic-development.all_native_data_types.all_data_types` order by id LIMIT 10".to_string();
-        let results = connection.raw_query(&sql, None).await.unwrap();
+        let results = connection.raw_query(&sql, &[], None).await.unwrap();
         println!("{results:?}");
     }
 
@@ -601,11 +822,30 @@ pub mod tests {
         assert_eq!(columns, &expected_bigquery_schema());
     }
 
+    #[test]
+    fn test_bigquery_query_parameters() {
+        let params = vec![
+            SqlParameter::Text("it's".into()),
+            SqlParameter::Number(Decimal::from(42)),
+            SqlParameter::Null,
+        ];
+        let query_parameters = to_query_parameters(&params);
+
+        assert_eq!(query_parameters[0].name, Some("p1".into()));
+        assert_eq!(
+            query_parameters[0].parameter_value.value,
+            Some("it's".into())
+        );
+        assert_eq!(query_parameters[1].parameter_type.parameter_type, "INT64");
+        assert_eq!(query_parameters[1].parameter_value.value, Some("42".into()));
+        assert_eq!(query_parameters[2].parameter_value.value, None);
+    }
+
     #[tokio::test]
     async fn test_bigquery_query_over_limit() {
         let mut connection = new_connection().await;
         let sql = "select * from `quadratic-development.all_native_data_types.all_data_types`";
-        let results = connection.raw_query(sql, Some(1)).await.unwrap();
+        let results = connection.raw_query(sql, &[], Some(1)).await.unwrap();
 
         assert_eq!(results, (Vec::new(), true, 0));
     }
@@ -59,141 +59,169 @@ mod tests {
                 name: "id".into(),
                 r#type: "int4".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "smallint_col".into(),
                 r#type: "int2".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "integer_col".into(),
                 r#type: "int4".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigint_col".into(),
                 r#type: "int8".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "decimal_col".into(),
                 r#type: "numeric".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "numeric_col".into(),
                 r#type: "numeric".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "real_col".into(),
                 r#type: "float4".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "double_col".into(),
                 r#type: "float8".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "serial_col".into(),
                 r#type: "int4".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigserial_col".into(),
                 r#type: "int8".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "char_col".into(),
                 r#type: "bpchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varchar_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "text_col".into(),
                 r#type: "text".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bytea_col".into(),
                 r#type: "bytea".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamp_col".into(),
                 r#type: "timestamp".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamptz_col".into(),
                 r#type: "timestamptz".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "date_col".into(),
                 r#type: "date".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "time_col".into(),
                 r#type: "time".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timetz_col".into(),
                 r#type: "timetz".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "interval_col".into(),
                 r#type: "interval".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "boolean_col".into(),
                 r#type: "bool".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "enum_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "inet_col".into(),
                 r#type: "inet".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "json_col".into(),
                 r#type: "jsonb".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "jsonb_col".into(),
                 r#type: "jsonb".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "uuid_col".into(),
                 r#type: "uuid".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "array_col".into(),
                 r#type: "int4[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "null_bool_col".into(),
                 r#type: "bool".into(),
                 is_nullable: true,
+                comment: None,
             },
         ];
 
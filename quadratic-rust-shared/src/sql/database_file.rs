@@ -0,0 +1,81 @@
+//! Database Files
+//!
+//! SQLite and DuckDB connections open a file on the server.  The file's path
+//! comes from the connection's details, so it's resolved within a configured
+//! directory and rejected if it's anywhere else (including through `..` or a
+//! symlink).
+
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::sql::connect_error;
+
+/// Resolve the path of a database file within `directory`.  Connections
+/// without a directory are rejected.
+pub fn resolve_database_path(directory: Option<&Path>, database: &str) -> Result<PathBuf> {
+    let directory = directory.ok_or_else(|| {
+        connect_error("Database files are disabled, no database directory is configured")
+    })?;
+
+    let directory = directory
+        .canonicalize()
+        .map_err(|e| connect_error(format!("Invalid database directory {directory:?}: {e}")))?;
+
+    let path = directory
+        .join(database)
+        .canonicalize()
+        .map_err(|e| connect_error(format!("{database:?}: {e}")))?;
+
+    if !path.starts_with(&directory) || !path.is_file() {
+        return Err(connect_error(format!(
+            "{database:?} is not a file in the database directory"
+        )));
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{File, create_dir};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolve_database_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let directory = temp_dir.path().join("databases");
+        create_dir(&directory).unwrap();
+        File::create(directory.join("data.db")).unwrap();
+        File::create(temp_dir.path().join("outside.db")).unwrap();
+
+        let resolve = |database| resolve_database_path(Some(&directory), database);
+
+        assert_eq!(
+            resolve("data.db").unwrap(),
+            directory.join("data.db").canonicalize().unwrap()
+        );
+        assert!(resolve("missing.db").is_err());
+        assert!(resolve("../outside.db").is_err());
+        assert!(resolve(&temp_dir.path().join("outside.db").to_string_lossy()).is_err());
+        assert!(resolve("/etc/passwd").is_err());
+        assert!(resolve(".").is_err());
+        assert!(resolve_database_path(None, "data.db").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_database_path_rejects_symlinks_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let directory = temp_dir.path().join("databases");
+        create_dir(&directory).unwrap();
+        File::create(temp_dir.path().join("outside.db")).unwrap();
+        std::os::unix::fs::symlink(
+            temp_dir.path().join("outside.db"),
+            directory.join("link.db"),
+        )
+        .unwrap();
+
+        assert!(resolve_database_path(Some(&directory), "link.db").is_err());
+    }
+}
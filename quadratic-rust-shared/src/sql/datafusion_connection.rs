@@ -3,25 +3,46 @@
 //! Functions to interact with datafusion
 //! Datafusion is a query engine for Apache Arrow/Parquet files.
 //! It is used to query the parquet files in the object store.
+//!
+//! Each stream is a table of parquet files.  The files of a stream can have
+//! different schemas, since they're written at different times, so a table's
+//! schema is the unified schema of its files.  The date partitions of a
+//! compacted month aren't read once the month's file is written.
 
 use arrow::array::ArrayRef;
+use arrow::datatypes::Date32Type;
 use arrow_array::array::Array;
 use async_trait::async_trait;
-use bytes::Bytes;
+use chrono::NaiveTime;
+use datafusion::datasource::file_format::FileFormat;
+use datafusion::datasource::file_format::options::ReadOptions;
+use datafusion::datasource::listing::{
+    ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl,
+};
 use datafusion::prelude::{ParquetReadOptions, SessionConfig, SessionContext};
+use datafusion::scalar::ScalarValue;
 use derivative::Derivative;
-use object_store::ObjectStore;
-use parquet::arrow::ArrowWriter;
+use futures_util::{StreamExt, TryStreamExt, stream};
+use object_store::{ObjectStore, path::Path};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::io::Write;
 use std::sync::Arc;
 use url::Url;
 use uuid::Uuid;
 
 use crate::arrow::arrow_type::ArrowType;
+use crate::arrow::object_store::list_objects;
+use crate::arrow::schema::unify_schemas;
 use crate::error::Result;
+use crate::sql::parameter::SqlParameter;
+use crate::sql::parquet_writer::ParquetBatchWriter;
 use crate::sql::schema::{DatabaseSchema, SchemaColumn, SchemaTable};
 use crate::sql::{Connection, connect_error, query_error, schema_error};
+use crate::synced::compaction::superseded_files;
+
+/// The most parquet footers read at once when inferring a table's schema
+const SCHEMA_CONCURRENCY: usize = 32;
 
 pub fn default_object_store() -> Arc<dyn ObjectStore> {
     use object_store::memory::InMemory;
@@ -101,6 +122,91 @@ impl DatafusionConnection {
 
         Ok(path)
     }
+
+    /// Get the path of a file in the object store, relative for FileSystem
+    /// like `object_store_parquet_path`
+    fn object_store_file_path(&self, location: &Path) -> String {
+        if self.object_store_url.scheme() == "file" {
+            format!("/{}", location)
+        } else {
+            format!("{}/{}", self.object_store_url, location)
+        }
+    }
+
+    /// Create a table of a stream's parquet files.  The table's schema is
+    /// the unified schema of the files (fields that were added are null in
+    /// older files, and conflicting types are widened), and each file is cast
+    /// to it when it's read.
+    async fn listing_table(&self, ctx: &SessionContext, table: &str) -> Result<ListingTable> {
+        let connection_id = self
+            .connection_id
+            .ok_or_else(|| connect_error("Connection ID is required"))?;
+        let table_url = ListingTableUrl::parse(self.object_store_parquet_path(table)?)
+            .map_err(connect_error)?;
+        let options: ListingOptions = ParquetReadOptions::default()
+            .to_listing_options(&ctx.copied_config(), ctx.copied_table_options());
+        let state = ctx.state();
+
+        let prefix = format!("{}/{}", connection_id, table);
+        let objects = list_objects(&self.object_store, Some(&prefix)).await?;
+
+        // while a compacted month's superseded files haven't been deleted
+        // yet, only the current files are read
+        let superseded = superseded_files(&objects);
+        let mut objects = objects
+            .into_iter()
+            .filter(|object| object.location.as_ref().ends_with(&options.file_extension))
+            .filter(|object| !superseded.contains(&object.location))
+            .collect::<Vec<_>>();
+
+        // the oldest files first, so that added fields are at the end
+        objects.sort_by(|a, b| a.location.cmp(&b.location));
+
+        let table_urls = match superseded.is_empty() {
+            true => vec![table_url],
+            false => objects
+                .iter()
+                .map(|object| self.object_store_file_path(&object.location))
+                .map(|path| ListingTableUrl::parse(path).map_err(connect_error))
+                .collect::<Result<Vec<_>>>()?,
+        };
+
+        let schemas = stream::iter(objects)
+            .map(|object| {
+                let (format, state, store) = (&options.format, &state, &self.object_store);
+                async move { format.infer_schema(state, store, &[object]).await }
+            })
+            .buffered(SCHEMA_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(connect_error)?;
+
+        let config = ListingTableConfig::new_with_multi_paths(table_urls)
+            .with_listing_options(options)
+            .with_schema(Arc::new(unify_schemas(&schemas)));
+
+        ListingTable::try_new(config).map_err(connect_error)
+    }
+}
+
+/// Convert a query parameter to the value datafusion binds to `$1`, `$2`, ...
+fn to_scalar_value(param: &SqlParameter) -> ScalarValue {
+    match param {
+        SqlParameter::Null => ScalarValue::Utf8(None),
+        SqlParameter::Text(text) => ScalarValue::Utf8(Some(text.to_owned())),
+        SqlParameter::Number(_) => match param.as_integer() {
+            Some(integer) => ScalarValue::Int64(Some(integer)),
+            None => ScalarValue::Float64(param.as_float()),
+        },
+        SqlParameter::Logical(logical) => ScalarValue::Boolean(Some(*logical)),
+        SqlParameter::Date(date) => ScalarValue::Date32(Some(Date32Type::from_naive_date(*date))),
+        SqlParameter::Time(time) => ScalarValue::Time64Nanosecond(
+            time.signed_duration_since(NaiveTime::MIN).num_nanoseconds(),
+        ),
+        SqlParameter::DateTime(date_time) => {
+            ScalarValue::TimestampMillisecond(Some(date_time.and_utc().timestamp_millis()), None)
+        }
+    }
 }
 
 /// Implement the Connection trait for datafusion
@@ -140,30 +246,32 @@ impl<'a> Connection<'a> for DatafusionConnection {
         // register the object store in datafusion context
         ctx.register_object_store(&self.object_store_url, self.object_store.clone());
 
-        // register the parquet path for every table
+        // register the parquet files of every table
         for table in &self.streams {
-            let parquet_path = self.object_store_parquet_path(table)?;
+            let listing_table = self.listing_table(&ctx, table).await?;
 
-            ctx.register_parquet(
-                table.to_owned(),
-                &parquet_path,
-                ParquetReadOptions::default(),
-            )
-            .await
-            .map_err(connect_error)?;
+            ctx.register_table(table.as_str(), Arc::new(listing_table))
+                .map_err(connect_error)?;
         }
 
         Ok(ctx)
     }
 
     /// Query rows from a parquet file
-    async fn query(
+    async fn query_stream<W: Write + Send>(
         &mut self,
         client: &mut Self::Conn,
         sql: &str,
-        max_bytes: Option<u64>,
-    ) -> Result<(Bytes, bool, usize)> {
-        let df = client.sql(sql).await.map_err(query_error)?;
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
+        let mut df = client.sql(sql).await.map_err(query_error)?;
+
+        if !params.is_empty() {
+            let values = params.iter().map(to_scalar_value).collect::<Vec<_>>();
+            df = df.with_param_values(values).map_err(query_error)?;
+        }
+
         // test helper
         // df.clone().show().await.unwrap();
         // df.clone()
@@ -172,36 +280,17 @@ impl<'a> Connection<'a> for DatafusionConnection {
         //     .show()
         //     .await
         //     .unwrap();
-        let batches = df.collect().await.map_err(query_error)?;
-
-        if batches.is_empty() {
-            return Ok((Bytes::new(), false, 0));
-        }
-
+        let mut stream = df.execute_stream().await.map_err(query_error)?;
         let mut total_records = 0;
-        let mut total_bytes = 0;
-        let mut over_the_limit = false;
-        let buffer = Vec::new();
-        let mut writer = ArrowWriter::try_new(buffer, batches[0].schema(), None)?;
 
-        // enforce max bytes
-        for batch in &batches {
+        // batches are still counted once the result is over the byte limit
+        while let Some(batch) = stream.next().await {
+            let batch = batch.map_err(query_error)?;
             total_records += batch.num_rows();
-            total_bytes += batch.get_array_memory_size() as u64;
-
-            if let Some(max_bytes) = max_bytes
-                && total_bytes > max_bytes
-            {
-                over_the_limit = true;
-                break;
-            }
-
-            writer.write(batch)?;
+            writer.write_batch(&batch)?;
         }
 
-        let parquet = writer.into_inner()?;
-
-        Ok((parquet.into(), over_the_limit, total_records))
+        Ok(Some(total_records))
     }
 
     /// Get the schema of a datafusion database
@@ -235,15 +324,15 @@ impl<'a> Connection<'a> for DatafusionConnection {
                     name: field.name().clone(),
                     r#type: format!("{:?}", field.data_type()),
                     is_nullable: field.is_nullable(),
+                    comment: None,
                 })
                 .collect::<Vec<_>>();
 
             schema.tables.insert(
                 table_name.clone(),
                 SchemaTable {
-                    name: table_name,
-                    schema: "public".to_string(),
                     columns,
+                    ..SchemaTable::new(table_name, "public".to_string())
                 },
             );
         }
@@ -255,6 +344,7 @@ impl<'a> Connection<'a> for DatafusionConnection {
 pub mod tests {
 
     use super::*;
+    use bytes::Bytes;
 
     pub const PARQUET_FILE: &str = "s3://synced-data/consolidated/mixpanel_data.parquet";
 
@@ -276,391 +366,469 @@ pub mod tests {
                 name: "event".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "time".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "distinct_id".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_browser".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_browser_version".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_city".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_current_url".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_device".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_device_id".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_initial_referrer".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_initial_referring_domain".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_insert_id".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_lib_version".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_os".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_region".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_screen_height".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_screen_width".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_user_id".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_country_code".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_lib".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_sent_by_lib_version".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "path".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "utm_campaign".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "utm_content".into(),
                 r#type: "Float64".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "utm_medium".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "utm_source".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "utm_term".into(),
                 r#type: "Float64".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "_sdc_extracted_at".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "_stream".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "database".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_referrer".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_referring_domain".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_search_engine".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "gclid".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "chatId".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "filenames".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "prompt".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "email".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "isOnPaidPlan".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "loadTimeMs".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "route".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "type".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "inline".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "label".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "userMessageCountUponSubmit".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "isPrivate".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "language".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "title".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "newFilename".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "id".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "fileName".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "exceededBillingLimit".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "location".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "ab_test".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "__createdAt".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "__version".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "goals[]".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "languages[]".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "personal-uses[]".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "use".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mp_reserved_source".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "Campaign ID".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "Campaign Name".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "Campaign Status".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "Campaign Type".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "Event Timestamp".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "messageCount".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "context".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "error".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "files".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "education-identity".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "education-subjects[]".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "AB Test Variant".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "Page".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "Test Type".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "description".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "fbclid".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "team_uuid".into(),
                 r#type: "Utf8View".into(),
                 is_nullable: true,
+                comment: None,
             },
         ]
     }
@@ -674,6 +842,7 @@ pub mod tests {
             .query(
                 &mut client,
                 "select * from mixpanel_data limit 10;",
+                &[],
                 max_bytes,
             )
             .await
@@ -685,6 +854,144 @@ pub mod tests {
         assert!(client.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_datafusion_unified_schema() {
+        use crate::arrow::object_store::{new_filesystem_object_store, upload_multipart};
+        use crate::parquet::json::grouped_json_to_parquet;
+        use arrow::array::AsArray;
+        use std::collections::HashMap;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        let (object_store, _) = new_filesystem_object_store(path).unwrap();
+        let connection_id = Uuid::new_v4();
+
+        // a later file has a new field and a different type for amount
+        for (date, line) in [
+            ("2024-01-01", r#"{"id": "a", "amount": 10}"#),
+            (
+                "2024-01-02",
+                r#"{"id": "b", "amount": "unknown", "note": "added"}"#,
+            ),
+        ] {
+            let data =
+                grouped_json_to_parquet(HashMap::from([(date.into(), vec![line.into()])])).unwrap();
+            let file_name = format!("{}/charges/{}.parquet", connection_id, date);
+            upload_multipart(&object_store, &file_name, &data[date])
+                .await
+                .unwrap();
+        }
+
+        let mut connection =
+            DatafusionConnection::new(object_store, Url::parse(&format!("file://{path}")).unwrap())
+                .with_connection_id(connection_id);
+        connection.streams = vec!["charges".into()];
+        let mut client = connection.connect().await.unwrap();
+
+        let schema = connection.schema(&mut client).await.unwrap();
+        let columns = schema.tables["charges"]
+            .columns
+            .iter()
+            .map(|column| (column.name.as_str(), column.r#type.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            vec![("id", "Utf8View"), ("amount", "Utf8"), ("note", "Utf8View")]
+        );
+
+        let batches = client
+            .sql("select note from charges order by id")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let notes = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_string_view()
+                    .iter()
+                    .map(|note| note.map(str::to_owned))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(notes, vec![None, Some("added".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_datafusion_skips_superseded_files() {
+        use crate::arrow::object_store::{new_filesystem_object_store, upload_multipart};
+        use crate::parquet::json::grouped_json_to_parquet;
+        use crate::synced::compaction::compact;
+        use std::collections::HashMap;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        let (object_store, _) = new_filesystem_object_store(path).unwrap();
+        let connection_id = Uuid::new_v4();
+        let prefix = format!("{}/charges", connection_id);
+
+        for (date, line) in [
+            ("2024-01-31", r#"{"id": "a", "amount": 10}"#),
+            ("2024-02-01", r#"{"id": "b", "amount": 20}"#),
+        ] {
+            let data =
+                grouped_json_to_parquet(HashMap::from([(date.into(), vec![line.into()])])).unwrap();
+            upload_multipart(
+                &object_store,
+                &format!("{prefix}/{date}.parquet"),
+                &data[date],
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut connection = DatafusionConnection::new(
+            object_store.clone(),
+            Url::parse(&format!("file://{path}")).unwrap(),
+        )
+        .with_connection_id(connection_id);
+        connection.streams = vec!["charges".into()];
+
+        let count = |connection: DatafusionConnection| async move {
+            let client = connection.connect().await.unwrap();
+            let batches = client
+                .sql("select count(*) from charges")
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+            batches[0]
+                .column(0)
+                .as_any()
+                .downcast_ref::<arrow_array::Int64Array>()
+                .unwrap()
+                .value(0)
+        };
+
+        assert_eq!(count(connection.clone()).await, 2);
+
+        // january is compacted but its date partition isn't deleted yet
+        let start_date = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        compact(
+            &object_store,
+            &prefix,
+            start_date,
+            chrono::Duration::hours(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(count(connection.clone()).await, 2);
+
+        compact(&object_store, &prefix, start_date, chrono::Duration::zero())
+            .await
+            .unwrap();
+        assert_eq!(count(connection).await, 2);
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_datafusion_query() {
@@ -0,0 +1,644 @@
+//! DuckDB
+//!
+//! Functions to interact with DuckDB database files
+//! DuckDB returns Arrow record batches directly, so rows are never
+//! converted through ArrowType.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::Date32Type;
+use arrow_array::array::Array;
+use async_trait::async_trait;
+use chrono::NaiveTime;
+use duckdb::types::{TimeUnit, Value};
+use duckdb::{AccessMode, Config, Connection as DuckdbConn, InterruptHandle, params_from_iter};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::Arc;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::task;
+
+use crate::arrow::arrow_type::ArrowType;
+use crate::error::Result;
+use crate::quadratic_api::Connection as ApiConnection;
+use crate::sql::database_file::resolve_database_path;
+use crate::sql::parameter::SqlParameter;
+use crate::sql::parquet_writer::ParquetBatchWriter;
+use crate::sql::schema::{
+    DatabaseSchema, SchemaColumn, SchemaForeignKey, SchemaIndex, SchemaTable, TableKind, comment,
+    row_estimate,
+};
+use crate::sql::{CancelQuery, Connection, connect_error, query_error, schema_error};
+
+/// DuckDB connection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DuckdbConnection {
+    /// Path to the DuckDB database file, relative to `directory`
+    pub database: String,
+
+    /// The directory that database files are opened from, set by the service
+    #[serde(skip)]
+    pub directory: Option<PathBuf>,
+}
+
+impl From<&ApiConnection<DuckdbConnection>> for DuckdbConnection {
+    fn from(connection: &ApiConnection<DuckdbConnection>) -> Self {
+        let details = connection.type_details.to_owned();
+        DuckdbConnection::new(details.database)
+    }
+}
+
+impl DuckdbConnection {
+    /// Create a new DuckDB connection
+    pub fn new(database: String) -> DuckdbConnection {
+        DuckdbConnection {
+            database,
+            directory: None,
+        }
+    }
+
+    /// Only open database files within `directory`
+    pub fn with_directory(mut self, directory: Option<impl Into<PathBuf>>) -> DuckdbConnection {
+        self.directory = directory.map(Into::into);
+        self
+    }
+
+    /// The catalog name DuckDB assigns to the attached database file
+    fn catalog_name(&self) -> String {
+        Path::new(&self.database)
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.database.to_owned())
+    }
+
+    /// Stream the record batches of a DuckDB query to `writer`, binding the
+    /// parameters to its `$1`, `$2`, ... placeholders
+    ///
+    /// Returns: the total number of records in the result
+    pub fn write_batches<W: Write + Send>(
+        conn: &DuckdbConn,
+        sql: &str,
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<usize> {
+        let values = params.iter().map(to_duckdb_value).collect::<Vec<_>>();
+        let mut statement = conn.prepare(sql).map_err(query_error)?;
+        let mut total_records = 0;
+
+        // batches are fetched lazily, and are still counted once the
+        // result is over the byte limit
+        for batch in statement
+            .query_arrow(params_from_iter(values))
+            .map_err(query_error)?
+        {
+            total_records += batch.num_rows();
+            writer.write_batch(&batch)?;
+        }
+
+        Ok(total_records)
+    }
+}
+
+/// Convert a query parameter to a DuckDB value
+fn to_duckdb_value(param: &SqlParameter) -> Value {
+    match param {
+        SqlParameter::Null => Value::Null,
+        SqlParameter::Text(text) => Value::Text(text.to_owned()),
+        SqlParameter::Number(_) => match param.as_integer() {
+            Some(integer) => Value::BigInt(integer),
+            None => param.as_float().map_or(Value::Null, Value::Double),
+        },
+        SqlParameter::Logical(logical) => Value::Boolean(*logical),
+        SqlParameter::Date(date) => Value::Date32(Date32Type::from_naive_date(*date)),
+        SqlParameter::Time(time) => Value::Time64(
+            TimeUnit::Microsecond,
+            time.signed_duration_since(NaiveTime::MIN)
+                .num_microseconds()
+                .unwrap_or_default(),
+        ),
+        SqlParameter::DateTime(date_time) => Value::Timestamp(
+            TimeUnit::Microsecond,
+            date_time.and_utc().timestamp_micros(),
+        ),
+    }
+}
+
+/// Interrupts a DuckDB query.  DuckDB runs in process and blocks the task
+/// running the query, so it must be interrupted from another task.
+pub struct DuckdbCancelQuery(Arc<InterruptHandle>);
+
+#[async_trait]
+impl CancelQuery for DuckdbCancelQuery {
+    async fn cancel(&self) -> Result<()> {
+        self.0.interrupt();
+
+        Ok(())
+    }
+}
+
+/// Only allow access to the database's directory, then lock the
+/// configuration
+fn lock_configuration_sql(path: &Path) -> String {
+    let directory = path.parent().unwrap_or(path);
+    let directory = directory.to_string_lossy().replace('\'', "''");
+
+    format!(
+        "
+        set autoinstall_known_extensions = false;
+        set autoload_known_extensions = false;
+        set allowed_directories = ['{directory}'];
+        set enable_external_access = false;
+        set lock_configuration = true;"
+    )
+}
+
+/// Implement the Connection trait for DuckDB
+///
+/// Since the duckdb api returns arrow data, we don't need some of the
+/// trait functions implemented.
+#[async_trait]
+impl<'a> Connection<'a> for DuckdbConnection {
+    type Conn = DuckdbConn;
+    type Row = Arc<dyn Array>;
+    type Column = ArrayRef;
+
+    /// Get the length of a row
+    fn row_len(_row: &Self::Row) -> usize {
+        unimplemented!();
+    }
+
+    /// Get the columns of a row
+    fn row_columns(_row: &Self::Row) -> Box<dyn Iterator<Item = &Self::Column> + '_> {
+        unimplemented!();
+    }
+
+    /// Get the name of a column
+    fn column_name(&self, _col: &Self::Column, _index: usize) -> String {
+        unimplemented!();
+    }
+
+    /// Convert a row to an Arrow type
+    fn to_arrow(&self, _row: &Self::Row, _: &ArrayRef, _index: usize) -> ArrowType {
+        unimplemented!();
+    }
+
+    /// Open a DuckDB database file in read-only mode.  Queries can't read
+    /// other files, attach databases or load extensions, and can't change
+    /// these settings.
+    async fn connect(&self) -> Result<Self::Conn> {
+        let path = resolve_database_path(self.directory.as_deref(), &self.database)?;
+        let config = Config::default()
+            .access_mode(AccessMode::ReadOnly)
+            .map_err(connect_error)?;
+
+        let conn = DuckdbConn::open_with_flags(&path, config)
+            .map_err(|e| connect_error(format!("{:?}: {e}", self.database)))?;
+
+        conn.execute_batch(&lock_configuration_sql(&path))
+            .map_err(connect_error)?;
+
+        Ok(conn)
+    }
+
+    /// Get the connection's interrupt handle
+    async fn cancel_handle(
+        &self,
+        conn: &mut Self::Conn,
+        _sql: &str,
+        _params: &[SqlParameter],
+    ) -> Result<Option<Box<dyn CancelQuery>>> {
+        Ok(Some(Box::new(DuckdbCancelQuery(conn.interrupt_handle()))))
+    }
+
+    /// Query rows from a DuckDB database.  DuckDB's api is synchronous, so
+    /// the worker thread is handed off while the query runs, which lets the
+    /// runtime time out or cancel it.
+    async fn query_stream<W: Write + Send>(
+        &mut self,
+        conn: &mut Self::Conn,
+        sql: &str,
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
+        let write_batches = || DuckdbConnection::write_batches(conn, sql, params, writer);
+        let total_records = match Handle::current().runtime_flavor() {
+            RuntimeFlavor::CurrentThread => write_batches()?,
+            _ => task::block_in_place(write_batches)?,
+        };
+
+        Ok(Some(total_records))
+    }
+
+    /// Get the schema of a DuckDB database.  DuckDB's indexes back its
+    /// constraints, so unique constraints are listed as unique indexes.
+    async fn schema(&self, conn: &mut Self::Conn) -> Result<DatabaseSchema> {
+        let sql = "
+            select c.schema_name, c.table_name, c.column_name, c.data_type, c.is_nullable, c.comment,
+                v.view_oid is not null as is_view, coalesce(t.comment, v.comment) as table_comment,
+                t.estimated_size
+            from duckdb_columns() as c
+            left join duckdb_tables() as t on t.table_oid = c.table_oid
+            left join duckdb_views() as v on v.view_oid = c.table_oid
+            where c.database_name = current_database() and not c.internal
+            order by c.schema_name, c.table_name, c.column_index";
+
+        let mut statement = conn.prepare(sql).map_err(schema_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    SchemaColumn {
+                        name: row.get::<_, String>(2)?,
+                        r#type: row.get::<_, String>(3)?,
+                        is_nullable: row.get::<_, bool>(4)?,
+                        comment: comment(row.get::<_, Option<String>>(5)?),
+                    },
+                    row.get::<_, bool>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                    row.get::<_, Option<i64>>(8)?,
+                ))
+            })
+            .map_err(schema_error)?;
+
+        let mut schema = DatabaseSchema {
+            database: self.catalog_name(),
+            tables: BTreeMap::new(),
+        };
+
+        for row in rows {
+            let (table_schema, table_name, column, is_view, table_comment, estimated_size) =
+                row.map_err(schema_error)?;
+
+            schema
+                .tables
+                .entry(table_name.to_owned())
+                .or_insert_with(|| SchemaTable {
+                    kind: match is_view {
+                        true => TableKind::View,
+                        false => TableKind::Table,
+                    },
+                    comment: comment(table_comment),
+                    row_estimate: row_estimate(estimated_size),
+                    ..SchemaTable::new(table_name, table_schema)
+                })
+                .columns
+                .push(column);
+        }
+
+        // constraints, a row per column
+        let sql = "
+            select table_name, constraint_type, constraint_name, referenced_table,
+                unnest(constraint_column_names) as column_name,
+                unnest(referenced_column_names) as referenced_column_name,
+                generate_subscripts(constraint_column_names, 1) as position
+            from duckdb_constraints()
+            where database_name = current_database()
+                and constraint_type in ('PRIMARY KEY', 'FOREIGN KEY', 'UNIQUE')
+            order by schema_name, table_name, constraint_index, position";
+
+        let mut statement = conn.prepare(sql).map_err(schema_error)?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            })
+            .map_err(schema_error)?;
+
+        for row in rows {
+            let (table_name, constraint_type, name, foreign_table, column, foreign_column) =
+                row.map_err(schema_error)?;
+
+            match constraint_type.as_str() {
+                "PRIMARY KEY" => schema.add_primary_key_column(&table_name, column),
+                "FOREIGN KEY" => {
+                    // DuckDB doesn't allow references across schemas
+                    let foreign_schema = schema
+                        .tables
+                        .get(&table_name)
+                        .map(|table| table.schema.to_owned())
+                        .unwrap_or_default();
+
+                    schema.add_foreign_key(
+                        &table_name,
+                        SchemaForeignKey {
+                            name,
+                            columns: vec![column],
+                            foreign_schema,
+                            foreign_table: foreign_table.unwrap_or_default(),
+                            foreign_columns: vec![foreign_column.unwrap_or_default()],
+                        },
+                    );
+                }
+                _ => schema.add_index(
+                    &table_name,
+                    SchemaIndex {
+                        name,
+                        columns: vec![column],
+                        is_unique: true,
+                    },
+                ),
+            }
+        }
+
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+
+    use super::*;
+    use crate::sql::parquet_writer::QuerySummary;
+    use arrow::datatypes::DataType;
+    use arrow::record_batch::RecordBatch;
+    use bytes::Bytes;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::TempDir;
+
+    /// Create a DuckDB database file with a table of common data types
+    pub fn new_duckdb_connection(temp_dir: &TempDir) -> DuckdbConnection {
+        let path = temp_dir.path().join("duckdb-connection.duckdb");
+        let conn = DuckdbConn::open(&path).unwrap();
+
+        conn.execute_batch(
+            "
+            create table all_native_data_types (
+                id integer primary key,
+                bigint_col bigint,
+                double_col double,
+                varchar_col varchar,
+                boolean_col boolean,
+                date_col date,
+                timestamp_col timestamp
+            );
+            insert into all_native_data_types values
+                (1, 9223372036854775807, 123.45, 'varchar_data', true, '2024-05-28', '2024-05-28 12:34:56'),
+                (2, null, null, null, null, null, null);
+            create table data_type_notes (
+                id integer primary key,
+                data_type_id integer references all_native_data_types(id),
+                note varchar
+            );
+            create view varchar_values as select id, varchar_col from all_native_data_types;
+            comment on table all_native_data_types is 'Common data types';",
+        )
+        .unwrap();
+
+        DuckdbConnection::new("duckdb-connection.duckdb".into())
+            .with_directory(Some(temp_dir.path()))
+    }
+
+    fn read_parquet(bytes: Bytes) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_connection() {
+        let temp_dir = TempDir::new().unwrap();
+        let connection = new_duckdb_connection(&temp_dir);
+
+        assert!(connection.connect().await.is_ok());
+
+        for database in [
+            "missing.duckdb",
+            "../duckdb-connection.duckdb",
+            "/etc/passwd",
+        ] {
+            let connection =
+                DuckdbConnection::new(database.into()).with_directory(Some(temp_dir.path()));
+            assert!(connection.connect().await.is_err());
+        }
+
+        // database files are disabled without a directory
+        let connection = DuckdbConnection::new("duckdb-connection.duckdb".into());
+        assert!(connection.connect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_has_no_external_access() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+
+        for sql in [
+            "select * from read_text('/etc/passwd')",
+            "select * from read_csv('/etc/passwd')",
+            "attach '/tmp/other.duckdb' as other",
+            "install httpfs",
+            "copy all_native_data_types to '/tmp/out.csv'",
+            "set enable_external_access = true",
+            "set lock_configuration = false",
+        ] {
+            let result = connection.query(&mut conn, sql, &[], None).await;
+            assert!(result.is_err(), "expected {sql} to fail");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_is_read_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+        let result = connection
+            .query(&mut conn, "delete from all_native_data_types", &[], None)
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_query_to_parquet() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+        let sql = "select * from all_native_data_types order by id";
+
+        let (bytes, over_the_limit, num_records) =
+            connection.query(&mut conn, sql, &[], None).await.unwrap();
+        assert!(!over_the_limit);
+        assert_eq!(num_records, 2);
+
+        let batches = read_parquet(bytes);
+        let schema = batches[0].schema();
+        let data_types = schema
+            .fields()
+            .iter()
+            .map(|field| field.data_type().clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(data_types[0], DataType::Int32);
+        assert_eq!(data_types[1], DataType::Int64);
+        assert_eq!(data_types[2], DataType::Float64);
+        assert_eq!(data_types[3], DataType::Utf8);
+        assert_eq!(data_types[4], DataType::Boolean);
+        assert_eq!(data_types[5], DataType::Date32);
+
+        let (_, over_the_limit, num_records) = connection
+            .query(&mut conn, sql, &[], Some(1))
+            .await
+            .unwrap();
+        assert!(over_the_limit);
+        assert_eq!(num_records, 0);
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_query_stream_total_records() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+        let sql = "select * from all_native_data_types order by id";
+        let mut writer = ParquetBatchWriter::new(Vec::new(), Some(1));
+
+        let total_records = connection
+            .query_stream(&mut conn, sql, &[], &mut writer)
+            .await
+            .unwrap();
+        assert_eq!(total_records, Some(2));
+
+        let (parquet, _) = writer.finish(total_records).unwrap();
+        let summary = QuerySummary::from_parquet(parquet.into()).unwrap().unwrap();
+
+        assert!(summary.over_the_limit);
+        assert_eq!(summary.num_records, 0);
+        assert_eq!(summary.total_records, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_cancel_query() {
+        use futures_util::FutureExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+        let sql = "select sum(a.range * b.range) from range(100000000) a, range(100000000) b";
+        let cancel = connection
+            .cancel_handle(&mut conn, sql, &[])
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the query blocks this task, so it's interrupted from another thread
+        let interrupt = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            cancel.cancel().now_or_never().unwrap().unwrap();
+        });
+
+        let mut writer = ParquetBatchWriter::new(Vec::new(), None);
+        let result = connection
+            .query_stream(&mut conn, sql, &[], &mut writer)
+            .await;
+        interrupt.join().unwrap();
+
+        assert!(result.unwrap_err().to_string().contains("INTERRUPT"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_duckdb_query_does_not_block_the_runtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+        let sql = "select sum(a.range * b.range) from range(100000000) a, range(100000000) b";
+        let cancel = connection
+            .cancel_handle(&mut conn, sql, &[])
+            .await
+            .unwrap()
+            .unwrap();
+
+        let query = tokio::spawn(async move {
+            let mut writer = ParquetBatchWriter::new(Vec::new(), None);
+            connection
+                .query_stream(&mut conn, sql, &[], &mut writer)
+                .await
+                .map(|_| ())
+        });
+
+        // with a single worker, the timer only fires if the query hands it off
+        let interrupt = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            cancel.cancel().await.unwrap();
+        });
+
+        let result = query.await.unwrap();
+        interrupt.await.unwrap();
+
+        assert!(result.unwrap_err().to_string().contains("INTERRUPT"));
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_query_with_parameters() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+        let sql = "select id from all_native_data_types where varchar_col = $1 or id in ($2, $3)";
+        let params = vec![
+            SqlParameter::Text("x' or '1'='1".into()),
+            SqlParameter::Number(3.into()),
+            SqlParameter::Number(1.into()),
+        ];
+
+        let (_, _, num_records) = connection
+            .query(&mut conn, sql, &params, None)
+            .await
+            .unwrap();
+        assert_eq!(num_records, 1);
+    }
+
+    #[tokio::test]
+    async fn test_duckdb_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let connection = new_duckdb_connection(&temp_dir);
+        let mut conn = connection.connect().await.unwrap();
+        let schema = connection.schema(&mut conn).await.unwrap();
+
+        assert_eq!(schema.database, "duckdb-connection");
+
+        let table = &schema.tables["all_native_data_types"];
+        assert_eq!(table.schema, "main");
+
+        let column = |name: &str, r#type: &str, is_nullable: bool| SchemaColumn {
+            name: name.into(),
+            r#type: r#type.into(),
+            is_nullable,
+            comment: None,
+        };
+        let expected = vec![
+            column("id", "INTEGER", false),
+            column("bigint_col", "BIGINT", true),
+            column("double_col", "DOUBLE", true),
+            column("varchar_col", "VARCHAR", true),
+            column("boolean_col", "BOOLEAN", true),
+            column("date_col", "DATE", true),
+            column("timestamp_col", "TIMESTAMP", true),
+        ];
+
+        assert_eq!(table.columns, expected);
+        assert_eq!(table.kind, TableKind::Table);
+        assert_eq!(table.comment, Some("Common data types".into()));
+        assert_eq!(table.primary_key, vec!["id"]);
+
+        let notes = &schema.tables["data_type_notes"];
+        assert_eq!(notes.foreign_keys.len(), 1);
+        assert_eq!(notes.foreign_keys[0].columns, vec!["data_type_id"]);
+        assert_eq!(notes.foreign_keys[0].foreign_table, "all_native_data_types");
+        assert_eq!(notes.foreign_keys[0].foreign_columns, vec!["id"]);
+
+        let view = &schema.tables["varchar_values"];
+        assert_eq!(view.kind, TableKind::View);
+        assert_eq!(view.row_estimate, None);
+    }
+}
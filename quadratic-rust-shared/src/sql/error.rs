@@ -17,6 +17,12 @@ pub enum Sql {
     #[error("Error executing query: {0}")]
     Query(String),
 
+    #[error("Read-only connection: {0}")]
+    ReadOnly(String),
+
     #[error("Error creating schema: {0}")]
     Schema(String),
+
+    #[error("TLS error connecting to database: {0}")]
+    Tls(String),
 }
@@ -130,156 +130,187 @@ mod tests {
                 name: "id".into(),
                 r#type: "int".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "tinyint_col".into(),
                 r#type: "tinyint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "smallint_col".into(),
                 r#type: "smallint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mediumint_col".into(),
                 r#type: "mediumint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "int_col".into(),
                 r#type: "int".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigint_col".into(),
                 r#type: "bigint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "decimal_col".into(),
                 r#type: "decimal".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "float_col".into(),
                 r#type: "float".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "double_col".into(),
                 r#type: "double".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bit_col".into(),
                 r#type: "bit".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "char_col".into(),
                 r#type: "char".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varchar_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "binary_col".into(),
                 r#type: "binary".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varbinary_col".into(),
                 r#type: "varbinary".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "tinyblob_col".into(),
                 r#type: "tinyblob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "blob_col".into(),
                 r#type: "blob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mediumblob_col".into(),
                 r#type: "mediumblob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "longblob_col".into(),
                 r#type: "longblob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "tinytext_col".into(),
                 r#type: "tinytext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "text_col".into(),
                 r#type: "text".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mediumtext_col".into(),
                 r#type: "mediumtext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "longtext_col".into(),
                 r#type: "longtext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "enum_col".into(),
                 r#type: "enum".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "set_col".into(),
                 r#type: "set".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "date_col".into(),
                 r#type: "date".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "datetime_col".into(),
                 r#type: "datetime".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamp_col".into(),
                 r#type: "timestamp".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "time_col".into(),
                 r#type: "time".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "year_col".into(),
                 r#type: "year".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "json_col".into(),
                 r#type: "longtext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "null_bool_col".into(),
                 r#type: "tinyint".into(),
                 is_nullable: true,
+                comment: None,
             },
         ];
 
@@ -1,35 +1,41 @@
 //! Accessing SQL databases that implement the Connection trait
 
-use arrow::{
-    array::{ArrayRef, RecordBatch},
-    datatypes::{Schema as ArrowSchema, *},
-};
 use async_trait::async_trait;
 use bytes::Bytes;
 use error::Sql as SqlError;
-use parquet::arrow::ArrowWriter;
+use parameter::SqlParameter;
+use parquet_writer::ParquetBatchWriter;
 use schema::DatabaseSchema;
 use snowflake_connection::SnowflakeConnection;
-use std::sync::Arc;
+use std::io::Write;
+use std::time::Duration;
 
 use crate::{SharedError, arrow::arrow_type::ArrowType, error::Result};
 
 use self::{
-    bigquery_connection::BigqueryConnection, mssql_connection::MsSqlConnection,
-    mysql_connection::MySqlConnection, postgres_connection::PostgresConnection,
+    bigquery_connection::BigqueryConnection, duckdb_connection::DuckdbConnection,
+    mssql_connection::MsSqlConnection, mysql_connection::MySqlConnection,
+    postgres_connection::PostgresConnection, sqlite_connection::SqliteConnection,
 };
 
 pub mod bigquery_connection;
 pub mod cockroachdb_connection;
+pub mod database_file;
 pub mod datafusion_connection;
+pub mod duckdb_connection;
 pub mod error;
 pub mod mariadb_connection;
 pub mod mssql_connection;
 pub mod mysql_connection;
 pub mod neon_connection;
+pub mod parameter;
+pub mod parquet_writer;
 pub mod postgres_connection;
 pub mod schema;
 pub mod snowflake_connection;
+pub mod sqlite_connection;
+pub mod statement;
+pub mod tls;
 
 pub fn query_error(e: impl ToString) -> SharedError {
     SharedError::Sql(SqlError::Query(e.to_string()))
@@ -43,32 +49,107 @@ pub fn connect_error(e: impl ToString) -> SharedError {
     SharedError::Sql(SqlError::Connect(e.to_string()))
 }
 
+pub fn tls_error(e: impl ToString) -> SharedError {
+    SharedError::Sql(SqlError::Tls(e.to_string()))
+}
+
 pub enum SqlConnection {
     BigqueryConnection(BigqueryConnection),
     Mssql(MsSqlConnection),
     Mysql(MySqlConnection),
     Postgres(PostgresConnection),
     SnowflakeConnection(SnowflakeConnection),
+    Sqlite(SqliteConnection),
+    Duckdb(DuckdbConnection),
+}
+
+/// Cancels a running query from outside of the task running it
+#[async_trait]
+pub trait CancelQuery: Send + Sync {
+    /// Ask the database to stop the query
+    async fn cancel(&self) -> Result<()>;
 }
 
 #[async_trait]
 pub trait Connection<'a> {
-    type Conn;
+    type Conn: Send;
     type Row;
     type Column;
 
     // Connect to a database
     async fn connect(&self) -> Result<Self::Conn>;
 
-    /// Generically query a database
+    /// Check that a connection that has been idle can still be used
+    async fn health_check(&self, _pool: &mut Self::Conn) -> Result<()> {
+        Ok(())
+    }
+
+    /// Reset a session before it's reused, so that a query doesn't see the
+    /// session state (settings, variables, temporary tables, etc.) left by an
+    /// earlier one.  Sessions that can't be reset aren't reused.
+    async fn reset(&self, _pool: &mut Self::Conn) -> Result<()> {
+        Err(SharedError::Sql(SqlError::Connect(
+            "Sessions of this database can't be reset".into(),
+        )))
+    }
+
+    /// Whether the connection only allows queries that read data.  Their
+    /// statements are checked with `statement::check_read_only` before
+    /// they're run.
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// How long the connection's query results are cached, when they're
+    /// cached
+    fn cache_ttl(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Get a handle that cancels `sql` once it's running on `pool`, if the
+    /// database supports cancelling queries.  This is called before the query
+    /// starts.
+    ///
+    /// Dropping a query's future stops reading its results, but a database
+    /// server may keep running it.
+    async fn cancel_handle(
+        &self,
+        _pool: &mut Self::Conn,
+        _sql: &str,
+        _params: &[SqlParameter],
+    ) -> Result<Option<Box<dyn CancelQuery>>> {
+        Ok(None)
+    }
+
+    /// Generically query a database, binding `params` to the query's
+    /// placeholders and streaming the results to `writer` in bounded batches
+    ///
+    /// Returns: the total number of records in the result, if the database
+    /// reports it
+    async fn query_stream<W: Write + Send>(
+        &mut self,
+        pool: &mut Self::Conn,
+        sql: &str,
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>>;
+
+    /// Query a database into an in-memory Parquet file
     ///
     /// Returns: (Parquet bytes, is over the limit, number of records)
     async fn query(
         &mut self,
         pool: &mut Self::Conn,
         sql: &str,
+        params: &[SqlParameter],
         max_bytes: Option<u64>,
-    ) -> Result<(Bytes, bool, usize)>;
+    ) -> Result<(Bytes, bool, usize)> {
+        let mut writer = ParquetBatchWriter::new(Vec::new(), max_bytes);
+        let total_records = self.query_stream(pool, sql, params, &mut writer).await?;
+        let (parquet, summary) = writer.finish(total_records)?;
+
+        Ok((parquet.into(), summary.over_the_limit, summary.num_records))
+    }
 
     /// Get the number of columns in a row
     fn row_len(row: &Self::Row) -> usize;
@@ -85,62 +166,43 @@ pub trait Connection<'a> {
     /// Convert a database-specific column to an Arrow type
     fn to_arrow(&self, row: &Self::Row, col: &Self::Column, col_index: usize) -> ArrowType;
 
-    /// Default implementation of converting a vec of rows to a Parquet byte array
+    /// Convert a database row to Arrow values and write it to `writer`
     ///
-    /// Returns: (Parquet bytes, number of records)
-    fn to_parquet(&'a self, data: Vec<Self::Row>) -> Result<(Bytes, usize)> {
-        if data.is_empty() {
-            return Ok((Bytes::new(), 0));
+    /// Returns false once the writer is over the byte limit
+    fn write_row<W: Write + Send>(
+        &self,
+        row: &Self::Row,
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<bool> {
+        if !writer.has_column_names() {
+            let column_names = Self::row_columns(row)
+                .enumerate()
+                .map(|(index, col)| self.column_name(col, index))
+                .collect();
+            writer.set_column_names(column_names);
         }
 
-        let col_count = Self::row_len(&data[0]);
+        let values = Self::row_columns(row)
+            .enumerate()
+            .map(|(col_index, col)| self.to_arrow(row, col, col_index))
+            .collect();
 
-        // transpose columns to rows, converting to Arrow types
-        let mut transposed = vec![vec![]; col_count];
+        writer.push_row(values)
+    }
+
+    /// Default implementation of converting a vec of rows to a Parquet byte array
+    ///
+    /// Returns: (Parquet bytes, number of records)
+    fn to_parquet(&'a self, data: Vec<Self::Row>) -> Result<(Bytes, usize)> {
+        let mut writer = ParquetBatchWriter::new(Vec::new(), None);
 
         for row in &data {
-            for (col_index, col) in Self::row_columns(row).enumerate() {
-                let value = self.to_arrow(row, col, col_index);
-                transposed[col_index].push(value);
-            }
+            self.write_row(row, &mut writer)?;
         }
 
-        let file = Vec::new();
-        let cols = transposed
-            .into_iter()
-            .map(ArrowType::to_array_ref)
-            .collect::<Vec<ArrayRef>>();
+        let (parquet, summary) = writer.finish(None)?;
 
-        // headings
-        let fields = Self::row_columns(&data[0])
-            .enumerate()
-            .map(|(index, col)| {
-                Field::new(
-                    self.column_name(col, index).to_string(),
-                    cols[index].data_type().to_owned(),
-                    true,
-                )
-            })
-            .collect::<Vec<Field>>();
-
-        // for (index, col) in cols.iter().enumerate() {
-        //     println!(
-        //         "{} ({}) = {:?}",
-        //         fields[index].name(),
-        //         fields[index].data_type(),
-        //         col
-        //     );
-        // }
-
-        let schema = ArrowSchema::new(fields);
-        let mut writer = ArrowWriter::try_new(file, Arc::new(schema.clone()), None)?;
-
-        let record_batch = RecordBatch::try_new(Arc::new(schema), cols)?;
-        let record_count = record_batch.num_rows();
-        writer.write(&record_batch)?;
-        let parquet = writer.into_inner()?;
-
-        Ok((parquet.into(), record_count))
+        Ok((parquet.into(), summary.num_records))
     }
 }
 
@@ -3,18 +3,19 @@
 //! Functions to interact with Microsoft SQL Server
 
 use std::collections::BTreeMap;
+use std::io::Write;
 use std::str::FromStr;
+use std::time::Duration;
 
 use arrow::datatypes::Date32Type;
 use async_trait::async_trait;
-use bytes::Bytes;
 use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use futures_util::{StreamExt, TryStreamExt};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use tiberius::ColumnData;
 use tiberius::xml::XmlData;
-use tiberius::{AuthMethod, Client, Column, Config, FromSql, FromSqlOwned, Row};
+use tiberius::{AuthMethod, Client, Column, Config, FromSql, FromSqlOwned, Query, Row};
 use tokio::net::TcpStream;
 use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
 use uuid::Uuid;
@@ -25,7 +26,13 @@ use crate::net::ssh::SshConfig;
 use crate::quadratic_api::Connection as ApiConnection;
 use crate::sql::Connection;
 use crate::sql::error::Sql as SqlError;
-use crate::sql::schema::{DatabaseSchema, SchemaColumn, SchemaTable};
+use crate::sql::parameter::SqlParameter;
+use crate::sql::parquet_writer::ParquetBatchWriter;
+use crate::sql::schema::{
+    DatabaseSchema, SchemaColumn, SchemaForeignKey, SchemaIndex, SchemaTable, TableKind, comment,
+    row_estimate,
+};
+use crate::sql::tls::TlsConfig;
 
 use super::UsesSsh;
 
@@ -43,6 +50,16 @@ pub struct MsSqlConnection {
     pub ssh_port: Option<String>,
     pub ssh_username: Option<String>,
     pub ssh_key: Option<String>,
+    #[serde(flatten)]
+    pub tls: TlsConfig,
+
+    /// Only allow queries that read data
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Cache query results for this many seconds
+    #[serde(default)]
+    pub cache_ttl_seconds: Option<u64>,
 }
 
 impl From<&ApiConnection<MsSqlConnection>> for MsSqlConnection {
@@ -60,6 +77,9 @@ impl From<&ApiConnection<MsSqlConnection>> for MsSqlConnection {
             details.ssh_username,
             details.ssh_key,
         )
+        .with_tls(details.tls)
+        .with_read_only(details.read_only)
+        .with_cache_ttl(details.cache_ttl_seconds)
     }
 }
 
@@ -114,14 +134,74 @@ impl MsSqlConnection {
             ssh_port,
             ssh_username,
             ssh_key,
+            tls: TlsConfig::default(),
+            read_only: false,
+            cache_ttl_seconds: None,
         }
     }
 
+    /// Set the TLS settings
+    pub fn with_tls(self, tls: TlsConfig) -> MsSqlConnection {
+        MsSqlConnection { tls, ..self }
+    }
+
+    /// Only allow queries that read data
+    pub fn with_read_only(self, read_only: bool) -> MsSqlConnection {
+        MsSqlConnection { read_only, ..self }
+    }
+
+    /// Cache query results for this many seconds
+    pub fn with_cache_ttl(self, cache_ttl_seconds: Option<u64>) -> MsSqlConnection {
+        MsSqlConnection {
+            cache_ttl_seconds,
+            ..self
+        }
+    }
+
+    /// Create a query with the parameters bound to its `@P1`, `@P2`, ...
+    /// placeholders
+    fn new_query<'b>(sql: &'b str, params: &[SqlParameter]) -> Query<'b> {
+        let mut query = Query::new(sql);
+
+        for param in params {
+            match param {
+                SqlParameter::Null => query.bind(None::<String>),
+                SqlParameter::Text(text) => query.bind(text.to_owned()),
+                SqlParameter::Number(number) => match param.as_integer() {
+                    Some(integer) => query.bind(integer),
+                    None => query.bind(*number),
+                },
+                SqlParameter::Logical(logical) => query.bind(*logical),
+                SqlParameter::Date(date) => query.bind(*date),
+                SqlParameter::Time(time) => query.bind(*time),
+                SqlParameter::DateTime(date_time) => query.bind(*date_time),
+            }
+        }
+
+        query
+    }
+
+    /// Query all rows of a schema query
+    async fn schema_rows(client: &mut Client<Compat<TcpStream>>, sql: String) -> Result<Vec<Row>> {
+        client
+            .query(sql, &[])
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Schema(e.to_string())))?
+            .into_row_stream()
+            .try_collect()
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Schema(e.to_string())))
+    }
+
     /// Query all rows from a SQL Server
-    async fn query_all(client: &mut Client<Compat<TcpStream>>, sql: &str) -> Result<Vec<Row>> {
+    pub async fn query_all(
+        client: &mut Client<Compat<TcpStream>>,
+        sql: &str,
+        params: &[SqlParameter],
+    ) -> Result<Vec<Row>> {
         let mut rows = vec![];
-        let mut row_stream = client
-            .query(sql, &[])
+        let mut row_stream = Self::new_query(sql, params)
+            .query(client)
             .await
             .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?
             .into_row_stream();
@@ -177,9 +257,16 @@ impl<'a> Connection<'a> for MsSqlConnection {
             ));
         }
 
-        config.trust_cert();
+        // SQL Server doesn't have read-only transactions, a read-only
+        // application intent only routes the connection to a readable
+        // secondary replica (in an availability group)
+        config.readonly(self.read_only);
+
+        // connect to the host, which may differ from the TLS server name
+        let addr = config.get_addr();
+        let _ca_file = self.tls.mssql_config(&mut config, &self.host)?;
 
-        let tcp = TcpStream::connect(config.get_addr())
+        let tcp = TcpStream::connect(addr)
             .await
             .map_err(|e| SharedError::Sql(SqlError::Connect(format!("Failed to connect: {e}"))))?;
         tcp.set_nodelay(true).map_err(|e| {
@@ -188,88 +275,98 @@ impl<'a> Connection<'a> for MsSqlConnection {
 
         let client = Client::connect(config, tcp.compat_write())
             .await
-            .map_err(|e| {
-                SharedError::Sql(SqlError::Connect(format!("Failed to create client: {e}")))
-            })?;
+            .map_err(|e| self.tls.tiberius_connect_error(e))?;
 
         Ok(client)
     }
 
+    /// Run a trivial query
+    async fn health_check(&self, pool: &mut Self::Conn) -> Result<()> {
+        pool.simple_query("SELECT 1")
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Connect(e.to_string())))?
+            .into_results()
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Connect(e.to_string())))?;
+
+        Ok(())
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl_seconds.map(Duration::from_secs)
+    }
+
     /// Query rows from a SQL Server
-    async fn query(
+    ///
+    /// Tiberius doesn't expose sending an attention, so there is no cancel
+    /// handle.  A cancelled query's client is dropped, which closes the
+    /// connection and makes the server abort the batch.
+    async fn query_stream<W: Write + Send>(
         &mut self,
         client: &mut Self::Conn,
         sql: &str,
-        max_bytes: Option<u64>,
-    ) -> Result<(Bytes, bool, usize)> {
-        let mut rows = vec![];
-        let mut over_the_limit = false;
-
-        if let Some(max_bytes) = max_bytes {
-            let mut bytes = 0;
-
-            let mut row_stream = client
-                .query(sql, &[])
-                .await
-                .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?
-                .into_row_stream();
-
-            while let Some(row_result) = row_stream.next().await {
-                match row_result {
-                    Ok(row) => {
-                        bytes += row.len() as u64;
-
-                        if bytes > max_bytes {
-                            over_the_limit = true;
-                            break;
-                        }
-
-                        rows.push(row);
-                    }
-                    Err(e) => return Err(SharedError::Sql(SqlError::Query(e.to_string()))),
-                }
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
+        let mut row_stream = Self::new_query(sql, params)
+            .query(client)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?
+            .into_row_stream();
+
+        while let Some(row_result) = row_stream.next().await {
+            let row = row_result.map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
+
+            // stop fetching rows once the result is over the byte limit
+            if !self.write_row(&row, writer)? {
+                break;
             }
-        } else {
-            rows = Self::query_all(client, sql).await?;
         }
 
-        let (bytes, num_records) = self.to_parquet(rows)?;
-
-        Ok((bytes, over_the_limit, num_records))
+        Ok(None)
     }
 
     /// Get the schema of a SQL Server
     async fn schema(&self, client: &mut Self::Conn) -> Result<DatabaseSchema> {
         let database = self.database.to_owned();
+
+        // tables and views, with their columns
         let sql = format!(
             "
 SELECT
-    DB_NAME() AS 'database',
     s.name AS 'schema',
-    t.name AS 'table',
+    o.name AS 'table',
+    o.type AS 'table_type',
+    CAST(tp.value AS NVARCHAR(MAX)) AS 'table_comment',
+    p.row_estimate AS 'row_estimate',
     c.name AS 'column_name',
     TYPE_NAME(c.user_type_id) AS 'column_type',
-    CASE WHEN c.is_nullable = 1 THEN 'YES' ELSE 'NO' END AS 'is_nullable'
+    CASE WHEN c.is_nullable = 1 THEN 'YES' ELSE 'NO' END AS 'is_nullable',
+    CAST(cp.value AS NVARCHAR(MAX)) AS 'column_comment'
 FROM
     {database}.sys.columns c
 INNER JOIN
-    {database}.sys.tables t ON c.object_id = t.object_id
+    {database}.sys.objects o ON c.object_id = o.object_id AND o.type IN ('U', 'V') AND o.is_ms_shipped = 0
 INNER JOIN
-    {database}.sys.schemas s ON t.schema_id = s.schema_id
+    {database}.sys.schemas s ON o.schema_id = s.schema_id
+LEFT JOIN
+    {database}.sys.extended_properties tp
+        ON tp.class = 1 AND tp.major_id = o.object_id AND tp.minor_id = 0 AND tp.name = 'MS_Description'
+LEFT JOIN
+    {database}.sys.extended_properties cp
+        ON cp.class = 1 AND cp.major_id = o.object_id AND cp.minor_id = c.column_id AND cp.name = 'MS_Description'
+LEFT JOIN
+    (SELECT object_id, SUM(rows) AS row_estimate FROM {database}.sys.partitions WHERE index_id IN (0, 1) GROUP BY object_id) p
+        ON p.object_id = o.object_id
 ORDER BY
-    t.name, c.column_id, c.name"
+    o.name, c.column_id, c.name"
         );
 
-        let row_stream = client
-            .query(sql, &[])
-            .await
-            .map_err(|e| SharedError::Sql(SqlError::Schema(e.to_string())))?
-            .into_row_stream();
-
-        let rows: Vec<Row> = row_stream
-            .try_collect()
-            .await
-            .map_err(|e| SharedError::Sql(SqlError::Schema(e.to_string())))?;
+        let rows = Self::schema_rows(client, sql).await?;
 
         let mut schema = DatabaseSchema {
             database: self.database.to_owned(),
@@ -281,24 +378,133 @@ ORDER BY
                 data.map(|s| s.to_string())
                     .unwrap_or(format!("Unknown {kind} - {index}"))
             };
-            let table_name = safe_get(row.get(2), "Table");
+            let table_name = safe_get(row.get(1), "Table");
 
             schema
                 .tables
                 .entry(table_name.to_owned())
                 .or_insert_with(|| SchemaTable {
-                    name: table_name,
-                    schema: safe_get(row.get(1), "Schema"),
-                    columns: vec![],
+                    kind: match row.get::<&str, usize>(2).map(str::trim) {
+                        Some("V") => TableKind::View,
+                        _ => TableKind::Table,
+                    },
+                    comment: comment(row.get::<&str, usize>(3).map(str::to_string)),
+                    row_estimate: row_estimate(row.get::<i64, usize>(4)),
+                    ..SchemaTable::new(table_name, safe_get(row.get(0), "Schema"))
                 })
                 .columns
                 .push(SchemaColumn {
-                    name: safe_get(row.get(3), "Column"),
-                    r#type: safe_get(row.get(4), "Type"),
-                    is_nullable: row.get(5).map_or("NO", |v| v).to_uppercase() == "YES",
+                    name: safe_get(row.get(5), "Column"),
+                    r#type: safe_get(row.get(6), "Type"),
+                    is_nullable: row.get(7).map_or("NO", |v| v).to_uppercase() == "YES",
+                    comment: comment(row.get::<&str, usize>(8).map(str::to_string)),
                 });
         }
 
+        let string = |row: &Row, index: usize| {
+            row.get::<&str, usize>(index)
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        // primary keys, a row per column
+        let sql = format!(
+            "
+SELECT
+    t.name AS 'table',
+    c.name AS 'column_name'
+FROM
+    {database}.sys.key_constraints kc
+INNER JOIN
+    {database}.sys.tables t ON t.object_id = kc.parent_object_id
+INNER JOIN
+    {database}.sys.index_columns ic ON ic.object_id = kc.parent_object_id AND ic.index_id = kc.unique_index_id
+INNER JOIN
+    {database}.sys.columns c ON c.object_id = ic.object_id AND c.column_id = ic.column_id
+WHERE
+    kc.type = 'PK'
+ORDER BY
+    t.name, ic.key_ordinal"
+        );
+
+        for row in Self::schema_rows(client, sql).await? {
+            schema.add_primary_key_column(&string(&row, 0), string(&row, 1));
+        }
+
+        // foreign keys, a row per column
+        let sql = format!(
+            "
+SELECT
+    t.name AS 'table',
+    fk.name AS 'name',
+    c.name AS 'column_name',
+    rs.name AS 'foreign_schema',
+    rt.name AS 'foreign_table',
+    rc.name AS 'foreign_column'
+FROM
+    {database}.sys.foreign_key_columns fkc
+INNER JOIN
+    {database}.sys.foreign_keys fk ON fk.object_id = fkc.constraint_object_id
+INNER JOIN
+    {database}.sys.tables t ON t.object_id = fkc.parent_object_id
+INNER JOIN
+    {database}.sys.columns c ON c.object_id = fkc.parent_object_id AND c.column_id = fkc.parent_column_id
+INNER JOIN
+    {database}.sys.tables rt ON rt.object_id = fkc.referenced_object_id
+INNER JOIN
+    {database}.sys.schemas rs ON rs.schema_id = rt.schema_id
+INNER JOIN
+    {database}.sys.columns rc ON rc.object_id = fkc.referenced_object_id AND rc.column_id = fkc.referenced_column_id
+ORDER BY
+    t.name, fk.name, fkc.constraint_column_id"
+        );
+
+        for row in Self::schema_rows(client, sql).await? {
+            schema.add_foreign_key(
+                &string(&row, 0),
+                SchemaForeignKey {
+                    name: string(&row, 1),
+                    columns: vec![string(&row, 2)],
+                    foreign_schema: string(&row, 3),
+                    foreign_table: string(&row, 4),
+                    foreign_columns: vec![string(&row, 5)],
+                },
+            );
+        }
+
+        // indexes, a row per key column
+        let sql = format!(
+            "
+SELECT
+    t.name AS 'table',
+    i.name AS 'name',
+    CASE WHEN i.is_unique = 1 THEN 'YES' ELSE 'NO' END AS 'is_unique',
+    c.name AS 'column_name'
+FROM
+    {database}.sys.indexes i
+INNER JOIN
+    {database}.sys.tables t ON t.object_id = i.object_id
+INNER JOIN
+    {database}.sys.index_columns ic ON ic.object_id = i.object_id AND ic.index_id = i.index_id
+INNER JOIN
+    {database}.sys.columns c ON c.object_id = ic.object_id AND c.column_id = ic.column_id
+WHERE
+    i.name IS NOT NULL AND ic.is_included_column = 0
+ORDER BY
+    t.name, i.name, ic.key_ordinal"
+        );
+
+        for row in Self::schema_rows(client, sql).await? {
+            schema.add_index(
+                &string(&row, 0),
+                SchemaIndex {
+                    name: string(&row, 1),
+                    columns: vec![string(&row, 3)],
+                    is_unique: string(&row, 2) == "YES",
+                },
+            );
+        }
+
         Ok(schema)
     }
 
@@ -464,7 +670,7 @@ mod tests {
     async fn test_mssql_query_to_arrow() {
         let (connection, client) = setup().await;
         let sql = "SELECT TOP 1 * FROM [dbo].[all_native_data_types] ORDER BY id";
-        let rows = MsSqlConnection::query_all(&mut client.unwrap(), sql)
+        let rows = MsSqlConnection::query_all(&mut client.unwrap(), sql, &[])
             .await
             .unwrap();
 
@@ -573,166 +779,199 @@ mod tests {
                 name: "id".into(),
                 r#type: "int".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "tinyint_col".into(),
                 r#type: "tinyint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "smallint_col".into(),
                 r#type: "smallint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "int_col".into(),
                 r#type: "int".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigint_col".into(),
                 r#type: "bigint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bit_col".into(),
                 r#type: "bit".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "decimal_col".into(),
                 r#type: "decimal".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "numeric_col".into(),
                 r#type: "numeric".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "money_col".into(),
                 r#type: "money".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "smallmoney_col".into(),
                 r#type: "smallmoney".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "float_col".into(),
                 r#type: "float".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "real_col".into(),
                 r#type: "real".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "date_col".into(),
                 r#type: "date".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "time_col".into(),
                 r#type: "time".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "datetime2_col".into(),
                 r#type: "datetime2".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "datetimeoffset_col".into(),
                 r#type: "datetimeoffset".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "datetime_col".into(),
                 r#type: "datetime".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "smalldatetime_col".into(),
                 r#type: "smalldatetime".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "char_col".into(),
                 r#type: "char".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varchar_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "text_col".into(),
                 r#type: "text".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "nchar_col".into(),
                 r#type: "nchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "nvarchar_col".into(),
                 r#type: "nvarchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "ntext_col".into(),
                 r#type: "ntext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "binary_col".into(),
                 r#type: "binary".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varbinary_col".into(),
                 r#type: "varbinary".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "image_col".into(),
                 r#type: "image".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "json_col".into(),
                 r#type: "nvarchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "uniqueidentifier_col".into(),
                 r#type: "uniqueidentifier".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "xml_col".into(),
                 r#type: "xml".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varchar_max_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "nvarchar_max_col".into(),
                 r#type: "nvarchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varbinary_max_col".into(),
                 r#type: "varbinary".into(),
                 is_nullable: true,
+                comment: None,
             },
         ];
 
@@ -3,10 +3,11 @@
 //! Functions to interact with MySQL
 
 use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
 
 use arrow::datatypes::Date32Type;
 use async_trait::async_trait;
-use bytes::Bytes;
 use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime};
 use futures_util::StreamExt;
 use rust_decimal::Decimal;
@@ -22,9 +23,16 @@ use sqlx::{
 use crate::error::{Result, SharedError};
 use crate::quadratic_api::Connection as ApiConnection;
 use crate::sql::error::Sql as SqlError;
-use crate::sql::schema::{DatabaseSchema, SchemaColumn, SchemaTable};
-use crate::sql::{ArrowType, Connection};
-use crate::{convert_sqlx_type, net::ssh::SshConfig, sql::UsesSsh, to_arrow_type};
+use crate::sql::parameter::SqlParameter;
+use crate::sql::parquet_writer::ParquetBatchWriter;
+use crate::sql::schema::{
+    DatabaseSchema, SchemaColumn, SchemaForeignKey, SchemaIndex, SchemaTable, TableKind, comment,
+};
+use crate::sql::tls::TlsConfig;
+use crate::sql::{ArrowType, CancelQuery, Connection};
+use crate::{
+    bind_sqlx_parameters, convert_sqlx_type, net::ssh::SshConfig, sql::UsesSsh, to_arrow_type,
+};
 
 /// MySQL connection
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -40,6 +48,16 @@ pub struct MySqlConnection {
     pub ssh_port: Option<String>,
     pub ssh_username: Option<String>,
     pub ssh_key: Option<String>,
+    #[serde(flatten)]
+    pub tls: TlsConfig,
+
+    /// Only allow queries that read data
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Cache query results for this many seconds
+    #[serde(default)]
+    pub cache_ttl_seconds: Option<u64>,
 }
 
 impl From<&ApiConnection<MySqlConnection>> for MySqlConnection {
@@ -57,6 +75,9 @@ impl From<&ApiConnection<MySqlConnection>> for MySqlConnection {
             details.ssh_username,
             details.ssh_key,
         )
+        .with_tls(details.tls)
+        .with_read_only(details.read_only)
+        .with_cache_ttl(details.cache_ttl_seconds)
     }
 }
 
@@ -111,6 +132,41 @@ impl MySqlConnection {
             ssh_port,
             ssh_username,
             ssh_key,
+            tls: TlsConfig::default(),
+            read_only: false,
+            cache_ttl_seconds: None,
+        }
+    }
+
+    /// Set the TLS settings
+    pub fn with_tls(self, tls: TlsConfig) -> MySqlConnection {
+        MySqlConnection { tls, ..self }
+    }
+
+    /// Only allow queries that read data
+    pub fn with_read_only(self, read_only: bool) -> MySqlConnection {
+        MySqlConnection { read_only, ..self }
+    }
+
+    /// Have the database reject writes that statements can't be checked for
+    /// (e.g. functions that write).  This is set before every query, since a
+    /// pooled session may have been changed by an earlier query.
+    pub async fn set_read_only(&self, pool: &mut SqlxMySqlConnection) -> Result<()> {
+        if self.read_only {
+            sqlx::raw_sql("SET SESSION TRANSACTION READ ONLY")
+                .execute(pool)
+                .await
+                .map_err(|e| SharedError::Sql(SqlError::Connect(e.to_string())))?;
+        }
+
+        Ok(())
+    }
+
+    /// Cache query results for this many seconds
+    pub fn with_cache_ttl(self, cache_ttl_seconds: Option<u64>) -> MySqlConnection {
+        MySqlConnection {
+            cache_ttl_seconds,
+            ..self
         }
     }
 
@@ -125,6 +181,28 @@ impl MySqlConnection {
     }
 }
 
+/// Cancels a MySQL query with `KILL QUERY`, which is run from a new connection
+/// to the same server
+pub struct MySqlCancelQuery {
+    connection: MySqlConnection,
+    connection_id: u64,
+}
+
+#[async_trait]
+impl CancelQuery for MySqlCancelQuery {
+    async fn cancel(&self) -> Result<()> {
+        let mut pool = self.connection.connect().await?;
+
+        // KILL doesn't accept placeholders, the id is a number
+        sqlx::raw_sql(&format!("KILL QUERY {}", self.connection_id))
+            .execute(&mut pool)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl<'a> Connection<'a> for MySqlConnection {
     type Conn = SqlxMySqlConnection;
@@ -164,55 +242,88 @@ impl<'a> Connection<'a> for MySqlConnection {
             options = options.port(port?);
         }
 
-        let pool = options.connect().await.map_err(|e| {
-            SharedError::Sql(SqlError::Connect(format!("{:?}: {e}", self.database)))
-        })?;
+        options = self.tls.mysql_options(options, &self.host)?;
+
+        let mut pool = options
+            .connect()
+            .await
+            .map_err(|e| self.tls.sqlx_connect_error(e, &self.database))?;
+
+        self.set_read_only(&mut pool).await?;
 
         Ok(pool)
     }
 
+    /// Ping the server
+    async fn health_check(&self, pool: &mut Self::Conn) -> Result<()> {
+        sqlx::Connection::ping(pool)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Connect(e.to_string())))
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl_seconds.map(Duration::from_secs)
+    }
+
+    /// Get the id of the connection, used to cancel its query
+    async fn cancel_handle(
+        &self,
+        pool: &mut Self::Conn,
+        _sql: &str,
+        _params: &[SqlParameter],
+    ) -> Result<Option<Box<dyn CancelQuery>>> {
+        let connection_id = sqlx::query_scalar::<_, u64>("SELECT CONNECTION_ID()")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
+
+        Ok(Some(Box::new(MySqlCancelQuery {
+            connection: self.clone(),
+            connection_id,
+        })))
+    }
+
     /// Query rows from a MySQL database
-    async fn query(
+    async fn query_stream<W: Write + Send>(
         &mut self,
         pool: &mut Self::Conn,
         sql: &str,
-        max_bytes: Option<u64>,
-    ) -> Result<(Bytes, bool, usize)> {
-        let mut rows = vec![];
-        let mut over_the_limit = false;
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
+        self.set_read_only(pool).await?;
 
-        if let Some(max_bytes) = max_bytes {
-            let mut bytes = 0;
-            let mut stream = sqlx::query(sql).fetch(pool);
+        let mut stream = bind_sqlx_parameters!(sqlx::query(sql), params).fetch(pool);
 
-            while let Some(row) = stream.next().await {
-                let row = row.map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
-                bytes += row.len() as u64;
+        while let Some(row) = stream.next().await {
+            let row = row.map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
 
-                if bytes > max_bytes {
-                    over_the_limit = true;
-                    break;
-                }
-
-                rows.push(row);
+            // stop fetching rows once the result is over the byte limit
+            if !self.write_row(&row, writer)? {
+                break;
             }
-        } else {
-            rows = MySqlConnection::query_all(pool, sql).await?;
         }
 
-        let (bytes, num_records) = self.to_parquet(rows)?;
-
-        Ok((bytes, over_the_limit, num_records))
+        Ok(None)
     }
 
     /// Get the schema of a MySQL database
     async fn schema(&self, pool: &mut Self::Conn) -> Result<DatabaseSchema> {
         let database = self.database.to_owned();
+
+        // tables and views, with their columns
         let sql = format!("
-            select c.TABLE_SCHEMA as 'database', c.TABLE_SCHEMA as 'schema', c.TABLE_NAME as 'table', 
-                c.COLUMN_NAME as 'column_name', c.DATA_TYPE as 'column_type', c.IS_NULLABLE as 'is_nullable'
+            select c.TABLE_SCHEMA as 'schema', c.TABLE_NAME as 'table', t.TABLE_TYPE as 'table_type',
+                t.TABLE_COMMENT as 'table_comment', cast(t.TABLE_ROWS as char) as 'row_estimate',
+                c.COLUMN_NAME as 'column_name', c.DATA_TYPE as 'column_type', c.IS_NULLABLE as 'is_nullable',
+                c.COLUMN_COMMENT as 'column_comment'
             from INFORMATION_SCHEMA.COLUMNS as c
-            where table_schema = '{database}'
+                inner join INFORMATION_SCHEMA.TABLES as t on t.TABLE_SCHEMA = c.TABLE_SCHEMA and t.TABLE_NAME = c.TABLE_NAME
+            where c.TABLE_SCHEMA = '{database}'
             order by c.TABLE_NAME, c.ORDINAL_POSITION, c.COLUMN_NAME");
 
         let rows = MySqlConnection::query_all(pool, &sql).await?;
@@ -222,30 +333,90 @@ impl<'a> Connection<'a> for MySqlConnection {
             tables: BTreeMap::new(),
         };
 
-        for row in rows.into_iter() {
-            let row_get = |row: &MySqlRow, index: usize| {
-                let bytes: Vec<u8> = row.get::<Vec<u8>, usize>(index);
-                String::from_utf8_lossy(&bytes).into_owned()
-            };
+        // information_schema values may be returned as bytes
+        let row_get_optional = |row: &MySqlRow, index: usize| {
+            row.get::<Option<Vec<u8>>, usize>(index)
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        };
+        let row_get =
+            |row: &MySqlRow, index: usize| row_get_optional(row, index).unwrap_or_default();
 
-            let table_name = row_get(&row, 2);
+        for row in rows.into_iter() {
+            let table_name = row_get(&row, 1);
 
             schema
                 .tables
                 .entry(table_name.to_owned())
-                .or_insert_with(|| SchemaTable {
-                    name: table_name,
-                    schema: row_get(&row, 1),
-                    columns: vec![],
+                .or_insert_with(|| match row_get(&row, 2).as_str() {
+                    // the comment of a view is "VIEW"
+                    "VIEW" | "SYSTEM VIEW" => SchemaTable {
+                        kind: TableKind::View,
+                        ..SchemaTable::new(table_name, row_get(&row, 0))
+                    },
+                    _ => SchemaTable {
+                        comment: comment(row_get_optional(&row, 3)),
+                        row_estimate: row_get_optional(&row, 4).and_then(|rows| rows.parse().ok()),
+                        ..SchemaTable::new(table_name, row_get(&row, 0))
+                    },
                 })
                 .columns
                 .push(SchemaColumn {
-                    name: row_get(&row, 3),
-                    r#type: row_get(&row, 4),
-                    is_nullable: matches!(row_get(&row, 5).to_lowercase().as_str(), "yes"),
+                    name: row_get(&row, 5),
+                    r#type: row_get(&row, 6),
+                    is_nullable: matches!(row_get(&row, 7).to_lowercase().as_str(), "yes"),
+                    comment: comment(row_get_optional(&row, 8)),
                 });
         }
 
+        // primary and foreign keys, a row per column
+        let sql = format!("
+            select k.TABLE_NAME as 'table', k.CONSTRAINT_NAME as 'name', k.COLUMN_NAME as 'column_name',
+                k.REFERENCED_TABLE_SCHEMA as 'foreign_schema', k.REFERENCED_TABLE_NAME as 'foreign_table',
+                k.REFERENCED_COLUMN_NAME as 'foreign_column'
+            from INFORMATION_SCHEMA.KEY_COLUMN_USAGE as k
+            where k.TABLE_SCHEMA = '{database}'
+                and (k.CONSTRAINT_NAME = 'PRIMARY' or k.REFERENCED_TABLE_NAME is not null)
+            order by k.TABLE_NAME, k.CONSTRAINT_NAME, k.ORDINAL_POSITION");
+
+        for row in MySqlConnection::query_all(pool, &sql).await? {
+            let table_name = row_get(&row, 0);
+            let name = row_get(&row, 1);
+            let column = row_get(&row, 2);
+
+            match name.as_str() {
+                "PRIMARY" => schema.add_primary_key_column(&table_name, column),
+                _ => schema.add_foreign_key(
+                    &table_name,
+                    SchemaForeignKey {
+                        name,
+                        columns: vec![column],
+                        foreign_schema: row_get(&row, 3),
+                        foreign_table: row_get(&row, 4),
+                        foreign_columns: vec![row_get(&row, 5)],
+                    },
+                ),
+            }
+        }
+
+        // indexes, a row per column (expressions are skipped)
+        let sql = format!("
+            select s.TABLE_NAME as 'table', s.INDEX_NAME as 'name', if(s.NON_UNIQUE = 0, 'YES', 'NO') as 'is_unique',
+                s.COLUMN_NAME as 'column_name'
+            from INFORMATION_SCHEMA.STATISTICS as s
+            where s.TABLE_SCHEMA = '{database}' and s.COLUMN_NAME is not null
+            order by s.TABLE_NAME, s.INDEX_NAME, s.SEQ_IN_INDEX");
+
+        for row in MySqlConnection::query_all(pool, &sql).await? {
+            schema.add_index(
+                &row_get(&row, 0),
+                SchemaIndex {
+                    name: row_get(&row, 1),
+                    columns: vec![row_get(&row, 3)],
+                    is_unique: row_get(&row, 2) == "YES",
+                },
+            );
+        }
+
         Ok(schema)
     }
 
@@ -355,6 +526,29 @@ mod tests {
         assert!(pool.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_mysql_read_only_is_set_for_every_query() {
+        let mut connection = new_mysql_connection().with_read_only(true);
+        let mut pool = connection.connect().await.unwrap();
+
+        // an earlier query on a pooled session made it writable
+        sqlx::raw_sql("SET SESSION TRANSACTION READ WRITE")
+            .execute(&mut pool)
+            .await
+            .unwrap();
+
+        let result = connection
+            .query(
+                &mut pool,
+                "delete from all_native_data_types where 1 = 0",
+                &[],
+                None,
+            )
+            .await;
+
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn test_mysql_query_to_arrow() {
         let (connection, pool) = setup().await;
@@ -437,156 +631,187 @@ mod tests {
                 name: "id".into(),
                 r#type: "int".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "tinyint_col".into(),
                 r#type: "tinyint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "smallint_col".into(),
                 r#type: "smallint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mediumint_col".into(),
                 r#type: "mediumint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "int_col".into(),
                 r#type: "int".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigint_col".into(),
                 r#type: "bigint".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "decimal_col".into(),
                 r#type: "decimal".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "float_col".into(),
                 r#type: "float".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "double_col".into(),
                 r#type: "double".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bit_col".into(),
                 r#type: "bit".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "char_col".into(),
                 r#type: "char".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varchar_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "binary_col".into(),
                 r#type: "binary".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varbinary_col".into(),
                 r#type: "varbinary".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "tinyblob_col".into(),
                 r#type: "tinyblob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "blob_col".into(),
                 r#type: "blob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mediumblob_col".into(),
                 r#type: "mediumblob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "longblob_col".into(),
                 r#type: "longblob".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "tinytext_col".into(),
                 r#type: "tinytext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "text_col".into(),
                 r#type: "text".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "mediumtext_col".into(),
                 r#type: "mediumtext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "longtext_col".into(),
                 r#type: "longtext".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "enum_col".into(),
                 r#type: "enum".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "set_col".into(),
                 r#type: "set".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "date_col".into(),
                 r#type: "date".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "datetime_col".into(),
                 r#type: "datetime".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamp_col".into(),
                 r#type: "timestamp".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "time_col".into(),
                 r#type: "time".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "year_col".into(),
                 r#type: "year".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "json_col".into(),
                 r#type: "json".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "null_bool_col".into(),
                 r#type: "tinyint".into(),
                 is_nullable: true,
+                comment: None,
             },
         ];
 
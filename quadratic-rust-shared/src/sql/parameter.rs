@@ -0,0 +1,239 @@
+//! Query Parameters
+//!
+//! Typed values bound to the placeholders of a query.  Quadratic replaces
+//! handlebars in connection cells with the placeholder style of each driver
+//! (`$1` for Postgres/DuckDB/Datafusion, `?` for MySQL/Snowflake, `?1` for
+//! SQLite, `@P1` for SQL Server and `@p1` for BigQuery) and sends the cell
+//! values along.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::sql::query_error;
+
+/// A typed value bound to a placeholder of a query.
+///
+/// Serialized as `{ "type": "number", "value": "1.5" }` to match
+/// `ConnectionParameter` in quadratic-core.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SqlParameter {
+    Null,
+    Text(String),
+    Number(Decimal),
+    Logical(bool),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    DateTime(NaiveDateTime),
+}
+
+impl SqlParameter {
+    /// Whole numbers are bound as integers so they can be used where the
+    /// database requires one (e.g. `LIMIT`).
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            SqlParameter::Number(number) if number.fract().is_zero() => number.to_i64(),
+            _ => None,
+        }
+    }
+
+    /// Other numbers are bound as floats, which every driver supports.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            SqlParameter::Number(number) => number.to_f64(),
+            _ => None,
+        }
+    }
+
+    /// Escaped SQL literal, for drivers that can't bind parameters.  Both
+    /// quotes and backslashes are escaped, since Snowflake reads backslashes
+    /// in strings as escapes.
+    pub fn to_sql_literal(&self) -> String {
+        match self {
+            SqlParameter::Null => "NULL".into(),
+            SqlParameter::Text(text) => {
+                format!("'{}'", text.replace('\\', "\\\\").replace('\'', "''"))
+            }
+            SqlParameter::Number(number) => number.to_string(),
+            SqlParameter::Logical(logical) => logical.to_string().to_uppercase(),
+            SqlParameter::Date(date) => format!("'{}'", date.format("%Y-%m-%d")),
+            SqlParameter::Time(time) => format!("'{}'", time.format("%H:%M:%S%.f")),
+            SqlParameter::DateTime(date_time) => {
+                format!("'{}'", date_time.format("%Y-%m-%d %H:%M:%S%.f"))
+            }
+        }
+    }
+}
+
+/// Replace the `?` placeholders of a query with escaped literals.
+///
+/// Only used for drivers whose API doesn't accept bindings (Snowflake).
+/// Placeholders inside strings (with backslash escapes), `$$` strings, quoted
+/// identifiers and comments are left alone.
+pub fn inline_parameters(sql: &str, params: &[SqlParameter]) -> Result<String> {
+    if params.is_empty() {
+        return Ok(sql.to_string());
+    }
+
+    let chars = sql.chars().collect::<Vec<_>>();
+    let mut result = String::with_capacity(sql.len());
+    let mut params = params.iter();
+    let mut i = 0;
+
+    // the index after the end of what starts at `i`, or the end of the query
+    let skip_until = |start: usize, end: &str, backslash_escapes: bool| -> usize {
+        let end = end.chars().collect::<Vec<_>>();
+        let mut i = start;
+
+        while i < chars.len() {
+            if backslash_escapes && chars[i] == '\\' {
+                i += 2;
+            } else if chars[i..].starts_with(&end) {
+                return i + end.len();
+            } else {
+                i += 1;
+            }
+        }
+
+        chars.len()
+    };
+
+    while i < chars.len() {
+        let next = chars.get(i + 1).copied();
+        let end = match (chars[i], next) {
+            ('\'', _) => skip_until(i + 1, "'", true),
+            ('"', _) => skip_until(i + 1, "\"", false),
+            ('$', Some('$')) => skip_until(i + 2, "$$", false),
+            ('-', Some('-')) | ('/', Some('/')) => skip_until(i + 2, "\n", false),
+            ('/', Some('*')) => skip_until(i + 2, "*/", false),
+            ('?', _) => {
+                let param = params
+                    .next()
+                    .ok_or_else(|| query_error("Not enough parameters for the query"))?;
+                result.push_str(&param.to_sql_literal());
+                i += 1;
+                continue;
+            }
+            _ => i + 1,
+        };
+
+        result.extend(&chars[i..end.min(chars.len())]);
+        i = end;
+    }
+
+    if params.next().is_some() {
+        return Err(query_error("Too many parameters for the query"));
+    }
+
+    Ok(result)
+}
+
+/// Bind a list of SqlParameters to a sqlx query
+#[macro_export]
+macro_rules! bind_sqlx_parameters {
+    ( $query:expr, $params:expr ) => {{
+        let mut query = $query;
+
+        for param in $params {
+            query = match param {
+                SqlParameter::Null => query.bind(None::<String>),
+                SqlParameter::Text(text) => query.bind(text.to_owned()),
+                SqlParameter::Number(_) => match param.as_integer() {
+                    Some(integer) => query.bind(integer),
+                    None => query.bind(param.as_float()),
+                },
+                SqlParameter::Logical(logical) => query.bind(*logical),
+                SqlParameter::Date(date) => query.bind(*date),
+                SqlParameter::Time(time) => query.bind(*time),
+                SqlParameter::DateTime(date_time) => query.bind(*date_time),
+            };
+        }
+
+        query
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_sql_parameter_deserialize() {
+        let json = r#"[
+            { "type": "null" },
+            { "type": "text", "value": "it's" },
+            { "type": "number", "value": "1.5" },
+            { "type": "logical", "value": true },
+            { "type": "date", "value": "2024-05-28" }
+        ]"#;
+        let params = serde_json::from_str::<Vec<SqlParameter>>(json).unwrap();
+
+        assert_eq!(
+            params,
+            vec![
+                SqlParameter::Null,
+                SqlParameter::Text("it's".into()),
+                SqlParameter::Number(Decimal::from_str("1.5").unwrap()),
+                SqlParameter::Logical(true),
+                SqlParameter::Date(NaiveDate::from_ymd_opt(2024, 5, 28).unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sql_parameter_numbers() {
+        let integer = SqlParameter::Number(Decimal::from(42));
+        let float = SqlParameter::Number(Decimal::from_str("1.5").unwrap());
+
+        assert_eq!(integer.as_integer(), Some(42));
+        assert_eq!(float.as_integer(), None);
+        assert_eq!(float.as_float(), Some(1.5));
+        assert_eq!(SqlParameter::Text("42".into()).as_integer(), None);
+    }
+
+    #[test]
+    fn test_inline_parameters() {
+        let sql = "select * from t where a = ? and b = '?' and \"c?\" in (?, ?)";
+        let params = vec![
+            SqlParameter::Text("x' or '1'='1".into()),
+            SqlParameter::Number(Decimal::from(1)),
+            SqlParameter::Null,
+        ];
+
+        assert_eq!(
+            inline_parameters(sql, &params).unwrap(),
+            "select * from t where a = 'x'' or ''1''=''1' and b = '?' and \"c?\" in (1, NULL)"
+        );
+        assert!(inline_parameters(sql, &params[..1]).is_err());
+        assert!(inline_parameters("select ?", &params).is_err());
+        assert_eq!(inline_parameters("select '?'", &[]).unwrap(), "select '?'");
+    }
+
+    #[test]
+    fn test_inline_parameters_escapes_backslashes() {
+        let params = vec![SqlParameter::Text(r"\' OR 1=1 --".into())];
+
+        assert_eq!(
+            inline_parameters("select * from t where a = ?", &params).unwrap(),
+            r"select * from t where a = '\\'' OR 1=1 --'"
+        );
+    }
+
+    #[test]
+    fn test_inline_parameters_skips_comments_and_strings() {
+        let sql = "select ? -- ?\n, 'a\\'?' /* ? */ // ?\n, $$ ? $$, ?";
+        let params = vec![
+            SqlParameter::Number(Decimal::from(1)),
+            SqlParameter::Number(Decimal::from(2)),
+        ];
+
+        assert_eq!(
+            inline_parameters(sql, &params).unwrap(),
+            "select 1 -- ?\n, 'a\\'?' /* ? */ // ?\n, $$ ? $$, 2"
+        );
+    }
+}
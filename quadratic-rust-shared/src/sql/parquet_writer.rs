@@ -0,0 +1,575 @@
+//! Parquet Batch Writer
+//!
+//! Query results are written as bounded Arrow record batches, each flushed to
+//! the underlying writer as its own Parquet row group.  Peak memory is bounded
+//! by the batch size rather than the size of the result, and the writer can be
+//! a stream (e.g. an HTTP response body).
+//!
+//! Row-based results are typed by the first non-null value in each column.
+//! Rows are held back (up to `MAX_UNTYPED_BATCHES` batches) until every column
+//! has a type, and once a column is typed, values of other types in later
+//! batches are written as nulls, as they are within a batch.
+//!
+//! When a result is cut off at the byte limit, the truncation is recorded in
+//! the Parquet file's key/value metadata so the client can tell the user how
+//! many records were returned (and, when the database reports it, how many
+//! there were in total).
+
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use bytes::Bytes;
+use parquet::arrow::ArrowWriter;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+use crate::arrow::arrow_type::ArrowType;
+use crate::error::Result;
+use crate::sql::query_error;
+
+/// Number of rows in each record batch and Parquet row group
+pub const DEFAULT_BATCH_ROWS: usize = 8_192;
+
+/// Number of batches of rows held back while a column has only nulls.  After
+/// that, the column is typed as text.
+pub const MAX_UNTYPED_BATCHES: usize = 16;
+
+/// Parquet metadata keys describing the result.  These must stay in sync with
+/// quadratic-core.
+pub const RECORD_COUNT_KEY: &str = "quadratic:record_count";
+pub const OVER_THE_LIMIT_KEY: &str = "quadratic:over_the_limit";
+pub const TOTAL_RECORD_COUNT_KEY: &str = "quadratic:total_record_count";
+
+/// Summary of a query result written by a `ParquetBatchWriter`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct QuerySummary {
+    /// Number of records written
+    pub num_records: usize,
+
+    /// Whether the result was cut off at the byte limit
+    pub over_the_limit: bool,
+
+    /// Total number of records in the result, if the database reports it
+    pub total_records: Option<usize>,
+}
+
+impl QuerySummary {
+    /// Read the summary from the metadata of a Parquet file.  Returns None for
+    /// empty results and files not written by a `ParquetBatchWriter`.
+    pub fn from_parquet(parquet: Bytes) -> Result<Option<Self>> {
+        if parquet.is_empty() {
+            return Ok(None);
+        }
+
+        let reader = SerializedFileReader::new(parquet)?;
+        let metadata = reader.metadata().file_metadata().key_value_metadata();
+        let value = |key: &str| {
+            metadata
+                .and_then(|metadata| metadata.iter().find(|kv| kv.key == key))
+                .and_then(|kv| kv.value.to_owned())
+        };
+
+        let Some(num_records) = value(RECORD_COUNT_KEY) else {
+            return Ok(None);
+        };
+
+        Ok(Some(QuerySummary {
+            num_records: num_records.parse().map_err(query_error)?,
+            over_the_limit: value(OVER_THE_LIMIT_KEY).is_some_and(|value| value == "true"),
+            total_records: value(TOTAL_RECORD_COUNT_KEY).and_then(|value| value.parse().ok()),
+        }))
+    }
+}
+
+/// Writes query results to `W` as bounded Parquet row groups
+pub struct ParquetBatchWriter<W: Write + Send> {
+    sink: Option<W>,
+    writer: Option<ArrowWriter<W>>,
+    schema: Option<SchemaRef>,
+    column_names: Vec<String>,
+    column_types: Vec<DataType>,
+    rows: Vec<Vec<ArrowType>>,
+    batch_rows: usize,
+    max_bytes: Option<u64>,
+    summary: QuerySummary,
+}
+
+impl<W: Write + Send> ParquetBatchWriter<W> {
+    pub fn new(sink: W, max_bytes: Option<u64>) -> Self {
+        Self::with_batch_rows(sink, max_bytes, DEFAULT_BATCH_ROWS)
+    }
+
+    pub fn with_batch_rows(sink: W, max_bytes: Option<u64>, batch_rows: usize) -> Self {
+        Self {
+            sink: Some(sink),
+            writer: None,
+            schema: None,
+            column_names: vec![],
+            column_types: vec![],
+            rows: vec![],
+            batch_rows: batch_rows.max(1),
+            max_bytes,
+            summary: QuerySummary::default(),
+        }
+    }
+
+    /// The maximum number of bytes to write
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    /// Whether the result has been cut off at the byte limit
+    pub fn over_the_limit(&self) -> bool {
+        self.summary.over_the_limit
+    }
+
+    /// Mark the result as cut off, for databases that enforce the byte limit
+    /// themselves
+    pub fn set_over_the_limit(&mut self) {
+        self.summary.over_the_limit = true;
+    }
+
+    /// Whether the column names of row-based results have been set
+    pub fn has_column_names(&self) -> bool {
+        !self.column_names.is_empty()
+    }
+
+    /// Set the column names of row-based results
+    pub fn set_column_names(&mut self, column_names: Vec<String>) {
+        self.column_names = column_names;
+    }
+
+    /// Buffer a row of values, writing a row group once the batch is full
+    /// and every column has a type.
+    ///
+    /// Returns false once the result is over the byte limit, after which
+    /// further rows are ignored.
+    pub fn push_row(&mut self, values: Vec<ArrowType>) -> Result<bool> {
+        if self.over_the_limit() {
+            return Ok(false);
+        }
+
+        if self.column_types.len() < values.len() {
+            self.column_types.resize(values.len(), DataType::Null);
+        }
+
+        for (data_type, value) in self.column_types.iter_mut().zip(&values) {
+            if data_type == &DataType::Null {
+                *data_type = value.data_type();
+            }
+        }
+
+        self.rows.push(values);
+
+        if self.rows.len() >= self.batch_rows && !self.holding_back_rows() {
+            self.flush_rows()?;
+        }
+
+        Ok(!self.over_the_limit())
+    }
+
+    /// Write a record batch, splitting it into row groups of at most
+    /// `batch_rows` rows.
+    ///
+    /// Returns false once the result is over the byte limit, after which
+    /// further batches are ignored.
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<bool> {
+        let mut offset = 0;
+
+        while offset < batch.num_rows() && !self.over_the_limit() {
+            let len = self.batch_rows.min(batch.num_rows() - offset);
+            self.write_record_batch(batch.slice(offset, len))?;
+            offset += len;
+        }
+
+        Ok(!self.over_the_limit())
+    }
+
+    /// Write any buffered rows and the Parquet footer, returning the
+    /// underlying writer.  `total_records` is the total number of records in
+    /// the result, for databases that report it.
+    ///
+    /// Nothing is written for empty results, unless they were cut off by the
+    /// database before any batch was written.
+    pub fn finish(mut self, total_records: Option<usize>) -> Result<(W, QuerySummary)> {
+        self.flush_rows()?;
+        self.summary.total_records = total_records;
+
+        if self.writer.is_none() && self.over_the_limit() {
+            self.schema.get_or_insert_with(|| Arc::new(Schema::empty()));
+            self.writer()?;
+        }
+
+        let summary = self.summary;
+
+        let sink = match self.writer {
+            Some(mut writer) => {
+                let metadata = [
+                    (RECORD_COUNT_KEY, summary.num_records.to_string()),
+                    (OVER_THE_LIMIT_KEY, summary.over_the_limit.to_string()),
+                ]
+                .into_iter()
+                .chain(total_records.map(|total| (TOTAL_RECORD_COUNT_KEY, total.to_string())));
+
+                for (key, value) in metadata {
+                    writer.append_key_value_metadata(KeyValue::new(key.to_string(), value));
+                }
+
+                writer.into_inner()?
+            }
+            None => self
+                .sink
+                .ok_or_else(|| query_error("Parquet writer has no sink"))?,
+        };
+
+        Ok((sink, summary))
+    }
+
+    /// Whether to keep buffering rows until a column that has only nulls so
+    /// far is typed.  Once the schema is set, it can't change.
+    fn holding_back_rows(&self) -> bool {
+        self.schema.is_none()
+            && self.rows.len() < self.batch_rows * MAX_UNTYPED_BATCHES
+            && self.column_types.contains(&DataType::Null)
+    }
+
+    /// Convert the buffered rows to record batches and write them
+    fn flush_rows(&mut self) -> Result<()> {
+        if self.rows.is_empty() || self.over_the_limit() {
+            self.rows.clear();
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.rows);
+        let col_count = rows[0].len();
+
+        // transpose rows to columns
+        let mut columns = vec![Vec::with_capacity(rows.len()); col_count];
+
+        for row in rows {
+            for (col_index, value) in row.into_iter().enumerate().take(col_count) {
+                columns[col_index].push(value);
+            }
+        }
+
+        let arrays = columns
+            .into_iter()
+            .zip(&self.column_types)
+            .map(|(values, data_type)| ArrowType::to_array_ref_as(values, data_type))
+            .collect::<Vec<ArrayRef>>();
+
+        let schema = match &self.schema {
+            Some(schema) => schema.to_owned(),
+            None => {
+                let fields = arrays
+                    .iter()
+                    .enumerate()
+                    .map(|(index, array)| {
+                        let name = self
+                            .column_names
+                            .get(index)
+                            .cloned()
+                            .unwrap_or_else(|| format!("Column {}", index + 1));
+
+                        Field::new(name, array.data_type().to_owned(), true)
+                    })
+                    .collect::<Vec<Field>>();
+
+                Arc::new(Schema::new(fields))
+            }
+        };
+
+        let batch = RecordBatch::try_new(schema, arrays)?;
+        self.write_batch(&batch)?;
+
+        Ok(())
+    }
+
+    /// Write a single row group, truncating it at the byte limit
+    fn write_record_batch(&mut self, batch: RecordBatch) -> Result<()> {
+        let batch = self.conform_to_schema(batch)?;
+        let bytes_written = self.writer()?.bytes_written() as u64;
+
+        // the in-memory size of a batch is an upper bound on its Parquet size
+        let batch = match self.max_bytes {
+            Some(max_bytes) if batch.num_rows() > 0 => {
+                let remaining = max_bytes.saturating_sub(bytes_written);
+                let batch_bytes = batch_memory_size(&batch) as u64;
+
+                if batch_bytes > remaining {
+                    let row_bytes = (batch_bytes / batch.num_rows() as u64).max(1);
+                    let rows = (remaining / row_bytes) as usize;
+                    self.summary.over_the_limit = true;
+                    batch.slice(0, rows.min(batch.num_rows()))
+                } else {
+                    batch
+                }
+            }
+            _ => batch,
+        };
+
+        if batch.num_rows() > 0 {
+            let writer = self.writer()?;
+            writer.write(&batch)?;
+            writer.flush()?;
+            self.summary.num_records += batch.num_rows();
+        }
+
+        Ok(())
+    }
+
+    /// Lazily create the Arrow writer from the schema of the first batch
+    fn writer(&mut self) -> Result<&mut ArrowWriter<W>> {
+        if self.writer.is_none() {
+            let schema = self
+                .schema
+                .to_owned()
+                .ok_or_else(|| query_error("Parquet writer has no schema"))?;
+            let sink = self
+                .sink
+                .take()
+                .ok_or_else(|| query_error("Parquet writer has no sink"))?;
+            let properties = WriterProperties::builder()
+                .set_max_row_group_size(self.batch_rows)
+                .build();
+
+            self.writer = Some(ArrowWriter::try_new(sink, schema, Some(properties))?);
+        }
+
+        self.writer
+            .as_mut()
+            .ok_or_else(|| query_error("Parquet writer is closed"))
+    }
+
+    /// The first batch sets the schema of the file.  Columns of later batches
+    /// are cast to it, e.g. a column of nulls written as text before it was
+    /// typed.
+    fn conform_to_schema(&mut self, batch: RecordBatch) -> Result<RecordBatch> {
+        let Some(schema) = &self.schema else {
+            self.schema = Some(batch.schema());
+            return Ok(batch);
+        };
+
+        if batch.schema().fields() == schema.fields() {
+            return Ok(batch);
+        }
+
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(schema.fields())
+            .map(|(column, field)| cast(column, field.data_type()).map_err(query_error))
+            .collect::<Result<Vec<ArrayRef>>>()?;
+
+        Ok(RecordBatch::try_new(schema.to_owned(), columns)?)
+    }
+}
+
+/// Memory used by the rows of a batch, which may be a slice of a larger one
+fn batch_memory_size(batch: &RecordBatch) -> usize {
+    batch
+        .columns()
+        .iter()
+        .map(|column| {
+            column
+                .to_data()
+                .get_slice_memory_size()
+                .unwrap_or_else(|_| column.get_array_memory_size())
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::DataType;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn int_batch(values: Vec<i64>) -> RecordBatch {
+        let schema = Schema::new(vec![Field::new("id", DataType::Int64, true)]);
+        let array = Arc::new(Int64Array::from(values)) as ArrayRef;
+
+        RecordBatch::try_new(Arc::new(schema), vec![array]).unwrap()
+    }
+
+    fn write_rows(values: Vec<ArrowType>) -> (DataType, Vec<RecordBatch>) {
+        let mut writer = ParquetBatchWriter::with_batch_rows(Vec::new(), None, 2);
+        writer.set_column_names(vec!["value".into()]);
+
+        for value in values {
+            assert!(writer.push_row(vec![value]).unwrap());
+        }
+
+        let (parquet, _) = writer.finish(None).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(parquet)).unwrap();
+        let data_type = builder.schema().field(0).data_type().to_owned();
+        let batches = builder
+            .build()
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+
+        (data_type, batches)
+    }
+
+    fn int_values(batches: &[RecordBatch]) -> Vec<Option<i64>> {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let values = batch.column(0).as_any().downcast_ref::<Int64Array>();
+                values.unwrap().iter().collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_row_groups() {
+        let mut writer = ParquetBatchWriter::with_batch_rows(Vec::new(), None, 2);
+        writer.set_column_names(vec!["id".into(), "name".into()]);
+
+        for id in 0..5 {
+            let name = match id {
+                // the first batch has no names, so it's held back until the
+                // column is typed
+                0 | 1 => ArrowType::Null,
+                _ => ArrowType::Utf8(format!("name {id}")),
+            };
+            assert!(writer.push_row(vec![ArrowType::Int64(id), name]).unwrap());
+        }
+
+        let (parquet, summary) = writer.finish(None).unwrap();
+        let parquet = Bytes::from(parquet);
+
+        assert_eq!(summary.num_records, 5);
+        assert!(!summary.over_the_limit);
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(parquet.clone()).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        assert_eq!(builder.schema().field(1).name(), "name");
+
+        let batches = builder
+            .build()
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        let names = batches
+            .iter()
+            .flat_map(|batch| {
+                let names = batch.column(1).as_any().downcast_ref::<StringArray>();
+                names.unwrap().iter().map(|name| name.map(String::from))
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(names[1], None);
+        assert_eq!(names[4], Some("name 4".into()));
+
+        assert_eq!(QuerySummary::from_parquet(parquet).unwrap(), Some(summary));
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_types_null_columns_from_later_batches() {
+        let (data_type, batches) = write_rows(vec![
+            ArrowType::Null,
+            ArrowType::Null,
+            ArrowType::Null,
+            ArrowType::Int64(4),
+            ArrowType::Int64(5),
+        ]);
+
+        assert_eq!(data_type, DataType::Int64);
+        assert_eq!(
+            int_values(&batches),
+            vec![None, None, None, Some(4), Some(5)]
+        );
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_column_type_changes_between_batches() {
+        let (data_type, batches) = write_rows(vec![
+            ArrowType::Int64(1),
+            ArrowType::Int64(2),
+            ArrowType::Utf8("three".into()),
+            ArrowType::Int64(4),
+        ]);
+
+        // values of other types are nulls, as they are within a batch
+        assert_eq!(data_type, DataType::Int64);
+        assert_eq!(int_values(&batches), vec![Some(1), Some(2), None, Some(4)]);
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_untyped_columns_are_text() {
+        let rows = 2 * MAX_UNTYPED_BATCHES;
+        let mut values = vec![ArrowType::Null; rows];
+        values.push(ArrowType::Int64(1));
+
+        let (data_type, batches) = write_rows(values);
+        let last = batches.last().unwrap().column(0);
+        let last = last.as_any().downcast_ref::<StringArray>().unwrap();
+
+        assert_eq!(data_type, DataType::Utf8);
+        assert_eq!(last.iter().last(), Some(Some("1")));
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_over_the_limit() {
+        let batch = int_batch((0..100).collect());
+        let max_bytes = batch_memory_size(&batch) as u64 / 2;
+        let mut writer = ParquetBatchWriter::with_batch_rows(Vec::new(), Some(max_bytes), 10);
+
+        assert!(!writer.write_batch(&batch).unwrap());
+        assert!(!writer.write_batch(&batch).unwrap());
+
+        let (parquet, summary) = writer.finish(Some(200)).unwrap();
+
+        assert!(summary.over_the_limit);
+        assert!(summary.num_records > 0 && summary.num_records < 100);
+        assert_eq!(summary.total_records, Some(200));
+        assert_eq!(
+            QuerySummary::from_parquet(parquet.into()).unwrap(),
+            Some(summary)
+        );
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_no_bytes() {
+        let mut writer = ParquetBatchWriter::new(Vec::new(), Some(0));
+
+        assert!(!writer.write_batch(&int_batch(vec![1, 2, 3])).unwrap());
+
+        let (parquet, summary) = writer.finish(None).unwrap();
+        let summary_from_file = QuerySummary::from_parquet(parquet.into()).unwrap().unwrap();
+
+        assert!(summary_from_file.over_the_limit);
+        assert_eq!(summary_from_file.num_records, 0);
+        assert_eq!(summary, summary_from_file);
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_over_the_limit_without_batches() {
+        let mut writer = ParquetBatchWriter::new(Vec::new(), Some(1));
+        writer.set_over_the_limit();
+
+        let (parquet, summary) = writer.finish(None).unwrap();
+
+        assert!(summary.over_the_limit);
+        assert_eq!(
+            QuerySummary::from_parquet(parquet.into()).unwrap(),
+            Some(summary)
+        );
+    }
+
+    #[test]
+    fn test_parquet_batch_writer_empty() {
+        let writer = ParquetBatchWriter::new(Vec::new(), None);
+        let (parquet, summary) = writer.finish(None).unwrap();
+
+        assert!(parquet.is_empty());
+        assert_eq!(summary, QuerySummary::default());
+    }
+}
@@ -3,10 +3,11 @@
 //! Functions to interact with PostgreSQL
 
 use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
 
 use arrow::datatypes::Date32Type;
 use async_trait::async_trait;
-use bytes::Bytes;
 use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime};
 use futures_util::StreamExt;
 use rust_decimal::Decimal;
@@ -21,13 +22,21 @@ use sqlx::{
 
 use crate::quadratic_api::Connection as ApiConnection;
 use crate::sql::error::Sql as SqlError;
-use crate::sql::schema::{DatabaseSchema, SchemaColumn, SchemaTable};
-use crate::sql::{ArrowType, Connection};
+use crate::sql::parameter::SqlParameter;
+use crate::sql::parquet_writer::ParquetBatchWriter;
+use crate::sql::schema::{
+    DatabaseSchema, SchemaColumn, SchemaForeignKey, SchemaIndex, SchemaTable, TableKind, comment,
+    row_estimate,
+};
+use crate::sql::tls::TlsConfig;
+use crate::sql::{ArrowType, CancelQuery, Connection};
+use crate::{
+    bind_sqlx_parameters, convert_sqlx_type, net::ssh::SshConfig, sql::UsesSsh, to_arrow_type,
+};
 use crate::{
     convert_sqlx_array_type,
     error::{Result, SharedError},
 };
-use crate::{convert_sqlx_type, net::ssh::SshConfig, sql::UsesSsh, to_arrow_type};
 
 /// PostgreSQL connection
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +52,16 @@ pub struct PostgresConnection {
     pub ssh_port: Option<String>,
     pub ssh_username: Option<String>,
     pub ssh_key: Option<String>,
+    #[serde(flatten)]
+    pub tls: TlsConfig,
+
+    /// Only allow queries that read data
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Cache query results for this many seconds
+    #[serde(default)]
+    pub cache_ttl_seconds: Option<u64>,
 }
 
 impl From<&ApiConnection<PostgresConnection>> for PostgresConnection {
@@ -60,6 +79,9 @@ impl From<&ApiConnection<PostgresConnection>> for PostgresConnection {
             details.ssh_username,
             details.ssh_key,
         )
+        .with_tls(details.tls)
+        .with_read_only(details.read_only)
+        .with_cache_ttl(details.cache_ttl_seconds)
     }
 }
 
@@ -114,6 +136,41 @@ impl PostgresConnection {
             ssh_port,
             ssh_username,
             ssh_key,
+            tls: TlsConfig::default(),
+            read_only: false,
+            cache_ttl_seconds: None,
+        }
+    }
+
+    /// Set the TLS settings
+    pub fn with_tls(self, tls: TlsConfig) -> PostgresConnection {
+        PostgresConnection { tls, ..self }
+    }
+
+    /// Only allow queries that read data
+    pub fn with_read_only(self, read_only: bool) -> PostgresConnection {
+        PostgresConnection { read_only, ..self }
+    }
+
+    /// Have the database reject writes that statements can't be checked for
+    /// (e.g. functions that write).  This is set before every query, since a
+    /// pooled session may have been changed by an earlier query.
+    pub async fn set_read_only(&self, pool: &mut PgConnection) -> Result<()> {
+        if self.read_only {
+            sqlx::raw_sql("SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY")
+                .execute(pool)
+                .await
+                .map_err(|e| SharedError::Sql(SqlError::Connect(e.to_string())))?;
+        }
+
+        Ok(())
+    }
+
+    /// Cache query results for this many seconds
+    pub fn with_cache_ttl(self, cache_ttl_seconds: Option<u64>) -> PostgresConnection {
+        PostgresConnection {
+            cache_ttl_seconds,
+            ..self
         }
     }
 
@@ -128,6 +185,28 @@ impl PostgresConnection {
     }
 }
 
+/// Cancels a PostgreSQL query with `pg_cancel_backend`, which is run from a
+/// new connection to the same server
+pub struct PostgresCancelQuery {
+    connection: PostgresConnection,
+    pid: i32,
+}
+
+#[async_trait]
+impl CancelQuery for PostgresCancelQuery {
+    async fn cancel(&self) -> Result<()> {
+        let mut pool = self.connection.connect().await?;
+
+        sqlx::query("SELECT pg_cancel_backend($1)")
+            .bind(self.pid)
+            .execute(&mut pool)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl<'a> Connection<'a> for PostgresConnection {
     type Conn = PgConnection;
@@ -167,64 +246,113 @@ impl<'a> Connection<'a> for PostgresConnection {
             options = options.port(port?);
         }
 
-        let pool = options.connect().await.map_err(|e| {
-            SharedError::Sql(SqlError::Connect(format!("{:?}: {e}", self.database)))
-        })?;
+        options = self.tls.postgres_options(options, &self.host)?;
+
+        let mut pool = options
+            .connect()
+            .await
+            .map_err(|e| self.tls.sqlx_connect_error(e, &self.database))?;
+
+        self.set_read_only(&mut pool).await?;
 
         Ok(pool)
     }
 
+    /// Ping the server
+    async fn health_check(&self, pool: &mut Self::Conn) -> Result<()> {
+        sqlx::Connection::ping(pool)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Connect(e.to_string())))
+    }
+
+    /// Discard the session's state and set it read-only again.  The cached
+    /// prepared statements are closed first, since `DISCARD ALL` deallocates
+    /// them.
+    async fn reset(&self, pool: &mut Self::Conn) -> Result<()> {
+        let reset_error = |e: sqlx::Error| SharedError::Sql(SqlError::Connect(e.to_string()));
+
+        sqlx::Connection::clear_cached_statements(&mut *pool)
+            .await
+            .map_err(reset_error)?;
+        sqlx::raw_sql("DISCARD ALL")
+            .execute(&mut *pool)
+            .await
+            .map_err(reset_error)?;
+
+        self.set_read_only(pool).await
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl_seconds.map(Duration::from_secs)
+    }
+
+    /// Get the backend process id of the connection, used to cancel its query
+    async fn cancel_handle(
+        &self,
+        pool: &mut Self::Conn,
+        _sql: &str,
+        _params: &[SqlParameter],
+    ) -> Result<Option<Box<dyn CancelQuery>>> {
+        let pid = sqlx::query_scalar::<_, i32>("SELECT pg_backend_pid()")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
+
+        Ok(Some(Box::new(PostgresCancelQuery {
+            connection: self.clone(),
+            pid,
+        })))
+    }
+
     /// Query rows from a PostgreSQL database
-    async fn query(
+    async fn query_stream<W: Write + Send>(
         &mut self,
         pool: &mut Self::Conn,
         sql: &str,
-        max_bytes: Option<u64>,
-    ) -> Result<(Bytes, bool, usize)> {
-        let mut rows = vec![];
-        let mut over_the_limit = false;
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
+        self.set_read_only(pool).await?;
 
-        if let Some(max_bytes) = max_bytes {
-            let mut bytes = 0;
-            let mut stream = sqlx::query(sql).fetch(pool);
+        let mut stream = bind_sqlx_parameters!(sqlx::query(sql), params).fetch(pool);
 
-            while let Some(row) = stream.next().await {
-                let row = row.map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
-                bytes += row.len() as u64;
+        while let Some(row) = stream.next().await {
+            let row = row.map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
 
-                if bytes > max_bytes {
-                    over_the_limit = true;
-                    break;
-                }
-
-                rows.push(row);
+            // stop fetching rows once the result is over the byte limit
+            if !self.write_row(&row, writer)? {
+                break;
             }
-            tracing::info!("Query executed with {bytes} bytes");
-        } else {
-            rows = sqlx::query(sql)
-                .fetch_all(pool)
-                .await
-                .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
         }
 
-        let (bytes, num_records) = self.to_parquet(rows)?;
-        Ok((bytes, over_the_limit, num_records))
+        Ok(None)
     }
 
     /// Get the schema of a PostgreSQL database
     async fn schema(&self, pool: &mut Self::Conn) -> Result<DatabaseSchema> {
         let database = self.database.to_owned();
-        let sql = format!("
-            select c.table_catalog as database, c.table_schema as schema, c.table_name as table, 
-                c.column_name, c.udt_name as column_type, c.is_nullable
-            from information_schema.tables as t inner join information_schema.columns as c on t.table_name = c.table_name
-            where t.table_type = 'BASE TABLE' 
-                and c.table_schema not in 
-                    ('pg_catalog', 'information_schema')
-                    and c.table_catalog = '{database}'
-            order by c.table_name, c.ordinal_position, c.column_name");
-
-        let rows = Self::query_all(pool, &sql).await?;
+
+        // tables, views and materialized views, with their columns
+        let sql = "
+            select n.nspname as schema, c.relname as table, c.relkind::text as kind,
+                obj_description(c.oid, 'pg_class') as table_comment, c.reltuples::bigint as row_estimate,
+                a.attname as column_name, t.typname as column_type, not a.attnotnull as is_nullable,
+                col_description(c.oid, a.attnum) as column_comment
+            from pg_catalog.pg_class as c
+                inner join pg_catalog.pg_namespace as n on n.oid = c.relnamespace
+                inner join pg_catalog.pg_attribute as a on a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped
+                inner join pg_catalog.pg_type as t on t.oid = a.atttypid
+            where c.relkind in ('r', 'p', 'f', 'v', 'm')
+                and n.nspname not in ('pg_catalog', 'information_schema', 'crdb_internal', 'pg_extension')
+                and n.nspname not like 'pg_toast%'
+                and n.nspname not like 'pg_temp%'
+            order by c.relname, a.attnum";
+
+        let rows = Self::query_all(pool, sql).await?;
 
         let mut schema = DatabaseSchema {
             database,
@@ -242,29 +370,99 @@ impl<'a> Connection<'a> for PostgresConnection {
         };
 
         for row in rows.into_iter() {
-            let table_name = row.get::<String, usize>(2);
+            let table_name = row.get::<String, usize>(1);
 
             schema
                 .tables
                 // get or insert the table
                 .entry(table_name.to_owned())
-                .or_insert_with(|| SchemaTable {
-                    name: table_name,
-                    schema: row.get::<String, usize>(1),
-                    columns: vec![],
+                .or_insert_with(|| {
+                    let kind = match row.get::<String, usize>(2).as_str() {
+                        "v" => TableKind::View,
+                        "m" => TableKind::MaterializedView,
+                        _ => TableKind::Table,
+                    };
+
+                    SchemaTable {
+                        kind,
+                        comment: comment(row.get::<Option<String>, usize>(3)),
+                        // views don't have statistics
+                        row_estimate: match kind {
+                            TableKind::View => None,
+                            _ => row_estimate(row.get::<Option<i64>, usize>(4)),
+                        },
+                        ..SchemaTable::new(table_name, row.get::<String, usize>(0))
+                    }
                 })
                 .columns
                 // add the column to the table
                 .push(SchemaColumn {
-                    name: row.get::<String, usize>(3),
-                    r#type: parse_type(row.get::<String, usize>(4)),
-                    is_nullable: matches!(
-                        row.get::<String, usize>(5).to_lowercase().as_str(),
-                        "yes"
-                    ),
+                    name: row.get::<String, usize>(5),
+                    r#type: parse_type(row.get::<String, usize>(6)),
+                    is_nullable: row.get::<bool, usize>(7),
+                    comment: comment(row.get::<Option<String>, usize>(8)),
                 });
         }
 
+        // primary and foreign keys, a row per column
+        let sql = "
+            select c.relname as table, con.conname as name, con.contype::text as type, a.attname as column_name,
+                fn.nspname as foreign_schema, fc.relname as foreign_table, fa.attname as foreign_column
+            from pg_catalog.pg_constraint as con
+                inner join pg_catalog.pg_class as c on c.oid = con.conrelid
+                inner join pg_catalog.pg_namespace as n on n.oid = c.relnamespace
+                cross join lateral unnest(con.conkey, con.confkey) with ordinality as k(attnum, foreign_attnum, position)
+                inner join pg_catalog.pg_attribute as a on a.attrelid = c.oid and a.attnum = k.attnum
+                left join pg_catalog.pg_class as fc on fc.oid = con.confrelid
+                left join pg_catalog.pg_namespace as fn on fn.oid = fc.relnamespace
+                left join pg_catalog.pg_attribute as fa on fa.attrelid = con.confrelid and fa.attnum = k.foreign_attnum
+            where con.contype in ('p', 'f')
+                and n.nspname not in ('pg_catalog', 'information_schema', 'crdb_internal', 'pg_extension')
+            order by c.relname, con.conname, k.position";
+
+        for row in Self::query_all(pool, sql).await? {
+            let table_name = row.get::<String, usize>(0);
+            let column = row.get::<String, usize>(3);
+
+            match row.get::<String, usize>(2).as_str() {
+                "p" => schema.add_primary_key_column(&table_name, column),
+                _ => schema.add_foreign_key(
+                    &table_name,
+                    SchemaForeignKey {
+                        name: row.get::<String, usize>(1),
+                        columns: vec![column],
+                        foreign_schema: row.get::<String, usize>(4),
+                        foreign_table: row.get::<String, usize>(5),
+                        foreign_columns: vec![row.get::<String, usize>(6)],
+                    },
+                ),
+            }
+        }
+
+        // indexes, a row per column (expressions are skipped)
+        let sql = "
+            select c.relname as table, i.relname as name, ix.indisunique as is_unique, a.attname as column_name
+            from pg_catalog.pg_index as ix
+                inner join pg_catalog.pg_class as c on c.oid = ix.indrelid
+                inner join pg_catalog.pg_class as i on i.oid = ix.indexrelid
+                inner join pg_catalog.pg_namespace as n on n.oid = c.relnamespace
+                cross join lateral unnest(ix.indkey::int2[]) with ordinality as k(attnum, position)
+                inner join pg_catalog.pg_attribute as a on a.attrelid = c.oid and a.attnum = k.attnum
+            where n.nspname not in ('pg_catalog', 'information_schema', 'crdb_internal', 'pg_extension')
+                and n.nspname not like 'pg_toast%'
+            order by c.relname, i.relname, k.position";
+
+        for row in Self::query_all(pool, sql).await? {
+            schema.add_index(
+                &row.get::<String, usize>(0),
+                SchemaIndex {
+                    name: row.get::<String, usize>(1),
+                    columns: vec![row.get::<String, usize>(3)],
+                    is_unique: row.get::<bool, usize>(2),
+                },
+            );
+        }
+
         Ok(schema)
     }
 
@@ -434,251 +632,301 @@ pub mod tests {
                 name: "id".into(),
                 r#type: "int4".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "smallint_col".into(),
                 r#type: "int2".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "integer_col".into(),
                 r#type: "int4".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigint_col".into(),
                 r#type: "int8".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "decimal_col".into(),
                 r#type: "numeric".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "numeric_col".into(),
                 r#type: "numeric".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "real_col".into(),
                 r#type: "float4".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "double_col".into(),
                 r#type: "float8".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "serial_col".into(),
                 r#type: "int4".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigserial_col".into(),
                 r#type: "int8".into(),
                 is_nullable: false,
+                comment: None,
             },
             SchemaColumn {
                 name: "money_col".into(),
                 r#type: "money".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "char_col".into(),
                 r#type: "bpchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varchar_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "text_col".into(),
                 r#type: "text".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bytea_col".into(),
                 r#type: "bytea".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamp_col".into(),
                 r#type: "timestamp".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamptz_col".into(),
                 r#type: "timestamptz".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "date_col".into(),
                 r#type: "date".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "time_col".into(),
                 r#type: "time".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timetz_col".into(),
                 r#type: "timetz".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "interval_col".into(),
                 r#type: "interval".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "boolean_col".into(),
                 r#type: "bool".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "enum_col".into(),
                 r#type: "varchar".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "point_col".into(),
                 r#type: "point".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "line_col".into(),
                 r#type: "line".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "lseg_col".into(),
                 r#type: "lseg".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "box_col".into(),
                 r#type: "box".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "path_col".into(),
                 r#type: "path".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "polygon_col".into(),
                 r#type: "polygon".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "circle_col".into(),
                 r#type: "circle".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "cidr_col".into(),
                 r#type: "cidr".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "inet_col".into(),
                 r#type: "inet".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "macaddr_col".into(),
                 r#type: "macaddr".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "json_col".into(),
                 r#type: "json".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "jsonb_col".into(),
                 r#type: "jsonb".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "uuid_col".into(),
                 r#type: "uuid".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "xml_col".into(),
                 r#type: "xml".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "array_col".into(),
                 r#type: "int4[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "smallint_array_col".into(),
                 r#type: "int2[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "bigint_array_col".into(),
                 r#type: "int8[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "numeric_array_col".into(),
                 r#type: "numeric[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "real_array_col".into(),
                 r#type: "float4[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "double_array_col".into(),
                 r#type: "float8[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "text_array_col".into(),
                 r#type: "text[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "varchar_array_col".into(),
                 r#type: "varchar[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "boolean_array_col".into(),
                 r#type: "bool[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "timestamp_array_col".into(),
                 r#type: "timestamp[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "date_array_col".into(),
                 r#type: "date[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "jsonb_array_col".into(),
                 r#type: "jsonb[]".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "null_bool_col".into(),
                 r#type: "bool".into(),
                 is_nullable: true,
+                comment: None,
             },
         ]
     }
@@ -766,6 +1014,49 @@ pub mod tests {
         // println!("{:?}", _data);
     }
 
+    #[cfg(test)]
+    #[tokio::test]
+    async fn test_postgres_reset() {
+        // temporary tables can't be created in a read-only session
+        let mut pool = new_postgres_connection().connect().await.unwrap();
+        let connection = new_postgres_connection().with_read_only(true);
+        let setting = "select current_setting('quadratic.test', true)";
+
+        sqlx::raw_sql("set quadratic.test = 'leaked'; create temporary table leaked (id int)")
+            .execute(&mut pool)
+            .await
+            .unwrap();
+        PostgresConnection::query_all(&mut pool, "select 1")
+            .await
+            .unwrap();
+
+        connection.reset(&mut pool).await.unwrap();
+
+        // the session's settings and temporary tables are discarded
+        let value = sqlx::query_scalar::<_, Option<String>>(setting)
+            .fetch_one(&mut pool)
+            .await
+            .unwrap();
+        assert!(value.is_none_or(|value| value.is_empty()));
+        assert!(
+            PostgresConnection::query_all(&mut pool, "select * from leaked")
+                .await
+                .is_err()
+        );
+
+        // statements prepared before the reset can be run again
+        PostgresConnection::query_all(&mut pool, "select 1")
+            .await
+            .unwrap();
+
+        // the session is still read-only
+        let read_only = sqlx::query_scalar::<_, String>("show transaction_read_only")
+            .fetch_one(&mut pool)
+            .await
+            .unwrap();
+        assert_eq!(read_only, "on");
+    }
+
     #[cfg(test)]
     #[tokio::test]
     async fn test_postgres_schema() {
@@ -776,7 +1067,15 @@ pub mod tests {
         // println!("{:?}", schema);
 
         let expected = expected_postgres_schema();
-        let columns = &schema.tables.get("all_native_data_types").unwrap().columns;
-        assert_eq!(columns, &expected);
+        let table = schema.tables.get("all_native_data_types").unwrap();
+        assert_eq!(&table.columns, &expected);
+        assert_eq!(table.kind, TableKind::Table);
+        assert_eq!(table.primary_key, vec!["id"]);
+        assert!(
+            table
+                .indexes
+                .iter()
+                .any(|index| index.is_unique && index.columns == vec!["id"])
+        );
     }
 }
@@ -11,14 +11,61 @@ pub struct SchemaColumn {
     pub name: String,
     pub r#type: String,
     pub is_nullable: bool,
+    pub comment: Option<String>,
 }
 
-/// A table in a database
+/// The kind of a table
+#[derive(Debug, Serialize, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TableKind {
+    #[default]
+    Table,
+    View,
+    MaterializedView,
+}
+
+/// A foreign key of a table
 #[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct SchemaForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub foreign_schema: String,
+    pub foreign_table: String,
+    pub foreign_columns: Vec<String>,
+}
+
+/// An index of a table
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct SchemaIndex {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub is_unique: bool,
+}
+
+/// A table in a database
+#[derive(Debug, Serialize, PartialEq, Clone, Default)]
 pub struct SchemaTable {
     pub name: String,
     pub schema: String,
+    pub kind: TableKind,
+    pub comment: Option<String>,
     pub columns: Vec<SchemaColumn>,
+    pub primary_key: Vec<String>,
+    pub foreign_keys: Vec<SchemaForeignKey>,
+    pub indexes: Vec<SchemaIndex>,
+
+    /// Approximate number of rows, from the database's statistics
+    pub row_estimate: Option<u64>,
+}
+
+impl SchemaTable {
+    pub fn new(name: String, schema: String) -> Self {
+        SchemaTable {
+            name,
+            schema,
+            ..Default::default()
+        }
+    }
 }
 
 /// A database schema
@@ -27,3 +74,120 @@ pub struct DatabaseSchema {
     pub database: String,
     pub tables: BTreeMap<String, SchemaTable>,
 }
+
+impl DatabaseSchema {
+    /// Add a column to a table's primary key.  Columns are added in key order.
+    pub fn add_primary_key_column(&mut self, table: &str, column: String) {
+        if let Some(table) = self.tables.get_mut(table) {
+            table.primary_key.push(column);
+        }
+    }
+
+    /// Add a foreign key to a table.  Keys are listed a column at a time, in
+    /// key order, so a key's columns are appended to the key with its name.
+    pub fn add_foreign_key(&mut self, table: &str, foreign_key: SchemaForeignKey) {
+        let Some(table) = self.tables.get_mut(table) else {
+            return;
+        };
+
+        match table
+            .foreign_keys
+            .iter_mut()
+            .find(|existing| existing.name == foreign_key.name)
+        {
+            Some(existing) => {
+                existing.columns.extend(foreign_key.columns);
+                existing.foreign_columns.extend(foreign_key.foreign_columns);
+            }
+            None => table.foreign_keys.push(foreign_key),
+        }
+    }
+
+    /// Add an index to a table.  Indexes are listed a column at a time, in
+    /// index order, so an index's columns are appended to the index with its
+    /// name.
+    pub fn add_index(&mut self, table: &str, index: SchemaIndex) {
+        let Some(table) = self.tables.get_mut(table) else {
+            return;
+        };
+
+        match table
+            .indexes
+            .iter_mut()
+            .find(|existing| existing.name == index.name)
+        {
+            Some(existing) => existing.columns.extend(index.columns),
+            None => table.indexes.push(index),
+        }
+    }
+}
+
+/// Convert a row count from the database's statistics, which are negative
+/// when a table hasn't been analyzed
+pub(crate) fn row_estimate(rows: Option<i64>) -> Option<u64> {
+    rows.and_then(|rows| u64::try_from(rows).ok())
+}
+
+/// Treat empty comments as missing
+pub(crate) fn comment(comment: Option<String>) -> Option<String> {
+    comment.filter(|comment| !comment.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_schema() -> DatabaseSchema {
+        let mut tables = BTreeMap::new();
+        tables.insert(
+            "orders".to_string(),
+            SchemaTable::new("orders".into(), "public".into()),
+        );
+
+        DatabaseSchema {
+            database: "test".into(),
+            tables,
+        }
+    }
+
+    #[test]
+    fn test_add_keys_and_indexes() {
+        let mut schema = new_schema();
+        let foreign_key = |column: &str, foreign_column: &str| SchemaForeignKey {
+            name: "orders_customer_fk".into(),
+            columns: vec![column.into()],
+            foreign_schema: "public".into(),
+            foreign_table: "customers".into(),
+            foreign_columns: vec![foreign_column.into()],
+        };
+        let index = |column: &str| SchemaIndex {
+            name: "orders_idx".into(),
+            columns: vec![column.into()],
+            is_unique: false,
+        };
+
+        schema.add_primary_key_column("orders", "id".into());
+        schema.add_foreign_key("orders", foreign_key("customer_id", "id"));
+        schema.add_foreign_key("orders", foreign_key("region", "region"));
+        schema.add_index("orders", index("created_at"));
+        schema.add_index("orders", index("status"));
+
+        // tables that aren't in the schema are ignored
+        schema.add_primary_key_column("missing", "id".into());
+
+        let table = &schema.tables["orders"];
+        assert_eq!(table.primary_key, vec!["id"]);
+        assert_eq!(table.foreign_keys.len(), 1);
+        assert_eq!(table.foreign_keys[0].columns, vec!["customer_id", "region"]);
+        assert_eq!(table.foreign_keys[0].foreign_columns, vec!["id", "region"]);
+        assert_eq!(table.indexes.len(), 1);
+        assert_eq!(table.indexes[0].columns, vec!["created_at", "status"]);
+    }
+
+    #[test]
+    fn test_row_estimate() {
+        assert_eq!(row_estimate(Some(10)), Some(10));
+        assert_eq!(row_estimate(Some(-1)), None);
+        assert_eq!(row_estimate(None), None);
+    }
+}
@@ -3,27 +3,30 @@
 //! Functions to interact with Snowflake
 
 use arrow::array::ArrayRef;
-use arrow::util::pretty::pretty_format_batches;
 use arrow_array::array::Array;
 use async_trait::async_trait;
-use bytes::Bytes;
 use futures_util::stream::StreamExt;
-use parquet::arrow::ArrowWriter;
 use serde::{Deserialize, Serialize};
 use snowflake_api::responses::ExecResponse;
 use snowflake_api::{QueryResult, RawQueryResult, SnowflakeApi};
 use std::collections::BTreeMap;
+use std::io::Write;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::arrow::arrow_type::ArrowType;
 use crate::error::{Result, SharedError};
-use crate::sql::Connection;
 use crate::sql::error::Sql as SqlError;
-use crate::sql::schema::{DatabaseSchema, SchemaColumn, SchemaTable};
+use crate::sql::parameter::{SqlParameter, inline_parameters};
+use crate::sql::parquet_writer::ParquetBatchWriter;
+use crate::sql::schema::{
+    DatabaseSchema, SchemaColumn, SchemaForeignKey, SchemaTable, TableKind, comment,
+};
+use crate::sql::{CancelQuery, Connection};
 use crate::utils::array::transpose;
 
 /// Snowflake connection
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnowflakeConnection {
     pub account_identifier: String,
     pub username: String,
@@ -32,6 +35,14 @@ pub struct SnowflakeConnection {
     pub database: String,
     pub schema: Option<String>,
     pub role: Option<String>,
+
+    /// Only allow queries that read data
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Cache query results for this many seconds
+    #[serde(default)]
+    pub cache_ttl_seconds: Option<u64>,
 }
 
 impl SnowflakeConnection {
@@ -53,8 +64,95 @@ impl SnowflakeConnection {
             database,
             schema,
             role,
+            read_only: false,
+            cache_ttl_seconds: None,
         }
     }
+
+    /// Only allow queries that read data
+    pub fn with_read_only(self, read_only: bool) -> SnowflakeConnection {
+        SnowflakeConnection { read_only, ..self }
+    }
+
+    /// Cache query results for this many seconds
+    pub fn with_cache_ttl(self, cache_ttl_seconds: Option<u64>) -> SnowflakeConnection {
+        SnowflakeConnection {
+            cache_ttl_seconds,
+            ..self
+        }
+    }
+
+    /// Run a query whose columns are all strings, returning its rows
+    async fn query_strings(client: &SnowflakeApi, sql: &str) -> Result<Vec<Vec<String>>> {
+        let query_result = client
+            .exec(sql)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
+
+        let mut data: Vec<Vec<String>> = vec![];
+
+        match query_result {
+            QueryResult::Arrow(a) => {
+                for batch in a {
+                    let num_cols = batch.num_columns();
+                    data.resize(num_cols, vec![]);
+
+                    #[allow(clippy::needless_range_loop)]
+                    for col_index in 0..num_cols {
+                        let col = batch.column(col_index);
+
+                        // convert columns into a vec of strings
+                        let col_values = col
+                            .as_any()
+                            .downcast_ref::<arrow::array::StringArray>()
+                            .ok_or_else(|| {
+                                SharedError::Sql(SqlError::Schema(format!(
+                                    "Expected column {col_index} to be a string"
+                                )))
+                            })?
+                            .iter()
+                            .map(|s| s.unwrap_or_default().to_owned())
+                            .collect::<Vec<String>>();
+
+                        // data in coming in as batches, so we need to combine them
+                        data[col_index].extend(col_values);
+                    }
+                }
+            }
+            QueryResult::Json(j) => {
+                return Err(SharedError::Sql(SqlError::Schema(format!(
+                    "Unexpected JSON result: {j}"
+                ))));
+            }
+            QueryResult::Empty => { /* noop */ }
+        }
+
+        Ok(transpose(data))
+    }
+}
+
+/// Cancels a Snowflake query with `SYSTEM$CANCEL_ALL_QUERIES`, run from a
+/// new session.  The API we use doesn't return a query's id until it
+/// completes, so the queries of the session that runs it are cancelled (the
+/// session only runs that query).
+pub struct SnowflakeCancelQuery {
+    connection: SnowflakeConnection,
+    session_id: u64,
+}
+
+#[async_trait]
+impl CancelQuery for SnowflakeCancelQuery {
+    async fn cancel(&self) -> Result<()> {
+        let client = self.connection.connect().await?;
+        let sql = format!("SELECT SYSTEM$CANCEL_ALL_QUERIES({})", self.session_id);
+
+        client
+            .exec(&sql)
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
+
+        Ok(())
+    }
 }
 
 /// Implement the Connection trait for Snowflake
@@ -87,6 +185,14 @@ impl<'a> Connection<'a> for SnowflakeConnection {
         unimplemented!();
     }
 
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl_seconds.map(Duration::from_secs)
+    }
+
     /// Connect to a Snowflake database
     async fn connect(&self) -> Result<SnowflakeApi> {
         let client = SnowflakeApi::with_password_auth(
@@ -107,17 +213,44 @@ impl<'a> Connection<'a> for SnowflakeConnection {
         Ok(client)
     }
 
+    /// The query is identified by the id of the client's session, which
+    /// starts the session before the query runs
+    async fn cancel_handle(
+        &self,
+        client: &mut Self::Conn,
+        _sql: &str,
+        _params: &[SqlParameter],
+    ) -> Result<Option<Box<dyn CancelQuery>>> {
+        let rows = Self::query_strings(client, "SELECT CURRENT_SESSION()").await?;
+        let session_id = rows
+            .first()
+            .and_then(|row| row.first())
+            .and_then(|session_id| session_id.parse::<u64>().ok())
+            .ok_or_else(|| {
+                SharedError::Sql(SqlError::Query(format!("Unexpected session id: {rows:?}")))
+            })?;
+
+        Ok(Some(Box::new(SnowflakeCancelQuery {
+            connection: self.clone(),
+            session_id,
+        })))
+    }
+
     /// Query rows from a Snowflake database
-    async fn query(
+    async fn query_stream<W: Write + Send>(
         &mut self,
         _client: &mut Self::Conn,
         sql: &str,
-        max_bytes: Option<u64>,
-    ) -> Result<(Bytes, bool, usize)> {
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
         let query_error = |e: String| SharedError::Sql(SqlError::Query(e));
 
+        // the Snowflake API we use doesn't support bindings, so parameters
+        // are inlined as escaped literals
+        let sql = inline_parameters(sql, params)?;
         let query_result = _client
-            .exec_raw(sql, true)
+            .exec_raw(&sql, true)
             .await
             .map_err(|e| query_error(e.to_string()))?;
 
@@ -126,13 +259,6 @@ impl<'a> Connection<'a> for SnowflakeConnection {
 
             while let Some(bytes) = bytes_stream.next().await {
                 let bytes = bytes.map_err(|e| query_error(e.to_string()))?;
-
-                if let Some(max_bytes) = max_bytes
-                    && (chunks.len() + bytes.len()) as u64 > max_bytes
-                {
-                    return Ok((Bytes::new(), true, 0));
-                }
-
                 chunks.push(bytes);
             }
 
@@ -146,20 +272,18 @@ impl<'a> Connection<'a> for SnowflakeConnection {
             let query_result = raw_query_result
                 .deserialize_arrow()
                 .map_err(|e| query_error(e.to_string()))?;
-            let mut num_records = 0;
 
             if let QueryResult::Arrow(batches) = query_result {
-                let file = Vec::new();
-                let mut writer = ArrowWriter::try_new(file, batches[0].schema(), None)?;
-                println!("batches: {}", pretty_format_batches(&batches).unwrap());
+                // the whole result is downloaded, so the total is known
+                // even when it's cut off at the byte limit
+                let mut total_records = 0;
 
                 for batch in batches {
-                    num_records += batch.num_rows();
-                    writer.write(&batch)?;
+                    total_records += batch.num_rows();
+                    writer.write_batch(&batch)?;
                 }
 
-                let parquet = writer.into_inner()?;
-                return Ok((parquet.into(), false, num_records));
+                return Ok(Some(total_records));
             }
         }
 
@@ -170,15 +294,20 @@ impl<'a> Connection<'a> for SnowflakeConnection {
 
     async fn schema(&self, _client: &mut Self::Conn) -> Result<DatabaseSchema> {
         let database = self.database.to_owned();
+
+        // tables and views, with their columns (all values are strings)
         let sql = format!(
             "
             SELECT
-                db.database_name,
                 sch.schema_name,
                 tbl.table_name,
+                tbl.table_type,
+                tbl.comment,
+                tbl.row_count::varchar,
                 col.column_name,
                 col.data_type,
-                col.is_nullable
+                col.is_nullable,
+                col.comment
             FROM
                 {database}.information_schema.columns col
             JOIN
@@ -200,41 +329,7 @@ impl<'a> Connection<'a> for SnowflakeConnection {
                 col.ordinal_position;"
         );
 
-        let row_stream = _client
-            .exec(&sql)
-            .await
-            .map_err(|e| SharedError::Sql(SqlError::Query(e.to_string())))?;
-
-        let mut data = vec![vec![]; 6];
-
-        match row_stream {
-            QueryResult::Arrow(a) => {
-                for batch in a {
-                    let num_cols = batch.num_columns();
-
-                    #[allow(clippy::needless_range_loop)]
-                    for col_index in 0..num_cols {
-                        let col = batch.column(col_index);
-
-                        // convert columns into a vec of strings
-                        let col_values = col
-                            .as_any()
-                            .downcast_ref::<arrow::array::StringArray>()
-                            .unwrap()
-                            .iter()
-                            .map(|s| s.unwrap_or_default().to_owned())
-                            .collect::<Vec<String>>();
-
-                        // data in coming in as batches, so we need to combine them
-                        data[col_index].extend(col_values);
-                    }
-                }
-            }
-            QueryResult::Json(j) => unimplemented!("{j}"),
-            QueryResult::Empty => { /* noop */ }
-        }
-
-        let rows = transpose(data);
+        let rows = Self::query_strings(_client, &sql).await?;
         let mut schema = DatabaseSchema {
             database: self.database.to_owned(),
             tables: BTreeMap::new(),
@@ -245,24 +340,80 @@ impl<'a> Connection<'a> for SnowflakeConnection {
                 data.map(|s| s.to_string())
                     .unwrap_or(format!("Unknown {kind} - {index}"))
             };
-            let table_name = safe_get(row.get(2), "Table");
+            let optional = |index: usize| comment(row.get(index).cloned());
+            let table_name = safe_get(row.get(1), "Table");
 
             schema
                 .tables
                 .entry(table_name.to_owned())
                 .or_insert_with(|| SchemaTable {
-                    name: table_name,
-                    schema: safe_get(row.get(1), "Schema"),
-                    columns: vec![],
+                    kind: match row.get(2).map(String::as_str) {
+                        Some("VIEW") => TableKind::View,
+                        Some("MATERIALIZED VIEW") => TableKind::MaterializedView,
+                        _ => TableKind::Table,
+                    },
+                    comment: optional(3),
+                    row_estimate: optional(4).and_then(|rows| rows.parse().ok()),
+                    ..SchemaTable::new(table_name, safe_get(row.get(0), "Schema"))
                 })
                 .columns
                 .push(SchemaColumn {
-                    name: safe_get(row.get(3), "Column"),
-                    r#type: safe_get(row.get(4), "Type"),
-                    is_nullable: row.get(5).map_or("NO", |v| v).to_uppercase() == "YES",
+                    name: safe_get(row.get(5), "Column"),
+                    r#type: safe_get(row.get(6), "Type"),
+                    is_nullable: row.get(7).map_or("NO", |v| v).to_uppercase() == "YES",
+                    comment: optional(8),
                 });
         }
 
+        // keys aren't in the information schema, so they're read from the
+        // results of SHOW commands (Snowflake doesn't have indexes)
+        _client
+            .exec(&format!("SHOW PRIMARY KEYS IN DATABASE {database}"))
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Schema(e.to_string())))?;
+        let sql = r#"
+            SELECT "table_name", "column_name"
+            FROM TABLE(RESULT_SCAN(LAST_QUERY_ID()))
+            ORDER BY "table_name", "key_sequence""#;
+
+        for row in Self::query_strings(_client, sql).await? {
+            if let [table_name, column] = row.as_slice() {
+                schema.add_primary_key_column(table_name, column.to_owned());
+            }
+        }
+
+        _client
+            .exec(&format!("SHOW IMPORTED KEYS IN DATABASE {database}"))
+            .await
+            .map_err(|e| SharedError::Sql(SqlError::Schema(e.to_string())))?;
+        let sql = r#"
+            SELECT "fk_table_name", "fk_name", "fk_column_name", "pk_schema_name", "pk_table_name", "pk_column_name"
+            FROM TABLE(RESULT_SCAN(LAST_QUERY_ID()))
+            ORDER BY "fk_table_name", "fk_name", "key_sequence""#;
+
+        for row in Self::query_strings(_client, sql).await? {
+            if let [
+                table_name,
+                name,
+                column,
+                foreign_schema,
+                foreign_table,
+                foreign_column,
+            ] = row.as_slice()
+            {
+                schema.add_foreign_key(
+                    table_name,
+                    SchemaForeignKey {
+                        name: name.to_owned(),
+                        columns: vec![column.to_owned()],
+                        foreign_schema: foreign_schema.to_owned(),
+                        foreign_table: foreign_table.to_owned(),
+                        foreign_columns: vec![foreign_column.to_owned()],
+                    },
+                );
+            }
+        }
+
         Ok(schema)
     }
 }
@@ -270,6 +421,7 @@ impl<'a> Connection<'a> for SnowflakeConnection {
 pub mod tests {
 
     use super::*;
+    use bytes::Bytes;
     use std::sync::{LazyLock, Mutex};
 
     pub const PARQUET_FILE: &str = "data/parquet/all_native_data_types-snowflake.parquet";
@@ -326,7 +478,7 @@ pub mod tests {
     //     ";
 
     //     connection
-    //         .query(&mut client.unwrap(), sql, None)
+    //         .query(&mut client.unwrap(), sql, &[], None)
     //         .await
     //         .unwrap();
 
@@ -349,91 +501,109 @@ pub mod tests {
                 name: "INTEGER_COL".into(),
                 r#type: "NUMBER".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "FLOAT_COL".into(),
                 r#type: "FLOAT".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "NUMBER_COL".into(),
                 r#type: "NUMBER".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "DECIMAL_COL".into(),
                 r#type: "NUMBER".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "BOOLEAN_COL".into(),
                 r#type: "BOOLEAN".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "VARCHAR_COL".into(),
                 r#type: "TEXT".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "CHAR_COL".into(),
                 r#type: "TEXT".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "STRING_COL".into(),
                 r#type: "TEXT".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "BINARY_COL".into(),
                 r#type: "BINARY".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "DATE_COL".into(),
                 r#type: "DATE".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "TIME_COL".into(),
                 r#type: "TIME".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "TIMESTAMP_NTZ_COL".into(),
                 r#type: "TIMESTAMP_NTZ".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "TIMESTAMP_LTZ_COL".into(),
                 r#type: "TIMESTAMP_LTZ".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "TIMESTAMP_TZ_COL".into(),
                 r#type: "TIMESTAMP_TZ".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "VARIANT_COL".into(),
                 r#type: "VARIANT".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "OBJECT_COL".into(),
                 r#type: "OBJECT".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "ARRAY_COL".into(),
                 r#type: "ARRAY".into(),
                 is_nullable: true,
+                comment: None,
             },
             SchemaColumn {
                 name: "GEOGRAPHY_COL".into(),
                 r#type: "GEOGRAPHY".into(),
                 is_nullable: true,
+                comment: None,
             },
         ]
     }
@@ -447,6 +617,7 @@ pub mod tests {
             .query(
                 &mut client,
                 "select * from ALL_NATIVE_DATA_TYPES.ALL_NATIVE_DATA_TYPES.ALL_NATIVE_DATA_TYPES limit 1;",
+                &[],
                 max_bytes,
             )
             .await
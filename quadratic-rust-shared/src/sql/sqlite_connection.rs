@@ -0,0 +1,547 @@
+//! SQLite
+//!
+//! Functions to interact with SQLite database files
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use arrow::datatypes::Date32Type;
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use sqlx::{
+    Column, ConnectOptions, Row, SqliteConnection as SqlxSqliteConnection, TypeInfo, ValueRef,
+    sqlite::{SqliteColumn, SqliteConnectOptions, SqliteRow},
+};
+
+use crate::error::Result;
+use crate::quadratic_api::Connection as ApiConnection;
+use crate::sql::database_file::resolve_database_path;
+use crate::sql::parameter::SqlParameter;
+use crate::sql::parquet_writer::ParquetBatchWriter;
+use crate::sql::schema::{
+    DatabaseSchema, SchemaColumn, SchemaForeignKey, SchemaIndex, SchemaTable, TableKind,
+};
+use crate::sql::{ArrowType, Connection, connect_error, query_error, schema_error};
+use crate::{bind_sqlx_parameters, convert_sqlx_type, to_arrow_type};
+
+/// SQLite connection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SqliteConnection {
+    /// Path to the SQLite database file, relative to `directory`
+    pub database: String,
+
+    /// The directory that database files are opened from, set by the service
+    #[serde(skip)]
+    pub directory: Option<PathBuf>,
+}
+
+impl From<&ApiConnection<SqliteConnection>> for SqliteConnection {
+    fn from(connection: &ApiConnection<SqliteConnection>) -> Self {
+        let details = connection.type_details.to_owned();
+        SqliteConnection::new(details.database)
+    }
+}
+
+impl SqliteConnection {
+    /// Create a new SQLite connection
+    pub fn new(database: String) -> SqliteConnection {
+        SqliteConnection {
+            database,
+            directory: None,
+        }
+    }
+
+    /// Only open database files within `directory`
+    pub fn with_directory(mut self, directory: Option<impl Into<PathBuf>>) -> SqliteConnection {
+        self.directory = directory.map(Into::into);
+        self
+    }
+
+    /// The file name of the database, used as the database name in the schema
+    fn database_name(&self) -> String {
+        Path::new(&self.database)
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.database.to_owned())
+    }
+
+    /// Query all rows from a SQLite database
+    pub async fn query_all(pool: &mut SqlxSqliteConnection, sql: &str) -> Result<Vec<SqliteRow>> {
+        let rows = sqlx::query(sql)
+            .fetch_all(pool)
+            .await
+            .map_err(query_error)?;
+
+        Ok(rows)
+    }
+}
+
+#[async_trait]
+impl<'a> Connection<'a> for SqliteConnection {
+    type Conn = SqlxSqliteConnection;
+    type Row = SqliteRow;
+    type Column = SqliteColumn;
+
+    /// Get the length of a row
+    fn row_len(row: &Self::Row) -> usize {
+        row.len()
+    }
+
+    /// Get the columns of a row
+    fn row_columns(row: &Self::Row) -> Box<dyn Iterator<Item = &Self::Column> + '_> {
+        Box::new(row.columns().iter())
+    }
+
+    /// Get the name of a column
+    fn column_name(&self, col: &Self::Column, _index: usize) -> String {
+        col.name().to_string()
+    }
+
+    /// Open a SQLite database file in read-only mode.  Queries can't attach
+    /// other database files, which could be outside of `directory`.
+    async fn connect(&self) -> Result<Self::Conn> {
+        let path = resolve_database_path(self.directory.as_deref(), &self.database)?;
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .read_only(true)
+            .create_if_missing(false);
+
+        let mut pool = options
+            .connect()
+            .await
+            .map_err(|e| connect_error(format!("{:?}: {e}", self.database)))?;
+
+        let mut handle = pool.lock_handle().await.map_err(connect_error)?;
+
+        // SAFETY: the handle is locked, so the connection is open and isn't
+        // used by another thread
+        unsafe {
+            libsqlite3_sys::sqlite3_limit(
+                handle.as_raw_handle().as_ptr(),
+                libsqlite3_sys::SQLITE_LIMIT_ATTACHED,
+                0,
+            );
+        }
+
+        drop(handle);
+
+        Ok(pool)
+    }
+
+    /// Query rows from a SQLite database
+    async fn query_stream<W: Write + Send>(
+        &mut self,
+        pool: &mut Self::Conn,
+        sql: &str,
+        params: &[SqlParameter],
+        writer: &mut ParquetBatchWriter<W>,
+    ) -> Result<Option<usize>> {
+        let mut stream = bind_sqlx_parameters!(sqlx::query(sql), params).fetch(pool);
+
+        while let Some(row) = stream.next().await {
+            let row = row.map_err(query_error)?;
+
+            // stop fetching rows once the result is over the byte limit
+            if !self.write_row(&row, writer)? {
+                break;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get the schema of a SQLite database
+    async fn schema(&self, pool: &mut Self::Conn) -> Result<DatabaseSchema> {
+        let sql = "
+            select m.name as table_name, p.name as column_name, p.type as column_type, p.\"notnull\" as not_null,
+                m.type as table_type
+            from sqlite_master as m
+            join pragma_table_info(m.name) as p
+            where m.type in ('table', 'view') and m.name not like 'sqlite_%'
+            order by m.name, p.cid";
+
+        let rows = sqlx::query(sql)
+            .fetch_all(&mut *pool)
+            .await
+            .map_err(schema_error)?;
+
+        let mut schema = DatabaseSchema {
+            database: self.database_name(),
+            tables: BTreeMap::new(),
+        };
+
+        for row in rows.into_iter() {
+            let table_name = row.get::<String, usize>(0);
+
+            schema
+                .tables
+                .entry(table_name.to_owned())
+                .or_insert_with(|| SchemaTable {
+                    kind: match row.get::<String, usize>(4).as_str() {
+                        "view" => TableKind::View,
+                        _ => TableKind::Table,
+                    },
+                    ..SchemaTable::new(table_name, "main".into())
+                })
+                .columns
+                .push(SchemaColumn {
+                    name: row.get::<String, usize>(1),
+                    r#type: row.get::<String, usize>(2).to_lowercase(),
+                    is_nullable: row.get::<i64, usize>(3) == 0,
+                    comment: None,
+                });
+        }
+
+        // primary keys, a row per column
+        let sql = "
+            select m.name as table_name, p.name as column_name
+            from sqlite_master as m
+            join pragma_table_info(m.name) as p
+            where m.type = 'table' and p.pk > 0
+            order by m.name, p.pk";
+
+        for row in sqlx::query(sql)
+            .fetch_all(&mut *pool)
+            .await
+            .map_err(schema_error)?
+        {
+            schema
+                .add_primary_key_column(&row.get::<String, usize>(0), row.get::<String, usize>(1));
+        }
+
+        // foreign keys, which aren't named, a row per column.  The referenced
+        // column is null when the key references the primary key.
+        let sql = "
+            select m.name as table_name, f.id, f.\"from\", f.\"table\", f.\"to\"
+            from sqlite_master as m
+            join pragma_foreign_key_list(m.name) as f
+            where m.type = 'table'
+            order by m.name, f.id, f.seq";
+
+        for row in sqlx::query(sql)
+            .fetch_all(&mut *pool)
+            .await
+            .map_err(schema_error)?
+        {
+            let table_name = row.get::<String, usize>(0);
+
+            schema.add_foreign_key(
+                &table_name,
+                SchemaForeignKey {
+                    name: format!("{table_name}_fk_{}", row.get::<i64, usize>(1)),
+                    columns: vec![row.get::<String, usize>(2)],
+                    foreign_schema: "main".into(),
+                    foreign_table: row.get::<String, usize>(3),
+                    foreign_columns: vec![row.get::<Option<String>, usize>(4).unwrap_or_default()],
+                },
+            );
+        }
+
+        // indexes, a row per column (expressions are skipped)
+        let sql = "
+            select m.name as table_name, il.name as index_name, il.\"unique\", ii.name as column_name
+            from sqlite_master as m
+            join pragma_index_list(m.name) as il
+            join pragma_index_info(il.name) as ii
+            where m.type = 'table' and ii.name is not null
+            order by m.name, il.name, ii.seqno";
+
+        for row in sqlx::query(sql)
+            .fetch_all(&mut *pool)
+            .await
+            .map_err(schema_error)?
+        {
+            schema.add_index(
+                &row.get::<String, usize>(0),
+                SchemaIndex {
+                    name: row.get::<String, usize>(1),
+                    columns: vec![row.get::<String, usize>(3)],
+                    is_unique: row.get::<i64, usize>(2) == 1,
+                },
+            );
+        }
+
+        Ok(schema)
+    }
+
+    /// Convert a row to an Arrow type
+    ///
+    /// SQLite is dynamically typed, so expressions without a declared type
+    /// fall back to the type of the value in the row.
+    fn to_arrow(&self, row: &Self::Row, column: &Self::Column, index: usize) -> ArrowType {
+        let type_name = match column.type_info().name() {
+            "NULL" => match row.try_get_raw(index) {
+                Ok(value) => value.type_info().name().to_owned(),
+                Err(_) => return ArrowType::Null,
+            },
+            name => name.to_owned(),
+        };
+
+        match type_name.as_str() {
+            "TEXT" => to_arrow_type!(ArrowType::Utf8, String, row, index),
+            "INTEGER" => to_arrow_type!(ArrowType::Int64, i64, row, index),
+            "REAL" | "NUMERIC" => to_arrow_type!(ArrowType::Float64, f64, row, index),
+            "BOOLEAN" => to_arrow_type!(ArrowType::Boolean, bool, row, index),
+            "DATETIME" => to_arrow_type!(ArrowType::Timestamp, NaiveDateTime, row, index),
+            "DATE" => match convert_sqlx_type!(NaiveDate, row, index) {
+                Some(naive_date) => ArrowType::Date32(Date32Type::from_naive_date(naive_date)),
+                None => ArrowType::Null,
+            },
+            "TIME" => to_arrow_type!(ArrowType::Time32, NaiveTime, row, index),
+            "NULL" => ArrowType::Void,
+            // BLOB and anything else we can't represent
+            _ => ArrowType::Unsupported,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+
+    use super::*;
+    use std::str::FromStr;
+    use tempfile::TempDir;
+
+    /// Create a SQLite database file with a table of all native data types
+    pub async fn new_sqlite_connection(temp_dir: &TempDir) -> SqliteConnection {
+        let path = temp_dir.path().join("sqlite-connection.db");
+        let mut pool = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap();
+
+        let sql = "
+            create table all_native_data_types (
+                id integer primary key not null,
+                integer_col integer,
+                real_col real,
+                text_col text,
+                blob_col blob,
+                boolean_col boolean,
+                date_col date,
+                datetime_col datetime,
+                time_col time
+            );
+            insert into all_native_data_types values
+                (1, 42, 3.5, 'text_data', x'0102', true, '2024-05-28', '2024-05-28 12:34:56', '12:34:56'),
+                (2, null, null, null, null, null, null, null, null);
+            create table data_type_notes (
+                id integer primary key not null,
+                data_type_id integer references all_native_data_types(id),
+                note text
+            );
+            create unique index data_type_notes_data_type_id on data_type_notes (data_type_id);
+            create view text_values as select id, text_col from all_native_data_types;";
+
+        sqlx::raw_sql(sql).execute(&mut pool).await.unwrap();
+
+        SqliteConnection::new("sqlite-connection.db".into()).with_directory(Some(temp_dir.path()))
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_connection() {
+        let temp_dir = TempDir::new().unwrap();
+        let connection = new_sqlite_connection(&temp_dir).await;
+
+        assert!(connection.connect().await.is_ok());
+
+        for database in ["missing.db", "../sqlite-connection.db", "/etc/passwd"] {
+            let connection =
+                SqliteConnection::new(database.into()).with_directory(Some(temp_dir.path()));
+            assert!(connection.connect().await.is_err());
+        }
+
+        // database files are disabled without a directory
+        let connection = SqliteConnection::new("sqlite-connection.db".into());
+        assert!(connection.connect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_is_read_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_sqlite_connection(&temp_dir).await;
+        let mut pool = connection.connect().await.unwrap();
+        let result = connection
+            .query(&mut pool, "delete from all_native_data_types", &[], None)
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_cannot_attach_databases() {
+        let temp_dir = TempDir::new().unwrap();
+        let other_dir = TempDir::new().unwrap();
+        let other = new_sqlite_connection(&other_dir).await;
+        let mut connection = new_sqlite_connection(&temp_dir).await;
+        let mut pool = connection.connect().await.unwrap();
+
+        for database in [
+            other_dir.path().join(&other.database).display().to_string(),
+            ":memory:".into(),
+        ] {
+            let sql = format!("attach database '{database}' as other");
+            let result = connection.query(&mut pool, &sql, &[], None).await;
+            assert!(result.is_err(), "expected {sql} to fail");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_query_to_arrow() {
+        let temp_dir = TempDir::new().unwrap();
+        let connection = new_sqlite_connection(&temp_dir).await;
+        let mut pool = connection.connect().await.unwrap();
+        let sql = "select * from all_native_data_types order by id";
+        let rows = SqliteConnection::query_all(&mut pool, sql).await.unwrap();
+
+        let row = &rows[0];
+        let columns = row.columns();
+        let to_arrow = |index: usize| connection.to_arrow(row, &columns[index], index);
+
+        assert_eq!(to_arrow(0), ArrowType::Int64(1));
+        assert_eq!(to_arrow(1), ArrowType::Int64(42));
+        assert_eq!(to_arrow(2), ArrowType::Float64(3.5));
+        assert_eq!(to_arrow(3), ArrowType::Utf8("text_data".into()));
+        assert_eq!(to_arrow(4), ArrowType::Unsupported);
+        assert_eq!(to_arrow(5), ArrowType::Boolean(true));
+        assert_eq!(to_arrow(6), ArrowType::Date32(19871));
+        assert_eq!(
+            to_arrow(7),
+            ArrowType::Timestamp(NaiveDateTime::from_str("2024-05-28T12:34:56").unwrap())
+        );
+        assert_eq!(
+            to_arrow(8),
+            ArrowType::Time32(NaiveTime::from_str("12:34:56").unwrap())
+        );
+
+        let row = &rows[1];
+        let columns = row.columns();
+        let to_arrow = |index: usize| connection.to_arrow(row, &columns[index], index);
+
+        assert_eq!(to_arrow(1), ArrowType::Null);
+        assert_eq!(to_arrow(3), ArrowType::Null);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_query_expressions() {
+        let temp_dir = TempDir::new().unwrap();
+        let connection = new_sqlite_connection(&temp_dir).await;
+        let mut pool = connection.connect().await.unwrap();
+        let sql = "select 1 + 1, 'a' || 'b', 1.5 * 2";
+        let rows = SqliteConnection::query_all(&mut pool, sql).await.unwrap();
+
+        let row = &rows[0];
+        let columns = row.columns();
+        let to_arrow = |index: usize| connection.to_arrow(row, &columns[index], index);
+
+        assert_eq!(to_arrow(0), ArrowType::Int64(2));
+        assert_eq!(to_arrow(1), ArrowType::Utf8("ab".into()));
+        assert_eq!(to_arrow(2), ArrowType::Float64(3.0));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_query_to_parquet() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_sqlite_connection(&temp_dir).await;
+        let mut pool = connection.connect().await.unwrap();
+        let sql = "select * from all_native_data_types";
+
+        let (bytes, over_the_limit, num_records) =
+            connection.query(&mut pool, sql, &[], None).await.unwrap();
+        assert!(!bytes.is_empty());
+        assert!(!over_the_limit);
+        assert_eq!(num_records, 2);
+
+        let (_, over_the_limit, num_records) = connection
+            .query(&mut pool, sql, &[], Some(1))
+            .await
+            .unwrap();
+        assert!(over_the_limit);
+        assert_eq!(num_records, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_query_with_parameters() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut connection = new_sqlite_connection(&temp_dir).await;
+        let mut pool = connection.connect().await.unwrap();
+        let sql = "select id from all_native_data_types where text_col = ?1 or id in (?2, ?3)";
+        let params = vec![
+            SqlParameter::Text("x' or '1'='1".into()),
+            SqlParameter::Number(3.into()),
+            SqlParameter::Number(1.into()),
+        ];
+
+        let (_, _, num_records) = connection
+            .query(&mut pool, sql, &params, None)
+            .await
+            .unwrap();
+        assert_eq!(num_records, 1);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        let connection = new_sqlite_connection(&temp_dir).await;
+        let mut pool = connection.connect().await.unwrap();
+        let schema = connection.schema(&mut pool).await.unwrap();
+
+        assert_eq!(schema.database, "sqlite-connection");
+
+        let table = &schema.tables["all_native_data_types"];
+        assert_eq!(table.schema, "main");
+
+        let column = |name: &str, r#type: &str, is_nullable: bool| SchemaColumn {
+            name: name.into(),
+            r#type: r#type.into(),
+            is_nullable,
+            comment: None,
+        };
+        let expected = vec![
+            column("id", "integer", false),
+            column("integer_col", "integer", true),
+            column("real_col", "real", true),
+            column("text_col", "text", true),
+            column("blob_col", "blob", true),
+            column("boolean_col", "boolean", true),
+            column("date_col", "date", true),
+            column("datetime_col", "datetime", true),
+            column("time_col", "time", true),
+        ];
+
+        assert_eq!(table.columns, expected);
+        assert_eq!(table.kind, TableKind::Table);
+        assert_eq!(table.primary_key, vec!["id"]);
+
+        let notes = &schema.tables["data_type_notes"];
+        assert_eq!(
+            notes.foreign_keys,
+            vec![SchemaForeignKey {
+                name: "data_type_notes_fk_0".into(),
+                columns: vec!["data_type_id".into()],
+                foreign_schema: "main".into(),
+                foreign_table: "all_native_data_types".into(),
+                foreign_columns: vec!["id".into()],
+            }]
+        );
+        assert_eq!(
+            notes.indexes,
+            vec![SchemaIndex {
+                name: "data_type_notes_data_type_id".into(),
+                columns: vec!["data_type_id".into()],
+                is_unique: true,
+            }]
+        );
+
+        let view = &schema.tables["text_values"];
+        assert_eq!(view.kind, TableKind::View);
+        assert!(view.primary_key.is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+//! Statement Classification
+//!
+//! Classify the statements of a query without a full SQL parser, so that
+//! read-only connections can reject statements that change data or schemas
+//! before they're sent to the database.
+//!
+//! Queries are split into statements on semicolons, skipping comments,
+//! strings and quoted identifiers.  A statement is classified by its first
+//! keyword, and a query (e.g. `SELECT` or `WITH`) that contains a keyword
+//! that writes (e.g. a data-modifying CTE or `SELECT ... INTO`) is
+//! classified by that keyword instead.  Classification is conservative: it
+//! may reject statements that are read-only, but shouldn't accept
+//! statements that aren't.  It can't see into functions with side effects,
+//! which is why read-only connections also use read-only transactions where
+//! the database supports them.
+
+use strum_macros::Display;
+
+use crate::error::{Result, SharedError};
+use crate::sql::error::Sql as SqlError;
+use crate::utils::sql_lexer::{LexemeKind, Quoting, lex};
+
+/// What a statement does
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementKind {
+    /// Reads data (SELECT, WITH, VALUES, SHOW, DESCRIBE, EXPLAIN)
+    Query,
+
+    /// Changes data (INSERT, UPDATE, DELETE, MERGE, COPY)
+    Dml,
+
+    /// Changes the schema (CREATE, ALTER, DROP, TRUNCATE)
+    Ddl,
+
+    /// Anything else (transactions, SET, GRANT, CALL, ...)
+    Other,
+}
+
+/// A classified statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub kind: StatementKind,
+
+    /// The keyword the statement was classified by, in uppercase
+    pub keyword: String,
+}
+
+impl Statement {
+    /// Classify a statement by its keywords
+    fn new(tokens: &[Token]) -> Option<Self> {
+        // parenthesized queries, e.g. `(SELECT 1) UNION (SELECT 2)`
+        let mut words = tokens
+            .iter()
+            .skip_while(|token| **token == Token::Symbol('('));
+
+        let keyword = match words.next()? {
+            Token::Word(word) => word.to_owned(),
+            Token::Symbol(symbol) => symbol.to_string(),
+        };
+        let kind = keyword_kind(&keyword);
+
+        // SHOW and DESCRIBE name objects, e.g. `SHOW CREATE TABLE t`
+        if kind != StatementKind::Query || matches!(keyword.as_str(), "SHOW" | "DESCRIBE" | "DESC")
+        {
+            return Some(Statement { kind, keyword });
+        }
+
+        // look for writes within a query
+        let write = tokens.iter().enumerate().find_map(|(index, token)| {
+            let Token::Word(word) = token else {
+                return None;
+            };
+
+            // qualified names (`t.update`) and functions (`replace(...)`)
+            // aren't keywords
+            let after_dot = index > 0 && tokens[index - 1] == Token::Symbol('.');
+            let before_paren = tokens.get(index + 1) == Some(&Token::Symbol('('));
+
+            match (after_dot, before_paren, embedded_write_kind(word)) {
+                (false, false, Some(kind)) => Some(Statement {
+                    kind,
+                    keyword: word.to_owned(),
+                }),
+                _ => None,
+            }
+        });
+
+        Some(write.unwrap_or(Statement { kind, keyword }))
+    }
+}
+
+/// The kind of a statement that starts with `keyword`
+fn keyword_kind(keyword: &str) -> StatementKind {
+    match keyword {
+        "SELECT" | "WITH" | "VALUES" | "TABLE" | "SHOW" | "DESCRIBE" | "DESC" | "EXPLAIN" => {
+            StatementKind::Query
+        }
+        "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "UPSERT" | "REPLACE" | "COPY" | "LOAD"
+        | "PUT" | "REMOVE" | "UNLOAD" => StatementKind::Dml,
+        "CREATE" | "ALTER" | "DROP" | "TRUNCATE" | "RENAME" | "COMMENT" | "UNDROP" => {
+            StatementKind::Ddl
+        }
+        _ => StatementKind::Other,
+    }
+}
+
+/// The kind of a keyword that writes when it appears within a query
+fn embedded_write_kind(keyword: &str) -> Option<StatementKind> {
+    match keyword {
+        "INSERT" | "UPDATE" | "DELETE" | "MERGE" | "UPSERT" | "INTO" => Some(StatementKind::Dml),
+        "CREATE" | "ALTER" | "DROP" | "TRUNCATE" => Some(StatementKind::Ddl),
+        "GRANT" | "REVOKE" | "EXEC" | "EXECUTE" | "CALL" => Some(StatementKind::Other),
+        _ => None,
+    }
+}
+
+/// Split a query into statements and classify them.  Empty statements are
+/// skipped.
+pub fn classify(sql: &str) -> Result<Vec<Statement>> {
+    tokenize(sql, Quoting::Standard).map(|statements| {
+        statements
+            .iter()
+            .filter_map(|tokens| Statement::new(tokens))
+            .collect()
+    })
+}
+
+/// Check that every statement of a query only reads data.
+///
+/// Databases disagree on how strings and comments are quoted, so the query
+/// is checked with each way of quoting.  A way that can't tokenize the query
+/// (e.g. `'it\'s'` without backslash escapes) isn't how the database reads
+/// it, unless none of them can.
+pub fn check_read_only(sql: &str) -> Result<()> {
+    let [standard, backslash] = [Quoting::Standard, Quoting::Backslash].map(|q| tokenize(sql, q));
+
+    if let (Err(e), Err(_)) = (&standard, &backslash) {
+        return Err(e.to_owned());
+    }
+
+    for tokens in [standard, backslash].into_iter().flatten().flatten() {
+        if let Some(statement) = Statement::new(&tokens)
+            && statement.kind != StatementKind::Query
+        {
+            return Err(read_only_error(format!(
+                "{} statements are not allowed, only queries that read data can be run",
+                statement.keyword
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_only_error(e: impl ToString) -> SharedError {
+    SharedError::Sql(SqlError::ReadOnly(e.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A keyword or an unquoted identifier, in uppercase
+    Word(String),
+    Symbol(char),
+}
+
+/// Split a query into the tokens of each statement, skipping whitespace,
+/// comments, strings and quoted identifiers
+fn tokenize(sql: &str, quoting: Quoting) -> Result<Vec<Vec<Token>>> {
+    let lexemes =
+        lex(sql, quoting).map_err(|e| read_only_error(format!("The query has an {e}")))?;
+    let mut statements = vec![vec![]];
+
+    for lexeme in lexemes
+        .iter()
+        .filter(|lexeme| lexeme.kind == LexemeKind::Code)
+    {
+        let chars = sql[lexeme.range.clone()].chars().collect::<Vec<_>>();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                c if c.is_whitespace() => i += 1,
+
+                ';' => {
+                    statements.push(vec![]);
+                    i += 1;
+                }
+
+                c if c.is_alphanumeric() || c == '_' => {
+                    let start = i;
+
+                    while i < chars.len()
+                        && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+                    {
+                        i += 1;
+                    }
+
+                    let word = chars[start..i].iter().collect::<String>().to_uppercase();
+
+                    if let Some(statement) = statements.last_mut() {
+                        statement.push(Token::Word(word));
+                    }
+                }
+
+                c => {
+                    if let Some(statement) = statements.last_mut() {
+                        statement.push(Token::Symbol(c));
+                    }
+
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    Ok(statements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(sql: &str) -> Vec<(StatementKind, String)> {
+        classify(sql)
+            .unwrap()
+            .into_iter()
+            .map(|statement| (statement.kind, statement.keyword))
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        use StatementKind::*;
+
+        assert_eq!(
+            kinds("select 1; insert into t values (1); drop table t; begin;;"),
+            vec![
+                (Query, "SELECT".into()),
+                (Dml, "INSERT".into()),
+                (Ddl, "DROP".into()),
+                (Other, "BEGIN".into()),
+            ]
+        );
+
+        // writes within queries
+        assert_eq!(
+            kinds("with d as (delete from t returning *) select * from d"),
+            vec![(Dml, "DELETE".into())]
+        );
+        assert_eq!(kinds("select * into t2 from t"), vec![(Dml, "INTO".into())]);
+        assert_eq!(
+            kinds("(select 1) union (select 2)"),
+            vec![(Query, "SELECT".into())]
+        );
+
+        // functions and qualified names aren't keywords
+        assert_eq!(
+            kinds("select replace(t.name, 'a', 'b'), t.update from t"),
+            vec![(Query, "SELECT".into())]
+        );
+    }
+
+    #[test]
+    fn test_classify_skips_comments_strings_and_identifiers() {
+        let sql = r#"
+            -- delete from t;
+            /* drop table t; /* nested */ */
+            select 'drop table t; it''s', "delete", `insert`, [update], $$ drop $$, $tag$ drop $tag$, $1
+            from t
+        "#;
+
+        assert_eq!(kinds(sql), vec![(StatementKind::Query, "SELECT".into())]);
+    }
+
+    #[test]
+    fn test_check_read_only() {
+        assert!(check_read_only("select * from t where id = $1").is_ok());
+        assert!(check_read_only("show tables").is_ok());
+        assert!(check_read_only("explain select 1").is_ok());
+        assert!(check_read_only("").is_ok());
+
+        let error = check_read_only("select 1; delete from t").unwrap_err();
+        assert_eq!(
+            error,
+            SharedError::Sql(SqlError::ReadOnly(
+                "DELETE statements are not allowed, only queries that read data can be run".into()
+            ))
+        );
+
+        assert!(check_read_only("truncate t").is_err());
+        assert!(check_read_only("set default_transaction_read_only = off").is_err());
+        assert!(check_read_only("explain analyze delete from t").is_err());
+        assert!(check_read_only("select 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_check_read_only_quoting() {
+        // MySQL's block comments don't nest, so it runs the delete
+        assert!(check_read_only("select 1 /* /* */ delete from t; */").is_err());
+
+        // MySQL's `#` comments hide the quote
+        assert!(check_read_only("select 1 # '\ndelete from t; -- '").is_err());
+    }
+
+    #[test]
+    fn test_check_read_only_backslash_escapes() {
+        // MySQL reads `'a\''` as a string, and runs the delete
+        assert!(check_read_only(r"select 'a\'' ; delete from t; select ''").is_err());
+
+        // PostgreSQL reads `'a\'` as a string, and runs the delete
+        assert!(check_read_only(r"select 'a\'; delete from t; select '\''").is_err());
+
+        // only tokenizes with backslash escapes
+        assert!(check_read_only(r"select 'it\'s'").is_ok());
+        assert!(check_read_only("show create table t").is_ok());
+    }
+
+    #[test]
+    fn test_check_read_only_executable_comments() {
+        // MySQL and MariaDB run the contents of executable comments
+        assert!(check_read_only("/*!40101 SET SESSION TRANSACTION READ WRITE */").is_err());
+        assert!(check_read_only("/*! DELETE FROM t */").is_err());
+        assert!(check_read_only("/*M!100100 DELETE FROM t */").is_err());
+        assert!(check_read_only("select 1 /*! ; delete from t */").is_err());
+        assert!(check_read_only("select 1 /*! union select 2").is_err());
+
+        // hints and executable comments that only read
+        assert!(check_read_only("select /*! SQL_NO_CACHE */ * from t").is_ok());
+        assert!(check_read_only("select /*+ MAX_EXECUTION_TIME(1000) */ * from t").is_ok());
+    }
+}
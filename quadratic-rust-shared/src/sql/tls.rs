@@ -0,0 +1,341 @@
+//! TLS
+//!
+//! TLS settings of the connections to PostgreSQL, MySQL and SQL Server, and
+//! how they're passed to sqlx and tiberius.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use sqlx::mysql::{MySqlConnectOptions, MySqlSslMode};
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use strum_macros::Display;
+use tiberius::{Config, EncryptionLevel};
+use uuid::Uuid;
+
+use crate::error::{Result, SharedError};
+use crate::sql::error::Sql as SqlError;
+use crate::sql::tls_error;
+
+/// How a connection uses TLS, named after PostgreSQL's `sslmode`
+#[derive(Display, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum SslMode {
+    /// Never use TLS
+    Disable,
+
+    /// Use TLS when the server supports it
+    #[default]
+    Prefer,
+
+    /// Require TLS, without verifying the server's certificate
+    Require,
+
+    /// Require TLS and verify the server's certificate against the CA
+    VerifyCa,
+
+    /// Require TLS and verify the server's certificate against the CA and
+    /// the server's name
+    VerifyFull,
+}
+
+/// TLS settings of a connection.  Certificates and keys are PEM encoded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TlsConfig {
+    pub ssl_mode: SslMode,
+
+    /// CA bundle to verify the server's certificate against, instead of the
+    /// system's root certificates
+    pub ssl_root_cert: Option<String>,
+
+    /// Client certificate and key, for servers that require mutual TLS
+    pub ssl_cert: Option<String>,
+    pub ssl_key: Option<String>,
+
+    /// The name to verify the server's certificate against, when it isn't
+    /// the host (e.g. when connecting through an SSH tunnel)
+    pub ssl_server_name: Option<String>,
+}
+
+impl TlsConfig {
+    fn root_cert(&self) -> Option<&str> {
+        non_empty(&self.ssl_root_cert)
+    }
+
+    fn client_cert(&self) -> Result<Option<(&str, &str)>> {
+        match (non_empty(&self.ssl_cert), non_empty(&self.ssl_key)) {
+            (Some(cert), Some(key)) => Ok(Some((cert, key))),
+            (None, None) => Ok(None),
+            _ => Err(tls_error(
+                "Both a client certificate and a client key are required",
+            )),
+        }
+    }
+
+    /// The server name to verify, if it's different from the host
+    fn server_name(&self, host: &str) -> Option<&str> {
+        non_empty(&self.ssl_server_name).filter(|server_name| *server_name != host)
+    }
+
+    /// sqlx verifies the certificate against the host it connects to
+    fn check_sqlx_server_name(&self, host: &str, database: &str) -> Result<()> {
+        match self.server_name(host) {
+            Some(_) => Err(tls_error(format!(
+                "{database} connections can't override the TLS server name, use the `verify-ca` SSL mode to skip verifying it"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply the TLS settings to a PostgreSQL connection
+    pub(crate) fn postgres_options(
+        &self,
+        mut options: PgConnectOptions,
+        host: &str,
+    ) -> Result<PgConnectOptions> {
+        self.check_sqlx_server_name(host, "PostgreSQL")?;
+
+        options = options.ssl_mode(match self.ssl_mode {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Prefer => PgSslMode::Prefer,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        });
+
+        if let Some(root_cert) = self.root_cert() {
+            options = options.ssl_root_cert_from_pem(root_cert.as_bytes().to_vec());
+        }
+
+        if let Some((cert, key)) = self.client_cert()? {
+            options = options
+                .ssl_client_cert_from_pem(cert)
+                .ssl_client_key_from_pem(key);
+        }
+
+        Ok(options)
+    }
+
+    /// Apply the TLS settings to a MySQL connection
+    pub(crate) fn mysql_options(
+        &self,
+        mut options: MySqlConnectOptions,
+        host: &str,
+    ) -> Result<MySqlConnectOptions> {
+        self.check_sqlx_server_name(host, "MySQL")?;
+
+        options = options.ssl_mode(match self.ssl_mode {
+            SslMode::Disable => MySqlSslMode::Disabled,
+            SslMode::Prefer => MySqlSslMode::Preferred,
+            SslMode::Require => MySqlSslMode::Required,
+            SslMode::VerifyCa => MySqlSslMode::VerifyCa,
+            SslMode::VerifyFull => MySqlSslMode::VerifyIdentity,
+        });
+
+        if let Some(root_cert) = self.root_cert() {
+            options = options.ssl_ca_from_pem(root_cert.as_bytes().to_vec());
+        }
+
+        if let Some((cert, key)) = self.client_cert()? {
+            options = options
+                .ssl_client_cert_from_pem(cert)
+                .ssl_client_key_from_pem(key);
+        }
+
+        Ok(options)
+    }
+
+    /// Apply the TLS settings to a SQL Server connection.
+    ///
+    /// tiberius always verifies the server's name along with its
+    /// certificate, so `verify-ca` is as strict as `verify-full`.  Set the
+    /// server name to verify a server behind an SSH tunnel.  tiberius reads
+    /// the CA bundle from a file, which is removed when the returned guard is
+    /// dropped (after connecting).
+    pub(crate) fn mssql_config(&self, config: &mut Config, host: &str) -> Result<Option<CaFile>> {
+        if self.client_cert()?.is_some() {
+            return Err(tls_error(
+                "SQL Server connections don't support client certificates",
+            ));
+        }
+
+        config.host(self.server_name(host).unwrap_or(host));
+        config.encryption(self.mssql_encryption());
+
+        let ca_file = match self.ssl_mode {
+            SslMode::Disable => None,
+            SslMode::Prefer | SslMode::Require => {
+                config.trust_cert();
+                None
+            }
+            SslMode::VerifyCa | SslMode::VerifyFull => match self.root_cert() {
+                Some(root_cert) => {
+                    let ca_file = CaFile::new(root_cert)?;
+                    config.trust_cert_ca(ca_file.0.to_string_lossy());
+                    Some(ca_file)
+                }
+                None => None,
+            },
+        };
+
+        Ok(ca_file)
+    }
+
+    /// SQL Server's `Off` level only encrypts the login, so `prefer` (the
+    /// default) requires encryption like `require`, and only `disable` sends
+    /// queries in plaintext.
+    fn mssql_encryption(&self) -> EncryptionLevel {
+        match self.ssl_mode {
+            SslMode::Disable => EncryptionLevel::NotSupported,
+            _ => EncryptionLevel::Required,
+        }
+    }
+
+    /// Describe a sqlx connection error, calling out TLS failures
+    pub(crate) fn sqlx_connect_error(&self, error: sqlx::Error, database: &str) -> SharedError {
+        match error {
+            sqlx::Error::Tls(e) => tls_error(format!("{e} (SSL mode: {})", self.ssl_mode)),
+            e => SharedError::Sql(SqlError::Connect(format!("{database:?}: {e}"))),
+        }
+    }
+
+    /// Describe a tiberius connection error, calling out TLS failures
+    pub(crate) fn tiberius_connect_error(&self, error: tiberius::error::Error) -> SharedError {
+        match error {
+            tiberius::error::Error::Tls(e) => {
+                tls_error(format!("{e} (SSL mode: {})", self.ssl_mode))
+            }
+            e => SharedError::Sql(SqlError::Connect(format!("Failed to create client: {e}"))),
+        }
+    }
+}
+
+/// A CA bundle written to a temporary file, which is removed on drop
+pub(crate) struct CaFile(PathBuf);
+
+impl CaFile {
+    fn new(pem: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("quadratic-ca-{}.pem", Uuid::new_v4()));
+
+        std::fs::write(&path, pem)
+            .map_err(|e| tls_error(format!("Could not write the CA bundle: {e}")))?;
+
+        Ok(CaFile(path))
+    }
+}
+
+impl Drop for CaFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Empty form fields are sent as empty strings
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().filter(|value| !value.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tls_config_deserialize() {
+        let tls: TlsConfig = serde_json::from_str(
+            r#"{"sslMode": "verify-full", "sslRootCert": "ca", "sslServerName": "db.example.com"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(tls.ssl_mode, SslMode::VerifyFull);
+        assert_eq!(tls.root_cert(), Some("ca"));
+        assert_eq!(tls.server_name("127.0.0.1"), Some("db.example.com"));
+        assert_eq!(tls.server_name("db.example.com"), None);
+
+        let tls: TlsConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(tls, TlsConfig::default());
+        assert_eq!(tls.ssl_mode.to_string(), "prefer");
+    }
+
+    #[test]
+    fn test_tls_config_client_cert() {
+        let mut tls = TlsConfig {
+            ssl_cert: Some("cert".into()),
+            ssl_key: Some("".into()),
+            ..Default::default()
+        };
+        assert!(tls.client_cert().is_err());
+
+        tls.ssl_key = Some("key".into());
+        assert_eq!(tls.client_cert().unwrap(), Some(("cert", "key")));
+
+        // SQL Server doesn't support client certificates
+        let mut config = Config::new();
+        assert!(tls.mssql_config(&mut config, "localhost").is_err());
+    }
+
+    #[test]
+    fn test_tls_config_sqlx_server_name() {
+        let tls = TlsConfig {
+            ssl_mode: SslMode::VerifyFull,
+            ssl_server_name: Some("db.example.com".into()),
+            ..Default::default()
+        };
+
+        assert!(
+            tls.postgres_options(PgConnectOptions::new(), "127.0.0.1")
+                .is_err()
+        );
+        assert!(
+            tls.postgres_options(PgConnectOptions::new(), "db.example.com")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_tls_config_mssql_encryption() {
+        let encryption = |ssl_mode| {
+            TlsConfig {
+                ssl_mode,
+                ..Default::default()
+            }
+            .mssql_encryption()
+        };
+
+        assert!(matches!(
+            encryption(SslMode::Disable),
+            EncryptionLevel::NotSupported
+        ));
+
+        for ssl_mode in [
+            SslMode::Prefer,
+            SslMode::Require,
+            SslMode::VerifyCa,
+            SslMode::VerifyFull,
+        ] {
+            assert!(matches!(encryption(ssl_mode), EncryptionLevel::Required));
+        }
+
+        // the default requires encryption
+        assert!(matches!(
+            TlsConfig::default().mssql_encryption(),
+            EncryptionLevel::Required
+        ));
+    }
+
+    #[test]
+    fn test_tls_config_mssql_ca_file() {
+        let tls = TlsConfig {
+            ssl_mode: SslMode::VerifyCa,
+            ssl_root_cert: Some("-----BEGIN CERTIFICATE-----".into()),
+            ..Default::default()
+        };
+        let mut config = Config::new();
+        let ca_file = tls.mssql_config(&mut config, "localhost").unwrap().unwrap();
+        let path = ca_file.0.clone();
+
+        assert!(path.exists());
+        drop(ca_file);
+        assert!(!path.exists());
+    }
+}
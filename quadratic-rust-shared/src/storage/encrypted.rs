@@ -0,0 +1,551 @@
+//! Encrypted Storage
+//!
+//! Wraps a storage backend to encrypt objects at rest using envelope
+//! encryption.  Each object is encrypted with its own random data key using
+//! AES-256-GCM, and the data key is encrypted (wrapped) with a master key.
+//! The wrapped data key and the id of the master key are stored in a header
+//! in front of the encrypted object:
+//!
+//! ```text
+//! QENC | version (1) | key id length (1) | key id | wrapped key length (2) | wrapped key | data
+//! ```
+//!
+//! Master keys are rotated by adding a new key to the front of the list of
+//! master keys.  New objects are wrapped with the first key, and existing
+//! objects can still be read with older keys until they're rewrapped with
+//! `rotate`, which doesn't need to re-encrypt the data.
+//!
+//! The data is authenticated with the object's key, so encrypted objects
+//! can't be swapped for each other.  Objects without a header (eg, written
+//! before encryption was enabled) are rejected unless unencrypted reads are
+//! allowed, which is only meant for migrating existing objects with `rotate`.
+//!
+//! Presigned URLs of the underlying storage would bypass decryption, so
+//! they're only generated for the file system, where they point to the file
+//! service.  Encrypted objects in S3 are read and written through the file
+//! service.
+
+use std::fmt::{self, Debug};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use super::{Storage, StorageConfig, StorageContainer};
+use crate::SharedError;
+use crate::crypto::aes_cbc::str_to_key;
+use crate::crypto::aes_gcm::{KEY_LENGTH, decrypt, encrypt, generate_key};
+use crate::error::Result;
+use crate::storage::error::Storage as StorageError;
+use crate::storage::file_system::FileSystem;
+
+const MAGIC: &[u8; 4] = b"QENC";
+const VERSION: u8 = 1;
+
+/// The prefix of the associated data of encrypted objects, which is followed
+/// by the object's key.  It doesn't cover the wrapped key so that data keys
+/// can be rewrapped without re-encrypting the data.
+const DATA_AAD: &[u8; 5] = b"QENC\x01";
+
+/// A master key, which wraps data keys.  The id is derived from the key so
+/// that it doesn't need to be configured separately.
+#[derive(Clone, PartialEq)]
+pub struct MasterKey {
+    id: String,
+    key: [u8; KEY_LENGTH],
+}
+
+impl MasterKey {
+    pub fn new(key: [u8; KEY_LENGTH]) -> Self {
+        let id = hex::encode(&Sha256::digest(key)[..8]);
+
+        Self { id, key }
+    }
+
+    /// Create a master key from a hex encoded 32 byte key.
+    pub fn from_hex(key: &str) -> Result<Self> {
+        Ok(Self::new(str_to_key(key)?))
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+// don't leak keys into logs
+impl Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MasterKey").field("id", &self.id).finish()
+    }
+}
+
+/// The header of an encrypted object
+#[derive(Debug, PartialEq)]
+struct Header<'a> {
+    key_id: &'a str,
+    wrapped_key: &'a [u8],
+}
+
+/// Encrypted Storage
+#[derive(Debug)]
+pub struct EncryptedStorage {
+    storage: StorageContainer,
+    master_keys: Vec<MasterKey>,
+    allow_unencrypted_reads: bool,
+}
+
+#[async_trait]
+impl Storage for EncryptedStorage {
+    type Config = StorageConfig;
+
+    /// Read an object and decrypt it.
+    async fn read(&self, key: &str) -> Result<Bytes> {
+        let data = self.storage.read(key).await?;
+
+        self.decrypt(key, data)
+            .map_err(|e| encryption_error(key, e))
+    }
+
+    /// Encrypt an object and write it.
+    async fn write<'a>(&self, key: &'a str, data: &'a Bytes) -> Result<()> {
+        let encrypted = self
+            .encrypt(key, data)
+            .map_err(|e| encryption_error(key, e))?;
+
+        self.storage.write(key, &encrypted).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.storage.delete(key).await
+    }
+
+    /// Presigned URLs of the file system point to the file service, which
+    /// decrypts the object.
+    async fn presigned_url(&self, data: &str) -> Result<String> {
+        self.presigned_file_system(data)?.presigned_url(data).await
+    }
+
+    /// Presigned upload URLs of the file system point to the file service,
+    /// which encrypts the object.
+    async fn presigned_upload_url(&self, key: &str, content_type: &str) -> Result<String> {
+        self.presigned_file_system(key)?
+            .presigned_upload_url(key, content_type)
+            .await
+    }
+
+    fn path(&self) -> &str {
+        self.storage.path()
+    }
+
+    /// Return the configuration of the underlying storage
+    fn config(&self) -> Self::Config {
+        self.storage.config()
+    }
+}
+
+/// Convenience function to add the key to encryption errors.
+fn encryption_error(key: &str, e: impl ToString) -> SharedError {
+    StorageError::Encryption(format!("Error with key {key}: {}", e.to_string())).into()
+}
+
+/// The associated data of an object, which binds the data to its key.
+fn data_aad(key: &str) -> Vec<u8> {
+    [DATA_AAD.as_slice(), key.as_bytes()].concat()
+}
+
+fn invalid_header() -> SharedError {
+    StorageError::Encryption("Invalid encryption header".into()).into()
+}
+
+/// Parse the header of an encrypted object, returning it and the encrypted
+/// data, or None if the object isn't encrypted.
+fn parse(data: &[u8]) -> Result<Option<(Header<'_>, &[u8])>> {
+    let Some(rest) = data.strip_prefix(MAGIC) else {
+        return Ok(None);
+    };
+
+    let (&version, rest) = rest.split_first().ok_or_else(invalid_header)?;
+
+    if version != VERSION {
+        return Err(
+            StorageError::Encryption(format!("Unsupported encryption version {version}")).into(),
+        );
+    }
+
+    let (&key_id_length, rest) = rest.split_first().ok_or_else(invalid_header)?;
+    let (key_id, rest) = rest
+        .split_at_checked(key_id_length as usize)
+        .ok_or_else(invalid_header)?;
+    let key_id = std::str::from_utf8(key_id).map_err(|_| invalid_header())?;
+
+    let (wrapped_key_length, rest) = rest.split_at_checked(2).ok_or_else(invalid_header)?;
+    let wrapped_key_length = u16::from_be_bytes([wrapped_key_length[0], wrapped_key_length[1]]);
+    let (wrapped_key, rest) = rest
+        .split_at_checked(wrapped_key_length as usize)
+        .ok_or_else(invalid_header)?;
+
+    Ok(Some((
+        Header {
+            key_id,
+            wrapped_key,
+        },
+        rest,
+    )))
+}
+
+/// Write the header of an encrypted object, followed by the encrypted data.
+fn serialize(key_id: &str, wrapped_key: &[u8], encrypted: &[u8]) -> Result<Bytes> {
+    let key_id_length = u8::try_from(key_id.len()).map_err(|_| invalid_header())?;
+    let wrapped_key_length = u16::try_from(wrapped_key.len()).map_err(|_| invalid_header())?;
+
+    let mut output =
+        Vec::with_capacity(MAGIC.len() + 4 + key_id.len() + wrapped_key.len() + encrypted.len());
+    output.extend_from_slice(MAGIC);
+    output.push(VERSION);
+    output.push(key_id_length);
+    output.extend_from_slice(key_id.as_bytes());
+    output.extend_from_slice(&wrapped_key_length.to_be_bytes());
+    output.extend_from_slice(wrapped_key);
+    output.extend_from_slice(encrypted);
+
+    Ok(output.into())
+}
+
+impl EncryptedStorage {
+    /// Create a new Encrypted Storage.  The first master key wraps new data
+    /// keys, and the rest are only used to read existing objects.
+    pub fn new(storage: StorageContainer, master_keys: Vec<MasterKey>) -> Result<Self> {
+        if master_keys.is_empty() {
+            return Err(StorageError::Encryption("No master keys found".into()).into());
+        }
+
+        Ok(Self {
+            storage,
+            master_keys,
+            allow_unencrypted_reads: false,
+        })
+    }
+
+    /// Read objects without an encryption header as they are.  This lets
+    /// objects written before encryption was enabled be read until they're
+    /// encrypted with `rotate`, but it also lets anyone who can write to the
+    /// underlying storage replace encrypted objects, so it's only for
+    /// migrations.
+    pub fn with_unencrypted_reads(mut self, allow_unencrypted_reads: bool) -> Self {
+        self.allow_unencrypted_reads = allow_unencrypted_reads;
+        self
+    }
+
+    /// Return the underlying storage.
+    pub fn storage(&self) -> &StorageContainer {
+        &self.storage
+    }
+
+    /// Return the master key that wraps new data keys.
+    pub fn current_key(&self) -> &MasterKey {
+        &self.master_keys[0]
+    }
+
+    /// Return the file system to generate presigned URLs with.  Presigned
+    /// URLs of other storage would return encrypted objects.
+    fn presigned_file_system(&self, key: &str) -> Result<&FileSystem> {
+        self.storage.file_system().ok_or_else(|| {
+            StorageError::GeneratePresignedUrl(
+                key.into(),
+                "Encrypted objects must be read and written through the file service".into(),
+            )
+            .into()
+        })
+    }
+
+    fn master_key(&self, id: &str) -> Result<&MasterKey> {
+        self.master_keys
+            .iter()
+            .find(|master_key| master_key.id == id)
+            .ok_or_else(|| StorageError::Encryption(format!("Master key {id} not found")).into())
+    }
+
+    /// Wrap a data key with the current master key.
+    fn wrap(&self, data_key: &[u8; KEY_LENGTH]) -> Result<(&str, Bytes)> {
+        let master_key = self.current_key();
+        let wrapped_key = encrypt(&master_key.key, data_key, master_key.id.as_bytes())?;
+
+        Ok((&master_key.id, wrapped_key))
+    }
+
+    /// Unwrap a data key with the master key it was wrapped with.
+    fn unwrap(&self, header: &Header<'_>) -> Result<[u8; KEY_LENGTH]> {
+        let master_key = self.master_key(header.key_id)?;
+        let data_key = decrypt(
+            &master_key.key,
+            header.wrapped_key,
+            header.key_id.as_bytes(),
+        )?;
+
+        data_key.as_ref().try_into().map_err(|_| invalid_header())
+    }
+
+    /// Encrypt the data of the object at `key` with a new data key.
+    pub fn encrypt(&self, key: &str, data: &[u8]) -> Result<Bytes> {
+        let data_key = generate_key();
+        let encrypted = encrypt(&data_key, data, &data_aad(key))?;
+        let (key_id, wrapped_key) = self.wrap(&data_key)?;
+
+        serialize(key_id, &wrapped_key, &encrypted)
+    }
+
+    /// Decrypt the data of the object at `key`.  Data that isn't encrypted
+    /// is an error unless unencrypted reads are allowed.
+    pub fn decrypt(&self, key: &str, data: Bytes) -> Result<Bytes> {
+        match parse(&data)? {
+            Some((header, encrypted)) => decrypt(&self.unwrap(&header)?, encrypted, &data_aad(key)),
+            None if self.allow_unencrypted_reads => Ok(data),
+            None => Err(StorageError::Encryption("Object isn't encrypted".into()).into()),
+        }
+    }
+
+    /// Rewrap the data key of an object with the current master key, or
+    /// encrypt the object if it isn't encrypted and unencrypted reads are
+    /// allowed.  Returns false if the object is already wrapped with the
+    /// current master key.
+    pub async fn rotate(&self, key: &str) -> Result<bool> {
+        let data = self.storage.read(key).await?;
+        let rotated = match parse(&data).map_err(|e| encryption_error(key, e))? {
+            Some((header, _)) if header.key_id == self.current_key().id => return Ok(false),
+            Some((header, encrypted)) => {
+                let data_key = self.unwrap(&header).map_err(|e| encryption_error(key, e))?;
+                let (key_id, wrapped_key) = self.wrap(&data_key)?;
+
+                serialize(key_id, &wrapped_key, encrypted)?
+            }
+            None if self.allow_unencrypted_reads => self
+                .encrypt(key, &data)
+                .map_err(|e| encryption_error(key, e))?,
+            None => return Err(encryption_error(key, "Object isn't encrypted")),
+        };
+
+        self.storage.write(key, &rotated).await?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use tokio::fs::{remove_dir, remove_file};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::storage::file_system::{FileSystem, FileSystemConfig};
+
+    fn file_system() -> FileSystem {
+        FileSystem::new(FileSystemConfig {
+            path: env::temp_dir().to_str().unwrap().to_string(),
+            encryption_keys: vec![
+                "4242424242424242424242424242424242424242424242424242424242424242".to_string(),
+            ],
+            presigned_url_base: "http://0.0.0.0:3002/storage/presigned".to_string(),
+        })
+    }
+
+    fn storage(master_keys: Vec<MasterKey>) -> EncryptedStorage {
+        let storage = StorageContainer::FileSystem(file_system());
+
+        EncryptedStorage::new(storage, master_keys).unwrap()
+    }
+
+    fn key() -> String {
+        format!("{}-0.grid", Uuid::new_v4())
+    }
+
+    async fn cleanup(key: &str) {
+        let (full_path, dir) = file_system().full_path(key, false).await.unwrap();
+        remove_file(full_path).await.unwrap();
+        remove_dir(dir).await.unwrap();
+    }
+
+    #[test]
+    fn master_key_from_hex() {
+        let master_key =
+            MasterKey::from_hex("4242424242424242424242424242424242424242424242424242424242424242")
+                .unwrap();
+
+        assert_eq!(master_key, MasterKey::new([0x42; KEY_LENGTH]));
+        assert_eq!(master_key.id().len(), 16);
+        assert!(!format!("{master_key:?}").contains("4242"));
+        assert!(MasterKey::from_hex("42").is_err());
+    }
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let storage = storage(vec![MasterKey::new(generate_key())]);
+        let data = Bytes::from("Hello, world!");
+
+        let encrypted = storage.encrypt("a", &data).unwrap();
+        assert!(encrypted.starts_with(MAGIC));
+        assert!(!encrypted.windows(data.len()).any(|window| window == data));
+        assert_eq!(storage.decrypt("a", encrypted.clone()).unwrap(), data);
+
+        // each object has its own data key
+        assert_ne!(storage.encrypt("a", &data).unwrap(), encrypted);
+
+        // objects can't be swapped for each other
+        assert!(storage.decrypt("b", encrypted.clone()).is_err());
+
+        // unencrypted data is only read as it is when allowed
+        assert!(storage.decrypt("a", data.clone()).is_err());
+        let migrating = self::storage(storage.master_keys.clone()).with_unencrypted_reads(true);
+        assert_eq!(migrating.decrypt("a", data.clone()).unwrap(), data);
+
+        // tampered data fails to decrypt
+        let mut tampered = encrypted.to_vec();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(storage.decrypt("a", tampered.into()).is_err());
+
+        // truncated headers fail to decrypt
+        assert!(storage.decrypt("a", encrypted.slice(..10)).is_err());
+
+        // unknown master keys fail to decrypt
+        let other = self::storage(vec![MasterKey::new(generate_key())]);
+        assert!(other.decrypt("a", encrypted).is_err());
+
+        assert!(
+            EncryptedStorage::new(StorageContainer::FileSystem(file_system()), vec![]).is_err()
+        );
+    }
+
+    #[test]
+    fn parse_and_serialize_header() {
+        let encrypted = serialize("abc", b"wrapped", b"data").unwrap();
+        let (header, data) = parse(&encrypted).unwrap().unwrap();
+
+        assert_eq!(
+            header,
+            Header {
+                key_id: "abc",
+                wrapped_key: b"wrapped",
+            }
+        );
+        assert_eq!(data, b"data");
+        assert_eq!(parse(b"not encrypted").unwrap(), None);
+        assert!(parse(b"QENC\x02").is_err());
+    }
+
+    #[tokio::test]
+    async fn encrypted_storage_write_and_read() {
+        let storage = storage(vec![MasterKey::new(generate_key())]);
+        let key = &key();
+        let data = &Bytes::from("Hello, world!");
+
+        storage.write(key, data).await.unwrap();
+
+        let stored = storage.storage().read(key).await.unwrap();
+        let read_data = storage.read(key).await.unwrap();
+
+        cleanup(key).await;
+
+        assert_ne!(data, &stored);
+        assert_eq!(data, &read_data);
+    }
+
+    #[tokio::test]
+    async fn encrypted_storage_rotate() {
+        let old_key = MasterKey::new(generate_key());
+        let new_key = MasterKey::new(generate_key());
+        let key = &key();
+        let data = &Bytes::from("Hello, world!");
+
+        let storage = storage(vec![old_key.clone()]);
+        storage.write(key, data).await.unwrap();
+
+        // rotate to the new key, keeping the old key to read existing objects
+        let storage = self::storage(vec![new_key.clone(), old_key]);
+        assert_eq!(storage.read(key).await.unwrap(), data);
+        assert!(storage.rotate(key).await.unwrap());
+        assert!(!storage.rotate(key).await.unwrap());
+
+        // the old key is no longer needed
+        let storage = self::storage(vec![new_key]);
+        let read_data = storage.read(key).await.unwrap();
+
+        cleanup(key).await;
+
+        assert_eq!(data, &read_data);
+    }
+
+    #[tokio::test]
+    async fn encrypted_storage_rotate_unencrypted() {
+        let file_system = file_system();
+        let key = &key();
+        let data = &Bytes::from("Hello, world!");
+
+        file_system.write(key, data).await.unwrap();
+
+        // unencrypted objects are rejected unless migrating
+        let storage = storage(vec![MasterKey::new(generate_key())]);
+        let read_error = storage.read(key).await;
+        let rotate_error = storage.rotate(key).await;
+
+        let migrating = self::storage(storage.master_keys.clone()).with_unencrypted_reads(true);
+        assert_eq!(migrating.read(key).await.unwrap(), data);
+        assert!(migrating.rotate(key).await.unwrap());
+
+        let stored = file_system.read(key).await.unwrap();
+        let read_data = storage.read(key).await.unwrap();
+
+        cleanup(key).await;
+
+        assert!(read_error.is_err());
+        assert!(rotate_error.is_err());
+        assert!(stored.starts_with(MAGIC));
+        assert_eq!(data, &read_data);
+    }
+
+    #[tokio::test]
+    async fn encrypted_storage_rejects_swapped_objects() {
+        let file_system = file_system();
+        let storage = storage(vec![MasterKey::new(generate_key())]);
+        let (key, other_key) = (&key(), &key());
+
+        storage.write(key, &Bytes::from("mine")).await.unwrap();
+        storage
+            .write(other_key, &Bytes::from("theirs"))
+            .await
+            .unwrap();
+
+        // replace the object with another encrypted object
+        let other = file_system.read(other_key).await.unwrap();
+        file_system.write(key, &other).await.unwrap();
+        let swapped = storage.read(key).await;
+
+        cleanup(key).await;
+        cleanup(other_key).await;
+
+        assert!(swapped.is_err());
+    }
+
+    #[tokio::test]
+    async fn encrypted_storage_presigned_urls() {
+        let storage = storage(vec![MasterKey::new(generate_key())]);
+        let key = &key();
+
+        // file system URLs point to the file service, which decrypts objects
+        assert!(
+            storage
+                .presigned_url(key)
+                .await
+                .unwrap()
+                .starts_with("http://0.0.0.0:3002/storage/presigned/")
+        );
+        assert!(
+            storage
+                .presigned_upload_url(key, "application/octet-stream")
+                .await
+                .unwrap()
+                .starts_with("http://0.0.0.0:3002/storage/upload/")
+        );
+    }
+}
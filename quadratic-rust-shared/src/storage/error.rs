@@ -14,6 +14,12 @@ pub enum Storage {
     #[error("Error creating directory {0}: {1}")]
     CreateDirectory(String, String),
 
+    #[error("Error deleting key {0}: {1}")]
+    Delete(String, String),
+
+    #[error("Encryption: {0}")]
+    Encryption(String),
+
     #[error("FileSystem key: {0}")]
     FileSystemKey(String),
 
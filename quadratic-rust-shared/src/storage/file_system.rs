@@ -5,7 +5,7 @@
 use async_trait::async_trait;
 use bytes::Bytes;
 use std::path::{Path, PathBuf};
-use tokio::fs::{File, create_dir_all};
+use tokio::fs::{File, create_dir_all, remove_file};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use super::Storage;
@@ -60,6 +60,16 @@ impl Storage for FileSystem {
         Ok(())
     }
 
+    /// Delete the file from the file system.  Deleting a missing file is ok.
+    async fn delete(&self, key: &str) -> Result<()> {
+        let file_path = self.full_path(key, false).await?.0;
+
+        match remove_file(file_path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Self::delete_error(key, &e)),
+            _ => Ok(()),
+        }
+    }
+
     /// Generate a presigned GET URL (for downloads)
     async fn presigned_url(&self, data: &str) -> Result<String> {
         let str_key = self.first_key()?;
@@ -148,7 +158,7 @@ impl FileSystem {
 
 #[cfg(test)]
 mod tests {
-    use tokio::fs::{remove_dir, remove_file};
+    use tokio::fs::remove_dir;
     use uuid::Uuid;
 
     use crate::crypto::aes_cbc::decrypt_from_api;
@@ -186,6 +196,25 @@ mod tests {
         assert_eq!(data, &read_data);
     }
 
+    #[tokio::test]
+    async fn file_system_delete() {
+        let storage = FileSystem { config: config() };
+        let key = &format!("{}-0.grid", Uuid::new_v4());
+
+        storage
+            .write(key, &Bytes::from("Hello, world!"))
+            .await
+            .unwrap();
+        storage.delete(key).await.unwrap();
+        assert!(storage.read(key).await.is_err());
+
+        // deleting a missing file is ok
+        storage.delete(key).await.unwrap();
+
+        let (_, dir) = storage.full_path(key, false).await.unwrap();
+        remove_dir(dir).await.unwrap();
+    }
+
     #[tokio::test]
     async fn file_system_presigned_url() {
         let config = config();
@@ -9,6 +9,7 @@ use strum_macros::Display;
 
 use crate::{SharedError, error::Result, storage::error::Storage as StorageError};
 
+pub mod encrypted;
 pub mod error;
 pub mod file_system;
 #[cfg(feature = "arrow")]
@@ -25,6 +26,7 @@ pub enum StorageConfig {
 pub enum StorageContainer {
     S3(s3::S3),
     FileSystem(file_system::FileSystem),
+    Encrypted(Box<encrypted::EncryptedStorage>),
 }
 
 impl From<&StorageContainer> for StorageConfig {
@@ -32,6 +34,18 @@ impl From<&StorageContainer> for StorageConfig {
         match container {
             StorageContainer::S3(s3) => StorageConfig::S3(s3.config()),
             StorageContainer::FileSystem(fs) => StorageConfig::FileSystem(fs.config()),
+            StorageContainer::Encrypted(encrypted) => encrypted.config(),
+        }
+    }
+}
+
+impl StorageContainer {
+    /// Return the FileSystem storage, looking through encryption.
+    pub fn file_system(&self) -> Option<&file_system::FileSystem> {
+        match self {
+            Self::S3(_) => None,
+            Self::FileSystem(fs) => Some(fs),
+            Self::Encrypted(encrypted) => encrypted.storage().file_system(),
         }
     }
 }
@@ -49,6 +63,7 @@ pub trait Storage {
 
     async fn read(&self, key: &str) -> Result<Bytes>;
     async fn write<'a>(&self, key: &'a str, data: &'a Bytes) -> Result<()>;
+    async fn delete(&self, key: &str) -> Result<()>;
     async fn presigned_url(&self, key: &str) -> Result<String>;
     async fn presigned_upload_url(&self, key: &str, content_type: &str) -> Result<String>;
     fn path(&self) -> &str;
@@ -62,6 +77,10 @@ pub trait Storage {
         StorageError::Write(key.into(), e.to_string()).into()
     }
 
+    fn delete_error(key: &str, e: impl ToString) -> SharedError {
+        StorageError::Delete(key.into(), e.to_string()).into()
+    }
+
     fn presigned_url_error(key: &str, e: impl ToString) -> SharedError {
         StorageError::GeneratePresignedUrl(key.into(), e.to_string()).into()
     }
@@ -76,6 +95,7 @@ impl Storage for StorageContainer {
         match self {
             Self::S3(s3) => s3.read(key).await,
             Self::FileSystem(fs) => fs.read(key).await,
+            Self::Encrypted(encrypted) => encrypted.read(key).await,
         }
     }
 
@@ -83,6 +103,15 @@ impl Storage for StorageContainer {
         match self {
             Self::S3(s3) => s3.write(key, data).await,
             Self::FileSystem(fs) => fs.write(key, data).await,
+            Self::Encrypted(encrypted) => encrypted.write(key, data).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self {
+            Self::S3(s3) => s3.delete(key).await,
+            Self::FileSystem(fs) => fs.delete(key).await,
+            Self::Encrypted(encrypted) => encrypted.delete(key).await,
         }
     }
 
@@ -90,6 +119,7 @@ impl Storage for StorageContainer {
         match self {
             Self::S3(s3) => s3.presigned_url(data).await,
             Self::FileSystem(fs) => fs.presigned_url(data).await,
+            Self::Encrypted(encrypted) => encrypted.presigned_url(data).await,
         }
     }
 
@@ -97,6 +127,7 @@ impl Storage for StorageContainer {
         match self {
             Self::S3(s3) => s3.presigned_upload_url(key, content_type).await,
             Self::FileSystem(fs) => fs.presigned_upload_url(key, content_type).await,
+            Self::Encrypted(encrypted) => encrypted.presigned_upload_url(key, content_type).await,
         }
     }
 
@@ -104,6 +135,7 @@ impl Storage for StorageContainer {
         match self {
             Self::S3(s3) => s3.path(),
             Self::FileSystem(fs) => fs.path(),
+            Self::Encrypted(encrypted) => encrypted.path(),
         }
     }
 
@@ -111,6 +143,7 @@ impl Storage for StorageContainer {
         match self {
             Self::S3(s3) => StorageConfig::S3(s3.config()),
             Self::FileSystem(fs) => StorageConfig::FileSystem(fs.config()),
+            Self::Encrypted(encrypted) => encrypted.config(),
         }
     }
 }
@@ -12,7 +12,7 @@ use super::Storage;
 use crate::{
     aws::{
         client,
-        s3::{download_object, upload_object},
+        s3::{delete_object, download_object, upload_object},
     },
     error::Result,
     storage::error::Storage as StorageError,
@@ -97,6 +97,17 @@ impl Storage for S3 {
         Ok(())
     }
 
+    /// Delete the object from the S3 bucket.
+    async fn delete(&self, key: &str) -> Result<()> {
+        let S3Config { client, bucket, .. } = &self.config;
+
+        delete_object(client, bucket, key)
+            .await
+            .map_err(|e| Self::delete_error(key, &e))?;
+
+        Ok(())
+    }
+
     /// Generate a presigned GET URL (for downloads)
     async fn presigned_url(&self, data: &str) -> Result<String> {
         let S3Config { client, bucket, .. } = &self.config;
@@ -0,0 +1,421 @@
+//! Backfill
+//!
+//! A backfill re-syncs a date range that was already synced, e.g. when a
+//! source corrects past data.  The range's records are staged outside of the
+//! stream's prefix as they're fetched, and replace the range's partitions
+//! once all of them are fetched, so a failed request leaves the synced data
+//! as it was.
+//!
+//! Each partition is replaced by a single write, so queries read either the
+//! old or the new records of a date, never both.  The range as a whole isn't
+//! replaced atomically: while it's being replaced (or if replacing it fails),
+//! some dates have their new records and others their old ones.  Backfilling
+//! the range again completes it.
+//!
+//! A compacted month's records are in a single file, so a range that covers
+//! part of a compacted month is extended to the whole month, and the month's
+//! file is replaced.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::NaiveDate;
+use object_store::{ObjectStore, path::Path};
+use uuid::Uuid;
+
+use crate::arrow::object_store::{list_objects, object_store_error, upload_multipart};
+use crate::error::Result;
+use crate::synced::compaction::{
+    concat_partitions, delete, first_of_month, month_file_name, months,
+};
+use crate::synced::{
+    DATE_FORMAT, month_dates, object_store_path, synced_error, today, write_synced_markers,
+};
+
+/// Where a backfill's records are staged (with `upload`).  This is outside of
+/// the stream's prefix, so syncs and queries don't see them.
+pub fn staging_prefix(connection_id: Uuid, stream: &str, run_id: Uuid) -> String {
+    format!(
+        "backfill/{}/{}",
+        run_id,
+        object_store_path(connection_id, stream)
+    )
+}
+
+/// Get the date range to backfill for a requested range: the range is
+/// extended to the compacted months it covers part of, and doesn't start
+/// before the sync's start date or end after today.
+pub async fn backfill_date_range(
+    object_store: &Arc<dyn ObjectStore>,
+    prefix: &str,
+    sync_start_date: NaiveDate,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<(NaiveDate, NaiveDate)> {
+    let objects = list_objects(object_store, Some(prefix)).await?;
+    let months = months(&objects);
+    let is_compacted = |date: NaiveDate| {
+        months
+            .get(&first_of_month(date))
+            .is_some_and(|month| month.compacted.is_some())
+    };
+
+    let mut start_date = start_date;
+    let mut end_date = end_date;
+
+    if is_compacted(start_date) {
+        start_date = first_of_month(start_date);
+    }
+
+    if is_compacted(end_date) {
+        end_date = *month_dates(first_of_month(end_date))
+            .last()
+            .unwrap_or(&end_date);
+    }
+
+    let start_date = start_date.max(sync_start_date);
+    let end_date = end_date.min(today());
+
+    if start_date > end_date {
+        return Err(synced_error(format!(
+            "Invalid date range: {} to {}",
+            start_date, end_date
+        )));
+    }
+
+    Ok((start_date, end_date))
+}
+
+/// Replace the partitions of a date range with the records staged under
+/// `staging_prefix`.  Dates without staged records get a marker, and
+/// compacted months get a new month's file.  Returns the number of parquet
+/// files written.
+pub async fn replace(
+    object_store: &Arc<dyn ObjectStore>,
+    prefix: &str,
+    staging_prefix: &str,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<usize> {
+    let objects = list_objects(object_store, Some(prefix)).await?;
+    let months = months(&objects);
+    let staged = list_objects(object_store, Some(staging_prefix))
+        .await?
+        .into_iter()
+        .map(|object| object.location)
+        .collect::<HashSet<_>>();
+    let staged_path = |date: &NaiveDate| {
+        Path::from(format!(
+            "{}/{}.parquet",
+            staging_prefix,
+            date.format(DATE_FORMAT)
+        ))
+    };
+    let mut num_files = 0;
+
+    let mut month_start = first_of_month(start_date);
+
+    while month_start <= end_date {
+        let dates = month_dates(month_start)
+            .into_iter()
+            .filter(|date| *date >= start_date && *date <= end_date)
+            .collect::<Vec<_>>();
+        let compacted = months
+            .get(&month_start)
+            .filter(|month| month.compacted.is_some());
+
+        match compacted {
+            Some(month) => {
+                let mut partitions = vec![];
+
+                for path in dates.iter().map(staged_path) {
+                    if staged.contains(&path) {
+                        partitions.push(get_file(object_store, &path).await?);
+                    }
+                }
+
+                let month_file_name = month_file_name(prefix, month_start);
+
+                if partitions.is_empty() {
+                    // the month no longer has records: its dates are covered by
+                    // markers before its parquet files are removed
+                    write_markers(object_store, prefix, &dates).await?;
+                    delete(object_store, month.dates.iter().map(|(_, object)| object)).await?;
+                    delete_file(object_store, &month_file_name).await?;
+                } else {
+                    let compacted = concat_partitions(partitions)?;
+                    upload_multipart(object_store, &month_file_name, &compacted).await?;
+                    num_files += 1;
+                }
+            }
+            None => {
+                for date in dates {
+                    let path = staged_path(&date);
+                    let date = date.format(DATE_FORMAT).to_string();
+                    let file_name = format!("{}/{}.parquet", prefix, date);
+                    let marker = format!("{}/{}.synced", prefix, date);
+
+                    // write the new partition before removing the old one
+                    if staged.contains(&path) {
+                        object_store
+                            .copy(&path, &Path::from(file_name))
+                            .await
+                            .map_err(object_store_error)?;
+                        delete_file(object_store, &marker).await?;
+                        num_files += 1;
+                    } else {
+                        upload_multipart(object_store, &marker, &Bytes::new()).await?;
+                        delete_file(object_store, &file_name).await?;
+                    }
+                }
+            }
+        }
+
+        month_start = month_start
+            .checked_add_months(chrono::Months::new(1))
+            .ok_or_else(|| synced_error("Invalid date range"))?;
+    }
+
+    Ok(num_files)
+}
+
+/// Delete the records staged for a backfill
+pub async fn discard_staged(
+    object_store: &Arc<dyn ObjectStore>,
+    staging_prefix: &str,
+) -> Result<()> {
+    let staged = list_objects(object_store, Some(staging_prefix)).await?;
+
+    delete(object_store, staged.iter()).await
+}
+
+/// Write markers for dates
+async fn write_markers(
+    object_store: &Arc<dyn ObjectStore>,
+    prefix: &str,
+    dates: &[NaiveDate],
+) -> Result<()> {
+    if let (Some(first), Some(last)) = (dates.first(), dates.last()) {
+        write_synced_markers(object_store, prefix, *first, *last).await?;
+    }
+
+    Ok(())
+}
+
+/// Read a file
+async fn get_file(object_store: &Arc<dyn ObjectStore>, path: &Path) -> Result<Bytes> {
+    object_store
+        .get(path)
+        .await
+        .map_err(object_store_error)?
+        .bytes()
+        .await
+        .map_err(object_store_error)
+}
+
+/// Delete a file, ignoring it if it doesn't exist
+async fn delete_file(object_store: &Arc<dyn ObjectStore>, file_name: &str) -> Result<()> {
+    match object_store.delete(&Path::from(file_name)).await {
+        Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+        Err(e) => Err(object_store_error(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::parquet::json::grouped_json_to_parquet;
+    use crate::parquet::utils::parquet_bytes_to_record_batch;
+    use crate::synced::compaction::compact;
+    use crate::synced::tests::create_temp_object_store;
+    use crate::synced::upload;
+    use chrono::Duration;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, DATE_FORMAT).unwrap()
+    }
+
+    fn records(records: &[(&str, &str)]) -> HashMap<String, Bytes> {
+        let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+
+        for (date, line) in records {
+            grouped
+                .entry(date.to_string())
+                .or_default()
+                .push(line.to_string());
+        }
+
+        grouped_json_to_parquet(grouped).unwrap()
+    }
+
+    async fn file_names(object_store: &Arc<dyn ObjectStore>) -> Vec<String> {
+        let mut files = list_objects(object_store, Some("orders"))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.location.filename().unwrap().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    async fn num_staged(object_store: &Arc<dyn ObjectStore>) -> usize {
+        list_objects(object_store, Some("backfill/orders"))
+            .await
+            .unwrap()
+            .len()
+    }
+
+    async fn num_rows(object_store: &Arc<dyn ObjectStore>, file_name: &str) -> usize {
+        let bytes = object_store
+            .get(&Path::from(file_name))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+
+        parquet_bytes_to_record_batch(bytes).unwrap().num_rows()
+    }
+
+    #[tokio::test]
+    async fn test_backfill_date_range() {
+        let (_temp_dir, store) = create_temp_object_store();
+        let data = records(&[("2024-01-31", r#"{"id": "a"}"#)]);
+        upload(&store, "orders", data).await.unwrap();
+        compact(&store, "orders", date("2024-01-31"), Duration::zero())
+            .await
+            .unwrap();
+
+        // extended to the compacted month, but not before the sync's start
+        let range = backfill_date_range(
+            &store,
+            "orders",
+            date("2024-01-20"),
+            date("2024-01-25"),
+            date("2024-02-03"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(range, (date("2024-01-20"), date("2024-02-03")));
+
+        let range = backfill_date_range(
+            &store,
+            "orders",
+            date("2023-01-01"),
+            date("2024-01-25"),
+            date("2024-01-26"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(range, (date("2024-01-01"), date("2024-01-31")));
+
+        let range = backfill_date_range(
+            &store,
+            "orders",
+            date("2024-03-01"),
+            date("2024-02-01"),
+            date("2024-02-03"),
+        )
+        .await;
+        assert!(range.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replace() {
+        let (_temp_dir, store) = create_temp_object_store();
+        let data = records(&[
+            ("2024-02-01", r#"{"id": "a"}"#),
+            ("2024-02-02", r#"{"id": "b"}"#),
+        ]);
+        upload(&store, "orders", data).await.unwrap();
+        write_synced_markers(&store, "orders", date("2024-02-03"), date("2024-02-03"))
+            .await
+            .unwrap();
+
+        // 02-01 has corrected records, 02-02 no longer has records and 02-03 now has
+        let data = records(&[
+            ("2024-02-01", r#"{"id": "a"}"#),
+            ("2024-02-01", r#"{"id": "c"}"#),
+            ("2024-02-03", r#"{"id": "d"}"#),
+        ]);
+        upload(&store, "backfill/orders", data).await.unwrap();
+        let num_files = replace(
+            &store,
+            "orders",
+            "backfill/orders",
+            date("2024-02-01"),
+            date("2024-02-03"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(num_files, 2);
+        assert_eq!(
+            file_names(&store).await,
+            vec![
+                "2024-02-01.parquet",
+                "2024-02-02.synced",
+                "2024-02-03.parquet"
+            ]
+        );
+        assert_eq!(num_rows(&store, "orders/2024-02-01.parquet").await, 2);
+
+        discard_staged(&store, "backfill/orders").await.unwrap();
+        assert_eq!(num_staged(&store).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_replace_compacted_month() {
+        let (_temp_dir, store) = create_temp_object_store();
+        let data = records(&[("2024-01-30", r#"{"id": "a"}"#)]);
+        upload(&store, "orders", data).await.unwrap();
+        write_synced_markers(&store, "orders", date("2024-01-31"), date("2024-01-31"))
+            .await
+            .unwrap();
+        compact(&store, "orders", date("2024-01-30"), Duration::hours(1))
+            .await
+            .unwrap();
+
+        let data = records(&[
+            ("2024-01-30", r#"{"id": "a"}"#),
+            ("2024-01-31", r#"{"id": "b"}"#),
+        ]);
+        upload(&store, "backfill/orders", data).await.unwrap();
+        let num_files = replace(
+            &store,
+            "orders",
+            "backfill/orders",
+            date("2024-01-30"),
+            date("2024-01-31"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(num_files, 1);
+        assert_eq!(num_rows(&store, "orders/2024-01.parquet").await, 2);
+
+        discard_staged(&store, "backfill/orders").await.unwrap();
+        assert_eq!(num_staged(&store).await, 0);
+
+        // without records, the month is covered by markers
+        let num_files = replace(
+            &store,
+            "orders",
+            "backfill/orders",
+            date("2024-01-30"),
+            date("2024-01-31"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(num_files, 0);
+        assert_eq!(
+            file_names(&store).await,
+            vec!["2024-01-30.synced", "2024-01-31.synced"]
+        );
+    }
+}
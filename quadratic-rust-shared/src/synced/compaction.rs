@@ -0,0 +1,382 @@
+//! Compaction
+//!
+//! Streams are synced into a parquet file per date, so a stream that has
+//! been synced for a year has hundreds of small files.  Compaction merges the
+//! date partitions of a closed month into a parquet file for the month
+//! (`{prefix}/2024-01.parquet`), which covers every date in the month.
+//!
+//! A month's file supersedes its date partitions and markers, so queries skip
+//! them as soon as it's written.  They're deleted by a later compaction, once
+//! the queries that listed them before the month was compacted have finished.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use arrow::compute::concat_batches;
+use bytes::Bytes;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use object_store::{ObjectMeta, ObjectStore, path::Path};
+
+use crate::arrow::object_store::{list_objects, object_store_error, upload_multipart};
+use crate::arrow::schema::unify_record_batches;
+use crate::error::Result;
+use crate::parquet::utils::{parquet_bytes_to_record_batch, record_batch_to_parquet_bytes};
+use crate::synced::{MONTH_FORMAT, month_dates, parse_file_month, string_to_date, today};
+
+/// A synced file of a stream
+enum Partition {
+    /// The records of a date
+    Date(NaiveDate),
+    /// A date that was synced without records
+    Marker(NaiveDate),
+    /// The records of a compacted month
+    Month(NaiveDate),
+}
+
+impl Partition {
+    fn from_location(location: &Path) -> Option<Partition> {
+        let file_name = location.filename()?;
+
+        if let Some(date) = file_name.strip_suffix(".synced") {
+            return string_to_date(date).ok().map(Partition::Marker);
+        }
+
+        let name = file_name.strip_suffix(".parquet")?;
+
+        match string_to_date(name) {
+            Ok(date) => Some(Partition::Date(date)),
+            Err(_) => parse_file_month(name).ok().map(Partition::Month),
+        }
+    }
+}
+
+/// The synced files of a month
+#[derive(Default)]
+pub(crate) struct Month {
+    pub(crate) dates: Vec<(NaiveDate, ObjectMeta)>,
+    pub(crate) markers: Vec<(NaiveDate, ObjectMeta)>,
+    pub(crate) compacted: Option<ObjectMeta>,
+}
+
+impl Month {
+    /// Check if every date of the month since the start date was synced
+    fn is_complete(&self, month: NaiveDate, start_date: NaiveDate) -> bool {
+        let synced = self
+            .dates
+            .iter()
+            .chain(self.markers.iter())
+            .map(|(date, _)| *date)
+            .collect::<HashSet<_>>();
+
+        month_dates(month)
+            .iter()
+            .filter(|date| **date >= start_date)
+            .all(|date| synced.contains(date))
+    }
+
+    /// The date partitions and markers of the month
+    pub(crate) fn files(&self) -> impl Iterator<Item = &ObjectMeta> {
+        self.dates
+            .iter()
+            .chain(self.markers.iter())
+            .map(|(_, object)| object)
+    }
+}
+
+/// Get the first date of the date's month
+pub(crate) fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+/// Group the synced files of a stream by month
+pub(crate) fn months(objects: &[ObjectMeta]) -> BTreeMap<NaiveDate, Month> {
+    let mut months: BTreeMap<NaiveDate, Month> = BTreeMap::new();
+
+    for object in objects {
+        match Partition::from_location(&object.location) {
+            Some(Partition::Date(date)) => months
+                .entry(first_of_month(date))
+                .or_default()
+                .dates
+                .push((date, object.clone())),
+            Some(Partition::Marker(date)) => months
+                .entry(first_of_month(date))
+                .or_default()
+                .markers
+                .push((date, object.clone())),
+            Some(Partition::Month(month)) => {
+                months.entry(month).or_default().compacted = Some(object.clone())
+            }
+            None => {}
+        }
+    }
+
+    months
+}
+
+/// Get the file name of a compacted month
+pub fn month_file_name(prefix: &str, month: NaiveDate) -> String {
+    format!("{}/{}.parquet", prefix, month.format(MONTH_FORMAT))
+}
+
+/// Get the files that were superseded by a compacted month and shouldn't be
+/// read anymore.
+pub fn superseded_files(objects: &[ObjectMeta]) -> HashSet<Path> {
+    months(objects)
+        .values()
+        .filter(|month| month.compacted.is_some())
+        .flat_map(|month| month.files().map(|object| object.location.clone()))
+        .collect()
+}
+
+/// Compact the date partitions of a stream's closed months into a file per
+/// month.  Only months where every date since the start date was synced are
+/// compacted, and months without records keep their markers.  The files that
+/// a month's file superseded more than `grace_period` ago are deleted.
+///
+/// Returns the number of months compacted.
+pub async fn compact(
+    object_store: &Arc<dyn ObjectStore>,
+    prefix: &str,
+    start_date: NaiveDate,
+    grace_period: Duration,
+) -> Result<usize> {
+    let objects = list_objects(object_store, Some(prefix)).await?;
+    let current_month = first_of_month(today());
+    let mut num_months = 0;
+
+    for (month, files) in months(&objects) {
+        match &files.compacted {
+            Some(compacted) => {
+                if Utc::now() - compacted.last_modified >= grace_period {
+                    delete(object_store, files.files()).await?;
+                }
+            }
+            None => {
+                let is_closed = month < current_month;
+
+                if is_closed && !files.dates.is_empty() && files.is_complete(month, start_date) {
+                    compact_month(object_store, prefix, month, &files).await?;
+                    num_months += 1;
+                }
+            }
+        }
+    }
+
+    Ok(num_months)
+}
+
+/// Merge the date partitions of a month into the month's file
+async fn compact_month(
+    object_store: &Arc<dyn ObjectStore>,
+    prefix: &str,
+    month: NaiveDate,
+    files: &Month,
+) -> Result<()> {
+    let mut dates = files.dates.iter().collect::<Vec<_>>();
+    dates.sort_by_key(|(date, _)| *date);
+
+    let mut partitions = Vec::with_capacity(dates.len());
+
+    for (_, object) in dates {
+        let bytes = object_store
+            .get(&object.location)
+            .await
+            .map_err(object_store_error)?
+            .bytes()
+            .await
+            .map_err(object_store_error)?;
+
+        partitions.push(bytes);
+    }
+
+    let compacted = concat_partitions(partitions)?;
+
+    upload_multipart(object_store, &month_file_name(prefix, month), &compacted).await
+}
+
+/// Concatenate parquet files into a parquet file with their unified schema
+pub(crate) fn concat_partitions(partitions: Vec<Bytes>) -> Result<Bytes> {
+    let batches = partitions
+        .into_iter()
+        .map(parquet_bytes_to_record_batch)
+        .collect::<Result<Vec<_>>>()?;
+    let (schema, batches) = unify_record_batches(&batches)?;
+
+    record_batch_to_parquet_bytes(concat_batches(&schema, &batches)?)
+}
+
+/// Delete files, ignoring the ones that were already deleted
+pub(crate) async fn delete(
+    object_store: &Arc<dyn ObjectStore>,
+    objects: impl Iterator<Item = &ObjectMeta>,
+) -> Result<()> {
+    for object in objects {
+        match object_store.delete(&object.location).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+            Err(e) => return Err(object_store_error(e)),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parquet::json::grouped_json_to_parquet;
+    use crate::synced::tests::create_temp_object_store;
+    use crate::synced::{DATE_FORMAT, get_missing_dates, upload, write_synced_markers};
+    use arrow::datatypes::DataType;
+    use arrow::record_batch::RecordBatch;
+    use std::collections::HashMap;
+
+    async fn upload_records(object_store: &Arc<dyn ObjectStore>, date: &str, line: &str) {
+        let data =
+            grouped_json_to_parquet(HashMap::from([(date.into(), vec![line.into()])])).unwrap();
+        upload(object_store, "orders", data).await.unwrap();
+    }
+
+    async fn file_names(object_store: &Arc<dyn ObjectStore>) -> Vec<String> {
+        let mut files = list_objects(object_store, Some("orders"))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.location.filename().unwrap().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    async fn read(object_store: &Arc<dyn ObjectStore>, file_name: &str) -> RecordBatch {
+        let bytes = object_store
+            .get(&Path::from(file_name))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+
+        parquet_bytes_to_record_batch(bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_compact() {
+        let (_temp_dir, store) = create_temp_object_store();
+        let start_date = NaiveDate::from_ymd_opt(2024, 1, 30).unwrap();
+        let today = today().format(DATE_FORMAT).to_string();
+
+        // january is complete since the start date and a field's type changed
+        upload_records(&store, "2024-01-29", r#"{"id": "a", "amount": 10}"#).await;
+        upload_records(&store, "2024-01-30", r#"{"id": "b", "amount": "20"}"#).await;
+        write_synced_markers(
+            &store,
+            "orders",
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        // february is missing dates and the current month isn't closed
+        upload_records(&store, "2024-02-01", r#"{"id": "c", "amount": 30}"#).await;
+        upload_records(&store, &today, r#"{"id": "d", "amount": 40}"#).await;
+
+        let num_months = compact(&store, "orders", start_date, Duration::zero())
+            .await
+            .unwrap();
+        assert_eq!(num_months, 1);
+
+        // the superseded files are kept until the next compaction
+        let objects = list_objects(&store, Some("orders")).await.unwrap();
+        let mut superseded = superseded_files(&objects)
+            .into_iter()
+            .map(|path| path.filename().unwrap().to_string())
+            .collect::<Vec<_>>();
+        superseded.sort();
+        assert_eq!(
+            superseded,
+            vec![
+                "2024-01-29.parquet",
+                "2024-01-30.parquet",
+                "2024-01-31.synced"
+            ]
+        );
+
+        let batch = read(&store, "orders/2024-01.parquet").await;
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch
+                .schema()
+                .field_with_name("amount")
+                .unwrap()
+                .data_type(),
+            &DataType::Utf8
+        );
+
+        // the compacted month is already compacted, so its files are deleted
+        let num_months = compact(&store, "orders", start_date, Duration::zero())
+            .await
+            .unwrap();
+        assert_eq!(num_months, 0);
+        assert_eq!(
+            file_names(&store).await,
+            vec![
+                "2024-01.parquet".to_string(),
+                "2024-02-01.parquet".to_string(),
+                format!("{today}.parquet")
+            ]
+        );
+
+        // the compacted month's dates are still synced
+        let missing_dates = get_missing_dates(
+            &store,
+            Some("orders"),
+            start_date,
+            NaiveDate::from_ymd_opt(2024, 2, 2).unwrap(),
+            vec![],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            missing_dates,
+            vec![NaiveDate::from_ymd_opt(2024, 2, 2).unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compact_keeps_superseded_files_during_grace_period() {
+        let (_temp_dir, store) = create_temp_object_store();
+        let start_date = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+
+        upload_records(&store, "2024-03-31", r#"{"id": "a"}"#).await;
+
+        for _ in 0..2 {
+            compact(&store, "orders", start_date, Duration::hours(1))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            file_names(&store).await,
+            vec!["2024-03-31.parquet", "2024-03.parquet"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compact_skips_months_without_records() {
+        let (_temp_dir, store) = create_temp_object_store();
+        let date = NaiveDate::from_ymd_opt(2024, 4, 30).unwrap();
+
+        write_synced_markers(&store, "orders", date, date)
+            .await
+            .unwrap();
+
+        let num_months = compact(&store, "orders", date, Duration::zero())
+            .await
+            .unwrap();
+
+        assert_eq!(num_months, 0);
+        assert_eq!(file_names(&store).await, vec!["2024-04-30.synced"]);
+    }
+}
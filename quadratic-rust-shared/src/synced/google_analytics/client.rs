@@ -60,7 +60,7 @@ impl SyncedConnection for GoogleAnalyticsConnection {
         self.start_date
     }
 
-    fn streams(&self) -> Vec<&'static str> {
+    fn streams(&self) -> Vec<&str> {
         GoogleAnalyticsClient::streams()
     }
 
@@ -0,0 +1,405 @@
+//! Incremental Sync
+//!
+//! Streams with an updated-since cursor sync the records that changed since
+//! their last sync rather than the dates that are missing.  The stream's
+//! cursor (its high-water mark) is stored next to the synced data, and
+//! changed records replace the synced ones with the same primary key in
+//! their date partition, or in their month's file once the month is
+//! compacted.
+//!
+//! Partitions written at different times can have different schemas, so a
+//! merged partition has the unified schema of its old and new records.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow::array::{Array, BooleanArray, StringArray};
+use arrow::compute::{cast, concat_batches, filter_record_batch};
+use arrow::datatypes::DataType;
+use arrow::record_batch::RecordBatch;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use object_store::{ObjectStore, path::Path};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::arrow::object_store::{object_store_error, upload_multipart};
+use crate::arrow::schema::unify_record_batches;
+use crate::error::Result;
+use crate::parquet::utils::{parquet_bytes_to_record_batch, record_batch_to_parquet_bytes};
+use crate::synced::compaction::month_file_name;
+use crate::synced::{string_to_date, synced_error};
+
+/// The changed records of an incremental stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncrementalBatch {
+    /// The column that identifies a record
+    pub primary_key: String,
+    /// A parquet file of the changed records for each date (only dates with
+    /// changed records)
+    pub data: HashMap<String, Bytes>,
+    /// The cursor to sync from next time
+    pub cursor: Option<String>,
+}
+
+/// The sync state of an incremental stream
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SyncState {
+    /// The high-water mark of the last sync, sent to the source as is
+    pub cursor: Option<String>,
+    pub synced_at: DateTime<Utc>,
+}
+
+impl SyncState {
+    pub fn new(cursor: Option<String>) -> Self {
+        Self {
+            cursor,
+            synced_at: Utc::now(),
+        }
+    }
+}
+
+/// Get the object store path of a stream's sync state.  It's outside of the
+/// stream's folder so that only parquet files are in there.
+pub fn sync_state_path(connection_id: Uuid, stream: &str) -> String {
+    format!("{}/_state/{}.json", connection_id, stream)
+}
+
+/// Get a stream's sync state, `None` if the stream hasn't been synced
+/// incrementally yet
+pub async fn get_sync_state(
+    object_store: &Arc<dyn ObjectStore>,
+    connection_id: Uuid,
+    stream: &str,
+) -> Result<Option<SyncState>> {
+    let path = Path::from(sync_state_path(connection_id, stream));
+
+    let bytes = match object_store.get(&path).await {
+        Ok(result) => result.bytes().await.map_err(object_store_error)?,
+        Err(object_store::Error::NotFound { .. }) => return Ok(None),
+        Err(e) => return Err(object_store_error(e)),
+    };
+
+    Ok(Some(serde_json::from_slice(&bytes)?))
+}
+
+/// Save a stream's sync state
+pub async fn set_sync_state(
+    object_store: &Arc<dyn ObjectStore>,
+    connection_id: Uuid,
+    stream: &str,
+    state: &SyncState,
+) -> Result<()> {
+    let path = sync_state_path(connection_id, stream);
+    let bytes = serde_json::to_vec(state)?;
+
+    upload_multipart(object_store, &path, &bytes).await
+}
+
+/// Merge changed records into the date partitions of a stream.  Records
+/// replace the partition's records with the same primary key, and new
+/// records are appended.  The records of a compacted month are merged into
+/// the month's file.  Returns the number of partitions written.
+pub async fn merge(
+    object_store: &Arc<dyn ObjectStore>,
+    prefix: &str,
+    primary_key: &str,
+    data: HashMap<String, Bytes>,
+) -> Result<usize> {
+    let num_files = data.len();
+
+    for (date, parquet_bytes) in data.into_iter() {
+        let month_file_name = month_file_name(prefix, string_to_date(&date)?);
+        let date_file_name = format!("{}/{}.parquet", prefix, date);
+
+        let (file_name, existing) = match get_bytes(object_store, &month_file_name).await? {
+            Some(existing) => (month_file_name, Some(existing)),
+            None => {
+                let existing = get_bytes(object_store, &date_file_name).await?;
+                (date_file_name, existing)
+            }
+        };
+
+        let merged = match existing {
+            Some(existing) => merge_parquet(existing, parquet_bytes, primary_key)?,
+            None => {
+                let changed = parquet_bytes_to_record_batch(parquet_bytes)?;
+                record_batch_to_parquet_bytes(dedupe(&changed, primary_key)?)?
+            }
+        };
+
+        upload_multipart(object_store, &file_name, &merged)
+            .await
+            .map_err(|e| {
+                synced_error(format!("Failed to merge parquet file {}: {}", file_name, e))
+            })?;
+
+        // the date has data now, so its marker is stale
+        let marker = Path::from(format!("{}/{}.synced", prefix, date));
+        match object_store.delete(&marker).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+            Err(e) => return Err(object_store_error(e)),
+        }
+    }
+
+    Ok(num_files)
+}
+
+/// Get the bytes of a file, `None` if it doesn't exist
+async fn get_bytes(object_store: &Arc<dyn ObjectStore>, file_name: &str) -> Result<Option<Bytes>> {
+    match object_store.get(&Path::from(file_name)).await {
+        Ok(result) => Ok(Some(result.bytes().await.map_err(object_store_error)?)),
+        Err(object_store::Error::NotFound { .. }) => Ok(None),
+        Err(e) => Err(object_store_error(e)),
+    }
+}
+
+/// Merge changed records into a partition's records
+fn merge_parquet(existing: Bytes, changed: Bytes, primary_key: &str) -> Result<Bytes> {
+    let existing = parquet_bytes_to_record_batch(existing)?;
+    let changed = parquet_bytes_to_record_batch(changed)?;
+    let (schema, batches) = unify_record_batches(&[existing, changed])?;
+
+    let changed = dedupe(&batches[1], primary_key)?;
+    let changed_keys = keys(&changed, primary_key)?
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>();
+
+    // keep the existing records that weren't changed
+    let unchanged = keys(&batches[0], primary_key)?
+        .iter()
+        .map(|key| Some(!key.as_ref().is_some_and(|key| changed_keys.contains(key))))
+        .collect::<BooleanArray>();
+    let unchanged = filter_record_batch(&batches[0], &unchanged)?;
+
+    let merged = concat_batches(&schema, &[unchanged, changed])?;
+
+    record_batch_to_parquet_bytes(merged)
+}
+
+/// Remove the earlier versions of records that changed more than once
+fn dedupe(batch: &RecordBatch, primary_key: &str) -> Result<RecordBatch> {
+    let keys = keys(batch, primary_key)?;
+    let mut last = HashMap::new();
+
+    for (index, key) in keys.iter().enumerate() {
+        if let Some(key) = key {
+            last.insert(key, index);
+        }
+    }
+
+    let latest = keys
+        .iter()
+        .enumerate()
+        .map(|(index, key)| Some(key.as_ref().is_none_or(|key| last[key] == index)))
+        .collect::<BooleanArray>();
+
+    Ok(filter_record_batch(batch, &latest)?)
+}
+
+/// Get the primary keys of records as strings
+fn keys(batch: &RecordBatch, primary_key: &str) -> Result<Vec<Option<String>>> {
+    let column = batch
+        .column_by_name(primary_key)
+        .ok_or_else(|| synced_error(format!("Primary key {} not found in records", primary_key)))?;
+    let column = cast(column, &DataType::Utf8)?;
+    let column = column
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(|| synced_error("Primary key can't be converted to a string"))?;
+
+    Ok(column.iter().map(|key| key.map(str::to_owned)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrow::object_store::list_objects;
+    use crate::parquet::json::grouped_json_to_parquet;
+    use crate::synced::tests::create_temp_object_store;
+    use arrow::array::AsArray;
+
+    fn parquet(date: &str, lines: &[&str]) -> HashMap<String, Bytes> {
+        let lines = lines.iter().map(|line| line.to_string()).collect();
+        grouped_json_to_parquet(HashMap::from([(date.to_string(), lines)])).unwrap()
+    }
+
+    async fn read(object_store: &Arc<dyn ObjectStore>, file_name: &str) -> RecordBatch {
+        let bytes = object_store
+            .get(&Path::from(file_name))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+
+        parquet_bytes_to_record_batch(bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sync_state() {
+        let (_temp_dir, store) = create_temp_object_store();
+        let connection_id = Uuid::new_v4();
+
+        assert_eq!(
+            get_sync_state(&store, connection_id, "orders")
+                .await
+                .unwrap(),
+            None
+        );
+
+        let state = SyncState::new(Some("2024-01-02T00:00:00Z".into()));
+        set_sync_state(&store, connection_id, "orders", &state)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_sync_state(&store, connection_id, "orders")
+                .await
+                .unwrap(),
+            Some(state)
+        );
+        assert_eq!(
+            get_sync_state(&store, connection_id, "users")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge() {
+        let (_temp_dir, store) = create_temp_object_store();
+
+        // a new partition
+        let data = parquet(
+            "2024-01-01",
+            &[
+                r#"{"id": "a", "status": "open", "amount": 10}"#,
+                r#"{"id": "b", "status": "open", "amount": 20}"#,
+            ],
+        );
+        assert_eq!(merge(&store, "orders", "id", data).await.unwrap(), 1);
+
+        // b changed twice, c is new and has a new field
+        let data = parquet(
+            "2024-01-01",
+            &[
+                r#"{"id": "b", "status": "paid", "amount": 20}"#,
+                r#"{"id": "c", "status": "open", "amount": 30, "note": "new"}"#,
+                r#"{"id": "b", "status": "refunded", "amount": 20}"#,
+            ],
+        );
+        merge(&store, "orders", "id", data).await.unwrap();
+
+        let batch = read(&store, "orders/2024-01-01.parquet").await;
+        let column = |name: &str| {
+            let column = cast(batch.column_by_name(name).unwrap(), &DataType::Utf8).unwrap();
+            column
+                .as_string::<i32>()
+                .iter()
+                .map(|value| value.map(str::to_owned))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            column("id"),
+            vec![Some("a".into()), Some("c".into()), Some("b".into())]
+        );
+        assert_eq!(
+            column("status"),
+            vec![
+                Some("open".into()),
+                Some("open".into()),
+                Some("refunded".into())
+            ]
+        );
+        assert_eq!(column("note"), vec![None, Some("new".into()), None]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_widens_types_and_removes_markers() {
+        let (_temp_dir, store) = create_temp_object_store();
+
+        let data = parquet("2024-01-01", &[r#"{"id": 1, "amount": 10}"#]);
+        merge(&store, "orders", "id", data).await.unwrap();
+        upload_multipart(&store, "orders/2024-01-02.synced", &[])
+            .await
+            .unwrap();
+
+        // a partition with a new type and a partition for a date without data
+        let data = grouped_json_to_parquet(HashMap::from([
+            (
+                "2024-01-01".to_string(),
+                vec![r#"{"id": 2, "amount": "unknown"}"#.to_string()],
+            ),
+            (
+                "2024-01-02".to_string(),
+                vec![r#"{"id": 3, "amount": "30"}"#.to_string()],
+            ),
+        ]))
+        .unwrap();
+        assert_eq!(merge(&store, "orders", "id", data).await.unwrap(), 2);
+
+        let batch = read(&store, "orders/2024-01-01.parquet").await;
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch
+                .schema()
+                .field_with_name("amount")
+                .unwrap()
+                .data_type(),
+            &DataType::Utf8
+        );
+
+        let mut files = list_objects(&store, Some("orders"))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|object| object.location.filename().unwrap().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, vec!["2024-01-01.parquet", "2024-01-02.parquet"]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_into_compacted_month() {
+        let (_temp_dir, store) = create_temp_object_store();
+
+        let data = parquet(
+            "2024-01",
+            &[
+                r#"{"id": "a", "status": "open"}"#,
+                r#"{"id": "b", "status": "open"}"#,
+            ],
+        );
+        upload_multipart(&store, "orders/2024-01.parquet", &data["2024-01"])
+            .await
+            .unwrap();
+
+        let data = parquet("2024-01-15", &[r#"{"id": "b", "status": "paid"}"#]);
+        merge(&store, "orders", "id", data).await.unwrap();
+
+        let batch = read(&store, "orders/2024-01.parquet").await;
+        let status = cast(batch.column_by_name("status").unwrap(), &DataType::Utf8).unwrap();
+        assert_eq!(
+            status.as_string::<i32>().iter().collect::<Vec<_>>(),
+            vec![Some("open"), Some("paid")]
+        );
+        assert!(
+            store
+                .head(&Path::from("orders/2024-01-15.parquet"))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_merge_requires_primary_key() {
+        let data = parquet("2024-01-01", &[r#"{"id": 1}"#]);
+        let bytes = data["2024-01-01"].clone();
+
+        assert!(merge_parquet(bytes.clone(), bytes, "uuid").is_err());
+    }
+}
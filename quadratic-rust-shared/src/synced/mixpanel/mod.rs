@@ -36,7 +36,7 @@ impl SyncedConnection for MixpanelConnection {
         NaiveDate::parse_from_str(&self.start_date, DATE_FORMAT).unwrap()
     }
 
-    fn streams(&self) -> Vec<&'static str> {
+    fn streams(&self) -> Vec<&str> {
         MixpanelClient::streams()
     }
 
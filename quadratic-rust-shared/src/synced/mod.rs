@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use bytes::Bytes;
-use chrono::NaiveDate;
+use chrono::{Datelike, NaiveDate};
 use futures_util::stream::{FuturesUnordered, TryStreamExt};
 use object_store::ObjectStore;
 use serde::Deserialize;
@@ -12,19 +12,28 @@ use crate::{
     arrow::object_store::{list_objects, upload_multipart},
     environment::Environment,
     error::Result,
+    synced::incremental::IncrementalBatch,
 };
 
+pub mod backfill;
+pub mod compaction;
 pub mod google_analytics;
+pub mod incremental;
 pub mod mixpanel;
 pub mod plaid;
+pub mod rest;
+pub mod stripe;
 
 pub const DATE_FORMAT: &str = "%Y-%m-%d";
+pub const MONTH_FORMAT: &str = "%Y-%m";
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum SyncedConnectionKind {
     Mixpanel,
     GoogleAnalytics,
     Plaid,
+    Rest,
+    Stripe,
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -38,7 +47,7 @@ pub trait SyncedConnection: Send + Sync {
     fn name(&self) -> &str;
     fn kind(&self) -> SyncedConnectionKind;
     fn start_date(&self) -> NaiveDate;
-    fn streams(&self) -> Vec<&'static str>;
+    fn streams(&self) -> Vec<&str>;
     async fn to_client(&self, environment: Environment) -> Result<Box<dyn SyncedClient>>;
 }
 
@@ -62,6 +71,23 @@ pub trait SyncedClient: Send + Sync {
         end_date: NaiveDate,
     ) -> Result<Option<HashMap<String, Bytes>>>;
 
+    /// Whether a stream is synced incrementally (see `process_incremental`)
+    /// rather than by date.
+    fn is_incremental(&self, _stream: &str) -> bool {
+        false
+    }
+
+    /// Process the records of a stream that changed since the cursor (all
+    /// records when there's no cursor yet).
+    /// Returns `None` if the stream isn't synced incrementally.
+    async fn process_incremental(
+        &self,
+        _stream: &str,
+        _cursor: Option<&str>,
+    ) -> Result<Option<IncrementalBatch>> {
+        Ok(None)
+    }
+
     /// Process all streams in parallel and collect results in one pass.
     /// Streams that return `None` (not supported) are excluded from results.
     async fn process_all(
@@ -122,9 +148,27 @@ fn parse_file_date(string_date: &str) -> Result<NaiveDate> {
     Ok(date)
 }
 
-/// Get the date from the location of the file.
-/// Recognizes both .parquet files (with data) and .synced marker files (no data).
-fn get_date_from_location(location: &str) -> Result<NaiveDate> {
+/// Parse the first date of a month from a string, removing known extensions.
+fn parse_file_month(string_month: &str) -> Result<NaiveDate> {
+    let mut clean_month = string_month.to_ascii_lowercase();
+    for ext in SYNCED_EXTENSIONS {
+        clean_month = clean_month.replace(ext, "");
+    }
+    let date = string_to_date(&format!("{clean_month}-01"))?;
+
+    Ok(date)
+}
+
+/// Get every date of the month that starts on `month`.
+fn month_dates(month: NaiveDate) -> Vec<NaiveDate> {
+    month
+        .iter_days()
+        .take_while(|date| date.month() == month.month())
+        .collect()
+}
+
+/// Get the file name of the location if it's a synced file.
+fn get_synced_file_name(location: &str) -> Result<&str> {
     let location_lower = location.to_ascii_lowercase();
     let has_valid_extension = SYNCED_EXTENSIONS
         .iter()
@@ -135,11 +179,28 @@ fn get_date_from_location(location: &str) -> Result<NaiveDate> {
     }
 
     let parts = location.split('/').collect::<Vec<&str>>();
-    let string_date = parts
+    let file_name = parts
         .last()
         .ok_or_else(|| synced_error("No date found in location"))?;
 
-    parse_file_date(string_date)
+    Ok(file_name)
+}
+
+/// Get the date from the location of the file.
+/// Recognizes both .parquet files (with data) and .synced marker files (no data).
+fn get_date_from_location(location: &str) -> Result<NaiveDate> {
+    parse_file_date(get_synced_file_name(location)?)
+}
+
+/// Get the dates that the file at the location covers: a date's file covers
+/// the date, and a compacted month's file covers every date in the month.
+fn get_dates_from_location(location: &str) -> Result<Vec<NaiveDate>> {
+    match get_date_from_location(location) {
+        Ok(date) => Ok(vec![date]),
+        Err(_) => Ok(month_dates(parse_file_month(get_synced_file_name(
+            location,
+        )?)?)),
+    }
 }
 
 /// Get the first date of the objects in the object store using the date in the location.
@@ -150,7 +211,8 @@ pub async fn get_first_and_last_date_processed(
     let objects = list_objects(object_store, prefix).await?;
     let dates = objects
         .iter()
-        .flat_map(|o| get_date_from_location(o.location.as_ref()))
+        .filter_map(|o| get_dates_from_location(o.location.as_ref()).ok())
+        .flatten()
         .collect::<Vec<NaiveDate>>();
 
     let first_date = dates.iter().min().cloned();
@@ -167,7 +229,8 @@ pub async fn get_first_date_processed(
     let objects = list_objects(object_store, prefix).await?;
     let first_date = objects
         .iter()
-        .flat_map(|o| get_date_from_location(o.location.as_ref()))
+        .filter_map(|o| get_dates_from_location(o.location.as_ref()).ok())
+        .flatten()
         .collect::<Vec<NaiveDate>>()
         .iter()
         .min()
@@ -184,7 +247,8 @@ pub async fn get_last_date_processed(
     let objects = list_objects(object_store, prefix).await?;
     let last_date = objects
         .iter()
-        .flat_map(|o| get_date_from_location(o.location.as_ref()))
+        .filter_map(|o| get_dates_from_location(o.location.as_ref()).ok())
+        .flatten()
         .collect::<Vec<NaiveDate>>()
         .iter()
         .max()
@@ -205,7 +269,8 @@ pub async fn get_missing_dates(
     let objects = list_objects(object_store, prefix).await?;
     let mut existing_dates: Vec<NaiveDate> = objects
         .iter()
-        .filter_map(|o| get_date_from_location(o.location.as_ref()).ok())
+        .filter_map(|o| get_dates_from_location(o.location.as_ref()).ok())
+        .flatten()
         .collect();
     existing_dates.extend(dates_to_exclude);
     existing_dates.sort();
@@ -724,6 +789,47 @@ pub mod tests {
         assert_eq!(result.len(), 0);
     }
 
+    #[test]
+    fn test_get_dates_from_location() {
+        let result = get_dates_from_location("data/events/2024-01-15.parquet").unwrap();
+        assert_eq!(result, vec![NaiveDate::from_ymd_opt(2024, 1, 15).unwrap()]);
+
+        let result = get_dates_from_location("data/events/2024-02.parquet").unwrap();
+        assert_eq!(result.len(), 29);
+        assert_eq!(result[0], NaiveDate::from_ymd_opt(2024, 2, 1).unwrap());
+        assert_eq!(result[28], NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+
+        assert!(get_dates_from_location("data/events/2024-13.parquet").is_err());
+        assert!(get_dates_from_location("data/events/2024-02.txt").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_missing_date_ranges_compacted_month() {
+        let (temp_dir, store) = create_temp_object_store();
+        create_test_parquet_files(&temp_dir, &["2024-01", "2024-02-02"]);
+
+        let start_date = NaiveDate::from_ymd_opt(2024, 1, 30).unwrap();
+        let end_date = NaiveDate::from_ymd_opt(2024, 2, 3).unwrap();
+
+        let result = get_missing_date_ranges(&store, None, start_date, end_date, vec![])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                (
+                    NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+                    NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()
+                ),
+                (
+                    NaiveDate::from_ymd_opt(2024, 2, 3).unwrap(),
+                    NaiveDate::from_ymd_opt(2024, 2, 3).unwrap()
+                ),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_get_missing_date_ranges_with_prefix() {
         let (temp_dir, store) = create_temp_object_store();
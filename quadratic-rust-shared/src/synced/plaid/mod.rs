@@ -48,7 +48,7 @@ impl SyncedConnection for PlaidConnection {
             .unwrap()
     }
 
-    fn streams(&self) -> Vec<&'static str> {
+    fn streams(&self) -> Vec<&str> {
         PlaidClient::streams()
     }
 
@@ -0,0 +1,1108 @@
+//! REST API Client
+//!
+//! Requests a stream's pages and converts its records to parquet.  Hosts
+//! that resolve to private, loopback or other non-public addresses are
+//! denied (unless private addresses are allowed, see `utils::egress`), since
+//! the URLs come from the connection's config.
+
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{Duration, NaiveDate, NaiveTime};
+use reqwest::header::LINK;
+use reqwest::{Client, RequestBuilder, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::SharedError;
+use crate::error::Result;
+use crate::synced::incremental::IncrementalBatch;
+use crate::synced::rest::records::{
+    changed_records_to_parquet, json_path, records, records_to_parquet,
+};
+use crate::synced::rest::{
+    ApiKeyLocation, DateWindow, DateWindowFormat, Incremental, Pagination, RestAuth,
+    RestConnection, RestStream,
+};
+use crate::synced::{DATE_FORMAT, SyncedClient, string_to_date, synced_error, today};
+use crate::utils::egress::EgressPolicy;
+
+/// The most pages requested for a stream and date range, in case an API
+/// keeps returning the same page
+const MAX_PAGES: usize = 10_000;
+
+const MAX_REDIRECTS: usize = 10;
+
+type Query = Vec<(String, String)>;
+
+pub struct RestClient {
+    connection: RestConnection,
+    client: Client,
+    allow_private_ips: bool,
+    /// The OAuth2 access token, requested when it's first needed
+    access_token: Mutex<Option<String>>,
+}
+
+impl std::fmt::Debug for RestClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "RestClient {{ base_url: {}, streams: {:?} }}",
+            self.connection.base_url,
+            self.connection
+                .streams
+                .iter()
+                .map(|stream| &stream.name)
+                .collect::<Vec<_>>()
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: String,
+}
+
+impl RestClient {
+    /// Create a new REST client.  Set `allow_private_ips` to allow requests
+    /// to private and loopback addresses (e.g. for local development).
+    pub fn new(connection: RestConnection, allow_private_ips: bool) -> Result<Self> {
+        validate(&connection)?;
+
+        let client = EgressPolicy::new(allow_private_ips, MAX_REDIRECTS)
+            .client_builder()
+            .build()
+            .map_err(synced_error)?;
+
+        Ok(Self {
+            connection,
+            client,
+            allow_private_ips,
+            access_token: Mutex::new(None),
+        })
+    }
+
+    /// Get all of a stream's records for a date range
+    pub async fn get_records(
+        &self,
+        stream: &RestStream,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<Value>> {
+        self.get_pages(stream, stream_query(stream, start_date, end_date))
+            .await
+    }
+
+    /// Get all of an incremental stream's records that changed since the
+    /// cursor (all records when there's no cursor)
+    pub async fn get_changed_records(
+        &self,
+        stream: &RestStream,
+        incremental: &Incremental,
+        cursor: Option<&str>,
+    ) -> Result<Vec<Value>> {
+        let mut query = params_query(stream);
+
+        if let Some(cursor) = cursor {
+            query.push((incremental.cursor_param.to_owned(), cursor.to_owned()));
+        }
+
+        self.get_pages(stream, query).await
+    }
+
+    /// Get the records of every page, starting with the query of the first
+    /// page
+    async fn get_pages(&self, stream: &RestStream, mut query: Query) -> Result<Vec<Value>> {
+        let mut url = self.stream_url(stream)?;
+        let mut page_query = Query::new();
+        let mut offset = 0;
+        let mut cursors = HashSet::new();
+        let mut all_records = vec![];
+
+        if let Pagination::Offset {
+            offset_param,
+            limit_param,
+            limit,
+        } = &stream.pagination
+        {
+            page_query = offset_query(offset_param, limit_param, offset, *limit);
+        }
+
+        for _ in 0..MAX_PAGES {
+            let request_query = [query.as_slice(), page_query.as_slice()].concat();
+            let (response, next_url) = self.get(url.clone(), &request_query).await?;
+            let cursor = match &stream.pagination {
+                Pagination::Cursor {
+                    cursor_path: Some(path),
+                    ..
+                } => json_path(&response, path).and_then(cursor_value),
+                _ => None,
+            };
+            let has_more = match &stream.pagination {
+                Pagination::Cursor {
+                    has_more_path: Some(path),
+                    ..
+                } => json_path(&response, path).and_then(Value::as_bool),
+                _ => None,
+            };
+            let page = records(response, stream.records_path.as_deref())?;
+            let num_records = page.len();
+
+            let next_page = match &stream.pagination {
+                Pagination::None => None,
+                Pagination::Cursor {
+                    param,
+                    record_cursor_path,
+                    ..
+                } => {
+                    let cursor = cursor.or_else(|| {
+                        let path = record_cursor_path.as_deref()?;
+                        json_path(page.last()?, path).and_then(cursor_value)
+                    });
+
+                    // stop at an empty page, the last page or a repeated cursor
+                    match cursor {
+                        Some(cursor)
+                            if num_records > 0
+                                && has_more != Some(false)
+                                && cursors.insert(cursor.clone()) =>
+                        {
+                            Some(vec![(param.to_owned(), cursor)])
+                        }
+                        _ => None,
+                    }
+                }
+                Pagination::Offset {
+                    offset_param,
+                    limit_param,
+                    limit,
+                } => {
+                    offset += num_records;
+
+                    (num_records >= *limit && num_records > 0)
+                        .then(|| offset_query(offset_param, limit_param, offset, *limit))
+                }
+                Pagination::LinkHeader => next_url.map(|next_url| {
+                    // the next URL has all of the parameters
+                    url = next_url;
+                    query.clear();
+                    vec![]
+                }),
+            };
+
+            all_records.extend(page);
+
+            match next_page {
+                Some(next_page) => page_query = next_page,
+                None => return Ok(all_records),
+            }
+        }
+
+        Err(synced_error(format!(
+            "Stream {} has more than {MAX_PAGES} pages",
+            stream.name
+        )))
+    }
+
+    /// Request a page, returning the response and the `rel="next"` URL of
+    /// its `Link` header
+    async fn get(&self, url: Url, query: &[(String, String)]) -> Result<(Value, Option<Url>)> {
+        check_url(&url, self.allow_private_ips)?;
+
+        let request = self.client.get(url.clone()).query(query);
+        let response = self
+            .authenticate(request)
+            .await?
+            .send()
+            .await
+            .map_err(request_error)?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let body = body.chars().take(500).collect::<String>();
+
+            return Err(synced_error(format!(
+                "Request to {url} failed with {status}: {body}"
+            )));
+        }
+
+        let next_url = response
+            .headers()
+            .get(LINK)
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_link)
+            .and_then(|next| url.join(&next).ok());
+        let response = response.json::<Value>().await.map_err(synced_error)?;
+
+        Ok((response, next_url))
+    }
+
+    /// Add the connection's headers and credentials to a request
+    async fn authenticate(&self, mut request: RequestBuilder) -> Result<RequestBuilder> {
+        for (name, value) in &self.connection.headers {
+            request = request.header(name, value);
+        }
+
+        let request = match &self.connection.auth {
+            RestAuth::None => request,
+            RestAuth::ApiKey {
+                name,
+                value,
+                location: ApiKeyLocation::Header,
+            } => request.header(name, value),
+            RestAuth::ApiKey {
+                name,
+                value,
+                location: ApiKeyLocation::Query,
+            } => request.query(&[(name, value)]),
+            RestAuth::Bearer { token } => request.bearer_auth(token),
+            RestAuth::Basic { username, password } => {
+                request.basic_auth(username, password.as_ref())
+            }
+            RestAuth::Oauth2ClientCredentials { .. } => {
+                request.bearer_auth(self.access_token().await?)
+            }
+        };
+
+        Ok(request)
+    }
+
+    /// Get an OAuth2 access token using the client credentials grant
+    async fn access_token(&self) -> Result<String> {
+        let RestAuth::Oauth2ClientCredentials {
+            token_url,
+            client_id,
+            client_secret,
+            scope,
+        } = &self.connection.auth
+        else {
+            return Err(synced_error("The connection doesn't use OAuth2"));
+        };
+
+        let mut access_token = self.access_token.lock().await;
+
+        if let Some(access_token) = access_token.as_ref() {
+            return Ok(access_token.to_owned());
+        }
+
+        let token_url = Url::parse(token_url).map_err(synced_error)?;
+        check_url(&token_url, self.allow_private_ips)?;
+
+        let mut form = vec![("grant_type", "client_credentials")];
+
+        if let Some(scope) = scope {
+            form.push(("scope", scope.as_str()));
+        }
+
+        let response = self
+            .client
+            .post(token_url)
+            .basic_auth(client_id, Some(client_secret))
+            .form(&form)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        if !response.status().is_success() {
+            return Err(synced_error(format!(
+                "Error getting an OAuth2 access token: {}",
+                response.status()
+            )));
+        }
+
+        let token = response
+            .json::<AccessToken>()
+            .await
+            .map_err(synced_error)?
+            .access_token;
+
+        *access_token = Some(token.to_owned());
+
+        Ok(token)
+    }
+
+    /// Get a stream's config by its name
+    pub fn stream(&self, name: &str) -> Result<&RestStream> {
+        self.connection
+            .streams
+            .iter()
+            .find(|stream| stream.name == name)
+            .ok_or_else(|| SharedError::Synced(format!("Unknown stream: {}", name)))
+    }
+
+    fn stream_url(&self, stream: &RestStream) -> Result<Url> {
+        let base_url = format!("{}/", self.connection.base_url.trim_end_matches('/'));
+        let path = stream.path.trim_start_matches('/');
+
+        Url::parse(&base_url)
+            .and_then(|base_url| base_url.join(path))
+            .map_err(|e| synced_error(format!("Invalid URL for stream {}: {e}", stream.name)))
+    }
+}
+
+#[async_trait]
+impl SyncedClient for RestClient {
+    /// The streams are configured for each connection
+    fn streams() -> Vec<&'static str> {
+        vec![]
+    }
+
+    /// Test the connection by requesting the first page of the first stream
+    async fn test_connection(&self) -> bool {
+        let Some(stream) = self.connection.streams.first() else {
+            return false;
+        };
+
+        let today = today();
+
+        match self.stream_url(stream) {
+            Ok(url) => self
+                .get(url, &stream_query(stream, today, today))
+                .await
+                .inspect_err(|e| tracing::warn!("Error testing REST connection: {e}"))
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Process a single stream
+    async fn process(
+        &self,
+        stream: &str,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Option<HashMap<String, Bytes>>> {
+        let stream = self.stream(stream)?;
+
+        // snapshots can only be taken of today
+        if stream.date_field.is_none() && end_date != today() {
+            tracing::trace!(
+                "Skipping snapshot stream {} for historical date {}",
+                stream.name,
+                end_date
+            );
+            return Ok(None);
+        }
+
+        let records = self.get_records(stream, start_date, end_date).await?;
+
+        records_to_parquet(
+            records,
+            &stream.name,
+            stream.date_field.as_deref(),
+            start_date,
+            end_date,
+        )
+        .map(Some)
+    }
+
+    /// Streams with an `incremental` config are synced incrementally
+    fn is_incremental(&self, stream: &str) -> bool {
+        self.stream(stream)
+            .is_ok_and(|stream| stream.incremental.is_some())
+    }
+
+    /// Process the records of an incremental stream that changed since the
+    /// cursor
+    async fn process_incremental(
+        &self,
+        stream: &str,
+        cursor: Option<&str>,
+    ) -> Result<Option<IncrementalBatch>> {
+        let stream = self.stream(stream)?;
+
+        let (Some(incremental), Some(date_field)) = (&stream.incremental, &stream.date_field)
+        else {
+            return Ok(None);
+        };
+
+        let records = self
+            .get_changed_records(stream, incremental, cursor)
+            .await?;
+        let next_cursor = max_cursor(&records, &incremental.cursor_field, cursor);
+        let start_date = string_to_date(&self.connection.start_date)?;
+        let data = changed_records_to_parquet(records, &stream.name, date_field, start_date)?;
+
+        Ok(Some(IncrementalBatch {
+            primary_key: incremental.primary_key.to_owned(),
+            data,
+            cursor: next_cursor,
+        }))
+    }
+
+    /// Process all of the connection's streams
+    async fn process_all(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<HashMap<String, HashMap<String, Bytes>>> {
+        let mut results = HashMap::new();
+
+        for stream in &self.connection.streams {
+            if let Some(data) = self.process(&stream.name, start_date, end_date).await? {
+                results.insert(stream.name.to_owned(), data);
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+/// Stream names are table names and storage paths, so they're limited to
+/// lowercase letters, numbers and underscores
+fn validate(connection: &RestConnection) -> Result<()> {
+    let mut names = HashSet::new();
+
+    for stream in &connection.streams {
+        let is_valid = !stream.name.is_empty()
+            && stream
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+
+        if !is_valid {
+            return Err(synced_error(format!(
+                "Invalid stream name {:?}, use lowercase letters, numbers and underscores",
+                stream.name
+            )));
+        }
+
+        if !names.insert(&stream.name) {
+            return Err(synced_error(format!("Duplicate stream {}", stream.name)));
+        }
+
+        // changed records are merged into the partition of their date
+        if stream.incremental.is_some() && stream.date_field.is_none() {
+            return Err(synced_error(format!(
+                "Incremental stream {} needs a date_field",
+                stream.name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// The query of a stream's first page
+fn stream_query(stream: &RestStream, start_date: NaiveDate, end_date: NaiveDate) -> Query {
+    let mut query = params_query(stream);
+
+    if let Some(date_window) = &stream.date_window {
+        query.extend(date_window_query(date_window, start_date, end_date));
+    }
+
+    query
+}
+
+/// The query parameters sent with every request of a stream
+fn params_query(stream: &RestStream) -> Query {
+    stream
+        .params
+        .iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+fn date_window_query(
+    date_window: &DateWindow,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Query {
+    let start_of_day = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_utc();
+    let end = end_date + Duration::days(1);
+
+    let (start, end) = match date_window.format {
+        DateWindowFormat::Date => (
+            start_date.format(DATE_FORMAT).to_string(),
+            end_date.format(DATE_FORMAT).to_string(),
+        ),
+        DateWindowFormat::DateTime => (
+            start_of_day(start_date)
+                .format("%Y-%m-%dT%H:%M:%SZ")
+                .to_string(),
+            start_of_day(end).format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        ),
+        DateWindowFormat::Unix => (
+            start_of_day(start_date).timestamp().to_string(),
+            start_of_day(end).timestamp().to_string(),
+        ),
+    };
+
+    let mut query = vec![(date_window.start_param.to_owned(), start)];
+
+    if let Some(end_param) = &date_window.end_param {
+        query.push((end_param.to_owned(), end));
+    }
+
+    query
+}
+
+fn offset_query(offset_param: &str, limit_param: &str, offset: usize, limit: usize) -> Query {
+    vec![
+        (offset_param.to_owned(), offset.to_string()),
+        (limit_param.to_owned(), limit.to_string()),
+    ]
+}
+
+/// A cursor is a non-empty string or a number
+fn cursor_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(cursor) if !cursor.is_empty() => Some(cursor.to_owned()),
+        Value::Number(cursor) => Some(cursor.to_string()),
+        _ => None,
+    }
+}
+
+/// Get the largest cursor of the records, or the current cursor if it's
+/// larger.  Numbers are compared as numbers, and anything else (e.g. ISO 8601
+/// dates) as strings.
+fn max_cursor(records: &[Value], cursor_field: &str, cursor: Option<&str>) -> Option<String> {
+    let compare = |a: &String, b: &String| match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    };
+
+    records
+        .iter()
+        .filter_map(|record| json_path(record, cursor_field).and_then(cursor_value))
+        .chain(cursor.map(str::to_owned))
+        .max_by(compare)
+}
+
+/// Get the `rel="next"` URL of a `Link` header
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let mut parts = link.split(';');
+        let url = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+
+        parts
+            .any(|param| {
+                let param = param.trim().replace(' ', "");
+                param == "rel=\"next\"" || param == "rel=next"
+            })
+            .then(|| url.to_owned())
+    })
+}
+
+/// Include the sources of a request error, since denied addresses are
+/// wrapped in connection errors
+fn request_error(error: reqwest::Error) -> SharedError {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(&error);
+
+    while let Some(error) = source {
+        let cause = error.to_string();
+
+        if !message.contains(&cause) {
+            message = format!("{message}: {cause}");
+        }
+
+        source = error.source();
+    }
+
+    synced_error(message)
+}
+
+/// Check a URL's scheme, and its address when the host is an IP address
+fn check_url(url: &Url, allow_private_ips: bool) -> Result<()> {
+    EgressPolicy::new(allow_private_ips, MAX_REDIRECTS)
+        .check_url(url)
+        .map_err(synced_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn new_stream(pagination: Pagination) -> RestStream {
+        RestStream {
+            name: "orders".into(),
+            path: "/orders".into(),
+            params: BTreeMap::new(),
+            records_path: Some("data".into()),
+            pagination,
+            date_window: None,
+            date_field: Some("created".into()),
+            incremental: None,
+        }
+    }
+
+    fn new_client(server: &MockServer, auth: RestAuth, stream: RestStream) -> RestClient {
+        let connection = RestConnection {
+            base_url: server.url("/v1"),
+            auth,
+            headers: BTreeMap::from([("Accept".into(), "application/json".into())]),
+            streams: vec![stream],
+            start_date: "2024-01-01".into(),
+        };
+
+        RestClient::new(connection, true).unwrap()
+    }
+
+    fn ids(records: &[Value]) -> Vec<i64> {
+        records
+            .iter()
+            .map(|record| record["id"].as_i64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_rest_auth() {
+        let server = MockServer::start();
+        let stream = new_stream(Pagination::None);
+        let body = json!({ "data": [{ "id": 1 }] });
+
+        let api_key = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/orders")
+                .header("x-api-key", "key")
+                .header("accept", "application/json");
+            then.status(200).json_body(body.clone());
+        });
+        let auth = RestAuth::ApiKey {
+            name: "X-Api-Key".into(),
+            value: "key".into(),
+            location: ApiKeyLocation::Header,
+        };
+        let client = new_client(&server, auth, stream.clone());
+        let records = client.get_records(&stream, date(1), date(1)).await.unwrap();
+        assert_eq!(ids(&records), vec![1]);
+        api_key.assert();
+
+        let query_key = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/orders")
+                .query_param("api_key", "key");
+            then.status(200).json_body(body.clone());
+        });
+        let auth = RestAuth::ApiKey {
+            name: "api_key".into(),
+            value: "key".into(),
+            location: ApiKeyLocation::Query,
+        };
+        let client = new_client(&server, auth, stream.clone());
+        client.get_records(&stream, date(1), date(1)).await.unwrap();
+        query_key.assert();
+
+        let basic = server.mock(|when, then| {
+            // base64("user:pass")
+            when.method(GET)
+                .path("/v1/orders")
+                .header("authorization", "Basic dXNlcjpwYXNz");
+            then.status(200).json_body(body.clone());
+        });
+        let auth = RestAuth::Basic {
+            username: "user".into(),
+            password: Some("pass".into()),
+        };
+        let client = new_client(&server, auth, stream.clone());
+        client.get_records(&stream, date(1), date(1)).await.unwrap();
+        basic.assert();
+
+        let token = server.mock(|when, then| {
+            when.method(POST)
+                .path("/oauth/token")
+                .header("authorization", "Basic aWQ6c2VjcmV0")
+                .body_contains("grant_type=client_credentials");
+            then.status(200)
+                .json_body(json!({ "access_token": "token", "expires_in": 3600 }));
+        });
+        let bearer = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/orders")
+                .header("authorization", "Bearer token");
+            then.status(200).json_body(body.clone());
+        });
+        let auth = RestAuth::Oauth2ClientCredentials {
+            token_url: server.url("/oauth/token"),
+            client_id: "id".into(),
+            client_secret: "secret".into(),
+            scope: None,
+        };
+        let client = new_client(&server, auth, stream.clone());
+        client.get_records(&stream, date(1), date(1)).await.unwrap();
+        client.get_records(&stream, date(1), date(1)).await.unwrap();
+
+        // the access token is reused
+        token.assert_hits(1);
+        bearer.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_rest_cursor_pagination() {
+        let server = MockServer::start();
+        let stream = new_stream(Pagination::Cursor {
+            param: "after".into(),
+            cursor_path: Some("next".into()),
+            record_cursor_path: None,
+            has_more_path: Some("has_more".into()),
+        });
+
+        // mocks are matched in the order they're created
+        let second = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/orders")
+                .query_param("after", "b");
+            then.status(200)
+                .json_body(json!({ "data": [{ "id": 3 }], "next": "c", "has_more": false }));
+        });
+        let first = server.mock(|when, then| {
+            when.method(GET).path("/v1/orders");
+            then.status(200).json_body(
+                json!({ "data": [{ "id": 1 }, { "id": 2 }], "next": "b", "has_more": true }),
+            );
+        });
+
+        let client = new_client(&server, RestAuth::None, stream.clone());
+        let records = client.get_records(&stream, date(1), date(1)).await.unwrap();
+
+        assert_eq!(ids(&records), vec![1, 2, 3]);
+        first.assert();
+        second.assert();
+    }
+
+    #[tokio::test]
+    async fn test_rest_record_cursor_pagination() {
+        let server = MockServer::start();
+        let stream = new_stream(Pagination::Cursor {
+            param: "starting_after".into(),
+            cursor_path: None,
+            record_cursor_path: Some("id".into()),
+            has_more_path: None,
+        });
+
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/orders")
+                .query_param("starting_after", "2");
+            then.status(200).json_body(json!({ "data": [] }));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/v1/orders");
+            then.status(200)
+                .json_body(json!({ "data": [{ "id": 1 }, { "id": 2 }] }));
+        });
+
+        let client = new_client(&server, RestAuth::None, stream.clone());
+        let records = client.get_records(&stream, date(1), date(1)).await.unwrap();
+
+        assert_eq!(ids(&records), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_rest_offset_pagination() {
+        let server = MockServer::start();
+        let stream = new_stream(Pagination::Offset {
+            offset_param: "offset".into(),
+            limit_param: "limit".into(),
+            limit: 2,
+        });
+
+        for (offset, body) in [
+            ("0", json!({ "data": [{ "id": 1 }, { "id": 2 }] })),
+            ("2", json!({ "data": [{ "id": 3 }, { "id": 4 }] })),
+            ("4", json!({ "data": [{ "id": 5 }] })),
+        ] {
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path("/v1/orders")
+                    .query_param("offset", offset)
+                    .query_param("limit", "2");
+                then.status(200).json_body(body);
+            });
+        }
+
+        let client = new_client(&server, RestAuth::None, stream.clone());
+        let records = client.get_records(&stream, date(1), date(1)).await.unwrap();
+
+        assert_eq!(ids(&records), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_rest_link_header_pagination() {
+        let server = MockServer::start();
+        let mut stream = new_stream(Pagination::LinkHeader);
+        stream.records_path = None;
+
+        let next = format!(
+            "<{}>; rel=\"next\", <{}>; rel=\"first\"",
+            server.url("/v1/orders?page=2"),
+            server.url("/v1/orders")
+        );
+        let last = format!("<{}>; rel=\"first\"", server.url("/v1/orders"));
+
+        server.mock(|when, then| {
+            when.method(GET).path("/v1/orders").query_param("page", "2");
+            then.status(200)
+                .header("link", last)
+                .json_body(json!([{ "id": 2 }]));
+        });
+        server.mock(|when, then| {
+            when.method(GET).path("/v1/orders");
+            then.status(200)
+                .header("link", next)
+                .json_body(json!([{ "id": 1 }]));
+        });
+
+        let client = new_client(&server, RestAuth::None, stream.clone());
+        let records = client.get_records(&stream, date(1), date(1)).await.unwrap();
+
+        assert_eq!(ids(&records), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_rest_process() {
+        let server = MockServer::start();
+        let mut stream = new_stream(Pagination::None);
+        stream.params = BTreeMap::from([("status".into(), "paid".into())]);
+        stream.date_window = Some(DateWindow {
+            start_param: "created[gte]".into(),
+            end_param: Some("created[lt]".into()),
+            format: DateWindowFormat::Unix,
+        });
+
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/orders")
+                .query_param("status", "paid")
+                .query_param("created[gte]", "1704067200")
+                .query_param("created[lt]", "1704326400");
+            then.status(200).json_body(json!({
+                "data": [
+                    { "id": 1, "created": 1704067200, "customer": { "name": "a" } },
+                    { "id": 2, "created": 1704240000, "customer": { "name": "b" } }
+                ]
+            }));
+        });
+
+        let client = new_client(&server, RestAuth::None, stream);
+        let data = client
+            .process("orders", date(1), date(3))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut dates = data.keys().cloned().collect::<Vec<_>>();
+        dates.sort();
+
+        assert_eq!(dates, vec!["2024-01-01", "2024-01-02", "2024-01-03"]);
+        mock.assert();
+
+        assert!(client.process("missing", date(1), date(3)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rest_process_incremental() {
+        let server = MockServer::start();
+        let mut stream = new_stream(Pagination::None);
+        stream.incremental = Some(Incremental {
+            cursor_param: "updated_since".into(),
+            cursor_field: "updated".into(),
+            primary_key: "id".into(),
+        });
+
+        let changed = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/orders")
+                .query_param("updated_since", "1704153600");
+            then.status(200).json_body(json!({
+                "data": [{ "id": 2, "created": 1704067200, "updated": 1704240000 }]
+            }));
+        });
+        let all = server.mock(|when, then| {
+            when.method(GET).path("/v1/orders");
+            then.status(200).json_body(json!({
+                "data": [
+                    { "id": 1, "created": 1703980800, "updated": 1703980800 },
+                    { "id": 2, "created": 1704067200, "updated": 1704153600 },
+                    { "id": 3, "created": 1704153600, "updated": 1704067200 }
+                ]
+            }));
+        });
+
+        let client = new_client(&server, RestAuth::None, stream);
+        assert!(client.is_incremental("orders"));
+
+        // the first sync gets every record since the start date
+        let batch = client
+            .process_incremental("orders", None)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut dates = batch.data.keys().cloned().collect::<Vec<_>>();
+        dates.sort();
+
+        assert_eq!(dates, vec!["2024-01-01", "2024-01-02"]);
+        assert_eq!(batch.primary_key, "id");
+        assert_eq!(batch.cursor, Some("1704153600".into()));
+        all.assert();
+
+        // later syncs only get the changed records
+        let batch = client
+            .process_incremental("orders", batch.cursor.as_deref())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(batch.data.keys().collect::<Vec<_>>(), vec!["2024-01-01"]);
+        assert_eq!(batch.cursor, Some("1704240000".into()));
+        changed.assert();
+    }
+
+    #[tokio::test]
+    async fn test_rest_request_error() {
+        let server = MockServer::start();
+        let stream = new_stream(Pagination::None);
+
+        server.mock(|when, then| {
+            when.method(GET).path("/v1/orders");
+            then.status(401).body("unauthorized");
+        });
+
+        let client = new_client(&server, RestAuth::None, stream.clone());
+        let error = client
+            .get_records(&stream, date(1), date(1))
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("401"));
+        assert!(!client.test_connection().await);
+    }
+
+    #[tokio::test]
+    async fn test_rest_denies_private_ips() {
+        let stream = new_stream(Pagination::None);
+        let connection = RestConnection {
+            base_url: "http://localhost:1".into(),
+            auth: RestAuth::None,
+            headers: BTreeMap::new(),
+            streams: vec![stream.clone()],
+            start_date: "2024-01-01".into(),
+        };
+        let client = RestClient::new(connection, false).unwrap();
+
+        let error = client
+            .get_records(&stream, date(1), date(1))
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("not a public address"),
+            "{error}"
+        );
+
+        let url = Url::parse("http://169.254.169.254/latest").unwrap();
+        assert!(check_url(&url, false).is_err());
+        assert!(check_url(&url, true).is_ok());
+        assert!(check_url(&Url::parse("file:///etc/passwd").unwrap(), true).is_err());
+        assert!(check_url(&Url::parse("https://8.8.8.8/").unwrap(), false).is_ok());
+    }
+
+    #[test]
+    fn test_date_window_query() {
+        let window = |format| DateWindow {
+            start_param: "from".into(),
+            end_param: Some("to".into()),
+            format,
+        };
+        let query = |format| {
+            date_window_query(&window(format), date(1), date(2))
+                .into_iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            query(DateWindowFormat::Date),
+            vec!["from=2024-01-01", "to=2024-01-02"]
+        );
+        assert_eq!(
+            query(DateWindowFormat::DateTime),
+            vec!["from=2024-01-01T00:00:00Z", "to=2024-01-03T00:00:00Z"]
+        );
+        assert_eq!(
+            query(DateWindowFormat::Unix),
+            vec!["from=1704067200", "to=1704240000"]
+        );
+    }
+
+    #[test]
+    fn test_max_cursor() {
+        let records = vec![
+            json!({ "updated": 9, "updated_at": "2024-01-02T00:00:00Z" }),
+            json!({ "updated": 10, "updated_at": "2024-01-01T00:00:00Z" }),
+            json!({}),
+        ];
+
+        assert_eq!(max_cursor(&records, "updated", None), Some("10".into()));
+        assert_eq!(
+            max_cursor(&records, "updated", Some("11")),
+            Some("11".into())
+        );
+        assert_eq!(
+            max_cursor(&records, "updated_at", Some("2023-12-31T00:00:00Z")),
+            Some("2024-01-02T00:00:00Z".into())
+        );
+        assert_eq!(max_cursor(&[], "updated", Some("1")), Some("1".into()));
+        assert_eq!(max_cursor(&[], "updated", None), None);
+    }
+
+    #[test]
+    fn test_next_link() {
+        assert_eq!(
+            next_link(
+                r#"<https://api.example.com/a?page=2>; rel="next", <https://api.example.com/a?page=5>; rel="last""#
+            ),
+            Some("https://api.example.com/a?page=2".into())
+        );
+        assert_eq!(
+            next_link(r#"<https://api.example.com/a?page=1>; rel="prev""#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validate_stream_names() {
+        let connection = |names: &[&str]| RestConnection {
+            base_url: "https://api.example.com".into(),
+            auth: RestAuth::None,
+            headers: BTreeMap::new(),
+            streams: names
+                .iter()
+                .map(|name| RestStream {
+                    name: name.to_string(),
+                    ..new_stream(Pagination::None)
+                })
+                .collect(),
+            start_date: "2024-01-01".into(),
+        };
+
+        assert!(validate(&connection(&["orders", "line_items_2"])).is_ok());
+        assert!(validate(&connection(&["Orders"])).is_err());
+        assert!(validate(&connection(&["../orders"])).is_err());
+        assert!(validate(&connection(&[""])).is_err());
+        assert!(validate(&connection(&["orders", "orders"])).is_err());
+
+        // incremental streams are partitioned by date
+        let mut connection = connection(&["orders"]);
+        connection.streams[0].incremental = Some(Incremental {
+            cursor_param: "updated_since".into(),
+            cursor_field: "updated_at".into(),
+            primary_key: "id".into(),
+        });
+        assert!(validate(&connection).is_ok());
+
+        connection.streams[0].date_field = None;
+        assert!(validate(&connection).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//! Declarative REST API Connection
+//!
+//! A synced connection for JSON APIs that's described by its config rather
+//! than by a hand-written client.  The config has the API's base URL, how to
+//! authenticate and a list of streams.  Each stream is an endpoint, how its
+//! records are paginated, which parameters limit it to a date window and
+//! where its records are in the response.
+//!
+//! Records are written to date-partitioned parquet files using a stream's
+//! `date_field`.  Streams without a date field are snapshots, and are only
+//! synced for today.
+//!
+//! Streams with an updated-since parameter can be synced incrementally: each
+//! sync requests the records that changed since the last one, and merges
+//! them into their partitions by primary key.
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::environment::Environment;
+use crate::error::Result;
+use crate::synced::rest::client::RestClient;
+use crate::synced::{DATE_FORMAT, SyncedClient, SyncedConnection, SyncedConnectionKind};
+
+pub mod client;
+pub mod records;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RestConnection {
+    pub base_url: String,
+    #[serde(default)]
+    pub auth: RestAuth,
+    /// Headers sent with every request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub streams: Vec<RestStream>,
+    pub start_date: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RestAuth {
+    #[default]
+    None,
+    ApiKey {
+        name: String,
+        value: String,
+        #[serde(default)]
+        location: ApiKeyLocation,
+    },
+    Bearer {
+        token: String,
+    },
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// The access token is requested when it's first needed, and is used for
+    /// the life of the client (a client is created for each sync)
+    Oauth2ClientCredentials {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RestStream {
+    /// The table name of the stream's records
+    pub name: String,
+    /// The endpoint, relative to the base URL
+    pub path: String,
+    /// Query parameters sent with every request
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// The path of the records in the response (e.g. `data.items`).  The
+    /// response itself is used when it's not set.
+    pub records_path: Option<String>,
+    #[serde(default)]
+    pub pagination: Pagination,
+    pub date_window: Option<DateWindow>,
+    /// The path of the date (or unix timestamp) in a record that's used to
+    /// partition the records
+    pub date_field: Option<String>,
+    /// Sync the records that changed since the last sync rather than by date,
+    /// requires a `date_field` that doesn't change (e.g. the creation date)
+    pub incremental: Option<Incremental>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Incremental {
+    /// The query parameter of the updated-since cursor
+    pub cursor_param: String,
+    /// The path of the cursor in a record (e.g. `updated_at`), the largest
+    /// value is the cursor of the next sync
+    pub cursor_field: String,
+    /// The top-level field that identifies a record
+    pub primary_key: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Pagination {
+    #[default]
+    None,
+    /// The cursor of the next page is read from the response (`cursor_path`)
+    /// or from the last record of the page (`record_cursor_path`), and sent
+    /// in the `param` query parameter.  Pagination stops when there's no
+    /// cursor, a page is empty or `has_more_path` is false.
+    Cursor {
+        param: String,
+        cursor_path: Option<String>,
+        record_cursor_path: Option<String>,
+        has_more_path: Option<String>,
+    },
+    /// Pagination stops when a page has fewer than `limit` records
+    Offset {
+        offset_param: String,
+        limit_param: String,
+        limit: usize,
+    },
+    /// The `rel="next"` URL of the `Link` header is requested as is
+    LinkHeader,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DateWindow {
+    pub start_param: String,
+    pub end_param: Option<String>,
+    #[serde(default)]
+    pub format: DateWindowFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateWindowFormat {
+    /// `2024-01-31`, the end date is inclusive
+    #[default]
+    Date,
+    /// `2024-02-01T00:00:00Z`, the end is the start of the day after the end
+    /// date
+    DateTime,
+    /// Seconds since the epoch, the end is the start of the day after the
+    /// end date
+    Unix,
+}
+
+#[async_trait]
+impl SyncedConnection for RestConnection {
+    fn name(&self) -> &str {
+        "REST_API"
+    }
+
+    fn kind(&self) -> SyncedConnectionKind {
+        SyncedConnectionKind::Rest
+    }
+
+    fn start_date(&self) -> NaiveDate {
+        NaiveDate::parse_from_str(&self.start_date, DATE_FORMAT).unwrap()
+    }
+
+    fn streams(&self) -> Vec<&str> {
+        self.streams
+            .iter()
+            .map(|stream| stream.name.as_str())
+            .collect()
+    }
+
+    async fn to_client(&self, environment: Environment) -> Result<Box<dyn SyncedClient>> {
+        // local APIs can only be synced in development
+        let allow_private_ips = environment.is_local_or_docker() || environment.is_test();
+        let client = RestClient::new(self.clone(), allow_private_ips)?;
+
+        Ok(Box::new(client))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_rest_connection() {
+        let connection: RestConnection = serde_json::from_value(serde_json::json!({
+            "base_url": "https://api.example.com/v1",
+            "auth": { "type": "api_key", "name": "X-Api-Key", "value": "secret" },
+            "start_date": "2024-01-01",
+            "streams": [
+                {
+                    "name": "orders",
+                    "path": "orders",
+                    "records_path": "data",
+                    "pagination": { "type": "cursor", "param": "after", "cursor_path": "next" },
+                    "date_window": { "start_param": "since", "format": "unix" },
+                    "date_field": "created_at",
+                    "incremental": {
+                        "cursor_param": "updated_since",
+                        "cursor_field": "updated_at",
+                        "primary_key": "id"
+                    }
+                },
+                { "name": "users", "path": "users" }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            connection.auth,
+            RestAuth::ApiKey {
+                name: "X-Api-Key".into(),
+                value: "secret".into(),
+                location: ApiKeyLocation::Header,
+            }
+        );
+        assert_eq!(connection.streams(), vec!["orders", "users"]);
+        assert_eq!(
+            connection.streams[0].date_window,
+            Some(DateWindow {
+                start_param: "since".into(),
+                end_param: None,
+                format: DateWindowFormat::Unix,
+            })
+        );
+        assert_eq!(
+            connection.streams[0].incremental,
+            Some(Incremental {
+                cursor_param: "updated_since".into(),
+                cursor_field: "updated_at".into(),
+                primary_key: "id".into(),
+            })
+        );
+        assert_eq!(connection.streams[1].pagination, Pagination::None);
+        assert_eq!(connection.streams[1].incremental, None);
+        assert_eq!(
+            connection.start_date(),
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+        );
+    }
+}
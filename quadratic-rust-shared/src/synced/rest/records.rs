@@ -0,0 +1,285 @@
+//! Extract records from responses and partition them by date
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use chrono::{DateTime, Duration, NaiveDate};
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::parquet::json::grouped_json_to_parquet;
+use crate::synced::{DATE_FORMAT, synced_error};
+use crate::utils::json::flatten_to_json;
+
+/// Get a value by its path: dot separated keys with optional array indices
+/// and an optional `$.` prefix (e.g. `$.data.items[0].id`).  An empty path
+/// (or `$`) is the value itself.
+pub fn json_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(value, |value, segment| {
+            let mut parts = segment.split('[');
+            let mut value = match parts.next() {
+                Some("") | None => value,
+                Some(key) => value.get(key)?,
+            };
+
+            for index in parts {
+                let index = index.strip_suffix(']')?.parse::<usize>().ok()?;
+                value = value.get(index)?;
+            }
+
+            Some(value)
+        })
+}
+
+/// Get the records of a response.  A missing or null value has no records,
+/// and an object is a single record.
+pub fn records(response: Value, records_path: Option<&str>) -> Result<Vec<Value>> {
+    let value = match records_path {
+        Some(path) => json_path(&response, path).cloned().unwrap_or(Value::Null),
+        None => response,
+    };
+
+    match value {
+        Value::Array(records) => Ok(records),
+        Value::Object(_) => Ok(vec![value]),
+        Value::Null => Ok(vec![]),
+        _ => Err(synced_error(format!(
+            "Expected records at {}, found {value}",
+            records_path.unwrap_or("$")
+        ))),
+    }
+}
+
+/// Get the date of a record from a date string (only the date is used) or a
+/// unix timestamp in seconds
+pub fn record_date(record: &Value, date_field: &str) -> Option<NaiveDate> {
+    match json_path(record, date_field)? {
+        Value::String(date) => NaiveDate::parse_from_str(date.get(..10)?, DATE_FORMAT).ok(),
+        Value::Number(timestamp) => {
+            DateTime::from_timestamp(timestamp.as_i64()?, 0).map(|date| date.date_naive())
+        }
+        _ => None,
+    }
+}
+
+/// Convert records into a parquet file per date.
+///
+/// With a date field, every date in the range gets a file (empty when there
+/// are no records for the date) and records outside of the range are
+/// dropped.  Without one, all records are the end date's snapshot.
+pub fn records_to_parquet(
+    records: Vec<Value>,
+    stream: &str,
+    date_field: Option<&str>,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<HashMap<String, Bytes>> {
+    let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+    let mut num_records = 0;
+
+    let Some(date_field) = date_field else {
+        let date = end_date.format(DATE_FORMAT).to_string();
+        let lines = records
+            .iter()
+            .map(to_json_line)
+            .collect::<Result<Vec<_>>>()?;
+
+        if lines.is_empty() {
+            tracing::trace!("No {} found for {}", stream, end_date);
+            return Ok(HashMap::new());
+        }
+
+        grouped.insert(date, lines);
+        return grouped_json_to_parquet(grouped);
+    };
+
+    let mut current = start_date;
+    while current <= end_date {
+        grouped.insert(current.format(DATE_FORMAT).to_string(), Vec::new());
+        current += Duration::days(1);
+    }
+
+    for record in &records {
+        match record_date(record, date_field) {
+            Some(date) if date >= start_date && date <= end_date => {
+                let date = date.format(DATE_FORMAT).to_string();
+                grouped.entry(date).or_default().push(to_json_line(record)?);
+                num_records += 1;
+            }
+            Some(_) => {}
+            None => tracing::warn!("Skipping {} record without a valid {}", stream, date_field),
+        }
+    }
+
+    if num_records == 0 {
+        tracing::trace!(
+            "No {} found for date range {} to {}",
+            stream,
+            start_date,
+            end_date
+        );
+        return Ok(HashMap::new());
+    }
+
+    tracing::info!(
+        "Processing {} {} records from {} to {}",
+        num_records,
+        stream,
+        start_date,
+        end_date
+    );
+
+    grouped_json_to_parquet(grouped)
+}
+
+/// Convert changed records into a parquet file for each date that has
+/// records.  Records before the start date are dropped.
+pub fn changed_records_to_parquet(
+    records: Vec<Value>,
+    stream: &str,
+    date_field: &str,
+    start_date: NaiveDate,
+) -> Result<HashMap<String, Bytes>> {
+    let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+
+    for record in &records {
+        match record_date(record, date_field) {
+            Some(date) if date >= start_date => {
+                let date = date.format(DATE_FORMAT).to_string();
+                grouped.entry(date).or_default().push(to_json_line(record)?);
+            }
+            Some(_) => {}
+            None => tracing::warn!("Skipping {} record without a valid {}", stream, date_field),
+        }
+    }
+
+    tracing::info!(
+        "Processing {} changed {} records in {} partitions",
+        records.len(),
+        stream,
+        grouped.len()
+    );
+
+    grouped_json_to_parquet(grouped)
+}
+
+fn to_json_line(record: &Value) -> Result<String> {
+    Ok(serde_json::to_string(&flatten_to_json(record, Some(2)))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    #[test]
+    fn test_json_path() {
+        let value = json!({ "data": { "items": [{ "id": 1 }, { "id": 2 }] }, "next": "abc" });
+
+        assert_eq!(json_path(&value, "next"), Some(&json!("abc")));
+        assert_eq!(json_path(&value, "$.next"), Some(&json!("abc")));
+        assert_eq!(json_path(&value, "data.items[1].id"), Some(&json!(2)));
+        assert_eq!(json_path(&value, "$"), Some(&value));
+        assert_eq!(json_path(&value, ""), Some(&value));
+        assert_eq!(json_path(&value, "data.items[2]"), None);
+        assert_eq!(json_path(&value, "data.missing"), None);
+        assert_eq!(json_path(&json!([[1, 2]]), "[0][1]"), Some(&json!(2)));
+    }
+
+    #[test]
+    fn test_records() {
+        let response = json!({ "data": [{ "id": 1 }], "one": { "id": 2 }, "count": 1 });
+
+        assert_eq!(
+            records(response.clone(), Some("data")).unwrap(),
+            vec![json!({ "id": 1 })]
+        );
+        assert_eq!(
+            records(response.clone(), Some("one")).unwrap(),
+            vec![json!({ "id": 2 })]
+        );
+        assert!(
+            records(response.clone(), Some("missing"))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(records(response, Some("count")).is_err());
+        assert_eq!(
+            records(json!([{ "id": 3 }]), None).unwrap(),
+            vec![json!({ "id": 3 })]
+        );
+    }
+
+    #[test]
+    fn test_record_date() {
+        let record = json!({
+            "date": "2024-01-02",
+            "created": "2024-01-03T23:59:59Z",
+            "timestamp": 1704240000,
+            "invalid": "yesterday"
+        });
+
+        assert_eq!(record_date(&record, "date"), Some(date(2)));
+        assert_eq!(record_date(&record, "created"), Some(date(3)));
+        assert_eq!(record_date(&record, "timestamp"), Some(date(3)));
+        assert_eq!(record_date(&record, "invalid"), None);
+        assert_eq!(record_date(&record, "missing"), None);
+    }
+
+    #[test]
+    fn test_records_to_parquet() {
+        let records = vec![
+            json!({ "id": 1, "date": "2024-01-01" }),
+            json!({ "id": 2, "date": "2024-01-01" }),
+            json!({ "id": 3, "date": "2024-01-03" }),
+            json!({ "id": 4, "date": "2024-02-01" }),
+            json!({ "id": 5 }),
+        ];
+
+        let parquet =
+            records_to_parquet(records.clone(), "orders", Some("date"), date(1), date(3)).unwrap();
+        let mut dates = parquet.keys().cloned().collect::<Vec<_>>();
+        dates.sort();
+        assert_eq!(dates, vec!["2024-01-01", "2024-01-02", "2024-01-03"]);
+
+        // snapshots are written to the end date
+        let parquet =
+            records_to_parquet(records.clone(), "orders", None, date(1), date(3)).unwrap();
+        assert_eq!(parquet.keys().collect::<Vec<_>>(), vec!["2024-01-03"]);
+
+        // no records in the range
+        let parquet =
+            records_to_parquet(records, "orders", Some("date"), date(4), date(5)).unwrap();
+        assert!(parquet.is_empty());
+
+        let parquet = records_to_parquet(vec![], "orders", None, date(1), date(3)).unwrap();
+        assert!(parquet.is_empty());
+    }
+
+    #[test]
+    fn test_changed_records_to_parquet() {
+        let records = vec![
+            json!({ "id": 1, "date": "2023-12-31" }),
+            json!({ "id": 2, "date": "2024-01-01" }),
+            json!({ "id": 3, "date": "2024-01-03" }),
+            json!({ "id": 4 }),
+        ];
+
+        // only dates with changed records get a file
+        let parquet = changed_records_to_parquet(records, "orders", "date", date(1)).unwrap();
+        let mut dates = parquet.keys().cloned().collect::<Vec<_>>();
+        dates.sort();
+        assert_eq!(dates, vec!["2024-01-01", "2024-01-03"]);
+
+        let parquet = changed_records_to_parquet(vec![], "orders", "date", date(1)).unwrap();
+        assert!(parquet.is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+//! Stripe Client
+//!
+//! Syncs Stripe's list endpoints by the `created` date of their objects.
+//! Lists are paginated with `starting_after` (the id of the last object of
+//! the previous page), so the client is a REST connection with a fixed
+//! config.
+
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::NaiveDate;
+
+use crate::error::Result;
+use crate::synced::SyncedClient;
+use crate::synced::rest::client::RestClient;
+use crate::synced::rest::{
+    DateWindow, DateWindowFormat, Pagination, RestAuth, RestConnection, RestStream,
+};
+
+pub const STRIPE_API_URL: &str = "https://api.stripe.com";
+
+/// Pin the API version so that the parquet schemas don't change with the
+/// account's default version
+pub static STRIPE_VERSION: &str = "2024-06-20";
+
+/// The most objects Stripe returns in a page
+const PAGE_SIZE: &str = "100";
+
+#[derive(Debug)]
+pub struct StripeClient {
+    client: RestClient,
+}
+
+impl StripeClient {
+    /// Create a new Stripe client.
+    pub fn new(api_key: &str) -> Result<Self> {
+        Self::new_with_base_url(api_key, STRIPE_API_URL)
+    }
+
+    /// Create a new Stripe client for a different API URL (e.g. a mock server).
+    pub fn new_with_base_url(api_key: &str, base_url: &str) -> Result<Self> {
+        let connection = RestConnection {
+            base_url: format!("{}/v1", base_url.trim_end_matches('/')),
+            auth: RestAuth::Bearer {
+                token: api_key.to_string(),
+            },
+            headers: BTreeMap::from([("Stripe-Version".into(), STRIPE_VERSION.into())]),
+            streams: Self::streams().into_iter().map(stream).collect(),
+            start_date: String::new(),
+        };
+
+        // the base URL isn't configured by users
+        let client = RestClient::new(connection, true)?;
+
+        Ok(Self { client })
+    }
+}
+
+/// The config of a stream, the stream's name is its endpoint
+fn stream(name: &str) -> RestStream {
+    let mut params = BTreeMap::from([("limit".into(), PAGE_SIZE.into())]);
+
+    // canceled subscriptions are only listed when asked for
+    if name == "subscriptions" {
+        params.insert("status".into(), "all".into());
+    }
+
+    RestStream {
+        name: name.into(),
+        path: name.into(),
+        params,
+        records_path: Some("data".into()),
+        pagination: Pagination::Cursor {
+            param: "starting_after".into(),
+            cursor_path: None,
+            record_cursor_path: Some("id".into()),
+            has_more_path: Some("has_more".into()),
+        },
+        date_window: Some(DateWindow {
+            start_param: "created[gte]".into(),
+            end_param: Some("created[lt]".into()),
+            format: DateWindowFormat::Unix,
+        }),
+        date_field: Some("created".into()),
+        incremental: None,
+    }
+}
+
+#[async_trait]
+impl SyncedClient for StripeClient {
+    /// Get the streams available for this client
+    fn streams() -> Vec<&'static str> {
+        vec![
+            "charges",
+            "invoices",
+            "subscriptions",
+            "customers",
+            "payouts",
+            "balance_transactions",
+        ]
+    }
+
+    /// Test the connection by listing today's charges
+    async fn test_connection(&self) -> bool {
+        self.client.test_connection().await
+    }
+
+    /// Process a single stream
+    async fn process(
+        &self,
+        stream: &str,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Option<HashMap<String, Bytes>>> {
+        self.client.process(stream, start_date, end_date).await
+    }
+}
+
+// For testing only
+use std::sync::{LazyLock, Mutex};
+pub static STRIPE_CREDENTIALS: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| {
+    // Try to load .env.test file, but don't fail if it doesn't exist
+    let _ = dotenv::from_filename(".env.test");
+    let credentials = std::env::var("STRIPE_CREDENTIALS").ok();
+
+    Mutex::new(credentials)
+});
+
+/// Create a client for a mock server.  Recorded responses don't need an API
+/// key, recording them uses the test mode key in `STRIPE_CREDENTIALS`.
+pub fn new_stripe_client(base_url: &str, record: bool) -> StripeClient {
+    let api_key = match record {
+        true => STRIPE_CREDENTIALS
+            .lock()
+            .expect("STRIPE_CREDENTIALS lock poisoned")
+            .clone()
+            .expect("STRIPE_CREDENTIALS not set in .env.test"),
+        false => "sk_test_playback".to_string(),
+    };
+
+    StripeClient::new_with_base_url(&api_key, base_url).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use httpmock::prelude::*;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::test::request::{get_server, record_start, record_stop};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn ids(records: &[Value]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|record| record["id"].as_str().unwrap())
+            .collect()
+    }
+
+    // to record: cargo test test_stripe --features record-request-mock
+    async fn records(scenario: &str, stream: &str) -> (Vec<Value>, HashMap<String, Bytes>) {
+        let record = cfg!(feature = "record-request-mock");
+        let server = get_server(record, scenario, STRIPE_API_URL);
+        let client = new_stripe_client(&server.base_url(), record);
+        let recording = record.then(|| record_start(&server));
+
+        let stream = client.client.stream(stream).unwrap();
+        let records = client
+            .client
+            .get_records(stream, date(1), date(2))
+            .await
+            .unwrap();
+        let data = client
+            .process(&stream.name, date(1), date(2))
+            .await
+            .unwrap()
+            .unwrap();
+
+        if let Some(recording) = recording {
+            record_stop(scenario, recording).await;
+        }
+
+        (records, data)
+    }
+
+    #[tokio::test]
+    async fn test_stripe_charges() {
+        let (records, data) = records("stripe-charges", "charges").await;
+
+        // pages are requested until has_more is false
+        assert_eq!(ids(&records), vec!["ch_3", "ch_2", "ch_1"]);
+
+        let mut dates = data.keys().cloned().collect::<Vec<_>>();
+        dates.sort();
+        assert_eq!(dates, vec!["2024-01-01", "2024-01-02"]);
+    }
+
+    #[tokio::test]
+    async fn test_stripe_subscriptions() {
+        // the scenario only matches requests for all subscriptions
+        let (records, data) = records("stripe-subscriptions", "subscriptions").await;
+
+        assert_eq!(ids(&records), vec!["sub_2", "sub_1"]);
+        assert_eq!(data.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stripe_invalid_api_key() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/charges")
+                .header("authorization", "Bearer sk_test_playback")
+                .header("stripe-version", STRIPE_VERSION);
+            then.status(401).json_body(serde_json::json!({
+                "error": { "message": "Invalid API Key provided", "type": "invalid_request_error" }
+            }));
+        });
+
+        let client = new_stripe_client(&server.base_url(), false);
+
+        assert!(!client.test_connection().await);
+        assert!(
+            client
+                .process("charges", date(1), date(2))
+                .await
+                .unwrap_err()
+                .to_string()
+                .contains("Invalid API Key")
+        );
+        mock.assert_hits(2);
+    }
+
+    #[test]
+    fn test_stripe_streams() {
+        let client = StripeClient::new("sk_test_123").unwrap();
+
+        for stream in StripeClient::streams() {
+            assert!(client.client.stream(stream).is_ok(), "{stream}");
+        }
+
+        assert!(client.client.stream("refunds").is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::environment::Environment;
+use crate::error::Result;
+use crate::synced::stripe::client::StripeClient;
+use crate::synced::{DATE_FORMAT, SyncedClient, SyncedConnection, SyncedConnectionKind};
+
+pub mod client;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StripeConnection {
+    /// A secret or restricted (read-only) API key
+    pub api_key: String,
+    pub start_date: String,
+}
+
+#[async_trait]
+impl SyncedConnection for StripeConnection {
+    fn name(&self) -> &str {
+        "STRIPE"
+    }
+
+    fn kind(&self) -> SyncedConnectionKind {
+        SyncedConnectionKind::Stripe
+    }
+
+    fn start_date(&self) -> NaiveDate {
+        NaiveDate::parse_from_str(&self.start_date, DATE_FORMAT).unwrap()
+    }
+
+    fn streams(&self) -> Vec<&str> {
+        StripeClient::streams()
+    }
+
+    async fn to_client(&self, _environment: Environment) -> Result<Box<dyn SyncedClient>> {
+        let client = StripeClient::new(&self.api_key)?;
+
+        Ok(Box::new(client))
+    }
+}
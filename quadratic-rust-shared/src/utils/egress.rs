@@ -0,0 +1,344 @@
+//! Egress
+//!
+//! HTTP clients for URLs that come from users.  Requests to private,
+//! loopback, link-local and other non-public addresses are denied unless
+//! they're explicitly allowed, hosts can be allowed or denied by name, and
+//! redirects are limited.
+//!
+//! Hosts are resolved by the policy's client, so the addresses that are
+//! checked are the addresses that are connected to (a host can't resolve to
+//! a public address when it's checked and a private address when it's
+//! connected to).
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::{ClientBuilder, Url};
+
+use crate::utils::ip::is_public_ip;
+
+/// A request that the egress policy doesn't allow
+#[derive(Debug, Clone, PartialEq)]
+pub struct EgressDenied(pub String);
+
+impl fmt::Display for EgressDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl StdError for EgressDenied {}
+
+/// Find a denied request in an error's sources (denied redirects and
+/// addresses surface as reqwest errors)
+pub fn egress_denied(error: &(dyn StdError + 'static)) -> Option<&EgressDenied> {
+    let mut source = Some(error);
+
+    while let Some(error) = source {
+        if let Some(denied) = error.downcast_ref::<EgressDenied>() {
+            return Some(denied);
+        }
+
+        source = error.source();
+    }
+
+    None
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EgressPolicy {
+    allow_private_ips: bool,
+
+    /// When not empty, only these hosts are allowed.  `*.example.com`
+    /// matches the subdomains of example.com.
+    allowed_hosts: Vec<String>,
+
+    /// These hosts are always denied, and take precedence over the allowed
+    /// hosts
+    denied_hosts: Vec<String>,
+
+    max_redirects: usize,
+}
+
+impl EgressPolicy {
+    /// A policy that only allows public addresses (unless
+    /// `allow_private_ips`, e.g. for local development) and follows at most
+    /// `max_redirects` redirects
+    pub fn new(allow_private_ips: bool, max_redirects: usize) -> Self {
+        EgressPolicy {
+            allow_private_ips,
+            max_redirects,
+            ..Default::default()
+        }
+    }
+
+    /// Only allow `allowed_hosts` (when there are any) and never allow
+    /// `denied_hosts`.  Empty entries are ignored.
+    pub fn with_hosts(
+        self,
+        allowed_hosts: &Option<Vec<String>>,
+        denied_hosts: &Option<Vec<String>>,
+    ) -> Self {
+        EgressPolicy {
+            allowed_hosts: host_patterns(allowed_hosts),
+            denied_hosts: host_patterns(denied_hosts),
+            ..self
+        }
+    }
+
+    /// Create a client builder that resolves hosts and follows redirects
+    /// according to the policy
+    pub fn client_builder(&self) -> ClientBuilder {
+        let policy = Arc::new(self.to_owned());
+        let redirect_policy = {
+            let policy = Arc::clone(&policy);
+
+            Policy::custom(move |attempt| {
+                // the previous URLs include the original request
+                if attempt.previous().len() > policy.max_redirects {
+                    let denied = policy.deny(
+                        attempt.url(),
+                        format!("more than {} redirects", policy.max_redirects),
+                    );
+                    return attempt.error(denied);
+                }
+
+                match policy.check_url(attempt.url()) {
+                    Ok(()) => attempt.follow(),
+                    Err(denied) => attempt.error(denied),
+                }
+            })
+        };
+
+        ClientBuilder::new()
+            .redirect(redirect_policy)
+            .dns_resolver(Arc::new(EgressResolver(policy)))
+            // a proxy from the environment would resolve hosts instead
+            .no_proxy()
+    }
+
+    /// Check a URL's scheme and host.  IP addresses are checked here, host
+    /// names are checked again when they're resolved.
+    pub fn check_url(&self, url: &Url) -> Result<(), EgressDenied> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(self.deny(url, format!("the {} scheme is not allowed", url.scheme())));
+        }
+
+        let Some(host) = url.host_str() else {
+            return Err(self.deny(url, "the URL has no host".into()));
+        };
+
+        // IPv6 addresses are in brackets
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        match host.parse::<IpAddr>() {
+            Ok(ip) => self.check_host(host).and_then(|_| self.check_ip(ip)),
+            Err(_) => self.check_host(host),
+        }
+        .map_err(|denied| self.deny(url, denied.0))
+    }
+
+    /// Resolve a host, denying it if any of its addresses isn't allowed
+    /// (rather than connecting to the ones that are)
+    pub async fn resolve(
+        &self,
+        host: &str,
+    ) -> Result<Vec<SocketAddr>, Box<dyn StdError + Send + Sync>> {
+        let check = |denied: EgressDenied| self.deny(host, denied.0);
+
+        self.check_host(host).map_err(check)?;
+
+        let addrs = tokio::net::lookup_host((host, 0))
+            .await?
+            .collect::<Vec<SocketAddr>>();
+
+        for addr in &addrs {
+            self.check_ip(addr.ip()).map_err(check)?;
+        }
+
+        Ok(addrs)
+    }
+
+    /// Check a host against the allowed and denied hosts
+    fn check_host(&self, host: &str) -> Result<(), EgressDenied> {
+        let host = host.trim_end_matches('.').to_lowercase();
+
+        if self
+            .denied_hosts
+            .iter()
+            .any(|pattern| matches_host(pattern, &host))
+        {
+            return Err(EgressDenied(format!("{host} is a denied host")));
+        }
+
+        if !self.allowed_hosts.is_empty()
+            && !self
+                .allowed_hosts
+                .iter()
+                .any(|pattern| matches_host(pattern, &host))
+        {
+            return Err(EgressDenied(format!("{host} is not an allowed host")));
+        }
+
+        Ok(())
+    }
+
+    /// Check an address that's about to be connected to
+    fn check_ip(&self, ip: IpAddr) -> Result<(), EgressDenied> {
+        match self.allow_private_ips || is_public_ip(ip) {
+            true => Ok(()),
+            false => Err(EgressDenied(format!("{ip} is not a public address"))),
+        }
+    }
+
+    fn deny(&self, url: impl fmt::Display, reason: String) -> EgressDenied {
+        tracing::warn!(%url, reason, "Blocked egress request");
+
+        EgressDenied(format!("Request to {url} is not allowed: {reason}"))
+    }
+}
+
+/// Resolves hosts for the policy's client, denying hosts that resolve to
+/// addresses the policy doesn't allow
+struct EgressResolver(Arc<EgressPolicy>);
+
+impl Resolve for EgressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = Arc::clone(&self.0);
+
+        Box::pin(async move {
+            let addrs = policy.resolve(name.as_str()).await?;
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Host patterns from a config, ignoring empty entries
+fn host_patterns(hosts: &Option<Vec<String>>) -> Vec<String> {
+    hosts
+        .iter()
+        .flatten()
+        .map(|host| host.trim().trim_end_matches('.').to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+fn matches_host(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn hosts(hosts: &[&str]) -> Option<Vec<String>> {
+        Some(hosts.iter().map(|host| host.to_string()).collect())
+    }
+
+    fn check(policy: &EgressPolicy, url: &str) -> bool {
+        policy.check_url(&Url::parse(url).unwrap()).is_ok()
+    }
+
+    #[test]
+    fn test_egress_denies_private_ips() {
+        let policy = EgressPolicy::new(false, 1);
+
+        assert!(check(&policy, "https://example.com/webhook"));
+        assert!(check(&policy, "https://8.8.8.8/"));
+        assert!(!check(&policy, "ftp://example.com"));
+        assert!(!check(&policy, "http://127.0.0.1:8080"));
+        assert!(!check(&policy, "http://169.254.169.254/latest/meta-data"));
+        assert!(!check(&policy, "http://[::1]:8080/"));
+
+        let policy = EgressPolicy::new(true, 1);
+        assert!(check(&policy, "http://127.0.0.1:8000/"));
+    }
+
+    #[test]
+    fn test_egress_allowed_and_denied_hosts() {
+        let policy = EgressPolicy::new(false, 1).with_hosts(
+            &hosts(&["api.example.com", "*.example.org"]),
+            &hosts(&["bad.example.org"]),
+        );
+
+        assert!(check(&policy, "https://api.example.com/v1"));
+        assert!(check(&policy, "https://API.example.com./v1"));
+        assert!(check(&policy, "https://data.example.org/"));
+        assert!(!check(&policy, "https://example.org/"));
+        assert!(!check(&policy, "https://bad.example.org/"));
+        assert!(!check(&policy, "https://www.google.com/"));
+        assert!(!check(&policy, "ftp://api.example.com/"));
+    }
+
+    #[test]
+    fn test_egress_host_patterns() {
+        let hosts = Some(vec!["".into(), " Example.com. ".into()]);
+
+        assert_eq!(host_patterns(&hosts), vec!["example.com"]);
+        assert_eq!(host_patterns(&None), Vec::<String>::new());
+        assert!(!matches_host("*.example.com", "badexample.com"));
+    }
+
+    #[tokio::test]
+    async fn test_egress_resolve() {
+        let policy = EgressPolicy::new(false, 1);
+
+        assert!(policy.resolve("localhost").await.is_err());
+        assert!(policy.resolve("127.0.0.1").await.is_err());
+        assert!(policy.resolve("1.1.1.1").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_egress_client_denies_private_hosts() {
+        let client = EgressPolicy::new(false, 1)
+            .client_builder()
+            .build()
+            .unwrap();
+        let error = client.get("http://localhost:1").send().await.unwrap_err();
+
+        assert!(egress_denied(&error).is_some(), "{error:?}");
+    }
+
+    #[tokio::test]
+    async fn test_egress_client_max_redirects() {
+        // redirects `/` to `/next`
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let len = stream.read(&mut request).await.unwrap_or_default();
+                let response = match request[..len].starts_with(b"GET / ") {
+                    true => "HTTP/1.1 302 Found\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n",
+                    false => "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let get = |max_redirects: usize| {
+            let client = EgressPolicy::new(true, max_redirects).client_builder();
+            let url = url.to_owned();
+
+            async move { client.build().unwrap().get(url).send().await }
+        };
+
+        assert_eq!(get(1).await.unwrap().status(), 200);
+
+        let error = get(0).await.unwrap_err();
+        assert!(egress_denied(&error).is_some(), "{error:?}");
+    }
+}
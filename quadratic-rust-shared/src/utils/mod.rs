@@ -1,4 +1,8 @@
 //! General purpose utilities
 
 pub mod array;
+#[cfg(all(feature = "reqwest", feature = "tokio"))]
+pub mod egress;
+pub mod ip;
 pub mod json;
+pub mod sql_lexer;
@@ -0,0 +1,300 @@
+//! SQL Lexer
+//!
+//! Split a query into code, comments, strings and quoted identifiers without
+//! a full SQL parser.  Used to classify statements for read-only connections
+//! and by quadratic-core to find the handlebars within string literals, so it
+//! doesn't depend on the `sql` feature.
+
+use std::fmt;
+use std::ops::Range;
+
+/// How a database quotes strings and comments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// PostgreSQL and SQL Server: block comments nest and PostgreSQL has
+    /// dollar quoted strings
+    Standard,
+
+    /// MySQL, Snowflake and BigQuery: backslashes escape quotes, `#` starts
+    /// a line comment, block comments don't nest and MySQL runs the contents
+    /// of executable comments (`/*! ... */` and MariaDB's `/*M! ... */`)
+    Backslash,
+}
+
+/// What a part of a query is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LexemeKind {
+    /// Keywords, identifiers, symbols and whitespace
+    Code,
+
+    /// Comments, including the markers of executable comments
+    Comment,
+
+    /// A single quoted string
+    String,
+
+    /// A PostgreSQL dollar quoted string, e.g. `$$text$$` or
+    /// `$tag$text$tag$`, with the length in bytes of its tag
+    DollarString { tag_len: usize },
+
+    /// A quoted identifier, e.g. `"name"`, `` `name` `` or `[name]`
+    Identifier,
+}
+
+/// A part of a query, with its range in bytes.  Adjacent code is merged into
+/// one lexeme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lexeme {
+    pub kind: LexemeKind,
+    pub range: Range<usize>,
+}
+
+/// A query with an unterminated string, identifier or comment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unterminated(pub &'static str);
+
+impl fmt::Display for Unterminated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unterminated {}", self.0)
+    }
+}
+
+/// Split a query into lexemes
+pub fn lex(sql: &str, quoting: Quoting) -> Result<Vec<Lexeme>, Unterminated> {
+    let backslash = quoting == Quoting::Backslash;
+    let chars = sql.chars().collect::<Vec<_>>();
+    let offsets = sql
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain([sql.len()])
+        .collect::<Vec<_>>();
+    let mut lexemes: Vec<Lexeme> = vec![];
+    let mut i = 0;
+
+    // within an executable comment, whose contents are code
+    let mut executable_comment = false;
+
+    // the index after the closing `quote`, with doubled quotes escaped
+    let skip_quoted = |start: usize, quote: char, backslash_escapes: bool| -> Option<usize> {
+        let mut i = start;
+
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if backslash_escapes => i += 2,
+                c if c == quote && chars.get(i + 1) == Some(&quote) => i += 2,
+                c if c == quote => return Some(i + 1),
+                _ => i += 1,
+            }
+        }
+
+        None
+    };
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        let kind = match c {
+            // line comments
+            '-' if next == Some('-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                LexemeKind::Comment
+            }
+            '#' if backslash => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                LexemeKind::Comment
+            }
+
+            // executable comments, e.g. `/*!40101 SET ... */`
+            '/' if backslash && next == Some('*') && executable_comment_start(&chars[i..]) > 0 => {
+                i += executable_comment_start(&chars[i..]);
+                executable_comment = true;
+
+                // the optional minimum server version
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                LexemeKind::Comment
+            }
+            '*' if executable_comment && next == Some('/') => {
+                executable_comment = false;
+                i += 2;
+                LexemeKind::Comment
+            }
+
+            // block comments
+            '/' if next == Some('*') => {
+                let mut depth = 0;
+
+                loop {
+                    match (chars.get(i), chars.get(i + 1)) {
+                        (Some('/'), Some('*')) if depth == 0 || !backslash => {
+                            depth += 1;
+                            i += 2;
+                        }
+                        (Some('*'), Some('/')) => {
+                            depth -= 1;
+                            i += 2;
+
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        (Some(_), _) => i += 1,
+                        (None, _) => return Err(Unterminated("comment")),
+                    }
+                }
+                LexemeKind::Comment
+            }
+
+            // strings and quoted identifiers
+            '\'' => {
+                i = skip_quoted(i + 1, '\'', backslash).ok_or(Unterminated("string"))?;
+                LexemeKind::String
+            }
+            '"' | '`' => {
+                i = skip_quoted(i + 1, c, false).ok_or(Unterminated("identifier"))?;
+                LexemeKind::Identifier
+            }
+            '[' => {
+                i = skip_quoted(i + 1, ']', false).ok_or(Unterminated("identifier"))?;
+                LexemeKind::Identifier
+            }
+
+            // PostgreSQL dollar quoted strings, e.g. `$$text$$` or `$tag$text$tag$`
+            '$' if !backslash
+                && next.is_some_and(|c| c == '$' || c.is_alphabetic() || c == '_') =>
+            {
+                let tag_end = (i + 1..chars.len())
+                    .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'))
+                    .filter(|&j| chars[j] == '$');
+
+                match tag_end {
+                    Some(tag_end) => {
+                        let tag = &chars[i..=tag_end];
+
+                        i = (tag_end + 1..chars.len())
+                            .find(|&j| chars[j..].starts_with(tag))
+                            .map(|j| j + tag.len())
+                            .ok_or(Unterminated("string"))?;
+
+                        LexemeKind::DollarString {
+                            tag_len: offsets[tag_end + 1] - offsets[start],
+                        }
+                    }
+                    None => {
+                        i += 1;
+                        LexemeKind::Code
+                    }
+                }
+            }
+
+            // words, which may contain `$` (e.g. `a$b$`)
+            c if c.is_alphanumeric() || c == '_' => {
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+                {
+                    i += 1;
+                }
+                LexemeKind::Code
+            }
+
+            _ => {
+                i += 1;
+                LexemeKind::Code
+            }
+        };
+
+        let range = offsets[start]..offsets[i.min(chars.len())];
+
+        match lexemes.last_mut() {
+            Some(last) if kind == LexemeKind::Code && last.kind == LexemeKind::Code => {
+                last.range.end = range.end;
+            }
+            _ => lexemes.push(Lexeme { kind, range }),
+        }
+    }
+
+    if executable_comment {
+        return Err(Unterminated("comment"));
+    }
+
+    Ok(lexemes)
+}
+
+/// The length of the start of an executable comment (`/*!` or `/*M!`), or 0
+fn executable_comment_start(chars: &[char]) -> usize {
+    match chars {
+        ['/', '*', '!', ..] => 3,
+        ['/', '*', 'M', '!', ..] => 4,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexemes(sql: &str, quoting: Quoting) -> Vec<(LexemeKind, &str)> {
+        lex(sql, quoting)
+            .unwrap()
+            .into_iter()
+            .map(|lexeme| (lexeme.kind, &sql[lexeme.range]))
+            .collect()
+    }
+
+    #[test]
+    fn test_lex() {
+        use LexemeKind::*;
+
+        assert_eq!(
+            lexemes(
+                "select 'it''s', \"é\" -- it's\nfrom $t$ '$t$ /* ' */",
+                Quoting::Standard
+            ),
+            vec![
+                (Code, "select "),
+                (String, "'it''s'"),
+                (Code, ", "),
+                (Identifier, "\"é\""),
+                (Code, " "),
+                (Comment, "-- it's"),
+                (Code, "\nfrom "),
+                (DollarString { tag_len: 3 }, "$t$ '$t$"),
+                (Code, " "),
+                (Comment, "/* ' */"),
+            ]
+        );
+
+        assert_eq!(
+            lexemes(r"select 'it\'s' # it's", Quoting::Backslash),
+            vec![
+                (Code, "select "),
+                (String, r"'it\'s'"),
+                (Code, " "),
+                (Comment, "# it's"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_unterminated() {
+        assert_eq!(
+            lex(r"select 'it\'s'", Quoting::Standard),
+            Err(Unterminated("string"))
+        );
+        assert_eq!(
+            lex("select /* /* */", Quoting::Standard),
+            Err(Unterminated("comment"))
+        );
+        assert_eq!(
+            lex("/*! select 1", Quoting::Backslash),
+            Err(Unterminated("comment"))
+        );
+    }
+}
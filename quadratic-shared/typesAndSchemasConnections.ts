@@ -24,11 +24,14 @@ export const ConnectionTypeSchema = z.enum([
   'MIXPANEL',
   'GOOGLE_ANALYTICS',
   'PLAID',
+  'STRIPE',
+  'SQLITE',
+  'DUCKDB',
 ]);
 export const ConnectionSemanticDescriptionSchema = z.string().optional().transform(transformEmptyStringToUndefined);
 
 export function isSyncedConnectionType(type: ConnectionType): boolean {
-  return ['MIXPANEL', 'GOOGLE_ANALYTICS', 'PLAID'].includes(type);
+  return ['MIXPANEL', 'GOOGLE_ANALYTICS', 'PLAID', 'STRIPE'].includes(type);
 }
 
 // Helper function to check if a host address is a localhost variant
@@ -86,10 +89,19 @@ const ConnectionSshSchema = z.object({
   sshUsername: z.string().optional(),
 });
 
+const ConnectionTlsSchema = z.object({
+  sslMode: z.enum(['disable', 'prefer', 'require', 'verify-ca', 'verify-full']).optional(),
+  sslRootCert: z.string().optional().transform(transformEmptyStringToUndefined),
+  sslCert: z.string().optional().transform(transformEmptyStringToUndefined),
+  sslKey: z.string().optional().transform(transformEmptyStringToUndefined),
+  sslServerName: z.string().optional().transform(transformEmptyStringToUndefined),
+});
+
 export type ConnectionTypeDetails = z.infer<typeof ConnectionTypeDetailsSchema>;
 export type ConnectionType = z.infer<typeof ConnectionTypeSchema>;
 export type Connection = z.infer<typeof ConnectionSchema>;
 export type ConnectionSsh = z.infer<typeof ConnectionSshSchema>;
+export type ConnectionTls = z.infer<typeof ConnectionTlsSchema>;
 
 /**
  * =============================================================================
@@ -102,6 +114,11 @@ export const ConnectionTypeDetailsBaseSchema = z.object({
   database: z.string().min(1, { message: 'Required' }),
   username: z.string().min(1, { message: 'Required' }),
   password: z.string().optional().transform(transformEmptyStringToUndefined),
+  ...ConnectionTlsSchema.shape,
+  // Only allow queries that read data
+  readOnly: z.boolean().optional(),
+  // Cache query results for this many seconds
+  cacheTtlSeconds: z.number().int().positive().optional(),
 });
 export const ConnectionTypeDetailsBaseSchemaWithSsh = z.object({
   ...ConnectionTypeDetailsBaseSchema.shape,
@@ -127,12 +144,16 @@ export const ConnectionTypeDetailsSnowflakeSchema = z.object({
   password: z.string().min(1, { message: 'Required' }),
   warehouse: z.string().optional().transform(transformEmptyStringToUndefined),
   role: z.string().optional().transform(transformEmptyStringToUndefined),
+  read_only: z.boolean().optional(),
+  cache_ttl_seconds: z.number().int().positive().optional(),
 });
 
 export const ConnectionTypeDetailsBigquerySchema = z.object({
   project_id: z.string().min(1, { message: 'Required' }),
   dataset: z.string().min(1, { message: 'Required' }),
   service_account_configuration: z.string().min(1, { message: 'Required' }),
+  read_only: z.boolean().optional(),
+  cache_ttl_seconds: z.number().int().positive().optional(),
 });
 
 export const ConnectionTypeDetailsMixpanelSchema = z.object({
@@ -153,6 +174,17 @@ export const ConnectionTypeDetailsPlaidSchema = z.object({
   institution_name: z.string().optional(), // For display purposes
 });
 
+// The database is a file in the connection service's database files directory
+export const ConnectionTypeDetailsSqliteSchema = z.object({
+  database: z.string().min(1, { message: 'Required' }),
+});
+export const ConnectionTypeDetailsDuckdbSchema = ConnectionTypeDetailsSqliteSchema;
+
+export const ConnectionTypeDetailsStripeSchema = z.object({
+  api_key: z.string().min(1, { message: 'Required' }),
+  start_date: z.string().date(),
+});
+
 /**
  * =============================================================================
  * Schemas for synced connections